@@ -3,8 +3,10 @@
 // license that can be found in the LICENSE file.
 
 use crate::branch::*;
+use crate::consteval::{pure_funcs, ConstEval};
 use crate::consts::*;
 use crate::context::*;
+use crate::dispatch::StaticDispatch;
 use crate::package::PkgHelper;
 use crate::types::{SelectionType, TypeCache, TypeLookup};
 use go_parser::ast::*;
@@ -46,10 +48,18 @@ pub(crate) struct CodeGen<'a, 'c> {
     pkg_helper: &'a mut PkgHelper<'a>,
 
     pkg_key: PackageKey,
+    // what the names of the functions of the package start with
+    pkg_path: String,
     blank_ident: IdentKey,
     func_ctx_stack: Vec<FuncCtx<'c>>,
     expr_ctx_stack: Vec<ExprCtx>,
     results: Vec<FuncCtx<'c>>,
+    // the interface variables with known dynamic types, see StaticDispatch
+    static_ifaces: Map<TCObjKey, TCTypeKey>,
+    // the hidden locals holding the concrete values of those variables
+    iface_shadows: Map<TCObjKey, (FunctionKey, Addr, TCTypeKey)>,
+    // the functions the calls to which may be evaluated at compile time
+    pure_funcs: Map<TCObjKey, FuncDeclKey>,
 }
 
 impl<'a, 'c> CodeGen<'a, 'c> {
@@ -78,10 +88,14 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             branch_helper,
             pkg_helper,
             pkg_key,
+            pkg_path: String::new(),
             blank_ident,
             func_ctx_stack: vec![],
             expr_ctx_stack: vec![],
             results: vec![],
+            static_ifaces: Map::new(),
+            iface_shadows: Map::new(),
+            pure_funcs: Map::new(),
         }
     }
 
@@ -123,27 +137,31 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             return VirtualAddr::Direct(index);
         }
         // 2. try upvalue
-        let upvalue = self
-            .func_ctx_stack
+        let len = self.func_ctx_stack.len();
+        let upvalue = self.func_ctx_stack[..len - 1]
             .iter()
+            .enumerate()
             .skip(1) // skip package constructor
             .rev()
-            .skip(1) // skip itself
-            .find_map(|ctx| {
-                let index = ctx.entity_index(&okey).map(|x| *x);
-                if let Some(ind) = index {
+            .find_map(|(i, ctx)| {
+                ctx.entity_index(&okey).map(|ind| {
                     let desc = ValueDesc::new(
                         ctx.f_key,
                         ind.as_var_index() as OpIndex,
                         self.t.obj_use_value_type(*ident),
                         false,
                     );
-                    Some(desc)
-                } else {
-                    None
-                }
+                    (i, desc)
+                })
             });
-        if let Some(uv) = upvalue {
+        if let Some((i, uv)) = upvalue {
+            self.func_ctx_stack[i].capture_local(uv.index as usize);
+            // The enclosing functions in between capture it as well, so that the
+            // closure can share their upvalue when the defining frame is not on the
+            // stack of the goroutine that creates it.
+            for ctx in self.func_ctx_stack[i + 1..len - 1].iter_mut() {
+                ctx.add_upvalue(&okey, uv.clone());
+            }
             let ctx = func_ctx!(self);
             let index = ctx.add_upvalue(&okey, uv);
             return index;
@@ -165,13 +183,16 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         }
         if is_def {
             let tc_obj = self.t.object_def(*ikey);
-            let (index, tc_type, _) = self.add_local_var(tc_obj);
-            let ctx = func_ctx!(self);
-            if ctx.is_ctor(&self.vmctx.functions()) {
-                let pkg_key = self.vmctx.functions()[ctx.f_key].package;
-                let pkg = &mut self.vmctx.packages_mut()[pkg_key];
-                pkg.add_var_mapping(ident.name.clone(), index.as_var_index() as OpIndex);
+            if func_ctx!(self).is_ctor(&self.vmctx.functions()) {
+                // package vars are initialized in place, so that the functions called
+                // by the initializers see them
+                let tc_type = self.t.obj_tc_type(tc_obj);
+                let va = self
+                    .pkg_helper
+                    .get_member_index(func_ctx!(self), tc_obj, *ikey);
+                return (va, Some(tc_type), pos);
             }
+            let (index, tc_type, _) = self.add_local_var(tc_obj);
             (VirtualAddr::Direct(index), Some(tc_type), pos)
         } else {
             let index = self.resolve_var_ident(ikey);
@@ -183,27 +204,116 @@ impl<'a, 'c> CodeGen<'a, 'c> {
     fn add_local_var(&mut self, okey: TCObjKey) -> (Addr, TCTypeKey, Meta) {
         let tc_type = self.t.obj_tc_type(okey);
         let meta = self.t.tc_type_to_meta(tc_type, self.vmctx);
-        let zero_val = self.vmctx.ffi_ctx().zero_val(&meta);
+        let name = self.t.obj_name(okey);
         let ctx = func_ctx!(self);
-        let index = ctx.add_local(Some(okey), Some(zero_val));
+        let index = ctx.add_local(Some(okey), Some(meta));
+        if name != "_" {
+            ctx.name_local(name, index);
+        }
         (index, tc_type, meta)
     }
 
     fn gen_def_var(&mut self, vs: &ValueSpec) {
-        let lhs = vs
-            .names
-            .iter()
-            .map(|n| -> (VirtualAddr, Option<TCTypeKey>, usize) {
-                let (vaddr, t, pos) = self.add_local_or_resolve_ident(n, true);
-                (vaddr, t, pos)
-            })
-            .collect::<Vec<(VirtualAddr, Option<TCTypeKey>, usize)>>();
+        let mut casts = vec![];
+        let mut lhs = vec![];
+        for n in vs.names.iter() {
+            let (vaddr, t, pos) = self.add_local_or_resolve_ident(n, true);
+            match self.add_iface_shadow(n) {
+                // the value is stored to the shadow first, and then casted to the variable
+                Some((shadow, typ)) => {
+                    casts.push((vaddr, t, shadow, typ, pos));
+                    lhs.push((VirtualAddr::Direct(shadow), Some(typ), pos));
+                }
+                None => lhs.push((vaddr, t, pos)),
+            }
+        }
         let rhs = if vs.values.is_empty() {
             RightHandSide::Nothing
         } else {
             RightHandSide::Values(&vs.values)
         };
         self.gen_assign_def_var(&lhs, &vs.typ, &rhs);
+        for (vaddr, t, shadow, typ, pos) in casts.into_iter() {
+            self.store_mode_call(vaddr, t, |g| {
+                g.cur_expr_emit_direct_assign(typ, shadow, Some(pos))
+            });
+        }
+    }
+
+    /// Adds a hidden local for the concrete value of an interface variable whose
+    /// dynamic type is known, the methods called on the variable are bound to it.
+    fn add_iface_shadow(&mut self, ikey: &IdentKey) -> Option<(Addr, TCTypeKey)> {
+        if self.ast_objs.idents[*ikey].is_blank() {
+            return None;
+        }
+        let okey = self.t.object_def(*ikey);
+        let typ = *self.static_ifaces.get(&okey)?;
+        let meta = self.t.tc_type_to_meta(typ, self.vmctx);
+        let fctx = func_ctx!(self);
+        let shadow = fctx.add_local(None, Some(meta));
+        self.iface_shadows.insert(okey, (fctx.f_key, shadow, typ));
+        Some((shadow, typ))
+    }
+
+    /// Binds the method directly if the selector is on an interface variable with a
+    /// shadow in the current function, and the method is declared by its dynamic type.
+    fn gen_static_bind_method(
+        &mut self,
+        lhs_expr: &Expr,
+        ident: &IdentKey,
+        expr_type: TCTypeKey,
+        pos: Option<Pos>,
+    ) -> bool {
+        let okey = match Parser::unparen(lhs_expr) {
+            Expr::Ident(ikey) => match self.t.type_info().uses.get(ikey) {
+                Some(okey) => *okey,
+                None => return false,
+            },
+            _ => return false,
+        };
+        let (fkey, shadow, typ) = match self.iface_shadows.get(&okey) {
+            Some(s) => *s,
+            None => return false,
+        };
+        if fkey != func_ctx!(self).f_key {
+            return false;
+        }
+        let (base, is_ptr) = match &self.tc_objs.types[typ] {
+            Type::Pointer(p) => (p.base(), true),
+            _ => (typ, false),
+        };
+        let methods = match &self.tc_objs.types[base] {
+            Type::Named(n) => n.methods(),
+            _ => return false,
+        };
+        let name = &self.ast_objs.idents[*ident].name;
+        let (index, ptr_recv) = match methods
+            .iter()
+            .enumerate()
+            .find(|(_, m)| self.tc_objs.lobjs[**m].name() == name)
+        {
+            // methods promoted from embedded fields go through the interface
+            Some((i, m)) => (i, self.tc_objs.lobjs[*m].entity_type().func_has_ptr_recv()),
+            None => return false,
+        };
+        if ptr_recv && !is_ptr {
+            return false;
+        }
+        let recv_addr = match is_ptr && !ptr_recv {
+            true => self.gen_load_pointer(shadow, pos),
+            false => shadow,
+        };
+        let meta = self.t.tc_type_to_meta(typ, self.vmctx);
+        self.cur_expr_emit_assign(expr_type, pos, |f, d, p| {
+            let inst = InterInst::with_op_index(
+                Opcode::BIND_METHOD,
+                d,
+                recv_addr,
+                f.add_method(meta, index),
+            );
+            f.emit_inst(inst, p);
+        });
+        true
     }
 
     fn gen_def_const(&mut self, names: &Vec<IdentKey>) {
@@ -228,15 +338,28 @@ impl<'a, 'c> CodeGen<'a, 'c> {
     ) -> Option<usize> {
         let lhs = lhs_exprs
             .iter()
+            .map(|expr| Parser::unparen(expr))
             .map(|expr| match expr {
                 Expr::Ident(ident) => {
                     let is_def = self.t.ident_is_def(ident);
                     let (vaddr, typ, pos) = self.add_local_or_resolve_ident(ident, is_def);
+                    let vaddr = match (vaddr, token, &rhs) {
+                        (VirtualAddr::Direct(addr), Token::ASSIGN, RightHandSide::Values(_))
+                            if !is_def
+                                && matches!(
+                                    self.t.tc_type_to_value_type(typ.unwrap()),
+                                    ValueType::Struct | ValueType::Array
+                                ) =>
+                        {
+                            VirtualAddr::LocalInPlace(addr)
+                        }
+                        (vaddr, _, _) => vaddr,
+                    };
                     (vaddr, typ, pos)
                 }
                 Expr::Index(ind_expr) => {
                     let obj = &ind_expr.as_ref().expr;
-                    let obj_addr = self.load_mode_call(|g| g.gen_expr(obj));
+                    let mut obj_addr = self.load_mode_call(|g| g.gen_expr(obj));
                     let ind = &ind_expr.as_ref().index;
                     let ind_addr = match self.t.need_cast_container_index(obj, ind) {
                         None => self.load_mode_call(|g| g.gen_expr(ind)),
@@ -248,15 +371,20 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                             iface_addr
                         }
                     };
-                    let obj_typ = self.t.expr_value_type(obj);
+                    let mut obj_typ = self.t.expr_value_type(obj);
                     let typ = self.t.expr_tc_type(expr);
                     let pos = ind_expr.as_ref().l_brack;
+                    if obj_typ == ValueType::Pointer {
+                        obj_addr = self.gen_load_pointer(obj_addr, Some(pos));
+                        obj_typ = ValueType::Array // has to be array
+                    }
                     let va = match obj_typ {
                         ValueType::Array => VirtualAddr::ArrayEntry(obj_addr, ind_addr),
                         ValueType::Slice => VirtualAddr::SliceEntry(obj_addr, ind_addr),
                         ValueType::Map => {
                             let zero_addr = self.add_zero_val(typ);
-                            VirtualAddr::MapEntry(obj_addr, ind_addr, zero_addr)
+                            let check_key = self.t.need_check_map_key(self.t.expr_tc_type(obj));
+                            VirtualAddr::MapEntry(obj_addr, ind_addr, zero_addr, check_key)
                         }
                         _ => unreachable!(),
                     };
@@ -452,10 +580,27 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                         let rhs: Vec<(Addr, TCTypeKey)> = values
                             .iter()
                             .map(|v| {
-                                (
-                                    self.load_mode_call(|g| g.gen_expr(v)),
-                                    self.t.expr_tc_type(v),
-                                )
+                                let addr = self.load_mode_call(|g| g.gen_expr(v));
+                                let typ = self.t.expr_tc_type(v);
+                                // structs and arrays are stored by overwriting them in place,
+                                // copy them so that the stores don't change the values yet
+                                // to be stored, as in a[0], a[1] = a[1], a[0]
+                                let addr = match self.t.tc_type_to_value_type(typ) {
+                                    ValueType::Struct | ValueType::Array => {
+                                        let copy = expr_ctx!(self).inc_cur_reg();
+                                        let inst = InterInst::with_op_index(
+                                            Opcode::DUPLICATE,
+                                            copy,
+                                            addr,
+                                            Addr::Void,
+                                        );
+                                        let pos = Some(v.pos(self.ast_objs));
+                                        func_ctx!(self).emit_inst(inst, pos);
+                                        copy
+                                    }
+                                    _ => addr,
+                                };
+                                (addr, typ)
                             })
                             .collect();
                         for (i, l) in lhs.iter().enumerate() {
@@ -515,7 +660,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                     Opcode::RANGE,
                     Addr::Imm(0), // the block_end address, to be set
                     Addr::Void,
-                    tkv[0],
+                    tkv[1],
                     Some(tkv[2]),
                     types[0],
                     types[1],
@@ -549,11 +694,30 @@ impl<'a, 'c> CodeGen<'a, 'c> {
     ) {
         let mut helper = SwitchHelper::new();
         let mut has_default = false;
+        // the metadata of the clauses listing exactly one interface type, whose variable
+        // is asserted to that interface, so it has the method bindings of it
+        let mut iface_metas = vec![None; body.list.len()];
+        // the clauses listing exactly one concrete type, whose variable is the unboxed value
+        let mut concrete = vec![false; body.list.len()];
         for (i, stmt) in body.list.iter().enumerate() {
             helper.add_case_clause();
             let cc = SwitchHelper::to_case_clause(stmt);
             match &cc.list {
                 Some(l) => {
+                    if type_switch_local_vars.is_some() && l.len() == 1 {
+                        let tc_type = self.t.expr_tc_type(&l[0]);
+                        match self.t.obj_underlying_value_type(tc_type) {
+                            ValueType::Interface => {
+                                let meta = self.t.tc_type_to_meta(tc_type, self.vmctx);
+                                iface_metas[i] = Some(
+                                    func_ctx!(self).add_comparable(FfiCtx::new_metadata(meta)),
+                                );
+                            }
+                            // case nil
+                            ValueType::Void => {}
+                            _ => concrete[i] = true,
+                        }
+                    }
                     for c in l.iter() {
                         let pos = Some(stmt.pos(&self.ast_objs));
                         let addr = self.load_mode_call(|g| g.gen_expr(c));
@@ -592,11 +756,21 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             if let Some((val_src, iface_src, ref dsts, p)) = type_switch_local_vars {
                 // Specs: In clauses with a case listing exactly one type, the variable has that type; otherwise,
                 // the variable has the type of the expression in the TypeSwitchGuard.
-                let src = if default { iface_src } else { val_src };
-                fctx.emit_inst(
-                    InterInst::with_op_index(Opcode::DUPLICATE, dsts[i], src, Addr::Void),
-                    p,
-                );
+                let inst = match iface_metas[i] {
+                    Some(meta_addr) => InterInst::with_op_t_index(
+                        Opcode::TYPE_ASSERT,
+                        None,
+                        Some(ValueType::FlagA),
+                        dsts[i],
+                        iface_src,
+                        meta_addr,
+                    ),
+                    None => {
+                        let src = if concrete[i] { val_src } else { iface_src };
+                        InterInst::with_op_index(Opcode::DUPLICATE, dsts[i], src, Addr::Void)
+                    }
+                };
+                fctx.emit_inst(inst, p);
             }
             for s in cc.body.iter() {
                 self.visit_stmt(s);
@@ -626,12 +800,13 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         f_type_key: FuncTypeKey,
         recv: Option<FieldList>,
         body: &BlockStmt,
+        name: FuncName,
     ) -> (FunctionKey, GosValue) {
         let typ = &self.ast_objs.ftypes[f_type_key];
         let fmeta = self.t.tc_type_to_meta(tc_type, &mut self.vmctx);
         let f = self
             .vmctx
-            .function_with_meta(Some(self.pkg_key), fmeta, FuncFlag::Default);
+            .function_with_meta(Some(self.pkg_key), name, fmeta, FuncFlag::Default);
         let fkey = *f.as_function();
         let mut fctx = FuncCtx::new(fkey, Some(tc_type), self.consts);
         if let Some(fl) = &typ.results {
@@ -657,6 +832,42 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         (fkey, cls)
     }
 
+    /// Names a function literal after the function it's in, like Go does, the
+    /// literals of the package level vars are `glob..func1`, `glob..func2`...
+    fn func_lit_name(&mut self) -> FuncName {
+        // the functions declared are generated on top of the package ctor, the
+        // literals on top of those
+        let in_lit = self.func_ctx_stack.len() > 2;
+        let fctx = func_ctx!(self);
+        fctx.lits += 1;
+        let (lits, outer) = (fctx.lits, &self.vmctx.functions()[fctx.f_key]);
+        let name = if outer.is_ctor() {
+            format!("glob..func{}", lits)
+        } else if in_lit {
+            format!("{}.{}", outer.name.name, lits)
+        } else {
+            format!("{}.func{}", outer.name.name, lits)
+        };
+        FuncName::new(&self.pkg_path, outer.name.recv.clone(), name)
+    }
+
+    /// The receiver type of a method as the name of the method has it, `T` or `*T`
+    fn recv_type_name(&self, recv: &FieldList) -> String {
+        let mut typ = &self.ast_objs.fields[recv.list[0]].typ;
+        let mut star = "";
+        loop {
+            match typ {
+                Expr::Paren(p) => typ = &p.expr,
+                Expr::Star(s) => {
+                    star = "*";
+                    typ = &s.expr;
+                }
+                Expr::Ident(i) => return format!("{}{}", star, self.ast_objs.idents[*i].name),
+                _ => unreachable!(),
+            }
+        }
+    }
+
     fn gen_builtin_call(
         &mut self,
         func_expr: &Expr,
@@ -776,21 +987,37 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                     f.emit_inst(inst, p);
                 });
             }
-            Builtin::Delete | Builtin::Close | Builtin::Panic | Builtin::Assert => {
+            Builtin::Panic => {
+                // the value is converted to interface{}, to be asserted to its type once recovered
+                let ft = self.t.try_expr_tc_type(func_expr).unwrap();
+                let init_reg = expr_ctx!(self).cur_reg;
+                self.gen_call_params(ft, params, false);
+                let inst = InterInst::with_op_index(
+                    Opcode::PANIC,
+                    Addr::Void,
+                    Addr::Regsiter(init_reg),
+                    Addr::Void,
+                );
+                func_ctx!(self).emit_inst(inst, pos);
+            }
+            Builtin::Delete | Builtin::Close | Builtin::Assert => {
                 let addr0 = self.load_mode_call(|g| g.gen_expr(&params[0]));
                 let addr1 = if params.len() > 1 {
                     self.load_mode_call(|g| g.gen_expr(&params[1]))
                 } else {
                     Addr::Void
                 };
-                let op = match builtin {
-                    Builtin::Delete => Opcode::DELETE,
-                    Builtin::Close => Opcode::CLOSE,
-                    Builtin::Panic => Opcode::PANIC,
-                    Builtin::Assert => Opcode::ASSERT,
+                let (op, t0) = match builtin {
+                    Builtin::Delete => {
+                        let map = self.t.expr_tc_type(&params[0]);
+                        let check_key = self.t.need_check_map_key(map);
+                        (Opcode::DELETE, check_key.then_some(ValueType::FlagA))
+                    }
+                    Builtin::Close => (Opcode::CLOSE, None),
+                    Builtin::Assert => (Opcode::ASSERT, None),
                     _ => unreachable!(),
                 };
-                let inst = InterInst::with_op_index(op, Addr::Void, addr0, addr1);
+                let inst = InterInst::with_op_t_index(op, t0, None, Addr::Void, addr0, addr1);
                 func_ctx!(self).emit_inst(inst, pos);
             }
 
@@ -922,12 +1149,26 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             }
             // normal goscript function
             _ => {
+                if let CallStyle::Default = style {
+                    if let Some(val) = ConstEval::new(
+                        self.ast_objs,
+                        self.tc_objs,
+                        self.t.type_info(),
+                        &self.pure_funcs,
+                    )
+                    .call(func_expr, params)
+                    {
+                        let typ = self.t.sig_returns_tc_types(ft)[0];
+                        let val = self.t.const_value(typ, &val);
+                        let addr = func_ctx!(self).add_comparable(val);
+                        self.cur_expr_emit_direct_assign(typ, addr, pos);
+                        return;
+                    }
+                }
                 let next_sb = expr_ctx!(self).cur_reg;
                 // make sure params are at the right place
                 let return_types = self.t.sig_returns_tc_types(ft);
-                let reg_usage =
-                    return_types.len() + if self.t.is_method(func_expr) { 1 } else { 0 };
-                expr_ctx!(self).cur_reg = next_sb + reg_usage;
+                expr_ctx!(self).cur_reg = next_sb + return_types.len();
                 self.gen_call_params(ft, params, ellipsis);
                 let func_addr = self.load_mode_call(|g| g.gen_expr(func_expr));
                 func_ctx!(self).emit_call(func_addr, next_sb, style, pos);
@@ -962,8 +1203,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             if let Some(t) = variadic {
                 let variadic_count = params.len() - non_variadic_count;
                 let variadic_begin_reg = init_reg + non_variadic_count;
-                let pos =
-                    (!params.is_empty()).then(|| params[non_variadic_count].pos(&self.ast_objs));
+                let pos = params.get(non_variadic_count).map(|e| e.pos(self.ast_objs));
                 let t_elem = self.t.tc_type_to_value_type(t);
                 let begin = Addr::Regsiter(variadic_begin_reg);
                 let end = Addr::Regsiter(variadic_begin_reg + variadic_count);
@@ -990,8 +1230,19 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         pos: Option<usize>,
     ) {
         let channel_addr = self.load_mode_call(|g| g.gen_expr(channel));
+        // the VM doesn't make the zero value of a closed channel if it's discarded
+        let discard = match &expr_ctx!(self).mode {
+            ExprMode::Discard => true,
+            ExprMode::Store(va, _) => va.is_blank(),
+            ExprMode::Load => false,
+        };
         match ok_lhs_ectx {
             Some(mut ok_ectx) => {
+                let flag = if discard {
+                    ValueType::FlagC
+                } else {
+                    ValueType::FlagB
+                };
                 self.emit_double_store(
                     &mut ok_ectx,
                     Opcode::RECV,
@@ -1000,17 +1251,22 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                     val_tc_type,
                     None,
                     None,
-                    Some(ValueType::FlagB),
+                    Some(flag),
                     Addr::Void,
                     pos,
                 );
             }
             None => {
+                let flag = if discard {
+                    ValueType::FlagD
+                } else {
+                    ValueType::FlagA
+                };
                 self.cur_expr_emit_assign(val_tc_type, pos, |f, d, p| {
                     let inst = InterInst::with_op_t_index(
                         Opcode::RECV,
                         None,
-                        Some(ValueType::FlagA),
+                        Some(flag),
                         d,
                         channel_addr,
                         Addr::Void,
@@ -1041,6 +1297,10 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         };
         let zero = self.add_zero_val(val_tc_type);
         let pos = Some(container.pos(&self.ast_objs));
+        let check_key = self
+            .t
+            .need_check_map_key(self.t.expr_tc_type(container))
+            .then_some(ValueType::FlagA);
         match ok_lhs_ectx {
             Some(mut ok_ectx) => {
                 self.emit_double_store(
@@ -1050,7 +1310,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                     index_reg,
                     val_tc_type,
                     None,
-                    None,
+                    check_key,
                     Some(ValueType::FlagB),
                     zero,
                     pos,
@@ -1069,15 +1329,14 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                     ValueType::String => (Opcode::LOAD_SLICE, ValueType::Void),
                     _ => unreachable!(),
                 };
-                self.cur_expr_emit_assign(val_tc_type, pos, |f, d, p| {
-                    let inst = InterInst::with_op_t_index(
-                        op,
-                        None,
-                        Some(t1),
-                        d,
-                        container_addr,
-                        index_reg,
-                    );
+                let t0 = if op == Opcode::LOAD_MAP {
+                    check_key
+                } else {
+                    None
+                };
+                self.cur_expr_emit_load(val_tc_type, pos, |f, d, p| {
+                    let inst =
+                        InterInst::with_op_t_index(op, t0, Some(t1), d, container_addr, index_reg);
                     f.emit_inst(inst, p);
                     if op == Opcode::LOAD_MAP {
                         let inst_ex =
@@ -1116,16 +1375,23 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                 );
             }
             None => {
+                // it panics when it fails, with the type of the interface in the error
+                let iface_tc_type = self.t.expr_tc_type(expr);
+                let iface_meta = self.t.tc_type_to_meta(iface_tc_type, self.vmctx);
+                let iface_addr = func_ctx!(self).add_comparable(FfiCtx::new_metadata(iface_meta));
                 self.cur_expr_emit_assign(val_tc_type, pos, |f, d, p| {
                     let inst = InterInst::with_op_t_index(
                         Opcode::TYPE_ASSERT,
                         None,
-                        Some(ValueType::FlagA),
+                        Some(ValueType::FlagC),
                         d,
                         val_addr,
                         meta_addr,
                     );
                     f.emit_inst(inst, p);
+                    let inst_ex =
+                        InterInst::with_op_index(Opcode::VOID, Addr::Void, iface_addr, Addr::Void);
+                    f.emit_inst(inst_ex, p);
                 });
             }
         }
@@ -1147,6 +1413,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                             ValueDesc::new(fctx.f_key, ind.as_var_index() as OpIndex, t, true);
                         // for package ctors, all locals are "closed"
                         if !fctx.is_ctor(self.vmctx.functions()) {
+                            fctx.capture_local(ind.as_var_index());
                             let uv_index = fctx.add_upvalue(&entity_key, desc);
                             self.cur_expr_emit_assign(ref_tc_type, pos, |f, d, p| {
                                 let inst = InterInst::with_op_index(
@@ -1183,9 +1450,12 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                 }
             }
             Expr::Index(iexpr) => {
-                let (t0, _) = self.t.sliceable_expr_value_types(&iexpr.expr, self.vmctx);
-                let t1 = self.t.expr_value_type(&iexpr.index);
-                let lhs_addr = self.load_mode_call(|g| g.gen_expr(&iexpr.expr));
+                let (t0, tct_elem) = self.t.sliceable_expr_value_types(&iexpr.expr, self.vmctx);
+                let t1 = self.t.tc_type_to_value_type(tct_elem);
+                let mut lhs_addr = self.load_mode_call(|g| g.gen_expr(&iexpr.expr));
+                if self.t.expr_value_type(&iexpr.expr) == ValueType::Pointer {
+                    lhs_addr = self.gen_load_pointer(lhs_addr, pos);
+                }
                 let index_addr = self.load_mode_call(|g| g.gen_expr(&iexpr.index));
                 let pos = Some(iexpr.index.pos(&self.ast_objs));
                 self.cur_expr_emit_assign(ref_tc_type, pos, |f, d, p| {
@@ -1244,7 +1514,10 @@ impl<'a, 'c> CodeGen<'a, 'c> {
 
     fn gen_expr_sub_composite_lit(&mut self, expr: &Expr, tc_type: TCTypeKey) {
         match expr {
-            Expr::CompositeLit(clit) => self.gen_expr_composite_lit(clit, tc_type),
+            // only the literals with the type elided take the type of the outer literal
+            Expr::CompositeLit(clit) if clit.typ.is_none() => {
+                self.gen_expr_composite_lit(clit, tc_type)
+            }
             _ => self.gen_expr(expr),
         }
     }
@@ -1258,6 +1531,10 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         let mtype = self.vmctx.metas()[meta.key].clone();
 
         let reg_base = expr_ctx!(self).cur_reg;
+        // the elements of a literal without keys are moved into the value all at once,
+        // a struct literal without keys has all the fields
+        let keyed = clit.elts.iter().any(|x| matches!(x, Expr::KeyValue(_)))
+            || (clit.elts.is_empty() && matches!(mtype, MetadataType::Struct(_)));
         let count = match mtype {
             MetadataType::Slice(_) | MetadataType::Array(_, _) => {
                 let elem_type = match typ {
@@ -1276,10 +1553,12 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                         }
                         _ => (-1, expr),
                     };
-                    let fctx = func_ctx!(self);
-                    let key_reg = VirtualAddr::Direct(expr_ctx!(self).inc_cur_reg());
-                    let index_addr = fctx.add_comparable((key as i32).into());
-                    fctx.emit_assign(key_reg, index_addr, None, pos);
+                    if keyed {
+                        let fctx = func_ctx!(self);
+                        let key_reg = VirtualAddr::Direct(expr_ctx!(self).inc_cur_reg());
+                        let index_addr = fctx.add_comparable((key as i32).into());
+                        fctx.emit_assign(key_reg, index_addr, None, pos);
+                    }
                     let elem_reg = VirtualAddr::Direct(expr_ctx!(self).inc_cur_reg());
                     self.store_mode_call(elem_reg, Some(elem_type), |g| {
                         g.gen_expr_sub_composite_lit(elem, elem_type)
@@ -1317,10 +1596,12 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                         }
                         _ => (i, expr),
                     };
-                    let fctx = func_ctx!(self);
-                    let key_reg = VirtualAddr::Direct(expr_ctx!(self).inc_cur_reg());
-                    let index_addr = fctx.add_comparable(index.into());
-                    fctx.emit_assign(key_reg, index_addr, None, pos);
+                    if keyed {
+                        let fctx = func_ctx!(self);
+                        let key_reg = VirtualAddr::Direct(expr_ctx!(self).inc_cur_reg());
+                        let index_addr = fctx.add_comparable(index.into());
+                        fctx.emit_assign(key_reg, index_addr, None, pos);
+                    }
                     let elem_reg = VirtualAddr::Direct(expr_ctx!(self).inc_cur_reg());
                     let field_type = self.tc_objs.lobjs[fields[index]].typ().unwrap();
                     self.store_mode_call(elem_reg, Some(field_type), |g| {
@@ -1336,9 +1617,14 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         };
         let meta_addr = func_ctx!(self).add_comparable(FfiCtx::new_metadata(meta));
         self.cur_expr_emit_assign(tc_type, pos, |f, d, p| {
-            f.emit_literal(d, reg_base, count, meta_addr, p);
+            f.emit_literal(d, reg_base, count, keyed, meta_addr, p);
         });
-        expr_ctx!(self).cur_reg = reg_base + 1; //reset register allocation
+        //reset register allocation, a loaded literal is above its elements and keeps its register
+        let ectx = expr_ctx!(self);
+        ectx.cur_reg = match (&ectx.mode, ectx.load_addr) {
+            (ExprMode::Load, Addr::Regsiter(r)) => r + 1,
+            _ => reg_base + 1,
+        };
     }
 
     fn gen_load_pointer(&mut self, ptr: Addr, pos: Option<usize>) -> Addr {
@@ -1464,6 +1750,9 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         if !ok_direct {
             ectx_ex.direct_assign(fctx, ok_addr, ok_cast_i, pos);
         }
+        if op == Opcode::LOAD_MAP && val_direct && val_cast_i.is_none() {
+            self.gen_copy_loaded(val_addr, t0, pos);
+        }
     }
 
     fn get_store_addr(
@@ -1555,6 +1844,32 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         expr_ctx!(self).assign_with(func_ctx!(self), index, pos, f);
     }
 
+    /// Like cur_expr_emit_assign, for the instructions loading a value from a container,
+    /// a pointer or a variable of another scope. The loaded value is shared with where
+    /// it is loaded from, so a struct or an array stored directly to a variable is copied.
+    fn cur_expr_emit_load<F>(&mut self, rhs_type: TCTypeKey, pos: Option<Pos>, f: F)
+    where
+        F: FnOnce(&mut FuncCtx, Addr, Option<Pos>),
+    {
+        self.cur_expr_emit_assign(rhs_type, pos, f);
+        if let ExprMode::Store(VirtualAddr::Direct(d), lhs) = expr_ctx!(self).mode.clone() {
+            let casted = lhs.is_some_and(|x| self.t.should_cast_to_iface(x, rhs_type));
+            if !casted {
+                self.gen_copy_loaded(d, rhs_type, pos);
+            }
+        }
+    }
+
+    fn gen_copy_loaded(&mut self, addr: Addr, typ: TCTypeKey, pos: Option<Pos>) {
+        match self.t.tc_type_to_value_type(typ) {
+            ValueType::Struct | ValueType::Array => {
+                let inst = InterInst::with_op_index(Opcode::DUPLICATE, addr, addr, Addr::Void);
+                func_ctx!(self).emit_inst(inst, pos);
+            }
+            _ => {}
+        }
+    }
+
     fn cur_expr_emit_direct_assign(&mut self, rhs_type: TCTypeKey, src: Addr, pos: Option<Pos>) {
         let lhs = expr_ctx!(self).lhs_type();
         let index = lhs.map(|x| self.cast_to_iface_index(x, rhs_type)).flatten();
@@ -1579,26 +1894,50 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         expr_ctx!(self).direct_assign(func_ctx!(self), src, index, pos);
     }
 
-    pub fn gen_with_files(
+    pub fn gen_with_files(self, files: &Vec<ast::File>, tcpkg: TCPackageKey) -> Vec<FuncCtx<'c>> {
+        // the 0th member is the constructor
+        self.gen_files(files, tcpkg, String::new())
+    }
+
+    /// Generates the files added to a package generated before, the function
+    /// initializing the variables they declare is the member named `init`, it's
+    /// not called by the imports, which are done with the package.
+    pub fn gen_more_files(
+        self,
+        files: &Vec<ast::File>,
+        tcpkg: TCPackageKey,
+        init: String,
+    ) -> Vec<FuncCtx<'c>> {
+        self.gen_files(files, tcpkg, init)
+    }
+
+    fn gen_files(
         mut self,
         files: &Vec<ast::File>,
         tcpkg: TCPackageKey,
+        ctor: String,
     ) -> Vec<FuncCtx<'c>> {
         let pkey = self.pkg_key;
+        let tc_pkg = &self.tc_objs.pkgs[tcpkg];
+        self.pkg_path = match tc_pkg.name().as_deref() {
+            Some("main") => "main".to_owned(),
+            _ => tc_pkg.path().clone(),
+        };
+        let name = FuncName::new(&self.pkg_path, None, "init".to_owned());
         let fmeta = self.vmctx.prim_meta().default_sig;
         let f = self
             .vmctx
-            .function_with_meta(Some(pkey), fmeta, FuncFlag::PkgCtor);
+            .function_with_meta(Some(pkey), name, fmeta, FuncFlag::PkgCtor);
         let fkey = *f.as_function();
-        // the 0th member is the constructor
-        self.vmctx.packages_mut()[pkey].add_member(
-            String::new(),
-            CodeGenVMCtx::new_closure_static(fkey, None, fmeta),
-        );
+        self.vmctx.packages_mut()[pkey]
+            .add_member(ctor, CodeGenVMCtx::new_closure_static(fkey, None, fmeta));
         self.pkg_key = pkey;
         self.func_ctx_stack
             .push(FuncCtx::new(fkey, None, self.consts));
 
+        self.static_ifaces =
+            StaticDispatch::new(self.ast_objs, self.tc_objs, self.t.type_info()).analyze(files);
+        self.pure_funcs = pure_funcs(self.ast_objs, self.tc_objs, self.t.type_info(), files);
         let (names, vars) = self.pkg_helper.sort_var_decls(files, self.t.type_info());
         self.add_pkg_var_member(pkey, &names);
 
@@ -1631,12 +1970,10 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
         match va {
             VirtualAddr::Direct(addr) => self.cur_expr_emit_direct_assign(tc_type, addr, pos),
             VirtualAddr::PackageMember(pkg, index) => {
-                self.cur_expr_emit_assign(tc_type, pos, |f, d, p| {
-                    f.emit_load_pkg(d, pkg, index, p)
-                });
+                self.cur_expr_emit_load(tc_type, pos, |f, d, p| f.emit_load_pkg(d, pkg, index, p));
             }
             VirtualAddr::UpValue(uv) => {
-                self.cur_expr_emit_assign(tc_type, pos, |f, d, p| {
+                self.cur_expr_emit_load(tc_type, pos, |f, d, p| {
                     let inst = InterInst::with_op_index(Opcode::LOAD_UP_VALUE, d, uv, Addr::Void);
                     f.emit_inst(inst, p);
                 });
@@ -1659,7 +1996,8 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
     /// Add function as a const and then generate a closure of it
     fn visit_expr_func_lit(&mut self, this: &Expr, flit: &FuncLit) {
         let tc_type = self.t.expr_tc_type(this);
-        let (fkey, _) = self.gen_func_def(tc_type, flit.typ, None, &flit.body);
+        let name = self.func_lit_name();
+        let (fkey, _) = self.gen_func_def(tc_type, flit.typ, None, &flit.body, name);
         let fctx = func_ctx!(self);
         let addr = fctx.add_comparable(FfiCtx::new_function(fkey));
         let pos = Some(flit.body.l_brace);
@@ -1696,6 +2034,9 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
         let indices = indices.clone();
         match &stype {
             SelectionType::MethodNonPtrRecv | SelectionType::MethodPtrRecv => {
+                if self.gen_static_bind_method(lhs_expr, ident, expr_type, pos) {
+                    return;
+                }
                 let index_count = indices.len();
                 let final_index = indices[index_count - 1];
                 let embedded_indices = Vec::from_iter(indices[..index_count - 1].iter().cloned());
@@ -1712,10 +2053,13 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
                     if !lhs_has_embedded {
                         self.load_mode_call(|g| g.gen_expr_ref(lhs_expr, recv_type))
                     } else {
-                        let lhs_addr = self.load_mode_call(|g| g.gen_expr(lhs_expr));
+                        let mut lhs_addr = self.load_mode_call(|g| g.gen_expr(lhs_expr));
                         let rt_indices = embedded_indices.iter().map(|x| *x as OpIndex).collect();
                         let (op, index) =
                             self.get_struct_field_op_index(rt_indices, Opcode::REF_STRUCT_FIELD);
+                        if op == Opcode::REF_STRUCT_FIELD && lhs_meta.ptr_depth > 0 {
+                            lhs_addr = self.gen_load_pointer(lhs_addr, pos);
+                        }
                         let result_addr = expr_ctx!(self).inc_cur_reg();
                         let inst =
                             InterInst::with_op_index(op, result_addr, lhs_addr, Addr::Imm(index));
@@ -1774,7 +2118,7 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
                 if op == Opcode::LOAD_STRUCT && lhs_meta.ptr_depth > 0 {
                     lhs_addr = self.gen_load_pointer(lhs_addr, pos);
                 }
-                self.cur_expr_emit_assign(expr_type, pos, |f, d, p| {
+                self.cur_expr_emit_load(expr_type, pos, |f, d, p| {
                     let inst = InterInst::with_op_index(op, d, lhs_addr, Addr::Imm(index));
                     f.emit_inst(inst, p);
                 });
@@ -1798,7 +2142,10 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
         let (t0, tct_elem) = self.t.sliceable_expr_value_types(expr, self.vmctx);
         let pos = Some(expr.pos(&self.ast_objs));
 
-        let slice_array_addr = self.load_mode_call(|g| g.gen_expr(expr));
+        let mut slice_array_addr = self.load_mode_call(|g| g.gen_expr(expr));
+        if self.t.expr_value_type(expr) == ValueType::Pointer {
+            slice_array_addr = self.gen_load_pointer(slice_array_addr, pos);
+        }
         let low_addr = match low {
             None => func_ctx!(self).add_comparable(0isize.into()),
             Some(e) => self.load_mode_call(|g| g.gen_expr(e)),
@@ -1844,7 +2191,7 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
                 let pos = Some(expr.pos(&self.ast_objs));
                 let typ = self.t.expr_tc_type(this);
                 let addr = self.load_mode_call(|g| g.gen_expr(expr));
-                self.cur_expr_emit_assign(typ, pos, |f, d, p| {
+                self.cur_expr_emit_load(typ, pos, |f, d, p| {
                     let inst = InterInst::with_op_index(Opcode::LOAD_POINTER, d, addr, Addr::Void);
                     f.emit_inst(inst, p);
                 });
@@ -2028,7 +2375,16 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
         }
         let tc_type = self.t.obj_def_tc_type(decl.name);
         let stmt = decl.body.as_ref().unwrap();
-        let (fkey, cls) = self.gen_func_def(tc_type, decl.typ, decl.recv.clone(), stmt);
+        let recv = decl.recv.as_ref().map(|r| self.recv_type_name(r));
+        let name = match (self.ast_objs.idents[decl.name].name.as_str(), &recv) {
+            ("init", None) => {
+                let inits = self.vmctx.packages()[self.pkg_key].init_func_count();
+                format!("init.{}", inits)
+            }
+            (name, _) => name.to_owned(),
+        };
+        let name = FuncName::new(&self.pkg_path, recv, name);
+        let (fkey, cls) = self.gen_func_def(tc_type, decl.typ, decl.recv.clone(), stmt, name);
         // this is a struct method
         if let Some(self_ident) = &decl.recv {
             let field = &self.ast_objs.fields[self_ident.list[0]];
@@ -2111,9 +2467,24 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
     fn visit_stmt_return(&mut self, rstmt: &ReturnStmt) {
         if !rstmt.results.is_empty() {
             let types = self.t.sig_returns_tc_types(func_ctx!(self).tc_key.unwrap());
-            for (i, expr) in rstmt.results.iter().enumerate() {
-                let va = VirtualAddr::Direct(Addr::LocalVar(i));
-                self.store_mode_call(va, Some(types[i]), |g| g.gen_expr(expr));
+            if rstmt.results.len() < types.len() {
+                // return with a function call that returns multiple values
+                let expr = &rstmt.results[0];
+                self.discard_mode_call(|g| g.gen_expr(expr));
+                let reg_begin = expr_ctx!(self).cur_reg;
+                let tuple_types = self.t.expr_tuple_tc_types(expr);
+                let pos = Some(expr.pos(self.ast_objs));
+                for (i, t) in tuple_types.into_iter().enumerate() {
+                    let va = VirtualAddr::Direct(Addr::LocalVar(i));
+                    self.store_mode_call(va, Some(types[i]), |g| {
+                        g.cur_expr_emit_direct_assign(t, Addr::Regsiter(reg_begin + i), pos)
+                    });
+                }
+            } else {
+                for (i, expr) in rstmt.results.iter().enumerate() {
+                    let va = VirtualAddr::Direct(Addr::LocalVar(i));
+                    self.store_mode_call(va, Some(types[i]), |g| g.gen_expr(expr));
+                }
             }
         }
         func_ctx!(self).emit_return(None, Some(rstmt.ret), &self.vmctx.functions());
@@ -2145,9 +2516,11 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
     }
 
     fn visit_stmt_block(&mut self, bstmt: &BlockStmt) {
+        let begin = func_ctx!(self).local_count();
         for stmt in bstmt.list.iter() {
             self.visit_stmt(stmt);
         }
+        func_ctx!(self).end_local_scopes(begin);
     }
 
     fn visit_stmt_if(&mut self, ifstmt: &IfStmt) {
@@ -2396,7 +2769,10 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
         } else {
             None
         };
+        let body_locals = func_ctx!(self).local_count();
         self.visit_stmt_block(&fstmt.body);
+        // the variables declared in the body are new in each iteration
+        let close_marker = func_ctx!(self).emit_close_up_values(body_locals, Some(fstmt.for_pos));
         let continue_marker = if let Some(post) = &fstmt.post {
             // "continue" jumps to post statements
             let m = func_ctx!(self).next_code_index();
@@ -2406,6 +2782,7 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
             // "continue" jumps to top directly if no post statements
             top_marker
         };
+        let continue_marker = close_marker.unwrap_or(continue_marker);
 
         // jump to the top
         let fctx = func_ctx!(self);
@@ -2424,6 +2801,10 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
 
         self.branch_helper
             .leave_block(func_ctx!(self), Some(continue_marker));
+        // "break" jumps here, leaving the variables of the last iteration open
+        if close_marker.is_some() {
+            func_ctx!(self).emit_close_up_values(body_locals, Some(fstmt.for_pos));
+        }
     }
 
     fn visit_stmt_range(&mut self, rstmt: &RangeStmt) {
@@ -2438,7 +2819,10 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
             .gen_assign(&rstmt.token, &lhs, RightHandSide::Range(&rstmt.expr))
             .unwrap();
 
+        let body_locals = func_ctx!(self).local_count();
         self.visit_stmt_block(&rstmt.body);
+        // the variables declared in the body are new in each iteration
+        let close_marker = func_ctx!(self).emit_close_up_values(body_locals, Some(rstmt.token_pos));
         // jump to the top
         let fctx = func_ctx!(self);
         let offset = -fctx.offset(marker) - 1;
@@ -2451,7 +2835,11 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
         );
 
         self.branch_helper
-            .leave_block(func_ctx!(self), Some(marker));
+            .leave_block(func_ctx!(self), Some(close_marker.unwrap_or(marker)));
+        // "break" jumps here, leaving the variables of the last iteration open
+        if close_marker.is_some() {
+            func_ctx!(self).emit_close_up_values(body_locals, Some(rstmt.token_pos));
+        }
     }
 
     fn visit_expr_stmt(&mut self, e: &Expr) {
@@ -2490,6 +2878,15 @@ impl<K: Eq + Hash + Ord + Clone> Selector<K> {
         self.vec
     }
 
+    /// The keys added since there were `len` of them
+    pub fn added_since(&self, len: usize) -> &[K] {
+        &self.vec[len..]
+    }
+
+    pub fn len(&self) -> usize {
+        self.vec.len()
+    }
+
     pub fn add(&mut self, key: K) -> OpIndex {
         match self.mapping.get(&key) {
             Some(v) => *v,
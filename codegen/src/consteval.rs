@@ -0,0 +1,561 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use go_parser::ast::*;
+use go_parser::{AstObjects, FuncDeclKey, Map, Token};
+use go_types::{
+    check::TypeInfo, typ::BasicInfo, typ::BasicType, ConstValue, ObjKey as TCObjKey, OperandMode,
+    TCObjects, TypeKey as TCTypeKey,
+};
+
+// the statements a call may run, and the calls it may make at once
+const MAX_STEPS: usize = 10000;
+const MAX_DEPTH: usize = 32;
+
+/// The functions of a package the calls to which may be evaluated at compile time.
+///
+/// They are the functions that aren't methods and return one value of a boolean,
+/// integer or string type. A call is evaluated when all its arguments are constants
+/// and, as it runs, it only uses its parameters and locals of those types, with the
+/// statements and expressions `ConstEval` knows, and calls such functions.
+/// Otherwise, or when the call would panic or an integer would overflow, it's left
+/// to run at runtime.
+pub(crate) fn pure_funcs(
+    ast_objs: &AstObjects,
+    tc_objs: &TCObjects,
+    ti: &TypeInfo,
+    files: &[File],
+) -> Map<TCObjKey, FuncDeclKey> {
+    let mut funcs = Map::new();
+    for f in files.iter() {
+        for d in f.decls.iter() {
+            if let Decl::Func(fkey) = d {
+                let fdecl = &ast_objs.fdecls[*fkey];
+                if fdecl.recv.is_some() || fdecl.body.is_none() {
+                    continue;
+                }
+                if let Some(Some(okey)) = ti.defs.get(&fdecl.name) {
+                    let sig = tc_objs.types[tc_objs.lobjs[*okey].typ().unwrap()]
+                        .try_as_signature()
+                        .unwrap();
+                    let results = tc_objs.types[sig.results()].try_as_tuple().unwrap();
+                    if !sig.variadic()
+                        && results.vars().len() == 1
+                        && is_basic(tc_objs, tc_objs.lobjs[results.vars()[0]].typ().unwrap())
+                    {
+                        funcs.insert(*okey, *fkey);
+                    }
+                }
+            }
+        }
+    }
+    funcs
+}
+
+fn is_basic(tc_objs: &TCObjects, typ: TCTypeKey) -> bool {
+    let t = &tc_objs.types[typ];
+    t.is_boolean(tc_objs) || t.is_integer(tc_objs) || t.is_string(tc_objs)
+}
+
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(ConstValue),
+}
+
+/// Evaluates the calls to the functions found by `pure_funcs`, returns None if a call
+/// can't be evaluated.
+pub(crate) struct ConstEval<'a> {
+    ast_objs: &'a AstObjects,
+    tc_objs: &'a TCObjects,
+    ti: &'a TypeInfo,
+    funcs: &'a Map<TCObjKey, FuncDeclKey>,
+    steps: usize,
+    depth: usize,
+}
+
+impl<'a> ConstEval<'a> {
+    pub fn new(
+        ast_objs: &'a AstObjects,
+        tc_objs: &'a TCObjects,
+        ti: &'a TypeInfo,
+        funcs: &'a Map<TCObjKey, FuncDeclKey>,
+    ) -> ConstEval<'a> {
+        ConstEval {
+            ast_objs,
+            tc_objs,
+            ti,
+            funcs,
+            steps: 0,
+            depth: 0,
+        }
+    }
+
+    /// Evaluates `func(args...)`, if `func` is one of the functions and all the
+    /// arguments are constants.
+    pub fn call(&mut self, func: &Expr, args: &[Expr]) -> Option<ConstValue> {
+        let vals = args
+            .iter()
+            .map(|a| self.const_of(a))
+            .collect::<Option<Vec<_>>>()?;
+        self.call_func(func, vals)
+    }
+
+    fn call_func(&mut self, func: &Expr, args: Vec<ConstValue>) -> Option<ConstValue> {
+        let okey = match func {
+            Expr::Ident(ikey) => *self.ti.uses.get(ikey)?,
+            Expr::Paren(p) => return self.call_func(&p.expr, args),
+            _ => return None,
+        };
+        let fdecl = &self.ast_objs.fdecls[*self.funcs.get(&okey)?];
+        if self.depth == MAX_DEPTH {
+            return None;
+        }
+        let sig = self.tc_objs.types[self.tc_objs.lobjs[okey].typ().unwrap()]
+            .try_as_signature()
+            .unwrap();
+        let params = self.tc_objs.types[sig.params()].try_as_tuple().unwrap();
+        let result = self.tc_objs.types[sig.results()]
+            .try_as_tuple()
+            .unwrap()
+            .vars()[0];
+        let result_type = self.tc_objs.lobjs[result].typ().unwrap();
+        let mut env = Map::new();
+        for (p, v) in params.vars().iter().zip(args) {
+            let typ = self.tc_objs.lobjs[*p].typ().unwrap();
+            if !is_basic(self.tc_objs, typ) {
+                return None;
+            }
+            env.insert(*p, self.typed(v, typ)?);
+        }
+
+        self.depth += 1;
+        let flow = self.block(&fdecl.body.as_ref().unwrap().list, &mut env);
+        self.depth -= 1;
+        match flow? {
+            Flow::Return(v) => self.typed(v, result_type),
+            _ => None,
+        }
+    }
+
+    fn const_of(&self, expr: &Expr) -> Option<ConstValue> {
+        let tv = self.ti.types.get(&expr.id())?;
+        match &tv.mode {
+            OperandMode::Constant(v) => Some(v.clone()),
+            _ => None,
+        }
+    }
+
+    fn expr_type(&self, expr: &Expr) -> Option<TCTypeKey> {
+        self.ti.types.get(&expr.id()).map(|tv| tv.typ)
+    }
+
+    fn basic_type(&self, typ: TCTypeKey) -> Option<BasicType> {
+        self.tc_objs.types[typ]
+            .underlying_val(self.tc_objs)
+            .try_as_basic()
+            .map(|b| b.typ())
+    }
+
+    /// Makes `v` a value of `typ`, None if it doesn't fit
+    fn typed(&self, v: ConstValue, typ: TCTypeKey) -> Option<ConstValue> {
+        let basic = self.tc_objs.types[typ]
+            .underlying_val(self.tc_objs)
+            .try_as_basic()?;
+        match basic.info() {
+            BasicInfo::IsInteger => {
+                let v = v.to_int().into_owned();
+                if !v.is_int() || !v.representable(basic, None) {
+                    return None;
+                }
+                Some(v)
+            }
+            BasicInfo::IsBoolean | BasicInfo::IsString => Some(v),
+            _ => None,
+        }
+    }
+
+    fn zero(&self, typ: TCTypeKey) -> Option<ConstValue> {
+        let t = &self.tc_objs.types[typ];
+        if t.is_integer(self.tc_objs) {
+            Some(ConstValue::with_i64(0))
+        } else if t.is_boolean(self.tc_objs) {
+            Some(ConstValue::with_bool(false))
+        } else if t.is_string(self.tc_objs) {
+            Some(ConstValue::with_str(String::new()))
+        } else {
+            None
+        }
+    }
+
+    fn step(&mut self) -> Option<()> {
+        self.steps += 1;
+        (self.steps <= MAX_STEPS).then_some(())
+    }
+
+    fn block(&mut self, stmts: &[Stmt], env: &mut Map<TCObjKey, ConstValue>) -> Option<Flow> {
+        for s in stmts.iter() {
+            match self.stmt(s, env)? {
+                Flow::Next => {}
+                flow => return Some(flow),
+            }
+        }
+        Some(Flow::Next)
+    }
+
+    fn opt_stmt(
+        &mut self,
+        stmt: &Option<Stmt>,
+        env: &mut Map<TCObjKey, ConstValue>,
+    ) -> Option<Flow> {
+        match stmt {
+            Some(s) => self.stmt(s, env),
+            None => Some(Flow::Next),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, env: &mut Map<TCObjKey, ConstValue>) -> Option<Flow> {
+        self.step()?;
+        match stmt {
+            Stmt::Empty(_) => Some(Flow::Next),
+            Stmt::Block(b) => self.block(&b.list, env),
+            Stmt::Return(r) => match r.results.len() {
+                1 => Some(Flow::Return(self.expr(&r.results[0], env)?)),
+                _ => None,
+            },
+            Stmt::Branch(b) if b.label.is_none() => match b.token {
+                Token::BREAK => Some(Flow::Break),
+                Token::CONTINUE => Some(Flow::Continue),
+                _ => None,
+            },
+            Stmt::Decl(decl) => match &**decl {
+                Decl::Gen(gdecl) if gdecl.token == Token::VAR => {
+                    for s in gdecl.specs.iter() {
+                        let vs = match &self.ast_objs.specs[*s] {
+                            Spec::Value(vs) => vs,
+                            _ => return None,
+                        };
+                        for (i, name) in vs.names.iter().enumerate() {
+                            let okey = (*self.ti.defs.get(name)?)?;
+                            let typ = self.tc_objs.lobjs[okey].typ().unwrap();
+                            let val = match vs.values.get(i) {
+                                Some(e) => self.expr(e, env)?,
+                                None if vs.values.is_empty() => self.zero(typ)?,
+                                None => return None,
+                            };
+                            env.insert(okey, self.typed(val, typ)?);
+                        }
+                    }
+                    Some(Flow::Next)
+                }
+                // the constants are in the type info already
+                Decl::Gen(gdecl) if gdecl.token == Token::CONST => Some(Flow::Next),
+                _ => None,
+            },
+            Stmt::Assign(akey) => {
+                let astmt = &self.ast_objs.a_stmts[*akey];
+                if astmt.lhs.len() != astmt.rhs.len() {
+                    return None;
+                }
+                // all the values are taken before any is assigned
+                let vals = astmt
+                    .lhs
+                    .iter()
+                    .zip(astmt.rhs.iter())
+                    .map(|(l, r)| {
+                        let val = self.expr(r, env)?;
+                        match Self::assign_op(&astmt.token) {
+                            Some(op) => self.binary(l, &op, val, env),
+                            None => Some(val),
+                        }
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                for (l, val) in astmt.lhs.iter().zip(vals) {
+                    self.assign(l, &astmt.token, val, env)?;
+                }
+                Some(Flow::Next)
+            }
+            Stmt::IncDec(s) => {
+                let op = match s.token {
+                    Token::INC => Token::ADD,
+                    _ => Token::SUB,
+                };
+                let val = self.binary(&s.expr, &op, ConstValue::with_i64(1), env)?;
+                self.assign(&s.expr, &Token::ASSIGN, val, env)?;
+                Some(Flow::Next)
+            }
+            Stmt::If(s) => {
+                self.opt_stmt(&s.init, env)?;
+                if self.expr(&s.cond, env)?.bool_as_bool() {
+                    self.block(&s.body.list, env)
+                } else {
+                    self.opt_stmt(&s.els, env)
+                }
+            }
+            Stmt::Switch(s) => {
+                self.opt_stmt(&s.init, env)?;
+                let tag = match &s.tag {
+                    Some(t) => Some(self.expr(t, env)?),
+                    None => None,
+                };
+                let mut default = None;
+                for c in s.body.list.iter() {
+                    let clause = match c {
+                        Stmt::Case(c) => c,
+                        _ => return None,
+                    };
+                    let list = match &clause.list {
+                        Some(l) => l,
+                        None => {
+                            default = Some(clause);
+                            continue;
+                        }
+                    };
+                    for e in list.iter() {
+                        let v = self.expr(e, env)?;
+                        let hit = match &tag {
+                            Some(t) => ConstValue::compare(t, &Token::EQL, &v),
+                            None => v.bool_as_bool(),
+                        };
+                        if hit {
+                            return self.case_body(&clause.body, env);
+                        }
+                    }
+                }
+                match default {
+                    Some(clause) => self.case_body(&clause.body, env),
+                    None => Some(Flow::Next),
+                }
+            }
+            Stmt::For(s) => {
+                self.opt_stmt(&s.init, env)?;
+                loop {
+                    self.step()?;
+                    if let Some(cond) = &s.cond {
+                        if !self.expr(cond, env)?.bool_as_bool() {
+                            break;
+                        }
+                    }
+                    match self.block(&s.body.list, env)? {
+                        Flow::Break => break,
+                        Flow::Return(v) => return Some(Flow::Return(v)),
+                        Flow::Next | Flow::Continue => {}
+                    }
+                    self.opt_stmt(&s.post, env)?;
+                }
+                Some(Flow::Next)
+            }
+            _ => None,
+        }
+    }
+
+    fn case_body(&mut self, body: &[Stmt], env: &mut Map<TCObjKey, ConstValue>) -> Option<Flow> {
+        match self.block(body, env)? {
+            // it ends the switch
+            Flow::Break => Some(Flow::Next),
+            flow => Some(flow),
+        }
+    }
+
+    fn assign_op(token: &Token) -> Option<Token> {
+        match token {
+            Token::ADD_ASSIGN => Some(Token::ADD),
+            Token::SUB_ASSIGN => Some(Token::SUB),
+            Token::MUL_ASSIGN => Some(Token::MUL),
+            Token::QUO_ASSIGN => Some(Token::QUO),
+            Token::REM_ASSIGN => Some(Token::REM),
+            Token::AND_ASSIGN => Some(Token::AND),
+            Token::OR_ASSIGN => Some(Token::OR),
+            Token::XOR_ASSIGN => Some(Token::XOR),
+            Token::SHL_ASSIGN => Some(Token::SHL),
+            Token::SHR_ASSIGN => Some(Token::SHR),
+            Token::AND_NOT_ASSIGN => Some(Token::AND_NOT),
+            _ => None,
+        }
+    }
+
+    fn assign(
+        &mut self,
+        lhs: &Expr,
+        token: &Token,
+        val: ConstValue,
+        env: &mut Map<TCObjKey, ConstValue>,
+    ) -> Option<()> {
+        let ikey = match lhs {
+            Expr::Ident(ikey) => ikey,
+            _ => return None,
+        };
+        if self.ast_objs.idents[*ikey].is_blank() {
+            return Some(());
+        }
+        let okey = match token {
+            Token::DEFINE => match self.ti.defs.get(ikey) {
+                Some(Some(okey)) => *okey,
+                // redeclared
+                _ => *self.ti.uses.get(ikey)?,
+            },
+            _ => *self.ti.uses.get(ikey)?,
+        };
+        // only the locals of the call, not the package variables
+        if *token != Token::DEFINE && !env.contains_key(&okey) {
+            return None;
+        }
+        let typ = self.tc_objs.lobjs[okey].typ().unwrap();
+        if !is_basic(self.tc_objs, typ) {
+            return None;
+        }
+        env.insert(okey, self.typed(val, typ)?);
+        Some(())
+    }
+
+    fn expr(&mut self, expr: &Expr, env: &mut Map<TCObjKey, ConstValue>) -> Option<ConstValue> {
+        if let Some(v) = self.const_of(expr) {
+            return Some(v);
+        }
+        match expr {
+            Expr::Ident(ikey) => env.get(self.ti.uses.get(ikey)?).cloned(),
+            Expr::Paren(p) => self.expr(&p.expr, env),
+            Expr::Unary(u) => {
+                let v = self.expr(&u.expr, env)?;
+                let typ = self.expr_type(expr)?;
+                let v = match u.op {
+                    Token::ADD => v,
+                    Token::SUB if v.is_int() => ConstValue::unary_op(&Token::SUB, &v, 0),
+                    Token::NOT if self.tc_objs.types[typ].is_boolean(self.tc_objs) => {
+                        ConstValue::unary_op(&Token::NOT, &v, 0)
+                    }
+                    Token::XOR if v.is_int() => {
+                        let prec = match self.basic_type(typ)? {
+                            BasicType::Uint8 | BasicType::Byte => 1,
+                            BasicType::Uint16 => 2,
+                            BasicType::Uint32 => 4,
+                            BasicType::Uint | BasicType::Uint64 | BasicType::Uintptr => 8,
+                            _ => 0,
+                        };
+                        ConstValue::unary_op(&Token::XOR, &v, prec)
+                    }
+                    _ => return None,
+                };
+                self.typed(v, typ)
+            }
+            Expr::Binary(b) => match b.op {
+                Token::LAND | Token::LOR => {
+                    let a = self.expr(&b.expr_a, env)?.bool_as_bool();
+                    if a == (b.op == Token::LOR) {
+                        Some(ConstValue::with_bool(a))
+                    } else {
+                        Some(ConstValue::with_bool(
+                            self.expr(&b.expr_b, env)?.bool_as_bool(),
+                        ))
+                    }
+                }
+                Token::EQL | Token::NEQ | Token::LSS | Token::LEQ | Token::GTR | Token::GEQ => {
+                    let x = self.expr(&b.expr_a, env)?;
+                    let y = self.expr(&b.expr_b, env)?;
+                    Some(ConstValue::with_bool(ConstValue::compare(&x, &b.op, &y)))
+                }
+                _ => {
+                    let y = self.expr(&b.expr_b, env)?;
+                    self.binary(&b.expr_a, &b.op, y, env)
+                }
+            },
+            Expr::Call(call) => {
+                if let OperandMode::TypeExpr = self.ti.types.get(&call.func.id())?.mode {
+                    // conversions between integer types, when the values fit
+                    let typ = self.expr_type(expr)?;
+                    let arg = call.args.first()?;
+                    if !self.tc_objs.types[typ].is_integer(self.tc_objs)
+                        || !self.tc_objs.types[self.expr_type(arg)?].is_integer(self.tc_objs)
+                    {
+                        return None;
+                    }
+                    let v = self.expr(arg, env)?;
+                    return self.typed(v, typ);
+                }
+                if let OperandMode::Builtin(_) = self.ti.types.get(&call.func.id())?.mode {
+                    return match (&call.func, call.args.first()) {
+                        (Expr::Ident(ikey), Some(arg))
+                            if self.ast_objs.idents[*ikey].name == "len"
+                                && self.tc_objs.types[self.expr_type(arg)?]
+                                    .is_string(self.tc_objs) =>
+                        {
+                            let s = self.expr(arg, env)?.str_as_string();
+                            Some(ConstValue::with_i64(s.len() as i64))
+                        }
+                        _ => None,
+                    };
+                }
+                if call.ellipsis.is_some() {
+                    return None;
+                }
+                let args = call
+                    .args
+                    .iter()
+                    .map(|a| self.expr(a, env))
+                    .collect::<Option<Vec<_>>>()?;
+                self.call_func(&call.func, args)
+            }
+            Expr::Index(ind) => {
+                if !self.tc_objs.types[self.expr_type(&ind.expr)?].is_string(self.tc_objs) {
+                    return None;
+                }
+                let s = self.expr(&ind.expr, env)?.str_as_string();
+                let (i, exact) = self.expr(&ind.index, env)?.to_int().int_as_i64();
+                if !exact || i < 0 || i as usize >= s.len() {
+                    return None;
+                }
+                Some(ConstValue::with_u64(s.as_bytes()[i as usize] as u64))
+            }
+            _ => None,
+        }
+    }
+
+    /// Evaluates `lhs op y`, for the arithmetic operators
+    fn binary(
+        &mut self,
+        lhs: &Expr,
+        op: &Token,
+        y: ConstValue,
+        env: &mut Map<TCObjKey, ConstValue>,
+    ) -> Option<ConstValue> {
+        let x = self.expr(lhs, env)?;
+        let typ = self.expr_type(lhs)?;
+        let t = &self.tc_objs.types[typ];
+        let v = if t.is_string(self.tc_objs) {
+            match op {
+                Token::ADD => ConstValue::binary_op(&x, op, &y),
+                _ => return None,
+            }
+        } else if t.is_integer(self.tc_objs) {
+            let y = y.to_int().into_owned();
+            if !x.is_int() || !y.is_int() {
+                return None;
+            }
+            match op {
+                Token::SHL | Token::SHR => {
+                    let (s, exact) = y.int_as_u64();
+                    if !exact || s >= 64 {
+                        return None;
+                    }
+                    ConstValue::shift(&x, op, s as usize)
+                }
+                Token::QUO | Token::REM if y.sign() == 0 => return None,
+                Token::QUO => ConstValue::binary_op(&x, &Token::QUO_ASSIGN, &y),
+                Token::ADD
+                | Token::SUB
+                | Token::MUL
+                | Token::REM
+                | Token::AND
+                | Token::OR
+                | Token::XOR
+                | Token::AND_NOT => ConstValue::binary_op(&x, op, &y),
+                _ => return None,
+            }
+        } else {
+            return None;
+        };
+        self.typed(v, typ)
+    }
+}
@@ -3,6 +3,7 @@
 // license that can be found in the LICENSE file.
 
 use super::consts::Consts;
+use super::peephole;
 use super::types::TypeLookup;
 use go_parser::ast::*;
 use go_parser::{AstObjects, IdentKey, Map, Pos};
@@ -71,10 +72,13 @@ impl Addr {
 #[derive(Clone, Debug)]
 pub enum VirtualAddr {
     Direct(Addr),
+    /// A local struct or array variable assigned as a whole, which is overwritten in place
+    LocalInPlace(Addr),
     UpValue(Addr),
     SliceEntry(Addr, Addr),
     ArrayEntry(Addr, Addr),
-    MapEntry(Addr, Addr, Addr),
+    /// (map, key, zero value, whether the key has to be checked for being hashable)
+    MapEntry(Addr, Addr, Addr, bool),
     StructMember(Addr, Addr),
     StructEmbedded(Addr, Addr),
     PackageMember(Addr, Addr),
@@ -360,11 +364,18 @@ pub(crate) struct FuncCtx<'c> {
     code: Vec<InterInst>,
     pos: Vec<Option<usize>>,
     pub up_ptrs: Vec<ValueDesc>,
-    local_zeros: Vec<GosValue>,
+    /// The types of the locals, for their zero values
+    local_metas: Vec<Meta>,
 
     entities: Map<TCObjKey, Addr>,
     uv_entities: Map<TCObjKey, Addr>,
     local_alloc: usize,
+    // the locals shared with closures or pointers
+    captured: Vec<usize>,
+    // the named locals for the debug info, the ones with open scopes end at u32::MAX
+    local_names: Vec<LocalVarInfo>,
+    /// The function literals in the function so far, they are numbered for their names
+    pub lits: usize,
 }
 
 impl<'a> FuncCtx<'a> {
@@ -376,10 +387,13 @@ impl<'a> FuncCtx<'a> {
             code: vec![],
             pos: vec![],
             up_ptrs: vec![],
-            local_zeros: vec![],
+            local_metas: vec![],
             entities: Map::new(),
             uv_entities: Map::new(),
             local_alloc: 0,
+            captured: vec![],
+            local_names: vec![],
+            lits: 0,
         }
     }
 
@@ -431,7 +445,7 @@ impl<'a> FuncCtx<'a> {
         addr
     }
 
-    pub fn add_local(&mut self, entity: Option<TCObjKey>, zero_val: Option<GosValue>) -> Addr {
+    pub fn add_local(&mut self, entity: Option<TCObjKey>, meta: Option<Meta>) -> Addr {
         let addr = Addr::LocalVar(self.local_alloc);
         if let Some(key) = entity {
             let old = self.entities.insert(key, addr);
@@ -439,12 +453,61 @@ impl<'a> FuncCtx<'a> {
         };
         self.local_alloc += 1;
 
-        if let Some(zero) = zero_val {
-            self.local_zeros.push(zero);
+        if let Some(meta) = meta {
+            self.local_metas.push(meta);
         }
         addr
     }
 
+    pub fn local_count(&self) -> usize {
+        self.local_alloc
+    }
+
+    /// Names the local at `addr` for the debug info, it's in scope from the next
+    /// instruction on, until `end_local_scopes` is called for it
+    pub fn name_local(&mut self, name: &str, addr: Addr) {
+        self.local_names.push(LocalVarInfo {
+            name: name.to_owned(),
+            slot: addr.as_var_index() as OpIndex,
+            begin: self.code.len() as u32,
+            end: u32::MAX,
+        });
+    }
+
+    /// Ends the scopes of the named locals allocated since `begin`
+    pub fn end_local_scopes(&mut self, begin: usize) {
+        let end = self.code.len() as u32;
+        for var in self.local_names.iter_mut() {
+            if var.slot as usize >= begin && var.end == u32::MAX {
+                var.end = end;
+            }
+        }
+    }
+
+    /// Marks the local at `index` as referred to by an upvalue
+    pub fn capture_local(&mut self, index: usize) {
+        if !self.captured.contains(&index) {
+            self.captured.push(index);
+        }
+    }
+
+    /// Emits CLOSE_UP_VALUES for the locals allocated since `begin`, if any of them
+    /// is referred to by an upvalue, returns the index of the instruction.
+    pub fn emit_close_up_values(&mut self, begin: usize, pos: Option<usize>) -> Option<usize> {
+        let end = self.local_alloc;
+        if !self.captured.iter().any(|&i| i >= begin && i < end) {
+            return None;
+        }
+        let inst = InterInst::with_op_index(
+            Opcode::CLOSE_UP_VALUES,
+            Addr::Void,
+            Addr::Imm(begin as OpIndex),
+            Addr::Imm(end as OpIndex),
+        );
+        self.emit_inst(inst, pos);
+        Some(self.code.len() - 1)
+    }
+
     pub(crate) fn add_upvalue(&mut self, entity: &TCObjKey, uv: ValueDesc) -> VirtualAddr {
         let addr = match self.uv_entities.get(entity) {
             Some(i) => *i,
@@ -477,7 +540,11 @@ impl<'a> FuncCtx<'a> {
                         .iter()
                         .map(|n| {
                             let key = t_lookup.object_def(*n);
-                            self.add_local(Some(key), None);
+                            let addr = self.add_local(Some(key), None);
+                            let ident = &o.idents[*n];
+                            if !ident.is_blank() {
+                                self.name_local(&ident.name, addr);
+                            }
                         })
                         .count()
                 }
@@ -526,6 +593,9 @@ impl<'a> FuncCtx<'a> {
                     None => InterInst::with_op_index(Opcode::DUPLICATE, l, rhs, Addr::Void),
                 }
             }
+            VirtualAddr::LocalInPlace(l) => {
+                InterInst::with_op_index(Opcode::STORE_LOCAL, l, rhs, Addr::Void)
+            }
             VirtualAddr::UpValue(l) => {
                 InterInst::with_op_index(Opcode::STORE_UP_VALUE, l, rhs, Addr::Void)
             }
@@ -535,9 +605,11 @@ impl<'a> FuncCtx<'a> {
             VirtualAddr::ArrayEntry(a, i) => {
                 InterInst::with_op_index(Opcode::STORE_ARRAY, a, i, rhs)
             }
-            VirtualAddr::MapEntry(m, k, zero) => {
-                inst_ex = Some(InterInst::with_op_index(
+            VirtualAddr::MapEntry(m, k, zero, check_key) => {
+                inst_ex = Some(InterInst::with_op_t_index(
                     Opcode::VOID,
+                    check_key.then_some(ValueType::FlagA),
+                    None,
                     Addr::Void,
                     zero,
                     Addr::Void,
@@ -578,16 +650,22 @@ impl<'a> FuncCtx<'a> {
         self.emit_inst(inst, pos);
     }
 
+    /// With `keyed`, every element is preceded by its index, otherwise the elements
+    /// are in order.
     pub fn emit_literal(
         &mut self,
         d: Addr,
         begin: usize,
         count: usize,
+        keyed: bool,
         meta: Addr,
         pos: Option<usize>,
     ) {
-        let inst = InterInst::with_op_index(
+        let flag = (!keyed).then_some(ValueType::FlagA);
+        let inst = InterInst::with_op_t_index(
             Opcode::LITERAL,
+            flag,
+            None,
             d,
             Addr::Regsiter(begin),
             Addr::Imm(count as OpIndex),
@@ -723,8 +801,9 @@ impl<'a> FuncCtx<'a> {
         vmctx: &mut CodeGenVMCtx,
         labels: &Map<TCObjKey, usize>,
         cst_map: &Map<usize, usize>,
+        consts: &[GosValue],
     ) {
-        let code: Vec<Instruction> = self
+        let mut code: Vec<Instruction> = self
             .code
             .into_iter()
             .enumerate()
@@ -732,6 +811,8 @@ impl<'a> FuncCtx<'a> {
                 x.into_runtime_inst(self.local_alloc, asto, vmctx.packages(), i, labels, cst_map)
             })
             .collect();
+        peephole::optimize(&mut code, consts);
+        let local_zeros = vmctx.zero_vec(&self.local_metas);
         let func = &mut vmctx.functions_mut()[self.f_key];
         func.pos = self
             .pos
@@ -745,7 +826,16 @@ impl<'a> FuncCtx<'a> {
             .collect();
         func.up_ptrs = self.up_ptrs;
         func.max_write_index = Instruction::max_write_index(&code);
-        func.local_zeros = self.local_zeros;
+        func.local_zeros = local_zeros;
+        let len = code.len() as u32;
+        func.locals = self
+            .local_names
+            .into_iter()
+            .map(|mut x| {
+                x.end = x.end.min(len);
+                x
+            })
+            .collect();
         func.code = code;
     }
 
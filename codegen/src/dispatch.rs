@@ -0,0 +1,274 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use go_parser::ast::*;
+use go_parser::{AstObjects, IdentKey, Map, Parser, Token};
+use go_types::{check::TypeInfo, typ::Type, ObjKey as TCObjKey, TCObjects, TypeKey as TCTypeKey};
+use std::collections::HashSet;
+
+/// StaticDispatch finds the local variables of interface types whose dynamic types
+/// are known at compile time, so that the methods called on them can be bound
+/// directly instead of being looked up through the interfaces.
+///
+/// A variable qualifies when it's declared with `var x I = v`, where v is of a
+/// concrete type with methods, and it's never assigned again or referenced by a
+/// pointer anywhere, including in the function literals that capture it.
+pub(crate) struct StaticDispatch<'a> {
+    ast_objs: &'a AstObjects,
+    tc_objs: &'a TCObjects,
+    ti: &'a TypeInfo,
+    candidates: Map<TCObjKey, TCTypeKey>,
+    tainted: HashSet<TCObjKey>,
+}
+
+impl<'a> StaticDispatch<'a> {
+    pub fn new(
+        ast_objs: &'a AstObjects,
+        tc_objs: &'a TCObjects,
+        ti: &'a TypeInfo,
+    ) -> StaticDispatch<'a> {
+        StaticDispatch {
+            ast_objs,
+            tc_objs,
+            ti,
+            candidates: Map::new(),
+            tainted: HashSet::new(),
+        }
+    }
+
+    /// Returns the qualified variables of the package, with their dynamic types.
+    pub fn analyze(mut self, files: &[File]) -> Map<TCObjKey, TCTypeKey> {
+        for f in files.iter() {
+            for d in f.decls.iter() {
+                match d {
+                    Decl::Gen(gdecl) => self.gen_decl(gdecl, false),
+                    Decl::Func(fdecl) => {
+                        if let Some(body) = &self.ast_objs.fdecls[*fdecl].body {
+                            self.block(body);
+                        }
+                    }
+                    Decl::Bad(_) => {}
+                }
+            }
+        }
+        let tainted = self.tainted;
+        self.candidates.retain(|k, _| !tainted.contains(k));
+        self.candidates
+    }
+
+    fn gen_decl(&mut self, gdecl: &GenDecl, local: bool) {
+        if gdecl.token != Token::VAR {
+            return;
+        }
+        for s in gdecl.specs.iter() {
+            if let Spec::Value(vs) = &self.ast_objs.specs[*s] {
+                // package vars can be assigned by any function
+                if local && vs.names.len() == vs.values.len() {
+                    for (name, val) in vs.names.iter().zip(vs.values.iter()) {
+                        self.add_candidate(name, val);
+                    }
+                }
+                for val in vs.values.iter() {
+                    self.expr(val);
+                }
+            }
+        }
+    }
+
+    fn add_candidate(&mut self, name: &IdentKey, val: &Expr) {
+        let okey = match self.ti.defs.get(name) {
+            Some(Some(okey)) => *okey,
+            _ => return,
+        };
+        let var_type = self.tc_objs.lobjs[okey].typ().unwrap();
+        let val_type = match self.ti.types.get(&val.id()) {
+            Some(tv) => tv.typ,
+            None => return,
+        };
+        if self.tc_objs.types[var_type].is_interface(self.tc_objs)
+            && !self.tc_objs.types[val_type].is_interface(self.tc_objs)
+            && self.has_methods(val_type)
+        {
+            self.candidates.insert(okey, val_type);
+        }
+    }
+
+    fn has_methods(&self, typ: TCTypeKey) -> bool {
+        let base = match &self.tc_objs.types[typ] {
+            Type::Pointer(p) => p.base(),
+            _ => typ,
+        };
+        match &self.tc_objs.types[base] {
+            Type::Named(n) => !n.methods().is_empty(),
+            _ => false,
+        }
+    }
+
+    fn taint(&mut self, expr: &Expr) {
+        if let Expr::Ident(ikey) = Parser::unparen(expr) {
+            if let Some(okey) = self.ti.uses.get(ikey) {
+                self.tainted.insert(*okey);
+            }
+        }
+    }
+
+    fn block(&mut self, block: &BlockStmt) {
+        for s in block.list.iter() {
+            self.stmt(s);
+        }
+    }
+
+    fn opt_stmt(&mut self, stmt: &Option<Stmt>) {
+        if let Some(s) = stmt {
+            self.stmt(s);
+        }
+    }
+
+    fn opt_expr(&mut self, expr: &Option<Expr>) {
+        if let Some(e) = expr {
+            self.expr(e);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Bad(_) | Stmt::Empty(_) | Stmt::Branch(_) => {}
+            Stmt::Decl(decl) => {
+                if let Decl::Gen(gdecl) = &**decl {
+                    self.gen_decl(gdecl, true);
+                }
+            }
+            Stmt::Labeled(lstmt) => self.stmt(&self.ast_objs.l_stmts[*lstmt].stmt),
+            Stmt::Expr(expr) => self.expr(expr),
+            Stmt::Send(sstmt) => {
+                self.expr(&sstmt.chan);
+                self.expr(&sstmt.val);
+            }
+            Stmt::IncDec(idstmt) => {
+                self.taint(&idstmt.expr);
+                self.expr(&idstmt.expr);
+            }
+            Stmt::Assign(astmt) => {
+                let astmt = &self.ast_objs.a_stmts[*astmt];
+                if astmt.token != Token::DEFINE {
+                    for e in astmt.lhs.iter() {
+                        self.taint(e);
+                    }
+                }
+                for e in astmt.lhs.iter().chain(astmt.rhs.iter()) {
+                    self.expr(e);
+                }
+            }
+            Stmt::Go(gostmt) => self.expr(&gostmt.call),
+            Stmt::Defer(dstmt) => self.expr(&dstmt.call),
+            Stmt::Return(rstmt) => {
+                for e in rstmt.results.iter() {
+                    self.expr(e);
+                }
+            }
+            Stmt::Block(bstmt) => self.block(bstmt),
+            Stmt::If(ifstmt) => {
+                self.opt_stmt(&ifstmt.init);
+                self.expr(&ifstmt.cond);
+                self.block(&ifstmt.body);
+                self.opt_stmt(&ifstmt.els);
+            }
+            Stmt::Case(cclause) => {
+                for e in cclause.list.iter().flatten() {
+                    self.expr(e);
+                }
+                for s in cclause.body.iter() {
+                    self.stmt(s);
+                }
+            }
+            Stmt::Switch(sstmt) => {
+                self.opt_stmt(&sstmt.init);
+                self.opt_expr(&sstmt.tag);
+                self.block(&sstmt.body);
+            }
+            Stmt::TypeSwitch(tstmt) => {
+                self.opt_stmt(&tstmt.init);
+                self.stmt(&tstmt.assign);
+                self.block(&tstmt.body);
+            }
+            Stmt::Comm(cclause) => {
+                self.opt_stmt(&cclause.comm);
+                for s in cclause.body.iter() {
+                    self.stmt(s);
+                }
+            }
+            Stmt::Select(sstmt) => self.block(&sstmt.body),
+            Stmt::For(fstmt) => {
+                self.opt_stmt(&fstmt.init);
+                self.opt_expr(&fstmt.cond);
+                self.opt_stmt(&fstmt.post);
+                self.block(&fstmt.body);
+            }
+            Stmt::Range(rstmt) => {
+                if rstmt.token == Token::ASSIGN {
+                    for e in rstmt.key.iter().chain(rstmt.val.iter()) {
+                        self.taint(e);
+                    }
+                }
+                self.opt_expr(&rstmt.key);
+                self.opt_expr(&rstmt.val);
+                self.expr(&rstmt.expr);
+                self.block(&rstmt.body);
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Bad(_) | Expr::Ident(_) | Expr::BasicLit(_) => {}
+            Expr::Ellipsis(els) => self.opt_expr(&els.elt),
+            Expr::FuncLit(flit) => self.block(&flit.body),
+            Expr::CompositeLit(clit) => {
+                for e in clit.elts.iter() {
+                    self.expr(e);
+                }
+            }
+            Expr::Paren(pexpr) => self.expr(&pexpr.expr),
+            Expr::Selector(sexpr) => self.expr(&sexpr.expr),
+            Expr::Index(iexpr) => {
+                self.expr(&iexpr.expr);
+                self.expr(&iexpr.index);
+            }
+            Expr::Slice(sexpr) => {
+                self.expr(&sexpr.expr);
+                self.opt_expr(&sexpr.low);
+                self.opt_expr(&sexpr.high);
+                self.opt_expr(&sexpr.max);
+            }
+            Expr::TypeAssert(taexpr) => self.expr(&taexpr.expr),
+            Expr::Call(cexpr) => {
+                self.expr(&cexpr.func);
+                for e in cexpr.args.iter() {
+                    self.expr(e);
+                }
+            }
+            Expr::Star(sexpr) => self.expr(&sexpr.expr),
+            Expr::Unary(uexpr) => {
+                if uexpr.op == Token::AND {
+                    self.taint(&uexpr.expr);
+                }
+                self.expr(&uexpr.expr);
+            }
+            Expr::Binary(bexpr) => {
+                self.expr(&bexpr.expr_a);
+                self.expr(&bexpr.expr_b);
+            }
+            Expr::KeyValue(kvexpr) => {
+                self.expr(&kvexpr.key);
+                self.expr(&kvexpr.val);
+            }
+            Expr::Array(_)
+            | Expr::Struct(_)
+            | Expr::Func(_)
+            | Expr::Interface(_)
+            | Expr::Map(_)
+            | Expr::Chan(_) => {}
+        }
+    }
+}
@@ -8,11 +8,11 @@ use super::consts::*;
 use super::context::*;
 use super::package::PkgHelper;
 use super::types::{TypeCache, TypeLookup};
-use go_parser::ast::Ident;
-use go_parser::{AstObjects, ErrorList, FileSet, IdentKey, Map};
+use go_parser::ast::{self, Ident};
+use go_parser::{AstObjects, ErrorList, FileSet, IdentKey, Map, Pos};
 use go_types::{
-    check::TypeInfo, ImportKey, Importer, PackageKey as TCPackageKey, SourceRead, TCObjects,
-    TraceConfig,
+    check::TypeInfo, BuildConfig, ImportKey, Importer, PackageKey as TCPackageKey, SourceRead,
+    TCObjects, TraceConfig, TypeKey, Universe,
 };
 use go_vm::types::*;
 use go_vm::*;
@@ -22,34 +22,159 @@ use std::vec;
 pub fn parse_check_gen<S: SourceRead>(
     path: &Path,
     tconfig: &TraceConfig,
+    bconfig: &BuildConfig,
     reader: &S,
     fset: &mut FileSet,
 ) -> Result<Bytecode, ErrorList> {
+    parse_check_gen_impl(path, tconfig, bconfig, reader, fset, false).map(|(code, _)| code)
+}
+
+/// Compiles the package at `path` with its `_test` files for running its tests, the
+/// entry function only initializes the packages. Returns the names and the positions
+/// of the test and the benchmark functions too, in the order they are declared.
+pub fn parse_check_gen_tests<S: SourceRead>(
+    path: &Path,
+    tconfig: &TraceConfig,
+    bconfig: &BuildConfig,
+    reader: &S,
+    fset: &mut FileSet,
+) -> Result<(Bytecode, Vec<(String, Pos)>), ErrorList> {
+    parse_check_gen_impl(path, tconfig, bconfig, reader, fset, true)
+}
+
+fn parse_check_gen_impl<S: SourceRead>(
+    path: &Path,
+    tconfig: &TraceConfig,
+    bconfig: &BuildConfig,
+    reader: &S,
+    fset: &mut FileSet,
+    tests: bool,
+) -> Result<(Bytecode, Vec<(String, Pos)>), ErrorList> {
     let ast_objs = &mut AstObjects::new();
     let tc_objs = &mut TCObjects::new();
     let results = &mut Map::new();
     let pkgs = &mut Map::new();
     let el = ErrorList::new();
 
+    for (name, val) in bconfig.universe_constants() {
+        if let Err(e) = Universe::def_host_const(&name, val, tc_objs) {
+            el.add(None, e, false, false);
+        }
+    }
+    if el.len() > 0 {
+        return Err(el);
+    }
+
     let importer = &mut Importer::new(
-        &tconfig, reader, fset, pkgs, results, ast_objs, tc_objs, &el, 0,
-    );
+        &tconfig, bconfig, reader, fset, pkgs, results, ast_objs, tc_objs, &el, 0,
+    )
+    .with_tests(tests);
     let key = ImportKey::new(path.to_str().unwrap(), "./");
     let main_pkg = importer.import(&key);
     if el.len() > 0 {
         Err(el)
     } else {
+        let main_pkg = main_pkg.unwrap();
         let blank_ident = ast_objs.idents.insert(Ident::blank(0));
-        let main_ident = ast_objs.idents.insert(Ident::with_str(0, "main"));
-        Ok(gen_byte_code(
+        let (main_ident, test_funcs) = if tests {
+            (
+                None,
+                find_tests(ast_objs, tc_objs, &results[&main_pkg], fset),
+            )
+        } else {
+            let main_ident = ast_objs.idents.insert(Ident::with_str(0, "main"));
+            (Some(main_ident), vec![])
+        };
+        let code = gen_byte_code(
             ast_objs,
             tc_objs,
             results,
-            main_pkg.unwrap(),
+            main_pkg,
             main_ident,
             blank_ident,
-        ))
+        );
+        // a bug of the code generator is reported where the code comes from, rather
+        // than found by the VM panicking
+        if let Err(e) = code.verify() {
+            let pos = code.objects.functions[e.func].pos.get(e.pc);
+            let fpos = pos.and_then(|p| fset.position(p as usize));
+            el.add(
+                fpos,
+                format!("internal compiler error, {}", e),
+                false,
+                false,
+            );
+            return Err(el);
+        }
+        Ok((code, test_funcs))
+    }
+}
+
+/// Returns the functions `func TestXxx(*testing.T)` and `func BenchmarkXxx(*testing.B)`
+/// of the `_test` files, where Xxx doesn't start with a lower case letter, as `go test`
+/// finds them
+fn find_tests(
+    ast_objs: &AstObjects,
+    tc_objs: &TCObjects,
+    ti: &TypeInfo,
+    fset: &FileSet,
+) -> Vec<(String, Pos)> {
+    let is_test_file = |f: &ast::File| match fset.position(f.package) {
+        Some(p) => Path::new(p.filename.as_str())
+            .file_stem()
+            .is_some_and(|s| s.to_string_lossy().ends_with("_test")),
+        None => false,
+    };
+    let is_testing_type = |t: TypeKey, name: &str| {
+        let named = match tc_objs.types[t].try_as_pointer() {
+            Some(p) => tc_objs.types[p.base()].try_as_named(),
+            None => None,
+        };
+        let obj = match named.and_then(|n| *n.obj()) {
+            Some(o) => &tc_objs.lobjs[o],
+            None => return false,
+        };
+        obj.name() == name
+            && obj
+                .pkg()
+                .is_some_and(|p| tc_objs.pkgs[p].path() == "testing")
+    };
+    let mut tests = vec![];
+    for file in ti.ast_files.iter().filter(|f| is_test_file(f)) {
+        for decl in file.decls.iter() {
+            let fdecl = match decl {
+                ast::Decl::Func(f) => &ast_objs.fdecls[*f],
+                _ => continue,
+            };
+            let name = &ast_objs.idents[fdecl.name].name;
+            let param_type = [("Test", "T"), ("Benchmark", "B")]
+                .iter()
+                .find(|(prefix, _)| {
+                    name.strip_prefix(prefix)
+                        .is_some_and(|rest| !rest.starts_with(|c: char| c.is_lowercase()))
+                })
+                .map(|(_, t)| *t);
+            let param_type = match param_type {
+                Some(t) if fdecl.recv.is_none() => t,
+                _ => continue,
+            };
+            let typ = ti.defs[&fdecl.name].and_then(|o| tc_objs.lobjs[o].typ());
+            let sig = match typ.and_then(|t| tc_objs.types[t].try_as_signature()) {
+                Some(sig) => sig,
+                None => continue,
+            };
+            let params = tc_objs.types[sig.params()].try_as_tuple().unwrap().vars();
+            let is_test_sig = params.len() == 1
+                && sig.results_count(tc_objs) == 0
+                && tc_objs.lobjs[params[0]]
+                    .typ()
+                    .is_some_and(|t| is_testing_type(t, param_type));
+            if is_test_sig {
+                tests.push((name.clone(), fdecl.pos(ast_objs)));
+            }
+        }
     }
+    tests
 }
 
 fn gen_byte_code(
@@ -57,7 +182,7 @@ fn gen_byte_code(
     tc_objs: &TCObjects,
     checker_result: &Map<TCPackageKey, TypeInfo>,
     tc_main_pkg: TCPackageKey,
-    main_ident: IdentKey,
+    main_ident: Option<IdentKey>,
     blank_ident: IdentKey,
 ) -> Bytecode {
     let vm_objs = VMObjects::new();
@@ -77,9 +202,15 @@ fn gen_byte_code(
     }
 
     let main_pkg = pkg_map[&tc_main_pkg];
-    let entry = gen_entry_func(&mut vmctx, &consts, main_pkg, main_ident);
-    let entry_key = entry.f_key;
-    result_funcs.push(entry);
+    // without main, like for the tests, running the code only initializes the packages
+    let entry = main_ident.map(|ident| gen_entry_func(&mut vmctx, &consts, main_pkg, ident));
+    let init_entry = gen_init_func(&mut vmctx, &consts, main_pkg);
+    let init_entry_key = init_entry.f_key;
+    let entry_key = entry.as_ref().map_or(init_entry_key, |e| e.f_key);
+    result_funcs.extend(entry);
+    result_funcs.push(init_entry);
+    let (runtime_error, mut methods) = gen_runtime_error(&mut vmctx, &consts);
+    result_funcs.append(&mut methods);
 
     for (tcpkg, ti) in checker_result.iter() {
         let mut pkg_helper = PkgHelper::new(ast_objs, tc_objs, &pkg_map);
@@ -102,7 +233,13 @@ fn gen_byte_code(
 
     let (consts, cst_map) = consts.get_runtime_consts(&mut vmctx);
     for f in result_funcs.into_iter() {
-        f.into_runtime_func(ast_objs, &mut vmctx, branch_helper.labels(), &cst_map);
+        f.into_runtime_func(
+            ast_objs,
+            &mut vmctx,
+            branch_helper.labels(),
+            &cst_map,
+            &consts,
+        );
     }
 
     let dummy_ti = TypeInfo::new();
@@ -120,7 +257,9 @@ fn gen_byte_code(
         struct_selector.result(),
         entry_key,
         main_pkg,
+        runtime_error,
     )
+    .with_init_entry(init_entry_key)
 }
 
 // generate the entry function for Bytecode
@@ -131,7 +270,8 @@ fn gen_entry_func<'a, 'c>(
     main_ident: IdentKey,
 ) -> FuncCtx<'c> {
     let fmeta = vmctx.prim_meta().default_sig;
-    let fobj = vmctx.function_with_meta(None, fmeta.clone(), FuncFlag::Default);
+    let name = FuncName::new("runtime", None, "main".to_owned());
+    let fobj = vmctx.function_with_meta(None, name, fmeta, FuncFlag::Default);
     let fkey = *fobj.as_function();
     let mut fctx = FuncCtx::new(fkey, None, consts);
     fctx.emit_import(pkg, None);
@@ -142,3 +282,60 @@ fn gen_entry_func<'a, 'c>(
     fctx.emit_return(None, None, vmctx.functions());
     fctx
 }
+
+// generate the function initializing the packages without calling main, for the
+// hosts calling other functions
+pub(crate) fn gen_init_func<'c>(
+    vmctx: &mut CodeGenVMCtx,
+    consts: &'c Consts,
+    pkg: PackageKey,
+) -> FuncCtx<'c> {
+    let fmeta = vmctx.prim_meta().default_sig;
+    let name = FuncName::new("runtime", None, "init".to_owned());
+    let fobj = vmctx.function_with_meta(None, name, fmeta, FuncFlag::Default);
+    let fkey = *fobj.as_function();
+    let mut fctx = FuncCtx::new(fkey, None, consts);
+    fctx.emit_import(pkg, None);
+    fctx.emit_return(None, None, vmctx.functions());
+    fctx
+}
+
+// generate the type of the errors the VM panics with, it's a string with the methods
+// Error() and RuntimeError(), so it implements error and runtime.Error
+pub(crate) fn gen_runtime_error<'c>(
+    vmctx: &mut CodeGenVMCtx,
+    consts: &'c Consts,
+) -> (Meta, Vec<FuncCtx<'c>>) {
+    let mstr = vmctx.prim_meta().mstr;
+    let name = TypeName::new("runtime", "runtime", "Error");
+    let meta = Meta::new_named(name, mstr, vmctx.metas_mut());
+    let methods = [("Error", vec![mstr]), ("RuntimeError", vec![])]
+        .into_iter()
+        .map(|(name, results)| {
+            let name = name.to_owned();
+            let has_result = !results.is_empty();
+            let fmeta = Meta::new_sig(Some(meta), vec![], results, None, vmctx.metas_mut());
+            let fname = FuncName::new("runtime", Some("Error".to_owned()), name.clone());
+            let fobj = vmctx.function_with_meta(None, fname, fmeta, FuncFlag::Default);
+            let fkey = *fobj.as_function();
+            meta.add_method(name.clone(), false, vmctx.metas_mut());
+            meta.set_method_code(&name, fkey, vmctx.metas_mut());
+
+            let mut fctx = FuncCtx::new(fkey, None, consts);
+            if has_result {
+                // the error message is the receiver itself
+                let result = fctx.add_local(None, None);
+                let recv = fctx.add_local(None, None);
+                fctx.emit_inst(
+                    InterInst::with_op_index(Opcode::DUPLICATE, result, recv, Addr::Void),
+                    None,
+                );
+            } else {
+                fctx.add_local(None, None);
+            }
+            fctx.emit_return(None, None, vmctx.functions());
+            fctx
+        })
+        .collect();
+    (meta, methods)
+}
@@ -9,14 +9,19 @@
 //! - `btree_map`: Make it use BTreeMap instead of HashMap
 
 mod branch;
+mod consteval;
 mod consts;
 mod context;
+mod dispatch;
 //mod emit;
 mod package;
+mod peephole;
 //mod selector;
 mod codegen;
 mod entry;
+mod session;
 mod types;
 
-pub use entry::parse_check_gen;
-pub use go_types::{SourceRead, TraceConfig};
+pub use entry::{parse_check_gen, parse_check_gen_tests};
+pub use go_types::{BuildConfig, ConstValue, SourceRead, TraceConfig};
+pub use session::{Chunk, ChunkCode, Session};
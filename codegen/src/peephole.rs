@@ -0,0 +1,78 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Rewriting the instructions of a function once their operands are resolved.
+//!
+//! The rewrites never add or remove an instruction, so the offsets of the jumps,
+//! the position table and the scopes of the locals stay what codegen made them.
+//! An instruction that's no longer needed becomes a `JUMP` of 0, which the jumps
+//! to it are threaded past.
+
+use go_vm::types::*;
+
+/// Folds the conditional jumps on constants, then makes the jumps that land on
+/// unconditional ones go to where those lead.
+pub(crate) fn optimize(code: &mut [Instruction], consts: &[GosValue]) {
+    let starts = inst_starts(code);
+    for &pc in starts.iter() {
+        fold_const_cond(&mut code[pc], consts);
+    }
+    for &pc in starts.iter() {
+        thread_jump(code, pc);
+    }
+}
+
+/// The indices of the instructions that aren't operands of the ones before them
+fn inst_starts(code: &[Instruction]) -> Vec<usize> {
+    let mut starts = Vec::with_capacity(code.len());
+    let mut pc = 0;
+    while pc < code.len() {
+        starts.push(pc);
+        pc += 1 + code[pc].ext_count();
+    }
+    starts
+}
+
+/// `JUMP_IF` and `JUMP_IF_NOT` on a constant either always jump or never do,
+/// e.g. for `if debug {...}` with `debug` a constant
+fn fold_const_cond(inst: &mut Instruction, consts: &[GosValue]) {
+    let when = match inst.op0 {
+        Opcode::JUMP_IF => true,
+        Opcode::JUMP_IF_NOT => false,
+        _ => return,
+    };
+    if inst.s0 >= 0 {
+        return;
+    }
+    let cond = match consts.get((-inst.s0 - 1) as usize) {
+        Some(c) if c.typ() == ValueType::Bool => *c.as_bool(),
+        _ => return,
+    };
+    inst.op0 = Opcode::JUMP;
+    if cond != when {
+        inst.d = 0;
+    }
+}
+
+/// Retargets the jump at `pc` to the end of the chain of `JUMP`s it lands on
+fn thread_jump(code: &mut [Instruction], pc: usize) {
+    match code[pc].op0 {
+        Opcode::JUMP | Opcode::JUMP_IF | Opcode::JUMP_IF_NOT => {}
+        _ => return,
+    }
+    let next = pc as OpIndex + 1;
+    let mut target = next + code[pc].d;
+    // a chain longer than the code is a loop, like the one of `for {}`
+    for _ in 0..code.len() {
+        match code.get(target as usize) {
+            Some(inst) if inst.op0 == Opcode::JUMP && target as usize != pc => {
+                target += 1 + inst.d;
+            }
+            _ => {
+                code[pc].d = target - next;
+                return;
+            }
+        }
+    }
+}
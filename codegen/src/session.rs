@@ -0,0 +1,679 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Compiling package main a piece at a time, for the interactive sessions.
+//!
+//! An input is split into chunks, each chunk is type checked as a new file of the
+//! package and generated into the bytecode of the ones before it, so that it's run
+//! with the variables they set. The variables declared by `:=` at the top level of
+//! the statements are package variables, the other statements are the body of a
+//! function. The objects declared again replace the ones before in the package
+//! scope, the code compiled before keeps using the ones it was compiled with.
+
+use crate::branch::BranchHelper;
+use crate::codegen::*;
+use crate::consts::Consts;
+use crate::entry::{gen_init_func, gen_runtime_error};
+use crate::package::PkgHelper;
+use crate::types::{TypeCache, TypeLookup};
+use go_parser::ast::*;
+use go_parser::{
+    AssignStmtKey, AstObjects, ErrorList, FileSet, IdentKey, Map, Parser, Pos, ScopeKey, SpecKey,
+    Token,
+};
+use go_types::{
+    check::{Checker, TypeInfo},
+    typ::{identical, Type},
+    BuildConfig, EntityType, ImportKey, Importer, OperandMode, PackageKey as TCPackageKey, Scope,
+    SourceRead, TCObjects, TraceConfig, Universe,
+};
+use go_vm::types::*;
+use go_vm::*;
+use std::rc::Rc;
+
+/// A part of an input that's compiled and run before the next one is compiled
+pub struct Chunk(ChunkKind);
+
+enum ChunkKind {
+    Imports(Vec<SpecKey>),
+    Decls(Vec<Decl>),
+    /// `:=` at the top level
+    Define(AssignStmtKey),
+    Stmts(Vec<Stmt>),
+    /// An expression statement, its values are kept for printing
+    Expr(Expr),
+}
+
+/// How to run a chunk compiled
+pub struct ChunkCode {
+    /// The functions of package main to call in order
+    pub calls: Vec<String>,
+    /// The variables of package main holding the values of the expression
+    pub values: Vec<String>,
+}
+
+/// Package main of an interactive session, with the bytecode compiled so far
+pub struct Session {
+    tconfig: TraceConfig,
+    bconfig: BuildConfig,
+    fset: FileSet,
+    ast_objs: AstObjects,
+    tc_objs: TCObjects,
+    pkgs: Map<String, TCPackageKey>,
+    results: Map<TCPackageKey, TypeInfo>,
+    main_tc: TCPackageKey,
+    main_ident: IdentKey,
+    blank_ident: IdentKey,
+    ast_scope: ScopeKey,
+    pkg_map: Map<TCPackageKey, PackageKey>,
+    type_cache: TypeCache,
+    iface_selector: IfaceSelector,
+    struct_selector: StructSelector,
+    branch_helper: BranchHelper,
+    code: Bytecode,
+    inputs: usize,
+    chunks: usize,
+}
+
+impl Session {
+    pub fn new<S: SourceRead>(
+        tconfig: &TraceConfig,
+        bconfig: &BuildConfig,
+        reader: &S,
+    ) -> Result<Session, ErrorList> {
+        let mut ast_objs = AstObjects::new();
+        let mut tc_objs = TCObjects::new();
+        let el = ErrorList::new();
+        for (name, val) in bconfig.universe_constants() {
+            if let Err(e) = Universe::def_host_const(&name, val, &mut tc_objs) {
+                el.add(None, e, false, false);
+            }
+        }
+        if el.len() > 0 {
+            return Err(el);
+        }
+
+        let main_tc = tc_objs.new_package("main".to_owned());
+        let main_ident = ast_objs.idents.insert(Ident::with_str(0, "main"));
+        let blank_ident = ast_objs.idents.insert(Ident::blank(0));
+        let ast_scope = ast_objs.scopes.insert(go_parser::scope::Scope::new(None));
+
+        // the functions that are not in any package
+        let mut vmctx = CodeGenVMCtx::new(VMObjects::new());
+        let main_pkg = vmctx
+            .packages_mut()
+            .insert(PackageObj::new("main".to_owned()));
+        let consts = Consts::new();
+        let init_entry = gen_init_func(&mut vmctx, &consts, main_pkg);
+        let init_entry_key = init_entry.f_key;
+        let (runtime_error, mut funcs) = gen_runtime_error(&mut vmctx, &consts);
+        funcs.push(init_entry);
+        let (consts, cst_map) = consts.get_runtime_consts(&mut vmctx);
+        for f in funcs.into_iter() {
+            f.into_runtime_func(&ast_objs, &mut vmctx, &Map::new(), &cst_map, &consts);
+        }
+        let code = Bytecode::new(
+            vmctx.into_vmo(),
+            consts,
+            vec![],
+            vec![],
+            init_entry_key,
+            main_pkg,
+            runtime_error,
+        );
+
+        let mut pkg_map = Map::new();
+        pkg_map.insert(main_tc, main_pkg);
+        let mut session = Session {
+            tconfig: tconfig.clone(),
+            bconfig: bconfig.clone(),
+            fset: FileSet::new(),
+            ast_objs,
+            tc_objs,
+            pkgs: Map::new(),
+            results: Map::new(),
+            main_tc,
+            main_ident,
+            blank_ident,
+            ast_scope,
+            pkg_map,
+            type_cache: Map::new(),
+            iface_selector: IfaceSelector::new(),
+            struct_selector: StructSelector::new(),
+            branch_helper: BranchHelper::new(),
+            code,
+            inputs: 0,
+            chunks: 0,
+        };
+        // the package is empty to begin with, its constructor is the 0th member
+        let pos = session.fset.base();
+        session.fset.add_file("<session>".to_owned(), Some(pos), 0);
+        let file = session.file(vec![], pos);
+        session.check(file, reader)?;
+        session.gen(String::new());
+        Ok(session)
+    }
+
+    pub fn code(&self) -> &Bytecode {
+        &self.code
+    }
+
+    pub fn fset(&self) -> &FileSet {
+        &self.fset
+    }
+
+    /// Parses an input into the chunks to compile and run in order: the imports, the
+    /// declarations, and the statements split at the ones declaring variables and
+    /// at the expressions.
+    pub fn parse(&mut self, input: &str) -> Result<Vec<Chunk>, ErrorList> {
+        self.inputs += 1;
+        let el = ErrorList::new();
+        let name = format!("<input {}>", self.inputs);
+        let base = self.fset.base();
+        let file = self.fset.add_file(name, Some(base), input.chars().count());
+        let parsed = Parser::new(
+            &mut self.ast_objs,
+            file,
+            &el,
+            input,
+            self.tconfig.trace_parser,
+        )
+        .parse_input();
+        let mut chunks = vec![];
+        match parsed {
+            None => return Err(el),
+            Some(Input::Decls(decls)) => {
+                let (imports, decls): (Vec<Decl>, Vec<Decl>) = decls
+                    .into_iter()
+                    .partition(|d| matches!(d, Decl::Gen(g) if g.token == Token::IMPORT));
+                let specs: Vec<SpecKey> = imports
+                    .iter()
+                    .flat_map(|d| match d {
+                        Decl::Gen(g) => g.specs.clone(),
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                if !specs.is_empty() {
+                    chunks.push(Chunk(ChunkKind::Imports(specs)));
+                }
+                if !decls.is_empty() {
+                    chunks.push(Chunk(ChunkKind::Decls(decls)));
+                }
+            }
+            Some(Input::Stmts(stmts)) => {
+                let mut pending = vec![];
+                for s in stmts.into_iter() {
+                    let chunk = match &s {
+                        Stmt::Decl(d) => ChunkKind::Decls(vec![(**d).clone()]),
+                        Stmt::Assign(key) if self.ast_objs.a_stmts[*key].token == Token::DEFINE => {
+                            ChunkKind::Define(*key)
+                        }
+                        Stmt::Expr(e) => ChunkKind::Expr((**e).clone()),
+                        _ => {
+                            pending.push(s);
+                            continue;
+                        }
+                    };
+                    if !pending.is_empty() {
+                        chunks.push(Chunk(ChunkKind::Stmts(std::mem::take(&mut pending))));
+                    }
+                    chunks.push(Chunk(chunk));
+                }
+                if !pending.is_empty() {
+                    chunks.push(Chunk(ChunkKind::Stmts(pending)));
+                }
+            }
+        }
+        Ok(chunks)
+    }
+
+    /// Compiles a chunk into the bytecode, the package is left as it was if it fails
+    pub fn compile<S: SourceRead>(
+        &mut self,
+        chunk: Chunk,
+        reader: &S,
+    ) -> Result<ChunkCode, ErrorList> {
+        self.chunks += 1;
+        let scope = *self.tc_objs.pkgs[self.main_tc].scope();
+        let elems = self.tc_objs.scopes[scope].elems().clone();
+        let imports = self.tc_objs.pkgs[self.main_tc].imports().clone();
+        let checked: Vec<TCPackageKey> = self.results.keys().cloned().collect();
+
+        let result = self.compile_chunk(chunk, reader);
+        if result.is_err() {
+            let names: Vec<String> = self.tc_objs.scopes[scope].elems().keys().cloned().collect();
+            for name in names.iter() {
+                self.tc_objs.scopes[scope].remove(name);
+            }
+            for (_, okey) in elems.iter() {
+                Scope::insert(scope, *okey, &mut self.tc_objs);
+            }
+            self.tc_objs.pkgs[self.main_tc].set_imports(imports);
+            // the packages imported with errors are checked again the next time
+            let failed: Vec<TCPackageKey> = self
+                .results
+                .keys()
+                .filter(|x| **x != self.main_tc && !checked.contains(x))
+                .cloned()
+                .collect();
+            for key in failed.iter() {
+                self.results.remove(key);
+            }
+            self.pkgs.retain(|_, v| !failed.contains(v));
+        }
+        result
+    }
+
+    fn compile_chunk<S: SourceRead>(
+        &mut self,
+        chunk: Chunk,
+        reader: &S,
+    ) -> Result<ChunkCode, ErrorList> {
+        let n = self.chunks;
+        let init = format!("__repl{}_init", n);
+        match chunk.0 {
+            ChunkKind::Imports(specs) => {
+                let pos = self.ast_objs.specs[specs[0]].pos(&self.ast_objs);
+                for spec in specs.iter() {
+                    self.import(*spec, reader)?;
+                }
+                self.check_gen(vec![], pos, init, reader)
+            }
+            ChunkKind::Decls(decls) => {
+                let pos = decls[0].pos(&self.ast_objs);
+                self.replace(&decls);
+                self.check_gen(decls, pos, init, reader)
+            }
+            ChunkKind::Define(key) => self.define(key, init, reader),
+            ChunkKind::Stmts(stmts) => self.check_gen_stmts(stmts, init, reader),
+            ChunkKind::Expr(e) => {
+                let pos = e.pos(&self.ast_objs);
+                let count = match Parser::unparen(&e) {
+                    // a call may have no value, or many
+                    Expr::Call(_) => {
+                        let func = format!("__repl{}", n);
+                        let decl = self.func_decl(&func, vec![Stmt::Expr(Box::new(e.clone()))]);
+                        let file = self.file(vec![decl], pos);
+                        let checked = self.check(file, reader);
+                        let scope = *self.tc_objs.pkgs[self.main_tc].scope();
+                        self.tc_objs.scopes[scope].remove(&func);
+                        let types = &self.results[&self.main_tc].types;
+                        match types.get(&e.id()) {
+                            // the builtins with a value can't be statements, the errors
+                            // of the other calls are reported by the declaration too
+                            _ if checked.is_err() => 1,
+                            Some(tv) if matches!(tv.mode, OperandMode::NoValue) => 0,
+                            Some(tv) => match &self.tc_objs.types[tv.typ] {
+                                Type::Tuple(t) => t.vars().len(),
+                                _ => 1,
+                            },
+                            None => 0,
+                        }
+                    }
+                    _ => 1,
+                };
+                if count == 0 {
+                    return self.check_gen_stmts(vec![Stmt::Expr(Box::new(e))], init, reader);
+                }
+                let values: Vec<String> =
+                    (0..count).map(|i| format!("__repl{}_{}", n, i)).collect();
+                let names = values.iter().map(|x| self.ident(pos, x)).collect();
+                let spec = Spec::Value(Rc::new(ValueSpec {
+                    names,
+                    typ: None,
+                    values: vec![e],
+                }));
+                let decl = self.var_decl(spec, pos);
+                let mut code = self.check_gen(vec![decl], pos, init, reader)?;
+                code.values = values;
+                Ok(code)
+            }
+        }
+    }
+
+    /// Imports a package to the package scope, where all the chunks see it
+    fn import<S: SourceRead>(&mut self, spec: SpecKey, reader: &S) -> Result<(), ErrorList> {
+        let el = ErrorList::new();
+        let ispec = match &self.ast_objs.specs[spec] {
+            Spec::Import(is) => is.clone(),
+            _ => unreachable!(),
+        };
+        let lit = ispec.path.token.get_literal();
+        let path = lit.trim_matches('"').to_owned();
+        let key = ImportKey::new(&path, "./");
+        let imported = Importer::new(
+            &self.tconfig,
+            &self.bconfig,
+            reader,
+            &mut self.fset,
+            &mut self.pkgs,
+            &mut self.results,
+            &mut self.ast_objs,
+            &mut self.tc_objs,
+            &el,
+            ispec.path.pos,
+        )
+        .import(&key);
+        let imported = match imported {
+            Ok(pkg) if el.len() == 0 => pkg,
+            _ => return Err(el),
+        };
+
+        let name = match ispec.name {
+            Some(ident) => self.ast_objs.idents[ident].name.clone(),
+            None => self.tc_objs.pkgs[imported].name().clone().unwrap(),
+        };
+        match name.as_str() {
+            "_" => {}
+            "." => {
+                let pos = self.fset.position(ispec.path.pos);
+                el.add(
+                    pos,
+                    "dot imports are not supported".to_owned(),
+                    false,
+                    false,
+                );
+                return Err(el);
+            }
+            _ => {
+                let scope = *self.tc_objs.pkgs[self.main_tc].scope();
+                self.tc_objs.scopes[scope].remove(&name);
+                let pos = ispec.path.pos;
+                let okey = self
+                    .tc_objs
+                    .new_pkg_name(pos, Some(self.main_tc), name, imported);
+                Scope::insert(scope, okey, &mut self.tc_objs);
+            }
+        }
+        let pkg = &mut self.tc_objs.pkgs[self.main_tc];
+        if !pkg.imports().contains(&imported) {
+            pkg.add_import(imported);
+        }
+        Ok(())
+    }
+
+    /// Removes the package level objects the declarations declare again
+    fn replace(&mut self, decls: &[Decl]) {
+        let mut names = vec![];
+        for d in decls.iter() {
+            match d {
+                Decl::Gen(g) => {
+                    for s in g.specs.iter() {
+                        match &self.ast_objs.specs[*s] {
+                            Spec::Value(vs) => names.extend(vs.names.iter().cloned()),
+                            Spec::Type(ts) => names.push(ts.name),
+                            Spec::Import(_) => {}
+                        }
+                    }
+                }
+                Decl::Func(f) => {
+                    let fdecl = &self.ast_objs.fdecls[*f];
+                    if fdecl.recv.is_none() {
+                        names.push(fdecl.name);
+                    }
+                }
+                Decl::Bad(_) => {}
+            }
+        }
+        let scope = *self.tc_objs.pkgs[self.main_tc].scope();
+        for n in names.into_iter() {
+            let name = &self.ast_objs.idents[n].name;
+            if name != "_" && name != "init" {
+                self.tc_objs.scopes[scope].remove(name);
+            }
+        }
+    }
+
+    /// Compiles a `:=` in two steps: the values are kept in new variables first, then
+    /// they are assigned to the variables on the left if those are all there with the
+    /// same types, or the variables are declared again with them.
+    fn define<S: SourceRead>(
+        &mut self,
+        key: AssignStmtKey,
+        init: String,
+        reader: &S,
+    ) -> Result<ChunkCode, ErrorList> {
+        let stmt = &self.ast_objs.a_stmts[key];
+        let pos = stmt.token_pos;
+        let names: Option<Vec<IdentKey>> = stmt
+            .lhs
+            .iter()
+            .map(|x| match x {
+                Expr::Ident(i) => Some(*i),
+                _ => None,
+            })
+            .collect();
+        let names = match names {
+            Some(names) => names,
+            // the checker reports it
+            None => return self.check_gen_stmts(vec![Stmt::Assign(key)], init, reader),
+        };
+        let temps: Vec<String> = (0..names.len())
+            .map(|i| format!("__repl{}_{}", self.chunks, i))
+            .collect();
+        let rhs = stmt.rhs.clone();
+        let spec = Spec::Value(Rc::new(ValueSpec {
+            names: temps.iter().map(|x| self.ident(pos, x)).collect(),
+            typ: None,
+            values: rhs,
+        }));
+        let decl = self.var_decl(spec, pos);
+        let mut code = self.check_gen(vec![decl], pos, init.clone(), reader)?;
+
+        let values: Vec<Expr> = temps
+            .iter()
+            .map(|x| Expr::Ident(self.ident(pos, x)))
+            .collect();
+        let assign = names
+            .iter()
+            .zip(temps.iter())
+            .all(|(name, temp)| self.same_var_type(*name, temp));
+        let set = format!("{}_set", init);
+        let mut more = if assign {
+            let lhs = names.into_iter().map(Expr::Ident).collect();
+            let stmt = AssignStmt::arena_new(&mut self.ast_objs, lhs, pos, Token::ASSIGN, values);
+            self.check_gen_stmts(vec![Stmt::Assign(stmt)], set, reader)?
+        } else {
+            let spec = Spec::Value(Rc::new(ValueSpec {
+                names,
+                typ: None,
+                values,
+            }));
+            let decl = self.var_decl(spec, pos);
+            self.replace(std::slice::from_ref(&decl));
+            self.check_gen(vec![decl], pos, set, reader)?
+        };
+        code.calls.append(&mut more.calls);
+        Ok(code)
+    }
+
+    /// Whether `name` is blank or a package variable of the type of `temp`
+    fn same_var_type(&self, name: IdentKey, temp: &str) -> bool {
+        let name = &self.ast_objs.idents[name].name;
+        if name == "_" {
+            return true;
+        }
+        let scope = &self.tc_objs.scopes[*self.tc_objs.pkgs[self.main_tc].scope()];
+        let typ = |name: &str| {
+            let obj = &self.tc_objs.lobjs[*scope.lookup(name)?];
+            match obj.entity_type() {
+                EntityType::Var(_) => obj.typ(),
+                _ => None,
+            }
+        };
+        match (typ(name), typ(temp)) {
+            (Some(x), Some(y)) => identical(x, y, &self.tc_objs),
+            _ => false,
+        }
+    }
+
+    fn ident(&mut self, pos: Pos, name: &str) -> IdentKey {
+        self.ast_objs.idents.insert(Ident::with_str(pos, name))
+    }
+
+    fn check_gen_stmts<S: SourceRead>(
+        &mut self,
+        stmts: Vec<Stmt>,
+        init: String,
+        reader: &S,
+    ) -> Result<ChunkCode, ErrorList> {
+        let pos = stmts[0].pos(&self.ast_objs);
+        let func = format!("__repl{}", self.chunks);
+        let decl = self.func_decl(&func, stmts);
+        let mut code = self.check_gen(vec![decl], pos, init, reader)?;
+        code.calls.push(func);
+        Ok(code)
+    }
+
+    fn check_gen<S: SourceRead>(
+        &mut self,
+        decls: Vec<Decl>,
+        pos: Pos,
+        init: String,
+        reader: &S,
+    ) -> Result<ChunkCode, ErrorList> {
+        let file = self.file(decls, pos);
+        self.check(file, reader)?;
+        self.gen(init.clone());
+        Ok(ChunkCode {
+            calls: vec![init],
+            values: vec![],
+        })
+    }
+
+    fn check<S: SourceRead>(&mut self, file: File, reader: &S) -> Result<(), ErrorList> {
+        let el = ErrorList::new();
+        let _ = Checker::new(
+            &mut self.tc_objs,
+            &mut self.ast_objs,
+            &mut self.fset,
+            &el,
+            &mut self.pkgs,
+            &mut self.results,
+            self.main_tc,
+            &self.tconfig,
+            &self.bconfig,
+            reader,
+        )
+        .check(vec![file]);
+        if el.len() > 0 {
+            Err(el)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Generates the file of package main checked last, and the packages it imported
+    /// for the first time, into the bytecode. The function initializing the variables
+    /// of the file is the member `init` of package main.
+    fn gen(&mut self, init: String) {
+        let objs = std::mem::replace(&mut self.code.objects, VMObjects::new());
+        let mut vmctx = CodeGenVMCtx::new(objs);
+        let new_pkgs: Vec<TCPackageKey> = self
+            .results
+            .keys()
+            .filter(|x| !self.pkg_map.contains_key(x))
+            .cloned()
+            .collect();
+        for tcpkg in new_pkgs.iter() {
+            let name = self.tc_objs.pkgs[*tcpkg].name().clone().unwrap();
+            let pkey = vmctx.packages_mut().insert(PackageObj::new(name));
+            self.pkg_map.insert(*tcpkg, pkey);
+        }
+
+        let consts = Consts::new();
+        let ifaces = self.iface_selector.len();
+        let indices = self.struct_selector.len();
+        let mut funcs = vec![];
+        for tcpkg in new_pkgs.iter().chain(std::iter::once(&self.main_tc)) {
+            let ti = &self.results[tcpkg];
+            let mut pkg_helper = PkgHelper::new(&self.ast_objs, &self.tc_objs, &self.pkg_map);
+            let cgen = CodeGen::new(
+                &mut vmctx,
+                &consts,
+                &self.ast_objs,
+                &self.tc_objs,
+                ti,
+                &mut self.type_cache,
+                &mut self.iface_selector,
+                &mut self.struct_selector,
+                &mut self.branch_helper,
+                &mut pkg_helper,
+                self.pkg_map[tcpkg],
+                self.blank_ident,
+            );
+            let mut result = if *tcpkg == self.main_tc {
+                cgen.gen_more_files(&ti.ast_files, *tcpkg, init.clone())
+            } else {
+                cgen.gen_with_files(&ti.ast_files, *tcpkg)
+            };
+            funcs.append(&mut result);
+        }
+
+        // the constants are added after the ones of the code generated before
+        let (mut consts, cst_map) = consts.get_runtime_consts(&mut vmctx);
+        let offset = self.code.consts.len();
+        let cst_map = cst_map.into_iter().map(|(k, v)| (k, v + offset)).collect();
+        self.code.consts.append(&mut consts);
+        for f in funcs.into_iter() {
+            f.into_runtime_func(
+                &self.ast_objs,
+                &mut vmctx,
+                self.branch_helper.labels(),
+                &cst_map,
+                &self.code.consts,
+            );
+        }
+
+        let dummy_ti = TypeInfo::new();
+        let mut lookup = TypeLookup::new(&self.tc_objs, &dummy_ti, &mut self.type_cache);
+        for x in self.iface_selector.added_since(ifaces).iter() {
+            let (meta, binding) = lookup.iface_binding_info(*x, &mut vmctx);
+            let binding = binding.into_iter().map(|x| x.into()).collect();
+            self.code.ifaces.push((meta, binding));
+        }
+        let added = self.struct_selector.added_since(indices);
+        self.code.indices.extend(added.iter().cloned());
+        self.code.objects = vmctx.into_vmo();
+    }
+
+    fn file(&self, decls: Vec<Decl>, pos: Pos) -> File {
+        File {
+            package: pos,
+            name: self.main_ident,
+            decls,
+            scope: self.ast_scope,
+            imports: vec![],
+            unresolved: vec![],
+        }
+    }
+
+    fn var_decl(&mut self, spec: Spec, pos: Pos) -> Decl {
+        Decl::Gen(Rc::new(GenDecl {
+            token_pos: pos,
+            token: Token::VAR,
+            l_paran: None,
+            specs: vec![self.ast_objs.specs.insert(spec)],
+            r_paren: None,
+        }))
+    }
+
+    fn func_decl(&mut self, name: &str, stmts: Vec<Stmt>) -> Decl {
+        let pos = stmts[0].pos(&self.ast_objs);
+        let end = stmts[stmts.len() - 1].end(&self.ast_objs);
+        let params = FieldList::new(Some(pos), vec![], Some(pos));
+        let typ = self
+            .ast_objs
+            .ftypes
+            .insert(FuncType::new(Some(pos), params, None));
+        let name = self.ast_objs.idents.insert(Ident::with_str(pos, name));
+        let body = Some(Rc::new(BlockStmt::new(pos, stmts, end)));
+        Decl::Func(self.ast_objs.fdecls.insert(FuncDecl {
+            recv: None,
+            name,
+            typ,
+            body,
+        }))
+    }
+}
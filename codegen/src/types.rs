@@ -5,9 +5,8 @@
 use go_parser::ast::{Expr, Node, NodeId};
 use go_parser::{IdentKey, Map};
 use go_types::{
-    check::TypeInfo, typ::BasicType, typ::ChanDir, typ::Type, ConstValue, EntityType,
-    ObjKey as TCObjKey, OperandMode, PackageKey as TCPackageKey, SelectionKind as TCSelectionKind,
-    TCObjects, TypeKey as TCTypeKey,
+    check::TypeInfo, typ, typ::BasicType, typ::ChanDir, typ::Type, ConstValue, EntityType,
+    ObjKey as TCObjKey, OperandMode, PackageKey as TCPackageKey, TCObjects, TypeKey as TCTypeKey,
 };
 use go_vm::types::*;
 use go_vm::*;
@@ -137,7 +136,12 @@ impl<'a> TypeLookup<'a> {
         vmctx: &mut CodeGenVMCtx,
     ) -> (ValueType, TCTypeKey) {
         let tc_type = self.expr_tc_type(&e);
-        let typ = self.tc_objs.types[tc_type].underlying().unwrap_or(tc_type);
+        let mut typ = self.tc_objs.types[tc_type].underlying().unwrap_or(tc_type);
+        // a pointer to array is indexed and sliced like the array
+        if let Some(p) = self.tc_objs.types[typ].try_as_pointer() {
+            let base = p.base();
+            typ = self.tc_objs.types[base].underlying().unwrap_or(base);
+        }
         let meta = self.tc_type_to_meta(typ, vmctx);
         let metas = &vmctx.metas();
         match &metas[meta.key] {
@@ -203,6 +207,11 @@ impl<'a> TypeLookup<'a> {
         obj.typ().unwrap()
     }
 
+    #[inline]
+    pub fn obj_name(&self, okey: TCObjKey) -> &String {
+        self.tc_objs.lobjs[okey].name()
+    }
+
     #[inline]
     pub fn ident_is_def(&self, ikey: &IdentKey) -> bool {
         self.ti.defs.contains_key(ikey)
@@ -260,14 +269,62 @@ impl<'a> TypeLookup<'a> {
         }
     }
 
+    /// Whether the keys of the map type have to be checked for being hashable when
+    /// they are hashed, a key can only hold a slice, a map or a func through an
+    /// interface.
+    pub fn need_check_map_key(&self, map: TCTypeKey) -> bool {
+        match &self.tc_objs.types[self.underlying_tc(map)] {
+            Type::Map(detail) => self.may_hold_unhashable(detail.key()),
+            _ => false,
+        }
+    }
+
+    fn may_hold_unhashable(&self, typ: TCTypeKey) -> bool {
+        match &self.tc_objs.types[self.underlying_tc(typ)] {
+            Type::Interface(_) => true,
+            Type::Array(detail) => self.may_hold_unhashable(detail.elem()),
+            Type::Struct(detail) => detail
+                .fields()
+                .iter()
+                .any(|f| self.may_hold_unhashable(self.tc_objs.lobjs[*f].typ().unwrap())),
+            _ => false,
+        }
+    }
+
     pub fn tc_type_to_meta(&mut self, typ: TCTypeKey, vmctx: &mut CodeGenVMCtx) -> Meta {
         if !self.types_cache.contains_key(&typ) {
-            let val = self.tc_type_to_meta_impl(typ, vmctx);
+            let val = match self.identical_cached(typ) {
+                Some(m) => m,
+                None => self.tc_type_to_meta_impl(typ, vmctx),
+            };
             self.types_cache.insert(typ, val);
         }
         self.types_cache.get(&typ).unwrap().clone()
     }
 
+    /// The identical unnamed types written in different places are different types to
+    /// the type checker, they share one meta so that type assertions can compare metas.
+    fn identical_cached(&self, typ: TCTypeKey) -> Option<Meta> {
+        let unnamed = |t: TCTypeKey| match &self.tc_objs.types[t] {
+            Type::Array(_)
+            | Type::Slice(_)
+            | Type::Map(_)
+            | Type::Struct(_)
+            | Type::Interface(_)
+            | Type::Chan(_) => true,
+            // the metas of methods have their receivers
+            Type::Signature(s) => s.recv().is_none(),
+            _ => false,
+        };
+        if !unnamed(typ) {
+            return None;
+        }
+        self.types_cache
+            .iter()
+            .find(|(&k, _)| unnamed(k) && typ::identical(k, typ, self.tc_objs))
+            .map(|(_, m)| *m)
+    }
+
     pub fn sig_params_tc_types(&self, func: TCTypeKey) -> (Vec<TCTypeKey>, Option<TCTypeKey>) {
         let typ = &self.tc_objs.types[func].underlying_val(self.tc_objs);
         let sig = typ.try_as_signature().unwrap();
@@ -300,19 +357,6 @@ impl<'a> TypeLookup<'a> {
         self.tuple_tc_types(sig.results())
     }
 
-    pub fn is_method(&self, expr: &Expr) -> bool {
-        match expr {
-            Expr::Selector(sel_expr) => match self.ti.selections.get(&sel_expr.id()) {
-                Some(sel) => match sel.kind() {
-                    TCSelectionKind::MethodVal => true,
-                    _ => false,
-                },
-                None => false,
-            },
-            _ => false,
-        }
-    }
-
     // returns vm_type(metadata) for the tc_type
     pub fn basic_type_meta(&self, tkey: TCTypeKey, prim_meta: &PrimitiveMeta) -> Option<Meta> {
         self.tc_objs.types[tkey].try_as_basic().map(|x| {
@@ -345,7 +389,7 @@ impl<'a> TypeLookup<'a> {
     }
 
     // get GosValue from type checker's Obj
-    fn const_value(&self, tkey: TCTypeKey, val: &ConstValue) -> GosValue {
+    pub fn const_value(&self, tkey: TCTypeKey, val: &ConstValue) -> GosValue {
         let underlying_type = self.tc_objs.types[tkey].underlying().unwrap_or(tkey);
         let typ = self.tc_type_to_value_type(underlying_type);
         match typ {
@@ -385,7 +429,7 @@ impl<'a> TypeLookup<'a> {
         match &self.tc_objs.types[typ] {
             Type::Basic(_) => self.basic_type_meta(typ, vmctx.prim_meta()).unwrap(),
             Type::Array(detail) => {
-                let elem = self.tc_type_to_meta_impl(detail.elem(), vmctx);
+                let elem = self.tc_type_to_meta(detail.elem(), vmctx);
                 Meta::new_array(elem, detail.len().unwrap() as usize, vmctx.metas_mut())
             }
             Type::Slice(detail) => {
@@ -452,7 +496,22 @@ impl<'a> TypeLookup<'a> {
             }
             Type::Named(detail) => {
                 // generate a Named with dummy underlying to avoid recursion
-                let md = Meta::new_named(vmctx.prim_meta().mint, vmctx.metas_mut());
+                let obj = &self.tc_objs.lobjs[detail.obj().unwrap()];
+                let name = match obj.pkg() {
+                    Some(p) => {
+                        let pkg = &self.tc_objs.pkgs[p];
+                        let pkg_name = pkg.name().as_deref().unwrap_or("");
+                        // the main package is imported by its file path, Go calls it "main"
+                        let path = if pkg_name == "main" {
+                            "main"
+                        } else {
+                            pkg.path()
+                        };
+                        TypeName::new(path, pkg_name, obj.name())
+                    }
+                    None => TypeName::new("", "", obj.name()),
+                };
+                let md = Meta::new_named(name, vmctx.prim_meta().mint, vmctx.metas_mut());
                 for key in detail.methods().iter() {
                     let mobj = &self.tc_objs.lobjs[*key];
                     md.add_method(
@@ -540,10 +599,19 @@ impl<'a> TypeLookup<'a> {
     }
 
     pub fn should_cast_to_iface(&self, lhs: TCTypeKey, rhs: TCTypeKey) -> bool {
-        let vt1 = self.obj_underlying_value_type(rhs);
-        self.obj_underlying_value_type(lhs) == ValueType::Interface
-            && vt1 != ValueType::Interface
-            && vt1 != ValueType::Void
+        if self.obj_underlying_value_type(lhs) != ValueType::Interface {
+            return false;
+        }
+        match self.obj_underlying_value_type(rhs) {
+            ValueType::Void => false,
+            // the method bindings need to be rearranged for a different interface
+            ValueType::Interface => !typ::identical_ignore_tags(
+                self.underlying_tc(lhs),
+                self.underlying_tc(rhs),
+                self.tc_objs,
+            ),
+            _ => true,
+        }
     }
 
     fn range_tc_types(&self, typ: TCTypeKey) -> [TCTypeKey; 3] {
@@ -551,7 +619,9 @@ impl<'a> TypeLookup<'a> {
         let typ = self.tc_objs.types[typ].underlying().unwrap_or(typ);
         match &self.tc_objs.types[typ] {
             Type::Basic(detail) => match detail.typ() {
-                BasicType::Str | BasicType::UntypedString => [typ, t_int, t_int],
+                BasicType::Str | BasicType::UntypedString => {
+                    [typ, t_int, *self.tc_objs.universe().rune()]
+                }
                 _ => unreachable!(),
             },
             Type::Slice(detail) => [typ, t_int, detail.elem()],
@@ -617,6 +687,7 @@ impl<'a> TypeLookup<'a> {
                 meta,
                 name: field.name().clone(),
                 tag,
+                embedded,
                 embedded_indices: None,
             });
             if embedded {
@@ -637,6 +708,7 @@ impl<'a> TypeLookup<'a> {
                             meta: f.meta,
                             name: f.name.clone(),
                             tag: f.tag.clone(),
+                            embedded: false,
                             embedded_indices: Some(indices),
                         });
                     }
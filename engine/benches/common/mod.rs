@@ -0,0 +1,18 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! What the benchmarks share, they run the programs of tests/demo, which need the
+//! standard library.
+
+#![cfg(feature = "go_std")]
+
+use std::path::{Path, PathBuf};
+
+/// Runs tests/demo/`name`.gos, which must succeed
+pub fn run_demo(name: &str) {
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = format!("./tests/demo/{}.gos", name);
+    let result = engine::run(engine::Config::default(), &sr, Path::new(&path));
+    assert!(result.is_ok());
+}
@@ -0,0 +1,20 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+extern crate go_engine as engine;
+
+mod common;
+use common::run_demo;
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("goroutines");
+    group.sample_size(10);
+    group.bench_function("spawn 100k", |b| b.iter(|| run_demo("goroutines")));
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
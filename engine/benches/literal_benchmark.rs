@@ -0,0 +1,21 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+extern crate go_engine as engine;
+
+mod common;
+use common::run_demo;
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("literals");
+    group.sample_size(10);
+    group.bench_function("composite 20k", |b| b.iter(|| run_demo("literals")));
+    group.bench_function("slices 1M", |b| b.iter(|| run_demo("slices")));
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
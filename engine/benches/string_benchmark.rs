@@ -0,0 +1,27 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+extern crate go_engine as engine;
+
+mod common;
+use common::run_demo;
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("strings");
+    group.sample_size(10);
+    group.bench_function("convert 64KB x 2000", |b| {
+        b.iter(|| run_demo("conversions"))
+    });
+    group.bench_function("sort 5000 x 2", |b| b.iter(|| run_demo("string_sort")));
+    group.bench_function("build 100k x 2", |b| b.iter(|| run_demo("string_build")));
+    group.bench_function("package strings 560KB x 20", |b| {
+        b.iter(|| run_demo("strings_pkg"))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,31 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+extern crate go_engine as engine;
+
+mod common;
+use common::run_demo;
+
+/// The basic operations of the VM, one program each, see BENCHMARKS.md for the
+/// baseline numbers
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("vm");
+    group.sample_size(10);
+    group.bench_function("calls 300k", |b| b.iter(|| run_demo("calls")));
+    group.bench_function("closures 200k", |b| b.iter(|| run_demo("closures")));
+    group.bench_function("channel ping-pong 50k", |b| b.iter(|| run_demo("channels")));
+    group.bench_function("maps 100k", |b| b.iter(|| run_demo("maps")));
+    group.bench_function("append 300k", |b| b.iter(|| run_demo("appends")));
+    group.bench_function("concat 200k", |b| b.iter(|| run_demo("concat")));
+    group.bench_function("interface calls 200k", |b| {
+        b.iter(|| run_demo("iface_calls"))
+    });
+    group.bench_function("numeric loops 1M", |b| b.iter(|| run_demo("numeric")));
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,89 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Writes the list of the features the engine is built with into OUT_DIR, for
+//! `runtime/goscript` to report them.
+//! Zips the Go-source standard library into OUT_DIR when the `embed_std` feature
+//! is on, so that it can be embedded into the binary.
+//! The std directory can be overridden with the `GOSCRIPT_STD_DIR` environment variable.
+
+fn main() {
+    write_features();
+    #[cfg(feature = "embed_std")]
+    embed_std::zip_std();
+}
+
+/// Cargo tells the build script the features on with CARGO_FEATURE_<NAME> variables,
+/// so the list can't miss one added to Cargo.toml
+fn write_features() {
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(k, _)| k.strip_prefix("CARGO_FEATURE_").map(|f| f.to_lowercase()))
+        .filter(|f| f != "default")
+        .collect();
+    features.sort();
+    let out = std::path::Path::new(&std::env::var_os("OUT_DIR").unwrap()).join("features.rs");
+    std::fs::write(out, format!("&{:?}", features)).unwrap();
+}
+
+#[cfg(feature = "embed_std")]
+mod embed_std {
+    use std::fs;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use zip::write::{FileOptions, ZipWriter};
+    use zip::CompressionMethod;
+
+    pub fn zip_std() {
+        println!("cargo:rerun-if-env-changed=GOSCRIPT_STD_DIR");
+        let std_dir = match std::env::var_os("GOSCRIPT_STD_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => Path::new(&std::env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("../std"),
+        };
+        println!("cargo:rerun-if-changed={}", std_dir.display());
+
+        let version = fs::read_to_string(std_dir.join("VERSION")).unwrap_or_else(|e| {
+            panic!(
+                "failed to read VERSION of std at {}: {}",
+                std_dir.display(),
+                e
+            )
+        });
+        let crate_version = env!("CARGO_PKG_VERSION");
+        if !compatible(version.trim(), crate_version) {
+            panic!(
+                "std library version {} is not compatible with go-engine {}",
+                version.trim(),
+                crate_version
+            );
+        }
+
+        let out = Path::new(&std::env::var_os("OUT_DIR").unwrap()).join("std.zip");
+        let mut zip = ZipWriter::new(fs::File::create(&out).unwrap());
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        let mut files = vec![];
+        collect_files(&std_dir, &mut files);
+        files.sort();
+        for path in files.iter() {
+            let rel = path.strip_prefix(&std_dir).unwrap();
+            let name = Path::new("std").join(rel);
+            let name = name.to_string_lossy().replace('\\', "/");
+            zip.start_file(name, options).unwrap();
+            zip.write_all(&fs::read(path).unwrap()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    include!("src/version.rs");
+
+    fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                collect_files(&path, files);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+}
@@ -3,15 +3,19 @@
 // license that can be found in the LICENSE file.
 
 use crate::ffi::Ffi;
-#[cfg(feature = "go_std")]
+use crate::plugin::EnginePlugin;
+#[cfg(feature = "codegen")]
+use crate::plugin::LayeredReader;
+#[cfg(feature = "std_base")]
 use crate::std::os;
+use crate::vfs::VirtualFs;
 #[cfg(feature = "serde_borsh")]
 use borsh::{BorshDeserialize, BorshSerialize};
 use std::path::Path;
 use std::rc::Rc;
 
 #[cfg(feature = "codegen")]
-pub use cg::SourceRead;
+pub use cg::{ConstValue, SourceRead};
 #[cfg(feature = "codegen")]
 extern crate go_codegen as cg;
 #[cfg(feature = "codegen")]
@@ -20,6 +24,12 @@ extern crate go_parser as parser;
 extern crate go_types as types;
 extern crate go_vm as vm;
 
+pub use vm::{
+    AllocFailure, AssertHook, AssertInfo, AuditHook, CallError, CancelHandle, Capability,
+    HeapStats, Instance, OomAction, OomPolicy, PanicHook, PanicInfo, PauseInfo, Quota, RootedValue,
+    RunError, RunStats, SandboxPolicy, StackSize, Step, StepVm, VmController,
+};
+
 #[derive(Default)]
 pub struct Config {
     /// print debug info in parser
@@ -27,60 +37,93 @@ pub struct Config {
     /// print debug info in checker
     pub trace_checker: bool,
     /// custom std in
-    pub std_in: Option<Box<dyn std::io::Read + Sync + Send>>,
-    /// custom std out
-    pub std_out: Option<Box<dyn std::io::Write + Sync + Send>>,
+    pub std_in: Option<Box<dyn std::io::Read + Send>>,
+    /// custom std out, like an `OutputBuffer`
+    pub std_out: Option<Box<dyn std::io::Write + Send>>,
     /// custom std err
-    pub std_err: Option<Box<dyn std::io::Write + Sync + Send>>,
+    pub std_err: Option<Box<dyn std::io::Write + Send>>,
+    /// the (GOOS, GOARCH) to compile for, defaults to the host platform
+    #[cfg(feature = "codegen")]
+    pub target: Option<(String, String)>,
+    /// build tags satisfied by the host, checked against `//go:build` lines
+    #[cfg(feature = "codegen")]
+    pub build_tags: Vec<String>,
+    /// untyped constants predeclared for the scripts, like `debug = true`
+    #[cfg(feature = "codegen")]
+    pub constants: Vec<(String, ConstValue)>,
+    /// the initial sizes of the goroutine stacks
+    pub stack_size: StackSize,
+    /// for pausing and resuming the run from other threads
+    pub controller: Option<VmController>,
+    /// called with the panics not recovered, instead of printing them
+    pub panic_hook: Option<PanicHook>,
+    /// called with the failed assertions, before they panic
+    pub assert_hook: Option<AssertHook>,
+    /// print the local variables of the calls with the panics not recovered
+    pub dump_locals_on_panic: bool,
+    /// the largest allocation, in bytes, a `make` may ask for
+    pub max_alloc: Option<usize>,
+    /// what to do with the allocations over `max_alloc`, or failed
+    pub oom_policy: OomPolicy,
+    /// what the scripts may do to the host, not restricted if None
+    pub sandbox: Option<SandboxPolicy>,
+    /// the instructions a run may execute before it's stopped
+    pub max_instructions: Option<u64>,
+    /// the limits of every goroutine but the main one
+    pub goroutine_quota: Option<Quota>,
+    /// the bytes the live objects of a run may take
+    pub max_heap: Option<usize>,
 }
 
 pub struct Engine {
-    ffi: vm::FfiFactory,
+    pub(crate) ffi: vm::FfiFactory,
+    #[cfg(feature = "codegen")]
+    pub(crate) build: cg::BuildConfig,
+    plugins: Vec<String>,
+    pub(crate) lib_layers: Vec<Box<dyn VirtualFs>>,
+    pub(crate) run_options: vm::RunOptions,
 }
 
 impl Engine {
     pub fn new() -> Engine {
-        #[cfg(not(feature = "go_std"))]
-        {
-            Engine {
-                ffi: vm::FfiFactory::new(),
-            }
-        }
-
-        #[cfg(feature = "go_std")]
-        {
-            let mut e = Engine {
-                ffi: vm::FfiFactory::new(),
-            };
-            crate::std::register(&mut e.ffi);
-            e
-        }
+        Engine::with_ffi(vm::FfiFactory::new())
     }
 
     pub fn with_user_data(data: usize) -> Engine {
-        #[cfg(not(feature = "go_std"))]
-        {
-            Engine {
-                ffi: vm::FfiFactory::with_user_data(data),
-            }
-        }
+        Engine::with_ffi(vm::FfiFactory::with_user_data(data))
+    }
 
-        #[cfg(feature = "go_std")]
+    /// Assembles the engine with the FFIs of the std packages compiled in, the ones
+    /// compiled out can't be imported.
+    fn with_ffi(ffi: vm::FfiFactory) -> Engine {
+        #[allow(unused_mut)]
+        let mut e = Engine {
+            ffi,
+            #[cfg(feature = "codegen")]
+            build: cg::BuildConfig::default(),
+            plugins: vec![],
+            lib_layers: vec![],
+            run_options: vm::RunOptions::default(),
+        };
+        #[cfg(feature = "std_base")]
         {
-            let mut e = Engine {
-                ffi: vm::FfiFactory::with_user_data(data),
-            };
             crate::std::register(&mut e.ffi);
-            e
+            #[cfg(feature = "codegen")]
+            {
+                e.build.excluded = crate::std::excluded_packages();
+            }
         }
+        e
     }
 
-    #[cfg(feature = "go_std")]
+    /// Sets the std io of the runs on the current thread, the std handles of the
+    /// process are used for the ones that are None.
+    #[cfg(feature = "std_base")]
     pub fn set_std_io(
         &self,
-        std_in: Option<Box<dyn std::io::Read + Sync + Send>>,
-        std_out: Option<Box<dyn std::io::Write + Sync + Send>>,
-        std_err: Option<Box<dyn std::io::Write + Sync + Send>>,
+        std_in: Option<Box<dyn std::io::Read + Send>>,
+        std_out: Option<Box<dyn std::io::Write + Send>>,
+        std_err: Option<Box<dyn std::io::Write + Send>>,
     ) {
         os::set_std_io(std_in, std_out, std_err);
     }
@@ -89,6 +132,145 @@ impl Engine {
         self.ffi.register(name, proto);
     }
 
+    /// Adds a plugin, which registers its FFIs, overlays its library files on the
+    /// library directory and configures the engine.
+    /// Panics if a plugin with the same name has been added.
+    pub fn add_plugin<P: EnginePlugin>(&mut self, plugin: P) {
+        let name = plugin.name();
+        assert!(
+            !self.plugins.iter().any(|p| p == name),
+            "plugin {} has already been added",
+            name
+        );
+        self.plugins.push(name.to_owned());
+        plugin.register_ffi(&mut self.ffi);
+        if let Some(fs) = plugin.lib_fs() {
+            self.lib_layers.push(fs);
+        }
+        plugin.configure(self);
+    }
+
+    /// Returns the names of the plugins added
+    pub fn plugins(&self) -> &[String] {
+        &self.plugins
+    }
+
+    /// Sets the (GOOS, GOARCH) to compile for, which decides what files like
+    /// `xxx_windows.gos` or `xxx_linux_amd64.gos` are compiled, and are satisfied
+    /// as build tags.
+    #[cfg(feature = "codegen")]
+    pub fn set_target(&mut self, goos: &str, goarch: &str) {
+        self.build.goos = goos.to_owned();
+        self.build.goarch = goarch.to_owned();
+    }
+
+    /// Adds a build tag, files with a `//go:build` line are only compiled
+    /// if the constraint is satisfied by the tags added.
+    #[cfg(feature = "codegen")]
+    pub fn add_build_tag(&mut self, tag: &str) {
+        self.build.tags.push(tag.to_owned());
+    }
+
+    /// Sets the initial sizes of the stacks of the main goroutine and the goroutines
+    /// it spawns, the stacks grow as needed.
+    pub fn set_stack_size(&mut self, size: StackSize) {
+        self.run_options.stack_size = size;
+    }
+
+    /// Returns the handle for pausing and resuming the scripts this engine runs, from
+    /// other threads.
+    pub fn controller(&self) -> VmController {
+        self.run_options.controller.clone()
+    }
+
+    pub fn set_controller(&mut self, ctl: VmController) {
+        self.run_options.controller = ctl;
+    }
+
+    /// Returns a token that stops the script this engine is running, from other
+    /// threads, the run returns `RunError::Cancelled`. It's the one of the controller.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.run_options.controller.cancel_handle()
+    }
+
+    /// Sets the function to call with the panics the scripts don't recover, instead of
+    /// printing them.
+    pub fn set_panic_hook(&mut self, hook: PanicHook) {
+        self.run_options.panic_hook = Some(hook);
+    }
+
+    /// Sets the function to call with the failed calls of the built-in `assert`, with
+    /// where they are. They still panic, the hook sees the ones recovered too.
+    pub fn set_assert_hook(&mut self, hook: AssertHook) {
+        self.run_options.assert_hook = Some(hook);
+    }
+
+    /// Makes the panics not recovered come with the local variables of the calls they
+    /// unwound, for the panic hook or for printing.
+    pub fn set_dump_locals_on_panic(&mut self, dump: bool) {
+        self.run_options.dump_locals_on_panic = dump;
+    }
+
+    /// Limits the size of the allocations of `make`, with what to do if a script asks
+    /// for more, or the allocator fails.
+    pub fn set_alloc_limit(&mut self, max_alloc: Option<usize>, policy: OomPolicy) {
+        self.run_options.max_alloc = max_alloc;
+        self.run_options.oom_policy = policy;
+    }
+
+    /// Restricts what the scripts may do to the host: the files they open, the
+    /// environment variables they see, the hosts they connect to and the number
+    /// of goroutines. Everything the policy doesn't allow is denied.
+    pub fn set_sandbox(&mut self, policy: SandboxPolicy) {
+        self.run_options.sandbox = Some(policy);
+    }
+
+    /// Limits the number of instructions the goroutines of a run execute in total,
+    /// a run going over it is stopped with `RunError::OutOfFuel`, so that a script
+    /// looping forever doesn't hang the host.
+    pub fn set_instruction_limit(&mut self, max: Option<u64>) {
+        self.run_options.max_instructions = max;
+    }
+
+    /// Limits what each goroutine started by a script may use, a goroutine going over
+    /// it panics while the others keep running. The main goroutine is not limited.
+    pub fn set_goroutine_quota(&mut self, quota: Option<Quota>) {
+        self.run_options.goroutine_quota = quota;
+    }
+
+    /// Limits the bytes the live objects of a run take, a goroutine allocating over
+    /// it panics with "runtime: out of memory" instead of the host running out of
+    /// memory. The bytes are estimated, see `HeapStats`.
+    pub fn set_heap_limit(&mut self, max: Option<usize>) {
+        self.run_options.max_heap = max;
+    }
+
+    /// Sets the number of instructions a goroutine runs before the others get a turn,
+    /// 1024 if None.
+    pub fn set_time_slice(&mut self, instructions: Option<usize>) {
+        self.run_options.time_slice = instructions;
+    }
+
+    /// Makes the addresses printed for pointers, funcs and channels made up ones,
+    /// numbered in the order they are printed, for the output to be the same on every
+    /// run, like in tests.
+    pub fn set_stable_addresses(&mut self, stable: bool) {
+        self.run_options.stable_addresses = stable;
+    }
+
+    /// Makes a select with several cases ready pick the first one instead of one at
+    /// random, for the runs to be repeatable, like in tests. Go doesn't promise an
+    /// order, the scripts relying on it are wrong.
+    pub fn set_deterministic_select(&mut self, deterministic: bool) {
+        self.run_options.deterministic_select = deterministic;
+    }
+
+    /// Predeclares an untyped constant that is visible in all the packages.
+    #[cfg(feature = "codegen")]
+    pub fn define_const(&mut self, name: &str, val: ConstValue) {
+        self.build.constants.push((name.to_owned(), val));
+    }
+
     #[cfg(feature = "codegen")]
     pub fn compile<S: SourceRead>(
         &self,
@@ -101,8 +283,10 @@ impl Engine {
             trace_parser,
             trace_checker,
         };
+        let reader = &LayeredReader::new(reader, &self.lib_layers);
+        check_std_version(reader)?;
         let mut fs = parser::FileSet::new();
-        cg::parse_check_gen(path, &cfg, reader, &mut fs).map(|x| (x, fs))
+        cg::parse_check_gen(path, &cfg, &self.build, reader, &mut fs).map(|x| (x, fs))
     }
 
     #[cfg(all(feature = "codegen", feature = "serde_borsh"))]
@@ -117,10 +301,38 @@ impl Engine {
             .map(|(code, _)| code.try_to_vec().unwrap())
     }
 
-    pub fn run_bytecode(&self, bc: &vm::Bytecode) {
-        vm::run(bc, &self.ffi, None)
+    /// Runs the bytecode, returns what the run used.
+    pub fn run_bytecode(&self, bc: &vm::Bytecode) -> Result<RunStats, RunError> {
+        vm::run(bc, &self.ffi, None, &self.run_options)
+    }
+
+    /// Calls the function `func` of the package named `pkg` with `args`, main is not
+    /// called. The packages are initialized by the first call, their variables keep
+    /// their values across the calls on the same bytecode. The arguments must have
+    /// the types of the parameters, the results are returned in order.
+    pub fn call(
+        &self,
+        bc: &vm::Bytecode,
+        pkg: &str,
+        func: &str,
+        args: Vec<vm::types::GosValue>,
+    ) -> Result<Vec<vm::types::GosValue>, CallError> {
+        vm::call(bc, &self.ffi, None, &self.run_options, pkg, func, args)
+    }
+
+    /// Initializes the packages of the bytecode and keeps the VM for calling its
+    /// functions many times, without starting it over for each call like `call`.
+    pub fn instance<'a>(&'a self, bc: &'a vm::Bytecode) -> Result<Instance<'a>, CallError> {
+        Instance::new(bc, &self.ffi, None, &self.run_options)
+    }
+
+    /// For running the bytecode one instruction at a time, with the FFIs and the
+    /// options of the engine.
+    pub fn step_vm<'a>(&'a self, bc: &'a vm::Bytecode) -> StepVm<'a> {
+        StepVm::with_options(bc, &self.ffi, self.run_options.clone())
     }
 
+    /// Compiles and runs the program, returns what the run used.
     #[cfg(feature = "codegen")]
     pub fn run_source<S: SourceRead>(
         &self,
@@ -128,20 +340,98 @@ impl Engine {
         trace_checker: bool,
         reader: &S,
         path: &Path,
-    ) -> Result<(), parser::ErrorList> {
-        self.compile(trace_parser, trace_checker, reader, path)
-            .map(|(code, fs)| {
-                #[cfg(feature = "serde_borsh")]
-                {
-                    let encoded = code.try_to_vec().unwrap();
-                    let decoded = go_vm::Bytecode::try_from_slice(&encoded).unwrap();
-                    dbg!(encoded.len());
-                    vm::run(&decoded, &self.ffi, Some(&fs))
-                }
-                #[cfg(not(feature = "serde_borsh"))]
-                {
-                    vm::run(&code, &self.ffi, Some(&fs))
-                }
-            })
+    ) -> Result<RunStats, SourceError> {
+        let (code, fs) = self.compile(trace_parser, trace_checker, reader, path)?;
+        #[cfg(feature = "serde_borsh")]
+        let code = {
+            let encoded = code.try_to_vec().unwrap();
+            dbg!(encoded.len());
+            go_vm::Bytecode::try_from_slice(&encoded).unwrap()
+        };
+        #[cfg(feature = "serde_borsh")]
+        code.verify().map_err(|e| {
+            let el = parser::ErrorList::new();
+            el.add(None, e.to_string(), false, false);
+            el
+        })?;
+        vm::run(&code, &self.ffi, Some(&fs), &self.run_options).map_err(SourceError::Run)
+    }
+}
+
+/// Why `Engine::run_source` didn't run the program to the end
+#[cfg(feature = "codegen")]
+#[derive(Clone, Debug)]
+pub enum SourceError {
+    /// The program didn't compile
+    Compile(parser::ErrorList),
+    /// The VM stopped the run
+    Run(RunError),
+}
+
+#[cfg(feature = "codegen")]
+impl std::fmt::Display for SourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SourceError::Compile(el) => write!(f, "{}", el),
+            SourceError::Run(e) => write!(f, "run aborted: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "codegen")]
+impl std::error::Error for SourceError {}
+
+#[cfg(feature = "codegen")]
+impl From<parser::ErrorList> for SourceError {
+    fn from(el: parser::ErrorList) -> Self {
+        SourceError::Compile(el)
+    }
+}
+
+#[cfg(feature = "codegen")]
+impl SourceError {
+    /// The errors as a list, with the reason a run was stopped as its only error.
+    pub fn into_error_list(self) -> parser::ErrorList {
+        match self {
+            SourceError::Compile(el) => el,
+            SourceError::Run(e) => {
+                let el = parser::ErrorList::new();
+                el.add(None, format!("run aborted: {}", e), false, false);
+                el
+            }
+        }
+    }
+}
+
+/// The version of the Go-source std library this engine works with,
+/// it's checked against the `VERSION` file in the root of the std library.
+pub const STD_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Makes sure the std library, if it has a `VERSION` file, is compatible with the engine,
+/// see `version::compatible` for which versions are.
+#[cfg(feature = "codegen")]
+pub(crate) fn check_std_version<S: SourceRead>(reader: &S) -> Result<(), parser::ErrorList> {
+    let path = match reader.base_dir() {
+        Some(base) => base.join("VERSION"),
+        None => return Ok(()),
+    };
+    let version = match reader.read_file(&path) {
+        Ok(v) => v,
+        Err(_) => return Ok(()),
+    };
+    if crate::version::compatible(version.trim(), STD_VERSION) {
+        return Ok(());
     }
+    let el = parser::ErrorList::new();
+    el.add(
+        None,
+        format!(
+            "std library version {} is not compatible with the engine, {} expected",
+            version.trim(),
+            STD_VERSION
+        ),
+        false,
+        false,
+    );
+    Err(el)
 }
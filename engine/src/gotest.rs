@@ -0,0 +1,291 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Running the tests and the benchmarks of a package, like `go test`.
+//!
+//! The package is compiled with its `_test.go` files, and every function of the form
+//! `func TestXxx(*testing.T)` in them is called, in the order they are declared, by
+//! `testing.RunTest` on a VM kept across the tests. The tests must be in the package
+//! tested, `package foo_test` is not supported.
+//!
+//! The functions `func BenchmarkXxx(*testing.B)` are called the same way by
+//! `testing.RunBenchmark`, with b.N growing like `go test -bench` grows it until a
+//! call takes the time asked for. The last call is measured: the wall-clock time,
+//! and the instructions and the allocations the VM counted.
+
+use crate::engine::{check_std_version, Engine};
+use crate::plugin::LayeredReader;
+use go_codegen::{SourceRead, TraceConfig};
+use go_parser::{ErrorList, FileSet, Pos};
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+extern crate go_vm as vm;
+
+// the code of a package compiled with its tests, and the test or benchmark functions
+type TestBuild = (vm::Bytecode, FileSet, Vec<(String, Pos)>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+/// The result of a test function
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    pub name: String,
+    /// Where the function is declared, as "file:line"
+    pub pos: String,
+    pub status: TestStatus,
+    /// What the test logged, with the subtests failed
+    pub output: String,
+}
+
+/// The results of the tests of a package
+#[derive(Debug, Clone, Default)]
+pub struct TestReport {
+    pub results: Vec<TestResult>,
+}
+
+/// The result of a benchmark function, the measures are of its last run
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub name: String,
+    /// Where the function is declared, as "file:line"
+    pub pos: String,
+    pub status: TestStatus,
+    /// What the benchmark logged
+    pub output: String,
+    /// The b.N of the last run
+    pub n: u64,
+    pub ns_per_op: f64,
+    /// The instructions the VM ran, by all the goroutines
+    pub instructions_per_op: f64,
+    /// The objects made, like `RunStats::allocations`
+    pub allocs_per_op: f64,
+    pub bytes_per_op: f64,
+}
+
+/// The results of the benchmarks of a package
+#[derive(Debug, Clone, Default)]
+pub struct BenchReport {
+    pub results: Vec<BenchResult>,
+}
+
+impl TestReport {
+    /// Reports whether no test failed
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|r| r.status != TestStatus::Failed)
+    }
+
+    pub fn count(&self, status: TestStatus) -> usize {
+        self.results.iter().filter(|r| r.status == status).count()
+    }
+}
+
+/// Prints the failed tests with what they logged, and a summary, like `go test`
+impl fmt::Display for TestReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for r in self
+            .results
+            .iter()
+            .filter(|r| r.status == TestStatus::Failed)
+        {
+            writeln!(f, "--- FAIL: {} ({})", r.name, r.pos)?;
+            f.write_str(&r.output)?;
+        }
+        writeln!(
+            f,
+            "{}\t{} passed, {} failed, {} skipped",
+            if self.passed() { "ok" } else { "FAIL" },
+            self.count(TestStatus::Passed),
+            self.count(TestStatus::Failed),
+            self.count(TestStatus::Skipped),
+        )
+    }
+}
+
+impl BenchReport {
+    /// Reports whether no benchmark failed
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|r| r.status != TestStatus::Failed)
+    }
+}
+
+/// Prints a line for each benchmark run, the failed ones with what they logged, like
+/// `go test -bench`
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for r in self.results.iter() {
+            match r.status {
+                TestStatus::Passed => writeln!(
+                    f,
+                    "{}\t{:8}\t{:12.1} ns/op\t{:10.1} instructions/op\t{:8.1} allocs/op\t{:8.1} B/op",
+                    r.name,
+                    r.n,
+                    r.ns_per_op,
+                    r.instructions_per_op,
+                    r.allocs_per_op,
+                    r.bytes_per_op,
+                )?,
+                TestStatus::Failed => {
+                    writeln!(f, "--- FAIL: {} ({})", r.name, r.pos)?;
+                    f.write_str(&r.output)?;
+                }
+                TestStatus::Skipped => {
+                    writeln!(f, "--- SKIP: {} ({})", r.name, r.pos)?;
+                    f.write_str(&r.output)?;
+                }
+            }
+        }
+        writeln!(f, "{}", if self.passed() { "ok" } else { "FAIL" })
+    }
+}
+
+impl Engine {
+    /// Compiles the package at `path` with its `_test.go` files and runs its tests.
+    /// The errors are the ones compiling the package or initializing it, the tests
+    /// failed are in the report.
+    pub fn run_tests<S: SourceRead>(
+        &self,
+        trace_parser: bool,
+        trace_checker: bool,
+        reader: &S,
+        path: &Path,
+    ) -> Result<TestReport, ErrorList> {
+        let (code, fs, funcs) = self.compile_tests(trace_parser, trace_checker, reader, path)?;
+        let inst =
+            vm::Instance::new(&code, &self.ffi, Some(&fs), &self.run_options).map_err(run_error)?;
+        let main = &code.objects.packages[code.main_pkg];
+        let results = funcs
+            .into_iter()
+            .filter(|(name, _)| name.starts_with("Test"))
+            .map(|(name, pos)| {
+                let func = main.member(*main.member_index(&name).unwrap()).clone();
+                let args = vec![name.clone().into(), func];
+                let (status, output) = outcome(inst.call("testing", "RunTest", args));
+                TestResult {
+                    name,
+                    pos: position(&fs, pos),
+                    status,
+                    output,
+                }
+            })
+            .collect();
+        Ok(TestReport { results })
+    }
+
+    /// Compiles the package at `path` with its `_test.go` files and runs its
+    /// benchmarks, b.N grows until a run takes `bench_time` or more, or reaches a
+    /// billion. The errors are like the ones of `run_tests`.
+    pub fn run_benchmarks<S: SourceRead>(
+        &self,
+        trace_parser: bool,
+        trace_checker: bool,
+        reader: &S,
+        path: &Path,
+        bench_time: Duration,
+    ) -> Result<BenchReport, ErrorList> {
+        let (code, fs, funcs) = self.compile_tests(trace_parser, trace_checker, reader, path)?;
+        let inst =
+            vm::Instance::new(&code, &self.ffi, Some(&fs), &self.run_options).map_err(run_error)?;
+        let main = &code.objects.packages[code.main_pkg];
+        let results = funcs
+            .into_iter()
+            .filter(|(name, _)| name.starts_with("Benchmark"))
+            .map(|(name, pos)| {
+                let func = main.member(*main.member_index(&name).unwrap()).clone();
+                let run = |n: u64| {
+                    let before = inst.stats();
+                    let start = Instant::now();
+                    let args = vec![name.clone().into(), func.clone(), (n as isize).into()];
+                    let result = inst.call("testing", "RunBenchmark", args);
+                    let elapsed = start.elapsed();
+                    (outcome(result), elapsed, before, inst.stats())
+                };
+                let mut n = 1;
+                let mut last = run(n);
+                while last.0 .0 == TestStatus::Passed && last.1 < bench_time && n < MAX_N {
+                    n = next_n(n, last.1, bench_time);
+                    last = run(n);
+                }
+                let ((status, output), elapsed, before, after) = last;
+                let per_op = |x: u64| x as f64 / n as f64;
+                BenchResult {
+                    name,
+                    pos: position(&fs, pos),
+                    status,
+                    output,
+                    n,
+                    ns_per_op: elapsed.as_nanos() as f64 / n as f64,
+                    instructions_per_op: per_op(after.instructions - before.instructions),
+                    allocs_per_op: per_op(after.allocations - before.allocations),
+                    bytes_per_op: per_op(after.allocated_bytes - before.allocated_bytes),
+                }
+            })
+            .collect();
+        Ok(BenchReport { results })
+    }
+
+    /// Compiles the package with its `_test.go` files, returns the test and the
+    /// benchmark functions too
+    fn compile_tests<S: SourceRead>(
+        &self,
+        trace_parser: bool,
+        trace_checker: bool,
+        reader: &S,
+        path: &Path,
+    ) -> Result<TestBuild, ErrorList> {
+        let cfg = TraceConfig {
+            trace_parser,
+            trace_checker,
+        };
+        let reader = &LayeredReader::new(reader, &self.lib_layers);
+        check_std_version(reader)?;
+        let mut fs = FileSet::new();
+        let (code, funcs) =
+            go_codegen::parse_check_gen_tests(path, &cfg, &self.build, reader, &mut fs)?;
+        Ok((code, fs, funcs))
+    }
+}
+
+/// The most b.N can grow to, like in `go test`
+const MAX_N: u64 = 1_000_000_000;
+
+/// Predicts the b.N for a run to take `goal` from the last one, like `go test`: 20%
+/// more than the rate says, at most 100 times the last one, and at least one more
+fn next_n(last: u64, elapsed: Duration, goal: Duration) -> u64 {
+    let elapsed = elapsed.as_nanos().max(1);
+    let mut n = (goal.as_nanos() * last as u128 / elapsed) as u64;
+    n += n / 5;
+    n.min(last * 100).max(last + 1).min(MAX_N)
+}
+
+/// The status and the output of a test or a benchmark from the results of calling
+/// `testing.RunTest` or `testing.RunBenchmark`
+fn outcome(result: Result<Vec<vm::types::GosValue>, vm::CallError>) -> (TestStatus, String) {
+    match result {
+        Ok(r) if *r[0].as_bool() => (TestStatus::Failed, r[2].to_string()),
+        Ok(r) if *r[1].as_bool() => (TestStatus::Skipped, r[2].to_string()),
+        Ok(r) => (TestStatus::Passed, r[2].to_string()),
+        // not a panic of the function, which is recovered, but the VM stopping
+        Err(e) => (TestStatus::Failed, format!("    {}\n", e)),
+    }
+}
+
+fn run_error(e: vm::CallError) -> ErrorList {
+    let el = ErrorList::new();
+    el.add(None, format!("run aborted: {}", e), false, false);
+    el
+}
+
+fn position(fs: &FileSet, pos: Pos) -> String {
+    match fs.position(pos) {
+        Some(p) => format!("{}:{}", p.filename, p.line),
+        None => String::new(),
+    }
+}
@@ -30,33 +30,73 @@
 //! The project is entended to be enbedded, so it has a lot of feature flags to turn on/off different parts.
 //! - `read_fs`: Read source code from local file system
 //! - `read_zip`: Read source code from zip file
+//! - `embed_std`: Embed the std library in the binary, see `SourceReader::with_embedded_std`
 //! - `async`: Channel and goroutine support
-//! - `go_std`: Enable the Go standard library
+//! - `go_std`: Enable the Go standard library, all of the following but `std_net`
+//! - `std_base`: What the other `std_*` features need: packages like `io`, `sync` or `reflect`,
+//!   and `os` with only the standard streams
+//! - `std_fmt`: Packages `fmt`, `fmt2` and `testing`
+//! - `std_os`: File system access for package `os`, without it only the standard streams work
+//! - `std_sql`: Package `database/sql`
+//! - `std_net`: Package `net/http`
+//! - `std_runtime`: Packages `runtime/goscript` and `runtime/bridge`
 //! - `btree_map`: Make it use BTreeMap instead of HashMap
 //! - `codegen`: Enable codegen
 //! - `instruction_pos`: Add instruction position to bytecode for debugging
 //! - `serde_borsh`: Serde support for bytecode using Borsh
+//! - `http`: `go_std` and `std_net`, a `net/http` client, and a server for the host to pass requests to, see `http::server`
+//! - `sqlite`: A SQLite driver for the `database/sql` package, see `sql::SqliteDriver`
+//! - `serde`: Convert `GosValue`s to and from Rust types with `from_value` and `to_value`
+//! - `race`: Report data races between goroutines while running, like `go run -race`
+//! - `leak_track`: Report where the objects surviving many garbage collections were allocated
+//! - `threaded_dispatch`: Dispatch the arithmetic, the comparisons and the jumps through a table of handlers, see `benches/BENCHMARKS.md`
 //! - `wasm`: Enable wasm support
 //!
 
 mod engine;
 
-#[cfg(feature = "go_std")]
+#[cfg(feature = "codegen")]
+mod gotest;
+
+mod plugin;
+
+#[cfg(feature = "codegen")]
+mod repl;
+
+#[cfg(feature = "std_base")]
 mod std;
 
 mod vfs;
 
 mod source;
 
+#[cfg(feature = "codegen")]
+mod version;
+
 #[macro_use]
 pub mod ffi;
 
-#[cfg(feature = "go_std")]
+#[cfg(feature = "std_base")]
 #[macro_use]
 extern crate lazy_static;
 
+#[cfg(all(feature = "std_runtime", feature = "async"))]
+pub use crate::std::bridge;
+#[cfg(feature = "std_net")]
+pub use crate::std::http;
+#[cfg(feature = "std_base")]
+pub use crate::std::os::OutputBuffer;
+#[cfg(feature = "std_sql")]
+pub use crate::std::sql;
 pub use engine::*;
 pub use go_parser::ErrorList;
+#[cfg(feature = "serde")]
+pub use go_vm::{from_value, to_value};
+#[cfg(feature = "codegen")]
+pub use gotest::{BenchReport, BenchResult, TestReport, TestResult, TestStatus};
+pub use plugin::EnginePlugin;
+#[cfg(feature = "codegen")]
+pub use repl::Repl;
 pub use source::*;
 
 pub use crate::vfs::{compound::CompoundFs, vfs_map::VfsMap, VirtualFs};
@@ -0,0 +1,110 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use crate::engine::Engine;
+#[cfg(feature = "codegen")]
+use crate::engine::SourceRead;
+use crate::vfs::VirtualFs;
+use go_vm::FfiFactory;
+#[cfg(feature = "codegen")]
+use std::io;
+#[cfg(feature = "codegen")]
+use std::path::{Path, PathBuf};
+
+/// An extension that can be shipped as a separate crate and enabled with
+/// `Engine::add_plugin`, e.g. a database driver with its Go API package.
+pub trait EnginePlugin {
+    /// The name of the plugin, a plugin can only be added once.
+    fn name(&self) -> &str;
+
+    /// Registers the FFIs the plugin implements.
+    fn register_ffi(&self, _factory: &mut FfiFactory) {}
+
+    /// A file system overlaid on the library directory, so that the plugin can ship
+    /// Go source packages. Paths are relative to the library directory, e.g. a `VfsMap`
+    /// with "database/sql/sql.gos" provides package "database/sql".
+    fn lib_fs(&self) -> Option<Box<dyn VirtualFs>> {
+        None
+    }
+
+    /// Called after the FFIs and the library files are added, to adjust the engine,
+    /// e.g. to add build tags or constants.
+    fn configure(&self, _engine: &mut Engine) {}
+}
+
+/// A SourceRead that looks up library files in the plugins' file systems
+/// before falling back to the inner reader.
+#[cfg(feature = "codegen")]
+pub(crate) struct LayeredReader<'a, S: SourceRead> {
+    inner: &'a S,
+    layers: &'a [Box<dyn VirtualFs>],
+}
+
+#[cfg(feature = "codegen")]
+impl<'a, S: SourceRead> LayeredReader<'a, S> {
+    pub(crate) fn new(inner: &'a S, layers: &'a [Box<dyn VirtualFs>]) -> Self {
+        LayeredReader { inner, layers }
+    }
+
+    fn layer_of(&self, path: &Path) -> Option<(&dyn VirtualFs, PathBuf)> {
+        if self.layers.is_empty() {
+            return None;
+        }
+        let rel = path.strip_prefix(self.inner.base_dir()?).ok()?;
+        self.layers
+            .iter()
+            .find(|l| l.read_dir(rel).is_ok() || l.read_file(rel).is_ok())
+            .map(|l| (l.as_ref(), rel.to_path_buf()))
+    }
+}
+
+#[cfg(feature = "codegen")]
+impl<'a, S: SourceRead> SourceRead for LayeredReader<'a, S> {
+    fn working_dir(&self) -> &Path {
+        self.inner.working_dir()
+    }
+
+    fn base_dir(&self) -> Option<&Path> {
+        self.inner.base_dir()
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<String> {
+        match self.layer_of(path) {
+            Some((fs, rel)) => fs.read_file(&rel),
+            None => self.inner.read_file(path),
+        }
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        match self.layer_of(path) {
+            Some((fs, rel)) => {
+                let base = self.inner.base_dir().unwrap();
+                fs.read_dir(&rel)
+                    .map(|x| x.into_iter().map(|p| base.join(p)).collect())
+            }
+            None => self.inner.read_dir(path),
+        }
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        match self.layer_of(path) {
+            Some((fs, rel)) => fs.is_file(&rel),
+            None => self.inner.is_file(path),
+        }
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        match self.layer_of(path) {
+            Some((fs, rel)) => fs.is_dir(&rel),
+            None => self.inner.is_dir(path),
+        }
+    }
+
+    fn canonicalize_path(&self, path: &PathBuf) -> io::Result<PathBuf> {
+        match self.layer_of(path) {
+            Some(_) => Ok(path.clone()),
+            None => self.inner.canonicalize_path(path),
+        }
+    }
+}
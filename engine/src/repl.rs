@@ -0,0 +1,161 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! An interactive session, reading Go statements and declarations and running them
+//! as they come, like a REPL.
+//!
+//! The inputs are compiled into package main one after another, the variables, the
+//! functions, the types and the imports stay there for the inputs after. A variable
+//! declared by `:=` at the top level of an input is a package variable, an expression
+//! statement prints its values. An input starting with `import`, `func` or `type` is
+//! declarations, like the ones of a file.
+//!
+//! Every input is run by a VM of its own over the bytecode compiled so far, so the
+//! goroutines it leaves running are stopped after it. The `init` functions of package
+//! main are not run, and the methods of a type must be declared in the same input as
+//! the type.
+
+use crate::engine::Engine;
+use crate::plugin::LayeredReader;
+use go_codegen::{Session, SourceRead, TraceConfig};
+use go_parser::ErrorList;
+use std::io::{self, BufRead, Write};
+
+extern crate go_vm as vm;
+
+pub struct Repl<'a, S: SourceRead> {
+    engine: &'a Engine,
+    reader: LayeredReader<'a, S>,
+    session: Session,
+}
+
+impl<'a, S: SourceRead> Repl<'a, S> {
+    /// Starts a session with an empty package main, the packages are imported
+    /// from `reader`
+    pub fn new(engine: &'a Engine, reader: &'a S) -> Result<Repl<'a, S>, ErrorList> {
+        let reader = LayeredReader::new(reader, &engine.lib_layers);
+        crate::engine::check_std_version(&reader)?;
+        let cfg = TraceConfig {
+            trace_parser: false,
+            trace_checker: false,
+        };
+        let session = Session::new(&cfg, &engine.build, &reader)?;
+        Ok(Repl {
+            engine,
+            reader,
+            session,
+        })
+    }
+
+    /// Compiles and runs an input, returns the values of its expressions formatted
+    /// like `%v`, one line for each expression. The inputs before a compile error
+    /// or a panic are run, the package is left as they left it.
+    pub fn eval(&mut self, input: &str) -> Result<Vec<String>, ErrorList> {
+        let chunks = self.session.parse(input)?;
+        let mut lines = vec![];
+        for chunk in chunks.into_iter() {
+            let code = self.session.compile(chunk, &self.reader)?;
+            let bc = self.session.code();
+            for func in code.calls.iter() {
+                vm::call(
+                    bc,
+                    &self.engine.ffi,
+                    Some(self.session.fset()),
+                    &self.engine.run_options,
+                    "main",
+                    func,
+                    vec![],
+                )
+                .map_err(|e| {
+                    let el = ErrorList::new();
+                    el.add(None, e.to_string(), false, false);
+                    el
+                })?;
+            }
+            if !code.values.is_empty() {
+                let pkg = &bc.objects.packages[bc.main_pkg];
+                let values: Vec<String> = code
+                    .values
+                    .iter()
+                    .map(|name| {
+                        let i = *pkg.member_index(name).unwrap();
+                        pkg.member(i).to_string()
+                    })
+                    .collect();
+                lines.push(values.join(" "));
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Reads the inputs from `input` until it ends, an input goes on over the lines
+    /// while it has brackets or a raw string not closed, and writes the values and
+    /// the errors to `output`
+    pub fn run<R: BufRead, W: Write>(&mut self, mut input: R, mut output: W) -> io::Result<()> {
+        let mut buf = String::new();
+        loop {
+            write!(output, "{}", if buf.is_empty() { "> " } else { "... " })?;
+            output.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            buf.push_str(&line);
+            if !is_complete(&buf) {
+                continue;
+            }
+            if !buf.trim().is_empty() {
+                match self.eval(&buf) {
+                    Ok(lines) => lines.iter().try_for_each(|l| writeln!(output, "{}", l))?,
+                    Err(el) => {
+                        el.sort();
+                        write!(output, "{}", el)?;
+                    }
+                }
+            }
+            buf.clear();
+        }
+    }
+}
+
+/// Whether the brackets of the input are all closed and it doesn't end inside a raw
+/// string or a comment
+fn is_complete(input: &str) -> bool {
+    let mut depth = 0i32;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            '"' | '\'' => {
+                // not closed by the end of the line, it's an error the parser reports
+                while let Some(x) = chars.next() {
+                    match x {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '\n' => break,
+                        _ if x == c => break,
+                        _ => {}
+                    }
+                }
+            }
+            '`' if !chars.any(|x| x == '`') => return false,
+            '/' if chars.peek() == Some(&'/') => while chars.next_if(|x| *x != '\n').is_some() {},
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut star = false;
+                loop {
+                    match chars.next() {
+                        Some('/') if star => break,
+                        Some(x) => star = x == '*',
+                        None => return false,
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    depth <= 0
+}
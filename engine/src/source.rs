@@ -2,20 +2,71 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
-use crate::engine::{Config, Engine, SourceRead};
+use crate::engine::{Config, Engine, SourceError, SourceRead};
 use crate::vfs::VirtualFs;
 use crate::ErrorList;
+#[cfg(feature = "std_base")]
+use crate::OutputBuffer;
 use go_parser::Map;
 use std::io;
 use std::path::{Path, PathBuf};
 
 const VIRTUAL_LOCAL_PATH_PREFIX: &str = "vfs_local_";
 
+/// Runs the program with the config, a run stopped by the VM is reported as the only
+/// error of the list, see `Engine::run_source` for telling it from the compile errors.
 pub fn run(config: Config, source: &SourceReader, path: &Path) -> Result<(), ErrorList> {
-    let engine = Engine::new();
-    #[cfg(feature = "go_std")]
+    let mut engine = Engine::new();
+    if let Some((goos, goarch)) = &config.target {
+        engine.set_target(goos, goarch);
+    }
+    for tag in config.build_tags.iter() {
+        engine.add_build_tag(tag);
+    }
+    for (name, val) in config.constants.into_iter() {
+        engine.define_const(&name, val);
+    }
+    engine.set_stack_size(config.stack_size);
+    if let Some(ctl) = config.controller {
+        engine.set_controller(ctl);
+    }
+    if let Some(hook) = config.panic_hook {
+        engine.set_panic_hook(hook);
+    }
+    if let Some(hook) = config.assert_hook {
+        engine.set_assert_hook(hook);
+    }
+    engine.set_dump_locals_on_panic(config.dump_locals_on_panic);
+    engine.set_alloc_limit(config.max_alloc, config.oom_policy);
+    if let Some(policy) = config.sandbox {
+        engine.set_sandbox(policy);
+    }
+    engine.set_instruction_limit(config.max_instructions);
+    engine.set_goroutine_quota(config.goroutine_quota);
+    engine.set_heap_limit(config.max_heap);
+    #[cfg(feature = "std_base")]
     engine.set_std_io(config.std_in, config.std_out, config.std_err);
-    engine.run_source(config.trace_parser, config.trace_checker, source, path)
+    engine
+        .run_source(config.trace_parser, config.trace_checker, source, path)
+        .map(|_| ())
+        .map_err(SourceError::into_error_list)
+}
+
+/// Runs like `run`, with what's written to the std out and err captured instead of
+/// printed, returns it along with the result.
+#[cfg(feature = "std_base")]
+pub fn run_captured(
+    mut config: Config,
+    source: &SourceReader,
+    path: &Path,
+) -> (Result<(), ErrorList>, Vec<u8>) {
+    let out = OutputBuffer::default();
+    config.std_out = Some(Box::new(out.clone()));
+    config.std_err = Some(Box::new(out.clone()));
+    let result = run(config, source, path);
+    // so that the buffer isn't written by the runs after this on the thread
+    crate::std::os::set_std_io(None, None, None);
+    (result, out.take())
 }
 
 pub struct SourceReader {
@@ -139,6 +190,29 @@ impl SourceReader {
     }
 }
 
+#[cfg(feature = "embed_std")]
+static EMBEDDED_STD: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/std.zip"));
+
+impl SourceReader {
+    /// The Go-source std library zipped by the build script, the files are
+    /// under the `std/` directory of the archive.
+    #[cfg(feature = "embed_std")]
+    pub fn embedded_std() -> std::borrow::Cow<'static, [u8]> {
+        std::borrow::Cow::Borrowed(EMBEDDED_STD)
+    }
+
+    /// Create a SourceReader that reads library files from the std library embedded
+    /// in the binary, and user's source code from the local file system.
+    #[cfg(feature = "embed_std")]
+    pub fn with_embedded_std(working_dir: PathBuf) -> SourceReader {
+        SourceReader::zip_lib_and_local_fs(
+            SourceReader::embedded_std(),
+            PathBuf::from("std/"),
+            working_dir,
+        )
+    }
+}
+
 impl SourceRead for SourceReader {
     fn working_dir(&self) -> &Path {
         &self.working_dir
@@ -0,0 +1,642 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Bridges the channels of the host to Go channels, so that an event-driven host
+//! can push values into a script and receive the results, with the script using
+//! plain channel operations and select loops.
+//!
+//! The host side of an endpoint is an `async_channel` channel, which can be used from
+//! any thread and with any async runtime. Hosts using other channels, like tokio's
+//! mpsc, forward to and from it. In the script, `bridge.Inbound` and `bridge.Outbound`
+//! of the `runtime/bridge` package connect an endpoint to a Go channel.
+//!
+//! ```ignore
+//! let mut bridge = Bridge::new();
+//! let events = bridge.inbound::<String>("events", 16);
+//! let results = bridge.outbound::<isize>("results", 16);
+//! engine.add_plugin(bridge);
+//! ```
+//!
+//! The script can also export a value with `bridge.Export`, for the host to call its
+//! methods through an `Exported` endpoint, or through a Rust trait implemented with
+//! `bridge_trait!`, which makes the script a plugin of the host.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+use crate::plugin::EnginePlugin;
+use futures_lite::future::{self, Future};
+use go_parser::Map;
+use go_vm::types::*;
+use std::pin::Pin;
+use std::rc::Rc;
+
+const BRIDGE_FFI: &str = "runtime.bridge";
+
+/// A value that can be passed through a bridge, `TYPE` is the kind of the element
+/// type of the Go channel it is bridged to, and `GO_TYPE` names it in errors.
+pub trait BridgeValue: Send + Sized + 'static {
+    const TYPE: ValueType;
+
+    const GO_TYPE: &'static str;
+
+    /// Reports whether a Go channel with elements of type `meta` can be bridged, by
+    /// default any type of kind `TYPE`. Values whose memory layout depends on more
+    /// than the kind must check the whole type.
+    fn is_type(meta: &Meta, ctx: &FfiCtx) -> bool {
+        meta.value_type(&ctx.vm_objs.metas) == Self::TYPE
+    }
+
+    fn into_value(self) -> GosValue;
+
+    fn from_value(val: &GosValue) -> RuntimeResult<Self>;
+}
+
+macro_rules! bridge_primitive {
+    ($t:ty, $vt:expr, $go:literal) => {
+        impl BridgeValue for $t {
+            const TYPE: ValueType = $vt;
+
+            const GO_TYPE: &'static str = $go;
+
+            fn into_value(self) -> GosValue {
+                self.into()
+            }
+
+            fn from_value(val: &GosValue) -> RuntimeResult<Self> {
+                Ok(val.as_())
+            }
+        }
+    };
+}
+
+bridge_primitive!(isize, ValueType::Int, "int");
+bridge_primitive!(i64, ValueType::Int64, "int64");
+bridge_primitive!(i32, ValueType::Int32, "int32");
+bridge_primitive!(usize, ValueType::Uint, "uint");
+bridge_primitive!(u64, ValueType::Uint64, "uint64");
+bridge_primitive!(u32, ValueType::Uint32, "uint32");
+bridge_primitive!(f64, ValueType::Float64, "float64");
+bridge_primitive!(f32, ValueType::Float32, "float32");
+
+impl BridgeValue for bool {
+    const TYPE: ValueType = ValueType::Bool;
+
+    const GO_TYPE: &'static str = "bool";
+
+    fn into_value(self) -> GosValue {
+        self.into()
+    }
+
+    fn from_value(val: &GosValue) -> RuntimeResult<Self> {
+        Ok(*val.as_bool())
+    }
+}
+
+impl BridgeValue for String {
+    const TYPE: ValueType = ValueType::String;
+
+    const GO_TYPE: &'static str = "string";
+
+    fn into_value(self) -> GosValue {
+        FfiCtx::new_string(&self)
+    }
+
+    fn from_value(val: &GosValue) -> RuntimeResult<Self> {
+        Ok(val.as_string().as_str().to_string())
+    }
+}
+
+/// Bridged to a []byte channel
+impl BridgeValue for Vec<u8> {
+    const TYPE: ValueType = ValueType::Slice;
+
+    const GO_TYPE: &'static str = "[]byte";
+
+    /// The bytes are read from and written to the storage of the slice as is, so
+    /// slices of any other element type are rejected.
+    fn is_type(meta: &Meta, ctx: &FfiCtx) -> bool {
+        let objs = ctx.vm_objs;
+        meta.ptr_depth == 0
+            && !meta.is_type
+            && matches!(&objs.metas[meta.key], MetadataType::Slice(m) if *m == objs.prim_meta.muint8)
+    }
+
+    fn into_value(self) -> GosValue {
+        FfiCtx::new_bytes(&self)
+    }
+
+    fn from_value(val: &GosValue) -> RuntimeResult<Self> {
+        match val.is_nil() {
+            true => Ok(vec![]),
+            false => Ok(FfiCtx::slice_as_primitive_slice::<u8, u8>(val)?.to_vec()),
+        }
+    }
+}
+
+/// The Go type of a `BridgeValue`, the element type of the channels an endpoint
+/// can be connected to, or of a parameter or a result of an exported method
+#[derive(Clone, Copy)]
+pub struct BridgeType {
+    go_type: &'static str,
+    is_type: fn(&Meta, &FfiCtx) -> bool,
+}
+
+impl BridgeType {
+    pub fn of<T: BridgeValue>() -> BridgeType {
+        BridgeType {
+            go_type: T::GO_TYPE,
+            is_type: T::is_type,
+        }
+    }
+
+    fn all_match(types: &[BridgeType], metas: &[Meta], ctx: &FfiCtx) -> bool {
+        types.len() == metas.len() && types.iter().zip(metas).all(|(t, m)| (t.is_type)(m, ctx))
+    }
+
+    fn names(types: &[BridgeType]) -> String {
+        let names: Vec<&str> = types.iter().map(|t| t.go_type).collect();
+        names.join(", ")
+    }
+}
+
+/// The arguments of a call to a method the script exports, a tuple of `BridgeValue`s
+pub trait BridgeArgs: Send + 'static {
+    fn types() -> Vec<BridgeType>;
+
+    fn into_values(self) -> Vec<GosValue>;
+}
+
+macro_rules! bridge_args {
+    ($($t:ident),*) => {
+        impl<$($t: BridgeValue),*> BridgeArgs for ($($t,)*) {
+            fn types() -> Vec<BridgeType> {
+                vec![$(BridgeType::of::<$t>()),*]
+            }
+
+            #[allow(non_snake_case)]
+            fn into_values(self) -> Vec<GosValue> {
+                let ($($t,)*) = self;
+                vec![$($t.into_value()),*]
+            }
+        }
+    };
+}
+
+bridge_args!();
+bridge_args!(A);
+bridge_args!(A, B);
+bridge_args!(A, B, C);
+bridge_args!(A, B, C, D);
+
+/// The results of a method the script exports, `()` or a single `BridgeValue`
+pub trait BridgeResults: Send + Sized + 'static {
+    fn types() -> Vec<BridgeType>;
+
+    fn from_values(vals: &[GosValue]) -> RuntimeResult<Self>;
+}
+
+impl BridgeResults for () {
+    fn types() -> Vec<BridgeType> {
+        vec![]
+    }
+
+    fn from_values(_: &[GosValue]) -> RuntimeResult<Self> {
+        Ok(())
+    }
+}
+
+impl<T: BridgeValue> BridgeResults for T {
+    fn types() -> Vec<BridgeType> {
+        vec![BridgeType::of::<T>()]
+    }
+
+    fn from_values(vals: &[GosValue]) -> RuntimeResult<Self> {
+        T::from_value(&vals[0])
+    }
+}
+
+/// A method the host calls on a value the script exports
+pub struct MethodDecl {
+    name: &'static str,
+    params: Vec<BridgeType>,
+    results: Vec<BridgeType>,
+}
+
+impl MethodDecl {
+    /// Declares the Go method `name`, with parameters `A` and results `R`
+    pub fn new<A: BridgeArgs, R: BridgeResults>(name: &'static str) -> MethodDecl {
+        MethodDecl {
+            name,
+            params: A::types(),
+            results: R::types(),
+        }
+    }
+}
+
+/// A call of the host, the arguments are made and the results read on the side of
+/// the VM, as GosValues can't be sent between threads.
+struct MethodCall {
+    method: &'static str,
+    args: Box<dyn FnOnce() -> Vec<GosValue> + Send>,
+    reply: Box<dyn FnOnce(RuntimeResult<Vec<GosValue>>) + Send>,
+}
+
+/// The host side of the value the script exports through an endpoint, it can be
+/// cloned and used from any thread. The calls are served one at a time, in the
+/// order they are made, the script stops serving them when all the clones are
+/// dropped.
+#[derive(Clone)]
+pub struct Exported {
+    calls: async_channel::Sender<MethodCall>,
+}
+
+impl Exported {
+    /// Calls the method `method` of the exported value, blocking until it returns.
+    /// A panic in the method is returned as an error.
+    pub fn call<A: BridgeArgs, R: BridgeResults>(
+        &self,
+        method: &'static str,
+        args: A,
+    ) -> RuntimeResult<R> {
+        future::block_on(self.call_async(method, args))
+    }
+
+    pub async fn call_async<A: BridgeArgs, R: BridgeResults>(
+        &self,
+        method: &'static str,
+        args: A,
+    ) -> RuntimeResult<R> {
+        let stopped = || RuntimeError::from("bridge: the script has stopped".to_owned());
+        let (sender, receiver) = async_channel::bounded(1);
+        let call = MethodCall {
+            method,
+            args: Box::new(move || args.into_values()),
+            reply: Box::new(move |result| {
+                let _ = sender.try_send(result.and_then(|vals| R::from_values(&vals)));
+            }),
+        };
+        self.calls.send(call).await.map_err(|_| stopped())?;
+        receiver.recv().await.map_err(|_| stopped())?
+    }
+}
+
+/// Implements a trait of the host with a value the script exports. The generated
+/// struct wraps an `Exported` endpoint and forwards each method of the trait to the
+/// Go method named on its left, the methods of the trait return `RuntimeResult`s.
+///
+/// ```ignore
+/// trait Greeter {
+///     fn greet(&self, name: String) -> RuntimeResult<String>;
+/// }
+///
+/// bridge_trait! {
+///     struct GreeterProxy: Greeter {
+///         "Greet" => fn greet(&self, name: String) -> RuntimeResult<String>;
+///     }
+/// }
+///
+/// let greeter = GreeterProxy::export(&mut bridge, "greeter");
+/// engine.add_plugin(bridge);
+/// ```
+#[macro_export]
+macro_rules! bridge_trait {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $proxy:ident: $trait_:path {
+            $($go_name:literal => fn $method:ident(&self $(, $arg:ident: $arg_ty:ty)*)
+                -> RuntimeResult<$ret:ty>;)*
+        }
+    ) => {
+        $(#[$attr])*
+        #[derive(Clone)]
+        $vis struct $proxy($crate::bridge::Exported);
+
+        impl $proxy {
+            /// Adds the endpoint `name` to the bridge, for the script to export the
+            /// value implementing the trait through
+            $vis fn export(bridge: &mut $crate::bridge::Bridge, name: &str) -> $proxy {
+                let methods = vec![$(
+                    $crate::bridge::MethodDecl::new::<($($arg_ty,)*), $ret>($go_name)
+                ),*];
+                $proxy(bridge.export(name, methods))
+            }
+        }
+
+        impl $trait_ for $proxy {
+            $(
+                fn $method(&self $(, $arg: $arg_ty)*) -> $crate::ffi::RuntimeResult<$ret> {
+                    self.0.call($go_name, ($($arg,)*))
+                }
+            )*
+        }
+    };
+}
+
+/// The named endpoints to be made available to the script
+#[derive(Default)]
+pub struct Bridge {
+    endpoints: Rc<Map<String, Endpoint>>,
+}
+
+impl Bridge {
+    pub fn new() -> Bridge {
+        Bridge {
+            endpoints: Rc::new(Map::new()),
+        }
+    }
+
+    /// Adds an endpoint for the host to send values to the script, the script stops
+    /// receiving when all the returned senders are dropped.
+    pub fn inbound<T: BridgeValue>(&mut self, name: &str, cap: usize) -> async_channel::Sender<T> {
+        let (sender, receiver) = async_channel::bounded(cap.max(1));
+        self.add(
+            name,
+            Endpoint::Inbound(BridgeType::of::<T>(), Rc::new(receiver)),
+        );
+        sender
+    }
+
+    /// Adds an endpoint for the script to send values to the host, the returned
+    /// receiver is closed once the script closes the Go channel.
+    pub fn outbound<T: BridgeValue>(
+        &mut self,
+        name: &str,
+        cap: usize,
+    ) -> async_channel::Receiver<T> {
+        let (sender, receiver) = async_channel::bounded(cap.max(1));
+        self.add(
+            name,
+            Endpoint::Outbound(BridgeType::of::<T>(), Rc::new(sender)),
+        );
+        receiver
+    }
+
+    /// Adds an endpoint for the script to export a value through, whose methods the
+    /// host calls with the returned `Exported`. The script fails to export a value
+    /// not having all the `methods` with the declared types.
+    pub fn export(&mut self, name: &str, methods: Vec<MethodDecl>) -> Exported {
+        let (sender, receiver) = async_channel::unbounded();
+        self.add(
+            name,
+            Endpoint::Export(Rc::new(Exports {
+                methods,
+                calls: receiver,
+            })),
+        );
+        Exported { calls: sender }
+    }
+
+    fn add(&mut self, name: &str, endpoint: Endpoint) {
+        let endpoints = Rc::get_mut(&mut self.endpoints).unwrap();
+        assert!(
+            endpoints.insert(name.to_owned(), endpoint).is_none(),
+            "bridge endpoint '{}' already exists",
+            name
+        );
+    }
+}
+
+impl EnginePlugin for Bridge {
+    fn name(&self) -> &'static str {
+        BRIDGE_FFI
+    }
+
+    fn register_ffi(&self, factory: &mut FfiFactory) {
+        factory.register(
+            BRIDGE_FFI,
+            Rc::new(BridgeFfi {
+                endpoints: self.endpoints.clone(),
+            }),
+        );
+    }
+}
+
+type RecvFuture = Pin<Box<dyn Future<Output = Option<GosValue>>>>;
+type SendFuture = Pin<Box<dyn Future<Output = bool>>>;
+
+trait Inbound {
+    /// Returns None once the host has dropped all the senders
+    fn recv(&self) -> RecvFuture;
+}
+
+impl<T: BridgeValue> Inbound for async_channel::Receiver<T> {
+    fn recv(&self) -> RecvFuture {
+        let receiver = self.clone();
+        Box::pin(async move { receiver.recv().await.ok().map(T::into_value) })
+    }
+}
+
+trait Outbound {
+    /// The future returns false if the host has dropped the receiver
+    fn send(&self, val: &GosValue) -> RuntimeResult<SendFuture>;
+
+    fn close(&self);
+}
+
+impl<T: BridgeValue> Outbound for async_channel::Sender<T> {
+    fn send(&self, val: &GosValue) -> RuntimeResult<SendFuture> {
+        let val = T::from_value(val)?;
+        let sender = self.clone();
+        Ok(Box::pin(async move { sender.send(val).await.is_ok() }))
+    }
+
+    fn close(&self) {
+        async_channel::Sender::close(self);
+    }
+}
+
+struct Exports {
+    methods: Vec<MethodDecl>,
+    calls: async_channel::Receiver<MethodCall>,
+}
+
+enum Endpoint {
+    Inbound(BridgeType, Rc<dyn Inbound>),
+    Outbound(BridgeType, Rc<dyn Outbound>),
+    Export(Rc<Exports>),
+}
+
+struct BridgeFfi {
+    endpoints: Rc<Map<String, Endpoint>>,
+}
+
+impl BridgeFfi {
+    /// Returns the endpoint and the Go channel, or an error message for the script
+    fn endpoint(
+        &self,
+        ctx: &FfiCtx,
+        name: &GosValue,
+        ch: &GosValue,
+    ) -> Result<(&Endpoint, GosValue), String> {
+        let name = name.as_string().as_str().to_string();
+        let endpoint = self
+            .endpoints
+            .get(&name)
+            .ok_or_else(|| format!("bridge: no endpoint named \"{}\"", name))?;
+        let ch = match ch.is_nil() {
+            true => None,
+            false => ch.iface_underlying().map_err(|e| e.as_str().to_owned())?,
+        };
+        let elem_meta = match &ch {
+            Some(c) if c.typ() == ValueType::Channel => c.as_channel().map(|c| c.elem_meta),
+            _ => None,
+        }
+        .ok_or_else(|| "bridge: not a non-nil channel".to_owned())?;
+        let want = match endpoint {
+            Endpoint::Inbound(t, _) | Endpoint::Outbound(t, _) => *t,
+            Endpoint::Export(_) => {
+                return Err(format!("bridge: endpoint \"{}\" is for exports", name))
+            }
+        };
+        if !(want.is_type)(&elem_meta, ctx) {
+            return Err(format!(
+                "bridge: endpoint \"{}\" carries {}, not the elements of the channel",
+                name, want.go_type
+            ));
+        }
+        Ok((endpoint, ch.unwrap()))
+    }
+
+    /// Returns the endpoint `name` and the methods of `val` it declares, or an error
+    /// message for the script
+    fn exports(
+        &self,
+        ctx: &FfiCtx,
+        name: &GosValue,
+        val: &GosValue,
+    ) -> Result<(Rc<Exports>, Vec<GosValue>), String> {
+        let name = name.as_string().as_str().to_string();
+        let exports = match self.endpoints.get(&name) {
+            Some(Endpoint::Export(e)) => e.clone(),
+            Some(_) => return Err(format!("bridge: endpoint \"{}\" is not for exports", name)),
+            None => return Err(format!("bridge: no endpoint named \"{}\"", name)),
+        };
+        if val.is_nil() {
+            return Err("bridge: cannot export nil".to_owned());
+        }
+        let methods = exports
+            .methods
+            .iter()
+            .map(|m| {
+                let f = ctx
+                    .bind_method(val, m.name)
+                    .map_err(|e| format!("bridge: {}", e.as_str()))?;
+                match ctx.func_types(&f) {
+                    Some((params, results))
+                        if BridgeType::all_match(&m.params, &params, ctx)
+                            && BridgeType::all_match(&m.results, &results, ctx) =>
+                    {
+                        Ok(f)
+                    }
+                    _ => Err(format!(
+                        "bridge: method {} is not func({}) ({})",
+                        m.name,
+                        BridgeType::names(&m.params),
+                        BridgeType::names(&m.results)
+                    )),
+                }
+            })
+            .collect::<Result<Vec<GosValue>, String>>()?;
+        Ok((exports, methods))
+    }
+
+    /// Calls the methods for the host until it drops all its handles of the endpoint
+    async fn serve(
+        exports: Rc<Exports>,
+        methods: Vec<GosValue>,
+        caller: GoCaller,
+    ) -> RuntimeResult<Vec<GosValue>> {
+        while let Ok(call) = exports.calls.recv().await {
+            let i = exports.methods.iter().position(|m| m.name == call.method);
+            let result = match i {
+                Some(i) => caller.call(methods[i].clone(), (call.args)()).await,
+                None => Err(format!("bridge: method {} is not declared", call.method).into()),
+            };
+            (call.reply)(result);
+        }
+        Ok(vec![])
+    }
+
+    /// Sends the values from the host to the Go channel until the host is done
+    async fn pump_in(inbound: Rc<dyn Inbound>, ch: GosValue) -> RuntimeResult<Vec<GosValue>> {
+        let chan = &ch.as_channel().unwrap().chan;
+        while let Some(val) = inbound.recv().await {
+            chan.send(&val).await?;
+        }
+        chan.close();
+        Ok(vec![])
+    }
+
+    /// Sends the values from the Go channel to the host until the channel is closed
+    async fn pump_out(outbound: Rc<dyn Outbound>, ch: GosValue) -> RuntimeResult<Vec<GosValue>> {
+        let chan = &ch.as_channel().unwrap().chan;
+        while let Some(val) = chan.recv().await {
+            if !outbound.send(&val)?.await {
+                break;
+            }
+        }
+        outbound.close();
+        Ok(vec![])
+    }
+}
+
+impl Ffi for BridgeFfi {
+    fn call(&self, ctx: &mut FfiCtx, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        match ctx.func_name {
+            "check" => {
+                let inbound = *args[1].as_bool();
+                let msg = match self.endpoint(ctx, &args[0], &args[2]) {
+                    Ok((Endpoint::Inbound(..), _)) if !inbound => {
+                        "bridge: the endpoint is inbound".to_owned()
+                    }
+                    Ok((Endpoint::Outbound(..), _)) if inbound => {
+                        "bridge: the endpoint is outbound".to_owned()
+                    }
+                    Ok(_) => String::new(),
+                    Err(e) => e,
+                };
+                Ok(vec![FfiCtx::new_string(&msg)])
+            }
+            "check_export" => {
+                let msg = self.exports(ctx, &args[0], &args[1]).err();
+                Ok(vec![FfiCtx::new_string(&msg.unwrap_or_default())])
+            }
+            _ => Err(format!("ffi function '{}' not found!", ctx.func_name).into()),
+        }
+    }
+
+    fn async_call(
+        &self,
+        ctx: &mut FfiCtx,
+        args: Vec<GosValue>,
+    ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>> + '_>> {
+        let name = ctx.func_name;
+        if name == "async_serve" {
+            let exports = self.exports(ctx, &args[0], &args[1]);
+            return match (exports, ctx.go_caller()) {
+                (Ok((exports, methods)), Some(caller)) => {
+                    Box::pin(BridgeFfi::serve(exports, methods, caller))
+                }
+                (Err(e), _) => Box::pin(async move { Err(e.into()) }),
+                (_, None) => Box::pin(async { Err("bridge: cannot call Go".to_owned().into()) }),
+            };
+        }
+        match self.endpoint(ctx, &args[0], &args[1]) {
+            Ok((Endpoint::Inbound(_, inbound), ch)) if name == "async_pump_in" => {
+                Box::pin(BridgeFfi::pump_in(inbound.clone(), ch))
+            }
+            Ok((Endpoint::Outbound(_, outbound), ch)) if name == "async_pump_out" => {
+                Box::pin(BridgeFfi::pump_out(outbound.clone(), ch))
+            }
+            Ok(_) => {
+                let err = Err(format!("ffi function '{}' not found!", name).into());
+                Box::pin(async move { err })
+            }
+            Err(e) => {
+                let err = Err(e.into());
+                Box::pin(async move { err })
+            }
+        }
+    }
+}
@@ -12,28 +12,36 @@ pub struct Fmt2Ffi;
 #[ffi_impl]
 impl Fmt2Ffi {
     fn ffi_println(args: GosValue) -> RuntimeResult<()> {
-        let vec = FfiCtx::slice_as_rust_slice::<GosElem>(&args)?;
-        let strs: Vec<String> = vec
+        let s = Self::format(&args)?;
+        #[cfg(feature = "wasm")]
+        crate::std::wasm::console_log(&s);
+        #[cfg(not(feature = "wasm"))]
+        println!("{}", s);
+        Ok(())
+    }
+
+    fn ffi_sprint(args: GosValue) -> RuntimeResult<String> {
+        Self::format(&args)
+    }
+
+    /// The values like Go prints them with `%v`, separated by ", "
+    fn format(args: &GosValue) -> RuntimeResult<String> {
+        let vec = FfiCtx::slice_as_rust_slice::<GosElem>(args)?;
+        let strs = vec
             .iter()
             .map(|x| {
                 let val = x.borrow();
                 let s = if val.is_nil() {
                     "<nil>".to_owned()
                 } else {
-                    let underlying = val.iface_underlying()?;
-                    match underlying {
+                    match val.iface_underlying()? {
                         Some(v) => v.to_string(),
                         None => "<ffi>".to_owned(),
                     }
                 };
                 Ok(s)
             })
-            .map(|x: RuntimeResult<String>| x.unwrap())
-            .collect();
-        #[cfg(feature = "wasm")]
-        crate::std::wasm::console_log(&strs.join(", "));
-        #[cfg(not(feature = "wasm"))]
-        println!("{}", strs.join(", "));
-        Ok(())
+            .collect::<RuntimeResult<Vec<String>>>()?;
+        Ok(strs.join(", "))
     }
 }
@@ -0,0 +1,82 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+use go_vm::types::*;
+
+/// The feature flags the engine is built with, listed by the build script
+const FEATURES: &[&str] = include!(concat!(env!("OUT_DIR"), "/features.rs"));
+
+#[derive(Ffi)]
+pub struct GoscriptFfi;
+
+#[ffi_impl(rename = "runtime.goscript")]
+impl GoscriptFfi {
+    fn ffi_version() -> String {
+        env!("CARGO_PKG_VERSION").to_owned()
+    }
+
+    fn ffi_features(ctx: &FfiCtx) -> GosValue {
+        let features = FEATURES
+            .iter()
+            .map(|name| FfiCtx::new_string(name))
+            .collect();
+        ctx.new_slice(features, ValueType::String)
+    }
+
+    /// The limits of the sandbox, the sizes are -1 if not limited
+    fn ffi_limits(ctx: &FfiCtx) -> (bool, GosValue, GosValue, GosValue, GosValue, isize, isize) {
+        let strings = |list: Vec<String>| {
+            let list = list.iter().map(|s| FfiCtx::new_string(s)).collect();
+            ctx.new_slice(list, ValueType::String)
+        };
+        let policy = match ctx.sandbox {
+            Some(p) => p,
+            None => {
+                let none = || strings(vec![]);
+                return (false, none(), none(), none(), none(), -1, -1);
+            }
+        };
+        let paths = |write: bool| {
+            let paths = policy.allowed_paths().iter().filter(|(_, w)| *w == write);
+            let paths = paths.map(|(p, _)| p.to_string_lossy().into_owned());
+            strings(paths.collect())
+        };
+        (
+            true,
+            paths(false),
+            paths(true),
+            strings(policy.allowed_env().to_vec()),
+            strings(policy.allowed_hosts().to_vec()),
+            policy.goroutine_limit().map_or(-1, |n| n as isize),
+            policy.file_size_limit().map_or(-1, |n| n as isize),
+        )
+    }
+
+    fn ffi_modules(ctx: &FfiCtx) -> GosValue {
+        let names = ctx
+            .ffi_names()
+            .into_iter()
+            .map(FfiCtx::new_string)
+            .collect();
+        ctx.new_slice(names, ValueType::String)
+    }
+
+    fn ffi_heap_stats(ctx: &FfiCtx) -> (isize, isize, isize, isize, isize, isize) {
+        let stats = ctx.gcc.heap_stats();
+        (
+            stats.bytes as isize,
+            stats.peak_bytes as isize,
+            stats.allocated_bytes as isize,
+            stats.objects as isize,
+            stats.collections as isize,
+            stats.minor_collections as isize,
+        )
+    }
+
+    fn ffi_goroutine_id(ctx: &FfiCtx) -> isize {
+        ctx.goroutine as isize
+    }
+}
@@ -0,0 +1,386 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The host side of the `net/http` package. Besides the client, which needs nothing
+//! from the host, a script can serve the requests of a web framework running in the
+//! host: add the `ServerPlugin` to the engine, and pass the requests to the script's
+//! `http.ListenAndServe` with the paired `ServerHandle`.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+use crate::plugin::EnginePlugin;
+use futures_lite::future::Future;
+use go_vm::types::{GosValue, RuntimeResult, ValueType};
+use go_vm::{Capability, SandboxPolicy};
+use std::io::Read;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The FFI name of the server, which is also the name of the plugin
+const SERVER_FFI: &str = "http.server";
+
+/// Like Go's client, the client stops after 10 redirects
+const MAX_REDIRECTS: usize = 10;
+
+/// The headers not sent along when redirected to another host, like Go does
+const SENSITIVE_HEADERS: [&str; 4] = ["authorization", "www-authenticate", "cookie", "cookie2"];
+
+/// The host of a URL, with the port if it has one. The URL is parsed the way the
+/// client does, so that the host checked is the one connected to.
+fn url_host(url: &str) -> Option<String> {
+    let url = url::Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    Some(match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_owned(),
+    })
+}
+
+/// An HTTP request passed by the host to the script
+#[derive(Debug, Clone, Default)]
+pub struct Request {
+    pub method: String,
+    /// The request target, like "/path?query"
+    pub url: String,
+    pub header: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// The response of the script's handler
+#[derive(Debug, Clone, Default)]
+pub struct Response {
+    pub status: u16,
+    pub header: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Returns the first value of the header named `key`, case insensitively
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.header
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+struct Exchange {
+    request: Request,
+    reply: async_channel::Sender<Response>,
+}
+
+/// Creates a server plugin for the engine and the handle to send requests to it
+pub fn server() -> (ServerPlugin, ServerHandle) {
+    let (sender, receiver) = async_channel::unbounded();
+    (ServerPlugin { receiver }, ServerHandle { sender })
+}
+
+/// Makes the requests sent with the `ServerHandle` available to `http.ListenAndServe`
+pub struct ServerPlugin {
+    receiver: async_channel::Receiver<Exchange>,
+}
+
+impl EnginePlugin for ServerPlugin {
+    fn name(&self) -> &'static str {
+        SERVER_FFI
+    }
+
+    fn register_ffi(&self, factory: &mut FfiFactory) {
+        factory.register(
+            SERVER_FFI,
+            Rc::new(ServerFfi {
+                receiver: self.receiver.clone(),
+            }),
+        );
+    }
+}
+
+/// Sends requests to the script, it can be cloned and used from any thread, while
+/// the engine runs on its own. `http.ListenAndServe` returns when all the handles
+/// are dropped.
+#[derive(Clone)]
+pub struct ServerHandle {
+    sender: async_channel::Sender<Exchange>,
+}
+
+impl ServerHandle {
+    /// Sends a request to the script and waits for the response of its handler
+    pub async fn call(&self, request: Request) -> Result<Response, String> {
+        let (reply, response) = async_channel::bounded(1);
+        self.sender
+            .send(Exchange { request, reply })
+            .await
+            .map_err(|_| "http: the server is closed".to_owned())?;
+        response
+            .recv()
+            .await
+            .map_err(|_| "http: the handler did not respond".to_owned())
+    }
+
+    /// The blocking version of `call`
+    pub fn call_blocking(&self, request: Request) -> Result<Response, String> {
+        futures_lite::future::block_on(self.call(request))
+    }
+}
+
+#[derive(UnsafePtr)]
+struct ReplyHandle(async_channel::Sender<Response>);
+
+/// The headers are passed as "Key: value" lines both ways
+fn encode_header(header: &[(String, String)]) -> String {
+    header
+        .iter()
+        .map(|(k, v)| format!("{}: {}\n", k, v))
+        .collect()
+}
+
+fn decode_header(header: &str) -> Vec<(String, String)> {
+    header
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_owned(), v.trim().to_owned()))
+        .collect()
+}
+
+struct ServerFfi {
+    receiver: async_channel::Receiver<Exchange>,
+}
+
+impl Ffi for ServerFfi {
+    fn call(&self, ctx: &mut FfiCtx, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        match ctx.func_name {
+            "respond" => {
+                let reply = args[0]
+                    .as_non_nil_unsafe_ptr()?
+                    .downcast_ref::<ReplyHandle>()?;
+                let body = match args[3].is_nil() {
+                    true => vec![],
+                    false => FfiCtx::slice_as_primitive_slice::<u8, u8>(&args[3])?.to_vec(),
+                };
+                let response = Response {
+                    status: *args[1].as_int() as u16,
+                    header: decode_header(&args[2].as_string().as_str()),
+                    body,
+                };
+                // the host may have stopped waiting, which is not the script's concern
+                let _ = reply.0.try_send(response);
+                Ok(vec![])
+            }
+            _ => Err(format!("ffi function '{}' not found!", ctx.func_name).into()),
+        }
+    }
+
+    fn async_call(
+        &self,
+        ctx: &mut FfiCtx,
+        _args: Vec<GosValue>,
+    ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>> + '_>> {
+        match ctx.func_name {
+            "async_next" => Box::pin(async move {
+                Ok(match self.receiver.recv().await {
+                    Ok(Exchange { request, reply }) => vec![
+                        FfiCtx::new_unsafe_ptr(Rc::new(ReplyHandle(reply))),
+                        FfiCtx::new_string(&request.method),
+                        FfiCtx::new_string(&request.url),
+                        FfiCtx::new_string(&encode_header(&request.header)),
+                        FfiCtx::new_bytes(&request.body),
+                        true.into(),
+                    ],
+                    Err(_) => vec![
+                        FfiCtx::new_nil(ValueType::UnsafePtr),
+                        FfiCtx::new_string(""),
+                        FfiCtx::new_string(""),
+                        FfiCtx::new_string(""),
+                        FfiCtx::new_nil_slice(ValueType::Uint8),
+                        false.into(),
+                    ],
+                })
+            }),
+            _ => {
+                let err = Err(format!("ffi function '{}' not found!", ctx.func_name).into());
+                Box::pin(async move { err })
+            }
+        }
+    }
+}
+
+struct ClientResponse {
+    status: u16,
+    status_text: String,
+    header: String,
+    body: Vec<u8>,
+    location: Option<String>,
+}
+
+#[derive(Ffi)]
+pub struct HttpFfi;
+
+#[ffi_impl(rename = "http.client")]
+impl HttpFfi {
+    /// The request is sent on its own thread, so that only the calling goroutine
+    /// is parked while waiting for the response.
+    fn ffi_async_do(
+        ctx: &mut FfiCtx,
+        method: String,
+        url: String,
+        header: String,
+        body: GosValue,
+        timeout_ms: i64,
+    ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>>>> {
+        let host = url_host(&url).unwrap_or_default();
+        if !ctx.allows(&Capability::Connect(&host)) {
+            let e = format!("dial {}: connection not allowed", host);
+            return Box::pin(async move { Ok(HttpFfi::error_results(&e)) });
+        }
+        let body = match body.is_nil() {
+            true => Ok(vec![]),
+            false => FfiCtx::slice_as_primitive_slice::<u8, u8>(&body).map(|b| b.to_vec()),
+        };
+        let policy = ctx.sandbox.cloned();
+        Box::pin(async move {
+            let body = body?;
+            let (sender, receiver) = async_channel::bounded(1);
+            thread::spawn(move || {
+                let result = HttpFfi::fetch(policy, method, url, header, body, timeout_ms);
+                let _ = sender.send_blocking(result);
+            });
+            let result = receiver.recv().await.map_err(|e| e.to_string())?;
+            Ok(match result {
+                Ok(resp) => vec![
+                    (resp.status as isize).into(),
+                    FfiCtx::new_string(&resp.status_text),
+                    FfiCtx::new_string(&resp.header),
+                    FfiCtx::new_bytes(&resp.body),
+                    FfiCtx::new_string(""),
+                ],
+                Err(e) => HttpFfi::error_results(&e),
+            })
+        })
+    }
+
+    fn error_results(e: &str) -> Vec<GosValue> {
+        vec![
+            0isize.into(),
+            FfiCtx::new_string(""),
+            FfiCtx::new_string(""),
+            FfiCtx::new_nil_slice(ValueType::Uint8),
+            FfiCtx::new_string(e),
+        ]
+    }
+
+    /// Sends the request and follows the redirects hop by hop, up to MAX_REDIRECTS
+    /// of them, so that the host of each hop is checked against the sandbox policy.
+    /// The response redirecting to a host the policy denies is returned as is. The
+    /// timeout is for the whole chain, like Go's Client.Timeout.
+    fn fetch(
+        policy: Option<SandboxPolicy>,
+        mut method: String,
+        mut url: String,
+        mut header: String,
+        mut body: Vec<u8>,
+        timeout_ms: i64,
+    ) -> Result<ClientResponse, String> {
+        let deadline =
+            (timeout_ms > 0).then(|| Instant::now() + Duration::from_millis(timeout_ms as u64));
+        let mut hops = 0;
+        loop {
+            let timeout = match deadline {
+                Some(d) => match d.checked_duration_since(Instant::now()) {
+                    Some(left) if !left.is_zero() => Some(left),
+                    _ => return Err("Client.Timeout exceeded while following redirects".into()),
+                },
+                None => None,
+            };
+            let resp = HttpFfi::send(&method, &url, &header, &body, timeout)?;
+            let next = match (resp.status, &resp.location) {
+                (301 | 302 | 303 | 307 | 308, Some(loc)) => url::Url::parse(&url)
+                    .and_then(|u| u.join(loc))
+                    .map_err(|e| format!("failed to parse Location header {:?}: {}", loc, e))?,
+                _ => return Ok(resp),
+            };
+            let host = url_host(next.as_str()).unwrap_or_default();
+            if !policy
+                .as_ref()
+                .is_none_or(|p| p.check(&Capability::Connect(&host)))
+            {
+                return Ok(resp);
+            }
+            if hops == MAX_REDIRECTS {
+                return Err(format!("stopped after {} redirects", MAX_REDIRECTS));
+            }
+            hops += 1;
+            // 301, 302 and 303 turn the request into a GET without a body, except a HEAD
+            if matches!(resp.status, 301..=303) {
+                if method != "HEAD" {
+                    method = "GET".to_owned();
+                }
+                body = vec![];
+            }
+            if url_host(&url).is_none_or(|h| h != host) {
+                header = header
+                    .lines()
+                    .filter(|line| {
+                        let name = line.split_once(':').map_or(*line, |(k, _)| k);
+                        !SENSITIVE_HEADERS.contains(&name.trim().to_ascii_lowercase().as_str())
+                    })
+                    .map(|line| format!("{}\n", line))
+                    .collect();
+            }
+            url = next.into();
+        }
+    }
+
+    /// Sends a single request, the headers are passed as "Key: value" lines both ways
+    fn send(
+        method: &str,
+        url: &str,
+        header: &str,
+        body: &[u8],
+        timeout: Option<Duration>,
+    ) -> Result<ClientResponse, String> {
+        // the redirects are followed by fetch
+        let mut builder = ureq::AgentBuilder::new().redirects(0);
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+        let mut req = builder.build().request(method, url);
+        for line in header.lines() {
+            if let Some((k, v)) = line.split_once(':') {
+                req = req.set(k.trim(), v.trim());
+            }
+        }
+        let result = match body.is_empty() {
+            true => req.call(),
+            false => req.send_bytes(body),
+        };
+        let resp = match result {
+            Ok(resp) => resp,
+            // Like Go, a non-2xx status is not an error
+            Err(ureq::Error::Status(_, resp)) => resp,
+            Err(e) => return Err(e.to_string()),
+        };
+        let mut header = String::new();
+        for name in resp.headers_names() {
+            for v in resp.all(&name) {
+                header.push_str(&format!("{}: {}\n", name, v));
+            }
+        }
+        let status = resp.status();
+        let status_text = resp.status_text().to_owned();
+        let location = resp.header("Location").map(|l| l.to_owned());
+        let mut body = vec![];
+        resp.into_reader()
+            .read_to_end(&mut body)
+            .map_err(|e| e.to_string())?;
+        Ok(ClientResponse {
+            status,
+            status_text,
+            header,
+            body,
+            location,
+        })
+    }
+}
@@ -5,23 +5,83 @@
 /// Go 1.12
 ///
 mod bits;
+#[cfg(all(feature = "std_runtime", feature = "async"))]
+pub mod bridge;
+#[cfg(feature = "std_fmt")]
 mod fmt2;
+#[cfg(feature = "std_runtime")]
+mod goscript;
+#[cfg(feature = "std_net")]
+pub mod http;
 mod io;
 pub(crate) mod os;
 mod reflect;
+#[cfg(feature = "std_sql")]
+pub mod sql;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod strconv;
+mod strings;
 #[cfg(feature = "async")]
 mod sync;
+mod time;
 #[cfg(feature = "wasm")]
 mod wasm;
 
+/// The std packages that can be compiled out, with the feature compiling them in.
+/// The packages not listed, like `io`, `sync` or `reflect`, are needed by most of
+/// the others, they are always there with any of the features.
+/// Without `std_os`, package `os` still has the standard streams, but no files.
+const PACKAGES: &[(&str, &[&str], bool)] = &[
+    (
+        "std_fmt",
+        &["fmt", "fmt2", "testing"],
+        cfg!(feature = "std_fmt"),
+    ),
+    ("std_sql", &["database/sql"], cfg!(feature = "std_sql")),
+    ("std_net", &["net/http"], cfg!(feature = "std_net")),
+    (
+        "std_runtime",
+        &["runtime/goscript", "runtime/bridge"],
+        cfg!(feature = "std_runtime"),
+    ),
+];
+
+/// Registers the FFIs of the std packages compiled in
 pub(crate) fn register(factory: &mut go_vm::FfiFactory) {
-    fmt2::Fmt2Ffi::register(factory);
     bits::BitsFfi::register(factory);
     #[cfg(feature = "async")]
     sync::MutexFfi::register(factory);
     #[cfg(feature = "async")]
     sync::RWMutexFfi::register(factory);
     reflect::ReflectFfi::register(factory);
+    time::TimeFfi::register(factory);
+    #[cfg(feature = "async")]
+    time::TimerFfi::register(factory);
     io::IoFfi::register(factory);
+    strconv::StrconvFfi::register(factory);
+    strings::StringsFfi::register(factory);
     os::FileFfi::register(factory);
+    os::EnvFfi::register(factory);
+    #[cfg(feature = "std_fmt")]
+    fmt2::Fmt2Ffi::register(factory);
+    #[cfg(feature = "std_runtime")]
+    goscript::GoscriptFfi::register(factory);
+    #[cfg(feature = "std_net")]
+    http::HttpFfi::register(factory);
+}
+
+/// Returns the import paths of the std packages compiled out, with why, importing
+/// them is a compile error
+pub(crate) fn excluded_packages() -> Vec<(String, String)> {
+    PACKAGES
+        .iter()
+        .filter(|(_, _, on)| !on)
+        .flat_map(|(feature, paths, _)| {
+            paths.iter().map(move |p| {
+                let reason = format!("the engine is built without feature {}", feature);
+                (p.to_string(), reason)
+            })
+        })
+        .collect()
 }
@@ -5,43 +5,60 @@
 extern crate self as go_engine;
 use crate::ffi::*;
 use go_vm::types::*;
+use go_vm::Capability;
 use std::cell::RefCell;
 use std::fs;
 use std::io;
 use std::io::prelude::*;
 use std::rc::Rc;
-use std::sync::Arc;
-use std::sync::Mutex;
-
-// Flags to OpenFile
-const O_RDONLY: usize = 0x00000;
-const O_WRONLY: usize = 0x00001;
-const O_RDWR: usize = 0x00002;
-const O_APPEND: usize = 0x00400;
-const O_CREATE: usize = 0x00040;
-const O_EXCL: usize = 0x00080;
-const O_TRUNC: usize = 0x00200;
-
-lazy_static! {
-    static ref STD_IO_API: Arc<Mutex<StdIoApi>> = Arc::new(Mutex::new(StdIoApi::default()));
+use std::sync::{Arc, Mutex};
+
+thread_local! {
+    // a run is on one thread, the runs on other threads have their own
+    static STD_IO_API: RefCell<StdIoApi> = RefCell::new(StdIoApi::default());
 }
 
 pub fn set_std_io(
-    std_in: Option<Box<dyn std::io::Read + Sync + Send>>,
-    std_out: Option<Box<dyn std::io::Write + Sync + Send>>,
-    std_err: Option<Box<dyn std::io::Write + Sync + Send>>,
+    std_in: Option<Box<dyn io::Read + Send>>,
+    std_out: Option<Box<dyn io::Write + Send>>,
+    std_err: Option<Box<dyn io::Write + Send>>,
 ) {
-    let mut api = STD_IO_API.lock().unwrap();
-    api.std_in = std_in;
-    api.std_out = std_out;
-    api.std_err = std_err;
+    STD_IO_API.with(|api| {
+        let mut api = api.borrow_mut();
+        api.std_in = std_in;
+        api.std_out = std_out;
+        api.std_err = std_err;
+    });
 }
 
 #[derive(Default)]
 pub struct StdIoApi {
-    pub(crate) std_in: Option<Box<dyn io::Read + Sync + Send>>,
-    pub(crate) std_out: Option<Box<dyn io::Write + Sync + Send>>,
-    pub(crate) std_err: Option<Box<dyn io::Write + Sync + Send>>,
+    pub(crate) std_in: Option<Box<dyn io::Read + Send>>,
+    pub(crate) std_out: Option<Box<dyn io::Write + Send>>,
+    pub(crate) std_err: Option<Box<dyn io::Write + Send>>,
+}
+
+/// The bytes written to the std out and err of the runs it's set as, shared by its
+/// clones.
+#[derive(Clone, Default)]
+pub struct OutputBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl OutputBuffer {
+    /// Takes the bytes written so far
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+impl io::Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
 }
 
 #[derive(Ffi)]
@@ -58,24 +75,8 @@ impl FileFfi {
         }
     }
 
-    fn ffi_open(path: GosValue, flags: isize) -> (GosValue, isize, GosValue) {
-        let path = path.as_string().as_str();
-        let flags = flags as usize;
-        let mut options = fs::OpenOptions::new();
-        match flags & O_RDWR {
-            O_RDONLY => options.read(true),
-            O_WRONLY => options.write(true),
-            O_RDWR => options.read(true).write(true),
-            _ => unreachable!(),
-        };
-        options.append((flags & O_APPEND) != 0);
-        options.append((flags & O_TRUNC) != 0);
-        match (((flags & O_CREATE) != 0), ((flags & O_EXCL) != 0)) {
-            (true, false) => options.create(true),
-            (true, true) => options.create_new(true),
-            _ => &options,
-        };
-        let r = options.open(&*path);
+    fn ffi_open(ctx: &FfiCtx, path: GosValue, flags: isize) -> (GosValue, isize, GosValue) {
+        let r = open_file(ctx, &path.as_string().as_str(), flags as usize);
         FileFfi::result_to_go(r, |opt| match opt {
             Some(f) => VirtualFile::with_sys_file(f).into_val(),
             None => FfiCtx::new_nil(ValueType::UnsafePtr),
@@ -90,11 +91,25 @@ impl FileFfi {
         Ok(FileFfi::result_to_go(r, |opt| opt.unwrap_or(0) as isize))
     }
 
-    fn ffi_write(fp: GosValue, buffer: GosValue) -> RuntimeResult<(isize, isize, GosValue)> {
+    fn ffi_write(
+        ctx: &FfiCtx,
+        fp: GosValue,
+        buffer: GosValue,
+    ) -> RuntimeResult<(isize, isize, GosValue)> {
         let file = fp.as_non_nil_unsafe_ptr()?.downcast_ref::<VirtualFile>()?;
         let slice = &buffer.as_non_nil_slice::<Elem8>()?.0;
         let buf = slice.as_raw_slice();
-        let r = file.write(&buf);
+        let too_large = ctx.sandbox.is_some()
+            && file
+                .size_after_write(buf.len())
+                .is_some_and(|size| !ctx.allows(&Capability::WriteFile { size }));
+        let r = match too_large {
+            true => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "write: file too large",
+            )),
+            false => file.write(&buf),
+        };
         Ok(FileFfi::result_to_go(r, |opt| opt.unwrap_or(0) as isize))
     }
 
@@ -125,6 +140,79 @@ impl FileFfi {
     }
 }
 
+#[cfg(feature = "std_os")]
+fn open_file(ctx: &FfiCtx, path: &str, flags: usize) -> io::Result<fs::File> {
+    // Flags to OpenFile
+    const O_RDONLY: usize = 0x00000;
+    const O_WRONLY: usize = 0x00001;
+    const O_RDWR: usize = 0x00002;
+    const O_APPEND: usize = 0x00400;
+    const O_CREATE: usize = 0x00040;
+    const O_EXCL: usize = 0x00080;
+    const O_TRUNC: usize = 0x00200;
+
+    let write = flags & (O_WRONLY | O_RDWR | O_APPEND | O_CREATE | O_TRUNC) != 0;
+    let path = std::path::Path::new(path);
+    if !ctx.allows(&Capability::OpenFile { path, write }) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("open {}: permission denied", path.display()),
+        ));
+    }
+    let mut options = fs::OpenOptions::new();
+    match flags & O_RDWR {
+        O_RDONLY => options.read(true),
+        O_WRONLY => options.write(true),
+        O_RDWR => options.read(true).write(true),
+        _ => unreachable!(),
+    };
+    options.append((flags & O_APPEND) != 0);
+    options.truncate((flags & O_TRUNC) != 0);
+    match (((flags & O_CREATE) != 0), ((flags & O_EXCL) != 0)) {
+        (true, false) => options.create(true),
+        (true, true) => options.create_new(true),
+        _ => &options,
+    };
+    options.open(path)
+}
+
+/// The file system is compiled out, only the standard streams can be used
+#[cfg(not(feature = "std_os"))]
+fn open_file(_ctx: &FfiCtx, path: &str, _flags: usize) -> io::Result<fs::File> {
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("open {}: file system access is not built in", path),
+    ))
+}
+
+#[derive(Ffi)]
+pub struct EnvFfi;
+
+#[ffi_impl(rename = "os.env")]
+impl EnvFfi {
+    /// The environment variables not allowed by the sandbox policy look unset
+    fn ffi_lookup(ctx: &FfiCtx, key: GosValue) -> (GosValue, bool) {
+        let key = key.as_string().as_str();
+        let val = match ctx.allows(&Capability::Env(&key)) {
+            true => lookup_env(&key),
+            false => None,
+        };
+        let ok = val.is_some();
+        (FfiCtx::new_string(&val.unwrap_or_default()), ok)
+    }
+}
+
+#[cfg(feature = "std_os")]
+fn lookup_env(key: &str) -> Option<String> {
+    std::env::var(key).ok()
+}
+
+/// The environment of the host is compiled out along with the file system
+#[cfg(not(feature = "std_os"))]
+fn lookup_env(_key: &str) -> Option<String> {
+    None
+}
+
 pub enum StdIo {
     StdIn,
     StdOut,
@@ -133,9 +221,8 @@ pub enum StdIo {
 
 impl StdIo {
     fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
-        let mut api = STD_IO_API.lock().unwrap();
-        match self {
-            Self::StdIn => match &mut api.std_in {
+        STD_IO_API.with(|api| match self {
+            Self::StdIn => match &mut api.borrow_mut().std_in {
                 Some(r) => r.read(buf),
                 None => io::stdin().lock().read(buf),
             },
@@ -147,13 +234,12 @@ impl StdIo {
                 io::ErrorKind::Unsupported,
                 "read from std error",
             )),
-        }
+        })
     }
 
     fn write(&self, buf: &[u8]) -> io::Result<usize> {
-        let mut api = STD_IO_API.lock().unwrap();
-        match self {
-            Self::StdOut => match &mut api.std_out {
+        STD_IO_API.with(|api| match self {
+            Self::StdOut => match &mut api.borrow_mut().std_out {
                 Some(r) => r.write(buf),
                 None => {
                     #[cfg(feature = "wasm")]
@@ -165,7 +251,7 @@ impl StdIo {
                     io::stdout().lock().write(buf)
                 }
             },
-            Self::StdErr => match &mut api.std_err {
+            Self::StdErr => match &mut api.borrow_mut().std_err {
                 Some(r) => r.write(buf),
                 None => {
                     #[cfg(feature = "wasm")]
@@ -181,7 +267,7 @@ impl StdIo {
                 io::ErrorKind::Unsupported,
                 "write to std in",
             )),
-        }
+        })
     }
 }
 
@@ -214,6 +300,20 @@ impl VirtualFile {
         }
     }
 
+    /// The size of the file after writing `n` bytes at the current offset, None for
+    /// the standard streams
+    fn size_after_write(&self, n: usize) -> Option<u64> {
+        match self {
+            Self::File(f) => {
+                let mut f = f.borrow_mut();
+                let len = f.metadata().ok()?.len();
+                let pos = f.stream_position().ok()?;
+                Some(len.max(pos + n as u64))
+            }
+            Self::StdIo(_) => None,
+        }
+    }
+
     fn seek(&self, pos: io::SeekFrom) -> io::Result<u64> {
         match self {
             Self::File(f) => f.borrow_mut().seek(pos),
@@ -7,7 +7,6 @@ use crate::ffi::*;
 use go_vm::types::*;
 use std::any::Any;
 use std::cell::RefCell;
-use std::mem;
 use std::rc::Rc;
 
 macro_rules! err_wrong_type {
@@ -45,6 +44,11 @@ fn val_to_std_val(val: &GosValue) -> RuntimeResult<&StdValue> {
     val.as_non_nil_unsafe_ptr()?.downcast_ref::<StdValue>()
 }
 
+#[inline]
+fn val_to_std_type(val: &GosValue) -> RuntimeResult<&StdType> {
+    val.as_non_nil_unsafe_ptr()?.downcast_ref::<StdType>()
+}
+
 #[inline]
 fn val_to_map_iter(val: &GosValue) -> RuntimeResult<&StdMapIter> {
     val.as_non_nil_unsafe_ptr()?.downcast_ref::<StdMapIter>()
@@ -115,10 +119,18 @@ impl ReflectFfi {
         val_to_std_val(&p)?.float_val(ctx)
     }
 
+    fn ffi_string_val(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<GosValue> {
+        val_to_std_val(&p)?.string_val(ctx)
+    }
+
     fn ffi_bytes_val(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<GosValue> {
         val_to_std_val(&p)?.bytes_val(ctx)
     }
 
+    fn ffi_interface_val(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<GosValue> {
+        val_to_std_val(&p)?.interface_val(ctx)
+    }
+
     fn ffi_elem(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<GosValue> {
         val_to_std_val(&p)?.elem(ctx)
     }
@@ -163,6 +175,10 @@ impl ReflectFfi {
         Ok(val_to_std_val(&p)?.can_addr())
     }
 
+    fn ffi_can_interface(p: GosValue) -> RuntimeResult<bool> {
+        Ok(val_to_std_val(&p)?.can_interface())
+    }
+
     fn ffi_can_set(p: GosValue) -> RuntimeResult<bool> {
         Ok(val_to_std_val(&p)?.can_set())
     }
@@ -203,6 +219,27 @@ impl ReflectFfi {
         val_to_std_val(&p)?.set_pointer(ctx, val)
     }
 
+    fn ffi_type_string(t: GosValue) -> RuntimeResult<GosValue> {
+        let t = val_to_std_type(&t)?;
+        Ok(FfiCtx::new_string(&type_string(
+            &t.meta,
+            meta_objs(t.mobjs),
+        )))
+    }
+
+    fn ffi_type_name(t: GosValue) -> RuntimeResult<(GosValue, GosValue)> {
+        let (name, pkg_path) = val_to_std_type(&t)?.name();
+        Ok((FfiCtx::new_string(&name), FfiCtx::new_string(&pkg_path)))
+    }
+
+    fn ffi_chan_dir(t: GosValue) -> RuntimeResult<isize> {
+        val_to_std_type(&t)?.chan_dir()
+    }
+
+    fn ffi_close(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<()> {
+        val_to_std_val(&p)?.close(ctx)
+    }
+
     fn ffi_swap(iface: GosValue, i: isize, j: isize) -> RuntimeResult<()> {
         let iface = iface.as_interface().unwrap();
         match iface.underlying_value() {
@@ -305,6 +342,14 @@ impl StdValue {
         .map(|x| x.into_inner())
     }
 
+    fn string_val(&self, ctx: &FfiCtx) -> RuntimeResult<GosValue> {
+        let val = self.val(ctx)?;
+        match val.typ() {
+            ValueType::String => Ok(val),
+            _ => err_wrong_type!(),
+        }
+    }
+
     fn bytes_val(&self, ctx: &FfiCtx) -> RuntimeResult<GosValue> {
         let val = self.val(ctx)?;
         if val.typ() != ValueType::Slice || val.t_elem() != ValueType::Uint8 {
@@ -313,6 +358,20 @@ impl StdValue {
         Ok(val)
     }
 
+    /// Wraps the value in an interface{} holding its type, so that it can be asserted to
+    /// the interfaces its type implements, like fmt does to find Stringers
+    fn interface_val(&self, ctx: &FfiCtx) -> RuntimeResult<GosValue> {
+        let meta = match self.meta() {
+            Some(m) => *m,
+            None => return Err("reflect: type info missing".to_owned().into()),
+        };
+        let val = self.val(ctx)?;
+        if meta.is_interface(&ctx.vm_objs.metas) {
+            return Ok(val);
+        }
+        Ok(FfiCtx::new_interface(val, Some((meta, vec![]))))
+    }
+
     fn elem(&self, ctx: &FfiCtx) -> RuntimeResult<GosValue> {
         let val = self.val(ctx)?;
         match val.typ() {
@@ -397,6 +456,24 @@ impl StdValue {
         Ok(val.len() as isize)
     }
 
+    /// Closes the channel, like `close` it must not be receive-only, which the
+    /// checker can't see through a Value
+    fn close(&self, ctx: &FfiCtx) -> RuntimeResult<()> {
+        let metas = &ctx.vm_objs.metas;
+        match self.meta().map(|m| &metas[m.underlying(metas).key]) {
+            Some(MetadataType::Channel(ChannelType::Recv, _)) => {
+                return Err("reflect: close of receive-only channel".to_owned().into())
+            }
+            Some(MetadataType::Channel(..)) => {}
+            _ => return err_wrong_type!(),
+        }
+        #[cfg(feature = "async")]
+        if let Some(c) = self.val(ctx)?.as_channel() {
+            c.close();
+        }
+        Ok(())
+    }
+
     fn can_addr(&self) -> bool {
         match self {
             Self::Value(_, _) => false,
@@ -404,6 +481,13 @@ impl StdValue {
         }
     }
 
+    fn can_interface(&self) -> bool {
+        match self {
+            Self::Value(_, m) => m.is_some(),
+            Self::Pointer(_, m, exported) => m.is_some() && exported.unwrap_or(true),
+        }
+    }
+
     fn can_set(&self) -> bool {
         match self {
             Self::Value(_, _) => false,
@@ -542,13 +626,46 @@ impl StdType {
         }
     }
 
+    /// The name and the package path, like `Type.Name` and `Type.PkgPath`, both are
+    /// empty for the types not named, and the path is for the predeclared ones
+    fn name(&self) -> (String, String) {
+        let objs = meta_objs(self.mobjs);
+        if self.meta.ptr_depth > 0 {
+            return (String::new(), String::new());
+        }
+        match &objs[self.meta.key] {
+            MetadataType::Named(_, _, n) => (n.name.clone(), n.pkg_path.clone()),
+            MetadataType::Array(..)
+            | MetadataType::Slice(_)
+            | MetadataType::Struct(_)
+            | MetadataType::Signature(_)
+            | MetadataType::Map(..)
+            | MetadataType::Interface(_)
+            | MetadataType::Channel(..)
+            | MetadataType::None => (String::new(), String::new()),
+            _ => (type_string(&self.meta, objs), String::new()),
+        }
+    }
+
+    /// The direction of a channel type, as a `reflect.ChanDir`
+    fn chan_dir(&self) -> RuntimeResult<isize> {
+        let objs = meta_objs(self.mobjs);
+        if self.meta.ptr_depth > 0 {
+            return err_wrong_type!();
+        }
+        match &objs[self.meta.underlying(objs).key] {
+            MetadataType::Channel(ChannelType::Recv, _) => Ok(1),
+            MetadataType::Channel(ChannelType::Send, _) => Ok(2),
+            MetadataType::Channel(ChannelType::SendRecv, _) => Ok(3),
+            _ => err_wrong_type!(),
+        }
+    }
+
     fn type_of(val: &StdValue, ctx: &FfiCtx) -> (GosValue, usize) {
         let m = val.meta().unwrap().clone();
         let typ = StdType::new(m, &ctx.vm_objs.metas);
-        let kind = match m
-            .underlying(&ctx.vm_objs.metas)
-            .value_type(&ctx.vm_objs.metas)
-        {
+        // the pointers to named types are pointers, not their underlying types
+        let kind = match m.value_type(&ctx.vm_objs.metas) {
             ValueType::Bool => GosKind::Bool,
             ValueType::Int => GosKind::Int,
             ValueType::Int8 => GosKind::Int8,
@@ -583,7 +700,7 @@ impl StdType {
 
 #[derive(Clone, Debug)]
 struct StdMapIterInner {
-    iter: GosMapIter<'static>,
+    iter: MapObjIter,
     item: Option<(GosValue, GosValue)>,
 }
 
@@ -591,7 +708,7 @@ struct StdMapIterInner {
 struct StdMapIter {
     inner: RefCell<StdMapIterInner>,
     key_meta: Meta,
-    //val_meta: Meta,
+    val_meta: Meta,
 }
 
 impl UnsafePtr for StdMapIter {
@@ -602,22 +719,21 @@ impl UnsafePtr for StdMapIter {
 
 impl StdMapIter {
     fn map_range(ctx: &FfiCtx, v: &StdValue) -> RuntimeResult<GosValue> {
-        let val = v.val(ctx)?;
-        let mref = val.as_map().unwrap().0.borrow_data();
-        let iter: GosMapIter<'static> = unsafe { mem::transmute(mref.iter()) };
+        let iter = MapObjIter::new(v.val(ctx)?);
         let metas = &ctx.vm_objs.metas;
         let map_meta = metas[v.meta().unwrap().underlying(metas).key].as_map();
-        let (key_meta, _) = (map_meta.0.clone(), map_meta.1.clone());
+        let (key_meta, val_meta) = (map_meta.0.clone(), map_meta.1.clone());
         let smi = StdMapIter {
             inner: RefCell::new(StdMapIterInner { iter, item: None }),
             key_meta,
+            val_meta,
         };
         Ok(FfiCtx::new_unsafe_ptr(Rc::new(smi)))
     }
 
     fn next(&self) -> GosValue {
         let mut inner = self.inner.borrow_mut();
-        inner.item = inner.iter.next().map(|x| (x.0.clone(), x.1.clone()));
+        inner.item = inner.iter.next();
         inner.item.is_some().into()
     }
 
@@ -638,6 +754,6 @@ impl StdMapIter {
                 .to_owned()
                 .into()),
         }
-        .map(|x| wrap_std_val(x, Some(self.key_meta)))
+        .map(|x| wrap_std_val(x, Some(self.val_meta)))
     }
 }
@@ -0,0 +1,318 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The host side of the `database/sql` package, a database driver is implemented
+//! in Rust with the `Driver` and `Conn` traits, and made available to `sql.Open`
+//! by adding a `DriverPlugin` to the engine.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+use crate::plugin::EnginePlugin;
+#[cfg(feature = "async")]
+use futures_lite::future::Future;
+use go_vm::types::*;
+use go_vm::Capability;
+use std::cell::{Cell, RefCell};
+use std::path::Path;
+#[cfg(feature = "async")]
+use std::pin::Pin;
+use std::rc::Rc;
+
+#[cfg(feature = "sqlite")]
+pub use super::sqlite::SqliteDriver;
+
+/// The FFI names of the drivers start with this prefix, followed by the driver name
+const FFI_PREFIX: &str = "sql.driver.";
+
+/// A value passed to or read from a database
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    /// The kind of the value as known by database/sql
+    fn kind(&self) -> isize {
+        match self {
+            Value::Null => 0,
+            Value::Int(_) => 1,
+            Value::Float(_) => 2,
+            Value::Text(_) => 3,
+            Value::Blob(_) => 4,
+        }
+    }
+}
+
+/// The result of `Conn::exec`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExecResult {
+    pub last_insert_id: i64,
+    pub rows_affected: i64,
+}
+
+/// The result of `Conn::query`, all the rows are fetched at once
+#[derive(Debug, Clone, Default)]
+pub struct Rows {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+/// A database driver, as database/sql/driver.Driver in Go
+pub trait Driver {
+    /// The name used in `sql.Open`, like "sqlite3"
+    fn name(&self) -> &'static str;
+
+    fn open(&self, dsn: &str) -> Result<Box<dyn Conn>, String>;
+
+    /// The file opened for the data source name, which the sandbox of the engine
+    /// must allow the script to write, None if it doesn't open a file
+    fn file<'a>(&self, _dsn: &'a str) -> Option<&'a Path> {
+        None
+    }
+}
+
+/// A connection to a database
+pub trait Conn {
+    fn exec(&mut self, query: &str, args: &[Value]) -> Result<ExecResult, String>;
+
+    fn query(&mut self, query: &str, args: &[Value]) -> Result<Rows, String>;
+
+    fn close(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Makes a `Driver` available to `sql.Open` in Go code
+pub struct DriverPlugin {
+    ffi_name: String,
+    driver: Rc<dyn Driver>,
+}
+
+impl DriverPlugin {
+    pub fn new<D: Driver + 'static>(driver: D) -> DriverPlugin {
+        let ffi_name = format!("{}{}", FFI_PREFIX, driver.name());
+        DriverPlugin {
+            ffi_name,
+            driver: Rc::new(driver),
+        }
+    }
+}
+
+impl EnginePlugin for DriverPlugin {
+    fn name(&self) -> &str {
+        &self.ffi_name
+    }
+
+    fn register_ffi(&self, factory: &mut FfiFactory) {
+        factory.register(
+            self.ffi_name.clone(),
+            Rc::new(DriverFfi {
+                driver: self.driver.clone(),
+            }),
+        );
+    }
+}
+
+#[derive(UnsafePtr)]
+struct ConnHandle(RefCell<Option<Box<dyn Conn>>>);
+
+impl ConnHandle {
+    fn with<T>(&self, f: impl FnOnce(&mut dyn Conn) -> Result<T, String>) -> Result<T, String> {
+        match self.0.borrow_mut().as_mut() {
+            Some(conn) => f(conn.as_mut()),
+            None => Err("sql: database is closed".to_owned()),
+        }
+    }
+}
+
+#[derive(UnsafePtr)]
+struct RowsHandle {
+    rows: Rows,
+    // index of the current row plus one, 0 means before the first row
+    cursor: Cell<usize>,
+}
+
+/// The FFI of a driver, unlike the FFIs generated by `ffi_impl`, it holds the driver
+struct DriverFfi {
+    driver: Rc<dyn Driver>,
+}
+
+impl DriverFfi {
+    fn dispatch(&self, ctx: &mut FfiCtx, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        match ctx.func_name {
+            "open" => {
+                let dsn = args[0].as_string();
+                let dsn = dsn.as_str();
+                if let Some(path) = self.driver.file(&dsn) {
+                    if !ctx.allows(&Capability::OpenFile { path, write: true }) {
+                        return Ok(vec![
+                            FfiCtx::new_nil(ValueType::UnsafePtr),
+                            FfiCtx::new_string(&format!(
+                                "open {}: permission denied",
+                                path.display()
+                            )),
+                        ]);
+                    }
+                }
+                Ok(match self.driver.open(&dsn) {
+                    Ok(conn) => vec![
+                        FfiCtx::new_unsafe_ptr(Rc::new(ConnHandle(RefCell::new(Some(conn))))),
+                        FfiCtx::new_string(""),
+                    ],
+                    Err(e) => vec![
+                        FfiCtx::new_nil(ValueType::UnsafePtr),
+                        FfiCtx::new_string(&e),
+                    ],
+                })
+            }
+            "close" => {
+                let conn = args[0]
+                    .as_non_nil_unsafe_ptr()?
+                    .downcast_ref::<ConnHandle>()?;
+                let result = match conn.0.borrow_mut().take() {
+                    Some(mut c) => c.close(),
+                    None => Ok(()),
+                };
+                Ok(vec![FfiCtx::new_string(&result.err().unwrap_or_default())])
+            }
+            "exec" => {
+                let conn = args[0]
+                    .as_non_nil_unsafe_ptr()?
+                    .downcast_ref::<ConnHandle>()?;
+                let params = DriverFfi::params(&args[2])?;
+                Ok(
+                    match conn.with(|c| c.exec(&args[1].as_string().as_str(), &params)) {
+                        Ok(r) => vec![
+                            r.last_insert_id.into(),
+                            r.rows_affected.into(),
+                            FfiCtx::new_string(""),
+                        ],
+                        Err(e) => vec![0i64.into(), 0i64.into(), FfiCtx::new_string(&e)],
+                    },
+                )
+            }
+            "query" => {
+                let conn = args[0]
+                    .as_non_nil_unsafe_ptr()?
+                    .downcast_ref::<ConnHandle>()?;
+                let params = DriverFfi::params(&args[2])?;
+                Ok(
+                    match conn.with(|c| c.query(&args[1].as_string().as_str(), &params)) {
+                        Ok(rows) => vec![
+                            FfiCtx::new_unsafe_ptr(Rc::new(RowsHandle {
+                                rows,
+                                cursor: Cell::new(0),
+                            })),
+                            FfiCtx::new_string(""),
+                        ],
+                        Err(e) => vec![
+                            FfiCtx::new_nil(ValueType::UnsafePtr),
+                            FfiCtx::new_string(&e),
+                        ],
+                    },
+                )
+            }
+            "columns" => {
+                let rows = args[0]
+                    .as_non_nil_unsafe_ptr()?
+                    .downcast_ref::<RowsHandle>()?;
+                let names = rows
+                    .rows
+                    .columns
+                    .iter()
+                    .map(|x| FfiCtx::new_string(x))
+                    .collect();
+                Ok(vec![ctx.new_slice(names, ValueType::String)])
+            }
+            "next" => {
+                let rows = args[0]
+                    .as_non_nil_unsafe_ptr()?
+                    .downcast_ref::<RowsHandle>()?;
+                let has_next = rows.cursor.get() < rows.rows.rows.len();
+                if has_next {
+                    rows.cursor.set(rows.cursor.get() + 1);
+                }
+                Ok(vec![has_next.into()])
+            }
+            "value" => {
+                let rows = args[0]
+                    .as_non_nil_unsafe_ptr()?
+                    .downcast_ref::<RowsHandle>()?;
+                let i: isize = args[1].as_();
+                let val = rows
+                    .cursor
+                    .get()
+                    .checked_sub(1)
+                    .and_then(|r| rows.rows.rows.get(r))
+                    .and_then(|row| row.get(i as usize))
+                    .ok_or_else(|| "sql: no current row or column".to_owned())?;
+                let (mut iv, mut fv, mut sv) = (0i64, 0f64, "");
+                let mut bv = FfiCtx::new_nil_slice(ValueType::Uint8);
+                match val {
+                    Value::Null => {}
+                    Value::Int(v) => iv = *v,
+                    Value::Float(v) => fv = *v,
+                    Value::Text(v) => sv = v,
+                    Value::Blob(v) => bv = FfiCtx::new_bytes(v),
+                }
+                Ok(vec![
+                    val.kind().into(),
+                    iv.into(),
+                    fv.into(),
+                    FfiCtx::new_string(sv),
+                    bv,
+                ])
+            }
+            _ => Err(format!("ffi function '{}' not found!", ctx.func_name).into()),
+        }
+    }
+
+    /// Converts the arguments of a query, which are normalized by the Go side to
+    /// nil, int64, float64, string or []byte
+    fn params(args: &GosValue) -> RuntimeResult<Vec<Value>> {
+        let vec = FfiCtx::slice_as_rust_slice::<GosElem>(args)?;
+        vec.iter()
+            .map(|x| {
+                let val = x.borrow();
+                let underlying = match val.is_nil() {
+                    true => None,
+                    false => val.iface_underlying()?,
+                };
+                Ok(match underlying {
+                    None => Value::Null,
+                    Some(v) => match v.typ() {
+                        ValueType::Int64 => Value::Int(*v.as_int64()),
+                        ValueType::Float64 => Value::Float(v.as_float64().into_inner()),
+                        ValueType::String => Value::Text(v.as_string().as_str().to_string()),
+                        ValueType::Slice if v.is_nil() => Value::Null,
+                        ValueType::Slice => {
+                            Value::Blob(FfiCtx::slice_as_primitive_slice::<u8, u8>(&v)?.to_vec())
+                        }
+                        t => return Err(format!("sql: unsupported argument type {:?}", t).into()),
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+impl Ffi for DriverFfi {
+    fn call(&self, ctx: &mut FfiCtx, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        self.dispatch(ctx, args)
+    }
+
+    #[cfg(feature = "async")]
+    fn async_call(
+        &self,
+        ctx: &mut FfiCtx,
+        _args: Vec<GosValue>,
+    ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>> + '_>> {
+        let err = Err(format!("ffi function '{}' not found!", ctx.func_name).into());
+        Box::pin(async move { err })
+    }
+}
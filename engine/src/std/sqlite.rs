@@ -0,0 +1,96 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use super::sql::{Conn, Driver, ExecResult, Rows, Value};
+use rusqlite::types::Value as SqliteValue;
+use rusqlite::{params_from_iter, Connection, OpenFlags};
+use std::path::Path;
+
+/// The reference driver of database/sql, backed by a bundled SQLite.
+/// The data source name is a file path or ":memory:", URIs are not supported.
+pub struct SqliteDriver;
+
+impl Driver for SqliteDriver {
+    fn name(&self) -> &'static str {
+        "sqlite3"
+    }
+
+    fn open(&self, dsn: &str) -> Result<Box<dyn Conn>, String> {
+        let conn = match dsn {
+            ":memory:" => Connection::open_in_memory(),
+            // without SQLITE_OPEN_URI, so that the file opened is the path checked
+            _ => Connection::open_with_flags(
+                dsn,
+                OpenFlags::SQLITE_OPEN_READ_WRITE
+                    | OpenFlags::SQLITE_OPEN_CREATE
+                    | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            ),
+        };
+        conn.map(|c| Box::new(SqliteConn(c)) as Box<dyn Conn>)
+            .map_err(|e| e.to_string())
+    }
+
+    fn file<'a>(&self, dsn: &'a str) -> Option<&'a Path> {
+        match dsn {
+            ":memory:" => None,
+            _ => Some(Path::new(dsn)),
+        }
+    }
+}
+
+struct SqliteConn(Connection);
+
+impl SqliteConn {
+    fn params(args: &[Value]) -> impl Iterator<Item = SqliteValue> + '_ {
+        args.iter().map(|x| match x {
+            Value::Null => SqliteValue::Null,
+            Value::Int(i) => SqliteValue::Integer(*i),
+            Value::Float(f) => SqliteValue::Real(*f),
+            Value::Text(s) => SqliteValue::Text(s.clone()),
+            Value::Blob(b) => SqliteValue::Blob(b.clone()),
+        })
+    }
+}
+
+impl Conn for SqliteConn {
+    fn exec(&mut self, query: &str, args: &[Value]) -> Result<ExecResult, String> {
+        let n = self
+            .0
+            .execute(query, params_from_iter(SqliteConn::params(args)))
+            .map_err(|e| e.to_string())?;
+        Ok(ExecResult {
+            last_insert_id: self.0.last_insert_rowid(),
+            rows_affected: n as i64,
+        })
+    }
+
+    fn query(&mut self, query: &str, args: &[Value]) -> Result<Rows, String> {
+        let mut stmt = self.0.prepare(query).map_err(|e| e.to_string())?;
+        let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+        let count = columns.len();
+        let mut rows = stmt
+            .query(params_from_iter(SqliteConn::params(args)))
+            .map_err(|e| e.to_string())?;
+        let mut result = vec![];
+        while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+            let values = (0..count)
+                .map(|i| {
+                    row.get::<_, SqliteValue>(i).map(|v| match v {
+                        SqliteValue::Null => Value::Null,
+                        SqliteValue::Integer(i) => Value::Int(i),
+                        SqliteValue::Real(f) => Value::Float(f),
+                        SqliteValue::Text(s) => Value::Text(s),
+                        SqliteValue::Blob(b) => Value::Blob(b),
+                    })
+                })
+                .collect::<Result<Vec<Value>, _>>()
+                .map_err(|e| e.to_string())?;
+            result.push(values);
+        }
+        Ok(Rows {
+            columns,
+            rows: result,
+        })
+    }
+}
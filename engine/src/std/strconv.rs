@@ -0,0 +1,346 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+use go_vm::types::*;
+use std::borrow::Cow;
+use std::io::Write;
+
+/// What went wrong parsing a number, `numError` in Go makes them the `*NumError`s
+/// with `ErrSyntax` or `ErrRange`.
+const OK: isize = 0;
+const SYNTAX: isize = 1;
+const RANGE: isize = 2;
+
+const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+/// The conversions of package strconv between strings and numbers, and the quoting
+/// of strings. The Go side checks the bases and the bit sizes, and builds the
+/// errors from the codes returned here.
+#[derive(Ffi)]
+pub struct StrconvFfi;
+
+#[ffi_impl]
+impl StrconvFfi {
+    fn ffi_parse_uint(s: GosValue, base: isize, bit_size: isize) -> (u64, isize) {
+        parse_uint(&s.as_string().as_bytes(), base as u32, bit_size as u32)
+    }
+
+    fn ffi_parse_int(s: GosValue, base: isize, bit_size: isize) -> (i64, isize) {
+        let s = s.as_string().as_bytes();
+        let (neg, digits) = match s.first() {
+            Some(b'+') => (false, &s[1..]),
+            Some(b'-') => (true, &s[1..]),
+            _ => (false, &s[..]),
+        };
+        let (un, code) = parse_uint(digits, base as u32, bit_size as u32);
+        if code == SYNTAX {
+            return (0, SYNTAX);
+        }
+        // a range error leaves un at the max of the bit size, clamped below
+        let cutoff = 1u64 << (bit_size - 1);
+        if !neg && un >= cutoff {
+            return ((cutoff - 1) as i64, RANGE);
+        }
+        if neg && un > cutoff {
+            return ((cutoff as i64).wrapping_neg(), RANGE);
+        }
+        match neg {
+            true => ((un as i64).wrapping_neg(), OK),
+            false => (un as i64, OK),
+        }
+    }
+
+    /// Overflows are range errors with ±Inf, underflows are not errors, like in Go
+    fn ffi_parse_float(s: GosValue, bit_size: isize) -> (f64, isize) {
+        let s = s.as_string().as_bytes();
+        let text = match std::str::from_utf8(&s) {
+            Ok(text) => text,
+            Err(_) => return (0.0, SYNTAX),
+        };
+        let unsigned = text.strip_prefix(&['+', '-'][..]).unwrap_or(text);
+        // Rust takes a sign for NaN, Go doesn't
+        if unsigned.eq_ignore_ascii_case("nan") && unsigned.len() < text.len() {
+            return (0.0, SYNTAX);
+        }
+        let inf = unsigned.eq_ignore_ascii_case("inf") || unsigned.eq_ignore_ascii_case("infinity");
+        let f = match bit_size {
+            32 => text.parse::<f32>().map(|f| f as f64),
+            _ => text.parse::<f64>(),
+        };
+        match f {
+            Ok(f) if f.is_infinite() && !inf => (f, RANGE),
+            Ok(f) => (f, OK),
+            Err(_) => (0.0, SYNTAX),
+        }
+    }
+
+    fn ffi_format_int(i: i64, base: isize) -> String {
+        let s = format_bits(i.unsigned_abs(), base as u64);
+        match i < 0 {
+            true => format!("-{}", s),
+            false => s,
+        }
+    }
+
+    fn ffi_format_uint(u: u64, base: isize) -> String {
+        format_bits(u, base as u64)
+    }
+
+    /// The decimal digits of |f| for `formatDigits`, without the leading and the
+    /// trailing zeros, and the position of the decimal point in them. They are the
+    /// shortest that read back as f if prec is negative, otherwise they are rounded
+    /// to prec as the format `fmt` takes it.
+    fn ffi_float_digits(f: f64, bit_size: isize, fmt: u8, prec: isize) -> (String, isize) {
+        let v = match bit_size {
+            32 => (f as f32).abs() as f64,
+            _ => f.abs(),
+        };
+        if v == 0.0 {
+            return (String::new(), 0);
+        }
+        let s = match (fmt, prec) {
+            (_, p) if p < 0 && bit_size == 32 => format!("{:e}", v as f32),
+            (_, p) if p < 0 => format!("{:e}", v),
+            (b'f', p) => format!("{:.*}", p as usize, v),
+            (b'e' | b'E', p) => format!("{:.*e}", p as usize, v),
+            (_, p) => format!("{:.*e}", p.max(1) as usize - 1, v),
+        };
+        let (mantissa, exp) = match s.split_once('e') {
+            Some((m, e)) => (m, e.parse::<isize>().unwrap() + 1),
+            None => (&s[..], s.find('.').unwrap_or(s.len()) as isize),
+        };
+        let digits: String = mantissa.chars().filter(|&c| c != '.').collect();
+        let trimmed = digits.trim_start_matches('0');
+        let dp = exp - (digits.len() - trimmed.len()) as isize;
+        let trimmed = trimmed.trim_end_matches('0');
+        match trimmed.is_empty() {
+            true => (String::new(), 0),
+            false => (trimmed.to_owned(), dp),
+        }
+    }
+
+    /// Returns false if s has chars that aren't ASCII and those aren't all escaped,
+    /// for the Go side to look them up in the tables of `IsPrint`.
+    fn ffi_quote(s: GosValue, quote: u8, ascii_only: bool) -> (GosValue, bool) {
+        let quoted = {
+            let bytes = s.as_string().as_bytes();
+            quote_ascii(&bytes, quote, ascii_only)
+        };
+        match quoted {
+            Some(buf) => (FfiCtx::new_string_of_bytes(buf), true),
+            None => (s, false),
+        }
+    }
+
+    fn ffi_unquote(s: GosValue) -> (GosValue, bool) {
+        let bytes = s.as_string().as_bytes();
+        let unquoted = unquote(&bytes);
+        match unquoted {
+            Some(Cow::Borrowed(_)) => (
+                GosValue::slice_string(&s, 1, bytes.len() as isize - 1, -1).unwrap(),
+                true,
+            ),
+            Some(Cow::Owned(buf)) => (FfiCtx::new_string_of_bytes(buf), true),
+            None => (FfiCtx::new_string(""), false),
+        }
+    }
+}
+
+/// `ParseUint` for a base that is valid or 0 and a bit size from 1 to 64
+fn parse_uint(s: &[u8], base: u32, bit_size: u32) -> (u64, isize) {
+    if s.is_empty() {
+        return (0, SYNTAX);
+    }
+    let (digits, base) = match (base, s) {
+        (0, [b'0', b'x' | b'X']) => return (0, SYNTAX),
+        (0, [b'0', b'x' | b'X', rest @ ..]) => (rest, 16),
+        (0, [b'0', rest @ ..]) => (rest, 8),
+        (0, _) => (s, 10),
+        _ => (s, base),
+    };
+    let max = u64::MAX >> (64 - bit_size);
+    let mut n: u64 = 0;
+    for &c in digits {
+        let d = match (c as char).to_digit(36) {
+            Some(d) if d < base => d as u64,
+            _ => return (0, SYNTAX),
+        };
+        match n.checked_mul(base as u64).and_then(|n| n.checked_add(d)) {
+            Some(n1) if n1 <= max => n = n1,
+            _ => return (max, RANGE),
+        }
+    }
+    (n, OK)
+}
+
+fn format_bits(mut u: u64, base: u64) -> String {
+    if base == 10 {
+        return u.to_string();
+    }
+    let mut buf = vec![];
+    loop {
+        buf.push(DIGITS[(u % base) as usize]);
+        u /= base;
+        if u == 0 {
+            break;
+        }
+    }
+    buf.reverse();
+    String::from_utf8(buf).unwrap()
+}
+
+fn quote_ascii(s: &[u8], quote: u8, ascii_only: bool) -> Option<Vec<u8>> {
+    let mut buf = Vec::with_capacity(s.len() + 2);
+    buf.push(quote);
+    let mut i = 0;
+    while i < s.len() {
+        let (c, size) = decode_rune(&s[i..]);
+        if c == char::REPLACEMENT_CHARACTER && size == 1 {
+            write!(buf, "\\x{:02x}", s[i]).unwrap();
+        } else if c.is_ascii() || ascii_only {
+            escape_rune(&mut buf, c, quote);
+        } else {
+            return None;
+        }
+        i += size;
+    }
+    buf.push(quote);
+    Some(buf)
+}
+
+/// `appendEscapedRune` for an ASCII char, or with ASCIIonly
+fn escape_rune(buf: &mut Vec<u8>, c: char, quote: u8) {
+    let escaped: &[u8] = match c {
+        _ if c == quote as char || c == '\\' => return buf.extend_from_slice(&[b'\\', c as u8]),
+        ' '..='~' => return buf.push(c as u8),
+        '\x07' => b"\\a",
+        '\x08' => b"\\b",
+        '\x0c' => b"\\f",
+        '\n' => b"\\n",
+        '\r' => b"\\r",
+        '\t' => b"\\t",
+        '\x0b' => b"\\v",
+        _ if c < ' ' => return write!(buf, "\\x{:02x}", c as u32).unwrap(),
+        _ if (c as u32) < 0x10000 => return write!(buf, "\\u{:04x}", c as u32).unwrap(),
+        _ => return write!(buf, "\\U{:08x}", c as u32).unwrap(),
+    };
+    buf.extend_from_slice(escaped);
+}
+
+/// `Unquote`, borrowing the part of s between the quotes when there is nothing to
+/// unescape in it
+fn unquote(s: &[u8]) -> Option<Cow<'_, [u8]>> {
+    let n = s.len();
+    if n < 2 || s[0] != s[n - 1] {
+        return None;
+    }
+    let (quote, inner) = (s[0], &s[1..n - 1]);
+    match quote {
+        b'`' if inner.contains(&b'`') => return None,
+        b'`' if inner.contains(&b'\r') => {
+            return Some(Cow::Owned(
+                inner.iter().copied().filter(|&b| b != b'\r').collect(),
+            ))
+        }
+        b'`' => return Some(Cow::Borrowed(inner)),
+        b'"' | b'\'' if !inner.contains(&b'\n') => {}
+        _ => return None,
+    }
+
+    if !inner.contains(&b'\\') && !inner.contains(&quote) {
+        let trivial = match quote {
+            b'"' => std::str::from_utf8(inner).is_ok(),
+            // an empty char literal passes, like it does in Go 1.12
+            _ => match decode_rune(inner) {
+                _ if inner.is_empty() => true,
+                (c, size) => {
+                    size == inner.len() && !(c == char::REPLACEMENT_CHARACTER && size == 1)
+                }
+            },
+        };
+        if trivial {
+            return Some(Cow::Borrowed(inner));
+        }
+    }
+
+    let mut buf = Vec::with_capacity(3 * inner.len() / 2);
+    let mut rest = inner;
+    while !rest.is_empty() {
+        let (c, multibyte, tail) = unquote_char(rest, quote)?;
+        rest = tail;
+        if c < 0x80 || !multibyte {
+            buf.push(c as u8);
+        } else {
+            let c = char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER);
+            buf.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+        // a char literal must be a single char
+        if quote == b'\'' && !rest.is_empty() {
+            return None;
+        }
+    }
+    Some(Cow::Owned(buf))
+}
+
+/// `UnquoteChar`, with None for `ErrSyntax`
+fn unquote_char(s: &[u8], quote: u8) -> Option<(u32, bool, &[u8])> {
+    let c = *s.first()?;
+    if c == quote {
+        return None;
+    }
+    if c >= 0x80 {
+        let (r, size) = decode_rune(s);
+        return Some((r as u32, true, &s[size..]));
+    }
+    if c != b'\\' {
+        return Some((c as u32, false, &s[1..]));
+    }
+
+    let (&c, s) = s[1..].split_first()?;
+    let value = match c {
+        b'a' => 0x07,
+        b'b' => 0x08,
+        b'f' => 0x0c,
+        b'n' => 0x0a,
+        b'r' => 0x0d,
+        b't' => 0x09,
+        b'v' => 0x0b,
+        b'x' | b'u' | b'U' => {
+            let n = match c {
+                b'x' => 2,
+                b'u' => 4,
+                _ => 8,
+            };
+            let v = s
+                .get(..n)?
+                .iter()
+                .try_fold(0u32, |v, &h| Some(v << 4 | (h as char).to_digit(16)?))?;
+            // \x makes a single byte, which may not be valid UTF-8
+            if c == b'x' {
+                return Some((v, false, &s[n..]));
+            }
+            if v > char::MAX as u32 {
+                return None;
+            }
+            return Some((v, true, &s[n..]));
+        }
+        b'0'..=b'7' => {
+            let v = s.get(..2)?.iter().try_fold((c - b'0') as u32, |v, &o| {
+                (b'0'..=b'7')
+                    .contains(&o)
+                    .then(|| v << 3 | (o - b'0') as u32)
+            })?;
+            if v > 255 {
+                return None;
+            }
+            return Some((v, false, &s[2..]));
+        }
+        b'\\' => b'\\' as u32,
+        b'\'' | b'"' if c == quote => c as u32,
+        _ => return None,
+    };
+    Some((value, false, s))
+}
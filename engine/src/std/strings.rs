@@ -0,0 +1,322 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+use go_vm::types::*;
+
+/// The primitives of package strings that scan or build strings, done on the
+/// bytes of the `StringObj`s. The results that are parts of an argument share its
+/// bytes, like they do in Go, and the bytes don't have to be valid UTF-8: an
+/// invalid byte is a "�" of its own wherever chars are decoded.
+#[derive(Ffi)]
+pub struct StringsFfi;
+
+#[ffi_impl]
+impl StringsFfi {
+    fn ffi_index(s: GosValue, substr: GosValue) -> isize {
+        let (s, substr) = (s.as_string().as_bytes(), substr.as_string().as_bytes());
+        index(&s, &substr).map_or(-1, |i| i as isize)
+    }
+
+    fn ffi_last_index(s: GosValue, substr: GosValue) -> isize {
+        let (s, substr) = (s.as_string().as_bytes(), substr.as_string().as_bytes());
+        last_index(&s, &substr).map_or(-1, |i| i as isize)
+    }
+
+    /// The non-overlapping instances of a substr that is not empty
+    fn ffi_count(s: GosValue, substr: GosValue) -> isize {
+        let (s, substr) = (s.as_string().as_bytes(), substr.as_string().as_bytes());
+        let (mut n, mut from) = (0, 0);
+        while let Some(i) = index(&s[from..], &substr) {
+            n += 1;
+            from += i + substr.len();
+        }
+        n
+    }
+
+    fn ffi_has_prefix(s: GosValue, prefix: GosValue) -> bool {
+        let (s, prefix) = (s.as_string().as_bytes(), prefix.as_string().as_bytes());
+        s.starts_with(&prefix)
+    }
+
+    fn ffi_has_suffix(s: GosValue, suffix: GosValue) -> bool {
+        let (s, suffix) = (s.as_string().as_bytes(), suffix.as_string().as_bytes());
+        s.ends_with(&suffix)
+    }
+
+    /// `genSplit` for a sep that is not empty and an n that is not 0
+    fn ffi_split(ctx: &FfiCtx, s: GosValue, sep: GosValue, sep_save: isize, n: isize) -> GosValue {
+        let parts = {
+            let (bytes, sep) = (s.as_string().as_bytes(), sep.as_string().as_bytes());
+            let mut parts = vec![];
+            let mut from = 0;
+            while n < 0 || parts.len() + 1 < n as usize {
+                match index(&bytes[from..], &sep) {
+                    Some(i) => {
+                        parts.push(sub_string(&s, from, from + i + sep_save as usize));
+                        from += i + sep.len();
+                    }
+                    None => break,
+                }
+            }
+            parts.push(sub_string(&s, from, bytes.len()));
+            parts
+        };
+        ctx.new_slice(parts, ValueType::String)
+    }
+
+    fn ffi_fields(ctx: &FfiCtx, s: GosValue) -> GosValue {
+        let fields = {
+            let bytes = s.as_string().as_bytes();
+            let mut fields = vec![];
+            let mut start = None;
+            for (i, c) in StringIter::new(&bytes) {
+                match (c.is_whitespace(), start) {
+                    (true, Some(from)) => {
+                        fields.push(sub_string(&s, from, i));
+                        start = None;
+                    }
+                    (false, None) => start = Some(i),
+                    _ => {}
+                }
+            }
+            if let Some(from) = start {
+                fields.push(sub_string(&s, from, bytes.len()));
+            }
+            fields
+        };
+        ctx.new_slice(fields, ValueType::String)
+    }
+
+    fn ffi_join(a: GosValue, sep: GosValue) -> RuntimeResult<GosValue> {
+        let elems = FfiCtx::slice_as_rust_slice::<GosElem>(&a)?;
+        let sep = sep.as_string().as_bytes();
+        let mut len = sep.len() * elems.len().saturating_sub(1);
+        for e in elems.iter() {
+            len += e.borrow().as_string().len();
+        }
+        let mut buf = Vec::with_capacity(len);
+        for (i, e) in elems.iter().enumerate() {
+            if i > 0 {
+                buf.extend_from_slice(&sep);
+            }
+            buf.extend_from_slice(&e.borrow().as_string().as_bytes());
+        }
+        Ok(FfiCtx::new_string_of_bytes(buf))
+    }
+
+    /// `Replace` for an old that is not empty and an n that is not 0
+    fn ffi_replace(s: GosValue, old: GosValue, new: GosValue, n: isize) -> GosValue {
+        let result = {
+            let bytes = s.as_string().as_bytes();
+            let (old, new) = (old.as_string().as_bytes(), new.as_string().as_bytes());
+            let mut buf = vec![];
+            let (mut done, mut count) = (0, 0);
+            while n < 0 || count < n {
+                match index(&bytes[done..], &old) {
+                    Some(i) => {
+                        buf.extend_from_slice(&bytes[done..done + i]);
+                        buf.extend_from_slice(&new);
+                        done += i + old.len();
+                        count += 1;
+                    }
+                    None => break,
+                }
+            }
+            if count == 0 {
+                None
+            } else {
+                buf.extend_from_slice(&bytes[done..]);
+                Some(buf)
+            }
+        };
+        match result {
+            Some(buf) => FfiCtx::new_string_of_bytes(buf),
+            None => s,
+        }
+    }
+
+    /// Trims the chars in cutset from the start of s if `left`, and from its end if
+    /// `right`
+    fn ffi_trim(s: GosValue, cutset: GosValue, left: bool, right: bool) -> GosValue {
+        let cutset: Vec<char> = StringIter::new(&cutset.as_string().as_bytes())
+            .map(|(_, c)| c)
+            .collect();
+        trim(&s, left, right, |c| cutset.contains(&c))
+    }
+
+    fn ffi_trim_space(s: GosValue) -> GosValue {
+        trim(&s, true, true, char::is_whitespace)
+    }
+
+    /// Returns false if s is not ASCII, for `unicode.ToUpper` to map the chars
+    fn ffi_to_upper(s: GosValue) -> (GosValue, bool) {
+        map_ascii(s, |b| b.is_ascii_lowercase(), u8::to_ascii_uppercase)
+    }
+
+    /// Returns false if s is not ASCII, for `unicode.ToLower` to map the chars
+    fn ffi_to_lower(s: GosValue) -> (GosValue, bool) {
+        map_ascii(s, |b| b.is_ascii_uppercase(), u8::to_ascii_lowercase)
+    }
+}
+
+/// The part of the string from `from` to `to`, sharing its bytes
+fn sub_string(s: &GosValue, from: usize, to: usize) -> GosValue {
+    GosValue::slice_string(s, from as isize, to as isize, -1).unwrap()
+}
+
+fn trim<F: Fn(char) -> bool>(s: &GosValue, left: bool, right: bool, cut: F) -> GosValue {
+    let (from, to) = {
+        let bytes = s.as_string().as_bytes();
+        let mut from = 0;
+        if left {
+            from = StringIter::new(&bytes)
+                .find(|(_, c)| !cut(*c))
+                .map_or(bytes.len(), |(i, _)| i);
+        }
+        let mut to = bytes.len();
+        if right {
+            while to > from {
+                let (c, size) = decode_last_rune(&bytes[from..to]);
+                if !cut(c) {
+                    break;
+                }
+                to -= size;
+            }
+        }
+        (from, to)
+    };
+    if from == 0 && to == s.len() {
+        s.clone()
+    } else {
+        sub_string(s, from, to)
+    }
+}
+
+fn map_ascii<P, F>(s: GosValue, mapped: P, f: F) -> (GosValue, bool)
+where
+    P: Fn(&u8) -> bool,
+    F: Fn(&u8) -> u8,
+{
+    let (ascii, result) = {
+        let bytes = s.as_string().as_bytes();
+        match bytes.is_ascii() && bytes.iter().any(mapped) {
+            true => (true, Some(bytes.iter().map(f).collect())),
+            false => (bytes.is_ascii(), None),
+        }
+    };
+    match result {
+        Some(buf) => (FfiCtx::new_string_of_bytes(buf), true),
+        None => (s, ascii),
+    }
+}
+
+/// Decodes the last char of the bytes like utf8.DecodeLastRune in Go
+fn decode_last_rune(bytes: &[u8]) -> (char, usize) {
+    let end = bytes.len();
+    if bytes[end - 1] < 0x80 {
+        return (bytes[end - 1] as char, 1);
+    }
+    // the start of the char is the last byte that isn't a continuation byte
+    let lim = end.saturating_sub(4);
+    let start = (lim..end - 1)
+        .rev()
+        .find(|&i| bytes[i] & 0xC0 != 0x80)
+        .unwrap_or(lim);
+    match decode_rune(&bytes[start..end]) {
+        (c, size) if start + size == end => (c, size),
+        _ => (char::REPLACEMENT_CHARACTER, 1),
+    }
+}
+
+/// The prime base of the Rabin-Karp hashes, as in Go
+const PRIME_RK: u32 = 16777619;
+
+fn hash(bytes: impl Iterator<Item = u8>) -> u32 {
+    bytes.fold(0u32, |h, b| h.wrapping_mul(PRIME_RK).wrapping_add(b as u32))
+}
+
+/// PRIME_RK to the power of n, for taking the byte n places back out of a hash
+fn pow_rk(n: usize) -> u32 {
+    (0..n).fold(1u32, |p, _| p.wrapping_mul(PRIME_RK))
+}
+
+/// Looks for the first byte of substr and compares the rest, like Go's `Index`
+/// does, falling back to Rabin-Karp when that keeps failing.
+fn index(s: &[u8], substr: &[u8]) -> Option<usize> {
+    let n = substr.len();
+    if n == 0 {
+        return Some(0);
+    }
+    if n > s.len() {
+        return None;
+    }
+    let last = s.len() - n;
+    let mut fails = 0;
+    let mut i = 0;
+    while i <= last {
+        i += s[i..=last].iter().position(|&b| b == substr[0])?;
+        if &s[i..i + n] == substr {
+            return Some(i);
+        }
+        i += 1;
+        fails += 1;
+        if fails >= 4 + (i >> 4) && i <= last {
+            return index_rabin_karp(&s[i..], substr).map(|j| i + j);
+        }
+    }
+    None
+}
+
+fn index_rabin_karp(s: &[u8], substr: &[u8]) -> Option<usize> {
+    let n = substr.len();
+    let target = hash(substr.iter().copied());
+    let pow = pow_rk(n);
+    let mut h = hash(s[..n].iter().copied());
+    if h == target && &s[..n] == substr {
+        return Some(0);
+    }
+    for i in n..s.len() {
+        h = h
+            .wrapping_mul(PRIME_RK)
+            .wrapping_add(s[i] as u32)
+            .wrapping_sub(pow.wrapping_mul(s[i - n] as u32));
+        if h == target && &s[i + 1 - n..=i] == substr {
+            return Some(i + 1 - n);
+        }
+    }
+    None
+}
+
+/// Rabin-Karp from the end of the string, like Go's `LastIndex`
+fn last_index(s: &[u8], substr: &[u8]) -> Option<usize> {
+    let n = substr.len();
+    if n == 0 {
+        return Some(s.len());
+    }
+    if n > s.len() {
+        return None;
+    }
+    if n == 1 {
+        return s.iter().rposition(|&b| b == substr[0]);
+    }
+    let target = hash(substr.iter().rev().copied());
+    let pow = pow_rk(n);
+    let last = s.len() - n;
+    let mut h = hash(s[last..].iter().rev().copied());
+    if h == target && &s[last..] == substr {
+        return Some(last);
+    }
+    for i in (0..last).rev() {
+        h = h
+            .wrapping_mul(PRIME_RK)
+            .wrapping_add(s[i] as u32)
+            .wrapping_sub(pow.wrapping_mul(s[i + n] as u32));
+        if h == target && &s[i..i + n] == substr {
+            return Some(i);
+        }
+    }
+    None
+}
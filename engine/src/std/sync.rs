@@ -10,6 +10,8 @@ use crate::ffi::*;
 use futures_lite::future;
 use futures_lite::future::Future;
 use go_vm::types::{GosValue, RuntimeResult, UnsafePtr};
+#[cfg(feature = "race")]
+use go_vm::RaceCtx;
 use std::any::Any;
 use std::cell::{Cell, RefCell};
 use std::pin::Pin;
@@ -35,6 +37,44 @@ macro_rules! create_mutex {
     }};
 }
 
+/// Tells the race detector when a lock is acquired and released by the calling
+/// goroutine, it does nothing without the `race` feature.
+#[derive(Clone)]
+struct RaceHook {
+    #[cfg(feature = "race")]
+    race: Option<RaceCtx>,
+}
+
+impl RaceHook {
+    #[cfg(feature = "race")]
+    fn new(ctx: &FfiCtx) -> RaceHook {
+        RaceHook {
+            race: ctx.race.clone(),
+        }
+    }
+
+    #[cfg(not(feature = "race"))]
+    fn new(_: &FfiCtx) -> RaceHook {
+        RaceHook {}
+    }
+
+    #[allow(unused_variables)]
+    fn acquire<T>(&self, lock: &Rc<T>) {
+        #[cfg(feature = "race")]
+        if let Some(r) = &self.race {
+            r.acquire(Rc::as_ptr(lock) as usize);
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn release<T>(&self, lock: &Rc<T>) {
+        #[cfg(feature = "race")]
+        if let Some(r) = &self.race {
+            r.release(Rc::as_ptr(lock) as usize);
+        }
+    }
+}
+
 #[derive(Ffi)]
 pub struct MutexFfi;
 
@@ -47,15 +87,18 @@ impl MutexFfi {
         // It'd probably be cleaner if we use interface{} instead of pointer as
         // the argument, but let's leave it like this to serve as an example.
         match MutexFfi::create_mutex(&lock, ctx) {
-            Ok(mutex) => Box::pin((|| async move { mutex.lock().await })()),
+            Ok(mutex) => Box::pin(mutex.lock(RaceHook::new(ctx))),
             Err(e) => Box::pin(async move { Err(e) }),
         }
     }
 
-    async fn ffi_async_unlock(lock: GosValue) -> RuntimeResult<Vec<GosValue>> {
+    fn ffi_async_unlock(
+        ctx: &mut FfiCtx,
+        lock: GosValue,
+    ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>>>> {
         let ud = lock.as_unsafe_ptr();
         let mutex = ud.unwrap().downcast_ref::<Mutex>().unwrap().clone();
-        mutex.unlock().await
+        Box::pin(mutex.unlock(RaceHook::new(ctx)))
     }
 
     fn create_mutex(arg: &GosValue, ctx: &mut FfiCtx) -> RuntimeResult<Mutex> {
@@ -81,20 +124,22 @@ impl Mutex {
         }
     }
 
-    async fn lock(self) -> RuntimeResult<Vec<GosValue>> {
+    async fn lock(self, race: RaceHook) -> RuntimeResult<Vec<GosValue>> {
         //dbg!("lock called");
         while self.locked.get() {
             future::yield_now().await;
         }
         self.locked.set(true);
+        race.acquire(&self.locked);
         Ok(vec![])
     }
 
-    async fn unlock(self) -> RuntimeResult<Vec<GosValue>> {
+    async fn unlock(self, race: RaceHook) -> RuntimeResult<Vec<GosValue>> {
         //dbg!("unlock called");
         if !self.locked.get() {
             Err("sync: unlock of unlocked mutex".to_owned().into())
         } else {
+            race.release(&self.locked);
             self.locked.set(false);
             Ok(vec![])
         }
@@ -111,15 +156,18 @@ impl RWMutexFfi {
         lock: GosValue,
     ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>>>> {
         match RWMutexFfi::create_mutex(&lock, ctx) {
-            Ok(m) => Box::pin((|| async move { m.r_lock().await })()),
+            Ok(m) => Box::pin(m.r_lock(RaceHook::new(ctx))),
             Err(e) => Box::pin(async move { Err(e) }),
         }
     }
 
-    async fn ffi_async_r_unlock(lock: GosValue) -> RuntimeResult<Vec<GosValue>> {
+    fn ffi_async_r_unlock(
+        ctx: &mut FfiCtx,
+        lock: GosValue,
+    ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>>>> {
         let ud = lock.as_unsafe_ptr();
         let mutex = ud.unwrap().downcast_ref::<RWMutex>().unwrap().clone();
-        mutex.r_unlock().await
+        Box::pin(mutex.r_unlock(RaceHook::new(ctx)))
     }
 
     fn ffi_async_w_lock(
@@ -127,15 +175,18 @@ impl RWMutexFfi {
         lock: GosValue,
     ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>>>> {
         match RWMutexFfi::create_mutex(&lock, ctx) {
-            Ok(m) => Box::pin((|| async move { m.w_lock().await })()),
+            Ok(m) => Box::pin(m.w_lock(RaceHook::new(ctx))),
             Err(e) => Box::pin(async move { Err(e) }),
         }
     }
 
-    async fn ffi_async_w_unlock(lock: GosValue) -> RuntimeResult<Vec<GosValue>> {
+    fn ffi_async_w_unlock(
+        ctx: &mut FfiCtx,
+        lock: GosValue,
+    ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>>>> {
         let ud = lock.as_unsafe_ptr();
         let mutex = ud.unwrap().downcast_ref::<RWMutex>().unwrap().clone();
-        mutex.w_unlock().await
+        Box::pin(mutex.w_unlock(RaceHook::new(ctx)))
     }
 
     fn create_mutex(arg: &GosValue, ctx: &mut FfiCtx) -> RuntimeResult<RWMutex> {
@@ -211,7 +262,7 @@ impl RWMutex {
         }
     }
 
-    async fn r_lock(self) -> RuntimeResult<Vec<GosValue>> {
+    async fn r_lock(self, race: RaceHook) -> RuntimeResult<Vec<GosValue>> {
         loop {
             let can_read = self.data.borrow().can_read();
             if !can_read {
@@ -221,10 +272,12 @@ impl RWMutex {
             }
         }
         self.data.borrow_mut().inc_reader_num();
+        race.acquire(&self.data);
         Ok(vec![])
     }
 
-    async fn r_unlock(self) -> RuntimeResult<Vec<GosValue>> {
+    async fn r_unlock(self, race: RaceHook) -> RuntimeResult<Vec<GosValue>> {
+        race.release(&self.data);
         let num = self.data.borrow_mut().dec_reader_num();
         if num < 0 {
             Err("sync: unmatched rUnlock call".to_owned().into())
@@ -233,7 +286,7 @@ impl RWMutex {
         }
     }
 
-    async fn w_lock(self) -> RuntimeResult<Vec<GosValue>> {
+    async fn w_lock(self, race: RaceHook) -> RuntimeResult<Vec<GosValue>> {
         self.data.borrow_mut().inc_writer_num();
         loop {
             let can_write = self.data.borrow().can_write();
@@ -246,10 +299,12 @@ impl RWMutex {
         let mut d = self.data.borrow_mut();
         d.dec_writer_num();
         d.set_writer_active(true);
+        race.acquire(&self.data);
         Ok(vec![])
     }
 
-    async fn w_unlock(self) -> RuntimeResult<Vec<GosValue>> {
+    async fn w_unlock(self, race: RaceHook) -> RuntimeResult<Vec<GosValue>> {
+        race.release(&self.data);
         let was_active = self.data.borrow_mut().set_writer_active(false);
         if !was_active {
             Err("sync: unmatched wUnlock call".to_owned().into())
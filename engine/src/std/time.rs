@@ -0,0 +1,164 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+#[cfg(feature = "async")]
+use futures_lite::future::{self, Future};
+use go_vm::types::*;
+#[cfg(feature = "async")]
+use go_vm::Sleep;
+#[cfg(feature = "async")]
+use std::any::Any;
+#[cfg(feature = "async")]
+use std::cell::{Cell, RefCell};
+#[cfg(feature = "async")]
+use std::pin::Pin;
+#[cfg(feature = "async")]
+use std::rc::Rc;
+#[cfg(feature = "async")]
+use std::task::{Poll, Waker};
+#[cfg(feature = "async")]
+use std::time::Duration;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+lazy_static! {
+    // where the monotonic clock of package time starts
+    static ref START: Instant = Instant::now();
+}
+
+fn runtime_nano() -> i64 {
+    START.elapsed().as_nanos() as i64
+}
+
+#[cfg(feature = "async")]
+fn instant_at(nano: i64) -> Instant {
+    *START + Duration::from_nanos(nano.max(0) as u64)
+}
+
+#[derive(Ffi)]
+pub struct TimeFfi;
+
+#[ffi_impl(rename = "time")]
+impl TimeFfi {
+    fn ffi_now() -> (i64, i32, i64) {
+        let (sec, nsec) = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => (d.as_secs() as i64, d.subsec_nanos() as i32),
+            Err(e) => {
+                let d = e.duration();
+                match d.subsec_nanos() {
+                    0 => (-(d.as_secs() as i64), 0),
+                    n => (-(d.as_secs() as i64) - 1, 1_000_000_000 - n as i32),
+                }
+            }
+        };
+        (sec, nsec, runtime_nano())
+    }
+
+    fn ffi_runtime_nano() -> i64 {
+        runtime_nano()
+    }
+}
+
+/// The sleeps and the timers of package time, on the scheduler of the VM
+#[cfg(feature = "async")]
+#[derive(Ffi)]
+pub struct TimerFfi;
+
+#[cfg(feature = "async")]
+#[ffi_impl(rename = "time.timer")]
+impl TimerFfi {
+    fn ffi_async_sleep(
+        ctx: &mut FfiCtx,
+        d: i64,
+    ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>>>> {
+        let sleep = ctx.sleep_until(instant_at(runtime_nano().saturating_add(d)), false);
+        Box::pin(async move {
+            sleep.await;
+            Ok(vec![])
+        })
+    }
+
+    fn ffi_new_timer() -> GosValue {
+        FfiCtx::new_unsafe_ptr(Rc::new(Timer::new()))
+    }
+
+    fn ffi_async_wait(
+        ctx: &mut FfiCtx,
+        t: GosValue,
+        when: i64,
+    ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>>>> {
+        let timer = TimerFfi::timer(&t);
+        // the goroutine waiting for a timer doesn't keep the program running
+        let sleep = ctx.sleep_until(instant_at(when), true);
+        Box::pin(timer.wait(sleep))
+    }
+
+    fn ffi_stop(t: GosValue) -> bool {
+        TimerFfi::timer(&t).stop()
+    }
+
+    fn timer(t: &GosValue) -> Timer {
+        let p = t.as_unsafe_ptr().unwrap();
+        p.downcast_ref::<Timer>().unwrap().clone()
+    }
+}
+
+/// The state of a timer shared by the goroutine running it and the ones stopping it
+#[cfg(feature = "async")]
+#[derive(Clone)]
+struct Timer {
+    // started and not fired or stopped yet
+    active: Rc<Cell<bool>>,
+    stopped: Rc<Cell<bool>>,
+    waker: Rc<RefCell<Option<Waker>>>,
+}
+
+#[cfg(feature = "async")]
+impl UnsafePtr for Timer {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(feature = "async")]
+impl Timer {
+    fn new() -> Timer {
+        Timer {
+            active: Rc::new(Cell::new(true)),
+            stopped: Rc::new(Cell::new(false)),
+            waker: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    /// Waits for the timer to fire, returns false if it's stopped first
+    async fn wait(self, mut sleep: Sleep) -> RuntimeResult<Vec<GosValue>> {
+        self.active.set(!self.stopped.get());
+        let fired = future::poll_fn(|cx| {
+            if self.stopped.get() {
+                return Poll::Ready(false);
+            }
+            match Pin::new(&mut sleep).poll(cx) {
+                Poll::Ready(()) => Poll::Ready(true),
+                Poll::Pending => {
+                    *self.waker.borrow_mut() = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await;
+        self.active.set(false);
+        self.waker.borrow_mut().take();
+        Ok(vec![fired.into()])
+    }
+
+    /// Stops the timer, returns whether it was active
+    fn stop(&self) -> bool {
+        self.stopped.set(true);
+        if let Some(w) = self.waker.borrow_mut().take() {
+            w.wake();
+        }
+        self.active.replace(false)
+    }
+}
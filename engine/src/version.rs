@@ -0,0 +1,17 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+// The rule for which std library versions work with an engine version, shared with
+// build.rs, which `include!`s this file to check the std it embeds.
+
+/// Versions are compatible as semver has it: the major versions match, and for
+/// 0.x versions, where a minor version may break things, the minor versions too.
+pub(crate) fn compatible(a: &str, b: &str) -> bool {
+    let parts = |v: &str| -> Vec<String> { v.split('.').take(2).map(str::to_owned).collect() };
+    let (a, b) = (parts(a), parts(b));
+    match a.first().map(String::as_str) {
+        Some("0") => a == b,
+        _ => a.first() == b.first(),
+    }
+}
@@ -27,11 +27,18 @@ impl VirtualFs for VfsMap {
     }
 
     fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
-        let result: Vec<PathBuf> = self
+        // only the direct children, files in sub directories show up as the sub directory
+        let mut result: Vec<PathBuf> = self
             .map
-            .iter()
-            .filter_map(|(p, _)| p.starts_with(path).then(|| p.to_path_buf()))
+            .keys()
+            .filter_map(|p| {
+                let rel = p.strip_prefix(path).ok()?;
+                let first = rel.components().next()?;
+                Some(path.join(first))
+            })
             .collect();
+        result.sort();
+        result.dedup();
         if result.is_empty() {
             Err(io::Error::from(io::ErrorKind::NotFound))
         } else {
@@ -0,0 +1,90 @@
+package stack
+
+import "testing"
+
+var cleaned []string
+
+func TestPushPop(t *testing.T) {
+	var s Stack
+	s.Push(1)
+	s.Push(2)
+	if v := s.Pop(); v != 2 {
+		t.Errorf("Pop() = %d, want 2", v)
+	}
+	if s.Len() != 1 {
+		t.Fatal("wrong length", s.Len())
+	}
+}
+
+func TestFail(t *testing.T) {
+	t.Cleanup(func() { cleaned = append(cleaned, "first") })
+	t.Cleanup(func() { cleaned = append(cleaned, "second") })
+	t.Log("logged")
+	t.Errorf("got %d", 1)
+	t.Fatalf("stopped")
+	t.Error("not reached")
+}
+
+func TestSub(t *testing.T) {
+	for _, n := range []int{1, 2, 3} {
+		t.Run("push "+string(rune('0'+n)), func(t *testing.T) {
+			var s Stack
+			for i := 0; i < n; i++ {
+				s.Push(i)
+			}
+			if s.Len() == 2 {
+				t.Error("two")
+			}
+		})
+	}
+}
+
+func TestPanic(t *testing.T) {
+	var s Stack
+	s.Pop()
+}
+
+func TestSkip(t *testing.T) {
+	if len(cleaned) != 2 || cleaned[0] != "second" {
+		t.Fatal("cleanups", cleaned)
+	}
+	t.Skip("skipped")
+	t.Error("not reached")
+}
+
+func BenchmarkPush(b *testing.B) {
+	var s Stack
+	b.ResetTimer()
+	for i := 0; i < b.N; i++ {
+		s.Push(i)
+	}
+	if s.Len() != b.N {
+		b.Fatal("wrong length", s.Len())
+	}
+}
+
+func BenchmarkClosure(b *testing.B) {
+	for i := 0; i < b.N; i++ {
+		f := func() int { return i }
+		f()
+	}
+}
+
+func BenchmarkFail(b *testing.B) {
+	b.Fatal("failed at", b.N)
+}
+
+func BenchmarkSkip(b *testing.B) {
+	b.Skip("skipped")
+}
+
+// not tests
+func Testlower(t *testing.T) { t.Fail() }
+
+func TestArgs(t *testing.T, n int) { t.Fail() }
+
+func helper(t *testing.T) { t.Fail() }
+
+func Benchmarklower(b *testing.B) { b.Fail() }
+
+func BenchmarkT(t *testing.T) { t.Fail() }
@@ -39,9 +39,32 @@ impl Write for WriteBuf {
     }
 }
 
+// Fails the test on the failed asserts of the scripts, the recovered ones too
+fn assert_trap() -> engine::AssertHook {
+    std::sync::Arc::new(|info: &engine::AssertInfo| {
+        panic!(
+            "assertion failed at {} in goroutine {}",
+            info.position.as_deref().unwrap_or("unknown position"),
+            info.goroutine
+        )
+    })
+}
+
+fn config() -> engine::Config {
+    let mut cfg = engine::Config::default();
+    cfg.assert_hook = Some(assert_trap());
+    cfg
+}
+
+fn new_engine() -> engine::Engine {
+    let mut engine = engine::Engine::new();
+    engine.set_assert_hook(assert_trap());
+    engine
+}
+
 #[cfg(feature = "go_std")]
 fn run(path: &str, trace: bool) -> Result<(), engine::ErrorList> {
-    let mut cfg = engine::Config::default();
+    let mut cfg = config();
     cfg.trace_parser = trace;
     cfg.trace_checker = trace;
     let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
@@ -62,7 +85,7 @@ fn run(_path: &str, _trace: bool) -> Result<(), engine::ErrorList> {
 fn run_zip(zip: &str, path: &str, trace: bool) -> Result<(), engine::ErrorList> {
     let zip = fs::read(Path::new(zip)).unwrap();
 
-    let mut cfg = engine::Config::default();
+    let mut cfg = config();
     cfg.trace_parser = trace;
     cfg.trace_checker = trace;
     let sr = engine::SourceReader::zip_lib_and_local_fs(
@@ -85,7 +108,7 @@ fn run_zip(_zip: &str, _path: &str, _trace: bool) -> Result<(), engine::ErrorLis
 
 #[cfg(feature = "go_std")]
 fn run_string(source: Cow<'static, str>, trace: bool) -> Result<(), engine::ErrorList> {
-    let mut cfg = engine::Config::default();
+    let mut cfg = config();
     cfg.trace_parser = trace;
     cfg.trace_checker = trace;
     let (sr, path) = engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), source);
@@ -110,7 +133,7 @@ fn run_zip_and_string(
 ) -> Result<(), engine::ErrorList> {
     let zip = fs::read(Path::new(file)).unwrap();
 
-    let mut cfg = engine::Config::default();
+    let mut cfg = config();
     cfg.trace_parser = trace;
     cfg.trace_checker = trace;
     let (sr, path) = engine::SourceReader::zip_lib_and_string(
@@ -165,6 +188,436 @@ fn test_zip_g2case0() {
     assert!(result.is_ok());
 }
 
+#[test]
+#[cfg(feature = "go_std")]
+fn test_g2build_tags() {
+    let mut cfg = config();
+    cfg.build_tags = vec!["host".to_owned()];
+    cfg.constants = vec![
+        ("debug".to_owned(), engine::ConstValue::with_bool(true)),
+        (
+            "version".to_owned(),
+            engine::ConstValue::with_str("1.2.3".to_owned()),
+        ),
+        ("maxItems".to_owned(), engine::ConstValue::with_i64(100)),
+    ];
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let result = engine::run(cfg, &sr, Path::new("./tests/group2/build_tags"));
+    if let Err(el) = &result {
+        el.sort();
+        eprint!("{}", el);
+    }
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_g2target() {
+    let mut cfg = config();
+    cfg.target = Some(("windows".to_owned(), "arm64".to_owned()));
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let result = engine::run(cfg, &sr, Path::new("./tests/group2/goos_target"));
+    if let Err(el) = &result {
+        el.sort();
+        eprint!("{}", el);
+    }
+    assert!(result.is_ok());
+}
+
+/// A target without files of its own in the std library is what runtime reports
+#[test]
+#[cfg(feature = "go_std")]
+fn test_g2target_runtime() {
+    let source = "package main\n\nimport \"runtime\"\n\nfunc main() {\n\tassert(runtime.GOOS == \"illumos\")\n\tassert(runtime.GOARCH == \"s390x\")\n}\n";
+    let mut cfg = config();
+    cfg.target = Some(("illumos".to_owned(), "s390x".to_owned()));
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let result = engine::run(cfg, &sr, &path);
+    if let Err(el) = &result {
+        eprint!("{}", el);
+    }
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(all(feature = "embed_std", feature = "go_std"))]
+fn test_embedded_std() {
+    let sr = engine::SourceReader::with_embedded_std(PathBuf::from("./"));
+    let result = engine::run(config(), &sr, Path::new("./tests/group2/case0.gos"));
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_std_version_mismatch() {
+    let run_with = |version: &'static str| {
+        let source = "package main\n\nfunc main() {}\n";
+        let files = go_parser::Map::from([
+            (PathBuf::from("std/VERSION"), Cow::Borrowed(version)),
+            (PathBuf::from("./main.gos"), Cow::Borrowed(source)),
+        ]);
+        let sr = engine::SourceReader::new(
+            Some(PathBuf::from("std/")),
+            PathBuf::from("./"),
+            Box::new(engine::VfsMap::new(files)),
+        );
+        engine::run(config(), &sr, Path::new("./main.gos"))
+    };
+    let el = run_with("99.0.0").unwrap_err();
+    assert!(format!("{}", el).contains("std library version 99.0.0"));
+    // the engine is 0.1.x, a minor version of 0.x may break things, a patch doesn't
+    let el = run_with("0.2.0").unwrap_err();
+    assert!(format!("{}", el).contains("std library version 0.2.0"));
+    assert!(run_with("0.1.99\n").is_ok());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_run_captured() {
+    let source = r#"
+    package main
+    import (
+        "fmt"
+        "os"
+    )
+    func main() {
+        fmt.Println("out", 1)
+        fmt.Fprintf(os.Stderr, "err %d\n", 2)
+        fmt.Print("out again")
+    }
+    "#;
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let (result, out) = engine::run_captured(config(), &sr, &path);
+    assert!(result.is_ok());
+    assert_eq!(String::from_utf8(out).unwrap(), "out 1\nerr 2\nout again");
+
+    // the output of a later run on the thread isn't captured
+    let (sr, path) = engine::SourceReader::fs_lib_and_string(
+        PathBuf::from("../std/"),
+        Cow::Borrowed("package main\n\nfunc main() {}\n"),
+    );
+    let (result, out) = engine::run_captured(config(), &sr, &path);
+    assert!(result.is_ok());
+    assert!(out.is_empty());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_rewrite_and_run() {
+    use go_parser::ast::{Decl, Expr, Stmt};
+    use go_parser::Token;
+
+    let source = "package main\n\nimport \"fmt\"\n\n// prints a sum\nfunc main() {\n\tfmt.Println(1 + 2) // the sum\n}\n";
+    let mut fs = go_parser::FileSet::new();
+    let o = &mut go_parser::AstObjects::new();
+    let el = &mut go_parser::ErrorList::new();
+    let (p, file) = go_parser::parse_file(o, &mut fs, el, "main.gos", source, false);
+    assert_eq!(p.get_errors().len(), 0);
+    let main_decl = file.unwrap().decls[1].clone();
+    let main = match &main_decl {
+        Decl::Func(f) => o.fdecls[*f].body.clone().unwrap(),
+        _ => unreachable!(),
+    };
+    let arg = match &main.list[0] {
+        Stmt::Expr(e) => match &**e {
+            Expr::Call(call) => call.args[0].clone(),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    };
+
+    // println(double(1 + 2), "done") in place of the sum, built as an AST
+    let double = Expr::new_ident(o, 0, "double");
+    let doubled = Expr::new_call(double, vec![arg.clone()]);
+    let done = Expr::new_basic_lit(0, Token::string_lit("done"));
+    let text = go_parser::printer::print_expr(o, &doubled);
+    let mut rw = go_parser::rewrite::Rewriter::new(fs.recent_file().unwrap(), source);
+    rw.replace(
+        o,
+        &arg,
+        &format!("{}, {}", text, go_parser::printer::print_expr(o, &done)),
+    );
+    rw.insert_after(
+        o,
+        &main_decl,
+        "\n\nfunc double(n int) int {\n\treturn n * 2\n}",
+    );
+    let source = rw.finish();
+    assert!(source.contains("fmt.Println(double(1 + 2), \"done\") // the sum"));
+
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Owned(source));
+    let (result, out) = engine::run_captured(config(), &sr, &path);
+    assert!(result.is_ok());
+    assert_eq!(String::from_utf8(out).unwrap(), "6 done\n");
+}
+
+#[cfg(feature = "go_std")]
+mod greet_plugin {
+    extern crate go_engine;
+    use go_engine::ffi::*;
+    use std::borrow::Cow;
+    use std::path::PathBuf;
+
+    #[derive(Ffi)]
+    pub struct GreetFfi;
+
+    #[ffi_impl(rename = "greet.native")]
+    impl GreetFfi {
+        fn ffi_hello(name: String) -> String {
+            format!("hello, {}", name)
+        }
+    }
+
+    const GREET_SRC: &str = r#"
+    package greet
+
+    type ffiGreet interface {
+        hello(name string) string
+    }
+
+    var native = ffi(ffiGreet, "greet.native")
+
+    func Hello(name string) string {
+        return native.hello(name)
+    }
+    "#;
+
+    pub struct GreetPlugin;
+
+    impl go_engine::EnginePlugin for GreetPlugin {
+        fn name(&self) -> &'static str {
+            "greet"
+        }
+
+        fn register_ffi(&self, factory: &mut FfiFactory) {
+            GreetFfi::register(factory);
+        }
+
+        fn lib_fs(&self) -> Option<Box<dyn go_engine::VirtualFs>> {
+            Some(Box::new(go_engine::VfsMap::new(go_parser::Map::from([(
+                PathBuf::from("greet/greet.gos"),
+                Cow::Borrowed(GREET_SRC),
+            )]))))
+        }
+
+        fn configure(&self, engine: &mut go_engine::Engine) {
+            engine.define_const("greetEnabled", go_engine::ConstValue::with_bool(true));
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_plugin() {
+    let source = r#"
+    package main
+
+    import "greet"
+
+    func main() {
+        assert(greetEnabled)
+        assert(greet.Hello("plugin") == "hello, plugin")
+    }
+    "#;
+    let mut engine = new_engine();
+    engine.add_plugin(greet_plugin::GreetPlugin);
+    assert_eq!(engine.plugins(), &["greet"]);
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let result = engine.run_source(false, false, &sr, &path);
+    if let Err(el) = &result {
+        eprint!("{}", el);
+    }
+    assert!(result.is_ok());
+}
+
+#[cfg(all(feature = "go_std", feature = "async"))]
+mod wait_ffi {
+    extern crate go_engine;
+    use go_engine::ffi::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::task::{Poll, Waker};
+    use std::thread;
+    use std::time::Duration;
+
+    pub static FINISHED: AtomicBool = AtomicBool::new(false);
+
+    #[derive(Ffi)]
+    pub struct WaitFfi;
+
+    #[ffi_impl(rename = "wait.native")]
+    impl WaitFfi {
+        /// Completes on another thread after `ms` milliseconds
+        async fn ffi_async_wait(ms: isize) -> RuntimeResult<Vec<GosValue>> {
+            let state: Arc<Mutex<(bool, Option<Waker>)>> = Arc::new(Mutex::new((false, None)));
+            let state2 = state.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(ms as u64));
+                let mut s = state2.lock().unwrap();
+                s.0 = true;
+                if let Some(w) = s.1.take() {
+                    w.wake();
+                }
+            });
+            futures_lite::future::poll_fn(|cx| {
+                let mut s = state.lock().unwrap();
+                match s.0 {
+                    true => Poll::Ready(()),
+                    false => {
+                        s.1 = Some(cx.waker().clone());
+                        Poll::Pending
+                    }
+                }
+            })
+            .await;
+            Ok(vec![ms.into()])
+        }
+
+        fn ffi_finish() {
+            FINISHED.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+#[test]
+#[cfg(all(feature = "go_std", feature = "async"))]
+fn test_async_ffi_pending() {
+    let source = r#"
+    package main
+
+    type ffiWait interface {
+        async_wait(ms int) int
+        finish()
+    }
+
+    var native = ffi(ffiWait, "wait.native")
+
+    func main() {
+        done := make(chan int)
+        go func() {
+            done <- native.async_wait(20)
+        }()
+        // every goroutine is parked while the FFI call is in flight
+        assert(<-done == 20)
+        assert(native.async_wait(5) == 5)
+        native.finish()
+    }
+    "#;
+    let mut engine = engine::Engine::new();
+    engine.register_extension(
+        wait_ffi::WaitFfi::auto_gen_ffi_id(),
+        wait_ffi::WaitFfi::auto_gen_ffi_new(),
+    );
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let result = engine.run_source(false, false, &sr, &path);
+    if let Err(el) = &result {
+        eprint!("{}", el);
+    }
+    assert!(result.is_ok());
+    assert!(wait_ffi::FINISHED.load(std::sync::atomic::Ordering::SeqCst));
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_iface_field() {
+    let mut engine = new_engine();
+    engine.add_plugin(greet_plugin::GreetPlugin);
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let result = engine.run_source(
+        false,
+        false,
+        &sr,
+        Path::new("./tests/group1/iface_field.gos"),
+    );
+    if let Err(el) = &result {
+        eprint!("{}", el);
+    }
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_embed_iface() {
+    let result = run("./tests/group1/embed_iface.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_pkg_func_lit() {
+    let result = run("./tests/group1/pkg_func_lit.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_iota() {
+    let result = run("./tests/group1/iota.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_iota_errors() {
+    let bad = [
+        (
+            "const (\n\ta uint8 = 1 << (iota + 6)\n\tb\n\tc\n)",
+            "6:2  1 << (iota + 6) (untyped int constant 256) overflows uint8",
+        ),
+        (
+            "const (\n\ta, b int8 = -65 * iota, iota\n\tc, d\n\te, f\n)",
+            "6:2  -65 * iota (untyped int constant -130) overflows int8",
+        ),
+        (
+            "var v = iota",
+            "cannot use iota outside constant declaration",
+        ),
+        ("const (\n\ta = iota\n\tb, c\n)", "missing init expr for c"),
+    ];
+    for (decl, msg) in bad {
+        let source = format!("package main\n\n{}\n\nfunc main() {{\n}}\n", decl);
+        let el = run_string(Cow::Owned(source), false).unwrap_err();
+        assert!(el.to_string().contains(msg), "{}: {}", decl, el);
+    }
+}
+
+#[test]
+fn test_method_sets() {
+    let result = run("./tests/group1/method_sets.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_method_sets_errors() {
+    let bad = [
+        (
+            "var i I = t\n\t_ = i",
+            "13:12  cannot use t (variable of type ./temp_file.gos.T) as ./temp_file.gos.I value in variable declaration: method Inc has pointer receiver",
+        ),
+        (
+            "m := map[int]T{}\n\tm[1].Inc()",
+            "14:7  cannot call pointer method Inc on ./temp_file.gos.T",
+        ),
+        ("mk().Inc()", "13:7  cannot call pointer method Inc on"),
+        ("T{}.Inc()", "13:6  cannot call pointer method Inc on"),
+        (
+            "var i I = &t\n\t_ = i.(T)",
+            "14:6  i (variable of type ./temp_file.gos.I) cannot have dynamic type ./temp_file.gos.T (method Inc has pointer receiver)",
+        ),
+    ];
+    for (stmt, msg) in bad {
+        let source = format!(
+            "package main\n\ntype T struct{{ n int }}\n\nfunc (t *T) Inc() {{ t.n++ }}\n\ntype I interface{{ Inc() }}\n\nfunc mk() T {{ return T{{}} }}\n\nfunc main() {{\n\tvar t T\n\t{}\n\t_ = t\n}}\n",
+            stmt
+        );
+        let el = run_string(Cow::Owned(source), false).unwrap_err();
+        assert!(el.to_string().contains(msg), "{}: {}", stmt, el);
+    }
+}
+
 #[test]
 fn test_g2case0() {
     let result = run("./tests/group2/case0.gos", false);
@@ -183,6 +636,20 @@ fn test_g2case2() {
     assert!(result.is_ok());
 }
 
+#[test]
+#[cfg(feature = "race")]
+fn test_g2race() {
+    let result = run("./tests/group2/race.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "leak_track")]
+fn test_g2leak() {
+    let result = run("./tests/group2/leak.gos", false);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_g2case3() {
     let result = run("./tests/group2/case3.gos", true);
@@ -221,6 +688,19 @@ fn test_sync_rwmutex() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_sync_waitgroup() {
+    let result = run("./tests/group2/sync_waitgroup.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_time() {
+    let result = run("./tests/group2/time.gos", true);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_g1case1() {
     let result = run("./tests/group1/case1.gos", true);
@@ -251,12 +731,24 @@ fn test_closure4() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_goroutine_closure() {
+    let result = run("./tests/group1/goroutine_closure.gos", true);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_func1() {
     let result = run("./tests/group1/func1.gos", true);
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_return_call() {
+    let result = run("./tests/group1/return_call.gos", true);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_blankid() {
     let result = run("./tests/group1/blankid.gos", true);
@@ -275,12 +767,42 @@ fn test_slice1() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_slice_offset() {
+    let result = run("./tests/group1/slice_offset.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_make_slice() {
+    let result = run("./tests/group1/make_slice.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_builtin() {
+    let result = run("./tests/group1/builtin.gos", true);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_map1() {
     let result = run("./tests/group1/map1.gos", true);
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_comma_ok() {
+    let result = run("./tests/group1/comma_ok.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_map_receiver() {
+    let result = run("./tests/group1/map_receiver.gos", true);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_pkg() {
     let result = run("./tests/group1/pkg.gos", true);
@@ -293,12 +815,24 @@ fn test_type1() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_tuple_assign() {
+    let result = run("./tests/group1/tuple_assign.gos", true);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_pointer() {
     let result = run("./tests/group1/pointer.gos", true);
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_escape() {
+    let result = run("./tests/group1/escape.gos", true);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_operations() {
     let result = run("./tests/group1/operations.gos", true);
@@ -317,6 +851,12 @@ fn test_for() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_range_nil() {
+    let result = run("./tests/group1/range_nil.gos", true);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_interface1() {
     let result = run("./tests/group1/interface1.gos", true);
@@ -330,74 +870,1200 @@ fn test_interface2() {
 }
 
 #[test]
-fn test_initorder() {
-    let result = run("./tests/group1/initorder.gos", true);
+fn test_iface_convert() {
+    let result = run("./tests/group1/iface_convert.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_switch() {
-    let result = run("./tests/group1/switch.gos", true);
+fn test_const_eval() {
+    let result = run("./tests/group1/const_eval.gos", false);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_typeswitch() {
-    let result = run("./tests/group1/typeswitch.gos", false);
+fn test_iface_conv() {
+    let result = run("./tests/group1/iface_conv.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_basictypes() {
-    let result = run("./tests/group1/basictypes.gos", true);
+fn test_assert_error() {
+    let result = run("./tests/group1/assert_error.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_composite() {
-    let result = run("./tests/group1/composite.gos", true);
+fn test_type_string() {
+    let result = run("./tests/group1/type_string.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_array() {
-    let result = run("./tests/group1/array.gos", true);
+fn test_chan_dir() {
+    let result = run("./tests/group1/chan_dir.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_conversion() {
-    let result = run("./tests/group1/conversion.gos", true);
+fn test_static_dispatch() {
+    let result = run("./tests/group1/static_dispatch.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_async() {
-    let result = run("./tests/group1/async.gos", true);
+fn test_initorder() {
+    let result = run("./tests/group1/initorder.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_channel() {
-    let result = run("./tests/group1/channel.gos", true);
+fn test_switch() {
+    let result = run("./tests/group1/switch.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_defer() {
-    let result = run("./tests/group1/defer.gos", true);
+fn test_typeswitch() {
+    let result = run("./tests/group1/typeswitch.gos", false);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_goto() {
-    let result = run("./tests/group1/goto.gos", true);
+fn test_basictypes() {
+    let result = run("./tests/group1/basictypes.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_recover() {
-    let result = run("./tests/group1/recover.gos", true);
+fn test_composite() {
+    let result = run("./tests/group1/composite.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_composite_elem() {
+    let result = run("./tests/group1/composite_elem.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_array() {
+    let result = run("./tests/group1/array.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_conversion() {
+    let result = run("./tests/group1/conversion.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_make() {
+    let result = run("./tests/group1/make.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_make_const_size() {
+    let bad = [
+        ("make([]int, -1)", "must not be negative"),
+        ("make([]int, 1, -1)", "must not be negative"),
+        ("make([]int, 1<<62)", "len argument too large in make"),
+        ("make([]string, 1, 1<<60)", "cap argument too large in make"),
+    ];
+    for (expr, msg) in bad {
+        let source = format!("package main\n\nfunc main() {{\n\t_ = {}\n}}\n", expr);
+        let el = run_string(Cow::Owned(source), false).unwrap_err();
+        assert!(el.to_string().contains(msg), "{}: {}", expr, el);
+    }
+}
+
+#[test]
+fn test_conversion_overflow() {
+    let result = run("./tests/group1/conversion_overflow.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_conversion_const_overflow() {
+    let bad = [
+        ("int8(300)", "constant 300 overflows int8"),
+        ("uint(-1)", "constant -1 overflows uint"),
+        ("int(1e300)", "cannot convert"),
+        ("uint8(-1.0)", "cannot convert"),
+        ("int(2.5)", "cannot convert"),
+        ("float32(1e300)", "cannot convert"),
+    ];
+    for (expr, msg) in bad {
+        let source = format!("package main\n\nfunc main() {{\n\t_ = {}\n}}\n", expr);
+        let el = run_string(Cow::Owned(source), false).unwrap_err();
+        assert!(el.to_string().contains(msg), "{}: {}", expr, el);
+    }
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_const_overflow() {
+    let bad = [
+        (
+            "_ = x << -1",
+            "5:11  invalid operation: negative shift count -1",
+        ),
+        ("_ = x >> -1.0", "negative shift count"),
+        ("const c = 1 << 600", "5:14  constant shift overflow"),
+        ("const c = 1 << 10000", "invalid shift count"),
+        (
+            "const c = 1 << 500\n\t_ = c * c",
+            "6:8  constant multiplication overflow",
+        ),
+        ("const c = uint8(1) << 8", "overflows uint8"),
+        ("type F uint8\n\tconst f = F(1) << 8", "overflows uint8"),
+        ("type F uint8\n\t_ = F(300)", "constant 300 overflows"),
+    ];
+    for (stmt, msg) in bad {
+        let source = format!(
+            "package main\n\nfunc main() {{\n\tx := 1\n\t{}\n\t_ = x\n}}\n",
+            stmt
+        );
+        let el = run_string(Cow::Owned(source), false).unwrap_err();
+        assert!(el.to_string().contains(msg), "{}: {}", stmt, el);
+    }
+}
+
+#[test]
+fn test_big_const() {
+    let result = run("./tests/group1/big_const.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_big_const_overflow() {
+    let bad = [
+        (
+            "var f float64 = 1e400",
+            "1e400 (untyped float constant 1e+400) overflows float64",
+        ),
+        ("var f float32 = 1e100", "overflows float32"),
+        (
+            "var f = -1e400 / 1e2",
+            "(untyped float constant -1e+398) overflows float64",
+        ),
+        ("var f int64 = 1 << 100", "overflows int64"),
+        ("var f complex128 = 1e400i", "overflows complex128"),
+    ];
+    for (stmt, msg) in bad {
+        let source = format!("package main\n\nfunc main() {{\n\t{}\n\t_ = f\n}}\n", stmt);
+        let el = run_string(Cow::Owned(source), false).unwrap_err();
+        assert!(el.to_string().contains(msg), "{}: {}", stmt, el);
+    }
+}
+
+#[test]
+fn test_string_compare() {
+    let result = run("./tests/group1/string_compare.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_string_concat() {
+    let result = run("./tests/group1/string_concat.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_invalid_utf8() {
+    let result = run("./tests/group1/invalid_utf8.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_named_arith() {
+    let result = run("./tests/group1/named_arith.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_op_assign() {
+    let result = run("./tests/group1/op_assign.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_incdec() {
+    let result = run("./tests/group1/incdec.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_async() {
+    let result = run("./tests/group1/async.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_stack() {
+    let result = run("./tests/group1/stack.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_stack_size() {
+    // stacks of one slot grow on the first call
+    let mut cfg = config();
+    cfg.stack_size = engine::StackSize {
+        main: 1,
+        goroutine: 1,
+    };
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let result = engine::run(cfg, &sr, Path::new("./tests/group1/stack.gos"));
+    if let Err(el) = &result {
+        el.sort();
+        eprint!("{}", el);
+    }
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_pause_resume() {
+    let ctl = engine::VmController::new();
+    // paused at the first safepoint
+    ctl.pause();
+    let mut cfg = config();
+    cfg.controller = Some(ctl.clone());
+    let vm = std::thread::spawn(move || {
+        let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+        engine::run(cfg, &sr, Path::new("./tests/group1/pause.gos")).is_ok()
+    });
+
+    let info = ctl.wait_paused().unwrap();
+    assert!(ctl.is_running());
+    assert_eq!(info.goroutine, 0);
+    // sum, main, and the entry function calling main
+    let stack = &info.call_stack;
+    assert_eq!(stack.len(), 3, "{:?}", stack);
+    assert!(stack[0].as_ref().unwrap().contains("pause.gos:"));
+    assert!(stack[1].as_ref().unwrap().contains("pause.gos:14"));
+    assert_eq!(info.functions, ["main.sum", "main.main", "runtime.main"]);
+    let names: Vec<&str> = info.locals[0].iter().map(|(n, _)| n.as_str()).collect();
+    assert_eq!(names, ["n", "total", "i"]);
+    assert_eq!(info.locals[0][0].1, "100000");
+    assert!(ctl.paused().is_some());
+
+    ctl.resume();
+    assert!(vm.join().unwrap());
+    assert!(ctl.paused().is_none());
+    assert!(!ctl.is_running());
+    assert!(ctl.wait_paused().is_none());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_cancel() {
+    let source = r#"
+package main
+
+import "os"
+
+func mark(name string) {
+	f, _ := os.Create(outDir + "/" + name)
+	f.Write([]byte(name))
+}
+
+func spin() {
+	n := 0
+	for {
+		n++
+	}
+}
+
+func main() {
+	defer mark("main")
+	defer func() {
+		// a cancel can't be recovered from
+		if recover() == nil {
+			mark("not_recovered")
+		}
+	}()
+	if !forever {
+		return
+	}
+	go spin()
+	spin()
+}
+"#;
+    let dir = std::env::temp_dir().join("goscript_cancel_test");
+    let run = |ctl: &engine::VmController, forever: bool| {
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut cfg = config();
+        cfg.controller = Some(ctl.clone());
+        cfg.constants = vec![
+            (
+                "outDir".to_owned(),
+                engine::ConstValue::with_str(dir.to_string_lossy().to_string()),
+            ),
+            ("forever".to_owned(), engine::ConstValue::with_bool(forever)),
+        ];
+        std::thread::spawn(move || {
+            let (sr, path) = engine::SourceReader::fs_lib_and_string(
+                PathBuf::from("../std/"),
+                Cow::Borrowed(source),
+            );
+            engine::run(cfg, &sr, &path).map_err(|e| e.to_string())
+        })
+    };
+    let marked = |f: &str| dir.join(f).exists();
+
+    let ctl = engine::VmController::new();
+    let handle = ctl.cancel_handle();
+    let vm = run(&ctl, true);
+    while !ctl.is_running() {
+        std::thread::yield_now();
+    }
+    handle.cancel();
+    let msg = vm.join().unwrap().unwrap_err();
+    assert!(
+        msg.contains("run aborted: cancelled by the host"),
+        "{}",
+        msg
+    );
+    assert!(!handle.is_cancelled());
+    // the deferred calls ran
+    assert!(marked("main") && marked("not_recovered"));
+
+    // a paused run is cancelled too
+    let ctl = engine::VmController::new();
+    ctl.pause();
+    let vm = run(&ctl, true);
+    assert!(ctl.wait_paused().is_some());
+    ctl.cancel_handle().cancel();
+    assert!(vm
+        .join()
+        .unwrap()
+        .unwrap_err()
+        .contains("cancelled by the host"));
+    assert!(ctl.paused().is_none());
+    assert!(marked("main"));
+
+    // a cancel before the run doesn't stop it
+    let ctl = engine::VmController::new();
+    ctl.cancel_handle().cancel();
+    assert!(run(&ctl, false).join().unwrap().is_ok());
+    assert!(marked("main"));
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_shutdown() {
+    use std::time::Duration;
+    let source = r#"
+package main
+
+import "os"
+
+func mark(name string) {
+	f, _ := os.Create(outDir + "/" + name)
+	f.Write([]byte(name))
+}
+
+func spin() {
+	n := 0
+	for {
+		n++
+	}
+}
+
+func worker() {
+	defer mark("worker")
+	spin()
+}
+
+func main() {
+	defer mark("main")
+	defer func() {
+		// a shutdown can't be recovered from
+		if recover() == nil {
+			mark("not_recovered")
+		}
+	}()
+	if stubborn {
+		defer spin()
+	}
+	go worker()
+	func() {
+		defer mark("nested")
+		spin()
+	}()
+}
+"#;
+    let run_shutdown = |name: &str, stubborn: bool, grace: Duration| {
+        let dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut cfg = config();
+        let ctl = engine::VmController::new();
+        cfg.controller = Some(ctl.clone());
+        cfg.constants = vec![
+            (
+                "outDir".to_owned(),
+                engine::ConstValue::with_str(dir.to_string_lossy().to_string()),
+            ),
+            (
+                "stubborn".to_owned(),
+                engine::ConstValue::with_bool(stubborn),
+            ),
+        ];
+        let vm = std::thread::spawn(move || {
+            let (sr, path) = engine::SourceReader::fs_lib_and_string(
+                PathBuf::from("../std/"),
+                Cow::Borrowed(source),
+            );
+            engine::run(cfg, &sr, &path).unwrap_err().to_string()
+        });
+        while !ctl.is_running() {
+            std::thread::yield_now();
+        }
+        std::thread::sleep(Duration::from_millis(50));
+        ctl.shutdown(grace);
+        let msg = vm.join().unwrap();
+        let marked = |f: &str| dir.join(f).exists();
+        (
+            msg,
+            marked("main"),
+            marked("nested"),
+            marked("worker"),
+            marked("not_recovered"),
+        )
+    };
+
+    let (msg, main, nested, worker, not_recovered) =
+        run_shutdown("goscript_shutdown_test", false, Duration::from_secs(5));
+    assert!(
+        msg.contains("run aborted: shut down by the host"),
+        "{}",
+        msg
+    );
+    assert!(!msg.contains("grace period"), "{}", msg);
+    assert!(main && nested && worker && not_recovered);
+
+    // the deferred calls that don't end are stopped with the run
+    let (msg, main, nested, _, _) =
+        run_shutdown("goscript_shutdown_test2", true, Duration::from_millis(100));
+    assert!(msg.contains("the grace period ran out"), "{}", msg);
+    assert!(nested && !main);
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_panic_hook() {
+    let panics = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let p = panics.clone();
+    let mut cfg = config();
+    cfg.panic_hook = Some(std::sync::Arc::new(move |info: &engine::PanicInfo| {
+        p.lock().unwrap().push((
+            info.goroutine,
+            info.message.clone(),
+            info.call_stack.clone(),
+            info.functions.clone(),
+        ));
+    }));
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let result = engine::run(cfg, &sr, Path::new("./tests/group1/panic_hook.gos"));
+    assert!(result.is_ok());
+
+    let panics = panics.lock().unwrap();
+    assert_eq!(panics.len(), 2);
+    let (goroutine, msg, stack, funcs) = &panics[0];
+    assert_eq!(*goroutine, 1);
+    assert!(!msg.is_empty());
+    assert!(stack[0].as_ref().unwrap().contains("panic_hook.gos:14"));
+    assert_eq!(funcs[0], "main.main.func1");
+    let (goroutine, msg, stack, funcs) = &panics[1];
+    assert_eq!(*goroutine, 0);
+    assert_eq!(msg, "boom");
+    // fail, main, and the entry function calling main
+    assert_eq!(stack.len(), 3, "{:?}", stack);
+    assert!(stack[0].as_ref().unwrap().contains("panic_hook.gos:4"));
+    assert!(stack[1].as_ref().unwrap().contains("panic_hook.gos:17"));
+    assert_eq!(funcs, &["main.fail", "main.main", "runtime.main"]);
+}
+
+#[test]
+#[cfg(all(feature = "go_std", feature = "async"))]
+fn test_assert_hook() {
+    let asserts = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let a = asserts.clone();
+    let mut cfg = engine::Config::default();
+    cfg.assert_hook = Some(std::sync::Arc::new(move |info: &engine::AssertInfo| {
+        a.lock()
+            .unwrap()
+            .push((info.goroutine, info.position.clone().unwrap()));
+    }));
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let result = engine::run(cfg, &sr, Path::new("./tests/group1/assert_hook.gos"));
+    assert!(result.is_ok());
+
+    // both are recovered, which doesn't hide them from the hook
+    let asserts = asserts.lock().unwrap();
+    assert_eq!(asserts.len(), 2, "{:?}", asserts);
+    assert_eq!(asserts[0].0, 0);
+    assert!(asserts[0].1.contains("assert_hook.gos:7"), "{:?}", asserts);
+    assert_eq!(asserts[1].0, 1);
+    assert!(asserts[1].1.contains("assert_hook.gos:20"), "{:?}", asserts);
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_dump_locals_on_panic() {
+    let locals = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let l = locals.clone();
+    let mut cfg = config();
+    cfg.dump_locals_on_panic = true;
+    cfg.panic_hook = Some(std::sync::Arc::new(move |info: &engine::PanicInfo| {
+        *l.lock().unwrap() = info.locals.clone();
+    }));
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let result = engine::run(cfg, &sr, Path::new("./tests/group1/panic_locals.gos"));
+    assert!(result.is_ok());
+
+    let locals = locals.lock().unwrap();
+    let vars = |i: usize| -> Vec<String> {
+        locals[i]
+            .iter()
+            .map(|(n, v)| format!("{}={}", n, v))
+            .collect()
+    };
+    assert_eq!(vars(0), ["n=42", "label=total", "limit=10"]);
+    assert_eq!(vars(1), ["total=42"]);
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_alloc_limit() {
+    let run_oom = |policy: engine::OomPolicy, expect: i64| {
+        let mut cfg = config();
+        cfg.max_alloc = Some(1 << 20);
+        cfg.oom_policy = policy;
+        cfg.constants = vec![(
+            "allocLimit".to_owned(),
+            engine::ConstValue::with_i64(expect),
+        )];
+        let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+        engine::run(cfg, &sr, Path::new("./tests/group1/oom.gos"))
+    };
+    assert!(run_oom(engine::OomPolicy::Panic, 0).is_ok());
+
+    let el = run_oom(engine::OomPolicy::Abort, 0).unwrap_err();
+    assert!(el
+        .to_string()
+        .contains("out of memory allocating 8388608 bytes"));
+
+    let asked = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let a = asked.clone();
+    let policy = engine::OomPolicy::Ask(std::sync::Arc::new(move |f: &engine::AllocFailure| {
+        a.lock().unwrap().push((f.bytes, f.over_limit));
+        engine::OomAction::Allow
+    }));
+    assert!(run_oom(policy, 1).is_ok());
+    assert_eq!(*asked.lock().unwrap(), [(8 << 20, true)]);
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_instruction_limit() {
+    let run_limited = |source: &'static str| {
+        let mut cfg = config();
+        cfg.max_instructions = Some(1_000_000);
+        let (sr, path) = engine::SourceReader::fs_lib_and_string(
+            PathBuf::from("../std/"),
+            Cow::Borrowed(source),
+        );
+        engine::run(cfg, &sr, &path)
+    };
+    // the goroutines share the budget
+    let spinning = r#"
+package main
+
+func spin() {
+	n := 0
+	for {
+		n++
+	}
+}
+
+func main() {
+	go spin()
+	spin()
+}
+"#;
+    let el = run_limited(spinning).unwrap_err();
+    assert!(el
+        .to_string()
+        .contains("run aborted: instruction budget of 1000000 exhausted"));
+
+    let bounded = r#"
+package main
+
+func main() {
+	n := 0
+	for i := 0; i < 1000; i++ {
+		n += i
+	}
+	assert(n == 499500)
+}
+"#;
+    assert!(run_limited(bounded).is_ok());
+
+    // run_source tells a stopped run from the compile errors
+    let mut engine = new_engine();
+    engine.set_instruction_limit(Some(1_000_000));
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(spinning));
+    match engine.run_source(false, false, &sr, &path) {
+        Err(engine::SourceError::Run(engine::RunError::OutOfFuel(1_000_000))) => {}
+        result => panic!("{:?}", result.map(|_| ())),
+    }
+    let (sr, path) = engine::SourceReader::fs_lib_and_string(
+        PathBuf::from("../std/"),
+        Cow::Borrowed("package main\n\nfunc main() { undefined() }\n"),
+    );
+    match engine.run_source(false, false, &sr, &path) {
+        Err(engine::SourceError::Compile(el)) => assert!(el.len() > 0),
+        result => panic!("{:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_heap_limit() {
+    let limit = 1 << 20;
+    let mut engine = new_engine();
+    engine.set_heap_limit(Some(limit));
+    engine.define_const("heapLimit", engine::ConstValue::with_i64(limit as i64));
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let result = engine.run_source(
+        false,
+        false,
+        &sr,
+        Path::new("./tests/group1/heap_limit.gos"),
+    );
+    let stats = match result {
+        Ok(stats) => stats,
+        Err(el) => panic!("{}", el),
+    };
+    assert!(stats.peak_heap_bytes > limit / 2);
+    assert!(stats.allocated_bytes > 10 * limit as u64);
+}
+
+#[test]
+#[cfg(all(feature = "go_std", feature = "async"))]
+fn test_scheduling() {
+    for slice in [None, Some(7)] {
+        let mut engine = new_engine();
+        engine.set_time_slice(slice);
+        let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+        let result = engine.run_source(
+            false,
+            false,
+            &sr,
+            Path::new("./tests/group1/scheduling.gos"),
+        );
+        if let Err(el) = result {
+            panic!("{}", el);
+        }
+    }
+}
+
+#[test]
+#[cfg(all(feature = "go_std", feature = "async"))]
+fn test_print() {
+    let mut engine = new_engine();
+    engine.set_stable_addresses(true);
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let result = engine.run_source(false, false, &sr, Path::new("./tests/group1/print.gos"));
+    if let Err(el) = result {
+        panic!("{}", el);
+    }
+}
+
+// Checks the cases a select runs, with its channels nil, closed, ready or not, against
+// a model of what Go may run, with the cases picked at random and in order.
+#[test]
+#[cfg(all(feature = "go_std", feature = "async"))]
+fn test_select_model() {
+    for deterministic in [false, true] {
+        let mut engine = new_engine();
+        engine.set_deterministic_select(deterministic);
+        engine.define_const(
+            "deterministic",
+            engine::ConstValue::with_bool(deterministic),
+        );
+        let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+        let path = Path::new("./tests/group1/select_model.gos");
+        if let Err(el) = engine.run_source(false, false, &sr, path) {
+            panic!("deterministic {}: {}", deterministic, el);
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_goroutine_quota() {
+    let source = r#"
+package main
+
+const prefix = "runtime error: goroutine quota exceeded: "
+
+func report(done chan string) {
+	msg := recover().(error).Error()
+	if len(msg) > len(prefix) && msg[:len(prefix)] == prefix {
+		msg = msg[len(prefix):]
+	}
+	done <- msg
+}
+
+func runaway(done chan string) {
+	defer report(done)
+	n := 0
+	for {
+		n++
+	}
+}
+
+func allocating(done chan string) {
+	defer report(done)
+	for {
+		_ = make([]byte, 1000)
+	}
+}
+
+func main() {
+	done := make(chan string)
+	go runaway(done)
+	msg := <-done
+	assert(msg[len(msg)-14:] == "100000 allowed")
+	go allocating(done)
+	msg = <-done
+	assert(msg == "allocating 1001000 bytes, 1000000 allowed")
+
+	// the main goroutine is not limited
+	n := 0
+	for i := 0; i < 200000; i++ {
+		n++
+	}
+	assert(n == 200000)
+}
+"#;
+    let mut cfg = config();
+    cfg.goroutine_quota = Some(engine::Quota {
+        instructions: Some(100_000),
+        alloc_bytes: Some(1_000_000),
+    });
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let result = engine::run(cfg, &sr, &path);
+    if let Err(el) = &result {
+        eprint!("{}", el);
+    }
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_run_stats() {
+    let source = r#"
+package main
+
+import "fmt2"
+
+func depth(n int) int {
+	if n == 0 {
+		return 0
+	}
+	return depth(n-1) + 1
+}
+
+func main() {
+	done := make(chan int)
+	go func() {
+		for i := 0; i < 10000; i++ {
+			_ = map[int]int{i: i}
+		}
+		done <- depth(500)
+	}()
+	fmt2.Println(<-done)
+	fmt2.Println("done")
+}
+"#;
+    let engine = new_engine();
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let stats = engine.run_source(false, false, &sr, &path).unwrap();
+    assert!(stats.instructions > 10000);
+    assert_eq!(stats.goroutines, 2);
+    // the recursion grows the stack of the goroutine
+    assert!(stats.peak_stack_slots > 1000);
+    // the maps die young, they are collected with the nursery
+    assert!(stats.peak_heap_objects >= 1024);
+    assert!(
+        stats.peak_heap_objects < 4096,
+        "{}",
+        stats.peak_heap_objects
+    );
+    assert!(stats.gc_cycles > 0);
+    assert_eq!(stats.ffi_calls.get("fmt2.println"), Some(&2));
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_step_vm() {
+    use go_vm::types::Opcode;
+    let source = r#"
+package main
+
+func add(a, b int) int {
+	return a + b
+}
+
+func main() {
+	one := 1
+	x := add(one, 2)
+	assert(x == 3)
+}
+"#;
+    let engine = new_engine();
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let (code, _) = engine.compile(false, false, &sr, &path).unwrap();
+
+    let mut total = 0;
+    let mut adds = vec![];
+    engine
+        .step_vm(&code)
+        .run(|step| {
+            total += 1;
+            // the entry function calls main, which calls add
+            if step.op() == Opcode::ADD && step.depth == 3 {
+                // the result, then the parameters
+                let slots: Vec<isize> = step.frame.iter().map(|v| *v.as_int()).collect();
+                adds.push((step.inst.t0, slots));
+            }
+            true
+        })
+        .unwrap();
+    assert_eq!(adds.len(), 1);
+    assert_eq!(adds[0].0, go_vm::types::ValueType::Int);
+    assert_eq!(&adds[0].1[..3], &[0, 1, 2]);
+
+    // stopping in the middle runs nothing after
+    let mut count = 0;
+    engine
+        .step_vm(&code)
+        .run(|_| {
+            count += 1;
+            count < total / 2
+        })
+        .unwrap();
+    assert_eq!(count, total / 2);
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_shared_zeros() {
+    use std::rc::Rc;
+    let source = r#"
+package main
+
+type T struct {
+	x int
+	a [2]T2
+}
+
+type T2 struct{ y int }
+
+func e1() error { return nil }
+
+func e2() error { return nil }
+
+func named() (t T) {
+	t.x++
+	t.a[1].y++
+	return
+}
+
+func locals() int {
+	var t, u T
+	t.x = 1
+	t.a[0].y = 2
+	return t.x + t.a[0].y + u.x + u.a[0].y
+}
+
+func main() {
+	assert(e1() == nil && e2() == nil)
+	for i := 0; i < 2; i++ {
+		t := named()
+		assert(t.x == 1 && t.a[1].y == 1)
+		assert(locals() == 3)
+	}
+}
+"#;
+    let engine = new_engine();
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let (code, _) = engine.compile(false, false, &sr, &path).unwrap();
+    assert!(engine.run_bytecode(&code).is_ok());
+
+    // the functions with the same results, or locals of the same types, share them:
+    // the results are (), (string) of the runtime error, (error), (T) and (int)
+    let funcs = code.objects.functions.vec();
+    let ptr = |z: &Rc<[_]>| Rc::as_ptr(z) as *const u8;
+    let mut rets: Vec<_> = funcs.iter().map(|f| ptr(&f.ret_zeros)).collect();
+    let mut locals: Vec<_> = funcs.iter().map(|f| ptr(&f.local_zeros)).collect();
+    rets.sort();
+    rets.dedup();
+    locals.sort();
+    locals.dedup();
+    assert_eq!(rets.len(), 5);
+    assert!(
+        locals.len() * 2 < funcs.len(),
+        "{} {}",
+        locals.len(),
+        funcs.len()
+    );
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_debug_info() {
+    use go_vm::types::Opcode;
+    let source = r#"
+package main
+
+func f(a int, b string) (n int) {
+	x := a
+	if x > 0 {
+		x := "inner"
+		n = len(x)
+	}
+	return n + len(b)
+}
+
+func main() {
+	assert(f(1, "ab") == 7)
+}
+"#;
+    let engine = new_engine();
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let (code, _) = engine.compile(false, false, &sr, &path).unwrap();
+
+    let mut at_len = vec![];
+    engine
+        .step_vm(&code)
+        .run(|step| {
+            if step.op() == Opcode::LEN && step.depth == 3 {
+                let func = &step.objects.functions[step.func];
+                let locals: Vec<(String, i32)> = func
+                    .locals_at(step.pc)
+                    .iter()
+                    .map(|v| (v.name.clone(), v.slot))
+                    .collect();
+                at_len.push(locals);
+            }
+            true
+        })
+        .unwrap();
+    assert_eq!(at_len.len(), 2);
+    let names = |i: usize| -> Vec<&str> { at_len[i].iter().map(|v| v.0.as_str()).collect() };
+    assert_eq!(names(0), ["n", "a", "b", "x"]);
+    assert_eq!(names(1), ["n", "a", "b", "x"]);
+    // the x of the if block shadows the one of the function body
+    assert_eq!(at_len[0][3].1, 4);
+    assert_eq!(at_len[1][3].1, 3);
+}
+
+// Compiles the programs in tests/golden and compares the code generated for their
+// main packages with the .golden files next to them. Set GOLDEN_BLESS to rewrite the
+// .golden files instead, for reviewing changes to the code generator as text diffs.
+#[test]
+#[cfg(feature = "go_std")]
+fn test_golden_bytecode() {
+    let bless = std::env::var_os("GOLDEN_BLESS").is_some();
+    let engine = new_engine();
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let mut paths: Vec<PathBuf> = std::fs::read_dir("./tests/golden")
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|x| x == "gos"))
+        .collect();
+    paths.sort();
+    let mut mismatched = vec![];
+    for path in paths.iter() {
+        let (code, _) = match engine.compile(false, false, &sr, path) {
+            Ok(r) => r,
+            Err(el) => panic!("{}: {}", path.display(), el),
+        };
+        let text = code.disassemble(code.main_pkg);
+        let golden = path.with_extension("golden");
+        if bless {
+            std::fs::write(&golden, &text).unwrap();
+            continue;
+        }
+        let want = std::fs::read_to_string(&golden).unwrap_or_default();
+        if let Some((i, (got, want))) = text
+            .lines()
+            .chain(std::iter::repeat(""))
+            .zip(want.lines().chain(std::iter::repeat("")))
+            .take(text.lines().count().max(want.lines().count()))
+            .enumerate()
+            .find(|(_, (a, b))| a != b)
+        {
+            eprintln!(
+                "{} line {}:\n  want: {}\n  got:  {}",
+                golden.display(),
+                i + 1,
+                want,
+                got
+            );
+            mismatched.push(golden);
+        }
+    }
+    assert!(!paths.is_empty());
+    assert!(
+        mismatched.is_empty(),
+        "bytecode changed, rerun with GOLDEN_BLESS=1 to accept: {:?}",
+        mismatched
+    );
+}
+
+// Breaks the code of a program in the ways a bug of the code generator would, and
+// checks the verifier finds the broken instruction.
+#[test]
+fn test_verify_bytecode() {
+    use go_vm::types::{Opcode, ValueType};
+
+    let engine = new_engine();
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let compile = || {
+        let (code, _) = engine
+            .compile(false, false, &sr, Path::new("./tests/group1/call.gos"))
+            .unwrap();
+        code
+    };
+    let code = compile();
+    assert!(code.verify().is_ok());
+
+    let break_first = |op: Opcode, f: &dyn Fn(&mut go_vm::types::Instruction)| {
+        let mut code = compile();
+        let (key, pc) = code
+            .objects
+            .functions
+            .vec()
+            .iter()
+            .enumerate()
+            .find_map(|(k, func)| Some((k, func.code.iter().position(|i| i.op0 == op)?)))
+            .unwrap();
+        let key = go_vm::types::FunctionKey::from(key);
+        f(&mut code.objects.functions[key].code[pc]);
+        let e = code.verify().unwrap_err();
+        assert_eq!((e.func, e.pc), (key, pc));
+        e.msg
+    };
+    let msg = break_first(Opcode::JUMP, &|i| i.d = 1000);
+    assert!(msg.contains("jumps to"), "{}", msg);
+    let msg = break_first(Opcode::ADD, &|i| i.s1 = -100000);
+    assert!(msg.contains("constant 99999 doesn't exist"), "{}", msg);
+    let msg = break_first(Opcode::ADD, &|i| i.t0 = ValueType::Bool);
+    assert!(msg.contains("ADD can't work on Bool"), "{}", msg);
+    let msg = break_first(Opcode::CLOSURE, &|i| i.d = -1);
+    assert!(msg.contains("register -1 written"), "{}", msg);
+
+    // a read of a temporary nothing wrote, found in debug builds
+    if cfg!(debug_assertions) {
+        let mut code = compile();
+        let (key, temp) = code
+            .objects
+            .functions
+            .vec()
+            .iter()
+            .enumerate()
+            .find_map(|(k, f)| {
+                let vars = f.ret_count() + f.param_count() + f.local_count();
+                let first = f.code.first()?;
+                (first.op0 == Opcode::DUPLICATE && f.max_write_index >= vars)
+                    .then_some((k, f.max_write_index))
+            })
+            .unwrap();
+        let key = go_vm::types::FunctionKey::from(key);
+        code.objects.functions[key].code[0].s0 = temp;
+        let e = code.verify().unwrap_err();
+        assert_eq!((e.func, e.pc), (key, 0));
+        assert!(e.msg.contains("before it's written"), "{}", e.msg);
+    }
+}
+
+// Checks the conditional jumps on constants are folded, and no jump lands on
+// another unconditional one, then that the program still runs the same.
+#[test]
+fn test_peephole() {
+    use go_vm::types::Opcode;
+
+    let engine = new_engine();
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let (code, _) = engine
+        .compile(false, false, &sr, Path::new("./tests/group1/peephole.gos"))
+        .unwrap();
+    for func in code.objects.functions.vec().iter() {
+        let mut pc = 0;
+        while pc < func.code.len() {
+            let inst = &func.code[pc];
+            match inst.op0 {
+                Opcode::JUMP_IF | Opcode::JUMP_IF_NOT => assert!(inst.s0 >= 0),
+                _ => {}
+            }
+            match inst.op0 {
+                Opcode::JUMP | Opcode::JUMP_IF | Opcode::JUMP_IF_NOT => {
+                    let target = (pc as i32 + 1 + inst.d) as usize;
+                    assert!(target == pc || func.code[target].op0 != Opcode::JUMP);
+                }
+                _ => {}
+            }
+            pc += 1 + inst.ext_count();
+        }
+    }
+
+    let result = run("./tests/group1/peephole.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_channel() {
+    let result = run("./tests/group1/channel.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_defer() {
+    let result = run("./tests/group1/defer.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_goto() {
+    let result = run("./tests/group1/goto.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_recover() {
+    let result = run("./tests/group1/recover.gos", true);
     assert!(result.is_ok());
 }
 
@@ -441,6 +2107,102 @@ fn test_fibonacci() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_goroutines() {
+    time_test!();
+
+    let result = run("./tests/demo/goroutines.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_literals() {
+    time_test!();
+
+    let result = run("./tests/demo/literals.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_slices() {
+    time_test!();
+
+    let result = run("./tests/demo/slices.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_conversions() {
+    time_test!();
+
+    let result = run("./tests/demo/conversions.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_string_sort() {
+    time_test!();
+
+    let result = run("./tests/demo/string_sort.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_calls() {
+    time_test!();
+
+    let result = run("./tests/demo/calls.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_closures() {
+    time_test!();
+
+    let result = run("./tests/demo/closures.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_channels() {
+    time_test!();
+
+    let result = run("./tests/demo/channels.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_maps() {
+    time_test!();
+
+    let result = run("./tests/demo/maps.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_appends() {
+    time_test!();
+
+    let result = run("./tests/demo/appends.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_concat() {
+    time_test!();
+
+    let result = run("./tests/demo/concat.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_iface_calls() {
+    time_test!();
+
+    let result = run("./tests/demo/iface_calls.gos", false);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_issue8() {
     time_test!();
@@ -481,6 +2243,328 @@ fn test_std_reflect() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_std_goscript() {
+    let result = run("./tests/std/goscript.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "sqlite")]
+fn test_std_sql() {
+    let mut engine = new_engine();
+    engine.add_plugin(engine::sql::DriverPlugin::new(engine::sql::SqliteDriver));
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let result = engine.run_source(false, false, &sr, Path::new("./tests/std/sql.gos"));
+    if let Err(el) = &result {
+        eprint!("{}", el);
+    }
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "sqlite")]
+fn test_std_sql_sandbox() {
+    let dir = std::env::temp_dir().join("goscript_sql_sandbox");
+    std::fs::create_dir_all(&dir).unwrap();
+    let _ = std::fs::remove_file(dir.join("test.db"));
+    let mut engine = new_engine();
+    engine.set_sandbox(engine::SandboxPolicy::new().allow_write(&dir));
+    engine.add_plugin(engine::sql::DriverPlugin::new(engine::sql::SqliteDriver));
+    let dir_str = dir.to_string_lossy().to_string();
+    engine.define_const("testDir", engine::ConstValue::with_str(dir_str));
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let result = engine.run_source(false, false, &sr, Path::new("./tests/std/sql_sandbox.gos"));
+    if let Err(el) = &result {
+        eprint!("{}", el);
+    }
+    assert!(result.is_ok());
+    assert!(dir.join("test.db").exists());
+    assert!(!dir.join("../outside.db").exists());
+}
+
+/// Serves `count` requests, the response echoes the request. "/redirect" and
+/// "/loop" redirect to the same server, "/away" to another host, and a POST to
+/// "/see-other" is redirected with a 303.
+#[cfg(feature = "http")]
+fn serve_http(count: usize) -> String {
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming().take(count) {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let mut parts = line.split_whitespace();
+            let (method, path) = (
+                parts.next().unwrap().to_owned(),
+                parts.next().unwrap().to_owned(),
+            );
+            let (mut len, mut content_type) = (0, String::new());
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                let (k, v) = line.split_once(':').unwrap();
+                match k.to_lowercase().as_str() {
+                    "content-length" => len = v.trim().parse().unwrap(),
+                    "content-type" => content_type = v.trim().to_owned(),
+                    _ => {}
+                }
+            }
+            let mut body = vec![0; len];
+            reader.read_exact(&mut body).unwrap();
+            let (status, location, body) = match (method.as_str(), path.as_str()) {
+                ("GET", "/redirect") => ("302 Found", "/redirected", vec![]),
+                ("GET", "/loop") => ("302 Found", "/loop", vec![]),
+                ("GET", "/away") => ("302 Found", "http://denied.example/", vec![]),
+                ("POST", "/see-other") => ("303 See Other", "/posted", vec![]),
+                ("GET", _) => ("200 OK", "", path.into_bytes()),
+                ("POST", _) => ("201 Created", "", body),
+                _ => ("404 Not Found", "", vec![]),
+            };
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nX-Method: {}\r\nX-Content-Type: {}\r\nLocation: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                method,
+                content_type,
+                location,
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(&body).unwrap();
+        }
+    });
+    addr
+}
+
+#[test]
+#[cfg(feature = "http")]
+fn test_std_http() {
+    let mut engine = new_engine();
+    engine.define_const("testServer", engine::ConstValue::with_str(serve_http(18)));
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let result = engine.run_source(false, false, &sr, Path::new("./tests/std/http.gos"));
+    if let Err(el) = &result {
+        eprint!("{}", el);
+    }
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "http")]
+fn test_std_http_sandbox() {
+    let server = serve_http(4);
+    let host = server.trim_start_matches("http://").to_owned();
+    let mut engine = new_engine();
+    engine.set_sandbox(engine::SandboxPolicy::new().allow_host(&host));
+    engine.define_const("testServer", engine::ConstValue::with_str(server));
+    engine.define_const("testHost", engine::ConstValue::with_str(host));
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let result = engine.run_source(false, false, &sr, Path::new("./tests/std/http_sandbox.gos"));
+    if let Err(el) = &result {
+        eprint!("{}", el);
+    }
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "http")]
+fn test_std_http_server() {
+    use engine::http::{server, Request};
+
+    let (plugin, handle) = server();
+    let client = std::thread::spawn(move || {
+        let call = |method: &str, url: &str, header: &[(&str, &str)], body: &str| {
+            let req = Request {
+                method: method.to_owned(),
+                url: url.to_owned(),
+                header: header
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+                body: body.as_bytes().to_vec(),
+            };
+            handle.call_blocking(req).unwrap()
+        };
+        let resp = call("GET", "/hello?x=1", &[], "");
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.header("content-type"), Some("text/plain"));
+        assert_eq!(resp.body, b"hello GET /hello?x=1");
+
+        let resp = call("POST", "/echo/a", &[("X-Prefix", ">")], "ping");
+        assert_eq!(resp.status, 201);
+        assert_eq!(resp.body, b">ping");
+
+        for i in 1..=2 {
+            let resp = call("GET", "/count", &[], "");
+            assert_eq!(resp.status, 204);
+            assert_eq!(resp.header("X-Count").unwrap().len(), i);
+        }
+
+        // the line breaks of a value don't start other headers
+        let resp = call("GET", "/note", &[], "");
+        assert_eq!(resp.header("X-Note"), Some("a  X-Injected: 1"));
+        assert_eq!(resp.header("X-Injected"), None);
+
+        let resp = call("GET", "/missing", &[], "");
+        assert_eq!(resp.status, 404);
+        assert_eq!(resp.body, b"404 page not found\n");
+    });
+
+    let mut engine = new_engine();
+    engine.add_plugin(plugin);
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let result = engine.run_source(false, false, &sr, Path::new("./tests/std/http_server.gos"));
+    if let Err(el) = &result {
+        eprint!("{}", el);
+    }
+    assert!(result.is_ok());
+    client.join().unwrap();
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_sandbox() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let dir = std::env::temp_dir().join("goscript_sandbox");
+    std::fs::create_dir_all(dir.join("out")).unwrap();
+    std::fs::write(dir.join("hello.txt"), "hello").unwrap();
+    std::fs::write(std::env::temp_dir().join("outside.txt"), "secret").unwrap();
+    std::env::set_var("GOSCRIPT_SANDBOX_VISIBLE", "yes");
+    std::env::set_var("GOSCRIPT_SANDBOX_HIDDEN", "no");
+
+    let denied = Arc::new(AtomicUsize::new(0));
+    let d = denied.clone();
+    let policy = engine::SandboxPolicy::new()
+        .allow_read(&dir)
+        .allow_write(dir.join("out"))
+        .allow_env("GOSCRIPT_SANDBOX_VISIBLE")
+        .max_file_size(8)
+        .max_goroutines(3)
+        .on_check(Arc::new(move |_, granted| {
+            if !granted {
+                d.fetch_add(1, Ordering::Relaxed);
+            }
+        }));
+    let mut engine = new_engine();
+    engine.set_sandbox(policy);
+    let dir_str = dir.to_string_lossy().to_string();
+    engine.define_const("testDir", engine::ConstValue::with_str(dir_str));
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let result = engine.run_source(false, false, &sr, Path::new("./tests/std/sandbox.gos"));
+    if let Err(el) = &result {
+        eprint!("{}", el);
+    }
+    assert!(result.is_ok());
+    // the outside file, writing to hello.txt, the 9th byte, the hidden variable
+    // twice and the 4th goroutine at least once
+    assert!(denied.load(Ordering::Relaxed) >= 6);
+}
+
+#[test]
+#[cfg(all(feature = "go_std", not(feature = "std_net")))]
+fn test_std_compiled_out() {
+    let source = "package main\n\nimport \"net/http\"\n\nfunc main() {\n\t_ = http.Get\n}\n";
+    let el = run_string(Cow::Borrowed(source), false).unwrap_err();
+    let msg = "package net/http is not available: the engine is built without feature std_net";
+    assert!(el.to_string().contains(msg), "{}", el);
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_std_bridge() {
+    let mut bridge = engine::bridge::Bridge::new();
+    let events = bridge.inbound::<String>("events", 1);
+    let results = bridge.outbound::<isize>("results", 1);
+    let blobs = bridge.outbound::<Vec<u8>>("blobs", 1);
+    let host = std::thread::spawn(move || {
+        assert_eq!(blobs.recv_blocking().unwrap(), b"abc");
+        assert!(blobs.recv_blocking().is_err());
+        for ev in ["a", "bb", "ccc"] {
+            events.send_blocking(ev.to_owned()).unwrap();
+            assert_eq!(results.recv_blocking().unwrap(), ev.len() as isize);
+        }
+        drop(events);
+        // closed by the script
+        assert!(results.recv_blocking().is_err());
+    });
+
+    let mut engine = new_engine();
+    engine.add_plugin(bridge);
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let result = engine.run_source(false, false, &sr, Path::new("./tests/std/bridge.gos"));
+    if let Err(el) = &result {
+        eprint!("{}", el);
+    }
+    assert!(result.is_ok());
+    host.join().unwrap();
+}
+
+#[cfg(feature = "async")]
+trait Greeter {
+    fn greet(&self, name: String) -> engine::ffi::RuntimeResult<String>;
+
+    fn count(&self) -> engine::ffi::RuntimeResult<isize>;
+
+    fn fail(&self, msg: String) -> engine::ffi::RuntimeResult<()>;
+
+    fn size(&self, data: Vec<u8>) -> engine::ffi::RuntimeResult<isize>;
+}
+
+#[cfg(feature = "async")]
+engine::bridge_trait! {
+    struct GreeterProxy: Greeter {
+        "Greet" => fn greet(&self, name: String) -> RuntimeResult<String>;
+        "Count" => fn count(&self) -> RuntimeResult<isize>;
+        "Fail" => fn fail(&self, msg: String) -> RuntimeResult<()>;
+        "Size" => fn size(&self, data: Vec<u8>) -> RuntimeResult<isize>;
+    }
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_std_bridge_export() {
+    let mut bridge = engine::bridge::Bridge::new();
+    let greeter = GreeterProxy::export(&mut bridge, "greeter");
+    let _events = bridge.inbound::<String>("events", 1);
+    let host = std::thread::spawn(move || {
+        let g: &dyn Greeter = &greeter;
+        assert_eq!(g.greet("a".to_owned()).unwrap(), "hello, a");
+        assert_eq!(g.greet("b".to_owned()).unwrap(), "hello, b");
+        assert_eq!(g.count().unwrap(), 2);
+        let err = g.fail("oops".to_owned()).unwrap_err();
+        assert_eq!(err.as_str(), "panic: failed: oops");
+        // still serving after a panic
+        assert_eq!(g.count().unwrap(), 2);
+        assert_eq!(g.size(b"abc".to_vec()).unwrap(), 3);
+    });
+
+    let mut engine = new_engine();
+    engine.add_plugin(bridge);
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let result = engine.run_source(
+        false,
+        false,
+        &sr,
+        Path::new("./tests/std/bridge_export.gos"),
+    );
+    if let Err(el) = &result {
+        eprint!("{}", el);
+    }
+    assert!(result.is_ok());
+    host.join().unwrap();
+}
+
 #[test]
 fn test_std_time() {
     time_test!();
@@ -565,3 +2649,353 @@ fn test_std_temp() {
     let result = run("./tests/std/temp.gos", false);
     assert!(result.is_ok());
 }
+
+#[test]
+fn test_std_errgroup() {
+    time_test!();
+
+    let result = run("./tests/std/errgroup.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_call() {
+    use engine::ffi::GosValue;
+
+    let engine = new_engine();
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let (bc, _) = engine
+        .compile(false, false, &sr, Path::new("./tests/group1/call.gos"))
+        .unwrap();
+    let int = |i: isize| GosValue::from(i);
+
+    let results = engine
+        .call(&bc, "main", "Add", vec![int(2), int(3)])
+        .unwrap();
+    assert_eq!(*results[0].as_int(), 5);
+    let results = engine
+        .call(&bc, "main", "Greet", vec!["world".to_owned().into()])
+        .unwrap();
+    assert_eq!(&*results[0].as_string().as_str(), "hello, world");
+    let results = engine
+        .call(&bc, "main", "Divmod", vec![int(7), int(2)])
+        .unwrap();
+    assert_eq!((*results[0].as_int(), *results[1].as_int()), (3, 1));
+    // set by init
+    let results = engine.call(&bc, "main", "Double", vec![int(21)]).unwrap();
+    assert_eq!(*results[0].as_int(), 42);
+    // the package variables keep their values across the calls, main never ran
+    let results = engine.call(&bc, "main", "Calls", vec![]).unwrap();
+    assert_eq!(*results[0].as_int(), 1);
+
+    let err = engine.call(&bc, "main", "Missing", vec![]).unwrap_err();
+    assert_eq!(err.to_string(), "function main.Missing not found");
+    let err = engine.call(&bc, "main", "calls", vec![]).unwrap_err();
+    assert!(matches!(err, engine::CallError::NotFound(_)));
+    let err = engine.call(&bc, "main", "Add", vec![int(1)]).unwrap_err();
+    assert_eq!(err.to_string(), "main.Add takes 2 arguments, 1 given");
+    let err = engine
+        .call(&bc, "main", "Add", vec![int(1), "2".to_owned().into()])
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "argument 2 of main.Add: String given, Int expected"
+    );
+    let err = engine
+        .call(&bc, "main", "Fail", vec!["oops".to_owned().into()])
+        .unwrap_err();
+    assert_eq!(err.to_string(), "panic: oops");
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_instance() {
+    use engine::ffi::GosValue;
+
+    let mut engine = new_engine();
+    engine.set_instruction_limit(Some(1_000_000));
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let (bc, _) = engine
+        .compile(false, false, &sr, Path::new("./tests/group1/instance.gos"))
+        .unwrap();
+    let inst = engine.instance(&bc).unwrap();
+    let count = |key: &str| {
+        let results = inst.call("main", "Count", vec![key.to_owned().into()]);
+        *results.unwrap()[0].as_int()
+    };
+    let call_int = |func: &str| *inst.call("main", func, vec![]).unwrap()[0].as_int();
+    let state = || {
+        let results = inst.call("main", "State", vec![]).unwrap();
+        (*results[0].as_int(), *results[1].as_int())
+    };
+
+    assert_eq!(
+        (count("a"), count("a"), count("b"), count("a")),
+        (1, 2, 1, 3)
+    );
+    // initialized once
+    assert_eq!(state(), (1, 4));
+    // the goroutine started by init runs between the calls
+    assert_eq!(
+        (call_int("Next"), call_int("Next"), call_int("Next")),
+        (0, 1, 2)
+    );
+
+    // the instruction limit is for each call, one going over it doesn't stop the others
+    let err = inst.call("main", "Spin", vec![]).unwrap_err();
+    assert!(matches!(
+        err,
+        engine::CallError::Run(engine::RunError::OutOfFuel(_))
+    ));
+    assert_eq!(count("b"), 2);
+    let err = inst.call("main", "Count", vec![GosValue::from(1isize)]);
+    assert!(matches!(err, Err(engine::CallError::Arguments(_))));
+
+    inst.reset().unwrap();
+    assert_eq!(state(), (1, 0));
+    assert_eq!(count("a"), 1);
+    assert_eq!(call_int("Next"), 0);
+    assert!(inst.stats().instructions > 1_000_000);
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_instance_values() {
+    use engine::ffi::{FfiCtx, GosValue};
+
+    let engine = new_engine();
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let (bc, _) = engine
+        .compile(false, false, &sr, Path::new("./tests/group1/instance.gos"))
+        .unwrap();
+    let inst = engine.instance(&bc).unwrap();
+
+    // made once, changed in place between the calls
+    let data = inst.new_bytes(vec![1, 2, 3]);
+    let checksum = || *inst.call("main", "Checksum", vec![data.clone()]).unwrap()[0].as_int();
+    assert_eq!(checksum(), 6);
+    assert_eq!(
+        &*FfiCtx::slice_as_primitive_slice::<u8, u8>(&data).unwrap(),
+        &[0xff, 2, 3]
+    );
+    FfiCtx::slice_as_primitive_slice_mut::<u8, u8>(&data).unwrap()[0] = 10;
+    assert_eq!(checksum(), 15);
+
+    let key = |k: &str| GosValue::from(k.to_owned());
+    let m = inst
+        .new_map(vec![(key("a"), GosValue::from(1isize))])
+        .unwrap();
+    let tally = |k: &str| {
+        let results = inst.call("main", "Tally", vec![m.clone(), key(k)]);
+        *results.unwrap()[0].as_int()
+    };
+    assert_eq!(tally("b"), 2);
+    let obj = &m.as_map().unwrap().0;
+    assert_eq!(*obj.get(&key("b")).unwrap().unwrap().as_int(), 1);
+    obj.delete(&key("a")).unwrap();
+    obj.insert(key("c"), GosValue::from(5isize)).unwrap();
+    assert_eq!(tally("c"), 2);
+    assert_eq!(*obj.get(&key("c")).unwrap().unwrap().as_int(), 6);
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_rooted_value() {
+    use engine::ffi::GosValue;
+
+    let engine = new_engine();
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let (bc, _) = engine
+        .compile(false, false, &sr, Path::new("./tests/group1/instance.gos"))
+        .unwrap();
+    let inst = engine.instance(&bc).unwrap();
+
+    let ring = inst.root(
+        inst.call("main", "Ring", vec![GosValue::from(100isize)])
+            .unwrap()
+            .remove(0),
+    );
+    let copy = ring.clone();
+    drop(ring);
+    let gc_cycles = inst.stats().gc_cycles;
+    inst.call("main", "Churn", vec![GosValue::from(20000isize)])
+        .unwrap();
+    assert!(inst.stats().gc_cycles > gc_cycles);
+    // the cycle is kept whole by the handle left
+    let sum = inst.call("main", "RingSum", vec![copy.value()]).unwrap();
+    assert_eq!(*sum[0].as_int(), 5050);
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_gc_generations() {
+    let result = run("./tests/group1/gc_generations.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_gc_stress() {
+    let result = run("./tests/group1/gc_stress.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_repl() {
+    let engine = new_engine();
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let mut repl = engine::Repl::new(&engine, &sr).unwrap();
+    let mut eval = |input: &str| repl.eval(input).map_err(|el| el.to_string());
+
+    assert_eq!(eval("x := 40").unwrap(), Vec::<String>::new());
+    assert_eq!(eval("x + 2").unwrap(), vec!["42"]);
+    assert_eq!(eval("x++; x").unwrap(), vec!["41"]);
+    // assigned again with the same type, or declared again with another one
+    eval("func getx() int { return x }").unwrap();
+    assert_eq!(eval("x := x * 2; getx()").unwrap(), vec!["82"]);
+    assert_eq!(eval("x, y := 1, 2; x + y").unwrap(), vec!["3"]);
+    assert_eq!(eval("x := \"s\"; x + x").unwrap(), vec!["ss"]);
+
+    eval("func swap(a, b int) (int, int) { return b, a }").unwrap();
+    assert_eq!(eval("swap(1, 2)").unwrap(), vec!["2 1"]);
+    eval("func swap(a, b int) int { return a - b }").unwrap();
+    assert_eq!(eval("swap(1, 2)").unwrap(), vec!["-1"]);
+
+    eval("type point struct { x, y int }\nfunc (p point) sum() int { return p.x + p.y }").unwrap();
+    assert_eq!(eval("p := point{3, 4}; p.sum()").unwrap(), vec!["7"]);
+    assert_eq!(
+        eval("for i := 0; i < 3; i++ { p.x += i }\np.x").unwrap(),
+        vec!["6"]
+    );
+
+    eval("import \"strings\"").unwrap();
+    assert_eq!(eval("strings.ToUpper(x)").unwrap(), vec!["S"]);
+    eval("import (\n\tstr \"strings\"\n\t\"fmt\"\n)").unwrap();
+    assert_eq!(eval("str.Repeat(x, 3)").unwrap(), vec!["sss"]);
+    assert_eq!(eval("fmt.Sprint(p)").unwrap(), vec!["{6 4}"]);
+
+    // the errors leave the package as it was
+    assert!(eval("z := undefined").is_err());
+    assert!(eval("x := 1 +").is_err());
+    assert!(eval("import \"no/such/pkg\"").is_err());
+    assert!(eval("z").is_err());
+    assert_eq!(eval("x").unwrap(), vec!["s"]);
+    let err = eval("s := []int{1}; s[1]").unwrap_err();
+    assert!(err.contains("panic: index 1 out of range"), "{}", err);
+    assert_eq!(eval("x; s; p.y").unwrap(), vec!["s", "[1]", "4"]);
+
+    let input = "a := []int{\n1,\n2}\nlen(a)\nb := `x\ny`\nb\nc :=\n";
+    let mut output = vec![];
+    repl.run(std::io::Cursor::new(input), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(
+        output.starts_with("> ... ... > 2\n> ... > x\ny\n> "),
+        "{}",
+        output
+    );
+    assert!(output.contains("expected"), "{}", output);
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_run_tests() {
+    use engine::TestStatus;
+
+    let engine = new_engine();
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let report = engine
+        .run_tests(false, false, &sr, Path::new("./tests/group2/gotest"))
+        .unwrap();
+    let results: Vec<(&str, TestStatus)> = report
+        .results
+        .iter()
+        .map(|r| (r.name.as_str(), r.status))
+        .collect();
+    assert_eq!(
+        results,
+        [
+            ("TestPushPop", TestStatus::Passed),
+            ("TestFail", TestStatus::Failed),
+            ("TestSub", TestStatus::Failed),
+            ("TestPanic", TestStatus::Failed),
+            ("TestSkip", TestStatus::Skipped),
+        ]
+    );
+    assert!(!report.passed());
+    let fail = &report.results[1];
+    assert!(fail.pos.ends_with("stack_test.go:19"), "{}", fail.pos);
+    assert_eq!(fail.output, "    logged\n    got 1\n    stopped\n");
+    assert_eq!(
+        report.results[2].output,
+        "    --- FAIL: TestSub/push_2\n        two\n"
+    );
+    assert!(report.results[3].output.contains("panic: "));
+
+    let text = report.to_string();
+    assert!(text.contains("--- FAIL: TestFail ("), "{}", text);
+    assert!(!text.contains("TestPushPop"), "{}", text);
+    assert!(
+        text.ends_with("FAIL\t1 passed, 3 failed, 1 skipped\n"),
+        "{}",
+        text
+    );
+
+    // the main packages are compiled without main
+    let err = engine
+        .run_tests(false, false, &sr, Path::new("./tests/group1/no_such_dir"))
+        .unwrap_err();
+    assert!(err.len() > 0);
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_run_benchmarks() {
+    use engine::TestStatus;
+    use std::time::Duration;
+
+    let engine = new_engine();
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let report = engine
+        .run_benchmarks(
+            false,
+            false,
+            &sr,
+            Path::new("./tests/group2/gotest"),
+            Duration::from_millis(20),
+        )
+        .unwrap();
+    let results: Vec<(&str, TestStatus)> = report
+        .results
+        .iter()
+        .map(|r| (r.name.as_str(), r.status))
+        .collect();
+    assert_eq!(
+        results,
+        [
+            ("BenchmarkPush", TestStatus::Passed),
+            ("BenchmarkClosure", TestStatus::Passed),
+            ("BenchmarkFail", TestStatus::Failed),
+            ("BenchmarkSkip", TestStatus::Skipped),
+        ]
+    );
+    assert!(!report.passed());
+
+    let push = &report.results[0];
+    assert!(push.n > 1);
+    assert!(push.ns_per_op > 0.0);
+    assert!(push.instructions_per_op > 1.0);
+    assert!(push.bytes_per_op > 0.0);
+    let closure = &report.results[1];
+    assert!(closure.allocs_per_op >= 1.0, "{}", closure.allocs_per_op);
+    // b.N stays 1 after a failure
+    let fail = &report.results[2];
+    assert_eq!(fail.n, 1);
+    assert_eq!(fail.output, "    failed at 1\n");
+
+    let text = report.to_string();
+    assert!(text.contains("ns/op"), "{}", text);
+    assert!(text.contains("--- FAIL: BenchmarkFail ("), "{}", text);
+    assert!(text.contains("--- SKIP: BenchmarkSkip ("), "{}", text);
+    assert!(text.ends_with("FAIL\n"), "{}", text);
+}
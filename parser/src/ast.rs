@@ -131,6 +131,30 @@ impl Expr {
         }))
     }
 
+    pub fn new_ident(objs: &mut AstObjects, pos: position::Pos, name: &str) -> Expr {
+        Expr::Ident(objs.idents.insert(Ident::with_str(pos, name)))
+    }
+
+    pub fn new_binary_expr(a: Expr, pos: position::Pos, op: token::Token, b: Expr) -> Expr {
+        Expr::Binary(Rc::new(BinaryExpr {
+            expr_a: a,
+            op_pos: pos,
+            op,
+            expr_b: b,
+        }))
+    }
+
+    /// A call without positions, to be printed rather than checked
+    pub fn new_call(func: Expr, args: Vec<Expr>) -> Expr {
+        Expr::Call(Rc::new(CallExpr {
+            func,
+            l_paren: 0,
+            args,
+            ellipsis: None,
+            r_paren: 0,
+        }))
+    }
+
     pub fn box_func_type(ft: FuncType, objs: &mut AstObjects) -> Expr {
         Expr::Func(objs.ftypes.insert(ft))
     }
@@ -213,7 +237,7 @@ impl Node for Expr {
                 Some(expr) => expr.end(objs),
                 None => e.pos + 3,
             },
-            Expr::BasicLit(e) => e.pos + e.token.get_literal().len(),
+            Expr::BasicLit(e) => e.pos + e.token.get_literal().chars().count(),
             Expr::FuncLit(e) => e.body.end(),
             Expr::CompositeLit(e) => e.r_brace + 1,
             Expr::Paren(e) => e.r_paren + 1,
@@ -278,6 +302,14 @@ impl Stmt {
         Stmt::Assign(AssignStmt::arena_new(objs, lhs, tpos, tok, rhs))
     }
 
+    pub fn new_expr(x: Expr) -> Stmt {
+        Stmt::Expr(Box::new(x))
+    }
+
+    pub fn new_return(pos: position::Pos, results: Vec<Expr>) -> Stmt {
+        Stmt::Return(Rc::new(ReturnStmt { ret: pos, results }))
+    }
+
     pub fn box_block(block: BlockStmt) -> Stmt {
         Stmt::Block(Rc::new(block))
     }
@@ -484,6 +516,14 @@ impl Node for Decl {
     }
 }
 
+/// What the input of an interactive session parses to, see `Parser::parse_input`
+#[derive(Debug)]
+pub enum Input {
+    /// Imports and declarations, the imports first
+    Decls(Vec<Decl>),
+    Stmts(Vec<Stmt>),
+}
+
 #[derive(Debug)]
 pub struct File {
     pub package: position::Pos,
@@ -575,7 +615,7 @@ impl Ident {
     }
 
     pub fn end(&self) -> position::Pos {
-        self.pos + self.name.len()
+        self.pos + self.name.chars().count()
     }
 
     pub fn entity_obj<'a>(&self, objs: &'a AstObjects) -> Option<&'a scope::Entity> {
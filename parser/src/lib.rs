@@ -31,6 +31,8 @@ mod scanner;
 mod token;
 
 pub mod ast;
+pub mod printer;
+pub mod rewrite;
 pub mod scope;
 pub mod visitor;
 
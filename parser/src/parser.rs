@@ -66,6 +66,12 @@ pub struct Parser<'a> {
     sync_pos: position::Pos,
     sync_count: isize,
 
+    // the errors before the top level declaration being parsed, and the keyword
+    // of the next one, held back while the parser winds down as if at the end of
+    // the file, after an error in the current one
+    decl_errors: Option<usize>,
+    held: Option<(Token, position::Pos)>,
+
     expr_level: isize,
     in_rhs: bool,
 
@@ -97,6 +103,8 @@ impl<'a> Parser<'a> {
             token: Token::NONE,
             sync_pos: 0,
             sync_count: 0,
+            decl_errors: None,
+            held: None,
             expr_level: 0,
             in_rhs: false,
             pkg_scope: None,
@@ -340,6 +348,9 @@ impl<'a> Parser<'a> {
     }
 
     fn next(&mut self) {
+        if self.held.is_some() {
+            return;
+        }
         // Get next token and skip comments
         loop {
             let (token, pos) = self.scanner.scan();
@@ -356,14 +367,53 @@ impl<'a> Parser<'a> {
                 }
             }
         }
+        if self.at_next_decl() {
+            let token = std::mem::replace(&mut self.token, Token::EOF);
+            self.held = Some((token, self.pos));
+        }
+    }
+
+    /// Reports whether the token starts the next top level declaration while the
+    /// current one has errors: a declaration keyword in the first column can't be
+    /// a part of a formatted function, so the statements around the error don't
+    /// take the declarations after it with them
+    fn at_next_decl(&self) -> bool {
+        match self.decl_errors {
+            Some(n) if self.errors.len() > n => match self.token {
+                Token::FUNC | Token::TYPE | Token::VAR | Token::CONST | Token::IMPORT => {
+                    self.file().position(self.pos).column == 1
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Parses a top level declaration, resuming at the keyword held back by the
+    /// previous one
+    fn parse_top_decl<F: FnOnce(&mut Parser<'a>) -> Decl>(&mut self, f: F) -> Decl {
+        self.decl_errors = Some(self.errors.len());
+        let decl = f(self);
+        self.decl_errors = None;
+        if let Some((token, pos)) = self.held.take() {
+            self.token = token;
+            self.pos = pos;
+        }
+        decl
     }
 
+    // the errors of winding down before a held declaration, at what is not the
+    // end of the file, are left out
     fn error_str(&self, pos: position::Pos, s: &str) {
-        FilePosErrors::new(self.file(), self.errors).parser_add_str(pos, s);
+        if self.held.is_none() {
+            FilePosErrors::new(self.file(), self.errors).parser_add_str(pos, s);
+        }
     }
 
     fn error(&self, pos: position::Pos, msg: String) {
-        FilePosErrors::new(self.file(), self.errors).parser_add(pos, msg);
+        if self.held.is_none() {
+            FilePosErrors::new(self.file(), self.errors).parser_add(pos, msg);
+        }
     }
 
     fn error_expected(&self, pos: position::Pos, msg: &str) {
@@ -1554,7 +1604,11 @@ impl<'a> Parser<'a> {
                         _ => {
                             let pos = self.pos;
                             self.error_expected(pos, "selector or type assertion");
-                            self.next();
+                            // a } is most likely the end of the block being typed,
+                            // which the statements after it need to parse
+                            if self.token != Token::RBRACE {
+                                self.next();
+                            }
                             let sel = new_ident!(self, pos, "_".to_owned(), IdentEntity::NoEntity);
                             x = Expr::new_selector(x, sel);
                         }
@@ -2770,11 +2824,15 @@ impl<'a> Parser<'a> {
         let mut decls = vec![];
         // import decls
         while self.token == Token::IMPORT {
-            decls.push(self.parse_gen_decl(&Token::IMPORT, Parser::parse_import_spec));
+            decls.push(
+                self.parse_top_decl(|p| {
+                    p.parse_gen_decl(&Token::IMPORT, Parser::parse_import_spec)
+                }),
+            );
         }
         // rest of package body
         while self.token != Token::EOF {
-            decls.push(self.parse_decl(Token::is_decl_start))
+            decls.push(self.parse_top_decl(|p| p.parse_decl(Token::is_decl_start)))
         }
         self.close_scope();
         assert!(self.top_scope.is_none(), "unbalanced scopes");
@@ -2808,6 +2866,49 @@ impl<'a> Parser<'a> {
             unresolved: self.unresolved.clone(),
         })
     }
+
+    /// Parses the input of an interactive session, which has no package clause. It's
+    /// imports and declarations if it starts with `import`, `func` or `type`, otherwise
+    /// it's statements, parsed like a function body.
+    pub fn parse_input(&mut self) -> Option<Input> {
+        self.trace_begin("Input");
+
+        let err_count = self.errors.len();
+        self.open_scope();
+        self.pkg_scope = self.top_scope;
+        let input = match self.token {
+            Token::IMPORT | Token::FUNC | Token::TYPE => {
+                let mut decls = vec![];
+                while self.token == Token::IMPORT {
+                    decls.push(self.parse_gen_decl(&Token::IMPORT, Parser::parse_import_spec));
+                }
+                while self.token != Token::EOF {
+                    decls.push(self.parse_decl(Token::is_decl_start))
+                }
+                Input::Decls(decls)
+            }
+            _ => {
+                self.open_scope();
+                self.open_label_scope();
+                let list = self.parse_stmt_list();
+                self.close_label_scope();
+                self.close_scope();
+                if self.token != Token::EOF {
+                    self.error_expected(self.pos, "statement");
+                }
+                Input::Stmts(list)
+            }
+        };
+        self.close_scope();
+        assert!(self.top_scope.is_none(), "unbalanced scopes");
+
+        self.trace_end();
+        if self.errors.len() > err_count {
+            None
+        } else {
+            Some(input)
+        }
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,661 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Printing the AST back to Go source, for the tools that build or change code as
+//! an AST and hand it to the engine as text.
+//!
+//! The output is laid out like gofmt does, with tabs, but without aligning the
+//! columns or keeping the blank lines. The comments are not in the AST, so they
+//! are lost: to change a file keeping its comments and layout, see `rewrite`.
+//!
+//! Printing what was parsed gives source that parses to the same AST. The ASTs
+//! built by hand don't need `ParenExpr`s: the operands that need parentheses to
+//! parse back the same get them.
+
+use super::ast::*;
+use super::objects::*;
+
+/// Prints a file, with its declarations separated by blank lines
+pub fn print_file(objs: &AstObjects, file: &File) -> String {
+    let mut p = Printer::new(objs);
+    p.file(file);
+    p.out
+}
+
+pub fn print_decl(objs: &AstObjects, decl: &Decl) -> String {
+    let mut p = Printer::new(objs);
+    p.decl(decl);
+    p.out
+}
+
+/// Prints a statement, the ones in it indented with tabs from the first column
+pub fn print_stmt(objs: &AstObjects, stmt: &Stmt) -> String {
+    let mut p = Printer::new(objs);
+    p.stmt(stmt);
+    p.out
+}
+
+pub fn print_expr(objs: &AstObjects, expr: &Expr) -> String {
+    let mut p = Printer::new(objs);
+    p.expr(expr);
+    p.out
+}
+
+struct Printer<'a> {
+    objs: &'a AstObjects,
+    out: String,
+    indent: usize,
+    // in the header of an if, for or switch, where a composite literal needs
+    // parentheses not to be taken for the block
+    header: bool,
+}
+
+impl<'a> Printer<'a> {
+    fn new(objs: &'a AstObjects) -> Printer<'a> {
+        Printer {
+            objs,
+            out: String::new(),
+            indent: 0,
+            header: false,
+        }
+    }
+
+    fn w(&mut self, s: &str) {
+        self.out.push_str(s);
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        for _ in 0..self.indent {
+            self.out.push('\t');
+        }
+    }
+
+    fn ident(&mut self, key: IdentKey) {
+        let objs = self.objs;
+        self.w(&objs.idents[key].name);
+    }
+
+    fn idents(&mut self, keys: &[IdentKey]) {
+        for (i, key) in keys.iter().enumerate() {
+            if i > 0 {
+                self.w(", ");
+            }
+            self.ident(*key);
+        }
+    }
+
+    fn file(&mut self, file: &File) {
+        self.w("package ");
+        self.ident(file.name);
+        self.w("\n");
+        for decl in file.decls.iter() {
+            self.w("\n");
+            self.decl(decl);
+            self.w("\n");
+        }
+    }
+
+    fn decl(&mut self, decl: &Decl) {
+        match decl {
+            Decl::Bad(_) => self.w("BadDecl"),
+            Decl::Gen(d) => {
+                self.w(d.token.text());
+                self.w(" ");
+                if d.l_paran.is_some() || d.specs.len() != 1 {
+                    self.w("(");
+                    self.indent += 1;
+                    for spec in d.specs.iter() {
+                        self.newline();
+                        self.spec(*spec);
+                    }
+                    self.indent -= 1;
+                    self.newline();
+                    self.w(")");
+                } else {
+                    self.spec(d.specs[0]);
+                }
+            }
+            Decl::Func(key) => {
+                let objs = self.objs;
+                let fdecl = &objs.fdecls[*key];
+                self.w("func ");
+                if let Some(recv) = &fdecl.recv {
+                    self.params(recv);
+                    self.w(" ");
+                }
+                self.ident(fdecl.name);
+                self.signature(&objs.ftypes[fdecl.typ]);
+                if let Some(body) = &fdecl.body {
+                    self.w(" ");
+                    self.block(body);
+                }
+            }
+        }
+    }
+
+    fn spec(&mut self, key: SpecKey) {
+        let objs = self.objs;
+        match &objs.specs[key] {
+            Spec::Import(s) => {
+                if let Some(name) = s.name {
+                    self.ident(name);
+                    self.w(" ");
+                }
+                self.w(s.path.token.get_literal());
+            }
+            Spec::Value(s) => {
+                self.idents(&s.names);
+                if let Some(t) = &s.typ {
+                    self.w(" ");
+                    self.expr(t);
+                }
+                if !s.values.is_empty() {
+                    self.w(" = ");
+                    self.exprs(&s.values);
+                }
+            }
+            Spec::Type(s) => {
+                self.ident(s.name);
+                // the position of the = of an alias, 0 if it's not one
+                self.w(if s.assign > 0 { " = " } else { " " });
+                self.expr(&s.typ);
+            }
+        }
+    }
+
+    fn block(&mut self, block: &BlockStmt) {
+        self.w("{");
+        self.stmt_list(&block.list);
+        self.newline();
+        self.w("}");
+    }
+
+    fn stmt_list(&mut self, list: &[Stmt]) {
+        self.indent += 1;
+        for stmt in list.iter() {
+            if let Stmt::Empty(_) = stmt {
+                continue;
+            }
+            if let Stmt::Labeled(_) = stmt {
+                // gofmt puts the labels one tab to the left
+                self.indent -= 1;
+                self.newline();
+                self.indent += 1;
+            } else {
+                self.newline();
+            }
+            self.stmt(stmt);
+        }
+        self.indent -= 1;
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        let objs = self.objs;
+        match stmt {
+            Stmt::Bad(_) => self.w("BadStmt"),
+            Stmt::Decl(d) => self.decl(d),
+            Stmt::Empty(_) => {}
+            Stmt::Labeled(key) => {
+                let l = &objs.l_stmts[*key];
+                self.ident(l.label);
+                self.w(":");
+                match &l.stmt {
+                    Stmt::Empty(_) => {}
+                    s => {
+                        self.newline();
+                        self.stmt(s);
+                    }
+                }
+            }
+            Stmt::Expr(e) => self.expr(e),
+            Stmt::Send(s) => {
+                self.expr(&s.chan);
+                self.w(" <- ");
+                self.expr(&s.val);
+            }
+            Stmt::IncDec(s) => {
+                self.expr(&s.expr);
+                self.w(s.token.text());
+            }
+            Stmt::Assign(key) => {
+                let s = &objs.a_stmts[*key];
+                self.exprs(&s.lhs);
+                self.w(" ");
+                self.w(s.token.text());
+                self.w(" ");
+                self.exprs(&s.rhs);
+            }
+            Stmt::Go(s) => {
+                self.w("go ");
+                self.expr(&s.call);
+            }
+            Stmt::Defer(s) => {
+                self.w("defer ");
+                self.expr(&s.call);
+            }
+            Stmt::Return(s) => {
+                self.w("return");
+                if !s.results.is_empty() {
+                    self.w(" ");
+                    self.exprs(&s.results);
+                }
+            }
+            Stmt::Branch(s) => {
+                self.w(s.token.text());
+                if let Some(label) = s.label {
+                    self.w(" ");
+                    self.ident(label);
+                }
+            }
+            Stmt::Block(b) => self.block(b),
+            Stmt::If(s) => {
+                self.w("if ");
+                self.header(|p| {
+                    if let Some(init) = &s.init {
+                        p.stmt(init);
+                        p.w("; ");
+                    }
+                    p.expr(&s.cond);
+                });
+                self.w(" ");
+                self.block(&s.body);
+                if let Some(els) = &s.els {
+                    self.w(" else ");
+                    self.stmt(els);
+                }
+            }
+            Stmt::Case(s) => {
+                match &s.list {
+                    Some(list) => {
+                        self.w("case ");
+                        self.exprs(list);
+                        self.w(":");
+                    }
+                    None => self.w("default:"),
+                }
+                self.stmt_list(&s.body);
+            }
+            Stmt::Switch(s) => {
+                self.w("switch ");
+                self.header(|p| {
+                    if let Some(init) = &s.init {
+                        p.stmt(init);
+                        p.w("; ");
+                    }
+                    if let Some(tag) = &s.tag {
+                        p.expr(tag);
+                        p.w(" ");
+                    }
+                });
+                self.clauses(&s.body);
+            }
+            Stmt::TypeSwitch(s) => {
+                self.w("switch ");
+                self.header(|p| {
+                    if let Some(init) = &s.init {
+                        p.stmt(init);
+                        p.w("; ");
+                    }
+                    p.stmt(&s.assign);
+                });
+                self.w(" ");
+                self.clauses(&s.body);
+            }
+            Stmt::Comm(s) => {
+                match &s.comm {
+                    Some(comm) => {
+                        self.w("case ");
+                        self.stmt(comm);
+                        self.w(":");
+                    }
+                    None => self.w("default:"),
+                }
+                self.stmt_list(&s.body);
+            }
+            Stmt::Select(s) => {
+                self.w("select ");
+                self.clauses(&s.body);
+            }
+            Stmt::For(s) => {
+                self.w("for ");
+                self.header(|p| {
+                    if s.init.is_some() || s.post.is_some() {
+                        if let Some(init) = &s.init {
+                            p.stmt(init);
+                        }
+                        p.w("; ");
+                        if let Some(cond) = &s.cond {
+                            p.expr(cond);
+                        }
+                        p.w(";");
+                        if let Some(post) = &s.post {
+                            p.w(" ");
+                            p.stmt(post);
+                        }
+                        p.w(" ");
+                    } else if let Some(cond) = &s.cond {
+                        p.expr(cond);
+                        p.w(" ");
+                    }
+                });
+                self.block(&s.body);
+            }
+            Stmt::Range(s) => {
+                self.w("for ");
+                self.header(|p| {
+                    if let Some(key) = &s.key {
+                        p.expr(key);
+                        if let Some(val) = &s.val {
+                            p.w(", ");
+                            p.expr(val);
+                        }
+                        p.w(" ");
+                        p.w(s.token.text());
+                        p.w(" ");
+                    }
+                    p.w("range ");
+                    p.expr(&s.expr);
+                });
+                self.w(" ");
+                self.block(&s.body);
+            }
+        }
+    }
+
+    /// The body of a switch or a select, the clauses aligned with the keyword
+    fn clauses(&mut self, body: &BlockStmt) {
+        self.w("{");
+        for clause in body.list.iter() {
+            self.newline();
+            self.stmt(clause);
+        }
+        self.newline();
+        self.w("}");
+    }
+
+    fn header<F: FnOnce(&mut Self)>(&mut self, f: F) {
+        let header = std::mem::replace(&mut self.header, true);
+        f(self);
+        self.header = header;
+    }
+
+    /// Prints what's between brackets, where composite literals need no parentheses
+    fn nested<F: FnOnce(&mut Self)>(&mut self, f: F) {
+        let header = std::mem::replace(&mut self.header, false);
+        f(self);
+        self.header = header;
+    }
+
+    fn exprs(&mut self, list: &[Expr]) {
+        for (i, e) in list.iter().enumerate() {
+            if i > 0 {
+                self.w(", ");
+            }
+            self.expr(e);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        let objs = self.objs;
+        match expr {
+            Expr::Bad(_) => self.w("BadExpr"),
+            Expr::Ident(key) => self.ident(*key),
+            Expr::Ellipsis(e) => {
+                self.w("...");
+                if let Some(elt) = &e.elt {
+                    self.expr(elt);
+                }
+            }
+            Expr::BasicLit(lit) => self.w(lit.token.get_literal()),
+            Expr::FuncLit(f) => {
+                self.w("func");
+                self.signature(&objs.ftypes[f.typ]);
+                self.w(" ");
+                self.nested(|p| p.block(&f.body));
+            }
+            Expr::CompositeLit(c) => {
+                let paren = self.header && c.typ.is_some();
+                if paren {
+                    self.w("(");
+                }
+                if let Some(t) = &c.typ {
+                    self.expr(t);
+                }
+                self.w("{");
+                self.nested(|p| p.exprs(&c.elts));
+                self.w("}");
+                if paren {
+                    self.w(")");
+                }
+            }
+            Expr::Paren(e) => {
+                self.w("(");
+                self.nested(|p| p.expr(&e.expr));
+                self.w(")");
+            }
+            Expr::Selector(e) => {
+                self.primary(&e.expr);
+                self.w(".");
+                self.ident(e.sel);
+            }
+            Expr::Index(e) => {
+                self.primary(&e.expr);
+                self.w("[");
+                self.nested(|p| p.expr(&e.index));
+                self.w("]");
+            }
+            Expr::Slice(e) => {
+                self.primary(&e.expr);
+                self.w("[");
+                self.nested(|p| {
+                    if let Some(low) = &e.low {
+                        p.expr(low);
+                    }
+                    p.w(":");
+                    if let Some(high) = &e.high {
+                        p.expr(high);
+                    }
+                    if e.slice3 {
+                        p.w(":");
+                        if let Some(max) = &e.max {
+                            p.expr(max);
+                        }
+                    }
+                });
+                self.w("]");
+            }
+            Expr::TypeAssert(e) => {
+                self.primary(&e.expr);
+                self.w(".(");
+                match &e.typ {
+                    Some(t) => self.nested(|p| p.expr(t)),
+                    None => self.w("type"),
+                }
+                self.w(")");
+            }
+            Expr::Call(e) => {
+                self.primary(&e.func);
+                self.w("(");
+                self.nested(|p| p.exprs(&e.args));
+                if e.ellipsis.is_some() {
+                    self.w("...");
+                }
+                self.w(")");
+            }
+            Expr::Star(e) => {
+                self.w("*");
+                self.operand(&e.expr);
+            }
+            Expr::Unary(e) => {
+                self.w(e.op.text());
+                self.operand(&e.expr);
+            }
+            Expr::Binary(e) => {
+                let prec = e.op.precedence();
+                self.binary_operand(&e.expr_a, prec, false);
+                self.w(" ");
+                self.w(e.op.text());
+                self.w(" ");
+                self.binary_operand(&e.expr_b, prec, true);
+            }
+            Expr::KeyValue(e) => {
+                self.expr(&e.key);
+                self.w(": ");
+                self.expr(&e.val);
+            }
+            Expr::Array(e) => {
+                self.w("[");
+                if let Some(len) = &e.len {
+                    self.nested(|p| p.expr(len));
+                }
+                self.w("]");
+                self.expr(&e.elt);
+            }
+            Expr::Struct(s) => {
+                self.w("struct");
+                self.field_block(&s.fields, false);
+            }
+            Expr::Func(key) => {
+                self.w("func");
+                self.signature(&objs.ftypes[*key]);
+            }
+            Expr::Interface(s) => {
+                self.w("interface");
+                self.field_block(&s.methods, true);
+            }
+            Expr::Map(e) => {
+                self.w("map[");
+                self.nested(|p| p.expr(&e.key));
+                self.w("]");
+                self.expr(&e.val);
+            }
+            Expr::Chan(e) => {
+                match e.dir {
+                    ChanDir::Send => self.w("chan<- "),
+                    ChanDir::Recv => self.w("<-chan "),
+                    ChanDir::SendRecv => self.w("chan "),
+                }
+                match &e.val {
+                    // chan (<-chan T), not (chan<- chan T)
+                    Expr::Chan(v) if v.dir == ChanDir::Recv && e.dir != ChanDir::Recv => {
+                        self.w("(");
+                        self.expr(&e.val);
+                        self.w(")");
+                    }
+                    v => self.expr(v),
+                }
+            }
+        }
+    }
+
+    /// The operand of a selector, an index, a slice, an assertion or a call
+    fn primary(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Star(_) | Expr::Unary(_) | Expr::Binary(_) | Expr::Func(_) | Expr::Chan(_) => {
+                self.w("(");
+                self.nested(|p| p.expr(expr));
+                self.w(")");
+            }
+            _ => self.expr(expr),
+        }
+    }
+
+    /// The operand of a unary operator, or of a *
+    fn operand(&mut self, expr: &Expr) {
+        match expr {
+            // - -x would be --x
+            Expr::Binary(_) | Expr::Unary(_) => {
+                self.w("(");
+                self.nested(|p| p.expr(expr));
+                self.w(")");
+            }
+            _ => self.expr(expr),
+        }
+    }
+
+    fn binary_operand(&mut self, expr: &Expr, prec: usize, right: bool) {
+        match expr {
+            Expr::Binary(b) if b.op.precedence() < prec || right && b.op.precedence() == prec => {
+                self.w("(");
+                self.nested(|p| p.expr(expr));
+                self.w(")");
+            }
+            _ => self.expr(expr),
+        }
+    }
+
+    /// The parameters and the results of a func, without the `func`
+    fn signature(&mut self, typ: &FuncType) {
+        self.params(&typ.params);
+        if let Some(results) = &typ.results {
+            let objs = self.objs;
+            match results.list.as_slice() {
+                [] => {}
+                [single] if objs.fields[*single].names.is_empty() => {
+                    self.w(" ");
+                    self.expr(&objs.fields[*single].typ);
+                }
+                _ => {
+                    self.w(" ");
+                    self.params(results);
+                }
+            }
+        }
+    }
+
+    fn params(&mut self, list: &FieldList) {
+        let objs = self.objs;
+        self.w("(");
+        self.nested(|p| {
+            for (i, key) in list.list.iter().enumerate() {
+                if i > 0 {
+                    p.w(", ");
+                }
+                let field = &objs.fields[*key];
+                if !field.names.is_empty() {
+                    p.idents(&field.names);
+                    p.w(" ");
+                }
+                p.expr(&field.typ);
+            }
+        });
+        self.w(")");
+    }
+
+    /// The fields of a struct, or the methods of an interface, one a line
+    fn field_block(&mut self, list: &FieldList, methods: bool) {
+        let objs = self.objs;
+        if list.list.is_empty() {
+            self.w("{}");
+            return;
+        }
+        self.w(" {");
+        self.indent += 1;
+        for key in list.list.iter() {
+            self.newline();
+            let field = &objs.fields[*key];
+            match &field.typ {
+                Expr::Func(ft) if methods && !field.names.is_empty() => {
+                    self.idents(&field.names);
+                    self.signature(&objs.ftypes[*ft]);
+                }
+                typ => {
+                    if !field.names.is_empty() {
+                        self.idents(&field.names);
+                        self.w(" ");
+                    }
+                    self.expr(typ);
+                }
+            }
+            if let Some(tag) = &field.tag {
+                self.w(" ");
+                self.expr(tag);
+            }
+        }
+        self.indent -= 1;
+        self.newline();
+        self.w("}");
+    }
+}
@@ -0,0 +1,127 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Changing source by the spans of its nodes, leaving the rest of it as it was.
+//!
+//! Where `printer` lays the whole file out again, a `Rewriter` only touches the
+//! spans it's told to, so the comments and the formatting around them are kept.
+//! The new text of a span can be anything, e.g. a node printed with `printer`.
+
+use super::ast::Node;
+use super::objects::AstObjects;
+use super::position::{File, Pos};
+
+#[derive(Debug)]
+struct Edit {
+    from: usize,
+    to: usize,
+    text: String,
+}
+
+/// Edits to the source of a file, applied all at once by `finish`
+///
+/// The spans are those of the AST parsed from the same source, with positions
+/// relative to the `base` of its `File`. The edits can be made in any order, but
+/// two of them can't change overlapping spans; text inserted at the same place
+/// comes out in the order it was inserted.
+#[derive(Debug)]
+pub struct Rewriter<'a> {
+    src: &'a str,
+    base: usize,
+    edits: Vec<Edit>,
+}
+
+impl<'a> Rewriter<'a> {
+    pub fn new(file: &File, src: &'a str) -> Rewriter<'a> {
+        Rewriter {
+            src,
+            base: file.base(),
+            edits: vec![],
+        }
+    }
+
+    /// The source of the node as it was parsed
+    pub fn source<N: Node>(&self, objs: &AstObjects, node: &N) -> &'a str {
+        let (from, to) = self.span(objs, node);
+        let (from, to) = (self.byte_offset(from), self.byte_offset(to));
+        &self.src[from..to]
+    }
+
+    pub fn replace<N: Node>(&mut self, objs: &AstObjects, node: &N, text: &str) {
+        let (from, to) = self.span(objs, node);
+        self.edit(from, to, text);
+    }
+
+    pub fn remove<N: Node>(&mut self, objs: &AstObjects, node: &N) {
+        self.replace(objs, node, "");
+    }
+
+    pub fn insert_before<N: Node>(&mut self, objs: &AstObjects, node: &N, text: &str) {
+        let (from, _) = self.span(objs, node);
+        self.edit(from, from, text);
+    }
+
+    pub fn insert_after<N: Node>(&mut self, objs: &AstObjects, node: &N, text: &str) {
+        let (_, to) = self.span(objs, node);
+        self.edit(to, to, text);
+    }
+
+    /// Replaces the source from `from` to `to`, for the spans that aren't those of
+    /// a node, like the one between two statements
+    pub fn replace_span(&mut self, from: Pos, to: Pos, text: &str) {
+        let (from, to) = (self.offset(from), self.offset(to));
+        self.edit(from, to, text);
+    }
+
+    /// Applies the edits to the source
+    pub fn finish(mut self) -> String {
+        // stable, for the insertions at the same place to keep their order
+        self.edits.sort_by_key(|e| (e.from, e.to));
+        let mut result = String::with_capacity(self.src.len());
+        let mut done = 0;
+        let mut last: Option<&Edit> = None;
+        for e in self.edits.iter() {
+            if let Some(l) = last {
+                if e.from < l.to {
+                    panic!(
+                        "overlapping edits at {}..{} and {}..{}",
+                        l.from, l.to, e.from, e.to
+                    );
+                }
+            }
+            let from = self.byte_offset(e.from);
+            result.push_str(&self.src[done..from]);
+            result.push_str(&e.text);
+            done = self.byte_offset(e.to);
+            last = Some(e);
+        }
+        result.push_str(&self.src[done..]);
+        result
+    }
+
+    fn edit(&mut self, from: usize, to: usize, text: &str) {
+        self.edits.push(Edit {
+            from,
+            to,
+            text: text.to_owned(),
+        });
+    }
+
+    fn span<N: Node>(&self, objs: &AstObjects, node: &N) -> (usize, usize) {
+        (self.offset(node.pos(objs)), self.offset(node.end(objs)))
+    }
+
+    /// The offset in chars of a position in the file
+    fn offset(&self, p: Pos) -> usize {
+        assert!(p >= self.base, "position {} is not in the file", p);
+        p - self.base
+    }
+
+    fn byte_offset(&self, chars: usize) -> usize {
+        self.src
+            .char_indices()
+            .nth(chars)
+            .map_or(self.src.len(), |(i, _)| i)
+    }
+}
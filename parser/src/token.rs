@@ -251,6 +251,31 @@ impl Token {
         Token::INT("1".to_owned().into())
     }
 
+    /// The token of an integer literal
+    pub fn int_lit(i: i64) -> Token {
+        Token::INT(i.to_string().into())
+    }
+
+    /// The token of a string literal of `s`, quoted with its special characters
+    /// escaped
+    pub fn string_lit(s: &str) -> Token {
+        let mut lit = String::with_capacity(s.len() + 2);
+        lit.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => lit.push_str("\\\""),
+                '\\' => lit.push_str("\\\\"),
+                '\n' => lit.push_str("\\n"),
+                '\t' => lit.push_str("\\t"),
+                '\r' => lit.push_str("\\r"),
+                c if c.is_control() => lit.push_str(&format!("\\u{:04x}", c as u32)),
+                c => lit.push(c),
+            }
+        }
+        lit.push('"');
+        Token::STRING((lit, s.to_owned()).into())
+    }
+
     pub fn precedence(&self) -> usize {
         match self {
             Token::LOR => 1,
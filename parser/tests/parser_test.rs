@@ -57,3 +57,120 @@ fn test_issue3() {
     let (p, _) = fe::parse_file(o, &mut fs, el, "/a", "`", false);
     print!("{}", p.get_errors());
 }
+
+fn parse_print(name: &str, src: &str) -> String {
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let (p, file) = fe::parse_file(o, &mut fs, el, name, src, false);
+    assert_eq!(p.get_errors().len(), 0, "{}: {}", name, p.get_errors());
+    fe::printer::print_file(o, &file.unwrap())
+}
+
+fn round_trip_dir(dir: &std::path::Path) -> usize {
+    let mut count = 0;
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            count += round_trip_dir(&path);
+            continue;
+        }
+        let name = path.to_str().unwrap();
+        if !(name.ends_with(".go") || name.ends_with(".gos")) {
+            continue;
+        }
+        let src = fs::read_to_string(&path).unwrap();
+        let mut fs = fe::FileSet::new();
+        let o = &mut fe::AstObjects::new();
+        let el = &mut fe::ErrorList::new();
+        let (p, _) = fe::parse_file(o, &mut fs, el, name, &src, false);
+        if p.get_errors().len() > 0 {
+            // some of the test scripts are meant not to parse
+            continue;
+        }
+        let printed = parse_print(name, &src);
+        assert_eq!(printed, parse_print(name, &printed), "{}", name);
+        count += 1;
+    }
+    count
+}
+
+#[test]
+fn test_print_round_trip() {
+    let count = round_trip_dir(std::path::Path::new("./../std"))
+        + round_trip_dir(std::path::Path::new("./../engine/tests"));
+    assert!(count > 100);
+}
+
+#[test]
+fn test_print_built() {
+    let o = &mut fe::AstObjects::new();
+    let a = fe::ast::Expr::new_ident(o, 0, "a");
+    let b = fe::ast::Expr::new_ident(o, 0, "b");
+    let c = fe::ast::Expr::new_basic_lit(0, fe::Token::int_lit(2));
+    let sum = fe::ast::Expr::new_binary_expr(a, 0, fe::Token::ADD, b);
+    let prod = fe::ast::Expr::new_binary_expr(sum, 0, fe::Token::MUL, c);
+    let neg = fe::ast::Expr::new_unary_expr(0, fe::Token::SUB, prod.clone());
+    let println = fe::ast::Expr::new_ident(o, 0, "println");
+    let s = fe::ast::Expr::new_basic_lit(0, fe::Token::string_lit("a \"b\"\n"));
+    let call = fe::ast::Expr::new_call(println, vec![s, neg]);
+    let stmt = fe::ast::Stmt::new_expr(call);
+    assert_eq!(
+        fe::printer::print_stmt(o, &stmt),
+        "println(\"a \\\"b\\\"\\n\", -((a + b) * 2))"
+    );
+    let x = fe::ast::Expr::new_ident(o, 0, "x");
+    let sub = fe::ast::Expr::new_binary_expr(x, 0, fe::Token::SUB, prod);
+    assert_eq!(fe::printer::print_expr(o, &sub), "x - (a + b) * 2");
+}
+
+#[test]
+fn test_rewrite() {
+    let src = "package main\n\n// adds é\nfunc add(a, b int) int {\n\treturn a + b // sum\n}\n";
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let (p, file) = fe::parse_file(o, &mut fs, el, "a.go", src, false);
+    assert_eq!(p.get_errors().len(), 0);
+    let file = file.unwrap();
+    let fdecl = match &file.decls[0] {
+        fe::ast::Decl::Func(f) => &o.fdecls[*f],
+        _ => unreachable!(),
+    };
+    let ret = match &fdecl.body.as_ref().unwrap().list[0] {
+        fe::ast::Stmt::Return(r) => r.clone(),
+        _ => unreachable!(),
+    };
+    let mut rw = fe::rewrite::Rewriter::new(fs.recent_file().unwrap(), src);
+    let sum = &ret.results[0];
+    assert_eq!(rw.source(o, sum), "a + b");
+    rw.replace(o, sum, "a*2 + b");
+    rw.insert_before(o, &file.decls[0], "var n = 0\n\n");
+    rw.insert_after(o, &file.decls[0], "\n\nfunc one() int { return 1 }");
+    assert_eq!(
+        rw.finish(),
+        "package main\n\n// adds é\nvar n = 0\n\nfunc add(a, b int) int {\n\treturn a*2 + b // sum\n}\n\nfunc one() int { return 1 }\n"
+    );
+}
+
+#[test]
+fn test_decl_recovery() {
+    for typing in ["x := ", "p.sum(", "if x ", "v := point{x: ", "fmt."] {
+        let src = format!(
+            "package main\n\nfunc typing() {{\n\t{}\n}}\n\nfunc after() int {{\n\treturn 1\n}}\n\ntype point struct{{ x int }}\n",
+            typing
+        );
+        let mut fs = fe::FileSet::new();
+        let o = &mut fe::AstObjects::new();
+        let el = &mut fe::ErrorList::new();
+        let (p, file) = fe::parse_file(o, &mut fs, el, "a.go", &src, false);
+        // the one error of the statement being typed, the declarations after it parsed
+        assert_eq!(p.get_errors().len(), 1, "{}", typing);
+        let decls = file.unwrap().decls;
+        assert_eq!(decls.len(), 3, "{}", typing);
+        match &decls[1] {
+            fe::ast::Decl::Func(f) => assert_eq!(o.idents[o.fdecls[*f].name].name, "after"),
+            _ => panic!("{}", typing),
+        }
+    }
+}
@@ -244,6 +244,18 @@ const (
 	BothDir = RecvDir | SendDir             // chan
 )
 
+func (d ChanDir) String() string {
+	switch d {
+	case SendDir:
+		return "chan<-"
+	case RecvDir:
+		return "<-chan"
+	case BothDir:
+		return "chan"
+	}
+	return "ChanDir" + strconv.Itoa(int(d))
+}
+
 // Method represents a single method.
 type Method struct {
 	// Name is the method name.
@@ -369,11 +381,13 @@ func (t reflectType) NumMethod() int {
 }
 
 func (t reflectType) Name() string {
-	panic("not implemented")
+	name, _ := native.type_name(t.typePtr)
+	return name
 }
 
 func (t reflectType) PkgPath() string {
-	panic("not implemented")
+	_, path := native.type_name(t.typePtr)
+	return path
 }
 
 func (t reflectType) Size() uintptr {
@@ -381,7 +395,7 @@ func (t reflectType) Size() uintptr {
 }
 
 func (t reflectType) String() string {
-	return t.kind.String()
+	return native.type_string(t.typePtr)
 }
 
 func (t reflectType) Kind() Kind {
@@ -409,7 +423,7 @@ func (t reflectType) Bits() int {
 }
 
 func (t reflectType) ChanDir() ChanDir {
-	panic("not implemented")
+	return ChanDir(native.chan_dir(t.typePtr))
 }
 
 func (t reflectType) IsVariadic() bool {
@@ -22,7 +22,9 @@ type ffiReflect interface {
 	int_val(p unsafe.Pointer) int64
 	uint_val(p unsafe.Pointer) uint64
 	float_val(p unsafe.Pointer) float64
+	string_val(p unsafe.Pointer) string
 	bytes_val(p unsafe.Pointer) []byte
+	interface_val(p unsafe.Pointer) interface{}
 	elem(p unsafe.Pointer) unsafe.Pointer
 	num_field(p unsafe.Pointer) int
 	field(p unsafe.Pointer, i int) unsafe.Pointer
@@ -36,6 +38,7 @@ type ffiReflect interface {
 	map_range_value(p unsafe.Pointer) unsafe.Pointer
 
 	can_addr(p unsafe.Pointer) bool
+	can_interface(p unsafe.Pointer) bool
 	can_set(p unsafe.Pointer) bool
 	set(p unsafe.Pointer, v unsafe.Pointer)
 	set_bool(p unsafe.Pointer, b bool)
@@ -47,6 +50,11 @@ type ffiReflect interface {
 	set_pointer(p unsafe.Pointer, x unsafe.Pointer)
 	set_string(p unsafe.Pointer, x string)
 
+	type_string(t unsafe.Pointer) string
+	type_name(t unsafe.Pointer) (string, string)
+	chan_dir(t unsafe.Pointer) int
+	close(p unsafe.Pointer)
+
 	swap(slice interface{}, i int, j int)
 }
 
@@ -149,7 +157,7 @@ func (v Value) Cap() int {
 // Close closes the channel v.
 // It panics if v's Kind is not Chan.
 func (v Value) Close() {
-	panic("not implemented")
+	native.close(v.ptr)
 }
 
 // Complex returns v's underlying value, as a complex128.
@@ -216,7 +224,7 @@ func (v Value) Int() int64 {
 
 // CanInterface reports whether Interface can be used without panicking.
 func (v Value) CanInterface() bool {
-	return false
+	return v.IsValid() && native.can_interface(v.ptr)
 }
 
 // Interface returns v's current value as an interface{}.
@@ -225,7 +233,7 @@ func (v Value) CanInterface() bool {
 // It panics if the Value was obtained by accessing
 // unexported struct fields.
 func (v Value) Interface() (i interface{}) {
-	panic("not implemented")
+	return native.interface_val(v.ptr)
 }
 
 // InterfaceData returns the interface v's value as a uintptr pair.
@@ -501,7 +509,10 @@ func (v Value) Slice3(i, j, k int) Value {
 // The fmt package treats Values specially. It does not call their String
 // method implicitly but instead prints the concrete values they hold.
 func (v Value) String() string {
-	panic("not implemented")
+	if v.Kind() == String {
+		return native.string_val(v.ptr)
+	}
+	return "<" + v.Type().String() + " Value>"
 }
 
 // TryRecv attempts to receive a value from the channel v but will not block.
@@ -6,15 +6,19 @@ package strings
 
 import (
 	"unicode/utf8"
-	"unsafe"
 )
 
 // A Builder is used to efficiently build a string using Write methods.
 // It minimizes memory copying. The zero value is ready to use.
 // Do not copy a non-zero Builder.
+//
+// The bytes are kept in a string rather than in a []byte: the VM appends to
+// a string in place when it's the last one made from its bytes, so String is
+// free and needs no unsafe conversion.
 type Builder struct {
 	addr *Builder // of receiver, to detect copies by value
-	buf  []byte
+	s    string
+	cap  int // as grown by Grow
 }
 
 func (b *Builder) copyCheck() {
@@ -27,29 +31,27 @@ func (b *Builder) copyCheck() {
 
 // String returns the accumulated string.
 func (b *Builder) String() string {
-	return *(*string)(unsafe.Pointer(&b.buf))
+	return b.s
 }
 
 // Len returns the number of accumulated bytes; b.Len() == len(b.String()).
-func (b *Builder) Len() int { return len(b.buf) }
+func (b *Builder) Len() int { return len(b.s) }
 
 // Cap returns the capacity of the builder's underlying byte slice. It is the
 // total space allocated for the string being built and includes any bytes
 // already written.
-func (b *Builder) Cap() int { return cap(b.buf) }
+func (b *Builder) Cap() int {
+	if b.cap < len(b.s) {
+		return len(b.s)
+	}
+	return b.cap
+}
 
 // Reset resets the Builder to be empty.
 func (b *Builder) Reset() {
 	b.addr = nil
-	b.buf = nil
-}
-
-// grow copies the buffer to a new, larger buffer so that there are at least n
-// bytes of capacity beyond len(b.buf).
-func (b *Builder) grow(n int) {
-	buf := make([]byte, len(b.buf), 2*cap(b.buf)+n)
-	copy(buf, b.buf)
-	b.buf = buf
+	b.s = ""
+	b.cap = 0
 }
 
 // Grow grows b's capacity, if necessary, to guarantee space for
@@ -60,8 +62,8 @@ func (b *Builder) Grow(n int) {
 	if n < 0 {
 		panic("strings.Builder.Grow: negative count")
 	}
-	if cap(b.buf)-len(b.buf) < n {
-		b.grow(n)
+	if b.Cap()-len(b.s) < n {
+		b.cap = 2*b.Cap() + n
 	}
 }
 
@@ -69,7 +71,7 @@ func (b *Builder) Grow(n int) {
 // Write always returns len(p), nil.
 func (b *Builder) Write(p []byte) (int, error) {
 	b.copyCheck()
-	b.buf = append(b.buf, p...)
+	b.s += string(p)
 	return len(p), nil
 }
 
@@ -77,7 +79,7 @@ func (b *Builder) Write(p []byte) (int, error) {
 // The returned error is always nil.
 func (b *Builder) WriteByte(c byte) error {
 	b.copyCheck()
-	b.buf = append(b.buf, c)
+	b.s += string([]byte{c})
 	return nil
 }
 
@@ -86,22 +88,18 @@ func (b *Builder) WriteByte(c byte) error {
 func (b *Builder) WriteRune(r rune) (int, error) {
 	b.copyCheck()
 	if r < utf8.RuneSelf {
-		b.buf = append(b.buf, byte(r))
+		b.s += string([]byte{byte(r)})
 		return 1, nil
 	}
-	l := len(b.buf)
-	if cap(b.buf)-l < utf8.UTFMax {
-		b.grow(utf8.UTFMax)
-	}
-	n := utf8.EncodeRune(b.buf[l:l+utf8.UTFMax], r)
-	b.buf = b.buf[:l+n]
-	return n, nil
+	s := string(r)
+	b.s += s
+	return len(s), nil
 }
 
 // WriteString appends the contents of s to b's buffer.
 // It returns the length of s and a nil error.
 func (b *Builder) WriteString(s string) (int, error) {
 	b.copyCheck()
-	b.buf = append(b.buf, s...)
+	b.s += s
 	return len(s), nil
 }
@@ -36,43 +36,6 @@ func explode(s string, n int) []string {
 	return a
 }
 
-// primeRK is the prime base used in Rabin-Karp algorithm.
-const primeRK = 16777619
-
-// hashStr returns the hash and the appropriate multiplicative
-// factor for use in Rabin-Karp algorithm.
-func hashStr(sep string) (uint32, uint32) {
-	hash := uint32(0)
-	for i := 0; i < len(sep); i++ {
-		hash = hash*primeRK + uint32(sep[i])
-	}
-	var pow, sq uint32 = 1, primeRK
-	for i := len(sep); i > 0; i >>= 1 {
-		if i&1 != 0 {
-			pow *= sq
-		}
-		sq *= sq
-	}
-	return hash, pow
-}
-
-// hashStrRev returns the hash of the reverse of sep and the
-// appropriate multiplicative factor for use in Rabin-Karp algorithm.
-func hashStrRev(sep string) (uint32, uint32) {
-	hash := uint32(0)
-	for i := len(sep) - 1; i >= 0; i-- {
-		hash = hash*primeRK + uint32(sep[i])
-	}
-	var pow, sq uint32 = 1, primeRK
-	for i := len(sep); i > 0; i >>= 1 {
-		if i&1 != 0 {
-			pow *= sq
-		}
-		sq *= sq
-	}
-	return hash, pow
-}
-
 // Count counts the number of non-overlapping instances of substr in s.
 // If substr is an empty string, Count returns 1 + the number of Unicode code points in s.
 func Count(s, substr string) int {
@@ -80,18 +43,7 @@ func Count(s, substr string) int {
 	if len(substr) == 0 {
 		return utf8.RuneCountInString(s) + 1
 	}
-	if len(substr) == 1 {
-		return bytealg.CountString(s, substr[0])
-	}
-	n := 0
-	for {
-		i := Index(s, substr)
-		if i == -1 {
-			return n
-		}
-		n++
-		s = s[i+len(substr):]
-	}
+	return native.count(s, substr)
 }
 
 // Contains reports whether substr is within s.
@@ -111,39 +63,7 @@ func ContainsRune(s string, r rune) bool {
 
 // LastIndex returns the index of the last instance of substr in s, or -1 if substr is not present in s.
 func LastIndex(s, substr string) int {
-	n := len(substr)
-	switch {
-	case n == 0:
-		return len(s)
-	case n == 1:
-		return LastIndexByte(s, substr[0])
-	case n == len(s):
-		if substr == s {
-			return 0
-		}
-		return -1
-	case n > len(s):
-		return -1
-	}
-	// Rabin-Karp search from the end of the string
-	hashss, pow := hashStrRev(substr)
-	last := len(s) - n
-	var h uint32
-	for i := len(s) - 1; i >= last; i-- {
-		h = h*primeRK + uint32(s[i])
-	}
-	if h == hashss && s[last:] == substr {
-		return last
-	}
-	for i := last - 1; i >= 0; i-- {
-		h *= primeRK
-		h += uint32(s[i])
-		h -= pow * uint32(s[i+n])
-		if h == hashss && s[i:i+n] == substr {
-			return i
-		}
-	}
-	return -1
+	return native.last_index(s, substr)
 }
 
 // IndexByte returns the index of the first instance of c in s, or -1 if c is not present in s.
@@ -249,24 +169,7 @@ func genSplit(s, sep string, sepSave, n int) []string {
 	if sep == "" {
 		return explode(s, n)
 	}
-	if n < 0 {
-		n = Count(s, sep) + 1
-	}
-
-	a := make([]string, n)
-	n--
-	i := 0
-	for i < n {
-		m := Index(s, sep)
-		if m < 0 {
-			break
-		}
-		a[i] = s[:m+sepSave]
-		s = s[m+len(sep):]
-		i++
-	}
-	a[i] = s
-	return a[:i+1]
+	return native.split(s, sep, sepSave, n)
 }
 
 // SplitN slices s into substrings separated by sep and returns a slice of
@@ -321,58 +224,11 @@ func SplitAfter(s, sep string) []string {
 	return genSplit(s, sep, len(sep), -1)
 }
 
-var asciiSpace = [256]uint8{'\t': 1, '\n': 1, '\v': 1, '\f': 1, '\r': 1, ' ': 1}
-
 // Fields splits the string s around each instance of one or more consecutive white space
 // characters, as defined by unicode.IsSpace, returning a slice of substrings of s or an
 // empty slice if s contains only white space.
 func Fields(s string) []string {
-	// First count the fields.
-	// This is an exact count if s is ASCII, otherwise it is an approximation.
-	n := 0
-	wasSpace := 1
-	// setBits is used to track which bits are set in the bytes of s.
-	setBits := uint8(0)
-	for i := 0; i < len(s); i++ {
-		r := s[i]
-		setBits |= r
-		isSpace := int(asciiSpace[r])
-		n += wasSpace & ^isSpace
-		wasSpace = isSpace
-	}
-
-	if setBits < utf8.RuneSelf { // ASCII fast path
-		a := make([]string, n)
-		na := 0
-		fieldStart := 0
-		i := 0
-		// Skip spaces in the front of the input.
-		for i < len(s) && asciiSpace[s[i]] != 0 {
-			i++
-		}
-		fieldStart = i
-		for i < len(s) {
-			if asciiSpace[s[i]] == 0 {
-				i++
-				continue
-			}
-			a[na] = s[fieldStart:i]
-			na++
-			i++
-			// Skip spaces in between fields.
-			for i < len(s) && asciiSpace[s[i]] != 0 {
-				i++
-			}
-			fieldStart = i
-		}
-		if fieldStart < len(s) { // Last field might end at EOF.
-			a[na] = s[fieldStart:]
-		}
-		return a
-	}
-
-	// Some runes in the input string are not ASCII.
-	return FieldsFunc(s, unicode.IsSpace)
+	return native.fields(s)
 }
 
 // FieldsFunc splits the string s at each run of Unicode code points c satisfying f(c)
@@ -429,29 +285,17 @@ func Join(a []string, sep string) string {
 	case 1:
 		return a[0]
 	}
-	n := len(sep) * (len(a) - 1)
-	for i := 0; i < len(a); i++ {
-		n += len(a[i])
-	}
-
-	var b Builder
-	b.Grow(n)
-	b.WriteString(a[0])
-	for _, s := range a[1:] {
-		b.WriteString(sep)
-		b.WriteString(s)
-	}
-	return b.String()
+	return native.join(a, sep)
 }
 
 // HasPrefix tests whether the string s begins with prefix.
 func HasPrefix(s, prefix string) bool {
-	return len(s) >= len(prefix) && s[0:len(prefix)] == prefix
+	return native.has_prefix(s, prefix)
 }
 
 // HasSuffix tests whether the string s ends with suffix.
 func HasSuffix(s, suffix string) bool {
-	return len(s) >= len(suffix) && s[len(s)-len(suffix):] == suffix
+	return native.has_suffix(s, suffix)
 }
 
 // Map returns a copy of the string s with all its characters modified
@@ -552,60 +396,16 @@ func Repeat(s string, count int) string {
 
 // ToUpper returns a copy of the string s with all Unicode letters mapped to their upper case.
 func ToUpper(s string) string {
-	isASCII, hasLower := true, false
-	for i := 0; i < len(s); i++ {
-		c := s[i]
-		if c >= utf8.RuneSelf {
-			isASCII = false
-			break
-		}
-		hasLower = hasLower || (c >= 'a' && c <= 'z')
-	}
-
-	if isASCII { // optimize for ASCII-only strings.
-		if !hasLower {
-			return s
-		}
-		var b Builder
-		b.Grow(len(s))
-		for i := 0; i < len(s); i++ {
-			c := s[i]
-			if c >= 'a' && c <= 'z' {
-				c -= 'a' - 'A'
-			}
-			b.WriteByte(c)
-		}
-		return b.String()
+	if u, isASCII := native.to_upper(s); isASCII {
+		return u
 	}
 	return Map(unicode.ToUpper, s)
 }
 
 // ToLower returns a copy of the string s with all Unicode letters mapped to their lower case.
 func ToLower(s string) string {
-	isASCII, hasUpper := true, false
-	for i := 0; i < len(s); i++ {
-		c := s[i]
-		if c >= utf8.RuneSelf {
-			isASCII = false
-			break
-		}
-		hasUpper = hasUpper || (c >= 'A' && c <= 'Z')
-	}
-
-	if isASCII { // optimize for ASCII-only strings.
-		if !hasUpper {
-			return s
-		}
-		var b Builder
-		b.Grow(len(s))
-		for i := 0; i < len(s); i++ {
-			c := s[i]
-			if c >= 'A' && c <= 'Z' {
-				c += 'a' - 'A'
-			}
-			b.WriteByte(c)
-		}
-		return b.String()
+	if l, isASCII := native.to_lower(s); isASCII {
+		return l
 	}
 	return Map(unicode.ToLower, s)
 }
@@ -770,27 +570,13 @@ func (as *asciiSet) contains(c byte) bool {
 	return (as[c>>5] & (1 << uint(c&31))) != 0
 }
 
-func makeCutsetFunc(cutset string) func(rune) bool {
-	if len(cutset) == 1 && cutset[0] < utf8.RuneSelf {
-		return func(r rune) bool {
-			return r == rune(cutset[0])
-		}
-	}
-	if as, isASCII := makeASCIISet(cutset); isASCII {
-		return func(r rune) bool {
-			return r < utf8.RuneSelf && as.contains(byte(r))
-		}
-	}
-	return func(r rune) bool { return IndexRune(cutset, r) >= 0 }
-}
-
 // Trim returns a slice of the string s with all leading and
 // trailing Unicode code points contained in cutset removed.
 func Trim(s string, cutset string) string {
 	if s == "" || cutset == "" {
 		return s
 	}
-	return TrimFunc(s, makeCutsetFunc(cutset))
+	return native.trim(s, cutset, true, true)
 }
 
 // TrimLeft returns a slice of the string s with all leading
@@ -801,7 +587,7 @@ func TrimLeft(s string, cutset string) string {
 	if s == "" || cutset == "" {
 		return s
 	}
-	return TrimLeftFunc(s, makeCutsetFunc(cutset))
+	return native.trim(s, cutset, true, false)
 }
 
 // TrimRight returns a slice of the string s, with all trailing
@@ -812,13 +598,13 @@ func TrimRight(s string, cutset string) string {
 	if s == "" || cutset == "" {
 		return s
 	}
-	return TrimRightFunc(s, makeCutsetFunc(cutset))
+	return native.trim(s, cutset, false, true)
 }
 
 // TrimSpace returns a slice of the string s, with all leading
 // and trailing white space removed, as defined by Unicode.
 func TrimSpace(s string) string {
-	return TrimFunc(s, unicode.IsSpace)
+	return native.trim_space(s)
 }
 
 // TrimPrefix returns s without the provided leading prefix string.
@@ -849,6 +635,9 @@ func Replace(s, old, new string, n int) string {
 	if old == new || n == 0 {
 		return s // avoid allocation
 	}
+	if old != "" {
+		return native.replace(s, old, new, n)
+	}
 
 	// Compute number of replacements.
 	if m := Count(s, old); m == 0 {
@@ -945,107 +734,5 @@ func EqualFold(s, t string) bool {
 
 // Index returns the index of the first instance of substr in s, or -1 if substr is not present in s.
 func Index(s, substr string) int {
-	n := len(substr)
-	switch {
-	case n == 0:
-		return 0
-	case n == 1:
-		return IndexByte(s, substr[0])
-	case n == len(s):
-		if substr == s {
-			return 0
-		}
-		return -1
-	case n > len(s):
-		return -1
-		// goscript: disabled for now
-		/*
-			case n <= bytealg.MaxLen:
-				// Use brute force when s and substr both are small
-				if len(s) <= bytealg.MaxBruteForce {
-					return bytealg.IndexString(s, substr)
-				}
-				c0 := substr[0]
-				c1 := substr[1]
-				i := 0
-				t := len(s) - n + 1
-				fails := 0
-				for i < t {
-					if s[i] != c0 {
-						// IndexByte is faster than bytealg.IndexString, so use it as long as
-						// we're not getting lots of false positives.
-						o := IndexByte(s[i:t], c0)
-						if o < 0 {
-							return -1
-						}
-						i += o
-					}
-					if s[i+1] == c1 && s[i:i+n] == substr {
-						return i
-					}
-					fails++
-					i++
-					// Switch to bytealg.IndexString when IndexByte produces too many false positives.
-					if fails > bytealg.Cutover(i) {
-						r := bytealg.IndexString(s[i:], substr)
-						if r >= 0 {
-							return r + i
-						}
-						return -1
-					}
-				}
-				return -1
-		*/
-	}
-	c0 := substr[0]
-	c1 := substr[1]
-	i := 0
-	t := len(s) - n + 1
-	fails := 0
-	for i < t {
-		if s[i] != c0 {
-			o := IndexByte(s[i:t], c0)
-			if o < 0 {
-				return -1
-			}
-			i += o
-		}
-		if s[i+1] == c1 && s[i:i+n] == substr {
-			return i
-		}
-		i++
-		fails++
-		if fails >= 4+i>>4 && i < t {
-			// See comment in ../bytes/bytes_generic.go.
-			j := indexRabinKarp(s[i:], substr)
-			if j < 0 {
-				return -1
-			}
-			return i + j
-		}
-	}
-	return -1
-}
-
-func indexRabinKarp(s, substr string) int {
-	// Rabin-Karp search
-	hashss, pow := hashStr(substr)
-	n := len(substr)
-	var h uint32
-	for i := 0; i < n; i++ {
-		h = h*primeRK + uint32(s[i])
-	}
-	if h == hashss && s[:n] == substr {
-		return 0
-	}
-	for i := n; i < len(s); {
-		h *= primeRK
-		h += uint32(s[i])
-		h -= pow * uint32(s[i-n])
-		i++
-		if h == hashss && s[i-n:i] == substr {
-			return i - n
-		}
-	}
-	return -1
+	return native.index(s, substr)
 }
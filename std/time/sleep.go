@@ -4,21 +4,47 @@
 
 package time
 
+import "unsafe"
+
+// goscript: the sleeps and the timers are on the scheduler of the VM
+type ffiTimer interface {
+	async_sleep(d int64)
+	new_timer() unsafe.Pointer
+	async_wait(t unsafe.Pointer, when int64) bool
+	stop(t unsafe.Pointer) bool
+}
+
+// only there with the async feature, it's looked up when first used
+var nativeTimer ffiTimer
+
+func timers() ffiTimer {
+	if nativeTimer == nil {
+		nativeTimer = ffi(ffiTimer, "time.timer")
+	}
+	return nativeTimer
+}
+
 // Sleep pauses the current goroutine for at least the duration d.
 // A negative or zero duration causes Sleep to return immediately.
-func Sleep(d Duration)
+func Sleep(d Duration) {
+	if d <= 0 {
+		return
+	}
+	timers().async_sleep(int64(d))
+}
 
 // Interface to timers implemented in package runtime.
-// Must be in sync with ../runtime/time.go:/^type timer
+// goscript: a timer is run by a goroutine waiting for it in the scheduler, which
+// doesn't keep the program running while it waits.
 type runtimeTimer struct {
-	tb uintptr
-	i  int
-
 	when   int64
 	period int64
 	f      func(interface{}, uintptr) // NOTE: must not be closure
 	arg    interface{}
 	seq    uintptr
+
+	// the timer waited for, nil if it's never started
+	handle unsafe.Pointer
 }
 
 // when is a helper function for setting the 'when' field of a runtimeTimer.
@@ -36,13 +62,31 @@ func when(d Duration) int64 {
 	return t
 }
 
-// goscript: todo
-func startTimer(*runtimeTimer) {
+func startTimer(t *runtimeTimer) {
+	h := timers().new_timer()
+	t.handle = h
+	// a Reset changes t, the goroutine keeps what it was started with
+	when, period, f, arg, seq := t.when, t.period, t.f, t.arg, t.seq
+	go func() {
+		for timers().async_wait(h, when) {
+			f(arg, seq)
+			if period <= 0 {
+				return
+			}
+			// the ticks missed are dropped
+			when += period
+			if now := runtimeNano(); when < now {
+				when += period * ((now - when) / period + 1)
+			}
+		}
+	}()
 }
 
-// goscript: todo
-func stopTimer(*runtimeTimer) bool {
-	return true
+func stopTimer(t *runtimeTimer) bool {
+	if t.handle == nil {
+		return false
+	}
+	return timers().stop(t.handle)
 }
 
 // The Timer type represents a single event.
@@ -1067,15 +1067,20 @@ func daysIn(m Month, year int) int {
 	return int(daysBefore[m] - daysBefore[m-1])
 }
 
-// goscript: todo
+type ffiTime interface {
+	now() (sec int64, nsec int32, mono int64)
+	runtime_nano() int64
+}
+
+var nativeTime = ffi(ffiTime, "time")
+
 func now() (sec int64, nsec int32, mono int64) {
-	return 0, 0, 0
+	return nativeTime.now()
 }
 
 // runtimeNano returns the current value of the runtime clock in nanoseconds.
-// goscript: todo
 func runtimeNano() int64 {
-	return 0
+	return nativeTime.runtime_nano()
 }
 
 // Monotonic times are reported as offsets from startNano.
@@ -0,0 +1,89 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use super::check::TypeInfo;
+use super::importer::{BuildConfig, ImportKey, Importer, SourceRead, TraceConfig};
+use super::objects::{PackageKey, TCObjects};
+use super::universe::Universe;
+use go_parser::ast;
+use go_parser::{AstObjects, ErrorList, FileSet, Map};
+use std::path::Path;
+
+/// The ASTs and the type information of a package and of the ones it imports, as
+/// far as they could be parsed and checked
+pub struct Analysis {
+    pub fset: FileSet,
+    pub ast_objs: AstObjects,
+    pub tc_objs: TCObjects,
+    /// The packages by import path
+    pub pkgs: Map<String, PackageKey>,
+    /// The type information of the packages that were checked
+    pub results: Map<PackageKey, TypeInfo>,
+    /// The package analyzed, None if none of its files could be read or parsed
+    pub pkg: Option<PackageKey>,
+    pub errors: ErrorList,
+}
+
+impl Analysis {
+    /// The type information of the package analyzed
+    pub fn type_info(&self) -> Option<&TypeInfo> {
+        self.pkg.and_then(|p| self.results.get(&p))
+    }
+
+    /// The files of the package analyzed, with `Bad` nodes where there were syntax
+    /// errors
+    pub fn files(&self) -> &[ast::File] {
+        self.type_info().map_or(&[], |ti| &ti.ast_files)
+    }
+}
+
+/// Parses and checks the package at `path` like the compiler does, but keeps going
+/// past the errors, for the tools working on code that is being edited, like
+/// completion. The build is made tolerant whatever `build` says.
+pub fn analyze<S: SourceRead>(
+    path: &Path,
+    trace: &TraceConfig,
+    build: &BuildConfig,
+    reader: &S,
+) -> Analysis {
+    let build = BuildConfig {
+        tolerant: true,
+        ..build.clone()
+    };
+    let mut fset = FileSet::new();
+    let mut ast_objs = AstObjects::new();
+    let mut tc_objs = TCObjects::new();
+    let mut pkgs = Map::new();
+    let mut results = Map::new();
+    let errors = ErrorList::new();
+    for (name, val) in build.universe_constants() {
+        if let Err(e) = Universe::def_host_const(&name, val, &mut tc_objs) {
+            errors.add(None, e, false, false);
+        }
+    }
+    let key = ImportKey::new(&path.to_string_lossy(), "./");
+    let pkg = Importer::new(
+        trace,
+        &build,
+        reader,
+        &mut fset,
+        &mut pkgs,
+        &mut results,
+        &mut ast_objs,
+        &mut tc_objs,
+        &errors,
+        0,
+    )
+    .import(&key)
+    .ok();
+    Analysis {
+        fset,
+        ast_objs,
+        tc_objs,
+        pkgs,
+        results,
+        pkg,
+        errors,
+    }
+}
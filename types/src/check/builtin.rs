@@ -554,8 +554,12 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                     return false;
                 }
 
+                let mut elem = None;
                 let min = match self.otype(arg0t).underlying_val(self.tc_objs) {
-                    Type::Slice(_) => 2,
+                    Type::Slice(detail) => {
+                        elem = Some(detail.elem());
+                        2
+                    }
                     Type::Map(_) | Type::Chan(_) => 1,
                     _ => {
                         let ed = self.new_dis(arg0);
@@ -582,21 +586,34 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                     return false;
                 }
 
-                // constant integer arguments, if any
-                let sizes: Vec<u64> = call.args[1..]
+                // constant integer arguments, if any, with their argument index
+                let sizes: Vec<(usize, u64)> = call.args[1..]
                     .iter()
-                    .filter_map(|x| {
-                        if let Ok(i) = self.index(x, None, fctx) {
-                            return i;
-                        }
-                        None
+                    .enumerate()
+                    .filter_map(|(i, x)| match self.index(x, None, fctx) {
+                        Ok(Some(n)) => Some((i, n)),
+                        _ => None,
                     })
                     .collect();
-                if sizes.len() == 2 && sizes[0] > sizes[1] {
+                if sizes.len() == 2 && sizes[0].1 > sizes[1].1 {
                     let pos = call.args[1].pos(self.ast_objs);
                     self.invalid_arg(pos, "length and capacity swapped");
                     // safe to continue
                 }
+                // constant sizes must not make the array larger than the platform allows
+                if let Some(elem) = elem.filter(|&t| typ::is_typed(t, self.tc_objs)) {
+                    let elem_size = typ::size_of(&elem, self.tc_objs) as u64;
+                    let too_large = |n: u64| {
+                        n.checked_mul(elem_size)
+                            .is_none_or(|bytes| bytes > isize::MAX as u64)
+                    };
+                    if let Some(&(i, _)) = sizes.iter().find(|(_, n)| too_large(*n)) {
+                        let pos = call.args[1 + i].pos(self.ast_objs);
+                        let what = if i == 0 { "len" } else { "cap" };
+                        self.invalid_arg(pos, &format!("{} argument too large in make", what));
+                        return false;
+                    }
+                }
                 x.mode = OperandMode::Value;
                 x.typ = Some(arg0t);
 
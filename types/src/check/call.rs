@@ -345,7 +345,13 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                     }
                     LookupResult::BadMethodReceiver => {
                         let td = self.new_td_o(&x.typ);
-                        format!("{} is not in method set of {}", sel_name, td)
+                        if x.mode == OperandMode::TypeExpr {
+                            format!("{} is not in method set of {}", sel_name, td)
+                        } else {
+                            // the operand is not addressable, so its address can't be
+                            // taken for the receiver
+                            format!("cannot call pointer method {} on {}", sel_name, td)
+                        }
                     }
                     LookupResult::Entry(_, _, _) => unreachable!(),
                 };
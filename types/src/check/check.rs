@@ -11,7 +11,7 @@
 
 #![allow(dead_code)]
 use super::super::constant::Value;
-use super::super::importer::{ImportKey, Importer, SourceRead, TraceConfig};
+use super::super::importer::{BuildConfig, ImportKey, Importer, SourceRead, TraceConfig};
 use super::super::objects::{DeclInfoKey, ObjKey, PackageKey, ScopeKey, TCObjects, TypeKey};
 use super::super::operand::OperandMode;
 use super::super::selection::Selection;
@@ -163,6 +163,9 @@ pub struct ObjContext {
     pub pos: Option<Pos>,
     // value of iota in a constant declaration; None otherwise
     pub iota: Option<Value>,
+    // if valid, errors are reported at this position instead, used for the
+    // constants whose init expression is inherited from a previous spec
+    pub err_pos: Option<Pos>,
     // function signature if inside a function; None otherwise
     pub sig: Option<TypeKey>,
     // set of panic call ids (used for termination check)
@@ -223,6 +226,8 @@ pub struct Checker<'a, S: SourceRead> {
 
     trace_config: &'a TraceConfig,
 
+    build_config: &'a BuildConfig,
+
     reader: &'a S,
     // result of type checking
     pub result: TypeInfo,
@@ -237,6 +242,7 @@ impl ObjContext {
             scope: None,
             pos: None,
             iota: None,
+            err_pos: None,
             sig: None,
             panics: None,
             has_label: false,
@@ -406,6 +412,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         all_results: &'a mut Map<PackageKey, TypeInfo>,
         pkg: PackageKey,
         cfg: &'a TraceConfig,
+        build_cfg: &'a BuildConfig,
         reader: &'a S,
     ) -> Checker<'a, S> {
         Checker {
@@ -420,6 +427,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             imp_map: Map::new(),
             octx: ObjContext::new(),
             trace_config: cfg,
+            build_config: build_cfg,
             reader: reader,
             result: TypeInfo::new(),
             indent: Rc::new(RefCell::new(0)),
@@ -427,7 +435,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
     }
 
     pub fn check(mut self, mut files: Vec<ast::File>) -> Result<PackageKey, ()> {
-        self.check_files_pkg_name(&files)?;
+        self.check_files_pkg_name(&mut files)?;
         let fctx = &mut FilesContext::new(&files);
         self.collect_objects(fctx);
         self.package_objects(fctx);
@@ -466,6 +474,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
     pub fn new_importer(&mut self, pos: Pos) -> Importer<S> {
         Importer::new(
             self.trace_config,
+            self.build_config,
             self.reader,
             self.fset,
             self.all_pkgs,
@@ -477,10 +486,12 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         )
     }
 
-    /// check files' package name
-    fn check_files_pkg_name(&mut self, files: &Vec<ast::File>) -> Result<(), ()> {
+    /// check files' package name, a tolerant build leaves out the files of another
+    /// package than the first one's
+    fn check_files_pkg_name(&mut self, files: &mut Vec<ast::File>) -> Result<(), ()> {
         let mut pkg_name: Option<String> = None;
-        for f in files.iter() {
+        let mut others = vec![];
+        for (i, f) in files.iter().enumerate() {
             let ident = &self.ast_objs.idents[f.name];
             if pkg_name.is_none() {
                 if ident.name == "_" {
@@ -498,9 +509,15 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                         pkg_name.as_ref().unwrap()
                     ),
                 );
-                return Err(());
+                if !self.build_config.tolerant {
+                    return Err(());
+                }
+                others.push(i);
             }
         }
+        for i in others.into_iter().rev() {
+            files.remove(i);
+        }
         self.tc_objs.pkgs[self.pkg].set_name(pkg_name.unwrap());
         Ok(())
     }
@@ -522,6 +539,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
     }
 
     fn error_impl(&self, pos: Pos, err: String, soft: bool) {
+        let pos = self.octx.err_pos.unwrap_or(pos);
         let file = self.fset.file(pos).unwrap();
         FilePosErrors::new(file, self.errors).add(pos, err, soft);
     }
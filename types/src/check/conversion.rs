@@ -34,18 +34,19 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             let v = constv.unwrap();
             let tval = self.otype(t).underlying_val(o);
             let basic = tval.try_as_basic().unwrap();
-            let clone = v.clone();
-            if clone.representable(basic, Some(v)) {
+            let mut rounded = v.clone();
+            if v.representable(basic, Some(&mut rounded)) {
+                *v = rounded;
                 true
             } else if typ::is_integer(xtype, o) && tval.is_string(o) {
-                let mut s = "\u{FFFD}".to_owned();
+                // surrogates and values out of range convert to "\uFFFD"
                 let (i, exact) = v.int_as_i64();
-                if exact {
-                    if let Some(c) = char::from_u32(i as u32) {
-                        s = c.to_string()
-                    }
-                }
-                *v = Value::with_str(s);
+                let c = u32::try_from(i)
+                    .ok()
+                    .filter(|_| exact)
+                    .and_then(char::from_u32)
+                    .unwrap_or(char::REPLACEMENT_CHARACTER);
+                *v = Value::with_str(c.to_string());
                 true
             } else {
                 false
@@ -61,7 +62,16 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         if !ok {
             let xd = self.new_dis(x);
             let td = self.new_dis(&t);
-            self.error(xd.pos(), format!("cannot convert {} to {}", xd, td));
+            let o = &self.tc_objs;
+            // a conversion from an integer constant to an integer type can only
+            // fail if there's overflow, give a concise error like gc
+            let msg = match x.mode.constant_val() {
+                Some(v) if typ::is_integer(xtype, o) && typ::is_integer(t, o) => {
+                    format!("constant {} overflows {}", v, td)
+                }
+                _ => format!("cannot convert {} to {}", xd, td),
+            };
+            self.error(xd.pos(), msg);
             x.mode = OperandMode::Invalid;
             return;
         }
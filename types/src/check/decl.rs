@@ -129,7 +129,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                         self.octx.decl = Some(dkey);
                         let cd = d.as_const();
                         let (typ, init) = (cd.typ.clone(), cd.init.clone());
-                        self.const_decl(okey, &typ, &init, fctx);
+                        self.const_decl(okey, &typ, &init, cd.inherited, fctx);
                     }
                     EntityType::Var(_) => {
                         self.octx.decl = Some(dkey);
@@ -297,10 +297,12 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         okey: ObjKey,
         typ: &Option<Expr>,
         init: &Option<Expr>,
+        inherited: bool,
         fctx: &mut FilesContext<S>,
     ) {
         let lobj = self.lobj(okey);
         assert!(lobj.typ().is_none());
+        let pos = lobj.pos();
         self.octx.iota = Some(lobj.const_val().clone());
 
         // provide valid constant value under all circumstances
@@ -328,12 +330,18 @@ impl<'a, S: SourceRead> Checker<'a, S> {
 
         let mut x = Operand::new();
         if let Some(expr) = init {
+            if inherited {
+                // the positions in the init expression refer to the spec it comes
+                // from, report the errors at the name of the constant instead
+                self.octx.err_pos = Some(pos);
+            }
             self.expr(&mut x, expr, fctx);
         }
         self.init_const(okey, &mut x, fctx);
 
         // clear iota
         self.octx.iota = None;
+        self.octx.err_pos = None;
     }
 
     pub fn var_decl(
@@ -580,6 +588,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                             let vspec = &**vs;
                             let top = fctx.delayed_count();
                             let mut current_vspec = None;
+                            let mut inherited = false;
                             let lhs: Vec<ObjKey> = match gdecl.token {
                                 Token::CONST => {
                                     if vspec.typ.is_some() || vspec.values.len() > 0 {
@@ -592,6 +601,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                                             match spec {
                                                 ast::Spec::Value(v) => {
                                                     current_vspec = Some(&*v);
+                                                    inherited = true;
                                                 }
                                                 _ => unreachable!(),
                                             }
@@ -622,7 +632,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                                             };
                                             let typ =
                                                 current_vspec.map(|x| x.typ.clone()).flatten();
-                                            self.const_decl(okey, &typ, &init, fctx);
+                                            self.const_decl(okey, &typ, &init, inherited, fctx);
                                             okey
                                         })
                                         .collect()
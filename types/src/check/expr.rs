@@ -166,14 +166,10 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                         0
                     };
                     *v = Value::unary_op(op, v, prec);
-                    // Typed constants must be representable in
-                    // their type after each constant operation.
-                    if tval.is_typed(self.tc_objs) {
-                        if e.is_some() {
-                            x.expr = e // for better error message
-                        }
-                        self.representable(x, ty);
+                    if e.is_some() {
+                        x.expr = e // for better error message
                     }
+                    self.overflow(x);
                     return;
                 }
                 x.mode = OperandMode::Value;
@@ -201,8 +197,10 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         let tval = self.otype(t);
         let tbasic = tval.try_as_basic().unwrap();
         if let OperandMode::Constant(v) = &mut x.mode {
-            let clone = v.clone();
-            if !clone.representable(tbasic, Some(v)) {
+            let mut rounded = v.clone();
+            if v.representable(tbasic, Some(&mut rounded)) {
+                *v = rounded;
+            } else {
                 let o = &self.tc_objs;
                 let xtval = self.otype(x.typ.unwrap());
                 let tval = self.otype(t);
@@ -231,6 +229,41 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         }
     }
 
+    /// overflow checks the result of a constant operation: typed constants must be
+    /// representable in their type, and untyped integer constants must not grow
+    /// arbitrarily large.
+    fn overflow(&mut self, x: &mut Operand) {
+        let t = x.typ.unwrap();
+        if typ::is_typed(t, self.tc_objs) {
+            self.representable(x, typ::underlying_type(t, self.tc_objs));
+            return;
+        }
+        // 512 is the constant precision
+        const PREC: u64 = 512;
+        if let Some(Value::Int(i)) = x.mode.constant_val() {
+            if i.bits() > PREC {
+                // report at the operator like gc does
+                let (pos, op_name) = match &x.expr {
+                    Some(Expr::Binary(b)) => (
+                        b.op_pos,
+                        match b.op {
+                            Token::ADD => "addition ",
+                            Token::SUB => "subtraction ",
+                            Token::XOR => "bitwise XOR ",
+                            Token::MUL => "multiplication ",
+                            Token::SHL => "shift ",
+                            _ => "",
+                        },
+                    ),
+                    Some(Expr::Unary(u)) if u.op == Token::XOR => (u.op_pos, "bitwise complement "),
+                    _ => (x.pos(self.ast_objs), ""),
+                };
+                self.error(pos, format!("constant {}overflow", op_name));
+                x.mode = OperandMode::Invalid;
+            }
+        }
+    }
+
     /// update_expr_type updates the type of x to typ and invokes itself
     /// recursively for the operands of x, depending on expression kind.
     /// If typ is still an untyped and not the final type, update_expr_type
@@ -600,6 +633,17 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             return;
         }
 
+        // provide a good error message for negative shift counts
+        if let Some(yv) = y.mode.constant_val() {
+            let yval = yv.to_int(); // consider -1, 1.0, but not -1.1
+            if yval.is_int() && yval.sign() < 0 {
+                let yd = self.new_dis(y);
+                self.invalid_op(yd.pos(), &format!("negative shift count {}", yd));
+                x.mode = OperandMode::Invalid;
+                return;
+            }
+        }
+
         // spec: "The right operand in a shift expression must have unsigned
         // integer type or be an untyped constant representable by a value of
         // type uint."
@@ -653,14 +697,10 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                 }
                 // x is a constant so xval != nil and it must be of Int kind.
                 *xv = Value::shift(xv.to_int().as_ref(), op, s as usize);
-                // Typed constants must be representable in
-                // their type after each constant operation.
-                if typ::is_typed(x.typ.unwrap(), self.tc_objs) {
-                    if e.is_some() {
-                        x.expr = e.map(|x| x.clone()); // for better error message
-                    }
-                    self.representable(x, x.typ.unwrap());
+                if e.is_some() {
+                    x.expr = e.map(|x| x.clone()); // for better error message
                 }
+                self.overflow(x);
                 return;
             }
 
@@ -816,12 +856,10 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                     op
                 };
                 *vx = Value::binary_op(vx, op2, vy);
-                // Typed constants must be representable in
-                // their type after each constant operation.
-                if typ::is_typed(ty, o) {
+                if e.is_some() {
                     x.expr = e.map(|x| x.clone()); // for better error message
-                    self.representable(x, ty)
                 }
+                self.overflow(x);
             }
             _ => {
                 x.mode = OperandMode::Value;
@@ -1581,20 +1619,15 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         t: TypeKey,
         fctx: &mut FilesContext<S>,
     ) {
-        if let Some((method, wrong_type)) = lookup::assertable_to(xtype, t, self, fctx) {
+        if let Some((method, missing)) = lookup::assertable_to(xtype, t, self, fctx) {
             let dx = self.new_dis(x);
             self.error(
                 pos.unwrap_or_else(|| dx.pos()),
                 format!(
-                    "{} cannot have dynamic type {} ({} {})",
+                    "{} cannot have dynamic type {} ({})",
                     dx,
                     self.new_dis(&t),
-                    if wrong_type {
-                        "wrong type for method"
-                    } else {
-                        "missing method"
-                    },
-                    self.lobj(method).name()
+                    missing.reason(self.lobj(method).name())
                 ),
             );
         }
@@ -26,6 +26,7 @@ pub struct DeclInfoConst {
     pub file_scope: ScopeKey,  // scope of file containing this declaration
     pub typ: Option<Expr>,     // type, or None
     pub init: Option<Expr>,    // init/orig expression, or None
+    pub inherited: bool,       // init expression is inherited from a previous spec
     pub deps: HashSet<ObjKey>, // deps tracks initialization expression dependencies.
 }
 
@@ -62,11 +63,17 @@ pub enum DeclInfo {
 }
 
 impl DeclInfo {
-    pub fn new_const(file_scope: ScopeKey, typ: Option<Expr>, init: Option<Expr>) -> DeclInfo {
+    pub fn new_const(
+        file_scope: ScopeKey,
+        typ: Option<Expr>,
+        init: Option<Expr>,
+        inherited: bool,
+    ) -> DeclInfo {
         DeclInfo::Const(DeclInfoConst {
             file_scope: file_scope,
             typ: typ,
             init: init,
+            inherited,
             deps: HashSet::new(),
         })
     }
@@ -286,6 +293,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                                     match gdecl.token {
                                         Token::CONST => {
                                             let mut current_vspec = None;
+                                            let mut inherited = false;
                                             if vspec.typ.is_some() || vspec.values.len() > 0 {
                                                 last_full_const_spec = Some(spec.clone());
                                                 current_vspec = Some(vspec);
@@ -296,6 +304,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                                                     match spec {
                                                         ast::Spec::Value(v) => {
                                                             current_vspec = Some(&*v);
+                                                            inherited = true;
                                                         }
                                                         _ => unreachable!(),
                                                     }
@@ -322,9 +331,10 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                                                 };
                                                 let typ =
                                                     current_vspec.map(|x| x.typ.clone()).flatten();
-                                                let d = self.tc_objs.decls.insert(
-                                                    DeclInfo::new_const(file_scope, typ, init),
-                                                );
+                                                let d =
+                                                    self.tc_objs.decls.insert(DeclInfo::new_const(
+                                                        file_scope, typ, init, inherited,
+                                                    ));
                                                 let _ = self.declare_pkg_obj(name, okey, d);
                                             }
                                             self.arity_match(vspec, true, current_vspec);
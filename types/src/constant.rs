@@ -17,6 +17,7 @@ use num_traits::cast::FromPrimitive;
 use num_traits::cast::ToPrimitive;
 use num_traits::sign::Signed;
 use num_traits::Num;
+use num_traits::Zero;
 use ordered_float;
 use std::borrow::Borrow;
 use std::borrow::Cow;
@@ -33,10 +34,10 @@ type F64 = ordered_float::OrderedFloat<f64>;
 /// values produce unknown values unless specified
 /// otherwise.
 ///
-/// Because BigFloat library is not available at the moment(2020/5)
-/// float numbers arbitrary precision is not supported for now
-/// float numbers is simply represented as f64
-/// todo: This is against the Go specs.
+/// There is no arbitrary precision float, a float literal is an exact Rat like the
+/// results of the divisions, so the untyped constants can go beyond the range of
+/// f64 as long as they end up in it. Float holds the values already rounded to a
+/// f64, like those of typed constants.
 
 /// All the values involved in the evaluation
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -69,14 +70,8 @@ impl fmt::Display for Value {
                 //f.write_str("int: ")?;
                 s.fmt(f)
             }
-            Value::Rat(r) => {
-                //f.write_str("rat: ")?;
-                r.fmt(f)
-            }
-            Value::Float(s) => {
-                //f.write_str("float: ")?;
-                s.fmt(f)
-            }
+            Value::Rat(r) => f.write_str(&format_rat(r)),
+            Value::Float(s) => f.write_str(&format_f64(**s)),
             Value::Complex(r, i) => {
                 //f.write_str("complex: ")?;
                 write!(f, "({} + {}i)", r, i)
@@ -113,7 +108,7 @@ impl Value {
             Token::IMAG(imlit) => {
                 let s = imlit.as_str();
                 let v = float_from_literal(&s[..(s.len() - 1)]);
-                if let Value::Float(_) = &v {
+                if let Value::Rat(_) | Value::Float(_) = &v {
                     Value::Complex(Box::new(Value::with_f64(0.0)), Box::new(v))
                 } else {
                     Value::Unknown
@@ -148,7 +143,12 @@ impl Value {
             |val: &Value, btype: BasicType, rounded: Option<&mut Value>| -> bool {
                 match val.to_float() {
                     Value::Float(f) => match btype {
-                        BasicType::Float64 => true,
+                        BasicType::Float64 => {
+                            if let Some(r) = rounded {
+                                *r = Value::Float(f);
+                            }
+                            f.is_finite()
+                        }
                         BasicType::Float32 => {
                             let f32_ = *f as f32;
                             let ok = !f32_.is_infinite();
@@ -790,6 +790,7 @@ fn short_quote_str(s: &str, max: usize) -> String {
 }
 
 fn int_from_literal(lit: &str) -> Value {
+    let lit = lit.replace('_', "").to_lowercase();
     let result = if lit.starts_with("0x") {
         BigInt::from_str_radix(&lit[2..], 16)
     } else if lit.starts_with("0o") {
@@ -797,7 +798,7 @@ fn int_from_literal(lit: &str) -> Value {
     } else if lit.starts_with("0b") {
         BigInt::from_str_radix(&lit[2..], 2)
     } else {
-        BigInt::from_str_radix(lit, 10)
+        BigInt::from_str_radix(&lit, 10)
     };
     match result {
         Ok(i) => Value::Int(i),
@@ -805,10 +806,115 @@ fn int_from_literal(lit: &str) -> Value {
     }
 }
 
+/// The exponents beyond which a float literal is no longer made exact, as the
+/// numerator or the denominator of its Rat would take kilobytes
+const MAX_LITERAL_EXP: i64 = 10000;
+
+/// Makes the exact value of a decimal float literal, as a Rat
 fn float_from_literal(lit: &str) -> Value {
-    match lit.parse::<f64>() {
-        Ok(f) => Value::with_f64(f),
-        Err(_) => Value::Unknown,
+    let lit = lit.replace('_', "").to_lowercase();
+    let (mantissa, exp) = match lit.split_once('e') {
+        Some((m, e)) => match e.parse::<i64>() {
+            Ok(e) => (m, e),
+            Err(_) => return Value::Unknown,
+        },
+        None => (lit.as_str(), 0),
+    };
+    let (int_part, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let exp = exp - frac.len() as i64;
+    let digits = match BigInt::from_str_radix(&format!("0{}{}", int_part, frac), 10) {
+        Ok(d) => d,
+        Err(_) => return Value::Unknown,
+    };
+    if exp.abs() > MAX_LITERAL_EXP {
+        return match lit.parse::<f64>() {
+            Ok(f) => Value::with_f64(f),
+            Err(_) => Value::Unknown,
+        };
+    }
+    let pow = num_traits::pow(BigInt::from(10), exp.unsigned_abs() as usize);
+    if exp >= 0 {
+        Value::Rat(BigRational::from_integer(digits * pow))
+    } else {
+        Value::Rat(BigRational::new(digits, pow))
+    }
+}
+
+/// Formats a float like Go's `%.6g`, which go/constant shows floats with
+fn format_f64(f: f64) -> String {
+    if f.is_infinite() {
+        return if f > 0.0 { "+Inf" } else { "-Inf" }.to_owned();
+    }
+    if f == 0.0 || f.is_nan() {
+        return format!("{}", f);
+    }
+    let sci = format!("{:.5e}", f);
+    let (mantissa, exp) = sci.split_once('e').unwrap();
+    let exp: i64 = exp.parse().unwrap();
+    if !(-4..6).contains(&exp) {
+        format_exp(mantissa, exp)
+    } else {
+        trim_fraction(format!("{:.*}", (5 - exp) as usize, f))
+    }
+}
+
+/// Formats a Rat like `format_f64` formats the f64 nearest to it, working out
+/// the digits from the Rat when it's out of the range of f64
+fn format_rat(r: &BigRational) -> String {
+    match r.to_f64() {
+        Some(f) if f.is_finite() && (f != 0.0 || r.is_zero()) => format_f64(f),
+        _ => {
+            // |r| is in [10^exp, 10^(exp+1)), with exp first estimated from the bit
+            // lengths, then corrected
+            let abs = r.abs();
+            let bits = abs.numer().bits() as i64 - abs.denom().bits() as i64;
+            let mut exp = (bits as f64 * std::f64::consts::LOG10_2).floor() as i64;
+            let six_digits = |exp: i64| (&abs * pow10(5 - exp)).round().to_integer();
+            let mut digits = six_digits(exp);
+            while digits >= BigInt::from(1_000_000) {
+                exp += 1;
+                digits = six_digits(exp);
+            }
+            while digits < BigInt::from(100_000) {
+                exp -= 1;
+                digits = six_digits(exp);
+            }
+            let digits = digits.to_string();
+            let mantissa = format!("{}.{}", &digits[..1], &digits[1..]);
+            let sign = if r.is_negative() { "-" } else { "" };
+            format!("{}{}", sign, format_exp(&mantissa, exp))
+        }
+    }
+}
+
+fn pow10(exp: i64) -> BigRational {
+    let pow = BigRational::from_integer(num_traits::pow(
+        BigInt::from(10),
+        exp.unsigned_abs() as usize,
+    ));
+    if exp >= 0 {
+        pow
+    } else {
+        pow.recip()
+    }
+}
+
+fn format_exp(mantissa: &str, exp: i64) -> String {
+    let sign = if exp < 0 { '-' } else { '+' };
+    format!(
+        "{}e{}{:02}",
+        trim_fraction(mantissa.to_owned()),
+        sign,
+        exp.abs()
+    )
+}
+
+/// Removes the trailing zeros of the fraction, and its point if nothing is left
+fn trim_fraction(s: String) -> String {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.').to_owned()
+    } else {
+        s
     }
 }
 
@@ -823,11 +929,9 @@ fn shorten_with_ellipsis(s: String, max: usize) -> String {
     }
 }
 
+/// The nearest f64, an infinity if the Rat is beyond the range of f64
 fn rat_to_f64(r: &BigRational) -> Option<f64> {
-    match (r.numer().to_f64(), r.denom().to_f64()) {
-        (Some(n), Some(d)) => Some(n / d),
-        _ => None,
-    }
+    r.to_f64()
 }
 
 #[cfg(test)]
@@ -0,0 +1,250 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+//
+//
+// This code is adapted from the offical Go code written in Go
+// with license as follows:
+// Copyright 2020 The Go Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Parsing and evaluation of `//go:build` constraint lines, as in go/build/constraint,
+//! and the GOOS/GOARCH file name suffixes, as in go/build.
+
+pub const KNOWN_OS: &[&str] = &[
+    "aix",
+    "android",
+    "darwin",
+    "dragonfly",
+    "freebsd",
+    "hurd",
+    "illumos",
+    "ios",
+    "js",
+    "linux",
+    "nacl",
+    "netbsd",
+    "openbsd",
+    "plan9",
+    "solaris",
+    "wasip1",
+    "windows",
+    "zos",
+];
+
+pub const UNIX_OS: &[&str] = &[
+    "aix",
+    "android",
+    "darwin",
+    "dragonfly",
+    "freebsd",
+    "hurd",
+    "illumos",
+    "ios",
+    "linux",
+    "netbsd",
+    "openbsd",
+    "solaris",
+];
+
+pub const KNOWN_ARCH: &[&str] = &[
+    "386", "amd64", "amd64p32", "arm", "armbe", "arm64", "arm64be", "loong64", "mips", "mipsle",
+    "mips64", "mips64le", "ppc", "ppc64", "ppc64le", "riscv", "riscv64", "s390", "s390x", "sparc",
+    "sparc64", "wasm",
+];
+
+/// A parsed build constraint expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Constraint {
+    Tag(String),
+    Not(Box<Constraint>),
+    And(Box<Constraint>, Box<Constraint>),
+    Or(Box<Constraint>, Box<Constraint>),
+}
+
+impl Constraint {
+    /// Parses the expression following `//go:build`
+    pub fn parse(text: &str) -> Result<Constraint, String> {
+        let mut p = ExprParser {
+            s: text.trim(),
+            pos: 0,
+        };
+        let expr = p.or()?;
+        p.skip_space();
+        if p.pos < p.s.len() {
+            return Err(format!("unexpected token at: {}", &p.s[p.pos..]));
+        }
+        Ok(expr)
+    }
+
+    pub fn eval(&self, ok: &dyn Fn(&str) -> bool) -> bool {
+        match self {
+            Constraint::Tag(t) => ok(t),
+            Constraint::Not(x) => !x.eval(ok),
+            Constraint::And(x, y) => x.eval(ok) && y.eval(ok),
+            Constraint::Or(x, y) => x.eval(ok) || y.eval(ok),
+        }
+    }
+}
+
+/// Reports whether the name of a file (without directory) is acceptable given the tags,
+/// `name_$(GOOS).*`, `name_$(GOARCH).*` and `name_$(GOOS)_$(GOARCH).*` are only
+/// acceptable if the corresponding tags are satisfied.
+pub fn match_file_name(name: &str, ok: &dyn Fn(&str) -> bool) -> bool {
+    let name = match name.find('.') {
+        Some(i) => &name[..i],
+        None => name,
+    };
+    let name = match name.find('_') {
+        Some(i) => &name[i..],
+        None => return true,
+    };
+    let mut l: Vec<&str> = name.split('_').collect();
+    if l.last() == Some(&"test") {
+        l.pop();
+    }
+    let n = l.len();
+    if n >= 2 && KNOWN_OS.contains(&l[n - 2]) && KNOWN_ARCH.contains(&l[n - 1]) {
+        return ok(l[n - 2]) && ok(l[n - 1]);
+    }
+    if n >= 1 && (KNOWN_OS.contains(&l[n - 1]) || KNOWN_ARCH.contains(&l[n - 1])) {
+        return ok(l[n - 1]);
+    }
+    true
+}
+
+/// Finds the `//go:build` line of a source file, only the comments and blank lines
+/// before the package clause are examined.
+pub fn find_go_build(src: &str) -> Option<&str> {
+    let mut in_block_comment = false;
+    for line in src.lines() {
+        let line = line.trim();
+        if in_block_comment {
+            if let Some(i) = line.find("*/") {
+                in_block_comment = false;
+                if !line[i + 2..].trim().is_empty() {
+                    return None;
+                }
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        } else if let Some(rest) = line.strip_prefix("//go:build") {
+            if rest.is_empty() || rest.starts_with(|c: char| c.is_whitespace()) {
+                return Some(rest.trim());
+            }
+        } else if line.starts_with("//") {
+            continue;
+        } else if line.starts_with("/*") {
+            in_block_comment = !line.contains("*/");
+        } else {
+            return None;
+        }
+    }
+    None
+}
+
+struct ExprParser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl<'a> ExprParser<'a> {
+    fn skip_space(&mut self) {
+        let rest = &self.s[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, tok: &str) -> bool {
+        self.skip_space();
+        if self.s[self.pos..].starts_with(tok) {
+            self.pos += tok.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Constraint, String> {
+        let mut x = self.and()?;
+        while self.eat("||") {
+            x = Constraint::Or(Box::new(x), Box::new(self.and()?));
+        }
+        Ok(x)
+    }
+
+    fn and(&mut self) -> Result<Constraint, String> {
+        let mut x = self.not()?;
+        while self.eat("&&") {
+            x = Constraint::And(Box::new(x), Box::new(self.not()?));
+        }
+        Ok(x)
+    }
+
+    fn not(&mut self) -> Result<Constraint, String> {
+        if self.eat("!") {
+            if self.eat("!") {
+                return Err("double negation not allowed".to_owned());
+            }
+            Ok(Constraint::Not(Box::new(self.atom()?)))
+        } else {
+            self.atom()
+        }
+    }
+
+    fn atom(&mut self) -> Result<Constraint, String> {
+        if self.eat("(") {
+            let x = self.or()?;
+            if !self.eat(")") {
+                return Err("missing )".to_owned());
+            }
+            return Ok(x);
+        }
+        self.skip_space();
+        let rest = &self.s[self.pos..];
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(match rest.is_empty() {
+                true => "unexpected end of expression".to_owned(),
+                false => format!("unexpected token at: {}", rest),
+            });
+        }
+        self.pos += len;
+        Ok(Constraint::Tag(rest[..len].to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_constraint() {
+        let tags = ["host", "debug"];
+        let ok = |t: &str| tags.contains(&t);
+        let eval = |s: &str| Constraint::parse(s).unwrap().eval(&ok);
+        assert!(eval("host"));
+        assert!(!eval("!host"));
+        assert!(eval("host && (wasm || debug)"));
+        assert!(!eval("host && !debug || wasm"));
+        assert!(Constraint::parse("host &&").is_err());
+        assert!(Constraint::parse("(host").is_err());
+
+        let src = "// Copyright\n\n//go:build host && !wasm\n\npackage main\n";
+        assert_eq!(find_go_build(src), Some("host && !wasm"));
+        assert_eq!(find_go_build("package main\n//go:build host\n"), None);
+
+        let ok = |t: &str| t == "linux" || t == "amd64";
+        assert!(match_file_name("file.gos", &ok));
+        assert!(match_file_name("file_linux.gos", &ok));
+        assert!(!match_file_name("file_windows.gos", &ok));
+        assert!(match_file_name("file_linux_amd64.gos", &ok));
+        assert!(!match_file_name("file_linux_arm64.gos", &ok));
+        assert!(!match_file_name("file_wasm_test.gos", &ok));
+        assert!(match_file_name("windows.gos", &ok));
+    }
+}
@@ -10,12 +10,15 @@
 // license that can be found in the LICENSE file.
 
 use super::check::{Checker, TypeInfo};
+use super::constant::Value;
+use super::constraint::{find_go_build, match_file_name, Constraint, UNIX_OS};
 use super::objects::{PackageKey, TCObjects};
 use go_parser::ast;
 use go_parser::{AstObjects, ErrorList, FileSet, Map, Parser, Pos};
 use std::io;
 use std::path::{Path, PathBuf};
 
+#[derive(Clone)]
 pub struct TraceConfig {
     //print debug info in parser
     pub trace_parser: bool,
@@ -23,6 +26,100 @@ pub struct TraceConfig {
     pub trace_checker: bool,
 }
 
+/// Host supplied settings that decide what gets compiled
+#[derive(Debug, Clone)]
+pub struct BuildConfig {
+    /// The target operating system, as in Go's GOOS
+    pub goos: String,
+    /// The target architecture, as in Go's GOARCH
+    pub goarch: String,
+    /// Build tags satisfied by the host, a file is skipped if its
+    /// `//go:build` constraint is not satisfied
+    pub tags: Vec<String>,
+    /// Untyped constants predeclared in the universe scope
+    pub constants: Vec<(String, Value)>,
+    /// Library packages that can't be imported, with the reason, e.g. the
+    /// std packages the host is built without
+    pub excluded: Vec<(String, String)>,
+    /// Checks what could be parsed of a package with syntax errors, leaving out
+    /// only the files that are not Go at all, for the tools that need the types
+    /// of the code being typed. The errors are reported all the same.
+    pub tolerant: bool,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        let (goos, goarch) = BuildConfig::host_target();
+        BuildConfig {
+            goos: goos.to_owned(),
+            goarch: goarch.to_owned(),
+            tags: vec![],
+            constants: vec![],
+            excluded: vec![],
+            tolerant: false,
+        }
+    }
+}
+
+impl BuildConfig {
+    /// Returns the (GOOS, GOARCH) of the platform the compiler is running on
+    pub fn host_target() -> (&'static str, &'static str) {
+        let goos = match std::env::consts::OS {
+            "macos" => "darwin",
+            "" if cfg!(target_arch = "wasm32") => "js",
+            os => os,
+        };
+        let goarch = match std::env::consts::ARCH {
+            "x86" => "386",
+            "x86_64" => "amd64",
+            "aarch64" => "arm64",
+            "wasm32" => "wasm",
+            "powerpc" => "ppc",
+            "powerpc64" => "ppc64",
+            "loongarch64" => "loong64",
+            arch => arch,
+        };
+        (goos, goarch)
+    }
+
+    /// Returns the constants to predeclare in the universe scope: the host's, and
+    /// the target as `_GOOS` and `_GOARCH`, which the runtime package defines its
+    /// GOOS and GOARCH with
+    pub fn universe_constants(&self) -> Vec<(String, Value)> {
+        let target = [("_GOOS", &self.goos), ("_GOARCH", &self.goarch)]
+            .into_iter()
+            .map(|(name, val)| (name.to_owned(), Value::with_str(val.clone())));
+        self.constants.iter().cloned().chain(target).collect()
+    }
+
+    /// Reports whether a build tag is satisfied, GOOS, GOARCH and `unix` are
+    /// satisfied implicitly
+    pub fn satisfies(&self, tag: &str) -> bool {
+        let goos = self.goos.as_str();
+        tag == goos
+            || tag == self.goarch
+            || (tag == "unix" && UNIX_OS.contains(&goos))
+            || (tag == "linux" && goos == "android")
+            || (tag == "solaris" && goos == "illumos")
+            || (tag == "darwin" && goos == "ios")
+            || self.tags.iter().any(|t| t == tag)
+    }
+
+    /// Reports whether a file with the given name and content should be compiled
+    pub fn matches(&self, file_name: &str, src: &str) -> Result<bool, String> {
+        let ok = |tag: &str| self.satisfies(tag);
+        if !match_file_name(file_name, &ok) {
+            return Ok(false);
+        }
+        match find_go_build(src) {
+            Some(line) => Constraint::parse(line)
+                .map(|c| c.eval(&ok))
+                .map_err(|e| format!("invalid //go:build line: {}", e)),
+            None => Ok(true),
+        }
+    }
+}
+
 pub trait SourceRead {
     fn working_dir(&self) -> &Path;
 
@@ -62,6 +159,7 @@ impl ImportKey {
 
 pub struct Importer<'a, S: SourceRead> {
     trace_config: &'a TraceConfig,
+    build_config: &'a BuildConfig,
     reader: &'a S,
     fset: &'a mut FileSet,
     pkgs: &'a mut Map<String, PackageKey>,
@@ -70,11 +168,14 @@ pub struct Importer<'a, S: SourceRead> {
     tc_objs: &'a mut TCObjects,
     errors: &'a ErrorList,
     pos: Pos,
+    // imports the `_test` files of the package too
+    tests: bool,
 }
 
 impl<'a, S: SourceRead> Importer<'a, S> {
     pub fn new(
         config: &'a TraceConfig,
+        build_config: &'a BuildConfig,
         reader: &'a S,
         fset: &'a mut FileSet,
         pkgs: &'a mut Map<String, PackageKey>,
@@ -86,6 +187,7 @@ impl<'a, S: SourceRead> Importer<'a, S> {
     ) -> Importer<'a, S> {
         Importer {
             trace_config: config,
+            build_config,
             reader: reader,
             fset: fset,
             pkgs: pkgs,
@@ -94,13 +196,27 @@ impl<'a, S: SourceRead> Importer<'a, S> {
             tc_objs: tc_objs,
             errors: errors,
             pos: pos,
+            tests: false,
         }
     }
 
+    /// Makes the importer import the package with its `_test` files, for running its
+    /// tests. The packages it imports are imported without them.
+    pub fn with_tests(mut self, tests: bool) -> Self {
+        self.tests = tests;
+        self
+    }
+
     pub fn import(&mut self, key: &'a ImportKey) -> Result<PackageKey, ()> {
         if key.path == "unsafe" {
             return Ok(*self.tc_objs.universe().unsafe_pkg());
         }
+        if !is_local(&key.path) {
+            let excluded = &self.build_config.excluded;
+            if let Some((_, reason)) = excluded.iter().find(|(p, _)| p == &key.path) {
+                return self.error(format!("package {} is not available: {}", key.path, reason));
+            }
+        }
         let pb = self.canonicalize_import(key)?;
         let path = pb.0.as_path();
         let import_path = pb.1;
@@ -109,7 +225,7 @@ impl<'a, S: SourceRead> Importer<'a, S> {
             None => {
                 let pkg = self.tc_objs.new_package(import_path.clone());
                 self.pkgs.insert(import_path, pkg);
-                let files = self.parse_path(path)?;
+                let files = self.parse_path(path, self.tests)?;
                 Checker::new(
                     self.tc_objs,
                     self.ast_objs,
@@ -119,6 +235,7 @@ impl<'a, S: SourceRead> Importer<'a, S> {
                     self.all_results,
                     pkg,
                     self.trace_config,
+                    self.build_config,
                     self.reader,
                 )
                 .check(files)
@@ -154,8 +271,8 @@ impl<'a, S: SourceRead> Importer<'a, S> {
         }
     }
 
-    fn parse_path(&mut self, path: &Path) -> Result<Vec<ast::File>, ()> {
-        match read_content(path, self.reader) {
+    fn parse_path(&mut self, path: &Path, tests: bool) -> Result<Vec<ast::File>, ()> {
+        match read_content(path, self.reader, self.build_config, tests) {
             Ok(contents) => {
                 if contents.len() == 0 {
                     self.error(format!("no source file found in dir: {}", path.display()))
@@ -175,15 +292,19 @@ impl<'a, S: SourceRead> Importer<'a, S> {
                             self.trace_config.trace_parser,
                         )
                         .parse_file();
-                        if afile.is_none() {
+                        match afile {
+                            Some(f) => afiles.push(f),
                             // parse error, the details should be in the errorlist already.
-                            // give up
-                            return Err(());
-                        } else {
-                            afiles.push(afile.unwrap());
+                            // skip the file when tolerant, give up otherwise
+                            None if self.build_config.tolerant => {}
+                            None => return Err(()),
                         }
                     }
-                    Ok(afiles)
+                    if afiles.is_empty() {
+                        Err(())
+                    } else {
+                        Ok(afiles)
+                    }
                 }
             }
             Err(e) => self.error(format!(
@@ -201,15 +322,21 @@ impl<'a, S: SourceRead> Importer<'a, S> {
     }
 }
 
-fn read_content(p: &Path, reader: &dyn SourceRead) -> io::Result<Vec<(String, String)>> {
+fn read_content(
+    p: &Path,
+    reader: &dyn SourceRead,
+    build_config: &BuildConfig,
+    tests: bool,
+) -> io::Result<Vec<(String, String)>> {
     let working_dir = reader.working_dir().canonicalize().ok();
     let mut result = vec![];
+    let mut excluded = false;
     let mut read = |path: PathBuf| -> io::Result<()> {
         if let Some(ext) = path.extension() {
             if ext == "gos" || ext == "go" || ext == "src" {
                 if let Some(fs) = path.file_stem() {
                     let s = fs.to_str();
-                    if s.is_some() && !s.unwrap().ends_with("_test") {
+                    if s.is_some() && (tests || !s.unwrap().ends_with("_test")) {
                         let p = path.as_path();
                         let content = reader.read_file(p)?;
                         // try get short display name for the file
@@ -219,7 +346,18 @@ fn read_content(p: &Path, reader: &dyn SourceRead) -> io::Result<Vec<(String, St
                         }
                         .to_string_lossy()
                         .to_string();
-                        result.push((full_name, content))
+                        let file_name = path.file_name().unwrap().to_string_lossy();
+                        let matched = build_config.matches(&file_name, &content).map_err(|e| {
+                            io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("{}: {}", full_name, e),
+                            )
+                        })?;
+                        if matched {
+                            result.push((full_name, content))
+                        } else {
+                            excluded = true;
+                        }
                     }
                 }
             }
@@ -236,7 +374,7 @@ fn read_content(p: &Path, reader: &dyn SourceRead) -> io::Result<Vec<(String, St
     } else if reader.is_file(p) {
         read(p.to_path_buf())?;
     }
-    if result.len() == 0 {
+    if result.is_empty() && !excluded {
         return Err(io::Error::new(io::ErrorKind::Other, "no file/dir found"));
     }
     Ok(result)
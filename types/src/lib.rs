@@ -10,7 +10,9 @@
 //! - `btree_map`: Make it use BTreeMap instead of HashMap
 //!
 
+mod analysis;
 mod constant;
+mod constraint;
 mod obj;
 mod package;
 mod scope;
@@ -27,11 +29,14 @@ mod universe;
 pub mod typ;
 pub mod check;
 
+pub use analysis::{analyze, Analysis};
 pub use constant::Value as ConstValue;
+pub use constraint::Constraint;
 pub use display::Displayer;
 pub use importer::*;
 pub use obj::*;
 pub use objects::*;
 pub use operand::OperandMode;
+pub use scope::Scope;
 pub use selection::*;
 pub use universe::*;
@@ -250,6 +250,28 @@ pub fn lookup_field_or_method(
     lookup_field_or_method_impl(tkey, addressable, pkg, name, objs)
 }
 
+/// why a method required by an interface is missing, see missing_method
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MissingMethod {
+    /// there is no method with the name
+    NotFound,
+    /// the method has the wrong type
+    WrongType,
+    /// the method has a pointer receiver, so it's only in the method set of
+    /// the pointer type
+    PtrRecv,
+}
+
+impl MissingMethod {
+    pub fn reason(&self, name: &str) -> String {
+        match self {
+            MissingMethod::NotFound => format!("missing method {}", name),
+            MissingMethod::WrongType => format!("wrong type for method {}", name),
+            MissingMethod::PtrRecv => format!("method {} has pointer receiver", name),
+        }
+    }
+}
+
 /// assertable_to reports whether a value of type iface can be asserted to have type t.
 /// It returns None as affirmative answer. See docs for missing_method for more info
 pub fn assertable_to<S: SourceRead>(
@@ -257,7 +279,7 @@ pub fn assertable_to<S: SourceRead>(
     t: TypeKey,
     checker: &mut Checker<S>,
     fctx: &mut FilesContext<S>,
-) -> Option<(ObjKey, bool)> {
+) -> Option<(ObjKey, MissingMethod)> {
     let objs = &checker.tc_objs;
     // no static check is required if T is an interface
     // spec: "If T is an interface type, x.(T) asserts that the
@@ -329,8 +351,7 @@ pub fn lookup_method<'a>(
 }
 
 /// missing_method returns None if 't' implements 'intf', otherwise it
-/// returns a missing method required by T and whether it is missing or
-/// just has the wrong type.
+/// returns a missing method required by T and why it is missing.
 ///
 /// For non-interface types 't', or if static is set, 't' implements
 /// 'intf' if all methods of 'intf' are present in 't'. Otherwise ('t'
@@ -344,7 +365,7 @@ pub fn missing_method<S: SourceRead>(
     static_: bool,
     checker: &mut Checker<S>,
     fctx: &mut FilesContext<S>,
-) -> Option<(ObjKey, bool)> {
+) -> Option<(ObjKey, MissingMethod)> {
     let objs = &checker.tc_objs;
     let ival = &objs.types[intf].try_as_interface().unwrap();
     if ival.is_empty() {
@@ -361,10 +382,10 @@ pub fn missing_method<S: SourceRead>(
                 objs,
             ) {
                 if !typ::identical_o(fval.typ(), objs.lobjs[*f].typ(), objs) {
-                    return Some((*fkey, true));
+                    return Some((*fkey, MissingMethod::WrongType));
                 }
             } else if static_ {
-                return Some((*fkey, false));
+                return Some((*fkey, MissingMethod::NotFound));
             }
         }
         return None;
@@ -384,7 +405,7 @@ pub fn missing_method<S: SourceRead>(
         match lookup_field_or_method(t, false, fval.pkg(), fval.name(), checker.tc_objs) {
             LookupResult::Entry(okey, _, _) => {
                 if !checker.tc_objs.lobjs[okey].entity_type().is_func() {
-                    return Some((fkey, false));
+                    return Some((fkey, MissingMethod::NotFound));
                 } else {
                     let x_type = fval.typ();
 
@@ -393,11 +414,12 @@ pub fn missing_method<S: SourceRead>(
                     let y_type = checker.tc_objs.lobjs[okey].typ();
 
                     if !typ::identical_o(x_type, y_type, checker.tc_objs) {
-                        return Some((fkey, true));
+                        return Some((fkey, MissingMethod::WrongType));
                     }
                 }
             }
-            _ => return Some((fkey, false)),
+            LookupResult::BadMethodReceiver => return Some((fkey, MissingMethod::PtrRecv)),
+            _ => return Some((fkey, MissingMethod::NotFound)),
         }
     }
     None
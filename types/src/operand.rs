@@ -192,15 +192,9 @@ impl Operand {
 
         // 'left' is an interface and 'right' implements 'left'
         if ut_left.try_as_interface().is_some() {
-            if let Some((m, wrong_type)) = missing_method(k_right, ut_key_left, true, checker, fctx)
-            {
+            if let Some((m, missing)) = missing_method(k_right, ut_key_left, true, checker, fctx) {
                 if let Some(re) = reason {
-                    let msg = if wrong_type {
-                        "wrong type for method"
-                    } else {
-                        "missing method"
-                    };
-                    *re = format!("{} {}", msg, checker.tc_objs.lobjs[m].name());
+                    *re = missing.reason(checker.tc_objs.lobjs[m].name());
                 }
                 return false;
             }
@@ -67,6 +67,11 @@ impl Scope {
         self.elems.len()
     }
 
+    /// is_empty reports whether the scope has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.elems.is_empty()
+    }
+
     pub fn pos(&self) -> Pos {
         self.pos
     }
@@ -158,6 +163,12 @@ impl Scope {
         None
     }
 
+    /// remove removes the object named `name` from the scope and returns it,
+    /// for an interactive session to declare a package level object again.
+    pub fn remove(&mut self, name: &str) -> Option<ObjKey> {
+        self.elems.remove(name)
+    }
+
     /// fmt formats a string representation for the scope.
     /// with the scope elements sorted by name.
     /// The level of indentation is controlled by n >= 0, with
@@ -129,6 +129,36 @@ impl Universe {
         }
     }
 
+    /// Predeclares an untyped constant in the universe scope, so that the host can
+    /// inject compile-time settings.
+    pub fn def_host_const(
+        name: &str,
+        val: constant::Value,
+        objs: &mut TCObjects,
+    ) -> Result<(), String> {
+        let basic = match &val {
+            constant::Value::Bool(_) => BasicType::UntypedBool,
+            constant::Value::Str(_) => BasicType::UntypedString,
+            constant::Value::Int(_) => BasicType::UntypedInt,
+            constant::Value::Rat(_) | constant::Value::Float(_) => BasicType::UntypedFloat,
+            constant::Value::Complex(_, _) => BasicType::UntypedComplex,
+            constant::Value::Unknown => return Err(format!("unknown value for constant {}", name)),
+        };
+        let valid = name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+        if !valid {
+            return Err(format!("invalid constant name: {}", name));
+        }
+        let universe = objs.universe();
+        let (scope, typ) = (universe.scope, universe.types[&basic]);
+        let cst = LangObj::new_const(0, None, name.to_owned(), Some(typ), val);
+        let okey = objs.lobjs.insert(cst);
+        match Scope::insert(scope, okey, objs) {
+            None => Ok(()),
+            Some(_) => Err(format!("constant {} redeclared in universe scope", name)),
+        }
+    }
+
     pub fn scope(&self) -> &ScopeKey {
         &self.scope
     }
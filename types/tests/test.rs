@@ -149,7 +149,10 @@ fn test_file(path: &str, trace: bool) {
     let tco = &mut types::TCObjects::new();
     let results = &mut Map::new();
 
-    let importer = &mut types::Importer::new(&config, &reader, fs, pkgs, results, asto, tco, el, 0);
+    let build = types::BuildConfig::default();
+    let importer = &mut types::Importer::new(
+        &config, &build, &reader, fs, pkgs, results, asto, tco, el, 0,
+    );
     let key = types::ImportKey::new(path, "./");
     let _ = importer.import(&key);
 
@@ -276,3 +279,61 @@ fn test_auto() {
 fn test_temp() {
     test_file("./tests/data/temp.gos", true);
 }
+
+#[test]
+fn test_tolerant() {
+    let config = types::TraceConfig {
+        trace_parser: false,
+        trace_checker: false,
+    };
+    let reader = FsReader::new(None, None);
+    let build = types::BuildConfig::default();
+    let path = Path::new("./tests/data/tolerant");
+
+    // b.src isn't Go and c.src is of another package, the importer gives up
+    {
+        let (fs, asto, tco) = (
+            &mut fe::FileSet::new(),
+            &mut fe::AstObjects::new(),
+            &mut types::TCObjects::new(),
+        );
+        let (pkgs, results, el) = (&mut Map::new(), &mut Map::new(), &fe::ErrorList::new());
+        let importer = &mut types::Importer::new(
+            &config, &build, &reader, fs, pkgs, results, asto, tco, el, 0,
+        );
+        let key = types::ImportKey::new(path.to_str().unwrap(), "./");
+        assert!(importer.import(&key).is_err());
+    }
+
+    // unless it's tolerant, which leaves them out, and reports them
+    let an = types::analyze(path, &config, &build, &reader);
+    assert!(an.errors.len() >= 3);
+    assert_eq!(an.files().len(), 1);
+
+    // what parsed of a.src is checked, the declarations after the error included
+    let ti = an.type_info().unwrap();
+    let defs = |name: &str| -> Vec<types::ObjKey> {
+        let mut objs: Vec<_> = ti
+            .defs
+            .iter()
+            .filter(|(i, o)| an.ast_objs.idents[**i].name == name && o.is_some())
+            .map(|(i, o)| (an.ast_objs.idents[*i].pos, o.unwrap()))
+            .collect();
+        objs.sort_by_key(|(pos, _)| *pos);
+        objs.into_iter().map(|(_, o)| o).collect()
+    };
+    assert_eq!(defs("add").len(), 1);
+    assert_eq!(defs("after").len(), 1);
+    assert!(defs("lost").is_empty() && defs("elsewhere").is_empty());
+    // the p being typed has the type of the receiver p
+    let ps = defs("p");
+    assert_eq!(ps.len(), 2);
+    let typ = |o: types::ObjKey| an.tc_objs.lobjs[o].typ().unwrap();
+    assert_eq!(typ(ps[0]), typ(ps[1]));
+    let sum = ti
+        .uses
+        .iter()
+        .filter(|(i, _)| an.ast_objs.idents[**i].name == "sum")
+        .count();
+    assert_eq!(sum, 1);
+}
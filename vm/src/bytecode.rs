@@ -2,12 +2,13 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
+use crate::gc::GcContainer;
 use crate::value::*;
 #[cfg(feature = "serde_borsh")]
 use borsh::{maybestd::io::Result, maybestd::io::Write, BorshDeserialize, BorshSerialize};
-#[cfg(feature = "serde_borsh")]
-use go_parser::PiggyVecKey;
-use go_parser::{piggy_key_type, PiggyVec};
+use go_parser::{piggy_key_type, Map, PiggyVec, PiggyVecKey};
+use std::cell::RefCell;
+use std::rc::Rc;
 
 #[cfg(feature = "serde_borsh")]
 macro_rules! impl_borsh_for_key {
@@ -43,6 +44,8 @@ impl_borsh_for_key!(PackageKey);
 pub type MetadataObjs = PiggyVec<MetadataKey, MetadataType>;
 pub type FunctionObjs = PiggyVec<FunctionKey, FunctionObj>;
 pub type PackageObjs = PiggyVec<PackageKey, PackageObj>;
+/// The methods bound to an interface for a type, shared by the interface values
+pub type IfaceBindings = Rc<[Binding4Runtime]>;
 
 pub struct VMObjects {
     pub metas: MetadataObjs,
@@ -50,6 +53,12 @@ pub struct VMObjects {
     pub packages: PackageObjs,
     pub prim_meta: PrimitiveMeta,
     pub(crate) arr_slice_caller: Box<ArrCaller>,
+    /// The zero values made at runtime, by metadata key, as templates to copy from
+    zeros: RefCell<Vec<Option<GosValue>>>,
+    /// The zero values of the results and the locals of the functions, by their types
+    zero_vecs: RefCell<Map<Vec<Meta>, Rc<[GosValue]>>>,
+    /// The method bindings found at runtime, by the value's type and the interface type
+    iface_bindings: RefCell<Map<(Meta, Meta), Option<IfaceBindings>>>,
 }
 
 impl VMObjects {
@@ -63,7 +72,118 @@ impl VMObjects {
             packages: PiggyVec::with_capacity(CAP),
             prim_meta,
             arr_slice_caller: Box::new(ArrCaller::new()),
+            zeros: RefCell::new(vec![]),
+            zero_vecs: RefCell::new(Map::new()),
+            iface_bindings: RefCell::new(Map::new()),
+        }
+    }
+
+    /// Returns the zero value of the type. The metadata is walked only the first time,
+    /// later calls copy the cached value, which for the types other than structs and
+    /// arrays doesn't allocate.
+    pub(crate) fn zero(&self, meta: &Meta, gcc: &GcContainer) -> GosValue {
+        self.zero_template(meta, gcc).copy_semantic(gcc)
+    }
+
+    /// Returns the cached zero value of the type itself, not a copy, it must be copied
+    /// before it's written to
+    fn zero_template(&self, meta: &Meta, gcc: &GcContainer) -> GosValue {
+        if meta.ptr_depth > 0 {
+            return GosValue::new_nil(ValueType::Pointer);
+        }
+        let index = meta.key.as_usize();
+        let mut zeros = self.zeros.borrow_mut();
+        if zeros.len() <= index {
+            zeros.resize(self.metas.vec().len(), None);
+        }
+        zeros[index]
+            .get_or_insert_with(|| meta.zero(&self.metas, gcc))
+            .clone()
+    }
+
+    /// Returns the zero values of the types, for the results or the locals of a
+    /// function. The functions with the same types share the vector, like the many
+    /// `func() error`, and the structs and the arrays in it are the cached zero values,
+    /// so the VM copies them before they are written to.
+    pub(crate) fn zero_vec(&self, metas: &[Meta], gcc: &GcContainer) -> Rc<[GosValue]> {
+        if let Some(zeros) = self.zero_vecs.borrow().get(metas) {
+            return zeros.clone();
         }
+        let zeros: Rc<[GosValue]> = metas.iter().map(|m| self.zero_template(m, gcc)).collect();
+        self.zero_vecs
+            .borrow_mut()
+            .insert(metas.to_vec(), zeros.clone());
+        zeros
+    }
+
+    /// Returns how the methods of the interface type bind to a value of type `meta`,
+    /// or None if the type doesn't implement the interface. This is for type assertions
+    /// and switches, where the bindings can't be worked out at compile time.
+    pub(crate) fn iface_binding(&self, meta: &Meta, iface: &Meta) -> Option<IfaceBindings> {
+        let key = (meta.into_value_category(), iface.into_value_category());
+        self.iface_bindings
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with(|| self.bind_iface_methods(meta, iface))
+            .clone()
+    }
+
+    fn bind_iface_methods(&self, meta: &Meta, iface: &Meta) -> Option<IfaceBindings> {
+        let methods = self.metas[iface.underlying(&self.metas).key].as_interface();
+        methods
+            .infos()
+            .iter()
+            .map(|method| self.bind_iface_method(meta, method))
+            .collect()
+    }
+
+    /// Returns the name of the first method of the interface `iface` that the type
+    /// `meta` doesn't have, for the errors of the failed assertions
+    pub(crate) fn missing_method(&self, meta: &Meta, iface: &Meta) -> Option<&str> {
+        let methods = self.metas[iface.underlying(&self.metas).key].as_interface();
+        methods
+            .infos()
+            .iter()
+            .find(|method| self.bind_iface_method(meta, method).is_none())
+            .map(|method| method.name.as_str())
+    }
+
+    fn bind_iface_method(&self, meta: &Meta, method: &FieldInfo) -> Option<Binding4Runtime> {
+        let (binding, sig_meta) = self.method_binding(meta, &method.name)?;
+        let want = self.metas[method.meta.key].as_signature();
+        match sig_meta {
+            Some(m) => self.metas[m.key]
+                .as_signature()
+                .identical_ignoring_recv(want, &self.metas)
+                .then_some(binding),
+            None => Some(binding),
+        }
+    }
+
+    /// Returns how the method `name` binds to a value of type `meta` and the Meta of
+    /// the method's signature, or None if the method is not in the method set of the
+    /// type. The signature is unknown for the methods of embedded interfaces.
+    pub(crate) fn method_binding(
+        &self,
+        meta: &Meta,
+        name: &String,
+    ) -> Option<(Binding4Runtime, Option<Meta>)> {
+        if meta.ptr_depth > 1 {
+            return None;
+        }
+        let binding = meta.get_iface_binding(name, &self.metas)?;
+        let sig_meta = match &binding {
+            IfaceBinding::Struct(desc, indices) => {
+                let desc = desc.borrow();
+                // methods with pointer receivers are not in the method set of the value
+                if desc.pointer_recv && meta.ptr_depth == 0 && indices.is_none() {
+                    return None;
+                }
+                Some(self.functions[desc.func?].meta)
+            }
+            IfaceBinding::Iface(_, _) => None,
+        };
+        Some((binding.into(), sig_meta))
     }
 }
 
@@ -89,6 +209,9 @@ impl BorshDeserialize for VMObjects {
             packages,
             prim_meta,
             arr_slice_caller: Box::new(ArrCaller::new()),
+            zeros: RefCell::new(vec![]),
+            zero_vecs: RefCell::new(Map::new()),
+            iface_bindings: RefCell::new(Map::new()),
         })
     }
 }
@@ -97,12 +220,21 @@ impl BorshDeserialize for VMObjects {
 pub struct Bytecode {
     pub objects: VMObjects,
     pub consts: Vec<GosValue>,
-    /// For calling method via interfaces
-    pub ifaces: Vec<(Meta, Vec<Binding4Runtime>)>,
+    /// For calling method via interfaces, the bindings are shared by the interfaces
+    /// the conversions make
+    pub ifaces: Vec<(Meta, IfaceBindings)>,
     /// For embedded fields of structs
     pub indices: Vec<Vec<OpIndex>>,
     pub entry: FunctionKey,
+    /// Initializes the packages without calling main
+    pub init_entry: FunctionKey,
     pub main_pkg: PackageKey,
+    /// The type of the errors the VM panics with, which implements runtime.Error
+    pub runtime_error: Meta,
+    /// The last conversion of an interface to each of `ifaces`, by the type of the
+    /// dynamic value, for the loops converting values of the same type over and over
+    #[cfg_attr(feature = "serde_borsh", borsh_skip)]
+    rebinds: RefCell<Vec<Option<(Meta, IfaceBindings)>>>,
 }
 
 impl Bytecode {
@@ -113,6 +245,7 @@ impl Bytecode {
         indices: Vec<Vec<OpIndex>>,
         entry: FunctionKey,
         main_pkg: PackageKey,
+        runtime_error: Meta,
     ) -> Bytecode {
         let ifaces = ifaces
             .into_iter()
@@ -127,7 +260,91 @@ impl Bytecode {
             ifaces,
             indices,
             entry,
+            init_entry: entry,
             main_pkg,
+            runtime_error,
+            rebinds: RefCell::new(vec![]),
+        }
+    }
+
+    /// Sets the function initializing the packages without calling main, it's the
+    /// entry function by default
+    pub fn with_init_entry(mut self, init_entry: FunctionKey) -> Bytecode {
+        self.init_entry = init_entry;
+        self
+    }
+
+    /// Converts the interface `iface` to the interface type of `ifaces[index]`, the
+    /// bindings made are reused by the next conversion of a value of the same type
+    pub(crate) fn rebind(&self, iface: &InterfaceObj, index: usize) -> RuntimeResult<InterfaceObj> {
+        let (val, meta) = match iface {
+            InterfaceObj::Gos(val, Some((meta, _))) => (val, meta),
+            _ => return Ok(iface.clone()),
+        };
+        let mut rebinds = self.rebinds.borrow_mut();
+        if rebinds.len() <= index {
+            rebinds.resize(self.ifaces.len(), None);
+        }
+        match &rebinds[index] {
+            Some((m, binding)) if m == meta => Ok(InterfaceObj::with_value(
+                val.clone(),
+                Some((*meta, binding.clone())),
+            )),
+            _ => {
+                let rebound = iface.rebind(&self.ifaces[index].1)?;
+                if let InterfaceObj::Gos(_, Some((_, binding))) = &rebound {
+                    rebinds[index] = Some((*meta, binding.clone()));
+                }
+                Ok(rebound)
+            }
+        }
+    }
+
+    /// Returns the code of the functions of a package as text, one instruction a
+    /// line with the instructions its jumps land on, in the order the functions
+    /// were generated. The functions are headed by their names, `main.init` is the
+    /// package ctor.
+    pub fn disassemble(&self, pkg: PackageKey) -> String {
+        let objs = &self.objects;
+        let mut text = String::new();
+        for func in objs.functions.vec().iter() {
+            if func.package != pkg {
+                continue;
+            }
+            text.push_str(&format!(
+                "func {}\n    params {}, results {}, locals {}, max write {}\n",
+                func.name,
+                func.param_count(),
+                func.ret_count(),
+                func.local_count(),
+                func.max_write_index
+            ));
+            let mut pc = 0;
+            while pc < func.code.len() {
+                let inst = &func.code[pc];
+                let next = (pc + 1 + inst.ext_count()).min(func.code.len());
+                for (i, ex) in func.code[pc..next].iter().enumerate() {
+                    let operands = match i {
+                        0 => inst.operands(),
+                        _ => inst.ext_operands(ex),
+                    };
+                    // the jumps are followed by where they land
+                    let targets: Vec<String> = [ex.d, ex.s0, ex.s1]
+                        .iter()
+                        .zip(operands.iter())
+                        .filter(|(_, o)| **o == Operand::Jump)
+                        .map(|(v, _)| (next as OpIndex + v).to_string())
+                        .collect();
+                    text.push_str(&format!("{:>5}  {:?}", pc + i, ex));
+                    if !targets.is_empty() {
+                        text.push_str(&format!("\t-> {}", targets.join(", ")));
+                    }
+                    text.push('\n');
+                }
+                pc = next;
+            }
+            text.push('\n');
         }
+        text
     }
 }
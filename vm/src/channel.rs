@@ -120,7 +120,7 @@ impl Channel {
                         future::yield_now().await;
                     }
                     async_channel::TrySendError::Closed(_) => {
-                        return Err("channel closed!".to_owned().into());
+                        return Err("send on closed channel".to_owned().into());
                     }
                 },
             }
@@ -157,49 +157,61 @@ pub struct SelectComm {
 pub struct Selector {
     pub comms: Vec<SelectComm>,
     pub default_offset: Option<OpIndex>,
+    /// Picks the first case ready instead of one at random
+    pub deterministic: bool,
 }
 
 impl Selector {
-    pub fn new(comms: Vec<SelectComm>, default_offset: Option<OpIndex>) -> Selector {
+    pub fn new(
+        comms: Vec<SelectComm>,
+        default_offset: Option<OpIndex>,
+        deterministic: bool,
+    ) -> Selector {
         Selector {
             comms,
             default_offset,
+            deterministic,
         }
     }
 
+    /// Waits for a case to be ready, returns its index, or the number of cases for the
+    /// default one, with the value received. Like in Go, the cases of nil channels are
+    /// never ready, and the ones of closed channels always are: a receive gets None
+    /// and a send fails. When several are ready one is picked at random, or the first
+    /// one in the order of the cases if `deterministic`.
     pub async fn select(&self) -> RuntimeResult<(usize, Option<GosValue>)> {
         let count = self.comms.len();
-        let rand_start = fastrand::usize(0..count);
         loop {
+            let start = if self.deterministic || count == 0 {
+                0
+            } else {
+                fastrand::usize(0..count)
+            };
             for i in 0..count {
-                let index = (i + rand_start) % count;
+                let index = (i + start) % count;
                 let entry = &self.comms[index];
+                let chan = match entry.chan.as_channel() {
+                    Some(c) => &c.chan,
+                    None => continue,
+                };
                 match &entry.typ {
-                    SelectCommType::Send(val) => {
-                        match entry.chan.as_non_nil_channel()?.chan.try_send(val.clone()) {
-                            Ok(_) => return Ok((index, None)),
-                            Err(e) => match e {
-                                async_channel::TrySendError::Full(_) => {}
-                                async_channel::TrySendError::Closed(_) => {
-                                    return Err("channel closed!".to_owned().into());
-                                }
-                            },
+                    SelectCommType::Send(val) => match chan.try_send(val.clone()) {
+                        Ok(_) => return Ok((index, None)),
+                        Err(async_channel::TrySendError::Full(_)) => {}
+                        Err(async_channel::TrySendError::Closed(_)) => {
+                            return Err("send on closed channel".to_owned().into());
                         }
-                    }
-                    SelectCommType::Recv(_, _) => {
-                        match entry.chan.as_non_nil_channel()?.chan.try_recv() {
-                            Ok(v) => return Ok((index, Some(v))),
-                            Err(e) => match e {
-                                async_channel::TryRecvError::Empty => {}
-                                async_channel::TryRecvError::Closed => return Ok((index, None)),
-                            },
-                        }
-                    }
+                    },
+                    SelectCommType::Recv(_, _) => match chan.try_recv() {
+                        Ok(v) => return Ok((index, Some(v))),
+                        Err(async_channel::TryRecvError::Empty) => {}
+                        Err(async_channel::TryRecvError::Closed) => return Ok((index, None)),
+                    },
                 }
             }
 
-            if let Some(_) = self.default_offset {
-                return Ok((self.comms.len(), None));
+            if self.default_offset.is_some() {
+                return Ok((count, None));
             }
             future::yield_now().await;
         }
@@ -0,0 +1,207 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Controlling a running VM from other threads of the host.
+//!
+//! The VM checks for pause and cancel requests at its safepoints, where a goroutine
+//! yields to the others after running a thousand or so instructions. A paused VM
+//! blocks the thread running it until it's resumed or cancelled. A VM with all its
+//! goroutines blocked, e.g. waiting for the host, pauses or stops once one of them
+//! runs again.
+//!
+//! A shutdown or a cancel lets the goroutines run their deferred calls first. The ones
+//! blocked waiting for others never reach a safepoint, they are dropped without
+//! running them.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Where the VM paused
+#[derive(Clone, Debug)]
+pub struct PauseInfo {
+    /// The id of the goroutine that reached the safepoint, the main goroutine is 0
+    pub goroutine: usize,
+    /// The positions of the calls of the goroutine, the innermost first, None for
+    /// the ones without debug info
+    pub call_stack: Vec<Option<String>>,
+    /// The names of the functions of the calls in `call_stack`, like `main.(*T).m`
+    pub functions: Vec<String>,
+    /// The named local variables in scope for each of the calls, with their values
+    /// as the VM prints them
+    pub locals: Vec<Vec<(String, String)>>,
+}
+
+#[derive(Default)]
+struct State {
+    paused: Option<PauseInfo>,
+    running: bool,
+    finished: bool,
+    // when a shutdown stops the run
+    shutdown_deadline: Option<Instant>,
+}
+
+#[derive(Default)]
+struct Inner {
+    pause_requested: AtomicBool,
+    cancel_requested: AtomicBool,
+    shutdown_requested: AtomicBool,
+    state: Mutex<State>,
+    cond: Condvar,
+}
+
+/// A handle to pause and resume the VM, which can be cloned and sent to other
+/// threads. One controller controls one run of the VM at a time.
+#[derive(Clone, Default)]
+pub struct VmController {
+    inner: Arc<Inner>,
+}
+
+impl VmController {
+    pub fn new() -> VmController {
+        VmController::default()
+    }
+
+    /// Asks the VM to pause at its next safepoint, it returns at once, `wait_paused`
+    /// waits for the VM to actually pause.
+    pub fn pause(&self) {
+        self.inner.pause_requested.store(true, Ordering::Release);
+    }
+
+    /// Lets the paused VM continue, or cancels a pause not taken yet.
+    pub fn resume(&self) {
+        self.inner.pause_requested.store(false, Ordering::Release);
+        let mut state = self.inner.state.lock().unwrap();
+        state.paused = None;
+        self.inner.cond.notify_all();
+    }
+
+    /// Returns where the VM is paused, or None if it is not paused
+    pub fn paused(&self) -> Option<PauseInfo> {
+        self.inner.state.lock().unwrap().paused.clone()
+    }
+
+    /// Blocks until the VM pauses and returns where, or None if the VM finishes
+    /// running first.
+    pub fn wait_paused(&self) -> Option<PauseInfo> {
+        let mut state = self.inner.state.lock().unwrap();
+        loop {
+            if let Some(info) = &state.paused {
+                return Some(info.clone());
+            }
+            if state.finished {
+                return None;
+            }
+            state = self.inner.cond.wait(state).unwrap();
+        }
+    }
+
+    /// Asks the goroutines to end at their next safepoints as if they called
+    /// runtime.Goexit, running their deferred calls, which can't recover from it. The
+    /// run is stopped after `grace` if they haven't ended by then. Either way it
+    /// returns `RunError::Shutdown`. A paused VM is resumed to shut down.
+    pub fn shutdown(&self, grace: Duration) {
+        let mut state = self.inner.state.lock().unwrap();
+        state.shutdown_deadline = Some(Instant::now() + grace);
+        self.inner.shutdown_requested.store(true, Ordering::Release);
+        drop(state);
+        self.resume();
+    }
+
+    /// Returns a token that stops the runs of the VM with this controller
+    pub fn cancel_handle(&self) -> CancelHandle {
+        CancelHandle {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Returns true if a run of the VM with this controller has started and not
+    /// finished yet
+    pub fn is_running(&self) -> bool {
+        self.inner.state.lock().unwrap().running
+    }
+
+    pub(crate) fn start(&self) {
+        let mut state = self.inner.state.lock().unwrap();
+        // a cancel before the run is for the one before it
+        self.inner.cancel_requested.store(false, Ordering::Release);
+        state.running = true;
+        state.finished = false;
+    }
+
+    pub(crate) fn finish(&self) {
+        let mut state = self.inner.state.lock().unwrap();
+        self.inner.cancel_requested.store(false, Ordering::Release);
+        self.inner
+            .shutdown_requested
+            .store(false, Ordering::Release);
+        state.shutdown_deadline = None;
+        state.running = false;
+        state.finished = true;
+        self.inner.cond.notify_all();
+    }
+
+    #[inline]
+    pub(crate) fn pause_requested(&self) -> bool {
+        self.inner.pause_requested.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn cancel_requested(&self) -> bool {
+        self.inner.cancel_requested.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn shutdown_requested(&self) -> bool {
+        self.inner.shutdown_requested.load(Ordering::Acquire)
+    }
+
+    /// Returns true if the grace period of the shutdown has run out
+    pub(crate) fn shutdown_overdue(&self) -> bool {
+        let state = self.inner.state.lock().unwrap();
+        state.shutdown_deadline.is_some_and(|d| Instant::now() >= d)
+    }
+
+    /// Called by the VM at a safepoint, blocks until resumed
+    pub(crate) fn park(&self, info: PauseInfo) {
+        let mut state = self.inner.state.lock().unwrap();
+        if !self.pause_requested() {
+            // resumed before the pause was taken
+            return;
+        }
+        state.paused = Some(info);
+        self.inner.cond.notify_all();
+        while state.paused.is_some() {
+            if self.cancel_requested() {
+                state.paused = None;
+                break;
+            }
+            state = self.inner.cond.wait(state).unwrap();
+        }
+    }
+}
+
+/// A token to stop a run of the VM from other threads, which can be cloned and
+/// sent to them. The goroutines unwind from their next safepoints like with
+/// runtime.Goexit, running their deferred calls, which can't recover from it, and the
+/// run returns `RunError::Cancelled`.
+#[derive(Clone)]
+pub struct CancelHandle {
+    inner: Arc<Inner>,
+}
+
+impl CancelHandle {
+    /// Stops the run in progress, a paused one included. It has no effect if the VM
+    /// is not running.
+    pub fn cancel(&self) {
+        self.inner.cancel_requested.store(true, Ordering::Release);
+        let _state = self.inner.state.lock().unwrap();
+        self.inner.cond.notify_all();
+    }
+
+    /// Returns true if the run is cancelled and has not finished yet
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancel_requested.load(Ordering::Acquire)
+    }
+}
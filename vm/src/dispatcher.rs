@@ -10,6 +10,9 @@ use std::hash::{Hash, Hasher};
 pub(crate) trait Dispatcher {
     fn typ(&self) -> ValueType;
 
+    /// The size of an element of the arrays in memory
+    fn elem_size(&self) -> usize;
+
     fn array_with_size(
         &self,
         size: usize,
@@ -102,6 +105,11 @@ macro_rules! define_dispatcher {
                 self.typ
             }
 
+            #[inline]
+            fn elem_size(&self) -> usize {
+                std::mem::size_of::<$elem>()
+            }
+
             fn array_with_size(
                 &self,
                 size: usize,
@@ -180,6 +188,10 @@ macro_rules! define_dispatcher {
                 end: isize,
                 max: isize,
             ) -> RuntimeResult<GosValue> {
+                // as in Go, a nil slice can be sliced with zero indices
+                if slice.is_nil() && begin == 0 && end <= 0 && max <= 0 {
+                    return Ok(slice.clone());
+                }
                 Ok(GosValue::new_slice(
                     slice
                         .as_non_nil_slice::<$elem>()?
@@ -212,11 +224,24 @@ macro_rules! define_dispatcher {
                 let a = this.as_slice::<$elem>();
                 let b = other.as_slice::<$elem>();
                 match b {
+                    // appending nothing returns the slice as it is, even if it's nil
+                    Some(y) if y.0.len() == 0 => Ok(this),
                     Some(y) => match a {
-                        Some(x) => {
-                            let mut to = x.0.clone();
-                            to.append(&y.0);
-                            Ok(GosValue::new_slice(to, other.t_elem()))
+                        Some(_) => {
+                            let t_elem = this.t_elem();
+                            let mut rc = this.into_slice::<$elem>().unwrap();
+                            match Rc::get_mut(&mut rc) {
+                                // nobody else sees the slice, so it's appended in place
+                                Some((x, _)) => {
+                                    x.append(&y.0);
+                                    Ok(GosValue::from_slice(Some(rc), t_elem))
+                                }
+                                None => {
+                                    let mut to = rc.0.clone();
+                                    to.append(&y.0);
+                                    Ok(GosValue::new_slice(to, t_elem))
+                                }
+                            }
                         }
                         None => {
                             let data = y.0.as_rust_slice().to_vec();
@@ -269,7 +294,14 @@ macro_rules! define_dispatcher {
                 val: &GosValue,
             ) -> RuntimeResult<SliceEnumIter<'static, AnyElem>> {
                 let rust_slice = match val.typ() {
-                    ValueType::Slice => val.as_non_nil_slice::<$elem>()?.0.as_rust_slice(),
+                    ValueType::Slice => match val.as_slice::<$elem>() {
+                        Some(s) => s.0.as_rust_slice(),
+                        // a nil slice has nothing to range over
+                        None => {
+                            let empty: &'static [AnyElem] = &[];
+                            return Ok(empty.iter().enumerate());
+                        }
+                    },
                     ValueType::Array => val.as_array::<$elem>().0.as_rust_slice(),
                     _ => unreachable!(),
                 };
@@ -5,13 +5,21 @@
 use crate::bytecode::*;
 use crate::dispatcher::ArrCaller;
 use crate::gc::GcContainer;
+#[cfg(feature = "race")]
+use crate::race::RaceCtx;
+use crate::sandbox::{Capability, SandboxPolicy};
+#[cfg(feature = "async")]
+use crate::scheduler::{Sleep, Timers};
 use crate::stack::Stack;
 use crate::value::*;
 use crate::value::{GosValue, RuntimeResult};
 #[cfg(feature = "async")]
+use crate::vm::Quota;
+#[cfg(feature = "async")]
 use futures_lite::future::Future;
 use go_parser::Map;
-use std::cell::Ref;
+use std::borrow::Cow;
+use std::cell::{Ref, RefMut};
 #[cfg(feature = "async")]
 use std::pin::Pin;
 use std::rc::Rc;
@@ -23,6 +31,19 @@ pub struct FfiCtx<'a> {
     pub stack: &'a mut Stack,
     pub gcc: &'a GcContainer,
     pub(crate) array_slice_caller: &'a ArrCaller,
+    pub(crate) func_meta: Meta,
+    pub(crate) ffi_factory: Option<&'a FfiFactory>,
+    /// What the script may do to the host, not restricted if None
+    pub sandbox: Option<&'a SandboxPolicy>,
+    /// The id of the goroutine calling, the main goroutine is 0
+    pub goroutine: usize,
+    #[cfg(feature = "async")]
+    pub(crate) go_caller: Option<&'a GoCaller>,
+    #[cfg(feature = "async")]
+    pub(crate) timers: Option<&'a Rc<Timers>>,
+    /// The race detector, for the FFIs implementing sync objects
+    #[cfg(feature = "race")]
+    pub race: Option<RaceCtx>,
 }
 
 impl<'a> FfiCtx<'a> {
@@ -71,6 +92,13 @@ impl<'a> FfiCtx<'a> {
         GosValue::with_str(s)
     }
 
+    /// Creates a string of the bytes, which don't have to be valid UTF-8
+    #[inline]
+    pub fn new_string_of_bytes(bytes: Vec<u8>) -> GosValue {
+        let buf: Vec<Elem8> = unsafe { std::mem::transmute(bytes) };
+        GosValue::new_string(StringObj::with_buf(buf))
+    }
+
     #[inline]
     pub fn new_unsafe_ptr(p: Rc<dyn UnsafePtr>) -> GosValue {
         GosValue::new_unsafe_ptr(p)
@@ -86,6 +114,11 @@ impl<'a> FfiCtx<'a> {
         GosValue::array_with_data(member, self.array_slice_caller.get(t_elem), self.gcc)
     }
 
+    #[inline]
+    pub fn new_slice(&self, member: Vec<GosValue>, t_elem: ValueType) -> GosValue {
+        GosValue::slice_with_data(member, self.array_slice_caller.get(t_elem), self.gcc)
+    }
+
     #[inline]
     pub fn new_primitive_array<T>(&self, member: Vec<T>, t_elem: ValueType) -> GosValue
     where
@@ -95,6 +128,14 @@ impl<'a> FfiCtx<'a> {
         GosValue::new_non_gc_array(ArrayObj::with_raw_data(buf), t_elem)
     }
 
+    /// Creates a []byte holding a copy of `bytes`
+    #[inline]
+    pub fn new_bytes(bytes: &[u8]) -> GosValue {
+        let buf: Vec<Elem8> = unsafe { std::mem::transmute(bytes.to_vec()) };
+        let arr = GosValue::new_non_gc_array(ArrayObj::with_raw_data(buf), ValueType::Uint8);
+        GosValue::new_slice(StringObj::with_array(arr, 0, -1).unwrap(), ValueType::Uint8)
+    }
+
     #[inline]
     pub fn new_pointer(pointee: GosValue) -> GosValue {
         let pobj = PointerObj::UpVal(UpValue::new_closed(pointee));
@@ -106,6 +147,7 @@ impl<'a> FfiCtx<'a> {
         underlying: GosValue,
         meta: Option<(Meta, Vec<Binding4Runtime>)>,
     ) -> GosValue {
+        let meta = meta.map(|(m, binding)| (m, binding.into()));
         GosValue::new_interface(InterfaceObj::with_value(underlying, meta))
     }
 
@@ -117,7 +159,94 @@ impl<'a> FfiCtx<'a> {
 
     #[inline]
     pub fn zero_val(&self, m: &Meta) -> GosValue {
-        m.zero(&self.vm_objs.metas, self.gcc)
+        self.vm_objs.zero(m, self.gcc)
+    }
+
+    /// Returns the sorted names of all the registered FFIs
+    pub fn ffi_names(&self) -> Vec<&'a str> {
+        self.ffi_factory.map(|f| f.names()).unwrap_or_default()
+    }
+
+    /// Returns the Meta of the i-th declared result of the FFI function being called
+    #[inline]
+    pub fn result_meta(&self, i: usize) -> Option<Meta> {
+        self.vm_objs.metas[self.func_meta.key]
+            .as_signature()
+            .results
+            .get(i)
+            .copied()
+    }
+
+    /// Binds the method `name` of the dynamic value of the interface `iface`, the way
+    /// a method value is made in Go, for the FFIs to call back into the script.
+    pub fn bind_method(&self, iface: &GosValue, name: &str) -> RuntimeResult<GosValue> {
+        let objs = self.vm_objs;
+        let (val, meta) = match iface.as_non_nil_interface()? {
+            InterfaceObj::Gos(val, Some((meta, _))) => (val, meta),
+            _ => return Err("not a Go value".to_owned().into()),
+        };
+        let (binding, _) = objs
+            .method_binding(meta, &name.to_owned())
+            .ok_or_else(|| format!("the value has no method {}", name))?;
+        let iface = InterfaceObj::with_value(val.clone(), Some((*meta, Rc::from([binding]))));
+        crate::vm::bind_iface_method(&iface, 0, self.stack, objs, self.gcc)
+    }
+
+    /// Returns the types of the parameters and the results of the function `f`
+    pub fn func_types(&self, f: &GosValue) -> Option<(Vec<Meta>, Vec<Meta>)> {
+        let meta = match &f.as_closure()?.0 {
+            ClosureObj::Gos(c) => c.meta,
+            ClosureObj::Ffi(c) => c.meta,
+        };
+        let sig = self.vm_objs.metas[meta.key].as_signature();
+        Some((sig.params.clone(), sig.results.clone()))
+    }
+
+    /// Reports whether the sandbox policy of the run, if any, lets the script do `cap`
+    pub fn allows(&self, cap: &Capability) -> bool {
+        self.sandbox.is_none_or(|p| p.check(cap))
+    }
+
+    /// Returns a handle for calling Go functions, which async FFI functions can keep
+    /// after the call to them returns.
+    #[cfg(feature = "async")]
+    pub fn go_caller(&self) -> Option<GoCaller> {
+        self.go_caller.cloned()
+    }
+
+    /// Returns a future ready at `deadline`, for async FFI functions to sleep on the
+    /// scheduler of the VM instead of blocking its thread. See `Sleep` for `daemon`.
+    #[cfg(feature = "async")]
+    pub fn sleep_until(&self, deadline: std::time::Instant, daemon: bool) -> Sleep {
+        let timers = self.timers.expect("sleeping out of a run");
+        Sleep::new(timers.clone(), deadline, daemon)
+    }
+
+    /// Converts a GosValue of the Go type described by `meta` into a Rust value,
+    /// pointers to local variables and package members are followed as well.
+    #[cfg(feature = "serde")]
+    #[inline]
+    pub fn from_value<T: serde::de::DeserializeOwned>(
+        &self,
+        val: &GosValue,
+        meta: &Meta,
+    ) -> RuntimeResult<T> {
+        T::deserialize(crate::value_serde::ValueDeserializer::new(
+            val.clone(),
+            *meta,
+            self.vm_objs,
+            Some(self.stack),
+        ))
+    }
+
+    #[cfg(feature = "serde")]
+    #[inline]
+    pub fn to_value<T: serde::Serialize + ?Sized>(
+        &self,
+        val: &T,
+        meta: &Meta,
+    ) -> RuntimeResult<GosValue> {
+        crate::value_serde::to_value(val, meta, self.vm_objs, self.gcc)
     }
 
     #[inline]
@@ -137,6 +266,31 @@ impl<'a> FfiCtx<'a> {
         Ok(val.as_non_nil_slice::<CellElem<C>>()?.0.as_raw_slice::<D>())
     }
 
+    /// Like `slice_as_rust_slice`, for changing the elements in place
+    #[inline]
+    pub fn slice_as_rust_slice_mut<T>(val: &GosValue) -> RuntimeResult<RefMut<'_, [T]>>
+    where
+        T: Element,
+    {
+        Ok(val.as_non_nil_slice::<T>()?.0.as_rust_slice_mut())
+    }
+
+    /// Like `slice_as_primitive_slice`, for changing the elements in place, e.g. of a
+    /// []byte with `slice_as_primitive_slice_mut::<u8, u8>`
+    #[inline]
+    pub fn slice_as_primitive_slice_mut<'b, C, D>(
+        val: &'b GosValue,
+    ) -> RuntimeResult<RefMut<'b, [D]>>
+    where
+        C: CellData + 'b,
+        D: Copy,
+    {
+        Ok(val
+            .as_non_nil_slice::<CellElem<C>>()?
+            .0
+            .as_raw_slice_mut::<D>())
+    }
+
     #[inline]
     pub fn array_as_rust_slice<T>(val: &GosValue) -> Ref<[T]>
     where
@@ -167,6 +321,66 @@ pub trait Ffi {
     ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>> + '_>>;
 }
 
+/// Calls Go functions from async FFI functions, each call runs as a new goroutine.
+#[cfg(feature = "async")]
+#[derive(Clone)]
+pub struct GoCaller {
+    calls: async_channel::Sender<GoCall>,
+}
+
+#[cfg(feature = "async")]
+pub(crate) struct GoCall {
+    pub closure: GosValue,
+    pub args: Vec<GosValue>,
+    pub quota: Option<Quota>,
+    pub reply: async_channel::Sender<RuntimeResult<Vec<GosValue>>>,
+}
+
+#[cfg(feature = "async")]
+impl GoCaller {
+    pub(crate) fn new() -> (GoCaller, async_channel::Receiver<GoCall>) {
+        let (calls, receiver) = async_channel::unbounded();
+        (GoCaller { calls }, receiver)
+    }
+
+    /// Calls the function `f` with `args`, which must match its parameters, and
+    /// returns its results. A panic not recovered in the call is returned as an error.
+    pub async fn call(&self, f: GosValue, args: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        self.call_with(f, args, None).await
+    }
+
+    /// Like `call`, with the goroutine of the call limited by `quota` instead of
+    /// `RunOptions::goroutine_quota`, for one runaway call not to hold up the others.
+    pub async fn call_with_quota(
+        &self,
+        f: GosValue,
+        args: Vec<GosValue>,
+        quota: Quota,
+    ) -> RuntimeResult<Vec<GosValue>> {
+        self.call_with(f, args, Some(quota)).await
+    }
+
+    async fn call_with(
+        &self,
+        f: GosValue,
+        args: Vec<GosValue>,
+        quota: Option<Quota>,
+    ) -> RuntimeResult<Vec<GosValue>> {
+        let stopped = || Err("the VM has stopped".to_owned().into());
+        let (reply, result) = async_channel::bounded(1);
+        let call = GoCall {
+            closure: f,
+            args,
+            quota,
+            reply,
+        };
+        if self.calls.send(call).await.is_err() {
+            return stopped();
+        }
+        result.recv().await.unwrap_or_else(|_| stopped())
+    }
+}
+
 impl std::fmt::Debug for dyn Ffi {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}", "ffi")
@@ -174,7 +388,7 @@ impl std::fmt::Debug for dyn Ffi {
 }
 
 pub struct FfiFactory {
-    registry: Map<&'static str, Rc<dyn Ffi>>,
+    registry: Map<Cow<'static, str>, Rc<dyn Ffi>>,
     /// Down-casting only works for 'static types,
     /// so we just use the good old pointers
     user_data: Option<usize>,
@@ -195,8 +409,16 @@ impl FfiFactory {
         }
     }
 
-    pub fn register(&mut self, name: &'static str, proto: Rc<dyn Ffi>) {
-        assert!(self.registry.insert(name, proto).is_none());
+    /// Registers an FFI, the name can be made at runtime, e.g. from a plugin's name
+    pub fn register(&mut self, name: impl Into<Cow<'static, str>>, proto: Rc<dyn Ffi>) {
+        assert!(self.registry.insert(name.into(), proto).is_none());
+    }
+
+    /// Returns the sorted names of all the registered FFIs
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.registry.keys().map(|k| k.as_ref()).collect();
+        names.sort_unstable();
+        names
     }
 
     pub(crate) fn user_data(&self) -> Option<usize> {
@@ -245,6 +467,16 @@ impl CodeGenVMCtx {
             stack: &mut self.dummy_stack,
             gcc: &&self.dummy_gcc,
             array_slice_caller: &self.caller,
+            func_meta: self.vm_objs.prim_meta.default_sig,
+            ffi_factory: None,
+            sandbox: None,
+            goroutine: 0,
+            #[cfg(feature = "async")]
+            go_caller: None,
+            #[cfg(feature = "async")]
+            timers: None,
+            #[cfg(feature = "race")]
+            race: None,
         }
     }
 
@@ -295,14 +527,21 @@ impl CodeGenVMCtx {
     pub fn function_with_meta(
         &mut self,
         package: Option<PackageKey>,
+        name: FuncName,
         meta: Meta,
         flag: FuncFlag,
     ) -> GosValue {
         let package = package.unwrap_or_else(|| PackageKey::null());
-        let val = FunctionObj::new(package, meta, &self.vm_objs.metas, &self.dummy_gcc, flag);
+        let val = FunctionObj::new(package, name, meta, &self.vm_objs, &self.dummy_gcc, flag);
         GosValue::new_function(self.vm_objs.functions.insert(val))
     }
 
+    /// Returns the zero values of the types for the locals of a function, shared with
+    /// the other functions with locals of the same types
+    pub fn zero_vec(&self, metas: &[Meta]) -> Rc<[GosValue]> {
+        self.vm_objs.zero_vec(metas, &self.dummy_gcc)
+    }
+
     pub fn new_struct_meta(&mut self, fields: Fields) -> Meta {
         Meta::new_struct(fields, &mut self.vm_objs)
     }
@@ -0,0 +1,221 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Formatting floats the way Go's strconv.FormatFloat does, for the values to be
+//! printed like Go prints them. Rust finds the digits, shortest or rounded, and they
+//! are laid out here.
+
+/// The decimal digits of a float, without the leading and trailing zeros, the value
+/// being 0.d[0]d[1]...d[n-1] * 10^dp, no digits for zero
+struct Digits {
+    d: Vec<u8>,
+    dp: i32,
+}
+
+impl Digits {
+    /// Parses the output of the `{:e}` formats, like "1.2345e-7"
+    fn parse(s: &str) -> Digits {
+        let (mant, exp) = s.split_once('e').unwrap();
+        let mut d: Vec<u8> = mant.bytes().filter(|b| *b != b'.').collect();
+        while d.last() == Some(&b'0') {
+            d.pop();
+        }
+        let dp = if d.is_empty() {
+            0
+        } else {
+            exp.parse::<i32>().unwrap() + 1
+        };
+        Digits { d, dp }
+    }
+
+    /// The fewest digits reading back to the same float of `bits` bits
+    fn shortest(f: f64, bits: u32) -> Digits {
+        if bits == 32 {
+            Digits::parse(&format!("{:e}", f as f32))
+        } else {
+            Digits::parse(&format!("{:e}", f))
+        }
+    }
+
+    /// `n` significant digits, rounded to the nearest, the ties to even
+    fn rounded(f: f64, n: usize) -> Digits {
+        Digits::parse(&format!("{:.*e}", n.max(1) - 1, f))
+    }
+
+    fn nd(&self) -> i32 {
+        self.d.len() as i32
+    }
+}
+
+/// Formats `f` like Go's `strconv.FormatFloat(f, fmt, prec, bits)`: `fmt` is one of
+/// b'e', b'E', b'f', b'g' and b'G', `prec` the digits after the point for 'e' and 'f'
+/// and the significant digits for 'g', -1 for the fewest reading back to `f` as a
+/// float of `bits` bits, 32 or 64. The values printed by `fmt.Println` are formatted
+/// with b'g' and -1.
+pub fn format_float(f: f64, fmt: u8, prec: i32, bits: u32) -> String {
+    if f.is_nan() {
+        return "NaN".to_owned();
+    } else if f.is_infinite() {
+        return if f > 0.0 { "+Inf" } else { "-Inf" }.to_owned();
+    }
+    let neg = f.is_sign_negative();
+    let f = f.abs();
+    let mut buf = String::new();
+    if neg {
+        buf.push('-');
+    }
+    let shortest = prec < 0;
+    match fmt {
+        b'e' | b'E' => {
+            let digs = if shortest {
+                Digits::shortest(f, bits)
+            } else {
+                Digits::rounded(f, prec as usize + 1)
+            };
+            let prec = if shortest { digs.nd() - 1 } else { prec };
+            fmt_e(&mut buf, &digs, prec, fmt as char);
+        }
+        b'f' => {
+            if shortest {
+                let digs = Digits::shortest(f, bits);
+                fmt_f(&mut buf, &digs, (digs.nd() - digs.dp).max(0));
+            } else {
+                buf.push_str(&format!("{:.*}", prec as usize, f));
+            }
+        }
+        b'g' | b'G' => {
+            let (digs, mut prec) = if shortest {
+                let digs = Digits::shortest(f, bits);
+                let nd = digs.nd();
+                (digs, nd)
+            } else {
+                let prec = prec.max(1);
+                (Digits::rounded(f, prec as usize), prec)
+            };
+            let mut eprec = prec;
+            if eprec > digs.nd() && digs.nd() >= digs.dp {
+                eprec = digs.nd();
+            }
+            // the exponent is used if it's less than -4 or not less than the
+            // precision, which is 6 for the shortest
+            if shortest {
+                eprec = 6;
+            }
+            let exp = digs.dp - 1;
+            if exp < -4 || exp >= eprec {
+                prec = prec.min(digs.nd());
+                let e = if fmt == b'g' { 'e' } else { 'E' };
+                fmt_e(&mut buf, &digs, prec - 1, e);
+            } else {
+                if prec > digs.dp {
+                    prec = digs.nd();
+                }
+                fmt_f(&mut buf, &digs, (prec - digs.dp).max(0));
+            }
+        }
+        _ => {
+            buf.clear();
+            buf.push('%');
+            buf.push(fmt as char);
+        }
+    }
+    buf
+}
+
+/// d.ddddde±dd, with `prec` digits after the point
+fn fmt_e(buf: &mut String, digs: &Digits, prec: i32, e: char) {
+    let digit = |i: i32| digs.d.get(i as usize).map_or('0', |d| *d as char);
+    buf.push(digit(0));
+    if prec > 0 {
+        buf.push('.');
+        (1..=prec).for_each(|i| buf.push(digit(i)));
+    }
+    buf.push(e);
+    let exp = if digs.d.is_empty() { 0 } else { digs.dp - 1 };
+    buf.push(if exp < 0 { '-' } else { '+' });
+    buf.push_str(&format!("{:02}", exp.abs()));
+}
+
+/// ddddd.ddddd, with `prec` digits after the point
+fn fmt_f(buf: &mut String, digs: &Digits, prec: i32) {
+    let digit = |i: i32| match i {
+        i if i < 0 => '0',
+        i => digs.d.get(i as usize).map_or('0', |d| *d as char),
+    };
+    if digs.dp > 0 {
+        (0..digs.dp).for_each(|i| buf.push(digit(i)));
+    } else {
+        buf.push('0');
+    }
+    if prec > 0 {
+        buf.push('.');
+        (0..prec).for_each(|i| buf.push(digit(digs.dp + i)));
+    }
+}
+
+/// Formats a complex number like Go prints it, e.g. "(1+2i)"
+pub fn format_complex(r: f64, i: f64, bits: u32) -> String {
+    let imag = format_float(i, b'g', -1, bits);
+    let sign = match imag.as_bytes()[0] {
+        b'-' | b'+' => "",
+        _ => "+",
+    };
+    format!("({}{}{}i)", format_float(r, b'g', -1, bits), sign, imag)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shortest() {
+        let g = |f: f64| format_float(f, b'g', -1, 64);
+        assert_eq!(g(0.1), "0.1");
+        assert_eq!(g(0.0), "0");
+        assert_eq!(g(-0.0), "-0");
+        assert_eq!(g(1.0), "1");
+        assert_eq!(g(100000.0), "100000");
+        assert_eq!(g(1000000.0), "1e+06");
+        assert_eq!(g(123456789.0), "1.23456789e+08");
+        assert_eq!(g(1e21), "1e+21");
+        assert_eq!(g(0.0001), "0.0001");
+        assert_eq!(g(0.00001234), "1.234e-05");
+        assert_eq!(g(-2.5e-300), "-2.5e-300");
+        assert_eq!(g(f64::INFINITY), "+Inf");
+        assert_eq!(g(f64::NEG_INFINITY), "-Inf");
+        assert_eq!(g(f64::NAN), "NaN");
+        assert_eq!(format_float(0.1f32 as f64, b'g', -1, 32), "0.1");
+        assert_eq!(
+            format_float(0.1f32 as f64, b'g', -1, 64),
+            "0.10000000149011612"
+        );
+        assert_eq!(format_float(3.0, b'e', -1, 64), "3e+00");
+        assert_eq!(format_float(1e21, b'f', -1, 64), "1000000000000000000000");
+        assert_eq!(format_float(0.000125, b'f', -1, 64), "0.000125");
+    }
+
+    #[test]
+    fn test_precision() {
+        assert_eq!(format_float(1.23456, b'f', 2, 64), "1.23");
+        assert_eq!(format_float(2.5, b'f', 0, 64), "2");
+        assert_eq!(format_float(-1.0, b'f', 3, 64), "-1.000");
+        assert_eq!(format_float(1234.5678, b'e', 3, 64), "1.235e+03");
+        assert_eq!(format_float(0.0, b'e', 2, 64), "0.00e+00");
+        assert_eq!(format_float(1234.5678, b'E', 0, 64), "1E+03");
+        assert_eq!(format_float(1234.5678, b'g', 3, 64), "1.23e+03");
+        assert_eq!(format_float(1234.5678, b'g', 6, 64), "1234.57");
+        assert_eq!(format_float(100.0, b'g', 6, 64), "100");
+        assert_eq!(format_float(0.000012345, b'G', 2, 64), "1.2E-05");
+        assert_eq!(format_float(1.0, b'g', 0, 64), "1");
+        assert_eq!(format_float(1.0, b'x', 0, 64), "%x");
+    }
+
+    #[test]
+    fn test_complex() {
+        assert_eq!(format_complex(1.0, 2.0, 64), "(1+2i)");
+        assert_eq!(format_complex(0.5, -1e-7, 64), "(0.5-1e-07i)");
+        assert_eq!(format_complex(0.0, f64::INFINITY, 32), "(0+Infi)");
+        assert_eq!(format_complex(f64::NAN, f64::NAN, 64), "(NaN+NaNi)");
+    }
+}
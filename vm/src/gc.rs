@@ -2,26 +2,350 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
+#[cfg(feature = "leak_track")]
+use super::bytecode::FunctionKey;
+#[cfg(feature = "leak_track")]
+use super::instruction::OpIndex;
 use super::instruction::ValueType;
 use super::objects::*;
 use super::value::{GosValue, RCQueue, RCount, IRC};
+use go_parser::Map;
 use std::cell::Ref;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::convert::TryFrom;
+use std::fmt;
+use std::mem::size_of;
+use std::ops::{Deref, Range};
 use std::rc::{Rc, Weak};
 
+// the least number of old objects over which they are collected
+const MIN_COLLECT_THRESHOLD: usize = 4096;
+
+// the number of objects made over which the young ones are collected
+const NURSERY_SIZE: usize = 1024;
+
+// the number of old objects scanned at once by an incremental collection
+const SLICE_SIZE: usize = 512;
+
+// every how many incremental collections the whole heap is collected at once, for
+// the cycles of objects far apart, that no slice holds whole
+const FULL_EVERY: usize = 4;
+
+/// The bytes of an entry added to a map, the key and the value
+pub(crate) const MAP_ENTRY_SIZE: usize = 2 * size_of::<GosValue>();
+
+/// What the objects of a run take, the bytes are estimated: the objects are measured
+/// when they are made and grow, and again by the collections, which find the ones
+/// freed. The strings are counted when made, and by the collections only if they are
+/// held by slices, arrays, maps or structs. The young objects are measured by the
+/// collections of the nursery, the old ones as the incremental collections go over
+/// them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// The bytes of the live objects
+    pub bytes: usize,
+    /// The most bytes live at once
+    pub peak_bytes: usize,
+    /// The bytes allocated over the run, the freed ones included
+    pub allocated_bytes: u64,
+    /// The objects made over the run, the arrays, structs, closures and maps, the
+    /// freed ones included
+    pub allocations: u64,
+    /// The objects the collector tracks
+    pub objects: usize,
+    /// The collections of the old objects done, at once or in slices
+    pub collections: usize,
+    /// The collections of the young objects done
+    pub minor_collections: usize,
+    /// The values the host keeps with `RootedValue`s
+    pub roots: usize,
+}
+
+/// The values kept by the host, by slot
+#[derive(Default)]
+struct RootSet {
+    values: Vec<Option<GosValue>>,
+    free: Vec<usize>,
+}
+
+/// A value the host keeps across the runs of the collector, it and what it refers to
+/// are not collected while the handle lives, whatever else refers to them. Made with
+/// `GcContainer::root` or `Instance::root`, it derefs to the value, for its typed
+/// accessors like `as_int` or `as_map`. Its clones are rooted too.
+pub struct RootedValue {
+    val: GosValue,
+    slot: usize,
+    roots: Weak<RefCell<RootSet>>,
+}
+
+impl RootedValue {
+    /// A copy of the value, not rooted, for passing to the calls
+    pub fn value(&self) -> GosValue {
+        self.val.clone()
+    }
+}
+
+impl Deref for RootedValue {
+    type Target = GosValue;
+
+    fn deref(&self) -> &GosValue {
+        &self.val
+    }
+}
+
+impl Clone for RootedValue {
+    fn clone(&self) -> Self {
+        match self.roots.upgrade() {
+            Some(roots) => add_root(&roots, self.val.clone()),
+            None => RootedValue {
+                val: self.val.clone(),
+                slot: self.slot,
+                roots: Weak::new(),
+            },
+        }
+    }
+}
+
+impl Drop for RootedValue {
+    fn drop(&mut self) {
+        // the set is gone with the container, and the values in it
+        if let Some(roots) = self.roots.upgrade() {
+            let mut roots = roots.borrow_mut();
+            roots.values[self.slot] = None;
+            roots.free.push(self.slot);
+        }
+    }
+}
+
+impl fmt::Debug for RootedValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("RootedValue").field(&self.val).finish()
+    }
+}
+
+fn add_root(roots: &Rc<RefCell<RootSet>>, val: GosValue) -> RootedValue {
+    let mut set = roots.borrow_mut();
+    let slot = match set.free.pop() {
+        Some(i) => {
+            set.values[i] = Some(val.clone());
+            i
+        }
+        None => {
+            set.values.push(Some(val.clone()));
+            set.values.len() - 1
+        }
+    };
+    RootedValue {
+        val,
+        slot,
+        roots: Rc::downgrade(roots),
+    }
+}
+
+/// A slice, an interface, an upvalue or an unsafe pointer shared by many values,
+/// what it refers to is taken a reference from once all of them are scanned. The
+/// handles are weak, for a shared value freed in the meantime to be skipped.
+#[derive(Clone)]
+pub(crate) enum SharedInner {
+    Interface(Weak<InterfaceObj>),
+    Slice(Weak<(GosSliceObj, RCount)>),
+    UpValue(Weak<RefCell<UpValueState>>),
+    Unsafe(Weak<dyn UnsafePtr>),
+}
+
+/// The references to a shared value from the objects being scanned
+struct SharedRef {
+    refs: usize,
+    // the values sharing it
+    strong: usize,
+    inner: SharedInner,
+    done: bool,
+}
+
+thread_local! {
+    // the values shared by the objects being scanned, by their addresses
+    static SHARED: RefCell<Option<Map<usize, SharedRef>>> = const { RefCell::new(None) };
+}
+
+/// for gc, takes a reference from an object being scanned to what the value at
+/// `addr`, shared by `strong` values, refers to, if all of them are referred to by
+/// the objects being scanned.
+pub(crate) fn ref_sub_one_shared(addr: *const (), strong: usize, inner: SharedInner) {
+    let inner = SHARED.with(|shared| match shared.borrow_mut().as_mut() {
+        Some(map) => {
+            let r = map.entry(addr as usize).or_insert(SharedRef {
+                refs: 0,
+                strong,
+                inner,
+                done: false,
+            });
+            r.refs += 1;
+            None
+        }
+        None => Some(inner),
+    });
+    if let Some(inner) = inner {
+        ref_sub_one_inner(inner);
+    }
+}
+
+fn ref_sub_one_inner(inner: SharedInner) {
+    match inner {
+        SharedInner::Interface(w) => {
+            if let Some(iface) = w.upgrade() {
+                if let InterfaceObj::Gos(v, _) = &*iface {
+                    v.ref_sub_one();
+                }
+            }
+        }
+        SharedInner::Slice(w) => {
+            if let Some(s) = w.upgrade() {
+                s.0.array().ref_sub_one();
+            }
+        }
+        SharedInner::UpValue(w) => {
+            if let Some(uv) = w.upgrade() {
+                if let UpValueState::Closed(v) = &*uv.borrow() {
+                    v.ref_sub_one();
+                }
+            }
+        }
+        SharedInner::Unsafe(w) => {
+            if let Some(p) = w.upgrade() {
+                p.ref_sub_one();
+            }
+        }
+    }
+}
+
+/// Takes the references to what the shared values refer to, for the ones only
+/// referred to by the objects scanned, until there are no more
+fn ref_sub_one_all_shared() {
+    loop {
+        let ready: Vec<SharedInner> = SHARED.with(|shared| {
+            let mut shared = shared.borrow_mut();
+            shared
+                .as_mut()
+                .unwrap()
+                .values_mut()
+                .filter(|r| !r.done && r.refs == r.strong)
+                .map(|r| {
+                    r.done = true;
+                    r.inner.clone()
+                })
+                .collect()
+        });
+        if ready.is_empty() {
+            break;
+        }
+        ready.into_iter().for_each(ref_sub_one_inner);
+    }
+}
+
+/// The arrays of numbers can't refer to other objects, they are not collected, only
+/// measured
+trait HeapSize {
+    fn heap_size(&self) -> usize;
+}
+
+impl<T: Element> HeapSize for (ArrayObj<T>, RCount) {
+    fn heap_size(&self) -> usize {
+        self.0.borrow_data().capacity() * size_of::<T>()
+    }
+}
+
+/// The instruction that allocated an object, None for the ones not allocated by
+/// the VM running code
+#[cfg(feature = "leak_track")]
+pub(crate) type AllocSite = Option<(FunctionKey, OpIndex)>;
+
+#[cfg(feature = "leak_track")]
+#[derive(Clone, Copy)]
+struct AllocTag {
+    site: AllocSite,
+    // the number of collections the object has survived
+    survived: u32,
+}
+
+/// An incremental collection of the old objects going on
+#[derive(Clone, Copy, Default)]
+struct Sweep {
+    // the next old object to scan
+    next: usize,
+    // where the next one found alive goes, the ones before it are alive
+    write: usize,
+    // the bytes of the ones found alive
+    bytes: usize,
+}
+
+/// The objects that can be in cycles are tracked by the collector, in the order they
+/// are made. The ones made since the last collection are young, and are collected
+/// together when there are `NURSERY_SIZE` of them, most cycles of garbage are short
+/// lived. The ones surviving it are old, they are collected when they have doubled,
+/// `SLICE_SIZE` of them at a time between running the instructions, or all at once
+/// every `FULL_EVERY` times.
 pub struct GcContainer {
     inner: Rc<RefCell<Vec<GcWeak>>>,
+    // where the young objects start in `inner`
+    young_start: Cell<usize>,
+    // the bytes charged for the young objects when made
+    young_bytes: Cell<usize>,
+    // the number of old objects over which they are collected
+    threshold: Cell<usize>,
+    sweep: Cell<Option<Sweep>>,
+    // the most objects tracked at once
+    peak: Cell<usize>,
+    collections: Cell<usize>,
+    minor_collections: Cell<usize>,
+    // the arrays not tracked by `inner`, the young ones from `sized_young_start`
+    sized: RefCell<Vec<Weak<dyn HeapSize>>>,
+    sized_young_start: Cell<usize>,
+    heap_bytes: Cell<usize>,
+    peak_heap_bytes: Cell<usize>,
+    allocated_bytes: Cell<u64>,
+    allocations: Cell<u64>,
+    roots: Rc<RefCell<RootSet>>,
+    // the site of the instruction being run, for tagging the objects it allocates
+    #[cfg(feature = "leak_track")]
+    site: Cell<AllocSite>,
+    // the tags of the objects, in the same order as `inner`
+    #[cfg(feature = "leak_track")]
+    tags: RefCell<Vec<AllocTag>>,
+    // the addresses of the objects made since the race detector last took them
+    #[cfg(feature = "race")]
+    made: RefCell<Vec<usize>>,
 }
 
 impl GcContainer {
     pub fn new() -> GcContainer {
         GcContainer {
             inner: Rc::new(RefCell::new(Vec::new())),
+            young_start: Cell::new(0),
+            young_bytes: Cell::new(0),
+            threshold: Cell::new(MIN_COLLECT_THRESHOLD),
+            sweep: Cell::new(None),
+            peak: Cell::new(0),
+            collections: Cell::new(0),
+            minor_collections: Cell::new(0),
+            sized: RefCell::new(Vec::new()),
+            sized_young_start: Cell::new(0),
+            heap_bytes: Cell::new(0),
+            peak_heap_bytes: Cell::new(0),
+            allocated_bytes: Cell::new(0),
+            allocations: Cell::new(0),
+            roots: Rc::new(RefCell::new(RootSet::default())),
+            #[cfg(feature = "leak_track")]
+            site: Cell::new(None),
+            #[cfg(feature = "leak_track")]
+            tags: RefCell::new(Vec::new()),
+            #[cfg(feature = "race")]
+            made: RefCell::new(Vec::new()),
         }
     }
 
     pub fn add_array(&self, arr: &Rc<(GosArrayObj, RCount)>) {
+        #[cfg(feature = "race")]
+        self.made.borrow_mut().push(Rc::as_ptr(arr) as usize);
         self.add_weak(GcWeak::new_array(arr))
     }
 
@@ -30,21 +354,163 @@ impl GcContainer {
     }
 
     pub fn add_map(&self, m: &Rc<(MapObj, RCount)>) {
+        #[cfg(feature = "race")]
+        self.made.borrow_mut().push(Rc::as_ptr(m) as usize);
         self.add_weak(GcWeak::new_map(m))
     }
 
     pub fn add_struct(&self, s: &Rc<(StructObj, RCount)>) {
+        #[cfg(feature = "race")]
+        self.made.borrow_mut().push(Rc::as_ptr(s) as usize);
         self.add_weak(GcWeak::new_struct(s))
     }
 
+    /// Measures an array that can't refer to other objects, it's not tracked for
+    /// collecting
+    pub(crate) fn add_sized<T: Element + 'static>(&self, arr: &Rc<(ArrayObj<T>, RCount)>) {
+        let w: Weak<(ArrayObj<T>, RCount)> = Rc::downgrade(arr);
+        #[cfg(feature = "race")]
+        self.made.borrow_mut().push(Rc::as_ptr(arr) as usize);
+        self.sized.borrow_mut().push(w);
+    }
+
+    /// Counts `bytes` allocated, until a collection finds what they were for freed
+    #[inline]
+    pub(crate) fn charge(&self, bytes: usize) {
+        let total = self.heap_bytes.get().saturating_add(bytes);
+        self.heap_bytes.set(total);
+        if total > self.peak_heap_bytes.get() {
+            self.peak_heap_bytes.set(total);
+        }
+        self.allocated_bytes
+            .set(self.allocated_bytes.get().saturating_add(bytes as u64));
+    }
+
+    /// Counts the `bytes` of an object made, measured again when it's collected young
+    #[inline]
+    pub(crate) fn charge_new(&self, bytes: usize) {
+        self.charge(bytes);
+        self.allocations.set(self.allocations.get() + 1);
+        self.young_bytes
+            .set(self.young_bytes.get().saturating_add(bytes));
+    }
+
+    /// The estimated bytes of the live objects
+    #[inline]
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.heap_bytes.get()
+    }
+
+    /// What the objects take, can be called by the FFIs while running
+    pub fn heap_stats(&self) -> HeapStats {
+        HeapStats {
+            bytes: self.heap_bytes.get(),
+            peak_bytes: self.peak_heap_bytes.get(),
+            allocated_bytes: self.allocated_bytes.get(),
+            allocations: self.allocations.get(),
+            objects: self.borrow_data().len(),
+            collections: self.collections.get(),
+            minor_collections: self.minor_collections.get(),
+            roots: self.roots.borrow().values.len() - self.roots.borrow().free.len(),
+        }
+    }
+
+    /// Keeps `val` alive until the handle returned is dropped, for the host holding it
+    /// across the calls or the FFIs holding it after they return
+    pub fn root(&self, val: GosValue) -> RootedValue {
+        add_root(&self.roots, val)
+    }
+
     #[inline]
     pub(crate) fn add_weak(&self, w: GcWeak) {
-        self.inner.borrow_mut().push(w);
+        let mut inner = self.inner.borrow_mut();
+        inner.push(w);
+        if inner.len() > self.peak.get() {
+            self.peak.set(inner.len());
+        }
+        drop(inner);
+        #[cfg(feature = "leak_track")]
+        self.tags.borrow_mut().push(AllocTag {
+            site: self.site.get(),
+            survived: 0,
+        });
+    }
+
+    /// Takes the addresses of the objects made since the last call, the race
+    /// detector forgets what it knows about the objects freed before at them
+    #[cfg(feature = "race")]
+    pub(crate) fn take_made(&self) -> Vec<usize> {
+        std::mem::take(&mut self.made.borrow_mut())
+    }
+
+    #[cfg(feature = "leak_track")]
+    #[inline]
+    pub(crate) fn set_site(&self, func: FunctionKey, pc: OpIndex) {
+        self.site.set(Some((func, pc)));
+    }
+
+    /// Returns the allocation sites of the live objects that have survived at least
+    /// `min_survived` collections, with the number of such objects, the most first.
+    #[cfg(feature = "leak_track")]
+    pub(crate) fn surviving_sites(&self, min_survived: u32) -> Vec<(AllocSite, usize)> {
+        let mut counts: go_parser::Map<AllocSite, usize> = go_parser::Map::new();
+        let data = self.borrow_data();
+        for (w, tag) in data.iter().zip(self.tags.borrow().iter()) {
+            if tag.survived >= min_survived && w.is_alive() {
+                *counts.entry(tag.site).or_insert(0) += 1;
+            }
+        }
+        let mut sites: Vec<(AllocSite, usize)> = counts.into_iter().collect();
+        sites.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        sites
+    }
+
+    /// The most objects tracked at once
+    pub(crate) fn peak(&self) -> usize {
+        self.peak.get()
     }
 
     fn borrow_data(&self) -> Ref<Vec<GcWeak>> {
         self.inner.borrow()
     }
+
+    /// Drops the objects freed from the ones tracked from `start` on, the others get
+    /// older
+    fn prune_from(&self, start: usize) {
+        let mut data = self.inner.borrow_mut();
+        let alive: Vec<bool> = data[start..].iter().map(|o| o.is_alive()).collect();
+        retain_from(&mut data, start, &alive);
+        #[cfg(feature = "leak_track")]
+        {
+            let mut tags = self.tags.borrow_mut();
+            retain_from(&mut tags, start, &alive);
+            tags[start..].iter_mut().for_each(|t| t.survived += 1);
+        }
+    }
+
+    /// Drops the arrays freed from the ones measured from `start` on, returns the
+    /// bytes of the others
+    fn prune_sized_from(&self, start: usize) -> usize {
+        let mut sized = self.sized.borrow_mut();
+        let alive: Vec<bool> = sized[start..]
+            .iter()
+            .map(|w| w.strong_count() > 0)
+            .collect();
+        retain_from(&mut sized, start, &alive);
+        sized[start..]
+            .iter()
+            .filter_map(|w| w.upgrade())
+            .map(|a| a.heap_size())
+            .sum()
+    }
+}
+
+/// Keeps the elements of `v` from `start` on for which `keep` is true
+fn retain_from<T>(v: &mut Vec<T>, start: usize, keep: &[bool]) {
+    let mut tail = v.split_off(start);
+    let mut keep = keep.iter();
+    tail.retain(|_| *keep.next().unwrap());
+    v.append(&mut tail);
 }
 
 #[derive(Clone)]
@@ -79,6 +545,39 @@ impl GcWeak {
         GcWeak::Struct(Rc::downgrade(s))
     }
 
+    fn is_alive(&self) -> bool {
+        match &self {
+            GcWeak::Array(w) => w.strong_count() > 0,
+            GcWeak::Closure(w) => w.strong_count() > 0,
+            GcWeak::Map(w) => w.strong_count() > 0,
+            GcWeak::Struct(w) => w.strong_count() > 0,
+        }
+    }
+
+    /// The bytes of the object and of the strings it holds, zero if it's freed
+    fn heap_size(&self) -> usize {
+        let vals_size = |vals: &mut dyn Iterator<Item = &GosValue>| -> usize {
+            vals.map(|v| size_of::<GosValue>() + str_size(v)).sum()
+        };
+        match &self {
+            GcWeak::Array(w) => w.upgrade().map_or(0, |a| {
+                let data = a.0.borrow_data();
+                let strs: usize = data.iter().map(|e| str_size(&e.borrow())).sum();
+                data.capacity() * size_of::<GosElem>() + strs
+            }),
+            GcWeak::Closure(w) => w.upgrade().map_or(0, |_| size_of::<ClosureObj>()),
+            GcWeak::Map(w) => w.upgrade().map_or(0, |m| {
+                let data = m.0.borrow_data();
+                let mut vals = data.iter().flat_map(|(k, v)| [k, v]);
+                size_of::<MapObj>() + vals_size(&mut vals)
+            }),
+            GcWeak::Struct(w) => w.upgrade().map_or(0, |s| {
+                let fields = s.0.borrow_fields();
+                size_of::<StructObj>() + vals_size(&mut fields.iter())
+            }),
+        }
+    }
+
     fn to_gosv(&self) -> Option<GosValue> {
         match &self {
             GcWeak::Array(w) => w.upgrade().map(|v| {
@@ -101,6 +600,13 @@ impl GcWeak {
     }
 }
 
+fn str_size(val: &GosValue) -> usize {
+    match val.typ() {
+        ValueType::String => val.as_string().len(),
+        _ => 0,
+    }
+}
+
 fn children_ref_sub_one(val: &GosValue) {
     match val.typ() {
         ValueType::Array => val
@@ -175,37 +681,33 @@ fn break_cycle(val: &GosValue) {
 
 /// put the non-zero-rc on the left, and the others on the right
 fn partition_to_scan(to_scan: &mut Vec<GosValue>) -> usize {
-    let len = to_scan.len();
-    if len == 0 {
-        return 0;
-    }
-    let mut p0 = 0;
-    let mut p1 = len - 1;
-    loop {
-        while p0 < len - 1 && to_scan[p0].rc() > 0 {
-            p0 += 1;
-        }
-        while p1 > 1 && to_scan[p1].rc() <= 0 {
-            p1 -= 1;
+    let mut boundary = 0;
+    for i in 0..to_scan.len() {
+        if to_scan[i].rc() > 0 {
+            to_scan.swap(boundary, i);
+            boundary += 1;
         }
-        if p0 >= p1 {
-            break;
-        }
-        to_scan.swap(p0, p1);
     }
-    p0
+    boundary
 }
 
-pub(crate) fn collect(objs: &GcContainer) {
-    let mut to_scan: Vec<GosValue> = objs
-        .borrow_data()
+/// Breaks the cycles of garbage among the objects tracked in `range`, by trial
+/// deletion: the ones referred to only by the others in it, and not reachable from
+/// the ones referred to from elsewhere, are garbage. The objects out of the range are
+/// taken as alive, with what they refer to.
+fn scan(objs: &GcContainer, range: Range<usize>) {
+    let mut to_scan: Vec<GosValue> = objs.borrow_data()[range]
         .iter()
         .filter_map(|o| o.to_gosv())
         .collect();
-    //print!("objs before GC: {}\n", to_scan.len());
+    // the references through the values shared by many are counted once they are all
+    // found
+    SHARED.with(|shared| *shared.borrow_mut() = Some(Map::new()));
     for v in to_scan.iter() {
         children_ref_sub_one(v);
     }
+    ref_sub_one_all_shared();
+    SHARED.with(|shared| *shared.borrow_mut() = None);
 
     let boundary = partition_to_scan(&mut to_scan);
     for i in boundary..to_scan.len() {
@@ -216,14 +718,19 @@ pub(crate) fn collect(objs: &GcContainer) {
     for i in 0..boundary {
         children_mark_dirty(&to_scan[i], &mut queue);
     }
+    // the rooted values are alive whether their references are counted or not
+    for v in objs.roots.borrow().values.iter().flatten() {
+        v.mark_dirty(&mut queue);
+    }
 
-    loop {
-        if let Some(i) = queue.pop_front() {
-            let obj = &to_scan[(-i) as usize];
-            obj.set_rc(666);
-            children_mark_dirty(&obj, &mut queue);
-        } else {
-            break;
+    while let Some((i, rc)) = queue.pop_front() {
+        // the counts of the objects not scanned are left from before
+        match to_scan.get((-i) as usize) {
+            Some(obj) if obj.has_rc(rc) => {
+                obj.set_rc(666);
+                children_mark_dirty(&obj, &mut queue);
+            }
+            _ => {}
         }
     }
 
@@ -232,11 +739,124 @@ pub(crate) fn collect(objs: &GcContainer) {
             break_cycle(&obj);
         }
     }
+}
 
-    let _result: Vec<GosValue> = objs
-        .borrow_data()
-        .iter()
-        .filter_map(|o| o.to_gosv())
+/// Collects all the objects at once
+pub(crate) fn collect(objs: &GcContainer) {
+    objs.collections.set(objs.collections.get() + 1);
+    let len = objs.borrow_data().len();
+    scan(objs, 0..len);
+    objs.prune_from(0);
+    // what's left is what the estimate of the live bytes is now, the strings and
+    // the arrays made in other ways included only if held by the objects
+    let data = objs.borrow_data();
+    let bytes = data.iter().map(|o| o.heap_size()).sum::<usize>() + objs.prune_sized_from(0);
+    objs.heap_bytes.set(bytes);
+    objs.young_bytes.set(0);
+    objs.young_start.set(data.len());
+    objs.sized_young_start.set(objs.sized.borrow().len());
+    objs.sweep.set(None);
+    objs.threshold
+        .set(((data.len() + objs.sized.borrow().len()) * 2).max(MIN_COLLECT_THRESHOLD));
+}
+
+/// Collects the young objects, the ones surviving get old
+fn collect_young(objs: &GcContainer) {
+    objs.minor_collections.set(objs.minor_collections.get() + 1);
+    let start = objs.young_start.get();
+    let len = objs.borrow_data().len();
+    scan(objs, start..len);
+    objs.prune_from(start);
+    let data = objs.borrow_data();
+    let survived = data[start..].iter().map(|o| o.heap_size()).sum::<usize>()
+        + objs.prune_sized_from(objs.sized_young_start.get());
+    let bytes = objs.heap_bytes.get().saturating_sub(objs.young_bytes.get());
+    objs.heap_bytes.set(bytes + survived);
+    objs.young_bytes.set(0);
+    objs.young_start.set(data.len());
+    objs.sized_young_start.set(objs.sized.borrow().len());
+}
+
+/// Collects the next slice of the old objects, with the cycles in it
+fn collect_slice(objs: &GcContainer) {
+    let mut sweep = objs.sweep.get().unwrap_or_default();
+    let end = objs.young_start.get();
+    // the slices of every other sweep are shifted by half, for the cycles cut in two
+    // by one to be whole in the next
+    let mut size = SLICE_SIZE;
+    if sweep.next == 0 && objs.collections.get() % 2 == 1 {
+        size /= 2;
+    }
+    let to = (sweep.next + size).min(end);
+    scan(objs, sweep.next..to);
+    {
+        let mut data = objs.inner.borrow_mut();
+        #[cfg(feature = "leak_track")]
+        let mut tags = objs.tags.borrow_mut();
+        for i in sweep.next..to {
+            if data[i].is_alive() {
+                sweep.bytes += data[i].heap_size();
+                data.swap(sweep.write, i);
+                #[cfg(feature = "leak_track")]
+                {
+                    tags[i].survived += 1;
+                    tags.swap(sweep.write, i);
+                }
+                sweep.write += 1;
+            }
+        }
+    }
+    sweep.next = to;
+    if to < end {
+        objs.sweep.set(Some(sweep));
+        return;
+    }
+
+    // the sweep is over, the ones freed are between the old and the young ones
+    objs.sweep.set(None);
+    objs.collections.set(objs.collections.get() + 1);
+    objs.inner.borrow_mut().drain(sweep.write..end);
+    #[cfg(feature = "leak_track")]
+    objs.tags.borrow_mut().drain(sweep.write..end);
+    objs.young_start.set(sweep.write);
+    let young = objs.sized_young_start.get();
+    let mut sized = objs.sized.borrow_mut();
+    let mut old: Vec<_> = sized
+        .drain(..young)
+        .filter(|w| w.strong_count() > 0)
         .collect();
-    //print!("objs left after GC: {}\n", result.len());
+    let old_bytes: usize = old
+        .iter()
+        .filter_map(|w| w.upgrade())
+        .map(|a| a.heap_size())
+        .sum();
+    let old_len = old.len();
+    objs.sized_young_start.set(old_len);
+    old.append(&mut sized);
+    *sized = old;
+    objs.heap_bytes
+        .set(sweep.bytes + old_bytes + objs.young_bytes.get());
+    objs.threshold
+        .set(((sweep.write + old_len) * 2).max(MIN_COLLECT_THRESHOLD));
+}
+
+/// Collects what's due, at the points where the goroutines can be switched: the young
+/// objects if there are enough of them, and a slice of the old ones if they have
+/// doubled since they were last collected. It takes time proportional to the
+/// objects made since it was last called.
+pub(crate) fn collect_step(objs: &GcContainer) {
+    let young = objs.borrow_data().len() - objs.young_start.get() + objs.sized.borrow().len()
+        - objs.sized_young_start.get();
+    if young >= NURSERY_SIZE {
+        collect_young(objs);
+    }
+    let sweeping = objs.sweep.get().is_some();
+    let old = objs.young_start.get() + objs.sized_young_start.get();
+    if sweeping || old >= objs.threshold.get() {
+        if !sweeping && objs.collections.get() % FULL_EVERY == FULL_EVERY - 1 {
+            collect(objs);
+        } else {
+            collect_slice(objs);
+        }
+    }
 }
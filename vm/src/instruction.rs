@@ -14,114 +14,298 @@ use std::fmt::Debug;
 
 pub type OpIndex = i32;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-#[repr(u8)]
-pub enum Opcode {
-    VOID,
-
-    DUPLICATE,
-    LOAD_SLICE,
-    STORE_SLICE,
-    LOAD_ARRAY,
-    STORE_ARRAY,
-    LOAD_MAP,
-    STORE_MAP,
-    LOAD_STRUCT,
-    STORE_STRUCT,
-    LOAD_EMBEDDED,
-    STORE_EMBEDDED,
-    LOAD_PKG,
-    STORE_PKG,
-    LOAD_POINTER,
-    STORE_POINTER,
-    LOAD_UP_VALUE,
-    STORE_UP_VALUE,
-
-    // arithmetic, logical, ref, arrow
-    ADD,            // +
-    SUB,            // -
-    MUL,            // *
-    QUO,            // /
-    REM,            // %
-    AND,            // &
-    OR,             // |
-    XOR,            // ^
-    AND_NOT,        // $^
-    SHL,            // <<
-    SHR,            // >>
-    ADD_ASSIGN,     // +
-    SUB_ASSIGN,     // -
-    MUL_ASSIGN,     // *
-    QUO_ASSIGN,     // /
-    REM_ASSIGN,     // %
-    AND_ASSIGN,     // &
-    OR_ASSIGN,      // |
-    XOR_ASSIGN,     // ^
-    AND_NOT_ASSIGN, // $^
-    SHL_ASSIGN,     // <<
-    SHR_ASSIGN,     // >>
-    INC,            //++
-    DEC,            //--
-    UNARY_SUB,      // -
-    UNARY_XOR,      // ^
-    NOT,            // !
-    EQL,            // ==
-    NEQ,            // !=
-    LSS,            // <
-    GTR,            // >
-    LEQ,            // <=
-    GEQ,            // >=
-    REF,            // &
-    REF_UPVALUE,
-    REF_SLICE_MEMBER,
-    REF_STRUCT_FIELD,
-    REF_EMBEDDED,
-    REF_PKG_MEMBER,
-    SEND, // <-
-    RECV, // <-
-
-    // call
-    PACK_VARIADIC,
-    CALL,
-    RETURN,
-
-    // jump
-    JUMP,
-    JUMP_IF,
-    JUMP_IF_NOT,
-    SWITCH,
-    SELECT,
-    RANGE_INIT,
-    RANGE,
-
-    // misc
-    LOAD_INIT_FUNC,
-    BIND_METHOD,
-    BIND_I_METHOD,
-    CAST,
-    TYPE_ASSERT,
-    TYPE,
-
-    // built-in functinalities
-    IMPORT,  // imports a package
-    SLICE,   // for slice expressions
-    CLOSURE, // for creating a closure with function literal
-    LITERAL, // for composite literal
-    NEW,     // for built-in function new
-    MAKE,    // for built-in function make
-    COMPLEX, // for built-in function complex
-    REAL,    // for built-in function real
-    IMAG,    // for built-in function imag
-    LEN,     // for built-in function len
-    CAP,     // for built-in function cap
-    APPEND,  // for built-in function append
-    COPY,    // for built-in function copy
-    DELETE,  // for built-in function delete
-    CLOSE,   // for built-in function close
-    PANIC,   // for built-in function panic
-    RECOVER, // for built-in function recover
-    ASSERT,  // for built-in function assert
-    FFI,     // for FFI
+/// What an operand of an instruction is, as the VM runs it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    /// Not used, or a number told by the doc of the opcode
+    Any,
+    /// A register the instruction writes to
+    Write,
+    /// A register or a constant the instruction reads
+    Read,
+    /// A register, as the start or the end of a range of them
+    Reg,
+    /// A constant of the type
+    Const(ValueType),
+    /// An offset from the instruction after the ones the instruction reads
+    Jump,
+    /// An index of `Bytecode::indices`
+    Indices,
+    /// An index of `Bytecode::ifaces`
+    Iface,
+    /// An index of the up values of the closure
+    UpValue,
+}
+
+/// Declares the opcodes along with what their operands are when no flag changes
+/// them, which `Instruction::operands` starts from.
+macro_rules! opcodes {
+    ($($(#[doc = $doc:literal])* $op:ident => [$d:expr, $s0:expr, $s1:expr],)*) => {
+        /// The operations of the VM. An `Instruction` has the opcode in `op0`, its
+        /// operands in `d`, `s0` and `s1`, and the types or flags it works with in
+        /// `t0` and `t1`; `op1` is a second opcode for the few that need one.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        #[repr(u8)]
+        pub enum Opcode {
+            $($(#[doc = $doc])* $op,)*
+        }
+
+        impl Opcode {
+            /// All the opcodes, in the order of their values
+            pub const ALL: &'static [Opcode] = &[$(Opcode::$op),*];
+
+            /// What the opcode does, the doc comment of its variant
+            pub fn doc(&self) -> &'static str {
+                match self {
+                    $(Opcode::$op => concat!($($doc, "\n",)* ""),)*
+                }
+            }
+
+            fn base_operands(&self) -> [Operand; 3] {
+                use Operand::*;
+                match self {
+                    $(Opcode::$op => [$d, $s0, $s1],)*
+                }
+            }
+        }
+    };
+}
+
+opcodes! {
+    /// Not run: the extra instructions some opcodes read their further operands
+    /// from, and `op1` of the instructions without a second opcode.
+    VOID => [Any, Any, Any],
+
+    /// `d = s0`, copying the structs and the arrays.
+    DUPLICATE => [Write, Read, Any],
+    /// Assigns `s0` to the local `d`. A struct or an array is copied into the one
+    /// there in place, for the pointers to it to see the new value.
+    STORE_LOCAL => [Write, Read, Any],
+    /// `d = s0[s1]`, `s0` a slice.
+    LOAD_SLICE => [Write, Read, Read],
+    /// `d[s0] = s1`, `d` a slice. With an arithmetic opcode in `op1` it's the
+    /// assignment `d[s0] op1= s1`, of the type `t0`.
+    STORE_SLICE => [Read, Read, Read],
+    /// `d = s0[s1]`, `s0` an array.
+    LOAD_ARRAY => [Write, Read, Read],
+    /// `d[s0] = s1`, `d` an array, `op1` as for `STORE_SLICE`.
+    STORE_ARRAY => [Read, Read, Read],
+    /// `d = s0[s1]`, `s0` a map. The `s0` of the extra instruction is the zero
+    /// value for a missing key; with `t1` FlagB its `d` gets whether the key was
+    /// there.
+    LOAD_MAP => [Write, Read, Read],
+    /// `d[s0] = s1`, `d` a map, `op1` as for `STORE_SLICE`. The `s0` of the extra
+    /// instruction is the zero value an assignment like `+=` starts from.
+    STORE_MAP => [Read, Read, Read],
+    /// `d = s0.f`, `s1` the index of the field `f`.
+    LOAD_STRUCT => [Write, Read, Any],
+    /// `d.f = s1`, `s0` the index of the field `f`, `op1` as for `STORE_SLICE`.
+    STORE_STRUCT => [Read, Any, Read],
+    /// `d = s0.f`, `f` a field reached through embedded structs, `s1` the index of
+    /// its path.
+    LOAD_EMBEDDED => [Write, Read, Indices],
+    /// `d.f = s1` for a field reached through embedded structs, `s0` the index of
+    /// its path, `op1` as for `STORE_SLICE`.
+    STORE_EMBEDDED => [Read, Indices, Read],
+    /// `d` = the member `s1` of the package `s0`.
+    LOAD_PKG => [Write, Read, Any],
+    /// The member `s0` of the package `d` = `s1`, `op1` as for `STORE_SLICE`.
+    STORE_PKG => [Read, Any, Read],
+    /// `d = *s0`.
+    LOAD_POINTER => [Write, Read, Any],
+    /// `*d = s0`, `op1` as for `STORE_SLICE`.
+    STORE_POINTER => [Read, Read, Any],
+    /// `d` = the variable `s0` the closure captured.
+    LOAD_UP_VALUE => [Write, UpValue, Any],
+    /// The variable `d` the closure captured = `s0`, `op1` as for `STORE_SLICE`.
+    STORE_UP_VALUE => [UpValue, Read, Any],
+
+    /// `d = s0 + s1`, numbers or strings of the type `t0`.
+    ADD => [Write, Read, Read],
+    /// `d = s0 - s1`, of the type `t0`.
+    SUB => [Write, Read, Read],
+    /// `d = s0 * s1`, of the type `t0`.
+    MUL => [Write, Read, Read],
+    /// `d = s0 / s1`, of the type `t0`.
+    QUO => [Write, Read, Read],
+    /// `d = s0 % s1`, of the type `t0`.
+    REM => [Write, Read, Read],
+    /// `d = s0 & s1`, of the type `t0`.
+    AND => [Write, Read, Read],
+    /// `d = s0 | s1`, of the type `t0`.
+    OR => [Write, Read, Read],
+    /// `d = s0 ^ s1`, of the type `t0`.
+    XOR => [Write, Read, Read],
+    /// `d = s0 &^ s1`, of the type `t0`.
+    AND_NOT => [Write, Read, Read],
+    /// `d = s0 << s1`, `s0` of the type `t0` and `s1` of `t1`.
+    SHL => [Write, Read, Read],
+    /// `d = s0 >> s1`, `s0` of the type `t0` and `s1` of `t1`.
+    SHR => [Write, Read, Read],
+    /// `d += s0`, of the type `t0`.
+    ADD_ASSIGN => [Write, Read, Any],
+    /// `d -= s0`, of the type `t0`.
+    SUB_ASSIGN => [Write, Read, Any],
+    /// `d *= s0`, of the type `t0`.
+    MUL_ASSIGN => [Write, Read, Any],
+    /// `d /= s0`, of the type `t0`.
+    QUO_ASSIGN => [Write, Read, Any],
+    /// `d %= s0`, of the type `t0`.
+    REM_ASSIGN => [Write, Read, Any],
+    /// `d &= s0`, of the type `t0`.
+    AND_ASSIGN => [Write, Read, Any],
+    /// `d |= s0`, of the type `t0`.
+    OR_ASSIGN => [Write, Read, Any],
+    /// `d ^= s0`, of the type `t0`.
+    XOR_ASSIGN => [Write, Read, Any],
+    /// `d &^= s0`, of the type `t0`.
+    AND_NOT_ASSIGN => [Write, Read, Any],
+    /// `d <<= s0`, `d` of the type `t0` and `s0` of `t1`.
+    SHL_ASSIGN => [Write, Read, Any],
+    /// `d >>= s0`, `d` of the type `t0` and `s0` of `t1`.
+    SHR_ASSIGN => [Write, Read, Any],
+    /// `d++`, of the type `t0`.
+    INC => [Write, Any, Any],
+    /// `d--`, of the type `t0`.
+    DEC => [Write, Any, Any],
+    /// `d = -s0`, of the type `t0`.
+    UNARY_SUB => [Write, Read, Any],
+    /// `d = ^s0`, of the type `t0`.
+    UNARY_XOR => [Write, Read, Any],
+    /// `d = !s0`.
+    NOT => [Write, Read, Any],
+    /// `d = s0 == s1`, `t0` and `t1` the types of `s0` and `s1`. The values of the
+    /// same copyable type are compared as they are, the others like Go does.
+    EQL => [Write, Read, Read],
+    /// `d = s0 != s1`, the types as for `EQL`.
+    NEQ => [Write, Read, Read],
+    /// `d = s0 < s1`, of the type `t0`.
+    LSS => [Write, Read, Read],
+    /// `d = s0 > s1`, of the type `t0`.
+    GTR => [Write, Read, Read],
+    /// `d = s0 <= s1`, of the type `t0`.
+    LEQ => [Write, Read, Read],
+    /// `d = s0 >= s1`, of the type `t0`.
+    GEQ => [Write, Read, Read],
+    /// `d` = a pointer to a new variable holding `s0`.
+    REF => [Write, Read, Any],
+    /// `d` = a pointer to the variable `s0` the closure captured.
+    REF_UPVALUE => [Write, UpValue, Any],
+    /// `d = &s0[s1]`, `s0` a slice or an array of the type `t0` with elements of
+    /// `t1`.
+    REF_SLICE_MEMBER => [Write, Read, Read],
+    /// `d = &s0.f`, `s1` the index of the field `f`.
+    REF_STRUCT_FIELD => [Write, Read, Any],
+    /// `d = &s0.f`, `f` a field reached through embedded structs, `s1` the index of
+    /// its path.
+    REF_EMBEDDED => [Write, Read, Indices],
+    /// `d` = a pointer to the member `s1` of the package `s0`.
+    REF_PKG_MEMBER => [Write, Read, Any],
+    /// Moves the variables captured by closures out of the locals from `s0` to
+    /// `s1`, excluded, before their registers are reused.
+    CLOSE_UP_VALUES => [Any, Reg, Reg],
+    /// `s0 <- s1`.
+    SEND => [Any, Read, Read],
+    /// `d = <-s0`. `t1` FlagA keeps the value, FlagB the value and whether the
+    /// channel is open in `s1`, FlagC only the latter, FlagD nothing.
+    RECV => [Any, Read, Any],
+
+    /// `d` = a slice of the registers from `s0` to `s1`, excluded, for the
+    /// variadic parameter, the elements of the type `t0`.
+    PACK_VARIADIC => [Write, Reg, Reg],
+    /// Calls the closure `d`, with the frame of the callee at `s0`: its results,
+    /// then its parameters. `t0` FlagA is a call, FlagB a go statement, FlagC a
+    /// defer statement.
+    CALL => [Read, Reg, Any],
+    /// Returns from the function. `t0` FlagA returns, FlagB ends the constructor of
+    /// the package `d`, marking it initialized, FlagC runs the deferred calls
+    /// first.
+    RETURN => [Any, Any, Any],
+
+    /// Jumps `d`.
+    JUMP => [Jump, Any, Any],
+    /// Jumps `d` if `s0` is true.
+    JUMP_IF => [Jump, Read, Any],
+    /// Jumps `d` if `s0` is false.
+    JUMP_IF_NOT => [Jump, Read, Any],
+    /// Jumps `d` if `s0 == s1`, of the type `t0`; with `t0` Metadata the types are
+    /// compared, for the type switches.
+    SWITCH => [Jump, Read, Read],
+    /// Runs one of the `s0` cases of a select statement, one extra instruction
+    /// each, jumping the `d` of the case chosen. With `t0` FlagE there's a default
+    /// case, jumping `d`. The `t0` of a case is FlagA to send its `s1` to `s0`,
+    /// FlagB to receive from `s0`, FlagC to receive into `s1`, FlagD to receive
+    /// into `s1` and whether the channel is open into the register after it.
+    SELECT => [Any, Any, Any],
+    /// Starts ranging over `s0`, of the type `t0` with elements of `t1`.
+    RANGE_INIT => [Any, Read, Any],
+    /// The next key in `d` and value in `s1` of the innermost range, jumping `s0`
+    /// past the loop when there are no more.
+    RANGE => [Write, Jump, Write],
+
+    /// `d` = the init function `s1` of the package `s0`, counting `s1` up; jumps
+    /// over the 2 instructions after it when there's no more.
+    LOAD_INIT_FUNC => [Write, Read, Write],
+    /// `d = s0.m`, the method `s1` bound to the receiver `s0`.
+    BIND_METHOD => [Write, Read, Read],
+    /// `d = s0.m`, the method of the index `s1` of the interface `s0`, bound to its
+    /// value.
+    BIND_I_METHOD => [Write, Read, Any],
+    /// `d = t0(s0)`, `s0` of the type `t1`. Converting to an interface, `s1` is the
+    /// binding of its methods.
+    CAST => [Write, Read, Any],
+    /// `d = s0.(T)`, `s1` the metadata of `T`. With `t1` FlagB the `d` of the
+    /// extra instruction gets whether it holds, with FlagC its `s0` is the
+    /// metadata of the interface for the error.
+    TYPE_ASSERT => [Write, Read, Const(ValueType::Metadata)],
+    /// `d` = the dynamic type of the interface `s0`, and with `t0` FlagA `s1` =
+    /// its value, for the type switches.
+    TYPE => [Write, Read, Any],
+
+    /// Jumps `d` if the package `s0` has been initialized.
+    IMPORT => [Jump, Read, Any],
+    /// `d = s0[s1:e.s0:e.s1]`, `e` the extra instruction, `s0` of the type `t0`, a
+    /// slice, a string or an array with elements of `t1`.
+    SLICE => [Write, Read, Read],
+    /// `d` = a closure of the function constant `s0`, capturing its up values.
+    CLOSURE => [Write, Const(ValueType::Function), Any],
+    /// `d` = a composite literal of the metadata in the `s0` of the extra
+    /// instruction, of the `s1` elements in the registers from `s0`. With `t0`
+    /// FlagA they are in order, otherwise in pairs of an index and a value.
+    LITERAL => [Write, Reg, Any],
+    /// `d = new(s0)`, `s0` a metadata.
+    NEW => [Write, Read, Any],
+    /// `d = make(s0)`, `s0` a metadata. `t0` FlagB has a size in `s1`, FlagC a
+    /// length in `s1` and a capacity in the `s0` of the extra instruction.
+    MAKE => [Write, Read, Any],
+    /// `d = complex(s0, s1)`.
+    COMPLEX => [Write, Read, Read],
+    /// `d = real(s0)`.
+    REAL => [Write, Read, Any],
+    /// `d = imag(s0)`.
+    IMAG => [Write, Read, Any],
+    /// `d = len(s0)`.
+    LEN => [Write, Read, Any],
+    /// `d = cap(s0)`.
+    CAP => [Write, Read, Any],
+    /// `d = append(s0, s1...)`, with elements of the type `t1`; with `t0` String,
+    /// `s1` is a string appended as bytes.
+    APPEND => [Write, Read, Read],
+    /// `d = copy(s0, s1)`, with elements of the type `t1`; with `t0` String, `s1`
+    /// is a string.
+    COPY => [Write, Read, Read],
+    /// `delete(s0, s1)`.
+    DELETE => [Any, Read, Read],
+    /// `close(s0)`.
+    CLOSE => [Any, Read, Any],
+    /// `panic(s0)`.
+    PANIC => [Any, Read, Any],
+    /// `d = recover()`.
+    RECOVER => [Write, Any, Any],
+    /// `assert(s0)`.
+    ASSERT => [Any, Read, Any],
+    /// `d` = the FFI named `s1`, as the interface type `s0`.
+    FFI => [Write, Read, Read],
 }
 
 impl fmt::Display for Opcode {
@@ -246,6 +430,76 @@ impl Instruction {
         unsafe { std::mem::transmute(self.op1) }
     }
 
+    /// The number of instructions after this one it reads as its operands
+    pub fn ext_count(&self) -> usize {
+        match self.op0 {
+            Opcode::LOAD_MAP | Opcode::STORE_MAP | Opcode::SLICE | Opcode::LITERAL => 1,
+            Opcode::TYPE_ASSERT => match self.t1 {
+                ValueType::FlagB | ValueType::FlagC => 1,
+                _ => 0,
+            },
+            Opcode::MAKE if self.t0 == ValueType::FlagC => 1,
+            Opcode::SELECT => self.s0.max(0) as usize,
+            _ => 0,
+        }
+    }
+
+    /// What `d`, `s0` and `s1` are, as the VM runs the instruction
+    pub fn operands(&self) -> [Operand; 3] {
+        use Operand::*;
+        match self.op0 {
+            Opcode::RECV => {
+                let value = match self.t1 {
+                    ValueType::FlagA | ValueType::FlagB => Write,
+                    _ => Any,
+                };
+                let ok = match self.t1 {
+                    ValueType::FlagB | ValueType::FlagC => Write,
+                    _ => Any,
+                };
+                [value, Read, ok]
+            }
+            Opcode::RETURN if self.t0 == ValueType::FlagB => [Read, Any, Any],
+            Opcode::SELECT if self.t0 == ValueType::FlagE => [Jump, Any, Any],
+            Opcode::CAST if self.t0 == ValueType::Interface => [Write, Read, Iface],
+            Opcode::TYPE if self.t0 == ValueType::FlagA => [Write, Read, Write],
+            Opcode::MAKE if matches!(self.t0, ValueType::FlagB | ValueType::FlagC) => {
+                [Write, Read, Read]
+            }
+            _ => self.op0.base_operands(),
+        }
+    }
+
+    /// What `d`, `s0` and `s1` of `ex`, one of the extra instructions of this one,
+    /// are
+    pub fn ext_operands(&self, ex: &Instruction) -> [Operand; 3] {
+        use Operand::*;
+        match self.op0 {
+            Opcode::LOAD_MAP => match self.t1 {
+                ValueType::FlagB => [Write, Read, Any],
+                _ => [Any, Read, Any],
+            },
+            Opcode::STORE_MAP => match self.op1 {
+                Opcode::VOID => [Any, Any, Any],
+                _ => [Any, Read, Any],
+            },
+            Opcode::SLICE => [Any, Read, Read],
+            Opcode::LITERAL => [Any, Const(ValueType::Metadata), Any],
+            Opcode::TYPE_ASSERT => match self.t1 {
+                ValueType::FlagB => [Write, Any, Any],
+                _ => [Any, Const(ValueType::Metadata), Any],
+            },
+            Opcode::MAKE => [Any, Read, Any],
+            Opcode::SELECT => match ex.t0 {
+                ValueType::FlagA => [Jump, Read, Read],
+                ValueType::FlagB => [Jump, Read, Any],
+                ValueType::FlagC | ValueType::FlagD => [Jump, Read, Write],
+                _ => [Any, Any, Any],
+            },
+            _ => [Any, Any, Any],
+        }
+    }
+
     // Get the max register index 'instructions' write to
     pub fn max_write_index(instructions: &[Instruction]) -> OpIndex {
         let mut i = 0;
@@ -255,6 +509,7 @@ impl Instruction {
             let index = match cur.op0 {
                 Opcode::VOID => 0,
                 Opcode::DUPLICATE => cur.d,
+                Opcode::STORE_LOCAL => cur.d,
                 Opcode::LOAD_SLICE => cur.d,
                 Opcode::STORE_SLICE => 0,
                 Opcode::LOAD_ARRAY => cur.d,
@@ -262,7 +517,7 @@ impl Instruction {
                 Opcode::LOAD_MAP => {
                     i += 1;
                     match cur.t1 {
-                        ValueType::FlagB => instructions[i].d,
+                        ValueType::FlagB => std::cmp::max(cur.d, instructions[i].d),
                         _ => cur.d,
                     }
                 }
@@ -319,9 +574,10 @@ impl Instruction {
                 Opcode::REF_STRUCT_FIELD => cur.d,
                 Opcode::REF_EMBEDDED => cur.d,
                 Opcode::REF_PKG_MEMBER => cur.d,
+                Opcode::CLOSE_UP_VALUES => 0,
                 Opcode::SEND => 0,
                 Opcode::RECV => match cur.t1 {
-                    ValueType::FlagB => std::cmp::max(cur.d, cur.s1),
+                    ValueType::FlagB | ValueType::FlagC => std::cmp::max(cur.d, cur.s1),
                     _ => cur.d,
                 },
                 Opcode::PACK_VARIADIC => cur.d,
@@ -334,7 +590,7 @@ impl Instruction {
                 Opcode::SELECT => {
                     let begin = i + 1;
                     i += cur.s0 as usize;
-                    instructions[begin..i].iter().fold(0, |acc, x| {
+                    instructions[begin..i + 1].iter().fold(0, |acc, x| {
                         let val = match x.t0 {
                             ValueType::FlagC => x.s1,
                             ValueType::FlagD => x.s1 + 1,
@@ -344,7 +600,7 @@ impl Instruction {
                     })
                 }
                 Opcode::RANGE_INIT => 0,
-                Opcode::RANGE => 0,
+                Opcode::RANGE => std::cmp::max(cur.d, cur.s1),
                 Opcode::LOAD_INIT_FUNC => {
                     i += 2;
                     std::cmp::max(cur.d, cur.s1)
@@ -355,7 +611,11 @@ impl Instruction {
                 Opcode::TYPE_ASSERT => match cur.t1 {
                     ValueType::FlagB => {
                         i += 1;
-                        instructions[i].d
+                        std::cmp::max(cur.d, instructions[i].d)
+                    }
+                    ValueType::FlagC => {
+                        i += 1;
+                        cur.d
                     }
                     _ => cur.d,
                 },
@@ -370,7 +630,7 @@ impl Instruction {
                 }
                 Opcode::CLOSURE => cur.d,
                 Opcode::LITERAL => {
-                    i += 1 + cur.s1 as usize;
+                    i += 1;
                     cur.d
                 }
                 Opcode::NEW => cur.d,
@@ -483,6 +743,166 @@ fn fmt_index(index: OpIndex, f: &mut std::fmt::Formatter) -> std::fmt::Result {
     }
 }
 
+/// The reference of the opcodes, in markdown: what each does and what its operands
+/// are, made from the doc comments of `Opcode` and from `Instruction::operands`, so
+/// it says what the verifier checks. It's kept in `OPCODES.md` of this crate.
+pub fn opcode_reference() -> String {
+    let mut text = String::from(REFERENCE_HEAD);
+    for op in Opcode::ALL.iter() {
+        text.push_str(&format!("## {}\n\n", op));
+        for line in op.doc().lines() {
+            text.push_str(line.strip_prefix(' ').unwrap_or(line));
+            text.push('\n');
+        }
+        text.push_str("\n|  | d | s0 | s1 |\n|---|---|---|---|\n");
+        for (label, operands) in operand_rows(*op) {
+            text.push_str(&format!("| {} |", label));
+            for o in operands.iter() {
+                text.push_str(&format!(" {} |", fmt_operand(*o)));
+            }
+            text.push('\n');
+        }
+        text.push('\n');
+    }
+    text
+}
+
+const REFERENCE_HEAD: &str = "# Opcodes
+
+Generated by `opcode_reference()` from `src/instruction.rs`, don't edit.
+
+An instruction is an opcode `op0`, an optional second opcode `op1`, two types
+`t0` and `t1`, and three operands `d`, `s0` and `s1`. The operands are:
+
+- *reg*: a register of the frame, numbered from 0: the results, the parameters,
+  the locals, then the temporaries
+- *read*: a register, or a constant when negative, -1 the first of them
+- *written*: a register the instruction writes to
+- *jump*: an offset from the instruction after this one and its extra ones
+- *path*: an index of the paths to the embedded fields of the bytecode
+- *binding*: an index of the interface method bindings of the bytecode
+- *up value*: an index of the variables captured by the closure
+- *const T*: a constant of the type T
+- *-*: not used, or a number told by the description
+
+Some opcodes read more operands from the `VOID` instructions after them, the
+*extra* rows. The rows after the first are for the flags changing the operands.
+
+";
+
+/// The rows of the table of operands of `op`, the first one without flags, then
+/// one for each flag or type in `t0`, `t1` or `op1` that changes them
+fn operand_rows(op: Opcode) -> Vec<(String, [Operand; 3])> {
+    let inst = |t0: ValueType, t1: ValueType, op1: Opcode| Instruction {
+        op0: op,
+        op1,
+        t0,
+        t1,
+        d: 0,
+        // for SELECT, a single case
+        s0: 1,
+        s1: 0,
+    };
+    let types: Vec<ValueType> = (0..=ValueType::FlagE as u8)
+        .map(|v| unsafe { std::mem::transmute(v) })
+        .collect();
+    let mut variants = vec![(
+        String::new(),
+        inst(ValueType::Void, ValueType::Void, Opcode::VOID),
+    )];
+    for t in types.iter().skip(1) {
+        variants.push((
+            format!("`t0` {}", t),
+            inst(*t, ValueType::Void, Opcode::VOID),
+        ));
+    }
+    for t in types.iter().skip(1) {
+        variants.push((
+            format!("`t1` {}", t),
+            inst(ValueType::Void, *t, Opcode::VOID),
+        ));
+    }
+    variants.push((
+        "`op1` set".to_owned(),
+        inst(ValueType::Void, ValueType::Void, Opcode::ADD),
+    ));
+
+    // the labels of the flags giving the operands; the rows without flags don't
+    // list the flags giving the same operands
+    let mut rows: Vec<(Vec<String>, bool, bool, [Operand; 3])> = vec![];
+    let mut add = |label: String, operands: [Operand; 3], plain: bool, ext: bool| match rows
+        .iter_mut()
+        .find(|(_, _, e, o)| *e == ext && *o == operands)
+    {
+        Some((labels, p, _, _)) if plain || !*p => labels.push(label),
+        Some(_) => {}
+        None => rows.push((vec![label], plain, ext, operands)),
+    };
+    for (i, (label, inst)) in variants.iter().enumerate() {
+        add(label.clone(), inst.operands(), i == 0, false);
+    }
+    for (i, (label, inst)) in variants.iter().enumerate() {
+        if inst.ext_count() == 0 {
+            continue;
+        }
+        let cases: Vec<(String, Instruction)> = match op {
+            Opcode::SELECT => types[ValueType::FlagA as usize..=ValueType::FlagD as usize]
+                .iter()
+                .map(|t| {
+                    let mut ex = *inst;
+                    ex.op0 = Opcode::VOID;
+                    ex.t0 = *t;
+                    (format!("case {}", t), ex)
+                })
+                .collect(),
+            _ => vec![(String::new(), *inst)],
+        };
+        for (case, ex) in cases {
+            let label = [label.as_str(), case.as_str()]
+                .iter()
+                .filter(|l| !l.is_empty())
+                .fold("extra".to_owned(), |acc, l| format!("{}, {}", acc, l));
+            add(label, inst.ext_operands(&ex), i == 0, true);
+        }
+    }
+    rows.into_iter()
+        .map(|(labels, _, _, o)| (merge_labels(&labels), o))
+        .collect()
+}
+
+/// Joins the labels like "`t1` FlagA" and "`t1` FlagB" into "`t1` FlagA, FlagB"
+fn merge_labels(labels: &[String]) -> String {
+    let mut text = String::new();
+    let mut last_field = "";
+    for l in labels.iter() {
+        let (field, value) = l.rsplit_once(' ').unwrap_or(("", l));
+        if !text.is_empty() && field == last_field {
+            text.push_str(&format!(", {}", value));
+        } else {
+            if !text.is_empty() {
+                text.push_str("; ");
+            }
+            text.push_str(l);
+            last_field = field;
+        }
+    }
+    text
+}
+
+fn fmt_operand(o: Operand) -> String {
+    match o {
+        Operand::Any => "-".to_owned(),
+        Operand::Write => "written".to_owned(),
+        Operand::Read => "read".to_owned(),
+        Operand::Reg => "reg".to_owned(),
+        Operand::Const(t) => format!("const {}", t),
+        Operand::Jump => "jump".to_owned(),
+        Operand::Indices => "path".to_owned(),
+        Operand::Iface => "binding".to_owned(),
+        Operand::UpValue => "up value".to_owned(),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -491,4 +911,20 @@ mod test {
     fn test_inst_size() {
         println!("size {} \n", std::mem::size_of::<Instruction>());
     }
+
+    // OPCODES.md has to be regenerated when the opcodes change, with GOLDEN_BLESS
+    // set like for the golden bytecode of the engine tests
+    #[test]
+    fn test_opcode_reference() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("OPCODES.md");
+        let text = opcode_reference();
+        if std::env::var_os("GOLDEN_BLESS").is_some() {
+            std::fs::write(&path, &text).unwrap();
+        }
+        let want = std::fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            want == text,
+            "OPCODES.md is out of date, rerun with GOLDEN_BLESS=1 to update it"
+        );
+    }
 }
@@ -7,8 +7,13 @@
 //! # Feature
 //! - `async`: Channel and goroutine support
 //! - `btree_map`: Make it use BTreeMap instead of HashMap
+//! - `debug_info`: Add the names of local variables to bytecode for debugging
 //! - `instruction_pos`: Add instruction position to bytecode for debugging
+//! - `leak_track`: Tag heap objects with where they are allocated, and report the long-lived ones
+//! - `race`: Instrumented mode that reports data races between goroutines
 //! - `serde_borsh`: Serde support for bytecode using Borsh
+//! - `serde`: Convert between `GosValue` and Rust types via serde
+//! - `threaded_dispatch`: Run the arithmetic, the comparisons and the jumps through a table of handlers instead of the opcode `match`
 
 mod instruction;
 #[macro_use]
@@ -16,12 +21,24 @@ mod metadata;
 #[cfg(feature = "async")]
 mod channel;
 mod objects;
+mod pos_table;
+#[cfg(feature = "race")]
+mod race;
+mod sandbox;
+#[cfg(feature = "async")]
+mod scheduler;
 #[macro_use]
 mod dispatcher;
 mod bytecode;
+mod control;
 mod ffi;
+mod ftoa;
 mod stack;
+mod step;
 mod value;
+#[cfg(feature = "serde")]
+mod value_serde;
+mod verifier;
 mod vm;
 
 pub mod gc;
@@ -30,8 +47,27 @@ pub mod types {
 }
 
 pub use {
+    control::{CancelHandle, PauseInfo, VmController},
     ffi::*,
+    ftoa::{format_complex, format_float},
+    gc::{HeapStats, RootedValue},
     go_pmacro::{ffi_impl, Ffi, UnsafePtr},
+    sandbox::{AuditHook, Capability, SandboxPolicy},
+    stack::StackSize,
+    step::{Step, StepVm},
     value::Bytecode,
-    vm::run,
+    verifier::VerifyError,
+    vm::{
+        call, run, AllocFailure, AssertHook, AssertInfo, CallError, Instance, OomAction, OomPolicy,
+        PanicHook, PanicInfo, Quota, RunError, RunOptions, RunStats,
+    },
 };
+
+#[cfg(feature = "async")]
+pub use scheduler::Sleep;
+
+#[cfg(feature = "race")]
+pub use race::RaceCtx;
+
+#[cfg(feature = "serde")]
+pub use value_serde::{from_value, to_value};
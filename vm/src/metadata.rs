@@ -155,9 +155,9 @@ impl Meta {
         Meta::with_type(t, metas)
     }
 
-    pub fn new_named(underlying: Meta, metas: &mut MetadataObjs) -> Meta {
+    pub fn new_named(name: TypeName, underlying: Meta, metas: &mut MetadataObjs) -> Meta {
         //debug_assert!(underlying.value_type(metas) != ValueType::Named);
-        Meta::with_type(MetadataType::Named(Methods::new(), underlying), metas)
+        Meta::with_type(MetadataType::Named(Methods::new(), underlying, name), metas)
     }
 
     #[inline]
@@ -165,6 +165,11 @@ impl Meta {
         metas[self.key].unwrap_named(metas)
     }
 
+    #[inline]
+    pub fn is_interface(&self, metas: &MetadataObjs) -> bool {
+        self.ptr_depth == 0 && matches!(self.mtype_unwraped(metas), MetadataType::Interface(_))
+    }
+
     #[inline]
     pub fn ptr_to(&self) -> Meta {
         let mut m = *self;
@@ -222,7 +227,7 @@ impl Meta {
                     MetadataType::Map(_, _) => ValueType::Map,
                     MetadataType::Interface(_) => ValueType::Interface,
                     MetadataType::Channel(_, _) => ValueType::Channel,
-                    MetadataType::Named(_, m) => m.value_type(metas),
+                    MetadataType::Named(_, m, _) => m.value_type(metas),
                     MetadataType::None => ValueType::Void,
                 },
                 _ => ValueType::Pointer,
@@ -270,7 +275,7 @@ impl Meta {
                 MetadataType::Map(_, _) => GosValue::new_nil(ValueType::Map),
                 MetadataType::Interface(_) => GosValue::new_nil(ValueType::Interface),
                 MetadataType::Channel(_, _) => GosValue::new_nil(ValueType::Channel),
-                MetadataType::Named(_, gm) => gm.zero(mobjs, gcc),
+                MetadataType::Named(_, gm, _) => gm.zero(mobjs, gcc),
                 MetadataType::None => unreachable!(),
             },
             _ => GosValue::new_nil(ValueType::Pointer),
@@ -289,7 +294,7 @@ impl Meta {
     #[inline]
     pub fn underlying(&self, metas: &MetadataObjs) -> Meta {
         match &metas[self.key] {
-            MetadataType::Named(_, u) => *u,
+            MetadataType::Named(_, u, _) => *u,
             _ => *self,
         }
     }
@@ -303,7 +308,7 @@ impl Meta {
     pub fn add_method(&self, name: String, pointer_recv: bool, metas: &mut MetadataObjs) {
         let k = self.recv_meta_key();
         match &mut metas[k] {
-            MetadataType::Named(m, _) => {
+            MetadataType::Named(m, _, _) => {
                 m.members.push(Rc::new(RefCell::new(MethodDesc {
                     pointer_recv: pointer_recv,
                     func: None,
@@ -317,7 +322,7 @@ impl Meta {
     pub fn set_method_code(&self, name: &String, func: FunctionKey, metas: &mut MetadataObjs) {
         let k = self.recv_meta_key();
         match &mut metas[k] {
-            MetadataType::Named(m, _) => {
+            MetadataType::Named(m, _, _) => {
                 let index = m.mapping[name] as usize;
                 m.members[index].borrow_mut().func = Some(func);
             }
@@ -328,7 +333,7 @@ impl Meta {
     /// Depth-first search for method by name
     pub fn get_iface_binding(&self, name: &String, metas: &MetadataObjs) -> Option<IfaceBinding> {
         match &metas[self.key] {
-            MetadataType::Named(m, underlying) => match m.mapping.get(name) {
+            MetadataType::Named(m, underlying, _) => match m.mapping.get(name) {
                 Some(&i) => Some(IfaceBinding::Struct(m.members[i as usize].clone(), None)),
                 None => underlying.get_iface_binding(name, metas),
             },
@@ -337,14 +342,18 @@ impl Meta {
                 .map(|x| IfaceBinding::Iface(x, None)),
             MetadataType::Struct(fields) => {
                 for (i, f) in fields.fields.iter().enumerate() {
+                    if !f.embedded {
+                        continue;
+                    }
                     if let Some(mut re) = f.meta.get_iface_binding(name, metas) {
                         let indices = match &mut re {
                             IfaceBinding::Struct(_, indices) | IfaceBinding::Iface(_, indices) => {
                                 indices
                             }
                         };
+                        // the path goes from the outermost struct in
                         if let Some(x) = indices {
-                            x.push(i as OpIndex)
+                            x.insert(0, i as OpIndex)
                         } else {
                             *indices = Some(vec![i as OpIndex]);
                         }
@@ -361,7 +370,7 @@ impl Meta {
     pub fn get_method(&self, index: OpIndex, metas: &MetadataObjs) -> Rc<RefCell<MethodDesc>> {
         let k = self.recv_meta_key();
         let m = match &metas[k] {
-            MetadataType::Named(methods, _) => methods,
+            MetadataType::Named(methods, _, _) => methods,
             _ => unreachable!(),
         };
         m.members[index as usize].clone()
@@ -379,6 +388,8 @@ pub struct FieldInfo {
     pub name: String,
     pub tag: Option<String>,
     //pub exported: bool,
+    /// Whether it's an embedded field, whose methods are promoted
+    pub embedded: bool,
     pub embedded_indices: Option<Vec<usize>>,
 }
 
@@ -517,6 +528,34 @@ impl Fields {
     }
 }
 
+/// The name of a named type and of the package declaring it, the package is empty
+/// for the predeclared types, like `error`
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TypeName {
+    pub pkg_path: String,
+    pub pkg_name: String,
+    pub name: String,
+}
+
+impl TypeName {
+    pub fn new(pkg_path: &str, pkg_name: &str, name: &str) -> TypeName {
+        TypeName {
+            pkg_path: pkg_path.to_owned(),
+            pkg_name: pkg_name.to_owned(),
+            name: name.to_owned(),
+        }
+    }
+
+    /// The name qualified by the name of the package, like `main.Point`
+    pub fn qualified(&self) -> String {
+        match self.pkg_name.is_empty() {
+            true => self.name.clone(),
+            false => format!("{}.{}", self.pkg_name, self.name),
+        }
+    }
+}
+
 #[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Debug, Clone, Copy)]
 pub struct MethodDesc {
@@ -599,6 +638,12 @@ impl SigMetadata {
         } {
             return false;
         }
+        self.identical_ignoring_recv(other, metas)
+    }
+
+    /// Compares the parameters and results only, as when checking a method
+    /// against the one an interface requires
+    pub fn identical_ignoring_recv(&self, other: &Self, metas: &MetadataObjs) -> bool {
         if self.params.len() != other.params.len() {
             return false;
         }
@@ -654,7 +699,7 @@ pub enum MetadataType {
     Map(Meta, Meta),
     Interface(Fields),
     Channel(ChannelType, Meta),
-    Named(Methods, Meta),
+    Named(Methods, Meta, TypeName),
     None,
 }
 
@@ -718,7 +763,7 @@ impl MetadataType {
     #[inline]
     pub fn as_named(&self) -> (&Methods, &Meta) {
         match self {
-            Self::Named(meth, meta) => (meth, meta),
+            Self::Named(meth, meta, _) => (meth, meta),
             _ => unreachable!(),
         }
     }
@@ -726,7 +771,7 @@ impl MetadataType {
     #[inline]
     pub fn as_named_mut(&mut self) -> (&mut Methods, &mut Meta) {
         match self {
-            Self::Named(meth, meta) => (meth, meta),
+            Self::Named(meth, meta, _) => (meth, meta),
             _ => unreachable!(),
         }
     }
@@ -734,7 +779,7 @@ impl MetadataType {
     #[inline]
     pub fn unwrap_named<'a, 'b: 'a>(&'a self, metas: &'b MetadataObjs) -> &'a Self {
         match self {
-            Self::Named(_, meta) => &metas[meta.key],
+            Self::Named(_, meta, _) => &metas[meta.key],
             _ => self,
         }
     }
@@ -769,8 +814,161 @@ impl MetadataType {
             (Self::Channel(at, avt), Self::Channel(bt, bvt)) => {
                 at == bt && avt.identical(bvt, metas)
             }
-            (Self::Named(_, a), Self::Named(_, b)) => a.identical(b, metas),
+            (Self::Named(_, a, _), Self::Named(_, b, _)) => a.identical(b, metas),
             _ => false,
         }
     }
 }
+
+/// Renders the type like Go prints it, in the runtime errors, `%T` and
+/// `reflect.Type.String`: `[]main.Point` or `map[string]interface {}`, the named types
+/// qualified by the name of their package, not its path
+pub fn type_string(meta: &Meta, metas: &MetadataObjs) -> String {
+    let mut s = String::new();
+    write_type(&mut s, meta, metas);
+    s
+}
+
+fn write_type(s: &mut String, meta: &Meta, metas: &MetadataObjs) {
+    for _ in 0..meta.ptr_depth {
+        s.push('*');
+    }
+    match &metas[meta.key] {
+        MetadataType::Bool => s.push_str("bool"),
+        MetadataType::Int => s.push_str("int"),
+        MetadataType::Int8 => s.push_str("int8"),
+        MetadataType::Int16 => s.push_str("int16"),
+        MetadataType::Int32 => s.push_str("int32"),
+        MetadataType::Int64 => s.push_str("int64"),
+        MetadataType::Uint => s.push_str("uint"),
+        MetadataType::UintPtr => s.push_str("uintptr"),
+        MetadataType::Uint8 => s.push_str("uint8"),
+        MetadataType::Uint16 => s.push_str("uint16"),
+        MetadataType::Uint32 => s.push_str("uint32"),
+        MetadataType::Uint64 => s.push_str("uint64"),
+        MetadataType::Float32 => s.push_str("float32"),
+        MetadataType::Float64 => s.push_str("float64"),
+        MetadataType::Complex64 => s.push_str("complex64"),
+        MetadataType::Complex128 => s.push_str("complex128"),
+        MetadataType::UnsafePtr => s.push_str("unsafe.Pointer"),
+        MetadataType::Str => s.push_str("string"),
+        MetadataType::Array(elem, size) => {
+            s.push_str(&format!("[{}]", size));
+            write_type(s, elem, metas);
+        }
+        MetadataType::Slice(elem) => {
+            s.push_str("[]");
+            write_type(s, elem, metas);
+        }
+        MetadataType::Struct(fields) => {
+            // the promoted fields are listed too, for looking them up
+            let fields: Vec<&FieldInfo> = fields
+                .infos()
+                .iter()
+                .filter(|f| f.embedded_indices.is_none())
+                .collect();
+            if fields.is_empty() {
+                s.push_str("struct {}");
+                return;
+            }
+            s.push_str("struct { ");
+            for (i, f) in fields.iter().enumerate() {
+                if i > 0 {
+                    s.push_str("; ");
+                }
+                if !f.embedded {
+                    s.push_str(&f.name);
+                    s.push(' ');
+                }
+                write_type(s, &f.meta, metas);
+                if let Some(tag) = &f.tag {
+                    s.push_str(&format!(" {:?}", tag));
+                }
+            }
+            s.push_str(" }");
+        }
+        MetadataType::Signature(sig) => {
+            s.push_str("func");
+            write_signature(s, sig, metas);
+        }
+        MetadataType::Map(k, v) => {
+            s.push_str("map[");
+            write_type(s, k, metas);
+            s.push(']');
+            write_type(s, v, metas);
+        }
+        MetadataType::Interface(methods) => {
+            if methods.infos().is_empty() {
+                s.push_str("interface {}");
+                return;
+            }
+            s.push_str("interface { ");
+            for (i, m) in methods.infos().iter().enumerate() {
+                if i > 0 {
+                    s.push_str("; ");
+                }
+                s.push_str(&m.name);
+                write_signature(s, metas[m.meta.key].as_signature(), metas);
+            }
+            s.push_str(" }");
+        }
+        MetadataType::Channel(typ, elem) => {
+            s.push_str(match typ {
+                ChannelType::Send => "chan<- ",
+                ChannelType::Recv => "<-chan ",
+                ChannelType::SendRecv => "chan ",
+            });
+            // chan (<-chan int) is not chan<- (chan int)
+            let paren = *typ == ChannelType::SendRecv
+                && elem.ptr_depth == 0
+                && matches!(
+                    &metas[elem.key],
+                    MetadataType::Channel(ChannelType::Recv, _)
+                );
+            if paren {
+                s.push('(');
+            }
+            write_type(s, elem, metas);
+            if paren {
+                s.push(')');
+            }
+        }
+        MetadataType::Named(_, _, name) => s.push_str(&name.qualified()),
+        MetadataType::None => s.push_str("nil"),
+    }
+}
+
+/// Writes the parameters and the results, without the receiver
+fn write_signature(s: &mut String, sig: &SigMetadata, metas: &MetadataObjs) {
+    s.push('(');
+    for (i, p) in sig.params.iter().enumerate() {
+        if i > 0 {
+            s.push_str(", ");
+        }
+        match &sig.variadic {
+            Some((_, elem)) if i == sig.params.len() - 1 => {
+                s.push_str("...");
+                write_type(s, elem, metas);
+            }
+            _ => write_type(s, p, metas),
+        }
+    }
+    s.push(')');
+    match sig.results.len() {
+        0 => {}
+        1 => {
+            s.push(' ');
+            write_type(s, &sig.results[0], metas);
+        }
+        _ => {
+            s.push_str(" (");
+            for (i, r) in sig.results.iter().enumerate() {
+                if i > 0 {
+                    s.push_str(", ");
+                }
+                write_type(s, r, metas);
+            }
+            s.push(')');
+        }
+    }
+}
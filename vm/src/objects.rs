@@ -7,7 +7,7 @@
 #[cfg(feature = "async")]
 use crate::channel::Channel;
 use crate::ffi::Ffi;
-use crate::gc::GcContainer;
+use crate::gc::{self, GcContainer, SharedInner};
 use crate::instruction::{Instruction, OpIndex, ValueType};
 use crate::metadata::*;
 use crate::stack::Stack;
@@ -30,6 +30,17 @@ use std::ops::Range;
 use std::rc::{Rc, Weak};
 use std::{panic, ptr, str};
 
+/// The start of the message of a borrow conflict, the VM adds where it happened
+pub(crate) const BORROW_CONFLICT: &str = "runtime error: concurrent access to";
+
+/// The error of accessing a container while it's being mutated, or mutating it while
+/// it's being read, by an FFI or an operation further up the stack. It becomes a Go
+/// panic instead of a RefCell panic of the VM, naming the container by its kind and
+/// address, its content can't be read.
+fn borrow_conflict<T>(what: &str, cell: &RefCell<T>) -> RuntimeError {
+    format!("{} {} {:p}", BORROW_CONFLICT, what, cell).into()
+}
+
 // ----------------------------------------------------------------------------
 // MapObj
 
@@ -50,24 +61,44 @@ impl MapObj {
     }
 
     #[inline]
-    pub fn insert(&self, key: GosValue, val: GosValue) -> Option<GosValue> {
-        self.borrow_data_mut().insert(key, val)
+    pub fn insert(&self, key: GosValue, val: GosValue) -> RuntimeResult<Option<GosValue>> {
+        key.check_hashable()?;
+        self.insert_hashable(key, val)
     }
 
     #[inline]
-    pub fn get(&self, key: &GosValue) -> Option<GosValue> {
-        let borrow = self.borrow_data();
-        let val = borrow.get(key);
-        match val {
-            Some(v) => Some(v.clone()),
-            None => None,
-        }
+    pub fn get(&self, key: &GosValue) -> RuntimeResult<Option<GosValue>> {
+        key.check_hashable()?;
+        self.get_hashable(key)
+    }
+
+    #[inline]
+    pub fn delete(&self, key: &GosValue) -> RuntimeResult<()> {
+        key.check_hashable()?;
+        self.delete_hashable(key)
+    }
+
+    /// Like `insert`, for a key known to be hashable, the VM only checks the keys
+    /// codegen finds can hold an unhashable value
+    #[inline]
+    pub(crate) fn insert_hashable(
+        &self,
+        key: GosValue,
+        val: GosValue,
+    ) -> RuntimeResult<Option<GosValue>> {
+        Ok(self.try_borrow_data_mut()?.insert(key, val))
+    }
+
+    #[inline]
+    pub(crate) fn get_hashable(&self, key: &GosValue) -> RuntimeResult<Option<GosValue>> {
+        Ok(self.try_borrow_data()?.get(key).cloned())
     }
 
     #[inline]
-    pub fn delete(&self, key: &GosValue) {
-        let mut mref = self.borrow_data_mut();
+    pub(crate) fn delete_hashable(&self, key: &GosValue) -> RuntimeResult<()> {
+        let mut mref = self.try_borrow_data_mut()?;
         mref.remove(key);
+        Ok(())
     }
 
     #[inline]
@@ -85,6 +116,20 @@ impl MapObj {
         self.map.borrow()
     }
 
+    #[inline]
+    pub fn try_borrow_data_mut(&self) -> RuntimeResult<RefMut<'_, GosMap>> {
+        self.map
+            .try_borrow_mut()
+            .map_err(|_| borrow_conflict("map", &self.map))
+    }
+
+    #[inline]
+    pub fn try_borrow_data(&self) -> RuntimeResult<Ref<'_, GosMap>> {
+        self.map
+            .try_borrow()
+            .map_err(|_| borrow_conflict("map", &self.map))
+    }
+
     #[inline]
     pub fn clone_inner(&self) -> RefCell<GosMap> {
         self.map.clone()
@@ -108,19 +153,84 @@ impl PartialEq for MapObj {
 impl Eq for MapObj {}
 
 impl Display for MapObj {
+    /// The entries are sorted by key, like Go prints them
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str("map[")?;
-        for (i, kv) in self.map.borrow().iter().enumerate() {
+        let map = self.map.borrow();
+        let mut entries: Vec<(&GosValue, &GosValue)> = map.iter().collect();
+        entries.sort_by(|a, b| key_order(a.0, b.0));
+        for (i, (k, v)) in entries.into_iter().enumerate() {
             if i > 0 {
                 f.write_char(' ')?;
             }
-            let v: &GosValue = &kv.1;
-            write!(f, "{}:{}", kv.0, v)?
+            k.fmt_go(f, false)?;
+            f.write_char(':')?;
+            v.fmt_go(f, false)?;
         }
         f.write_char(']')
     }
 }
 
+/// The order Go prints the keys of maps in, NaNs first
+fn key_order(a: &GosValue, b: &GosValue) -> Ordering {
+    let nan = |v: &GosValue| match v.typ() {
+        ValueType::Float32 => v.as_float32().is_nan(),
+        ValueType::Float64 => v.as_float64().is_nan(),
+        _ => false,
+    };
+    match (nan(a), nan(b)) {
+        (false, false) => a.cmp(b),
+        (x, y) => y.cmp(&x),
+    }
+}
+
+/// Iterates over a map the way `range` does in Go, the map may be modified during
+/// the iteration: an entry deleted before it's reached is skipped, and an entry added
+/// after the iteration started is not produced. The iterator walks a snapshot of the
+/// keys taken at the start, and looks each key up again when it's reached, so it
+/// also sees the latest values.
+#[derive(Clone, Debug)]
+pub struct MapObjIter {
+    map: GosValue,
+    keys: Vec<GosValue>,
+    cursor: usize,
+}
+
+impl MapObjIter {
+    /// `map` must be a non-nil map
+    pub fn new(map: GosValue) -> MapObjIter {
+        let keys = map
+            .as_map()
+            .unwrap()
+            .0
+            .borrow_data()
+            .keys()
+            .cloned()
+            .collect();
+        MapObjIter {
+            map,
+            keys,
+            cursor: 0,
+        }
+    }
+}
+
+impl Iterator for MapObjIter {
+    type Item = (GosValue, GosValue);
+
+    fn next(&mut self) -> Option<(GosValue, GosValue)> {
+        let map = self.map.as_map().unwrap().0.borrow_data();
+        while self.cursor < self.keys.len() {
+            let key = &self.keys[self.cursor];
+            self.cursor += 1;
+            if let Some(val) = map.get(key) {
+                return Some((key.clone(), val.clone()));
+            }
+        }
+        None
+    }
+}
+
 // ----------------------------------------------------------------------------
 // ArrayObj
 
@@ -330,6 +440,8 @@ where
 
 pub struct ArrayObj<T> {
     vec: RefCell<Vec<T>>,
+    /// Only strings use the array, made by concatenating them, see `StringObj::add`
+    strings_only: Cell<bool>,
 }
 
 pub type GosArrayObj = ArrayObj<GosElem>;
@@ -351,18 +463,21 @@ where
         }
         ArrayObj {
             vec: RefCell::new(v),
+            strings_only: Cell::new(false),
         }
     }
 
     pub fn with_data(data: Vec<GosValue>) -> ArrayObj<T> {
         ArrayObj {
             vec: RefCell::new(data.into_iter().map(|x| T::from_value(x)).collect()),
+            strings_only: Cell::new(false),
         }
     }
 
     pub fn with_raw_data(data: Vec<T>) -> ArrayObj<T> {
         ArrayObj {
             vec: RefCell::new(data),
+            strings_only: Cell::new(false),
         }
     }
 
@@ -381,6 +496,20 @@ where
         self.vec.borrow()
     }
 
+    #[inline]
+    pub fn try_borrow_data_mut(&self) -> RuntimeResult<RefMut<'_, Vec<T>>> {
+        self.vec
+            .try_borrow_mut()
+            .map_err(|_| borrow_conflict("array", &self.vec))
+    }
+
+    #[inline]
+    pub fn try_borrow_data(&self) -> RuntimeResult<Ref<'_, Vec<T>>> {
+        self.vec
+            .try_borrow()
+            .map_err(|_| borrow_conflict("array", &self.vec))
+    }
+
     #[inline]
     pub fn as_rust_slice(&self) -> Ref<[T]> {
         Ref::map(self.borrow_data(), |x| &x[..])
@@ -398,18 +527,21 @@ where
 
     #[inline(always)]
     pub fn get(&self, i: usize, t: ValueType) -> RuntimeResult<GosValue> {
-        if i >= self.len() {
+        let data = self.try_borrow_data()?;
+        if i >= data.len() {
             return Err(format!("index {} out of range", i).to_owned().into());
         }
-        Ok(self.borrow_data()[i].clone().into_value(t))
+        Ok(data[i].clone().into_value(t))
     }
 
     #[inline(always)]
     pub fn set(&self, i: usize, val: &GosValue) -> RuntimeResult<()> {
-        if i >= self.len() {
+        let data = self.try_borrow_data()?;
+        if i >= data.len() {
             return Err(format!("index {} out of range", i).to_owned().into());
         }
-        Ok(self.borrow_data()[i].set_value(&val))
+        data[i].set_value(val);
+        Ok(())
     }
 
     #[inline]
@@ -503,6 +635,7 @@ where
     fn clone(&self) -> Self {
         ArrayObj {
             vec: RefCell::new(self.borrow_data().iter().map(|x| x.clone()).collect()),
+            strings_only: Cell::new(false),
         }
     }
 }
@@ -636,18 +769,20 @@ where
         let new_end = self.end() + other.len();
         let after_end_len = data.len() - self.end();
         let sharing = self.sharing_with(other);
-        if after_end_len <= other.len() {
-            data.truncate(self.end());
-            if !sharing {
+        if !sharing {
+            if after_end_len <= other.len() {
+                data.truncate(self.end());
                 data.extend_from_slice(&other.as_rust_slice());
             } else {
-                data.extend_from_within(other.range());
+                T::copy_or_clone_slice(&mut data[self.end()..new_end], &other.as_rust_slice());
             }
         } else {
-            if !sharing {
-                T::copy_or_clone_slice(&mut data[self.end()..new_end], &other.as_rust_slice());
+            // other may overlap with what is truncated or overwritten
+            let cloned = data[other.range()].to_vec();
+            if after_end_len <= other.len() {
+                data.truncate(self.end());
+                data.extend_from_slice(&cloned);
             } else {
-                let cloned = data[other.range()].to_vec();
                 T::copy_or_clone_slice(&mut data[self.end()..new_end], &cloned);
             }
         }
@@ -707,7 +842,8 @@ where
         } else if j >= len {
             Err(format!("index {} out of range", j).into())
         } else {
-            self.borrow_all_data_mut()
+            self.array_obj()
+                .try_borrow_data_mut()?
                 .swap(i + self.begin(), j + self.begin());
             Ok(())
         }
@@ -740,7 +876,7 @@ where
         let cap = if max < 0 {
             this_cap
         } else {
-            let val = max as usize;
+            let val = this_begin + max as usize;
             if val > this_cap {
                 return Err(format!("index {} out of range", max).to_owned().into());
             }
@@ -748,7 +884,11 @@ where
         };
 
         let ei = if end < 0 {
-            this_len
+            let val = this_begin + this_len;
+            if val < bi {
+                return Err(format!("index {} out of range", begin).to_owned().into());
+            }
+            val
         } else {
             let val = this_begin + end as usize;
             if val < bi || val > cap {
@@ -791,9 +931,50 @@ pub type SliceEnumIter<'a, T> = std::iter::Enumerate<SliceIter<'a, T>>;
 // ----------------------------------------------------------------------------
 // StringObj
 
-pub type StringIter<'a> = std::str::Chars<'a>;
+/// Iterates over the chars of a string like a for range loop in Go, yielding the byte
+/// offset of each char. Every byte of invalid UTF-8 decodes to "\uFFFD".
+pub struct StringIter<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> StringIter<'a> {
+    #[inline]
+    pub fn new(bytes: &'a [u8]) -> StringIter<'a> {
+        StringIter { bytes, offset: 0 }
+    }
+}
 
-pub type StringEnumIter<'a> = std::iter::Enumerate<StringIter<'a>>;
+impl<'a> Iterator for StringIter<'a> {
+    type Item = (usize, char);
+
+    #[inline]
+    fn next(&mut self) -> Option<(usize, char)> {
+        let rest = &self.bytes[self.offset..];
+        if rest.is_empty() {
+            return None;
+        }
+        let (c, size) = decode_rune(rest);
+        let offset = self.offset;
+        self.offset += size;
+        Some((offset, c))
+    }
+}
+
+/// Decodes the first char of the bytes like utf8.DecodeRune in Go, returns the char
+/// and its size, invalid UTF-8 decodes to "\uFFFD" of size 1.
+#[inline]
+pub fn decode_rune(bytes: &[u8]) -> (char, usize) {
+    let prefix = &bytes[..bytes.len().min(4)];
+    let valid = match str::from_utf8(prefix) {
+        Ok(s) => s,
+        Err(e) => str::from_utf8(&prefix[..e.valid_up_to()]).unwrap(),
+    };
+    match valid.chars().next() {
+        Some(c) => (c, c.len_utf8()),
+        None => (char::REPLACEMENT_CHARACTER, 1),
+    }
+}
 
 pub type StringObj = SliceObj<Elem8>;
 
@@ -804,8 +985,18 @@ impl StringObj {
         Self::with_buf(buf)
     }
 
+    /// Makes a string or a byte slice with a copy of the bytes, for the conversions
+    /// between them, which don't share the underlying array in Go. The bytes are not
+    /// validated, strings are allowed to hold invalid UTF-8.
+    #[inline]
+    pub fn with_bytes(bytes: &[Elem8]) -> StringObj {
+        let bytes: &[u8] = unsafe { std::mem::transmute(bytes) };
+        let buf: Vec<Elem8> = unsafe { std::mem::transmute(bytes.to_vec()) };
+        Self::with_buf(buf)
+    }
+
     #[inline]
-    fn with_buf(buf: Vec<Elem8>) -> StringObj {
+    pub(crate) fn with_buf(buf: Vec<Elem8>) -> StringObj {
         let arr = GosValue::new_non_gc_array(ArrayObj::with_raw_data(buf), ValueType::Uint8);
         SliceObj::with_array(arr, 0, -1).unwrap()
     }
@@ -818,6 +1009,34 @@ impl StringObj {
         unsafe { std::mem::transmute(self.as_rust_slice()) }
     }
 
+    /// The bytes of the string, which don't have to be valid UTF-8
+    #[inline]
+    pub fn as_bytes(&self) -> Ref<'_, [u8]> {
+        unsafe { std::mem::transmute(self.as_rust_slice()) }
+    }
+
+    /// Copies the string into a Rust string, replacing each byte of invalid UTF-8
+    /// with "\uFFFD", as converting to []rune and back does in Go
+    pub fn to_lossy_string(&self) -> String {
+        let bytes = self.as_bytes();
+        match str::from_utf8(&bytes) {
+            Ok(s) => s.to_owned(),
+            Err(_) => StringIter::new(&bytes).map(|(_, c)| c).collect(),
+        }
+    }
+
+    /// Compares the strings byte-wise like Go does, without decoding the chars
+    #[inline]
+    pub fn compare(&self, other: &StringObj) -> Ordering {
+        if self.sharing_with(other)
+            && self.begin.get() == other.begin.get()
+            && self.end.get() == other.end.get()
+        {
+            return Ordering::Equal;
+        }
+        self.as_bytes().cmp(&other.as_bytes())
+    }
+
     #[inline]
     pub fn index(&self, i: usize) -> RuntimeResult<GosValue> {
         self.get(i, ValueType::Uint8)
@@ -828,11 +1047,50 @@ impl StringObj {
         self.index_elem(i).into_inner()
     }
 
-    #[inline]
+    /// Concatenates the strings. The array of the result is only used by strings,
+    /// so when `self` ends where the bytes of such an array do, and it has the room,
+    /// `other` is appended to the array in place: the strings sharing it don't see
+    /// past their own ends. Without the room the bytes are copied to an array twice
+    /// the size, which makes `s += x` in a loop amortized O(len(x)) instead of a copy
+    /// of `s` each time. The bytes are never moved, the iterators of `range` point
+    /// into them.
     pub fn add(&self, other: &StringObj) -> StringObj {
-        let mut buf = self.as_rust_slice().to_vec();
+        let arr = self.array_obj();
+        let len = self.len() + other.len();
+        let (in_place, grow) = match arr.strings_only.get() {
+            true => {
+                let data = arr.borrow_data();
+                let at_end = data.len() == self.end();
+                (
+                    at_end && data.capacity() - data.len() >= other.len(),
+                    at_end,
+                )
+            }
+            false => (false, false),
+        };
+        if in_place {
+            let mut data = arr.borrow_data_mut();
+            if self.sharing_with(other) {
+                data.extend_from_within(other.range());
+            } else {
+                data.extend_from_slice(&other.as_rust_slice());
+            }
+            let end = self.end() + other.len();
+            return SliceObj {
+                array: self.array.clone(),
+                begin: Cell::new(self.begin()),
+                end: Cell::new(end),
+                cap_end: Cell::new(end),
+                phantom: PhantomData,
+            };
+        }
+        // only the strings appended to again get the room
+        let mut buf = Vec::with_capacity(if grow { len * 2 } else { len });
+        buf.extend_from_slice(&self.as_rust_slice());
         buf.extend_from_slice(&other.as_rust_slice());
-        Self::with_buf(buf)
+        let result = Self::with_buf(buf);
+        result.array_obj().strings_only.set(true);
+        result
     }
 }
 
@@ -860,6 +1118,20 @@ impl StructObj {
     pub fn borrow_fields_mut(&self) -> RefMut<Vec<GosValue>> {
         self.fields.borrow_mut()
     }
+
+    #[inline]
+    pub fn try_borrow_fields(&self) -> RuntimeResult<Ref<'_, Vec<GosValue>>> {
+        self.fields
+            .try_borrow()
+            .map_err(|_| borrow_conflict("struct", &self.fields))
+    }
+
+    #[inline]
+    pub fn try_borrow_fields_mut(&self) -> RuntimeResult<RefMut<'_, Vec<GosValue>>> {
+        self.fields
+            .try_borrow_mut()
+            .map_err(|_| borrow_conflict("struct", &self.fields))
+    }
 }
 
 impl Eq for StructObj {}
@@ -914,7 +1186,7 @@ impl Display for StructObj {
             if i > 0 {
                 f.write_char(' ')?;
             }
-            write!(f, "{}", fld)?
+            fld.fmt_go(f, false)?
         }
         f.write_char('}')
     }
@@ -927,11 +1199,17 @@ impl Display for StructObj {
 pub struct UnderlyingFfi {
     pub ffi_obj: Rc<dyn Ffi>,
     pub meta: Meta,
+    /// The name the FFI is registered with
+    pub name: Rc<str>,
 }
 
 impl UnderlyingFfi {
-    pub fn new(ffi_obj: Rc<dyn Ffi>, meta: Meta) -> UnderlyingFfi {
-        UnderlyingFfi { ffi_obj, meta }
+    pub fn new(ffi_obj: Rc<dyn Ffi>, meta: Meta, name: Rc<str>) -> UnderlyingFfi {
+        UnderlyingFfi {
+            ffi_obj,
+            meta,
+            name,
+        }
     }
 }
 
@@ -968,15 +1246,33 @@ pub enum InterfaceObj {
     // They are not available if the Interface is created at runtime
     // as an empty Interface holding a GosValue, which acts like a
     // dynamic type.
-    Gos(GosValue, Option<(Meta, Vec<Binding4Runtime>)>),
+    Gos(GosValue, Option<(Meta, IfaceBindings)>),
     Ffi(UnderlyingFfi),
 }
 
 impl InterfaceObj {
-    pub fn with_value(val: GosValue, meta: Option<(Meta, Vec<Binding4Runtime>)>) -> InterfaceObj {
+    pub fn with_value(val: GosValue, meta: Option<(Meta, IfaceBindings)>) -> InterfaceObj {
         InterfaceObj::Gos(val, meta)
     }
 
+    /// Converts to another interface type, `binding` maps each method of the
+    /// target interface to the index of the same method in this one
+    pub fn rebind(&self, binding: &[Binding4Runtime]) -> RuntimeResult<InterfaceObj> {
+        match self {
+            Self::Gos(v, Some((meta, methods))) => {
+                let methods = binding
+                    .iter()
+                    .map(|b| match b {
+                        Binding4Runtime::Iface(i, None) => Ok(methods[*i].clone()),
+                        _ => Err("interface conversion: bad method binding".to_owned().into()),
+                    })
+                    .collect::<RuntimeResult<Rc<[_]>>>()?;
+                Ok(Self::Gos(v.clone(), Some((*meta, methods))))
+            }
+            _ => Ok(self.clone()),
+        }
+    }
+
     #[inline]
     pub fn underlying_value(&self) -> Option<&GosValue> {
         match self {
@@ -1073,23 +1369,24 @@ impl Display for InterfaceObj {
 #[cfg(feature = "async")]
 #[derive(Clone, Debug)]
 pub struct ChannelObj {
-    pub recv_zero: GosValue,
+    /// The type of the elements, the zero value received from a closed channel
+    /// is made from it only when it's needed.
+    pub elem_meta: Meta,
+    pub elem_type: ValueType,
     pub chan: Channel,
 }
 
 #[cfg(feature = "async")]
 impl ChannelObj {
-    pub fn new(cap: usize, recv_zero: GosValue) -> ChannelObj {
-        ChannelObj {
-            chan: Channel::new(cap),
-            recv_zero: recv_zero,
-        }
+    pub fn new(cap: usize, elem_meta: Meta, metas: &MetadataObjs) -> ChannelObj {
+        ChannelObj::with_chan(Channel::new(cap), elem_meta, elem_meta.value_type(metas))
     }
 
-    pub fn with_chan(chan: Channel, recv_zero: GosValue) -> ChannelObj {
+    pub fn with_chan(chan: Channel, elem_meta: Meta, elem_type: ValueType) -> ChannelObj {
         ChannelObj {
-            chan: chan,
-            recv_zero: recv_zero,
+            elem_meta,
+            elem_type,
+            chan,
         }
     }
 
@@ -1186,7 +1483,7 @@ impl PointerObj {
             PointerObj::UpVal(uv) => Ok(uv.value(stack).into_owned()),
             PointerObj::SliceMember(s, index) => s.caller_slow().slice_get(s, *index as usize),
             PointerObj::StructField(s, index) => {
-                Ok(s.as_struct().0.borrow_fields()[*index as usize].clone())
+                Ok(s.as_struct().0.try_borrow_fields()?[*index as usize].clone())
             }
             PointerObj::PkgMember(pkg, index) => Ok(pkgs[*pkg].member(*index).clone()),
         }
@@ -1221,9 +1518,8 @@ impl PointerObj {
                     .slice_set(s, &val.copy_semantic(gcc), *index as usize)?;
             }
             PointerObj::StructField(s, index) => {
-                let target: &mut GosValue =
-                    &mut s.as_struct().0.borrow_fields_mut()[*index as usize];
-                *target = val.copy_semantic(gcc);
+                let val = val.copy_semantic(gcc);
+                s.as_struct().0.try_borrow_fields_mut()?[*index as usize] = val;
             }
             PointerObj::PkgMember(p, index) => {
                 let target: &mut GosValue = &mut pkgs[*p].member_mut(*index);
@@ -1261,6 +1557,49 @@ impl PointerObj {
         };
     }
 
+    /// The address of the array a slice member points into and the index in it, which
+    /// are the same whichever slice or array the pointer was taken from
+    #[inline]
+    fn array_member(s: &GosValue, i: OpIndex) -> (*const usize, usize) {
+        match s.slice_array_equivalent(i as usize) {
+            Ok((array, i)) => (array.as_addr(), i),
+            Err(_) => (s.as_addr(), i as usize),
+        }
+    }
+
+    /// The address printed for the pointer, the members of arrays and structs are
+    /// a word apart, the members of packages get made up ones
+    pub(crate) fn addr(&self) -> usize {
+        let word = std::mem::size_of::<usize>();
+        match self {
+            Self::UpVal(uv) => Rc::as_ptr(&uv.inner) as usize,
+            Self::SliceMember(s, i) => {
+                let (array, i) = Self::array_member(s, *i);
+                array as usize + i * word
+            }
+            Self::StructField(s, i) => s.as_addr() as usize + *i as usize * word,
+            Self::PkgMember(p, i) => ((p.as_usize() + 1) << 20) | (*i as usize * word),
+        }
+    }
+
+    /// The struct, array, slice or map pointed to, printed after a '&' by Go, None
+    /// for the other values, and for the members of packages, which can't be read
+    /// without the packages
+    pub(crate) fn pointee(&self) -> Option<GosValue> {
+        let val = match self {
+            Self::UpVal(uv) => uv.peek()?,
+            Self::SliceMember(s, i) => s.caller_slow().slice_get(s, *i as usize).ok()?,
+            Self::StructField(s, i) => {
+                s.as_struct().0.try_borrow_fields().ok()?[*i as usize].clone()
+            }
+            Self::PkgMember(_, _) => return None,
+        };
+        match val.typ() {
+            ValueType::Struct | ValueType::Array | ValueType::Slice | ValueType::Map => Some(val),
+            _ => None,
+        }
+    }
+
     #[inline]
     fn order(&self) -> usize {
         match self {
@@ -1280,7 +1619,7 @@ impl PartialEq for PointerObj {
         match (self, other) {
             (Self::UpVal(x), Self::UpVal(y)) => x == y,
             (Self::SliceMember(x, ix), Self::SliceMember(y, iy)) => {
-                x.as_addr() == y.as_addr() && ix == iy
+                Self::array_member(x, *ix) == Self::array_member(y, *iy)
             }
             (Self::StructField(x, ix), Self::StructField(y, iy)) => {
                 x.as_addr() == y.as_addr() && ix == iy
@@ -1295,10 +1634,7 @@ impl Hash for PointerObj {
     fn hash<H: Hasher>(&self, state: &mut H) {
         match self {
             Self::UpVal(x) => x.hash(state),
-            Self::SliceMember(s, index) => {
-                s.as_addr().hash(state);
-                index.hash(state);
-            }
+            Self::SliceMember(s, index) => Self::array_member(s, *index).hash(state),
             Self::StructField(s, index) => {
                 s.as_addr().hash(state);
                 index.hash(state);
@@ -1323,7 +1659,7 @@ impl Ord for PointerObj {
         match (self, other) {
             (Self::UpVal(x), Self::UpVal(y)) => x.cmp(&y),
             (Self::SliceMember(x, ix), Self::SliceMember(y, iy)) => {
-                x.as_addr().cmp(&y.as_addr()).then(ix.cmp(&iy))
+                Self::array_member(x, *ix).cmp(&Self::array_member(y, *iy))
             }
             (Self::StructField(x, ix), Self::StructField(y, iy)) => {
                 x.as_addr().cmp(&y.as_addr()).then(ix.cmp(&iy))
@@ -1336,12 +1672,7 @@ impl Ord for PointerObj {
 
 impl Display for PointerObj {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::UpVal(uv) => write!(f, "{:p}", Rc::as_ptr(&uv.inner)),
-            Self::SliceMember(s, i) => write!(f, "{:p}i{}", s.as_addr(), i),
-            Self::StructField(s, i) => write!(f, "{:p}i{}", s.as_addr(), i),
-            Self::PkgMember(p, i) => write!(f, "{:x}i{}", p.as_usize(), i),
-        }
+        fmt_addr(f, self.addr())
     }
 }
 
@@ -1444,6 +1775,16 @@ impl UnsafePtrObj {
         &*self.ptr
     }
 
+    /// for gc, the number of values sharing the pointer
+    pub(crate) fn shared_count(&self) -> usize {
+        Rc::strong_count(&self.ptr)
+    }
+
+    /// for gc
+    pub(crate) fn downgrade(&self) -> Weak<dyn UnsafePtr> {
+        Rc::downgrade(&self.ptr)
+    }
+
     pub fn downcast_ref<T: Any>(&self) -> RuntimeResult<&T> {
         self.ptr
             .as_any()
@@ -1463,7 +1804,7 @@ impl PartialEq for UnsafePtrObj {
 
 impl Display for UnsafePtrObj {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:p}", &*self.ptr as *const dyn UnsafePtr)
+        fmt_addr(f, &*self.ptr as *const dyn UnsafePtr as *const () as usize)
     }
 }
 // ----------------------------------------------------------------------------
@@ -1622,6 +1963,20 @@ impl UpValue {
         }
     }
 
+    /// The value, without the stack of the goroutine it's on, for printing it
+    pub(crate) fn peek(&self) -> Option<GosValue> {
+        match &self.inner.borrow() as &UpValueState {
+            UpValueState::Open(desc) => {
+                let stack = desc.stack.upgrade()?;
+                // the stack is borrowed by the goroutine calling the FFI printing it,
+                // which doesn't change it while it's being printed
+                let stack = unsafe { &*stack.as_ptr() };
+                Some(stack.get(desc.abs_index()).clone())
+            }
+            UpValueState::Closed(val) => Some(val.clone()),
+        }
+    }
+
     pub fn set_value(&self, val: GosValue, stack: &mut Stack) {
         match &mut self.inner.borrow_mut() as &mut UpValueState {
             UpValueState::Open(desc) => desc.store(val, stack),
@@ -1633,9 +1988,11 @@ impl UpValue {
 
     /// for gc
     pub fn ref_sub_one(&self) {
-        let state: &UpValueState = &self.inner.borrow();
-        if let UpValueState::Closed(uvs) = state {
-            uvs.ref_sub_one()
+        if matches!(&*self.inner.borrow(), UpValueState::Closed(_)) {
+            let addr = Rc::as_ptr(&self.inner) as *const ();
+            let strong = Rc::strong_count(&self.inner);
+            let inner = SharedInner::UpValue(Rc::downgrade(&self.inner));
+            gc::ref_sub_one_shared(addr, strong, inner);
         }
     }
 
@@ -1767,6 +2124,8 @@ impl GosClosureObj {
 #[derive(Clone, Debug)]
 pub struct FfiClosureObj {
     pub ffi: Rc<dyn Ffi>,
+    /// The name the FFI is registered with
+    pub ffi_name: Rc<str>,
     pub func_name: String,
     pub is_async: bool,
     pub meta: Meta,
@@ -1861,8 +2220,8 @@ pub struct PackageObj {
     members: Vec<RefCell<GosValue>>, // imports, const, var, func are all stored here
     member_indices: Map<String, OpIndex>,
     init_funcs: Vec<GosValue>,
-    // maps func_member_index of the constructor to pkg_member_index
-    var_mapping: RefCell<Option<Map<OpIndex, OpIndex>>>,
+    // set when the constructor returns
+    inited: Cell<bool>,
 }
 
 impl PackageObj {
@@ -1872,7 +2231,7 @@ impl PackageObj {
             members: vec![],
             member_indices: Map::new(),
             init_funcs: vec![],
-            var_mapping: RefCell::new(Some(Map::new())),
+            inited: Cell::new(false),
         }
     }
 
@@ -1887,20 +2246,14 @@ impl PackageObj {
         index as OpIndex
     }
 
-    pub fn add_var_mapping(&mut self, name: String, fn_index: OpIndex) -> OpIndex {
-        let index = *self.member_index(&name).unwrap();
-        self.var_mapping
-            .borrow_mut()
-            .as_mut()
-            .unwrap()
-            .insert(fn_index, index);
-        index
-    }
-
     pub fn add_init_func(&mut self, func: GosValue) {
         self.init_funcs.push(func);
     }
 
+    pub fn init_func_count(&self) -> usize {
+        self.init_funcs.len()
+    }
+
     pub fn member_indices(&self) -> &Map<String, OpIndex> {
         &self.member_indices
     }
@@ -1910,7 +2263,7 @@ impl PackageObj {
     }
 
     pub fn inited(&self) -> bool {
-        self.var_mapping.borrow().is_none()
+        self.inited.get()
     }
 
     #[inline]
@@ -1929,14 +2282,24 @@ impl PackageObj {
     }
 
     #[inline]
-    pub fn init_vars(&self, vals: Vec<GosValue>) {
-        let mut borrow = self.var_mapping.borrow_mut();
-        let mapping = borrow.as_ref().unwrap();
-        for (i, v) in vals.into_iter().enumerate() {
-            let vi = mapping[&(i as OpIndex)];
-            *self.member_mut(vi) = v;
+    pub fn set_inited(&self) {
+        self.inited.set(true);
+    }
+
+    /// Copies of the values of the members, for `restore`
+    pub(crate) fn snapshot(&self, gcc: &GcContainer) -> Vec<GosValue> {
+        self.members
+            .iter()
+            .map(|m| m.borrow().copy_semantic(gcc))
+            .collect()
+    }
+
+    /// Sets the members to copies of `vals` and the package to not initialized
+    pub(crate) fn restore(&self, vals: &[GosValue], gcc: &GcContainer) {
+        for (m, v) in self.members.iter().zip(vals.iter()) {
+            *m.borrow_mut() = v.copy_semantic(gcc);
         }
-        *borrow = None;
+        self.inited.set(false);
     }
 }
 
@@ -1952,7 +2315,7 @@ impl BorshSerialize for PackageObj {
         members.serialize(writer)?;
         self.member_indices.serialize(writer)?;
         self.init_funcs.serialize(writer)?;
-        self.var_mapping.borrow().serialize(writer)
+        self.inited.get().serialize(writer)
     }
 }
 
@@ -1966,13 +2329,13 @@ impl BorshDeserialize for PackageObj {
             .collect();
         let member_indices = Map::<String, OpIndex>::deserialize(buf)?;
         let init_funcs = Vec::<GosValue>::deserialize(buf)?;
-        let var_mapping = RefCell::new(Option::<Map<OpIndex, OpIndex>>::deserialize(buf)?);
+        let inited = Cell::new(bool::deserialize(buf)?);
         Ok(PackageObj {
             name,
             members,
             member_indices,
             init_funcs,
-            var_mapping,
+            inited,
         })
     }
 }
@@ -1988,50 +2351,94 @@ pub enum FuncFlag {
     HasDefer,
 }
 
+/// The name of a function the way Go's stack traces show it: `main.f`, `main.T.m`,
+/// `main.(*T).m`, `main.f.func1` for the first function literal in f and
+/// `main.f.func1.1` for the first one in that.
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FuncName {
+    /// The import path of the package, `main` for the main package
+    pub package: String,
+    /// The receiver type of a method, `*T` for a pointer receiver
+    pub recv: Option<String>,
+    pub name: String,
+}
+
+impl FuncName {
+    pub fn new(package: &str, recv: Option<String>, name: String) -> FuncName {
+        FuncName {
+            package: package.to_owned(),
+            recv,
+            name,
+        }
+    }
+}
+
+impl Display for FuncName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.recv {
+            Some(recv) if recv.starts_with('*') => {
+                write!(f, "{}.({}).{}", self.package, recv, self.name)
+            }
+            Some(recv) => write!(f, "{}.{}.{}", self.package, recv, self.name),
+            None => write!(f, "{}.{}", self.package, self.name),
+        }
+    }
+}
+
 /// FunctionObj is the direct container of the Opcode.
 #[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Clone, Debug)]
 pub struct FunctionObj {
     pub package: PackageKey,
+    /// Kept without the debug info too, like the symbols of a stripped Go binary
+    pub name: FuncName,
     pub meta: Meta,
     pub flag: FuncFlag,
     pub param_count: OpIndex,
     pub max_write_index: OpIndex,
-    pub ret_zeros: Vec<GosValue>,
+    /// Shared with the other functions with the same results, see `VMObjects::zero_vec`
+    pub ret_zeros: Rc<[GosValue]>,
 
     pub code: Vec<Instruction>,
     #[cfg_attr(
         all(feature = "serde_borsh", not(feature = "instruction_pos")),
         borsh_skip
     )]
-    pub pos: Vec<Option<u32>>,
+    pub pos: PosTable,
     pub up_ptrs: Vec<ValueDesc>,
-    pub local_zeros: Vec<GosValue>,
+    /// Placeholders the locals start with, every local is written to before it's read
+    pub local_zeros: Rc<[GosValue]>,
+    #[cfg_attr(all(feature = "serde_borsh", not(feature = "debug_info")), borsh_skip)]
+    pub locals: Vec<LocalVarInfo>,
 }
 
 impl FunctionObj {
     pub fn new(
         package: PackageKey,
+        name: FuncName,
         meta: Meta,
-        metas: &MetadataObjs,
+        objs: &VMObjects,
         gcc: &GcContainer,
         flag: FuncFlag,
     ) -> FunctionObj {
-        let s = &metas[meta.key].as_signature();
-        let ret_zeros = s.results.iter().map(|m| m.zero(metas, gcc)).collect();
+        let s = &objs.metas[meta.key].as_signature();
+        let ret_zeros = objs.zero_vec(&s.results, gcc);
         let mut param_count = s.recv.map_or(0, |_| 1);
         param_count += s.params.len() as OpIndex;
         FunctionObj {
             package,
+            name,
             meta,
             flag,
             param_count,
             max_write_index: 0,
             ret_zeros,
             code: Vec::new(),
-            pos: Vec::new(),
+            pos: PosTable::default(),
             up_ptrs: Vec::new(),
-            local_zeros: Vec::new(),
+            local_zeros: Rc::from([]),
+            locals: Vec::new(),
         }
     }
 
@@ -2054,4 +2461,34 @@ impl FunctionObj {
     pub fn is_ctor(&self) -> bool {
         self.flag == FuncFlag::PkgCtor
     }
+
+    /// Returns the named variables in scope at instruction `pc`, by slot, leaving out
+    /// the ones shadowed by the variables of inner blocks
+    pub fn locals_at(&self, pc: OpIndex) -> Vec<&LocalVarInfo> {
+        let pc = pc as u32;
+        let mut result: Vec<&LocalVarInfo> = vec![];
+        for var in self.locals.iter().filter(|v| v.begin <= pc && pc < v.end) {
+            match result.iter_mut().find(|v| v.name == var.name) {
+                Some(v) if v.begin <= var.begin => *v = var,
+                Some(_) => {}
+                None => result.push(var),
+            }
+        }
+        result.sort_by_key(|v| v.slot);
+        result
+    }
+}
+
+/// A named local variable of a function, the results and the parameters are the
+/// ones in scope for all the instructions.
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
+pub struct LocalVarInfo {
+    pub name: String,
+    /// The stack slot of the variable, counted from the stack base of the call
+    pub slot: OpIndex,
+    /// The index of the first instruction the variable is in scope for
+    pub begin: u32,
+    /// The index of the first instruction after the scope of the variable
+    pub end: u32,
 }
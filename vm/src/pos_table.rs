@@ -0,0 +1,220 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The compact table of the source positions of the instructions of a function.
+//!
+//! Consecutive instructions mostly come from the same expression, so the positions
+//! are stored as runs: for every run, the number of instructions in it and the
+//! difference between its position and the one of the run before, both as LEB128
+//! varints, the difference zigzag encoded. An instruction without a position is
+//! stored as position 0, the real ones are shifted by one.
+//!
+//! Decoding has to start from a known state, so one is recorded every
+//! `CHECKPOINT_RUNS` runs, a lookup then decodes at most that many runs.
+
+#[cfg(feature = "serde_borsh")]
+use borsh::{BorshDeserialize, BorshSerialize};
+
+const CHECKPOINT_RUNS: usize = 16;
+
+/// The state of the decoder at the beginning of a run
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Copy, Debug, Default)]
+struct Checkpoint {
+    /// The index of the first instruction of the run
+    pc: u32,
+    /// The offset of the run in the encoded data
+    offset: u32,
+    /// The encoded position of the run before
+    prev: u64,
+}
+
+/// Maps the instructions of a function to their source positions
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug, Default)]
+pub struct PosTable {
+    data: Vec<u8>,
+    checkpoints: Vec<Checkpoint>,
+    len: u32,
+}
+
+impl PosTable {
+    /// The number of instructions in the table
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The size of the encoded table in bytes
+    pub fn encoded_size(&self) -> usize {
+        self.data.len() + self.checkpoints.len() * std::mem::size_of::<Checkpoint>()
+    }
+
+    /// Returns the source position of the instruction at `pc`, None if it's out of
+    /// range or the instruction has no position
+    pub fn get(&self, pc: usize) -> Option<u32> {
+        if pc >= self.len() {
+            return None;
+        }
+        let i = self.checkpoints.partition_point(|c| c.pc as usize <= pc) - 1;
+        let cp = self.checkpoints[i];
+        let mut runs = Runs {
+            data: &self.data,
+            offset: cp.offset as usize,
+            pc: cp.pc as usize,
+            prev: cp.prev,
+        };
+        runs.find(|(end, _)| pc < *end).and_then(|(_, v)| decode(v))
+    }
+
+    /// Returns the positions of all the instructions in order
+    pub fn iter(&self) -> impl Iterator<Item = Option<u32>> + '_ {
+        let runs = Runs {
+            data: &self.data,
+            offset: 0,
+            pc: 0,
+            prev: 0,
+        };
+        runs.scan(0, |start, (end, v)| {
+            let count = end - *start;
+            *start = end;
+            Some(std::iter::repeat_n(decode(v), count))
+        })
+        .flatten()
+    }
+}
+
+impl FromIterator<Option<u32>> for PosTable {
+    fn from_iter<I: IntoIterator<Item = Option<u32>>>(iter: I) -> Self {
+        let mut runs: Vec<(u64, u64)> = vec![];
+        for p in iter {
+            let v = p.map_or(0, |x| x as u64 + 1);
+            match runs.last_mut() {
+                Some((count, last)) if *last == v => *count += 1,
+                _ => runs.push((1, v)),
+            }
+        }
+
+        let mut table = PosTable::default();
+        let (mut pc, mut prev) = (0u64, 0u64);
+        for (i, (count, v)) in runs.into_iter().enumerate() {
+            if i % CHECKPOINT_RUNS == 0 {
+                table.checkpoints.push(Checkpoint {
+                    pc: pc as u32,
+                    offset: table.data.len() as u32,
+                    prev,
+                });
+            }
+            write_varint(&mut table.data, count);
+            write_varint(&mut table.data, zigzag(v as i64 - prev as i64));
+            pc += count;
+            prev = v;
+        }
+        assert!(pc <= u32::MAX as u64);
+        table.len = pc as u32;
+        table
+    }
+}
+
+/// Decodes the runs from a checkpoint, yielding the index of the instruction
+/// after each run and the encoded position of it
+struct Runs<'a> {
+    data: &'a [u8],
+    offset: usize,
+    pc: usize,
+    prev: u64,
+}
+
+impl<'a> Iterator for Runs<'a> {
+    type Item = (usize, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.data.len() {
+            return None;
+        }
+        let count = read_varint(self.data, &mut self.offset);
+        let delta = unzigzag(read_varint(self.data, &mut self.offset));
+        self.pc += count as usize;
+        self.prev = (self.prev as i64 + delta) as u64;
+        Some((self.pc, self.prev))
+    }
+}
+
+#[inline]
+fn decode(v: u64) -> Option<u32> {
+    v.checked_sub(1).map(|x| x as u32)
+}
+
+#[inline]
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+#[inline]
+fn unzigzag(v: u64) -> i64 {
+    (v >> 1) as i64 ^ -((v & 1) as i64)
+}
+
+fn write_varint(data: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        data.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    data.push(v as u8);
+}
+
+fn read_varint(data: &[u8], offset: &mut usize) -> u64 {
+    let mut v = 0;
+    let mut shift = 0;
+    loop {
+        let b = data[*offset];
+        *offset += 1;
+        v |= ((b & 0x7f) as u64) << shift;
+        if b < 0x80 {
+            return v;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pos_table() {
+        let empty: PosTable = std::iter::empty().collect();
+        assert!(empty.is_empty());
+        assert_eq!(empty.get(0), None);
+        assert_eq!(empty.iter().count(), 0);
+
+        // enough runs for several checkpoints, with positions going back and forth,
+        // gaps without a position and deltas that need multi-byte varints
+        let mut pos = vec![];
+        for i in 0..200u32 {
+            let p = match i % 5 {
+                0 => None,
+                1 => Some(i * 1000),
+                2 => Some(3),
+                3 => Some(u32::MAX),
+                _ => Some(0),
+            };
+            pos.extend(std::iter::repeat_n(p, (i % 4 + 1) as usize));
+        }
+        let table: PosTable = pos.iter().copied().collect();
+        assert_eq!(table.len(), pos.len());
+        assert!(table.checkpoints.len() > 1);
+        assert!(table.encoded_size() < pos.len() * std::mem::size_of::<Option<u32>>());
+        for (pc, p) in pos.iter().enumerate() {
+            assert_eq!(table.get(pc), *p, "pc {}", pc);
+        }
+        assert_eq!(table.get(pos.len()), None);
+        assert!(table.iter().eq(pos.iter().copied()));
+    }
+}
@@ -0,0 +1,406 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The race detector of the instrumented mode, which is enabled by the `race` feature.
+//!
+//! Goroutines are scheduled on a single thread, but as they yield in the middle of
+//! their work, unsynchronized accesses to shared memory still race. Every goroutine
+//! has a vector clock, the happens-before relation is built from goroutine creation,
+//! channel operations and the locking of mutexes, and two accesses to the same memory
+//! cell from different goroutines race if at least one of them is a write and neither
+//! happens before the other.
+//!
+//! The cells are the elements and fields of heap objects, package members and the
+//! variables shared with closures. The accesses of a function to its own local
+//! variables are not tracked, so a race is only found when at least one side of it
+//! reaches the variable through a closure or a pointer.
+//!
+//! The memory used is bounded the way it is in ThreadSanitizer: the entry of a
+//! goroutine that ended in the vector clocks is taken over by the next one started,
+//! so a race between the two of them can be missed, the shadows of an object are
+//! dropped when a new object is made at its address, and the whole history is dropped
+//! when it grows over a limit.
+
+use crate::objects::{PointerObj, UpValue, UpValueState};
+use crate::value::{FunctionKey, GosValue, OpIndex, PackageKey};
+use go_parser::{Map, PiggyVecKey};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The shadows and sync objects kept at most
+const MAX_ENTRIES: usize = 1 << 20;
+
+/// A memory location tracked by the detector
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub(crate) enum Cell {
+    /// An element or a field of a heap object, (address, index)
+    Object(usize, usize),
+    /// A package member, (package, index)
+    Package(usize, OpIndex),
+    /// A variable on the stack of a goroutine shared with closures, (stack, index)
+    Stack(usize, OpIndex),
+    /// A variable that has outlived its frame, the address of the upvalue
+    UpValue(usize),
+}
+
+impl Cell {
+    pub(crate) fn element(obj: &GosValue, index: usize) -> Cell {
+        Cell::Object(obj.as_addr() as usize, index)
+    }
+
+    pub(crate) fn package(pkg: PackageKey, index: OpIndex) -> Cell {
+        Cell::Package(pkg.as_usize(), index)
+    }
+
+    pub(crate) fn up_value(uv: &UpValue) -> Cell {
+        match &uv.inner.borrow() as &UpValueState {
+            UpValueState::Open(desc) => Cell::Stack(desc.stack.as_ptr() as usize, desc.abs_index()),
+            UpValueState::Closed(_) => Cell::UpValue(Rc::as_ptr(&uv.inner) as usize),
+        }
+    }
+
+    pub(crate) fn pointee(p: &PointerObj) -> Cell {
+        match p {
+            PointerObj::UpVal(uv) => Cell::up_value(uv),
+            PointerObj::SliceMember(s, i) => match s.slice_array_equivalent(*i as usize) {
+                Ok((array, i)) => Cell::element(array, i),
+                Err(_) => Cell::element(s, *i as usize),
+            },
+            PointerObj::StructField(s, i) => Cell::element(s, *i as usize),
+            PointerObj::PkgMember(pkg, i) => Cell::package(*pkg, *i),
+        }
+    }
+
+    /// The object, package or stack the cell is in, and the index of the cell there
+    fn split(self) -> (Cell, usize) {
+        match self {
+            Cell::Object(addr, i) => (Cell::Object(addr, 0), i),
+            Cell::Package(pkg, i) => (Cell::Package(pkg, 0), i as usize),
+            Cell::Stack(stack, i) => (Cell::Stack(stack, 0), i as usize),
+            Cell::UpValue(addr) => (Cell::UpValue(addr), 0),
+        }
+    }
+}
+
+/// Indexed by the slots of the goroutines
+#[derive(Clone, Default, Debug)]
+struct VectorClock(Vec<u32>);
+
+impl VectorClock {
+    fn get(&self, slot: usize) -> u32 {
+        self.0.get(slot).copied().unwrap_or(0)
+    }
+
+    fn set(&mut self, slot: usize, epoch: u32) {
+        if self.0.len() <= slot {
+            self.0.resize(slot + 1, 0);
+        }
+        self.0[slot] = epoch;
+    }
+
+    fn tick(&mut self, slot: usize) {
+        self.set(slot, self.get(slot) + 1);
+    }
+
+    fn join(&mut self, other: &VectorClock) {
+        if self.0.len() < other.0.len() {
+            self.0.resize(other.0.len(), 0);
+        }
+        for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+            *a = (*a).max(*b);
+        }
+    }
+}
+
+/// An access to a cell
+#[derive(Clone, Debug)]
+pub(crate) struct Access {
+    pub fiber: usize,
+    pub write: bool,
+    /// The instruction making the access, the stack is only built for a report
+    pub pc: (FunctionKey, OpIndex),
+    // the slot of the goroutine in the vector clocks
+    slot: usize,
+    // the clock of the goroutine at the time of the access
+    epoch: u32,
+}
+
+#[derive(Default)]
+struct Shadow {
+    write: Option<Access>,
+    // the last read of each slot since the last write
+    reads: Vec<Access>,
+}
+
+/// Two accesses racing with each other
+#[derive(Debug)]
+pub(crate) struct Race {
+    pub current: Access,
+    pub previous: Access,
+}
+
+#[derive(Default)]
+pub(crate) struct RaceDetector {
+    // by slot, the entry of a goroutine that ended is reused by the next one
+    clocks: Vec<VectorClock>,
+    // the slots of the live goroutines, by id
+    slots: Map<usize, usize>,
+    free_slots: Vec<usize>,
+    // channels and mutexes, by address
+    syncs: Map<usize, VectorClock>,
+    // cells accessed with sync/atomic
+    atomics: Map<Cell, VectorClock>,
+    // by the object, package or stack the cells are in
+    cells: Map<Cell, Map<usize, Shadow>>,
+    shadows: usize,
+    // the pairs of instructions already reported
+    reported: Vec<((FunctionKey, OpIndex), (FunctionKey, OpIndex))>,
+}
+
+impl RaceDetector {
+    pub(crate) fn new() -> RaceDetector {
+        RaceDetector::default()
+    }
+
+    /// The number of races reported
+    pub(crate) fn count(&self) -> usize {
+        self.reported.len()
+    }
+
+    fn slot(&mut self, fiber: usize) -> usize {
+        if let Some(slot) = self.slots.get(&fiber) {
+            return *slot;
+        }
+        let slot = match self.free_slots.pop() {
+            Some(slot) => slot,
+            None => {
+                self.clocks.push(VectorClock::default());
+                self.clocks.len() - 1
+            }
+        };
+        // the epochs go on from the ones of the goroutine that had the slot
+        let clock = &mut self.clocks[slot];
+        let epoch = clock.get(slot) + 1;
+        *clock = VectorClock::default();
+        clock.set(slot, epoch);
+        self.slots.insert(fiber, slot);
+        slot
+    }
+
+    /// Everything the parent did so far happens before the child starts
+    pub(crate) fn fork(&mut self, parent: usize, child: usize) {
+        let (parent_slot, slot) = (self.slot(parent), self.slot(child));
+        let parent_clock = self.clocks[parent_slot].clone();
+        let clock = &mut self.clocks[slot];
+        let epoch = clock.get(slot).max(parent_clock.get(slot)) + 1;
+        clock.join(&parent_clock);
+        clock.set(slot, epoch);
+        self.clocks[parent_slot].tick(parent_slot);
+    }
+
+    /// Frees the slot of a goroutine that ended, and forgets the variables on its
+    /// stack, at the address of which the stack of another one can be made.
+    pub(crate) fn exit(&mut self, fiber: usize, stack: usize) {
+        if let Some(slot) = self.slots.remove(&fiber) {
+            self.free_slots.push(slot);
+        }
+        self.forget(Cell::Stack(stack, 0));
+    }
+
+    /// Forgets the cells of the objects that were freed, as new objects are made at
+    /// the addresses
+    pub(crate) fn forget_objects(&mut self, addrs: impl Iterator<Item = usize>) {
+        for addr in addrs {
+            self.forget(Cell::Object(addr, 0));
+        }
+    }
+
+    fn forget(&mut self, owner: Cell) {
+        if let Some(cells) = self.cells.remove(&owner) {
+            self.shadows -= cells.len();
+        }
+    }
+
+    /// Publishes what the goroutine did so far to the sync object, like unlocking
+    /// a mutex or sending to a channel.
+    pub(crate) fn release(&mut self, fiber: usize, obj: usize) {
+        let slot = self.slot(fiber);
+        let clock = &self.clocks[slot];
+        self.syncs.entry(obj).or_default().join(clock);
+        self.clocks[slot].tick(slot);
+    }
+
+    /// Picks up what has been released to the sync object, like locking a mutex
+    /// or receiving from a channel.
+    pub(crate) fn acquire(&mut self, fiber: usize, obj: usize) {
+        let slot = self.slot(fiber);
+        if let Some(sync) = self.syncs.get(&obj) {
+            self.clocks[slot].join(sync);
+        }
+    }
+
+    /// Atomic loads and stores never race, but synchronize like acquire and release
+    pub(crate) fn atomic(&mut self, fiber: usize, cell: Cell, write: bool) {
+        let slot = self.slot(fiber);
+        let sync = self.atomics.entry(cell).or_default();
+        if write {
+            sync.join(&self.clocks[slot]);
+            self.clocks[slot].tick(slot);
+        } else {
+            self.clocks[slot].join(sync);
+        }
+    }
+
+    /// Records an access, and returns the earlier access it races with, if it has
+    /// not been reported yet.
+    pub(crate) fn access(
+        &mut self,
+        fiber: usize,
+        cell: Cell,
+        write: bool,
+        pc: (FunctionKey, OpIndex),
+    ) -> Option<Race> {
+        self.bound();
+        let slot = self.slot(fiber);
+        let clock = &self.clocks[slot];
+        let (owner, index) = cell.split();
+        let cells = self.cells.entry(owner).or_default();
+        let shadow = cells.entry(index).or_insert_with(|| {
+            self.shadows += 1;
+            Shadow::default()
+        });
+        let racy = |a: &&Access| a.fiber != fiber && a.epoch > clock.get(a.slot);
+        let previous = match shadow.write.as_ref().filter(racy) {
+            Some(w) => Some(w.clone()),
+            None if write => shadow.reads.iter().find(racy).cloned(),
+            None => None,
+        };
+
+        let current = Access {
+            fiber,
+            write,
+            pc,
+            slot,
+            epoch: clock.get(slot),
+        };
+        if write {
+            shadow.write = Some(current.clone());
+            shadow.reads.clear();
+        } else {
+            match shadow.reads.iter_mut().find(|a| a.slot == slot) {
+                Some(a) => *a = current.clone(),
+                None => shadow.reads.push(current.clone()),
+            }
+        }
+
+        let previous = previous?;
+        let pair = (current.pc, previous.pc);
+        if self.reported.contains(&pair) || self.reported.contains(&(pair.1, pair.0)) {
+            return None;
+        }
+        self.reported.push(pair);
+        Some(Race { current, previous })
+    }
+
+    /// Drops the history over the limit. Dropping the sync objects along with the
+    /// shadows loses no happens-before relation for the accesses made after.
+    fn bound(&mut self) {
+        if self.shadows + self.syncs.len() + self.atomics.len() > MAX_ENTRIES {
+            self.cells.clear();
+            self.shadows = 0;
+            self.syncs.clear();
+            self.atomics.clear();
+        }
+    }
+}
+
+/// The race detector as seen by an FFI call, for the FFIs implementing sync objects
+/// to tell the detector when they are locked and unlocked.
+#[derive(Clone)]
+pub struct RaceCtx {
+    pub(crate) detector: Rc<RefCell<RaceDetector>>,
+    pub(crate) fiber: usize,
+}
+
+impl RaceCtx {
+    /// To be called after the sync object at `addr` is acquired, like a mutex locked
+    pub fn acquire(&self, addr: usize) {
+        self.detector.borrow_mut().acquire(self.fiber, addr);
+    }
+
+    /// To be called before the sync object at `addr` is released, like a mutex unlocked
+    pub fn release(&self, addr: usize) {
+        self.detector.borrow_mut().release(self.fiber, addr);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pc(pc: OpIndex) -> (FunctionKey, OpIndex) {
+        (FunctionKey::default(), pc)
+    }
+
+    #[test]
+    fn test_race() {
+        let cell = Cell::Object(1, 0);
+        let mut rd = RaceDetector::new();
+        rd.fork(0, 1);
+        assert!(rd.access(0, cell, true, pc(1)).is_none());
+        let race = rd.access(1, cell, false, pc(2)).unwrap();
+        assert!(race.previous.write && race.previous.fiber == 0);
+        // reported only once
+        assert!(rd.access(1, cell, false, pc(2)).is_none());
+        assert_eq!(rd.count(), 1);
+    }
+
+    #[test]
+    fn test_happens_before() {
+        let cell = Cell::Object(1, 0);
+        let mut rd = RaceDetector::new();
+        // written before the goroutine starts
+        assert!(rd.access(0, cell, true, pc(1)).is_none());
+        rd.fork(0, 1);
+        assert!(rd.access(1, cell, true, pc(2)).is_none());
+        // synchronized by a channel
+        rd.release(1, 100);
+        rd.acquire(0, 100);
+        assert!(rd.access(0, cell, false, pc(3)).is_none());
+        // reads don't race with each other
+        assert!(rd.access(1, cell, false, pc(4)).is_none());
+        // but a write races with the read of the other goroutine
+        assert!(rd.access(0, cell, true, pc(5)).is_some());
+    }
+
+    #[test]
+    fn test_reused_slot() {
+        let cell = Cell::Object(1, 0);
+        let mut rd = RaceDetector::new();
+        rd.fork(0, 1);
+        assert!(rd.access(1, cell, true, pc(1)).is_none());
+        rd.exit(1, 200);
+        // the slot of the goroutine is taken over
+        rd.fork(0, 2);
+        assert_eq!(rd.clocks.len(), 2);
+        assert!(rd.access(2, Cell::Object(2, 0), true, pc(2)).is_none());
+        // the accesses of the goroutine that ended are still checked
+        assert!(rd.access(0, cell, true, pc(3)).is_some());
+    }
+
+    #[test]
+    fn test_forget() {
+        let mut rd = RaceDetector::new();
+        rd.fork(0, 1);
+        assert!(rd.access(1, Cell::Object(1, 0), true, pc(1)).is_none());
+        assert!(rd.access(1, Cell::Stack(200, 0), true, pc(2)).is_none());
+        // a new object is made at the address of a freed one
+        rd.forget_objects([1].into_iter());
+        assert!(rd.access(0, Cell::Object(1, 0), true, pc(3)).is_none());
+        // and a new stack at the one of a goroutine that ended
+        rd.exit(1, 200);
+        assert!(rd.access(0, Cell::Stack(200, 0), true, pc(4)).is_none());
+        assert_eq!(rd.shadows, 2);
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Limiting what the scripts can do to the host.
+//!
+//! The FFIs doing privileged operations, like opening files, reading environment
+//! variables or connecting to other hosts, ask the `SandboxPolicy` of the run first,
+//! and fail the way the operating system would have refused if it's denied. A policy
+//! denies everything it doesn't allow. A run without a policy is not restricted.
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// A privileged operation a script asks for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability<'a> {
+    /// Opening the file at the path, for writing if `write`
+    OpenFile { path: &'a Path, write: bool },
+    /// Writing to an open file, growing it to `size` bytes
+    WriteFile { size: u64 },
+    /// Reading the environment variable
+    Env(&'a str),
+    /// Connecting to the host, with the port if the URL has one
+    Connect(&'a str),
+    /// Starting a goroutine, with the number of goroutines running, the new one
+    /// included
+    Spawn(usize),
+}
+
+/// Called with every capability checked and whether it's granted
+pub type AuditHook = Arc<dyn Fn(&Capability, bool) + Send + Sync>;
+
+/// What the scripts are allowed to do, built like
+/// `SandboxPolicy::new().allow_read("data").allow_env("HOME").max_goroutines(100)`
+#[derive(Clone, Default)]
+pub struct SandboxPolicy {
+    // the directories or files, with whether they can be written
+    paths: Vec<(PathBuf, bool)>,
+    env: Vec<String>,
+    hosts: Vec<String>,
+    max_goroutines: Option<usize>,
+    max_file_size: Option<u64>,
+    audit: Option<AuditHook>,
+}
+
+impl SandboxPolicy {
+    /// A policy that denies everything
+    pub fn new() -> SandboxPolicy {
+        SandboxPolicy::default()
+    }
+
+    /// Allows reading the file, or the files under the directory
+    pub fn allow_read<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.paths.push((resolve(path.as_ref()), false));
+        self
+    }
+
+    /// Allows reading, writing and creating the file, or the files under the directory
+    pub fn allow_write<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.paths.push((resolve(path.as_ref()), true));
+        self
+    }
+
+    /// Makes the environment variable visible, the others look unset
+    pub fn allow_env(mut self, name: &str) -> Self {
+        self.env.push(name.to_owned());
+        self
+    }
+
+    /// Allows connecting to the host, e.g. "example.com" or "localhost:8080", a
+    /// host without a port is allowed on any port
+    pub fn allow_host(mut self, host: &str) -> Self {
+        self.hosts.push(host.to_ascii_lowercase());
+        self
+    }
+
+    /// Limits the number of goroutines running at the same time, the main one
+    /// included, not limited by default
+    pub fn max_goroutines(mut self, n: usize) -> Self {
+        self.max_goroutines = Some(n);
+        self
+    }
+
+    /// Limits the size of the files written, not limited by default
+    pub fn max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = Some(bytes);
+        self
+    }
+
+    /// Calls `hook` with every check, for the host to log or count them
+    pub fn on_check(mut self, hook: AuditHook) -> Self {
+        self.audit = Some(hook);
+        self
+    }
+
+    /// The files and directories allowed, with whether they can be written
+    pub fn allowed_paths(&self) -> &[(PathBuf, bool)] {
+        &self.paths
+    }
+
+    /// The environment variables visible
+    pub fn allowed_env(&self) -> &[String] {
+        &self.env
+    }
+
+    /// The hosts that can be connected to
+    pub fn allowed_hosts(&self) -> &[String] {
+        &self.hosts
+    }
+
+    /// The number of goroutines that can run at the same time, if limited
+    pub fn goroutine_limit(&self) -> Option<usize> {
+        self.max_goroutines
+    }
+
+    /// The size of the files that can be written, if limited
+    pub fn file_size_limit(&self) -> Option<u64> {
+        self.max_file_size
+    }
+
+    /// Reports whether the script may do `cap`
+    pub fn check(&self, cap: &Capability) -> bool {
+        let granted = match cap {
+            Capability::OpenFile { path, write } => {
+                let path = resolve(path);
+                self.paths
+                    .iter()
+                    .any(|(p, w)| path.starts_with(p) && (*w || !write))
+            }
+            Capability::WriteFile { size } => self.max_file_size.is_none_or(|m| *size <= m),
+            Capability::Env(name) => self.env.iter().any(|e| e == name),
+            Capability::Connect(host) => {
+                let host = host.to_ascii_lowercase();
+                let name = host.rsplit_once(':').map_or(host.as_str(), |(n, _)| n);
+                self.hosts.iter().any(|h| *h == host || *h == name)
+            }
+            Capability::Spawn(count) => self.max_goroutines.is_none_or(|m| *count <= m),
+        };
+        if let Some(hook) = &self.audit {
+            hook(cap, granted);
+        }
+        granted
+    }
+}
+
+/// Makes the path absolute with the symbolic links followed, or lexically for the
+/// paths that don't exist yet, so that ".." can't get out of an allowed directory
+fn resolve(path: &Path) -> PathBuf {
+    if let Ok(p) = path.canonicalize() {
+        return p;
+    }
+    if let (Some(parent), Some(name)) = (path.parent(), path.file_name()) {
+        let parent = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+        if let Ok(p) = parent.canonicalize() {
+            return p.join(name);
+        }
+    }
+    let mut result = std::env::current_dir().unwrap_or_default();
+    for c in path.components() {
+        match c {
+            Component::ParentDir => {
+                result.pop();
+            }
+            Component::CurDir => {}
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_deny_by_default() {
+        let p = SandboxPolicy::new();
+        let path = Path::new("a.txt");
+        assert!(!p.check(&Capability::OpenFile { path, write: false }));
+        assert!(!p.check(&Capability::Env("HOME")));
+        assert!(!p.check(&Capability::Connect("example.com")));
+        assert!(p.check(&Capability::Spawn(1000)));
+    }
+
+    #[test]
+    fn test_paths() {
+        let dir = std::env::temp_dir().join("goscript_sandbox_test");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let p = SandboxPolicy::new()
+            .allow_read(&dir)
+            .allow_write(dir.join("sub"));
+        let open = |path: &Path, write| p.check(&Capability::OpenFile { path, write });
+        assert!(open(&dir.join("x.txt"), false));
+        assert!(!open(&dir.join("x.txt"), true));
+        assert!(open(&dir.join("sub/new.txt"), true));
+        assert!(!open(&dir.join("sub/../../x.txt"), false));
+        assert!(!open(&dir.join("../goscript_sandbox_test2/x.txt"), false));
+    }
+
+    #[test]
+    fn test_limits_and_audit() {
+        let checks = Arc::new(AtomicUsize::new(0));
+        let denied = Arc::new(AtomicUsize::new(0));
+        let (c, d) = (checks.clone(), denied.clone());
+        let p = SandboxPolicy::new()
+            .allow_host("Example.com")
+            .allow_host("localhost:8080")
+            .allow_env("HOME")
+            .max_goroutines(2)
+            .max_file_size(10)
+            .on_check(Arc::new(move |_, granted| {
+                c.fetch_add(1, Ordering::Relaxed);
+                if !granted {
+                    d.fetch_add(1, Ordering::Relaxed);
+                }
+            }));
+        assert!(p.check(&Capability::Connect("example.com:443")));
+        assert!(p.check(&Capability::Connect("localhost:8080")));
+        assert!(!p.check(&Capability::Connect("localhost:9090")));
+        assert!(p.check(&Capability::Env("HOME")));
+        assert!(!p.check(&Capability::Env("PATH")));
+        assert!(p.check(&Capability::Spawn(2)));
+        assert!(!p.check(&Capability::Spawn(3)));
+        assert!(p.check(&Capability::WriteFile { size: 10 }));
+        assert!(!p.check(&Capability::WriteFile { size: 11 }));
+        assert_eq!(checks.load(Ordering::Relaxed), 9);
+        assert_eq!(denied.load(Ordering::Relaxed), 4);
+    }
+}
@@ -0,0 +1,370 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The scheduler running the goroutines, and the tasks of the VM like serving the
+//! calls of the FFIs, on the thread of the run.
+//!
+//! The runnable tasks wait in a queue and are polled in turn, a goroutine runs for
+//! a time slice of instructions and goes back to the end of the queue. The tasks
+//! waiting for something, like an async FFI call, are put back in it when woken,
+//! from any thread. The tasks sleeping are woken by the scheduler itself, as their
+//! deadlines pass.
+
+use go_parser::Map;
+use std::cell::{Cell, RefCell};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::Instant;
+
+type LocalFuture<'a> = Pin<Box<dyn Future<Output = ()> + 'a>>;
+
+struct Task<'a> {
+    future: LocalFuture<'a>,
+    waker: Waker,
+    // in the queue, to be run once however many times it's woken
+    queued: bool,
+}
+
+/// The ids of the tasks woken since the scheduler last looked, with the thread to
+/// unpark if it's waiting for them
+struct Woken {
+    ids: Mutex<Vec<usize>>,
+    thread: Thread,
+}
+
+struct TaskWaker {
+    id: usize,
+    woken: Arc<Woken>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.ids.lock().unwrap().push(self.id);
+        self.woken.thread.unpark();
+    }
+}
+
+/// The deadlines of the sleeping tasks
+#[derive(Default)]
+pub(crate) struct Timers {
+    // the earliest first, with the id of the sleep, which may be gone already
+    deadlines: RefCell<BinaryHeap<Reverse<(Instant, usize)>>>,
+    // the sleeps not done, with whether they are daemons
+    sleeps: RefCell<Map<usize, (Waker, bool)>>,
+    daemons: Cell<usize>,
+    next_id: Cell<usize>,
+}
+
+impl Timers {
+    /// The number of the sleeps not done that don't keep the run going
+    pub fn daemons(&self) -> usize {
+        self.daemons.get()
+    }
+
+    fn add(&self, deadline: Instant, daemon: bool, waker: Waker) -> usize {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.deadlines.borrow_mut().push(Reverse((deadline, id)));
+        self.sleeps.borrow_mut().insert(id, (waker, daemon));
+        if daemon {
+            self.daemons.set(self.daemons.get() + 1);
+        }
+        id
+    }
+
+    fn set_waker(&self, id: usize, waker: &Waker) {
+        if let Some((w, _)) = self.sleeps.borrow_mut().get_mut(&id) {
+            if !w.will_wake(waker) {
+                *w = waker.clone();
+            }
+        }
+    }
+
+    fn remove(&self, id: usize) {
+        if let Some((_, true)) = self.sleeps.borrow_mut().remove(&id) {
+            self.daemons.set(self.daemons.get() - 1);
+        }
+    }
+
+    /// Wakes the sleeps whose deadlines have passed, returns the next deadline
+    fn fire(&self, now: Instant) -> Option<Instant> {
+        let mut deadlines = self.deadlines.borrow_mut();
+        let sleeps = self.sleeps.borrow();
+        while let Some(&Reverse((deadline, id))) = deadlines.peek() {
+            match sleeps.get(&id) {
+                Some(_) if deadline > now => return Some(deadline),
+                // woken, it's removed when it sees the deadline has passed
+                Some((waker, _)) => waker.wake_by_ref(),
+                None => {}
+            }
+            deadlines.pop();
+        }
+        None
+    }
+}
+
+/// A future ready at its deadline, the task awaiting it is woken by the scheduler.
+/// A daemon sleep doesn't keep the run going: when all the goroutines not finished
+/// are in daemon sleeps, the run ends, like the timers of package time.
+pub struct Sleep {
+    timers: Rc<Timers>,
+    deadline: Instant,
+    daemon: bool,
+    id: Option<usize>,
+}
+
+impl Sleep {
+    pub(crate) fn new(timers: Rc<Timers>, deadline: Instant, daemon: bool) -> Sleep {
+        Sleep {
+            timers,
+            deadline,
+            daemon,
+            id: None,
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            if let Some(id) = self.id.take() {
+                self.timers.remove(id);
+            }
+            return Poll::Ready(());
+        }
+        match self.id {
+            Some(id) => self.timers.set_waker(id, cx.waker()),
+            None => {
+                let id = self
+                    .timers
+                    .add(self.deadline, self.daemon, cx.waker().clone());
+                self.id = Some(id);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() {
+            self.timers.remove(id);
+        }
+    }
+}
+
+pub(crate) struct Scheduler<'a> {
+    tasks: RefCell<Map<usize, Task<'a>>>,
+    // the runnable tasks, in the order they run
+    queue: RefCell<VecDeque<usize>>,
+    woken: Arc<Woken>,
+    timers: Rc<Timers>,
+    next_id: Cell<usize>,
+}
+
+impl<'a> Scheduler<'a> {
+    /// A scheduler running the tasks on the current thread
+    pub fn new() -> Scheduler<'a> {
+        Scheduler {
+            tasks: RefCell::new(Map::new()),
+            queue: RefCell::new(VecDeque::new()),
+            woken: Arc::new(Woken {
+                ids: Mutex::new(vec![]),
+                thread: thread::current(),
+            }),
+            timers: Rc::new(Timers::default()),
+            next_id: Cell::new(0),
+        }
+    }
+
+    pub fn timers(&self) -> &Rc<Timers> {
+        &self.timers
+    }
+
+    /// Queues `future` to run after the runnable tasks, returns the id of the task
+    pub fn spawn<F>(&self, future: F) -> usize
+    where
+        F: Future<Output = ()> + 'a,
+    {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        let waker = Waker::from(Arc::new(TaskWaker {
+            id,
+            woken: self.woken.clone(),
+        }));
+        let task = Task {
+            future: Box::pin(future),
+            waker,
+            queued: true,
+        };
+        self.tasks.borrow_mut().insert(id, task);
+        self.queue.borrow_mut().push_back(id);
+        id
+    }
+
+    /// Drops the task where it is, if it's not done
+    pub fn cancel(&self, id: usize) {
+        let task = self.tasks.borrow_mut().remove(&id);
+        drop(task);
+    }
+
+    /// Polls the next runnable task, returns false if there's none
+    pub fn try_tick(&self) -> bool {
+        if !self.timers.sleeps.borrow().is_empty() {
+            self.timers.fire(Instant::now());
+        }
+        self.take_woken();
+        loop {
+            let id = match self.queue.borrow_mut().pop_front() {
+                Some(id) => id,
+                None => return false,
+            };
+            // the ones cancelled are skipped
+            let task = self.tasks.borrow_mut().remove(&id);
+            if let Some(mut task) = task {
+                let waker = task.waker.clone();
+                let poll = task.future.as_mut().poll(&mut Context::from_waker(&waker));
+                if poll.is_pending() {
+                    task.queued = false;
+                    self.tasks.borrow_mut().insert(id, task);
+                }
+                return true;
+            }
+        }
+    }
+
+    /// Blocks the thread until a task is woken, or a sleep is over
+    pub fn wait(&self) {
+        while self.woken.ids.lock().unwrap().is_empty() {
+            match self.timers.fire(Instant::now()) {
+                Some(deadline) => {
+                    thread::park_timeout(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => thread::park(),
+            }
+        }
+    }
+
+    /// Moves the tasks woken to the end of the queue, the ones not done and not in it
+    /// already
+    fn take_woken(&self) {
+        let ids = std::mem::take(&mut *self.woken.ids.lock().unwrap());
+        let mut tasks = self.tasks.borrow_mut();
+        let mut queue = self.queue.borrow_mut();
+        for id in ids {
+            if let Some(task) = tasks.get_mut(&id) {
+                if !task.queued {
+                    task.queued = true;
+                    queue.push_back(id);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_lite::future::yield_now;
+    use std::time::Duration;
+
+    #[test]
+    fn test_round_robin() {
+        let sched = Scheduler::new();
+        let order = Rc::new(RefCell::new(vec![]));
+        for g in 0..3 {
+            let order = order.clone();
+            sched.spawn(async move {
+                for turn in 0..2 {
+                    order.borrow_mut().push((g, turn));
+                    yield_now().await;
+                }
+            });
+        }
+        while sched.try_tick() {}
+        let expected: Vec<(usize, i32)> = (0..2)
+            .flat_map(|turn| (0..3).map(move |g| (g, turn)))
+            .collect();
+        assert_eq!(*order.borrow(), expected);
+        assert!(sched.tasks.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_cancel_and_wake() {
+        let sched = Scheduler::new();
+        let done = Rc::new(Cell::new(false));
+        let d = done.clone();
+        let (tx, rx) = std::sync::mpsc::channel::<Waker>();
+        sched.spawn(async move {
+            let mut sent = false;
+            std::future::poll_fn(|cx| {
+                if sent {
+                    return Poll::Ready(());
+                }
+                sent = true;
+                tx.send(cx.waker().clone()).unwrap();
+                Poll::Pending
+            })
+            .await;
+            d.set(true);
+        });
+        let cancelled = sched.spawn(std::future::pending());
+        sched.cancel(cancelled);
+        assert!(sched.try_tick());
+        // waiting, not runnable
+        assert!(!sched.try_tick());
+        let waker = rx.recv().unwrap();
+        thread::spawn(move || waker.wake()).join().unwrap();
+        sched.wait();
+        assert!(sched.try_tick());
+        assert!(done.get());
+        assert!(sched.tasks.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_sleep() {
+        let sched = Scheduler::new();
+        let order = Rc::new(RefCell::new(vec![]));
+        let start = Instant::now();
+        for (g, ms) in [(0, 30), (1, 10), (2, 20)] {
+            let order = order.clone();
+            let sleep = Sleep::new(
+                sched.timers().clone(),
+                start + Duration::from_millis(ms),
+                g == 2,
+            );
+            sched.spawn(async move {
+                sleep.await;
+                order.borrow_mut().push(g);
+            });
+        }
+        let hour = start + Duration::from_secs(3600);
+        let cancelled = sched.spawn(Sleep::new(sched.timers().clone(), hour, true));
+        while sched.try_tick() {}
+        assert_eq!(sched.timers().daemons(), 2);
+        // a sleep dropped before its deadline is forgotten
+        sched.cancel(cancelled);
+        assert_eq!(sched.timers().daemons(), 1);
+        while !sched.tasks.borrow().is_empty() {
+            sched.wait();
+            while sched.try_tick() {}
+        }
+        assert_eq!(*order.borrow(), [1, 2, 0]);
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert_eq!(sched.timers().daemons(), 0);
+        assert!(sched.timers().sleeps.borrow().is_empty());
+    }
+}
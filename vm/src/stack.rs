@@ -6,6 +6,28 @@ use crate::value::*;
 
 const DEFAULT_CAPACITY: usize = 256;
 
+const DEFAULT_GOROUTINE_CAPACITY: usize = 32;
+
+/// The initial sizes of the stacks of the goroutines, in number of slots. A stack
+/// doubles its size when it runs out of room, so small stacks are cheap to start
+/// with, which matters when tens of thousands of goroutines are spawned.
+#[derive(Clone, Copy, Debug)]
+pub struct StackSize {
+    /// the stack of the main goroutine
+    pub main: usize,
+    /// the stack of every goroutine spawned with a `go` statement
+    pub goroutine: usize,
+}
+
+impl Default for StackSize {
+    fn default() -> Self {
+        StackSize {
+            main: DEFAULT_CAPACITY,
+            goroutine: DEFAULT_GOROUTINE_CAPACITY,
+        }
+    }
+}
+
 pub struct Stack {
     vec: Vec<GosValue>,
 }
@@ -13,18 +35,54 @@ pub struct Stack {
 impl Stack {
     #[inline]
     pub fn new() -> Stack {
+        Stack::with_size(DEFAULT_CAPACITY)
+    }
+
+    #[inline]
+    pub fn with_size(size: usize) -> Stack {
         Stack {
-            vec: vec![GosValue::new_nil(ValueType::Void); DEFAULT_CAPACITY],
+            vec: vec![GosValue::new_nil(ValueType::Void); size],
         }
     }
 
     #[inline]
-    pub fn with_vec(v: Vec<GosValue>) -> Stack {
+    pub fn with_vec(v: Vec<GosValue>, size: usize) -> Stack {
         let mut s = Stack { vec: v };
-        s.set_min_size(DEFAULT_CAPACITY);
+        s.set_min_size(size);
         s
     }
 
+    /// Like `with_vec`, but reuses a cleared stack
+    #[inline]
+    #[cfg(feature = "async")]
+    pub(crate) fn reuse(mut self, v: Vec<GosValue>, size: usize) -> Stack {
+        self.set_vec(0, v);
+        self.set_min_size(size);
+        self
+    }
+
+    /// Drops all the values, keeping the memory for the stack to be reused
+    #[cfg(feature = "async")]
+    pub(crate) fn clear(&mut self) {
+        self.vec.clear()
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn capacity(&self) -> usize {
+        self.vec.capacity()
+    }
+
+    /// The number of slots, the stack is never shrunk
+    pub(crate) fn len(&self) -> usize {
+        self.vec.len()
+    }
+
+    /// The values from `begin` to `end`, or to the top if the stack is shorter
+    pub(crate) fn slice(&self, begin: OpIndex, end: OpIndex) -> &[GosValue] {
+        let end = (end as usize).min(self.vec.len());
+        &self.vec[(begin as usize).min(end)..end]
+    }
+
     #[inline]
     pub fn get(&self, index: OpIndex) -> &GosValue {
         unsafe { self.vec.get_unchecked(index as usize) }
@@ -84,6 +142,8 @@ impl Stack {
     #[inline]
     pub fn set_min_size(&mut self, size: usize) {
         if size > self.vec.len() {
+            // grow geometrically, for deep recursions not to resize on every call
+            let size = size.max(self.vec.len() * 2);
             self.vec.resize(size, GosValue::new_nil(ValueType::Void))
         }
     }
@@ -144,9 +204,12 @@ impl std::fmt::Debug for Stack {
 
 /// store iterators for Opcode::RANGE
 pub struct RangeStack {
-    maps: Vec<GosMapIter<'static>>,
-    slices: Vec<SliceEnumIter<'static, AnyElem>>,
-    strings: Vec<StringEnumIter<'static>>,
+    // None for nil maps, which have nothing to range over
+    maps: Vec<Option<MapObjIter>>,
+    // the iters are held with the values they iterate over, which may otherwise be
+    // dropped during the ranging, e.g. when the value is in a temporary register
+    slices: Vec<(SliceEnumIter<'static, AnyElem>, GosValue)>,
+    strings: Vec<(StringIter<'static>, GosValue)>,
 }
 
 impl RangeStack {
@@ -165,6 +228,8 @@ impl RangeStack {
     /// But it's not rust-safe just go-safe. because the Ref is dropped inside the transmute.
     /// that means if you write to the container we are ranging, it'll not be stopped by
     /// the borrow checker. Which is not safe to Rust, but it's exactly what Go does.
+    /// Maps are the exception, as inserting may reallocate the storage, they are ranged
+    /// over with MapObjIter which allows the map to be modified.
     pub(crate) fn range_init(
         &mut self,
         target: &GosValue,
@@ -173,18 +238,20 @@ impl RangeStack {
     ) -> RuntimeResult<()> {
         match typ {
             ValueType::Map => {
-                let map = target.as_non_nil_map()?.0.borrow_data();
-                let iter = unsafe { std::mem::transmute(map.iter()) };
+                let iter = target.as_map().map(|_| MapObjIter::new(target.clone()));
                 self.maps.push(iter);
             }
             ValueType::Array | ValueType::Slice => {
                 let iter = arr_caller.array_slice_iter(&target)?;
-                self.slices.push(iter);
+                self.slices.push((iter, target.clone()));
             }
             ValueType::String => {
-                let iter =
-                    unsafe { std::mem::transmute(target.as_string().as_str().chars().enumerate()) };
-                self.strings.push(iter);
+                let iter = unsafe {
+                    std::mem::transmute::<StringIter, StringIter<'static>>(StringIter::new(
+                        &target.as_string().as_bytes(),
+                    ))
+                };
+                self.strings.push((iter, target.clone()));
             }
             _ => unreachable!(),
         }
@@ -200,10 +267,16 @@ impl RangeStack {
         index_val: OpIndex,
     ) -> bool {
         match typ {
-            ValueType::Map => match self.maps.last_mut().unwrap().next() {
+            ValueType::Map => match self
+                .maps
+                .last_mut()
+                .unwrap()
+                .as_mut()
+                .and_then(|x| x.next())
+            {
                 Some((k, v)) => {
-                    stack.set(index_key, k.clone());
-                    stack.set(index_val, v.clone());
+                    stack.set(index_key, k);
+                    stack.set(index_val, v);
                     false
                 }
                 None => {
@@ -212,7 +285,7 @@ impl RangeStack {
                 }
             },
             ValueType::Array | ValueType::Slice => {
-                match arr_caller.array_slice_next(self.slices.last_mut().unwrap()) {
+                match arr_caller.array_slice_next(&mut self.slices.last_mut().unwrap().0) {
                     Some((k, v)) => {
                         stack.set(index_key, (k as isize).into());
                         stack.set(index_val, v);
@@ -224,10 +297,10 @@ impl RangeStack {
                     }
                 }
             }
-            ValueType::String => match self.strings.last_mut().unwrap().next() {
+            ValueType::String => match self.strings.last_mut().unwrap().0.next() {
                 Some((k, v)) => {
                     stack.set(index_key, (k as isize).into());
-                    stack.set(index_val, (v as isize).into());
+                    stack.set(index_val, (v as i32).into());
                     false
                 }
                 None => {
@@ -0,0 +1,97 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Running the VM one instruction at a time, for testing what the code generator
+//! emits without writing a program whose output shows it.
+
+use crate::ffi::FfiFactory;
+use crate::value::*;
+use crate::vm::{run_with, RunError, RunOptions};
+use std::cell::{Cell, RefCell};
+
+/// The state of the VM right before it executes an instruction
+pub struct Step<'a> {
+    /// The id of the goroutine running the instruction, the main goroutine is 0
+    pub goroutine: usize,
+    /// The number of frames on the call stack of the goroutine
+    pub depth: usize,
+    pub func: FunctionKey,
+    /// The index of the instruction in the code of `func`
+    pub pc: OpIndex,
+    pub inst: &'a Instruction,
+    /// The stack slots of the current call: the results, the parameters, the
+    /// locals and the temporaries, in that order
+    pub frame: &'a [GosValue],
+    pub objects: &'a VMObjects,
+}
+
+impl<'a> Step<'a> {
+    #[inline]
+    pub fn op(&self) -> Opcode {
+        self.inst.op0
+    }
+
+    #[inline]
+    pub fn func_name(&self) -> &'a FuncName {
+        &self.objects.functions[self.func].name
+    }
+
+    /// Returns the stack slot `i` of the current call, None if the function
+    /// doesn't use it
+    #[inline]
+    pub fn slot(&self, i: OpIndex) -> Option<&'a GosValue> {
+        self.frame.get(i as usize)
+    }
+}
+
+/// Runs a program calling back before every instruction, the goroutines are switched
+/// after every instruction.
+pub struct StepVm<'a> {
+    code: &'a Bytecode,
+    ffi: &'a FfiFactory,
+    opts: RunOptions,
+}
+
+impl<'a> StepVm<'a> {
+    pub fn new(code: &'a Bytecode, ffi: &'a FfiFactory) -> StepVm<'a> {
+        StepVm::with_options(code, ffi, RunOptions::default())
+    }
+
+    pub fn with_options(code: &'a Bytecode, ffi: &'a FfiFactory, opts: RunOptions) -> StepVm<'a> {
+        StepVm { code, ffi, opts }
+    }
+
+    /// Runs the program to the end, or until `f` returns false, in which case all the
+    /// goroutines are stopped without running any more instructions.
+    pub fn run<F: FnMut(&Step) -> bool>(&self, f: F) -> Result<(), RunError> {
+        let f = RefCell::new(f);
+        let call = |step: &Step| (f.borrow_mut())(step);
+        let stepper = Stepper::new(&call);
+        run_with(self.code, self.ffi, None, &self.opts, Some(&stepper))
+    }
+}
+
+pub(crate) struct Stepper<'a> {
+    f: &'a dyn Fn(&Step) -> bool,
+    stopped: Cell<bool>,
+}
+
+impl<'a> Stepper<'a> {
+    fn new(f: &'a dyn Fn(&Step) -> bool) -> Stepper<'a> {
+        Stepper {
+            f,
+            stopped: Cell::new(false),
+        }
+    }
+
+    /// Returns false if the run is to be stopped
+    pub(crate) fn step(&self, step: &Step) -> bool {
+        if self.stopped.get() {
+            return false;
+        }
+        let go_on = (self.f)(step);
+        self.stopped.set(!go_on);
+        go_on
+    }
+}
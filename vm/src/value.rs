@@ -8,10 +8,12 @@ pub use crate::bytecode::*;
 #[cfg(feature = "async")]
 use crate::channel::Channel;
 pub(crate) use crate::dispatcher::*;
-use crate::gc::GcContainer;
+use crate::ftoa::{format_complex, format_float};
+use crate::gc::{self, GcContainer, SharedInner};
 pub use crate::instruction::*;
 pub use crate::metadata::*;
 pub use crate::objects::*;
+pub use crate::pos_table::PosTable;
 #[cfg(feature = "serde_borsh")]
 use borsh::{
     maybestd::io::Result as BorshResult, maybestd::io::Write as BorshWrite, BorshDeserialize,
@@ -20,8 +22,9 @@ use borsh::{
 #[cfg(feature = "serde_borsh")]
 use std::io::{Error, ErrorKind};
 
+use go_parser::Map;
 use ordered_float;
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::cmp::Ordering;
 use std::collections::VecDeque;
 use std::convert::From;
@@ -29,24 +32,38 @@ use std::fmt::{self, Debug, Display, Write};
 use std::hash::{Hash, Hasher};
 use std::num::Wrapping;
 use std::ptr;
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 use std::result;
 
 pub type F32 = ordered_float::OrderedFloat<f32>;
 pub type F64 = ordered_float::OrderedFloat<f64>;
 pub type IRC = i32;
 pub type RCount = Cell<IRC>;
-pub type RCQueue = VecDeque<IRC>;
+/// The objects found alive by a collection, with the counts they had, for the ones
+/// not collected to be told apart by the address of the counts
+pub type RCQueue = VecDeque<(IRC, *const RCount)>;
 
 #[inline]
 pub(crate) fn rcount_mark_and_queue(rc: &RCount, queue: &mut RCQueue) {
     let i = rc.get();
     if i <= 0 {
-        queue.push_back(i);
+        queue.push_back((i, rc));
         rc.set(1);
     }
 }
 
+/// The number of values sharing the object at `p`, made by `Rc::into_raw`
+unsafe fn shared_count<T>(p: *const T) -> usize {
+    let rc = std::mem::ManuallyDrop::new(Rc::from_raw(p));
+    Rc::strong_count(&rc)
+}
+
+/// A weak reference to the object at `p`, made by `Rc::into_raw`
+unsafe fn weak_ref<T>(p: *const T) -> Weak<T> {
+    let rc = std::mem::ManuallyDrop::new(Rc::from_raw(p));
+    Rc::downgrade(&rc)
+}
+
 fn ref_ptr_eq<T>(x: Option<&T>, y: Option<&T>) -> bool {
     match (x, y) {
         (Some(a), Some(b)) => a as *const T == b as *const T,
@@ -91,6 +108,14 @@ macro_rules! union_cmp {
     };
 }
 
+// Floats are stored as OrderedFloat, which orders NaN and makes it equal to
+// itself, compare the raw floats instead like Go does.
+macro_rules! union_cmp_float {
+    ($a:ident, $b:ident, $name:tt, $op:tt) => {
+        $a.$name.0 $op $b.$name.0
+    };
+}
+
 macro_rules! binary_op_int_float_str {
     ($t:ident, $a:ident, $b:ident, $op:tt) => {
         match $t {
@@ -147,8 +172,8 @@ macro_rules! cmp_bool_int_float {
             ValueType::Uint16 => union_cmp!($a, $b, uint16, $op),
             ValueType::Uint32 => union_cmp!($a, $b, uint32, $op),
             ValueType::Uint64 => union_cmp!($a, $b, uint64, $op),
-            ValueType::Float32 => union_cmp!($a, $b, float32, $op),
-            ValueType::Float64 => union_cmp!($a, $b, float64, $op),
+            ValueType::Float32 => union_cmp_float!($a, $b, float32, $op),
+            ValueType::Float64 => union_cmp_float!($a, $b, float64, $op),
             _ => unreachable!(),
         }
     };
@@ -168,8 +193,8 @@ macro_rules! cmp_int_float {
             ValueType::Uint16 => union_cmp!($a, $b, uint16, $op),
             ValueType::Uint32 => union_cmp!($a, $b, uint32, $op),
             ValueType::Uint64 => union_cmp!($a, $b, uint64, $op),
-            ValueType::Float32 => union_cmp!($a, $b, float32, $op),
-            ValueType::Float64 => union_cmp!($a, $b, float64, $op),
+            ValueType::Float32 => union_cmp_float!($a, $b, float32, $op),
+            ValueType::Float64 => union_cmp_float!($a, $b, float64, $op),
             _ => unreachable!(),
         }
     };
@@ -209,14 +234,40 @@ macro_rules! convert_to_int {
                 ValueType::Int16 => $val.$d_type = $val.int16 as $typ,
                 ValueType::Int32 => $val.$d_type = $val.int32 as $typ,
                 ValueType::Int64 => $val.$d_type = $val.int64 as $typ,
-                ValueType::Float32 => $val.$d_type = f32::from($val.float32) as $typ,
-                ValueType::Float64 => $val.$d_type = f64::from($val.float64) as $typ,
+                ValueType::Float32 => {
+                    $val.$d_type = float_to_int!(f64::from(f32::from($val.float32)), $typ)
+                }
+                ValueType::Float64 => $val.$d_type = float_to_int!(f64::from($val.float64), $typ),
                 _ => unreachable!(),
             }
         }
     }};
 }
 
+// Go leaves the conversions of floats out of the range of the integer type to the
+// implementation. They truncate toward zero and wrap around like the integers do,
+// so int8(300.0) is 44 and uint8(-1.0) is 255, which is what gc does on amd64. The
+// floats out of the range of int64, or uint64 for the unsigned types, saturate for
+// the 64-bit types and give 0 for the narrower ones, so int32(1<<63) is 0. NaN
+// converts to 0.
+macro_rules! float_to_int {
+    ($f:expr, $typ:tt) => {{
+        let f: f64 = $f;
+        let narrow = std::mem::size_of::<$typ>() < 8;
+        if $typ::MIN == 0 && f >= 0.0 {
+            if narrow && f >= 18446744073709551616.0 {
+                0
+            } else {
+                f as u64 as $typ
+            }
+        } else if narrow && !(-9223372036854775808.0..9223372036854775808.0).contains(&f) {
+            0
+        } else {
+            f as i64 as $typ
+        }
+    }};
+}
+
 macro_rules! convert_to_float {
     ($val:expr, $vt:expr, $d_type:tt, $f_type:tt, $typ:tt) => {{
         unsafe {
@@ -735,18 +786,19 @@ impl ValueData {
     pub(crate) fn inc(&self, t: ValueType) -> ValueData {
         let mut v = unsafe { self.copy_non_ptr() };
         match t {
-            ValueType::Int => v.int = unsafe { self.int } + 1,
-            ValueType::Int8 => v.int8 = unsafe { self.int8 } + 1,
-            ValueType::Int16 => v.int16 = unsafe { self.int16 } + 1,
-            ValueType::Int32 => v.int32 = unsafe { self.int32 } + 1,
-            ValueType::Int64 => v.int64 = unsafe { self.int64 } + 1,
+            ValueType::Int => v.int = unsafe { self.int }.wrapping_add(1),
+            ValueType::Int8 => v.int8 = unsafe { self.int8 }.wrapping_add(1),
+            ValueType::Int16 => v.int16 = unsafe { self.int16 }.wrapping_add(1),
+            ValueType::Int32 => v.int32 = unsafe { self.int32 }.wrapping_add(1),
+            ValueType::Int64 => v.int64 = unsafe { self.int64 }.wrapping_add(1),
             ValueType::Float32 => v.float32 = unsafe { self.float32 } + 1.0,
             ValueType::Float64 => v.float64 = unsafe { self.float64 } + 1.0,
-            ValueType::Uint => v.uint = unsafe { self.uint } + 1,
-            ValueType::Uint8 => v.uint8 = unsafe { self.uint8 } + 1,
-            ValueType::Uint16 => v.uint16 = unsafe { self.uint16 } + 1,
-            ValueType::Uint32 => v.uint32 = unsafe { self.uint32 } + 1,
-            ValueType::Uint64 => v.uint64 = unsafe { self.uint64 } + 1,
+            ValueType::Uint => v.uint = unsafe { self.uint }.wrapping_add(1),
+            ValueType::Uint8 => v.uint8 = unsafe { self.uint8 }.wrapping_add(1),
+            ValueType::Uint16 => v.uint16 = unsafe { self.uint16 }.wrapping_add(1),
+            ValueType::Uint32 => v.uint32 = unsafe { self.uint32 }.wrapping_add(1),
+            ValueType::Uint64 => v.uint64 = unsafe { self.uint64 }.wrapping_add(1),
+            ValueType::UintPtr => v.uint_ptr = unsafe { self.uint_ptr }.wrapping_add(1),
             _ => unreachable!(),
         };
         v
@@ -756,18 +808,19 @@ impl ValueData {
     pub(crate) fn dec(&self, t: ValueType) -> ValueData {
         let mut v = unsafe { self.copy_non_ptr() };
         match t {
-            ValueType::Int => v.int = unsafe { self.int } - 1,
-            ValueType::Int8 => v.int8 = unsafe { self.int8 } - 1,
-            ValueType::Int16 => v.int16 = unsafe { self.int16 } - 1,
-            ValueType::Int32 => v.int32 = unsafe { self.int32 } - 1,
-            ValueType::Int64 => v.int64 = unsafe { self.int64 } - 1,
+            ValueType::Int => v.int = unsafe { self.int }.wrapping_sub(1),
+            ValueType::Int8 => v.int8 = unsafe { self.int8 }.wrapping_sub(1),
+            ValueType::Int16 => v.int16 = unsafe { self.int16 }.wrapping_sub(1),
+            ValueType::Int32 => v.int32 = unsafe { self.int32 }.wrapping_sub(1),
+            ValueType::Int64 => v.int64 = unsafe { self.int64 }.wrapping_sub(1),
             ValueType::Float32 => v.float32 = unsafe { self.float32 } - 1.0,
             ValueType::Float64 => v.float64 = unsafe { self.float64 } - 1.0,
-            ValueType::Uint => v.uint = unsafe { self.uint } - 1,
-            ValueType::Uint8 => v.uint8 = unsafe { self.uint8 } - 1,
-            ValueType::Uint16 => v.uint16 = unsafe { self.uint16 } - 1,
-            ValueType::Uint32 => v.uint32 = unsafe { self.uint32 } - 1,
-            ValueType::Uint64 => v.uint64 = unsafe { self.uint64 } - 1,
+            ValueType::Uint => v.uint = unsafe { self.uint }.wrapping_sub(1),
+            ValueType::Uint8 => v.uint8 = unsafe { self.uint8 }.wrapping_sub(1),
+            ValueType::Uint16 => v.uint16 = unsafe { self.uint16 }.wrapping_sub(1),
+            ValueType::Uint32 => v.uint32 = unsafe { self.uint32 }.wrapping_sub(1),
+            ValueType::Uint64 => v.uint64 = unsafe { self.uint64 }.wrapping_sub(1),
+            ValueType::UintPtr => v.uint_ptr = unsafe { self.uint_ptr }.wrapping_sub(1),
             _ => unreachable!(),
         };
         v
@@ -1015,11 +1068,14 @@ impl ValueData {
     #[inline]
     fn new_array<T>(arr: ArrayObj<T>, gcc: &GcContainer) -> ValueData
     where
-        T: Element,
+        T: Element + 'static,
     {
+        gcc.charge_new(arr.borrow_data().capacity() * std::mem::size_of::<T>());
         let rc = Rc::new((arr, Cell::new(0)));
         if T::need_gc() {
             gcc.add_array(&ValueData::from_array(rc.clone()).into_array::<GosElem>());
+        } else {
+            gcc.add_sized(&rc);
         }
         ValueData::from_array(rc)
     }
@@ -1036,6 +1092,8 @@ impl ValueData {
 
     #[inline]
     fn new_struct(obj: StructObj, gcc: &GcContainer) -> ValueData {
+        let fields = obj.borrow_fields().len();
+        gcc.charge_new(std::mem::size_of::<StructObj>() + fields * std::mem::size_of::<GosValue>());
         let s = Rc::new((obj, Cell::new(0)));
         gcc.add_struct(&s);
         ValueData::from_struct(s)
@@ -1053,6 +1111,7 @@ impl ValueData {
 
     #[inline]
     fn new_closure(obj: ClosureObj, gcc: &GcContainer) -> ValueData {
+        gcc.charge_new(std::mem::size_of::<ClosureObj>());
         let cls = Rc::new((obj, Cell::new(0)));
         gcc.add_closure(&cls);
         ValueData::from_closure(Some(cls))
@@ -1076,6 +1135,7 @@ impl ValueData {
 
     #[inline]
     fn new_map(obj: MapObj, gcc: &GcContainer) -> ValueData {
+        gcc.charge_new(std::mem::size_of::<MapObj>());
         let m = Rc::new((obj, Cell::new(0)));
         gcc.add_map(&m);
         ValueData::from_map(Some(m))
@@ -1218,20 +1278,29 @@ impl ValueData {
     #[inline]
     fn copy_semantic(&self, t: ValueType, t_elem: ValueType, gcc: &GcContainer) -> ValueData {
         match t {
-            _ if t != ValueType::Array
-                && t != ValueType::Struct
-                && t != ValueType::Slice
-                && t != ValueType::Map =>
-            {
+            // a map is a reference, copying it shares the underlying map
+            _ if t != ValueType::Array && t != ValueType::Struct && t != ValueType::Slice => {
                 self.clone(t)
             }
-            ValueType::Array => ArrCaller::get_slow(t_elem).array_copy_semantic(self, gcc),
-            ValueType::Struct => ValueData::new_struct(StructObj::clone(&self.as_struct().0), gcc),
-            ValueType::Slice => ArrCaller::get_slow(t_elem).slice_copy_semantic(self),
-            ValueType::Map => match self.as_map() {
-                Some(m) => ValueData::new_map(m.0.clone(), gcc),
-                None => ValueData::new_nil(t),
+            // the struct and array elements and fields are values too, copied as well
+            ValueType::Array => match t_elem {
+                ValueType::Struct | ValueType::Array => {
+                    let arr = &self.as_array::<GosElem>().0;
+                    let data = arr
+                        .borrow_data()
+                        .iter()
+                        .map(|x| x.borrow().copy_semantic(gcc))
+                        .collect();
+                    ValueData::new_array(ArrayObj::<GosElem>::with_data(data), gcc)
+                }
+                _ => ArrCaller::get_slow(t_elem).array_copy_semantic(self, gcc),
             },
+            ValueType::Struct => {
+                let fields = self.as_struct().0.borrow_fields();
+                let fields = fields.iter().map(|x| x.copy_semantic(gcc)).collect();
+                ValueData::new_struct(StructObj::new(fields), gcc)
+            }
+            ValueType::Slice => ArrCaller::get_slow(t_elem).slice_copy_semantic(self),
             _ => unreachable!(),
         }
     }
@@ -1431,7 +1500,7 @@ impl GosValue {
     #[inline]
     pub(crate) fn new_array<T>(obj: ArrayObj<T>, t_elem: ValueType, gcc: &GcContainer) -> GosValue
     where
-        T: Element,
+        T: Element + 'static,
     {
         let data = ValueData::new_array(obj, gcc);
         GosValue::with_elem_type(ValueType::Array, t_elem, data)
@@ -1535,7 +1604,9 @@ impl GosValue {
         caller: &Box<dyn Dispatcher>,
         gcc: &GcContainer,
     ) -> GosValue {
-        let arr = GosValue::array_with_size(size, cap, val, caller, gcc);
+        // the elements past the length are made too, for the slice to have the
+        // capacity and to be resliced up to it
+        let arr = GosValue::array_with_size(cap, cap, val, caller, gcc);
         GosValue::slice_array(arr, 0, size as isize, caller).unwrap()
     }
 
@@ -1568,8 +1639,12 @@ impl GosValue {
 
     #[cfg(feature = "async")]
     #[inline]
-    pub(crate) fn channel_with_chan(chan: Channel, recv_zero: GosValue) -> GosValue {
-        GosValue::new_channel(ChannelObj::with_chan(chan, recv_zero))
+    pub(crate) fn channel_with_chan(
+        chan: Channel,
+        elem_meta: Meta,
+        elem_type: ValueType,
+    ) -> GosValue {
+        GosValue::new_channel(ChannelObj::with_chan(chan, elem_meta, elem_type))
     }
 
     #[inline]
@@ -1597,8 +1672,8 @@ impl GosValue {
     }
 
     #[inline]
-    pub(crate) fn from_slice<T>(s: OptionRc<(SliceObj<T>, RCount)>) -> GosValue {
-        GosValue::new(ValueType::Slice, ValueData::from_slice(s))
+    pub(crate) fn from_slice<T>(s: OptionRc<(SliceObj<T>, RCount)>, t_elem: ValueType) -> GosValue {
+        GosValue::with_elem_type(ValueType::Slice, t_elem, ValueData::from_slice(s))
     }
 
     #[inline]
@@ -2037,6 +2112,83 @@ impl GosValue {
         Ok(iface.underlying_value().map(|x| x.clone()))
     }
 
+    /// Slices, maps and funcs can't be map keys, but they can be held by interfaces
+    /// that are, hashing them is a runtime error like in Go.
+    pub fn check_hashable(&self) -> RuntimeResult<()> {
+        let unhashable = |kind: &str| -> RuntimeResult<()> {
+            Err(format!("runtime error: hash of unhashable type {}", kind).into())
+        };
+        match self.typ {
+            ValueType::Slice => unhashable("slice"),
+            ValueType::Map => unhashable("map"),
+            ValueType::Closure => unhashable("func"),
+            ValueType::Interface => match self.as_interface() {
+                Some(InterfaceObj::Gos(v, _)) => v.check_hashable(),
+                _ => Ok(()),
+            },
+            ValueType::Struct => self
+                .as_struct()
+                .0
+                .borrow_fields()
+                .iter()
+                .try_for_each(|f| f.check_hashable()),
+            ValueType::Array => match self.t_elem {
+                ValueType::Interface | ValueType::Struct | ValueType::Array => {
+                    let arr = &self.as_array::<GosElem>().0;
+                    (0..arr.len()).try_for_each(|i| arr.get(i, self.t_elem)?.check_hashable())
+                }
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Assigns `val` to a struct or an array by overwriting its fields or elements in
+    /// place, for the pointers to them to see the new value, like a variable's storage
+    /// is reused in Go. Returns false for the other types, which are simply replaced.
+    pub(crate) fn overwrite(&self, val: &GosValue, caller: &ArrCaller) -> RuntimeResult<bool> {
+        match self.typ {
+            ValueType::Struct | ValueType::Array if self.as_addr() == val.as_addr() => Ok(true),
+            ValueType::Struct => {
+                let src = val.as_struct().0.try_borrow_fields()?.clone();
+                let mut fields = self.as_struct().0.try_borrow_fields_mut()?;
+                for (field, v) in fields.iter_mut().zip(src) {
+                    if !field.overwrite(&v, caller)? {
+                        *field = v;
+                    }
+                }
+                Ok(true)
+            }
+            ValueType::Array => {
+                let dispatcher = self.caller(caller);
+                for i in 0..dispatcher.array_len(self) {
+                    self.overwrite_element(&dispatcher.array_get(val, i)?, i, caller)?;
+                }
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Sets the element `i` of an array, overwriting it in place if it's a struct or
+    /// an array, see `overwrite`.
+    pub(crate) fn overwrite_element(
+        &self,
+        val: &GosValue,
+        i: usize,
+        caller: &ArrCaller,
+    ) -> RuntimeResult<()> {
+        let dispatcher = self.caller(caller);
+        match self.t_elem {
+            ValueType::Struct | ValueType::Array
+                if dispatcher.array_get(self, i)?.overwrite(val, caller)? =>
+            {
+                Ok(())
+            }
+            _ => dispatcher.array_set(self, val, i),
+        }
+    }
+
     #[inline]
     pub fn slice_string(
         s: &GosValue,
@@ -2088,18 +2240,29 @@ impl GosValue {
                 self.as_pointer().map(|p| p.ref_sub_one());
             }
             ValueType::UnsafePtr => {
-                self.as_unsafe_ptr().map(|p| p.ptr().ref_sub_one());
+                if let Some(p) = self.as_unsafe_ptr() {
+                    let inner = SharedInner::Unsafe(p.downgrade());
+                    gc::ref_sub_one_shared(p.as_rust_ptr() as *const (), p.shared_count(), inner)
+                }
             }
             ValueType::Interface => {
-                self.as_interface().map(|x| x.ref_sub_one());
+                if let Some(InterfaceObj::Gos(..)) = self.as_interface() {
+                    let strong = unsafe { shared_count(self.data.interface) };
+                    let inner = SharedInner::Interface(unsafe { weak_ref(self.data.interface) });
+                    gc::ref_sub_one_shared(self.data.as_addr() as *const (), strong, inner);
+                }
+            }
+            ValueType::Array if self.is_gc_array() => {
+                self.as_gos_array().1.set(self.as_gos_array().1.get() - 1)
             }
-            ValueType::Array => self.as_gos_array().1.set(self.as_gos_array().1.get() - 1),
             ValueType::Struct => self.as_struct().1.set(self.as_struct().1.get() - 1),
             ValueType::Closure => {
                 self.as_closure().map(|x| x.1.set(x.1.get() - 1));
             }
-            ValueType::Slice => {
-                self.as_gos_slice().map(|x| x.0.array().ref_sub_one());
+            ValueType::Slice if self.as_gos_slice().is_some() => {
+                let strong = unsafe { shared_count(self.data.slice) };
+                let inner = SharedInner::Slice(unsafe { weak_ref(self.data.slice) });
+                gc::ref_sub_one_shared(self.data.as_addr() as *const (), strong, inner)
             }
             ValueType::Map => {
                 self.as_map().map(|x| x.1.set(x.1.get() - 1));
@@ -2108,10 +2271,19 @@ impl GosValue {
         };
     }
 
+    /// for gc, the arrays of numbers are not tracked by the collector, their counts
+    /// are not to be touched
+    #[inline]
+    fn is_gc_array(&self) -> bool {
+        matches!(ArrCaller::get_elem_type(self.t_elem), ElemType::ElemTypeGos)
+    }
+
     /// for gc
     pub(crate) fn mark_dirty(&self, queue: &mut RCQueue) {
         match &self.typ {
-            ValueType::Array => rcount_mark_and_queue(&self.as_gos_array().1, queue),
+            ValueType::Array if self.is_gc_array() => {
+                rcount_mark_and_queue(&self.as_gos_array().1, queue)
+            }
             ValueType::Pointer => {
                 self.as_pointer().map(|x| x.mark_dirty(queue));
             }
@@ -2146,6 +2318,12 @@ impl GosValue {
         self.data.rc(self.typ).unwrap().set(rc)
     }
 
+    /// for gc, whether `rc` is the count of this object
+    #[inline]
+    pub(crate) fn has_rc(&self, rc: *const RCount) -> bool {
+        std::ptr::eq(self.data.rc(self.typ).unwrap(), rc)
+    }
+
     #[inline]
     pub(crate) fn drop_as_copyable(self) {
         debug_assert!(self.copyable());
@@ -2154,6 +2332,10 @@ impl GosValue {
 
     #[inline]
     pub(crate) fn cast(&self, new_type: ValueType) -> GosValue {
+        // a string is never nil, a nil []byte is an empty one
+        if new_type == ValueType::String && self.typ == ValueType::Slice && self.is_nil() {
+            return GosValue::with_str("");
+        }
         GosValue::new(new_type, self.data.clone(self.typ))
     }
 
@@ -2227,7 +2409,7 @@ impl PartialEq for GosValue {
                 x.r == y.r && x.i == y.i
             }
             (ValueType::String, ValueType::String) => {
-                *self.as_string().as_str() == *b.as_string().as_str()
+                self.as_string().compare(b.as_string()) == Ordering::Equal
             }
             (ValueType::Array, ValueType::Array) => {
                 self.caller_slow().array_eq(self.data(), b.data())
@@ -2305,6 +2487,7 @@ impl Hash for GosValue {
                 Some(iface) => iface.hash(state),
                 None => 0.hash(state),
             },
+            ValueType::UnsafePtr | ValueType::Channel => self.as_addr().hash(state),
             _ => {
                 dbg!(self.typ);
                 unreachable!();
@@ -2339,21 +2522,19 @@ impl Ord for GosValue {
             (ValueType::Float64, ValueType::Float64) => self.as_float64().cmp(b.as_float64()),
             (ValueType::Complex128, ValueType::Complex128) => {
                 let left = self.as_complex128();
-                let right = self.as_complex128();
+                let right = b.as_complex128();
                 left.r.cmp(&right.r).then(left.i.cmp(&right.i))
             }
             (ValueType::Function, ValueType::Function) => self.as_uint64().cmp(b.as_uint64()),
             (ValueType::Package, ValueType::Package) => self.as_uint64().cmp(b.as_uint64()),
             (ValueType::Metadata, ValueType::Metadata) => self.as_metadata().cmp(b.as_metadata()),
-            (ValueType::String, ValueType::String) => {
-                self.as_string().as_str().cmp(&b.as_string().as_str())
-            }
+            (ValueType::String, ValueType::String) => self.as_string().compare(b.as_string()),
             (ValueType::Array, ValueType::Array) => {
                 self.caller_slow().array_cmp(self.data(), b.data())
             }
             (ValueType::Complex64, ValueType::Complex64) => {
                 let left = self.as_complex64();
-                let right = self.as_complex64();
+                let right = b.as_complex64();
                 left.r.cmp(&right.r).then(left.i.cmp(&right.i))
             }
             (ValueType::Struct, ValueType::Struct) => self.as_struct().0.cmp(&b.as_struct().0),
@@ -2371,6 +2552,8 @@ impl Ord for GosValue {
                     (None, Some(_)) => Ordering::Less,
                 }
             }
+            #[cfg(feature = "async")]
+            (ValueType::Channel, ValueType::Channel) => self.as_addr().cmp(&b.as_addr()),
             _ => {
                 dbg!(self.typ(), b.typ());
                 unreachable!()
@@ -2711,6 +2894,15 @@ impl BorshDeserialize for GosValue {
 
 impl Display for GosValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_go(f, true)
+    }
+}
+
+impl GosValue {
+    /// Formats the value like Go's `%v` does, `top` is false for the values inside
+    /// others, the pointers to structs, arrays, slices and maps are printed with a
+    /// '&' and what they point to only at the top, as addresses inside
+    pub(crate) fn fmt_go(&self, f: &mut fmt::Formatter<'_>, top: bool) -> fmt::Result {
         match self.typ {
             ValueType::Bool => write!(f, "{}", self.as_bool()),
             ValueType::Int => write!(f, "{}", self.as_int()),
@@ -2724,57 +2916,91 @@ impl Display for GosValue {
             ValueType::Uint16 => write!(f, "{}", self.as_uint16()),
             ValueType::Uint32 => write!(f, "{}", self.as_uint32()),
             ValueType::Uint64 => write!(f, "{}", self.as_uint64()),
-            ValueType::Float32 => write!(f, "{}", self.as_float32()),
-            ValueType::Float64 => write!(f, "{}", self.as_float64()),
+            ValueType::Float32 => {
+                f.write_str(&format_float(self.as_float32().0 as f64, b'g', -1, 32))
+            }
+            ValueType::Float64 => f.write_str(&format_float(self.as_float64().0, b'g', -1, 64)),
             ValueType::Complex64 => {
                 let c = self.as_complex64();
-                write!(f, "({}, {})", c.r, c.i)
+                f.write_str(&format_complex(c.r.0 as f64, c.i.0 as f64, 32))
             }
             ValueType::Function => f.write_str("<function>"),
             ValueType::Package => f.write_str("<package>"),
             ValueType::Metadata => f.write_str("<metadata>"),
             ValueType::Complex128 => {
                 let c = self.as_complex128();
-                write!(f, "({}, {})", c.r, c.i)
+                f.write_str(&format_complex(c.r.0, c.i.0, 64))
             }
-            ValueType::String => f.write_str(&self.as_string().as_str()),
+            ValueType::String => f.write_str(&self.as_string().to_lossy_string()),
             ValueType::Array => display_vec(&self.caller_slow().array_get_vec(self), f),
             ValueType::Struct => write!(f, "{}", self.as_struct().0),
             ValueType::Pointer => match self.as_pointer() {
-                Some(p) => std::fmt::Display::fmt(p, f),
-                None => f.write_str("<nil(pointer)>"),
+                Some(p) => match p.pointee().filter(|_| top) {
+                    Some(v) => {
+                        f.write_char('&')?;
+                        v.fmt_go(f, false)
+                    }
+                    None => std::fmt::Display::fmt(p, f),
+                },
+                None => f.write_str("<nil>"),
             },
             ValueType::UnsafePtr => match self.as_unsafe_ptr() {
                 Some(p) => std::fmt::Display::fmt(p, f),
-                None => f.write_str("<nil(unsafe pointer)>"),
+                None => f.write_str("<nil>"),
             },
             ValueType::Closure => match self.as_closure() {
-                Some(_) => f.write_str("<closure>"),
-                None => f.write_str("<nil(closure)>"),
+                Some(_) => fmt_addr(f, self.as_addr() as usize),
+                None => f.write_str("<nil>"),
             },
             ValueType::Slice => match self.caller_slow().slice_get_vec(self) {
                 Some(v) => display_vec(&v, f),
-                None => f.write_str("<nil(slice)>"),
+                None => f.write_str("[]"),
             },
             ValueType::Map => match self.as_map() {
                 Some(m) => write!(f, "{}", m.0),
-                None => f.write_str("<nil(map)>"),
+                None => f.write_str("map[]"),
             },
             ValueType::Interface => match self.as_interface() {
+                Some(InterfaceObj::Gos(v, _)) => v.fmt_go(f, top),
                 Some(i) => write!(f, "{}", i),
-                None => f.write_str("<nil(interface)>"),
+                None => f.write_str("<nil>"),
             },
             #[cfg(feature = "async")]
             ValueType::Channel => match self.as_channel() {
-                Some(_) => f.write_str("<channel>"),
-                None => f.write_str("<nil(channel)>"),
+                Some(_) => fmt_addr(f, self.as_addr() as usize),
+                None => f.write_str("<nil>"),
             },
-            ValueType::Void => f.write_str("<nil(untyped)>"),
+            ValueType::Void => f.write_str("<nil>"),
             _ => unreachable!(),
         }
     }
 }
 
+thread_local! {
+    /// The made up addresses handed out, by the real ones, when they are stable
+    static STABLE_ADDRS: RefCell<Option<Map<usize, usize>>> = const { RefCell::new(None) };
+}
+
+/// Makes the addresses printed on this thread stable or not, when they are, the
+/// pointers, funcs and channels are given made up addresses in the order they are
+/// first printed, the same on every run of a script printing the same things. The
+/// numbering starts over every time it's turned on.
+pub fn set_stable_addresses(stable: bool) {
+    STABLE_ADDRS.with(|addrs| *addrs.borrow_mut() = stable.then(Map::new))
+}
+
+/// Writes an address like Go prints one, e.g. 0xc000010000
+pub(crate) fn fmt_addr(f: &mut fmt::Formatter<'_>, addr: usize) -> fmt::Result {
+    let addr = STABLE_ADDRS.with(|addrs| match addrs.borrow_mut().as_mut() {
+        Some(addrs) => {
+            let n = addrs.len();
+            *addrs.entry(addr).or_insert(0xc000010000 + n * 0x10)
+        }
+        None => addr,
+    });
+    write!(f, "{:#x}", addr)
+}
+
 impl fmt::Debug for GosValue {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let t = self.typ();
@@ -2838,7 +3064,7 @@ fn display_vec(vec: &Vec<GosValue>, f: &mut fmt::Formatter) -> fmt::Result {
         if i > 0 {
             f.write_char(' ')?;
         }
-        write!(f, "{}", v)?
+        v.fmt_go(f, false)?
     }
     f.write_char(']')
 }
@@ -3047,7 +3273,7 @@ impl From<String> for GosValue {
 impl AsPrimitive<String> for GosValue {
     #[inline]
     fn as_(&self) -> String {
-        self.as_string().as_str().to_owned()
+        self.as_string().to_lossy_string()
     }
 }
 
@@ -3110,4 +3336,25 @@ mod test {
         //let s2 = s.clone().into_string();
         //dbg!(s2);
     }
+
+    #[test]
+    fn test_borrow_conflict() {
+        let gcc = &GcContainer::new();
+        let map = GosValue::new_map(gcc);
+        let m = &map.as_map().unwrap().0;
+        let iter = m.borrow_data();
+        let err = m.insert(1.into(), 2.into()).unwrap_err();
+        assert!(err
+            .as_str()
+            .starts_with("runtime error: concurrent access to map 0x"));
+        assert!(m.get(&1.into()).unwrap().is_none());
+        drop(iter);
+        assert!(m.insert(1.into(), 2.into()).is_ok());
+
+        let s = GosValue::new_struct(StructObj::new(vec![1.into()]), gcc);
+        let fields = s.as_struct().0.borrow_fields_mut();
+        assert!(s.as_struct().0.try_borrow_fields().is_err());
+        drop(fields);
+        assert!(s.as_struct().0.try_borrow_fields().is_ok());
+    }
 }
@@ -0,0 +1,1024 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! A serde bridge between `GosValue` and Rust types.
+//!
+//! Both directions need the `Meta` of the Go type: a Rust value alone does not
+//! tell which Go type (int vs int64, slice vs array ...) the script expects, and a
+//! Go struct value does not know the names of its fields. Struct fields are
+//! matched by name, so serde attributes like `rename`, `skip` and `flatten` work.
+//! Go fields a Rust struct doesn't serialize are left zero. A Go struct can also
+//! be converted to and from a tuple, whose elements are matched by position.
+
+use crate::bytecode::VMObjects;
+use crate::gc::GcContainer;
+use crate::stack::Stack;
+use crate::value::*;
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde::ser::{self, Impossible, Serialize};
+use std::fmt::Display;
+use std::rc::Rc;
+
+impl de::Error for RuntimeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        RuntimeError::new(msg.to_string())
+    }
+}
+
+impl ser::Error for RuntimeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        RuntimeError::new(msg.to_string())
+    }
+}
+
+/// Converts a `GosValue` into a Rust value.
+///
+/// Pointers are followed as long as they don't point to a local variable
+/// that is still on the stack or to a package member, use `FfiCtx::from_value`
+/// for those.
+pub fn from_value<T: DeserializeOwned>(
+    val: &GosValue,
+    meta: &Meta,
+    objs: &VMObjects,
+) -> RuntimeResult<T> {
+    T::deserialize(ValueDeserializer::new(val.clone(), *meta, objs, None))
+}
+
+/// Converts a Rust value into a `GosValue` of the Go type described by `meta`.
+pub fn to_value<T: Serialize + ?Sized>(
+    val: &T,
+    meta: &Meta,
+    objs: &VMObjects,
+    gcc: &GcContainer,
+) -> RuntimeResult<GosValue> {
+    val.serialize(ValueSerializer::new(*meta, objs, gcc))
+}
+
+pub(crate) struct ValueDeserializer<'a> {
+    val: GosValue,
+    meta: Meta,
+    objs: &'a VMObjects,
+    /// The stack of the running goroutine, to follow pointers to local variables
+    stack: Option<&'a Stack>,
+}
+
+impl<'a> ValueDeserializer<'a> {
+    pub(crate) fn new(
+        val: GosValue,
+        meta: Meta,
+        objs: &'a VMObjects,
+        stack: Option<&'a Stack>,
+    ) -> ValueDeserializer<'a> {
+        ValueDeserializer {
+            val,
+            meta,
+            objs,
+            stack,
+        }
+    }
+
+    fn child(&self, val: GosValue, meta: Meta) -> ValueDeserializer<'a> {
+        ValueDeserializer::new(val, meta, self.objs, self.stack)
+    }
+
+    fn mtype(&self) -> &'a MetadataType {
+        self.meta.mtype_unwraped(&self.objs.metas)
+    }
+
+    /// The Meta of what the pointer points to
+    fn pointee(&self) -> Meta {
+        match self.meta.ptr_depth {
+            0 => self.meta.underlying(&self.objs.metas).unptr_to(),
+            _ => self.meta.unptr_to(),
+        }
+    }
+
+    /// The value held by the interface, with its own Meta
+    fn dynamic(&self) -> RuntimeResult<Option<ValueDeserializer<'a>>> {
+        match self.val.as_interface() {
+            Some(InterfaceObj::Gos(v, Some((meta, _)))) => Ok(Some(self.child(v.clone(), *meta))),
+            Some(InterfaceObj::Gos(_, None)) => {
+                Err("cannot deserialize an interface value of unknown type"
+                    .to_owned()
+                    .into())
+            }
+            Some(InterfaceObj::Ffi(_)) => {
+                Err("cannot deserialize an FFI interface".to_owned().into())
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn deref(&self, p: &PointerObj) -> RuntimeResult<GosValue> {
+        if let Some(stack) = self.stack {
+            return p.deref(stack, &self.objs.packages);
+        }
+        match p {
+            PointerObj::UpVal(uv) => match &uv.inner.borrow() as &UpValueState {
+                UpValueState::Closed(v) => Ok(v.clone()),
+                UpValueState::Open(_) => Err("cannot dereference a pointer to a local variable"
+                    .to_owned()
+                    .into()),
+            },
+            PointerObj::SliceMember(s, index) => s.caller_slow().slice_get(s, *index as usize),
+            PointerObj::StructField(s, index) => {
+                Ok(s.as_struct().0.borrow_fields()[*index as usize].clone())
+            }
+            PointerObj::PkgMember(_, _) => Err("cannot dereference a pointer to a package member"
+                .to_owned()
+                .into()),
+        }
+    }
+
+    /// The elements of an array or a slice, or the fields of a struct in order
+    fn elems(&self) -> Vec<ValueDeserializer<'a>> {
+        let vals = match self.val.typ() {
+            ValueType::Array => self.val.caller_slow().array_get_vec(&self.val),
+            ValueType::Slice => self
+                .val
+                .caller_slow()
+                .slice_get_vec(&self.val)
+                .unwrap_or_default(),
+            ValueType::Struct => self.val.as_struct().0.borrow_fields().clone(),
+            _ => unreachable!(),
+        };
+        match self.mtype() {
+            MetadataType::Array(m, _) | MetadataType::Slice(m) => {
+                vals.into_iter().map(|x| self.child(x, *m)).collect()
+            }
+            MetadataType::Struct(f) => vals
+                .into_iter()
+                .zip(f.infos())
+                .map(|(x, info)| self.child(x, info.meta))
+                .collect(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// The fields of a struct, keyed by their Go names
+    fn fields(&self) -> Vec<(String, ValueDeserializer<'a>)> {
+        let fields = match self.mtype() {
+            MetadataType::Struct(f) => f.infos(),
+            _ => unreachable!(),
+        };
+        let names = fields.iter().map(|info| info.name.clone());
+        names.zip(self.elems()).collect()
+    }
+
+    /// Follows non-nil pointers and interfaces down to the value they hold
+    fn resolved(self) -> RuntimeResult<ValueDeserializer<'a>> {
+        let inner = match self.val.typ() {
+            ValueType::Pointer => match self.val.as_pointer() {
+                Some(p) => self.child(self.deref(p)?, self.pointee()),
+                None => return Ok(self),
+            },
+            ValueType::Interface => match self.dynamic()? {
+                Some(inner) => inner,
+                None => return Ok(self),
+            },
+            _ => return Ok(self),
+        };
+        inner.resolved()
+    }
+
+    fn is_bytes(&self) -> bool {
+        match self.val.typ() {
+            ValueType::Array | ValueType::Slice => self.val.t_elem() == ValueType::Uint8,
+            _ => false,
+        }
+    }
+}
+
+impl<'de, 'a> IntoDeserializer<'de, RuntimeError> for ValueDeserializer<'a> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for ValueDeserializer<'a> {
+    type Error = RuntimeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> RuntimeResult<V::Value> {
+        let val = &self.val;
+        match val.typ() {
+            ValueType::Bool => visitor.visit_bool(*val.as_bool()),
+            ValueType::Int => visitor.visit_i64(*val.as_int() as i64),
+            ValueType::Int8 => visitor.visit_i8(*val.as_int8()),
+            ValueType::Int16 => visitor.visit_i16(*val.as_int16()),
+            ValueType::Int32 => visitor.visit_i32(*val.as_int32()),
+            ValueType::Int64 => visitor.visit_i64(*val.as_int64()),
+            ValueType::Uint => visitor.visit_u64(*val.as_uint() as u64),
+            ValueType::UintPtr => visitor.visit_u64(*val.as_uint_ptr() as u64),
+            ValueType::Uint8 => visitor.visit_u8(*val.as_uint8()),
+            ValueType::Uint16 => visitor.visit_u16(*val.as_uint16()),
+            ValueType::Uint32 => visitor.visit_u32(*val.as_uint32()),
+            ValueType::Uint64 => visitor.visit_u64(*val.as_uint64()),
+            ValueType::Float32 => visitor.visit_f32(val.as_float32().into_inner()),
+            ValueType::Float64 => visitor.visit_f64(val.as_float64().into_inner()),
+            ValueType::Complex64 => {
+                let c = val.as_complex64();
+                let parts = [c.r.into_inner(), c.i.into_inner()];
+                visitor.visit_seq(SeqDeserializer::new(parts.into_iter()))
+            }
+            ValueType::Complex128 => {
+                let c = val.as_complex128();
+                let parts = [c.r.into_inner(), c.i.into_inner()];
+                visitor.visit_seq(SeqDeserializer::new(parts.into_iter()))
+            }
+            ValueType::String => visitor.visit_str(&val.as_string().as_str()),
+            ValueType::Array | ValueType::Slice => {
+                visitor.visit_seq(SeqDeserializer::new(self.elems().into_iter()))
+            }
+            ValueType::Struct => visitor.visit_map(MapDeserializer::new(self.fields().into_iter())),
+            ValueType::Map => {
+                let (km, vm) = match self.mtype() {
+                    MetadataType::Map(k, v) => (*k, *v),
+                    _ => unreachable!(),
+                };
+                let entries: Vec<(ValueDeserializer, ValueDeserializer)> = match val.as_map() {
+                    Some(m) => {
+                        m.0.borrow_data()
+                            .iter()
+                            .map(|(k, v)| (self.child(k.clone(), km), self.child(v.clone(), vm)))
+                            .collect()
+                    }
+                    None => vec![],
+                };
+                visitor.visit_map(MapDeserializer::new(entries.into_iter()))
+            }
+            ValueType::Pointer => match val.as_pointer() {
+                Some(p) => self
+                    .child(self.deref(p)?, self.pointee())
+                    .deserialize_any(visitor),
+                None => visitor.visit_unit(),
+            },
+            ValueType::Interface => match self.dynamic()? {
+                Some(inner) => inner.deserialize_any(visitor),
+                None => visitor.visit_unit(),
+            },
+            t => Err(format!("cannot deserialize a value of type {:?}", t).into()),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> RuntimeResult<V::Value> {
+        match self.val.typ() {
+            ValueType::Pointer | ValueType::Interface if self.val.is_nil() => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> RuntimeResult<V::Value> {
+        let this = self.resolved()?;
+        if this.is_bytes() {
+            let bytes: Vec<u8> = this.elems().iter().map(|x| *x.val.as_uint8()).collect();
+            visitor.visit_byte_buf(bytes)
+        } else if this.val.typ() == ValueType::String {
+            visitor.visit_bytes(this.val.as_string().as_str().as_bytes())
+        } else {
+            this.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> RuntimeResult<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    /// Go structs are visited as sequences of their fields for tuples
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> RuntimeResult<V::Value> {
+        let this = self.resolved()?;
+        match this.val.typ() {
+            ValueType::Struct => visitor.visit_seq(SeqDeserializer::new(this.elems().into_iter())),
+            _ => this.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> RuntimeResult<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> RuntimeResult<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> RuntimeResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> RuntimeResult<V::Value> {
+        let this = self.resolved()?;
+        match this.val.typ() {
+            ValueType::String => {
+                let s = this.val.as_string().as_str().to_string();
+                visitor.visit_enum(s.into_deserializer())
+            }
+            t => Err(format!("cannot deserialize an enum from a value of type {:?}", t).into()),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        unit unit_struct map struct identifier ignored_any
+    }
+}
+
+pub(crate) struct ValueSerializer<'a> {
+    meta: Meta,
+    objs: &'a VMObjects,
+    gcc: &'a GcContainer,
+}
+
+impl<'a> ValueSerializer<'a> {
+    pub(crate) fn new(meta: Meta, objs: &'a VMObjects, gcc: &'a GcContainer) -> Self {
+        ValueSerializer { meta, objs, gcc }
+    }
+
+    fn mtype(&self) -> &'a MetadataType {
+        self.meta.mtype_unwraped(&self.objs.metas)
+    }
+
+    /// Wraps `val` in as many pointers as the target type has
+    fn finish(&self, mut val: GosValue) -> RuntimeResult<GosValue> {
+        for _ in 0..self.meta.ptr_depth {
+            val = GosValue::new_pointer(PointerObj::new_closed_up_value(&val));
+        }
+        Ok(val)
+    }
+
+    fn mismatch(&self, what: &str) -> RuntimeError {
+        format!(
+            "cannot convert {} to a Go value of type {}",
+            what,
+            type_string(&self.meta, &self.objs.metas)
+        )
+        .into()
+    }
+
+    /// Boxes a primitive value when the target is the empty interface
+    fn try_boxed(&self, val: GosValue, meta: Meta) -> Option<RuntimeResult<GosValue>> {
+        match self.mtype() {
+            MetadataType::Interface(fields) if fields.infos().is_empty() => {
+                let iface = InterfaceObj::with_value(val, Some((meta, Rc::from([]))));
+                Some(self.finish(GosValue::new_interface(iface)))
+            }
+            _ => None,
+        }
+    }
+
+    fn int(self, v: i128) -> RuntimeResult<GosValue> {
+        if self.meta.ptr_depth == 0 {
+            if let Some(boxed) = i64::try_from(v)
+                .ok()
+                .and_then(|i| self.try_boxed(GosValue::from(i as isize), self.objs.prim_meta.mint))
+            {
+                return boxed;
+            }
+        }
+        macro_rules! convert {
+            ($t:ty) => {
+                <$t>::try_from(v)
+                    .map(GosValue::from)
+                    .map_err(|_| self.mismatch(&format!("integer {}", v)))
+            };
+        }
+        let val = match self.mtype() {
+            MetadataType::Int => convert!(isize),
+            MetadataType::Int8 => convert!(i8),
+            MetadataType::Int16 => convert!(i16),
+            MetadataType::Int32 => convert!(i32),
+            MetadataType::Int64 => convert!(i64),
+            MetadataType::Uint => convert!(usize),
+            MetadataType::UintPtr => usize::try_from(v)
+                .map(GosValue::new_uint_ptr)
+                .map_err(|_| self.mismatch(&format!("integer {}", v))),
+            MetadataType::Uint8 => convert!(u8),
+            MetadataType::Uint16 => convert!(u16),
+            MetadataType::Uint32 => convert!(u32),
+            MetadataType::Uint64 => convert!(u64),
+            MetadataType::Float32 => Ok(GosValue::new_float32((v as f32).into())),
+            MetadataType::Float64 => Ok(GosValue::new_float64((v as f64).into())),
+            _ => Err(self.mismatch("an integer")),
+        }?;
+        self.finish(val)
+    }
+
+    fn float(self, v: f64) -> RuntimeResult<GosValue> {
+        if self.meta.ptr_depth == 0 {
+            let val = GosValue::new_float64(v.into());
+            if let Some(boxed) = self.try_boxed(val, self.objs.prim_meta.mfloat64) {
+                return boxed;
+            }
+        }
+        let val = match self.mtype() {
+            MetadataType::Float32 => GosValue::new_float32((v as f32).into()),
+            MetadataType::Float64 => GosValue::new_float64(v.into()),
+            _ => return Err(self.mismatch("a float")),
+        };
+        self.finish(val)
+    }
+
+    fn compound(self, len: Option<usize>) -> RuntimeResult<Compound<'a>> {
+        let target = match self.mtype() {
+            MetadataType::Slice(m) => Target::Slice(*m),
+            MetadataType::Array(m, size) => Target::Array(*m, *size),
+            MetadataType::Struct(f) => Target::Struct(f, vec![None; f.infos().len()]),
+            MetadataType::Map(k, v) => Target::Map(*k, *v, GosValue::new_map(self.gcc)),
+            _ => return Err(self.mismatch("a sequence or a map")),
+        };
+        Ok(Compound {
+            ser: self,
+            target,
+            vals: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+}
+
+enum Target<'a> {
+    Slice(Meta),
+    Array(Meta, usize),
+    /// The fields set by name, the ones left unset are zero
+    Struct(&'a Fields, Vec<Option<GosValue>>),
+    Map(Meta, Meta, GosValue),
+}
+
+pub(crate) struct Compound<'a> {
+    ser: ValueSerializer<'a>,
+    target: Target<'a>,
+    vals: Vec<GosValue>,
+    key: Option<GosValue>,
+}
+
+impl<'a> Compound<'a> {
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> RuntimeResult<()> {
+        let meta = match &self.target {
+            Target::Slice(m) | Target::Array(m, _) => *m,
+            Target::Struct(fields, _) => {
+                fields
+                    .infos()
+                    .get(self.vals.len())
+                    .ok_or_else(|| self.ser.mismatch("a sequence with too many elements"))?
+                    .meta
+            }
+            Target::Map(_, _, _) => return Err(self.ser.mismatch("a sequence")),
+        };
+        let val = value.serialize(ValueSerializer::new(meta, self.ser.objs, self.ser.gcc))?;
+        self.vals.push(val);
+        Ok(())
+    }
+
+    /// The keys of a map serialized to a struct, like a struct with a flattened
+    /// field is, are the names of the fields
+    fn map_key<T: Serialize + ?Sized>(&mut self, key: &T) -> RuntimeResult<()> {
+        let meta = match &self.target {
+            Target::Map(m, _, _) => *m,
+            Target::Struct(_, _) => self.ser.objs.prim_meta.mstr,
+            _ => return Err(self.ser.mismatch("a map")),
+        };
+        let k = key.serialize(ValueSerializer::new(meta, self.ser.objs, self.ser.gcc))?;
+        self.key = Some(k);
+        Ok(())
+    }
+
+    fn map_value<T: Serialize + ?Sized>(&mut self, value: &T) -> RuntimeResult<()> {
+        let k = self
+            .key
+            .take()
+            .expect("serialize_value called before serialize_key");
+        match &self.target {
+            Target::Map(_, m, map) => {
+                let v = value.serialize(ValueSerializer::new(*m, self.ser.objs, self.ser.gcc))?;
+                map.as_map().unwrap().0.insert(k, v)?;
+                Ok(())
+            }
+            Target::Struct(_, _) => self.field(&k.as_string().as_str(), value),
+            _ => Err(self.ser.mismatch("a map")),
+        }
+    }
+
+    fn field<T: Serialize + ?Sized>(&mut self, name: &str, value: &T) -> RuntimeResult<()> {
+        match &mut self.target {
+            Target::Map(_, _, _) => {
+                self.map_key(name)?;
+                self.map_value(value)
+            }
+            Target::Struct(fields, set) => {
+                let i = fields
+                    .infos()
+                    .iter()
+                    .position(|f| f.name == name)
+                    .ok_or_else(|| self.ser.mismatch(&format!("a struct with field {}", name)))?;
+                let meta = fields.infos()[i].meta;
+                set[i] = Some(value.serialize(ValueSerializer::new(
+                    meta,
+                    self.ser.objs,
+                    self.ser.gcc,
+                ))?);
+                Ok(())
+            }
+            _ => Err(self.ser.mismatch("a struct")),
+        }
+    }
+
+    fn end(self) -> RuntimeResult<GosValue> {
+        let objs = self.ser.objs;
+        let gcc = self.ser.gcc;
+        let val = match self.target {
+            Target::Slice(m) => {
+                let caller = objs.arr_slice_caller.get(m.value_type(&objs.metas));
+                GosValue::slice_with_data(self.vals, caller, gcc)
+            }
+            Target::Array(m, size) => {
+                if self.vals.len() != size {
+                    return Err(self
+                        .ser
+                        .mismatch(&format!("a sequence of {} elements", self.vals.len())));
+                }
+                let caller = objs.arr_slice_caller.get(m.value_type(&objs.metas));
+                GosValue::array_with_data(self.vals, caller, gcc)
+            }
+            Target::Struct(fields, set) => {
+                let vals = match self.vals.is_empty() {
+                    // set by name
+                    true => set
+                        .into_iter()
+                        .zip(fields.infos())
+                        .map(|(v, f)| v.unwrap_or_else(|| objs.zero(&f.meta, gcc)))
+                        .collect(),
+                    false if self.vals.len() != fields.infos().len() => {
+                        return Err(self
+                            .ser
+                            .mismatch(&format!("a sequence of {} elements", self.vals.len())));
+                    }
+                    false => self.vals,
+                };
+                GosValue::new_struct(StructObj::new(vals), gcc)
+            }
+            Target::Map(_, _, map) => map,
+        };
+        self.ser.finish(val)
+    }
+}
+
+impl<'a> ser::SerializeSeq for Compound<'a> {
+    type Ok = GosValue;
+    type Error = RuntimeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> RuntimeResult<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> RuntimeResult<GosValue> {
+        Compound::end(self)
+    }
+}
+
+impl<'a> ser::SerializeTuple for Compound<'a> {
+    type Ok = GosValue;
+    type Error = RuntimeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> RuntimeResult<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> RuntimeResult<GosValue> {
+        Compound::end(self)
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for Compound<'a> {
+    type Ok = GosValue;
+    type Error = RuntimeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> RuntimeResult<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> RuntimeResult<GosValue> {
+        Compound::end(self)
+    }
+}
+
+impl<'a> ser::SerializeMap for Compound<'a> {
+    type Ok = GosValue;
+    type Error = RuntimeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> RuntimeResult<()> {
+        self.map_key(key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> RuntimeResult<()> {
+        self.map_value(value)
+    }
+
+    fn end(self) -> RuntimeResult<GosValue> {
+        Compound::end(self)
+    }
+}
+
+impl<'a> ser::SerializeStruct for Compound<'a> {
+    type Ok = GosValue;
+    type Error = RuntimeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> RuntimeResult<()> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> RuntimeResult<GosValue> {
+        Compound::end(self)
+    }
+}
+
+impl<'a> ser::Serializer for ValueSerializer<'a> {
+    type Ok = GosValue;
+    type Error = RuntimeError;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Impossible<GosValue, RuntimeError>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Impossible<GosValue, RuntimeError>;
+
+    fn serialize_bool(self, v: bool) -> RuntimeResult<GosValue> {
+        if self.meta.ptr_depth == 0 {
+            if let Some(boxed) = self.try_boxed(v.into(), self.objs.prim_meta.mbool) {
+                return boxed;
+            }
+        }
+        match self.mtype() {
+            MetadataType::Bool => self.finish(v.into()),
+            _ => Err(self.mismatch("a bool")),
+        }
+    }
+
+    fn serialize_i8(self, v: i8) -> RuntimeResult<GosValue> {
+        self.int(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> RuntimeResult<GosValue> {
+        self.int(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> RuntimeResult<GosValue> {
+        self.int(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> RuntimeResult<GosValue> {
+        self.int(v.into())
+    }
+
+    fn serialize_i128(self, v: i128) -> RuntimeResult<GosValue> {
+        self.int(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> RuntimeResult<GosValue> {
+        self.int(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> RuntimeResult<GosValue> {
+        self.int(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> RuntimeResult<GosValue> {
+        self.int(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> RuntimeResult<GosValue> {
+        self.int(v.into())
+    }
+
+    fn serialize_u128(self, v: u128) -> RuntimeResult<GosValue> {
+        let v = i128::try_from(v).map_err(|_| self.mismatch(&format!("integer {}", v)))?;
+        self.int(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> RuntimeResult<GosValue> {
+        self.float(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> RuntimeResult<GosValue> {
+        self.float(v)
+    }
+
+    fn serialize_char(self, v: char) -> RuntimeResult<GosValue> {
+        match self.mtype() {
+            MetadataType::Str => self.serialize_str(v.encode_utf8(&mut [0; 4])),
+            _ => self.int((v as u32).into()),
+        }
+    }
+
+    fn serialize_str(self, v: &str) -> RuntimeResult<GosValue> {
+        if self.meta.ptr_depth == 0 {
+            if let Some(boxed) = self.try_boxed(GosValue::with_str(v), self.objs.prim_meta.mstr) {
+                return boxed;
+            }
+        }
+        match self.mtype() {
+            MetadataType::Str => self.finish(GosValue::with_str(v)),
+            _ => Err(self.mismatch("a string")),
+        }
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> RuntimeResult<GosValue> {
+        match self.mtype() {
+            MetadataType::Str => match std::str::from_utf8(v) {
+                Ok(s) => self.serialize_str(s),
+                Err(_) => Err(self.mismatch("invalid UTF-8 bytes")),
+            },
+            _ => {
+                use ser::SerializeSeq;
+                let mut seq = self.compound(Some(v.len()))?;
+                for b in v.iter() {
+                    seq.serialize_element(b)?;
+                }
+                SerializeSeq::end(seq)
+            }
+        }
+    }
+
+    fn serialize_none(self) -> RuntimeResult<GosValue> {
+        match self.meta.ptr_depth {
+            0 => self.serialize_unit(),
+            _ => Ok(GosValue::new_nil(ValueType::Pointer)),
+        }
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> RuntimeResult<GosValue> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> RuntimeResult<GosValue> {
+        Ok(self.objs.zero(&self.meta, self.gcc))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> RuntimeResult<GosValue> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> RuntimeResult<GosValue> {
+        match self.mtype() {
+            MetadataType::Str | MetadataType::Interface(_) => self.serialize_str(variant),
+            _ => self.int(variant_index.into()),
+        }
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> RuntimeResult<GosValue> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> RuntimeResult<GosValue> {
+        Err(self.mismatch("an enum variant with data"))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> RuntimeResult<Compound<'a>> {
+        self.compound(len)
+    }
+
+    fn serialize_tuple(self, len: usize) -> RuntimeResult<Compound<'a>> {
+        self.compound(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> RuntimeResult<Compound<'a>> {
+        self.compound(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> RuntimeResult<Self::SerializeTupleVariant> {
+        Err(self.mismatch("an enum variant with data"))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> RuntimeResult<Compound<'a>> {
+        self.compound(len)
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> RuntimeResult<Compound<'a>> {
+        self.compound(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> RuntimeResult<Self::SerializeStructVariant> {
+        Err(self.mismatch("an enum variant with data"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    fn field(meta: Meta, name: &str) -> FieldInfo {
+        FieldInfo {
+            meta,
+            name: name.to_owned(),
+            tag: None,
+            embedded: false,
+            embedded_indices: None,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut objs = VMObjects::new();
+        let gcc = GcContainer::new();
+
+        let mint64 = objs.prim_meta.mint64;
+        let slice_meta = Meta::new_slice(mint64, &mut objs.metas);
+        let v = to_value(&vec![1i64, 2, 3], &slice_meta, &objs, &gcc).unwrap();
+        assert_eq!(v.typ(), ValueType::Slice);
+        assert_eq!(
+            from_value::<Vec<i64>>(&v, &slice_meta, &objs).unwrap(),
+            vec![1, 2, 3]
+        );
+
+        let fields = Fields::new(vec![
+            field(objs.prim_meta.mint, "A"),
+            field(objs.prim_meta.mstr, "B"),
+        ]);
+        let struct_meta = Meta::new_struct(fields, &mut objs);
+        let v = to_value(&(7, "seven"), &struct_meta, &objs, &gcc).unwrap();
+        assert_eq!(v.typ(), ValueType::Struct);
+        let s: (isize, String) = from_value(&v, &struct_meta, &objs).unwrap();
+        assert_eq!(s, (7, "seven".to_owned()));
+        assert!(to_value(&(7, "seven", 7), &struct_meta, &objs, &gcc).is_err());
+
+        let map_meta = Meta::new_map(objs.prim_meta.mstr, objs.prim_meta.mint32, &mut objs.metas);
+        let m: HashMap<String, i32> = [("a".to_owned(), 1), ("b".to_owned(), 2)].into();
+        let v = to_value(&m, &map_meta, &objs, &gcc).unwrap();
+        assert_eq!(
+            from_value::<HashMap<String, i32>>(&v, &map_meta, &objs).unwrap(),
+            m
+        );
+        assert!(to_value(&300, &objs.prim_meta.mint8, &objs, &gcc).is_err());
+
+        let ptr_meta = objs.prim_meta.muint8.ptr_to();
+        let ptr = to_value(&Some(5u8), &ptr_meta, &objs, &gcc).unwrap();
+        assert_eq!(
+            from_value::<Option<u8>>(&ptr, &ptr_meta, &objs).unwrap(),
+            Some(5)
+        );
+        let nil = to_value(&None::<u8>, &ptr_meta, &objs, &gcc).unwrap();
+        assert_eq!(
+            from_value::<Option<u8>>(&nil, &ptr_meta, &objs).unwrap(),
+            None
+        );
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Inner {
+        #[serde(rename = "Tag")]
+        tag: String,
+        #[serde(rename = "Weight")]
+        weight: f64,
+    }
+
+    /// The fields are in a different order than in the Go struct
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(rename_all = "PascalCase")]
+    struct Outer {
+        name: String,
+        inner: Option<Box<Inner>>,
+        #[serde(rename = "ID")]
+        id: i64,
+        #[serde(skip)]
+        cache: i32,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Flat {
+        #[serde(rename = "Name")]
+        name: String,
+        #[serde(flatten)]
+        rest: Rest,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Rest {
+        #[serde(rename = "ID")]
+        id: i64,
+    }
+
+    #[test]
+    fn test_struct_fields_by_name() {
+        let mut objs = VMObjects::new();
+        let gcc = GcContainer::new();
+
+        // struct { Weight float64; Tag string; Extra int }
+        let inner_fields = Fields::new(vec![
+            field(objs.prim_meta.mfloat64, "Weight"),
+            field(objs.prim_meta.mstr, "Tag"),
+            field(objs.prim_meta.mint, "Extra"),
+        ]);
+        let inner_meta = Meta::new_struct(inner_fields, &mut objs);
+        // struct { ID int64; Inner *Inner; Name string }
+        let outer_fields = Fields::new(vec![
+            field(objs.prim_meta.mint64, "ID"),
+            field(inner_meta.ptr_to(), "Inner"),
+            field(objs.prim_meta.mstr, "Name"),
+        ]);
+        let outer_meta = Meta::new_struct(outer_fields, &mut objs);
+
+        let outer = Outer {
+            name: "a".to_owned(),
+            inner: Some(Box::new(Inner {
+                tag: "b".to_owned(),
+                weight: 1.5,
+            })),
+            id: 7,
+            cache: 3,
+        };
+        let v = to_value(&outer, &outer_meta, &objs, &gcc).unwrap();
+        let (id, inner, name): (i64, Option<(f64, String, isize)>, String) =
+            from_value(&v, &outer_meta, &objs).unwrap();
+        assert_eq!((id, name.as_str()), (7, "a"));
+        // the Go field the Rust struct doesn't have is zero
+        assert_eq!(inner, Some((1.5, "b".to_owned(), 0)));
+
+        let back: Outer = from_value(&v, &outer_meta, &objs).unwrap();
+        assert_eq!(back, Outer { cache: 0, ..outer });
+
+        let nil_inner = to_value(&(7, None::<()>, "a"), &outer_meta, &objs, &gcc).unwrap();
+        let back: Outer = from_value(&nil_inner, &outer_meta, &objs).unwrap();
+        assert_eq!(back.inner, None);
+
+        // struct { Name string; ID int64 }
+        let flat_fields = Fields::new(vec![
+            field(objs.prim_meta.mstr, "Name"),
+            field(objs.prim_meta.mint64, "ID"),
+        ]);
+        let flat_meta = Meta::new_struct(flat_fields, &mut objs);
+        let flat = Flat {
+            name: "c".to_owned(),
+            rest: Rest { id: 9 },
+        };
+        let v = to_value(&flat, &flat_meta, &objs, &gcc).unwrap();
+        assert_eq!(from_value::<Flat>(&v, &flat_meta, &objs).unwrap(), flat);
+
+        #[derive(Serialize)]
+        struct Unknown {
+            #[serde(rename = "Missing")]
+            missing: i64,
+        }
+        let err = to_value(&Unknown { missing: 1 }, &flat_meta, &objs, &gcc).unwrap_err();
+        assert!(
+            err.as_str().contains("a struct with field Missing"),
+            "{}",
+            err
+        );
+    }
+}
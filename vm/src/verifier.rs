@@ -0,0 +1,485 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Checking the code of the functions before it's run, so that bytecode the code
+//! generator got wrong, or that was corrupted on its way from disk, is an error
+//! naming the instruction instead of a panic of the VM in the middle of a run.
+//!
+//! The checks are the ones the VM relies on without checking: the jumps land in
+//! the function, the extra instructions some opcodes read are there, the constants
+//! and the tables indexed exist, the registers written are in the frame, and the
+//! types of the instructions are ones the opcodes work on.
+//!
+//! In debug builds the temporaries are checked too: following the jumps from block
+//! to block, every temporary read has to be written before on all the paths to the
+//! read. A read of one that isn't gets whatever the last call left in the register,
+//! which is the register machine's version of popping an empty stack.
+
+use crate::value::*;
+use go_parser::PiggyVecKey;
+use std::fmt;
+
+/// The first instruction of the code of a function the VM can't run
+#[derive(Debug, Clone)]
+pub struct VerifyError {
+    pub func: FunctionKey,
+    /// The index of the instruction in the code of `func`
+    pub pc: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "bad bytecode at instruction {} of function {:?}: {}",
+            self.pc, self.func, self.msg
+        )
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+impl Bytecode {
+    /// Checks the code of all the functions, returns the first instruction the VM
+    /// would misbehave on. It's run on the output of the code generator, and should
+    /// be on the bytecode loaded from elsewhere.
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        for (i, func) in self.objects.functions.vec().iter().enumerate() {
+            let key: FunctionKey = i.into();
+            let v = Verifier {
+                code: self,
+                func,
+                key,
+            };
+            v.verify()?;
+            if cfg!(debug_assertions) {
+                v.check_temps()?;
+            }
+        }
+        for (name, f) in [("entry", self.entry), ("init entry", self.init_entry)] {
+            if f.as_usize() >= self.objects.functions.vec().len() {
+                return Err(VerifyError {
+                    func: f,
+                    pc: 0,
+                    msg: format!("the {} function doesn't exist", name),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+use Operand::*;
+
+struct Verifier<'a> {
+    code: &'a Bytecode,
+    func: &'a FunctionObj,
+    key: FunctionKey,
+}
+
+impl<'a> Verifier<'a> {
+    fn verify(&self) -> Result<(), VerifyError> {
+        let code = &self.func.code;
+        if code.is_empty() {
+            // the functions of the FFIs and the ones declared without a body
+            return Ok(());
+        }
+        if code.last().unwrap().op0 != Opcode::RETURN {
+            // a panic unwinds by running the last instruction
+            return self.error(
+                code.len() - 1,
+                "the code doesn't end with RETURN".to_owned(),
+            );
+        }
+        let mut pc = 0;
+        while pc < code.len() {
+            let inst = &code[pc];
+            let ext = inst.ext_count();
+            if pc + ext >= code.len() {
+                let msg = format!(
+                    "{} reads {} more instructions than there are",
+                    inst.op0, ext
+                );
+                return self.error(pc, msg);
+            }
+            let next = (pc + 1 + ext) as OpIndex;
+            let [d, s0, s1] = inst.operands();
+            self.check_types(pc, inst)?;
+            self.check(pc, inst.d, d, next, "d")?;
+            self.check(pc, inst.s0, s0, next, "s0")?;
+            self.check(pc, inst.s1, s1, next, "s1")?;
+            for i in 0..ext {
+                let ex = &code[pc + 1 + i];
+                let [d, s0, s1] = inst.ext_operands(ex);
+                self.check(pc + 1 + i, ex.d, d, next, "d")?;
+                self.check(pc + 1 + i, ex.s0, s0, next, "s0")?;
+                self.check(pc + 1 + i, ex.s1, s1, next, "s1")?;
+            }
+            if inst.op0 == Opcode::LOAD_INIT_FUNC {
+                // skips the call and the jump back when there are no more init functions
+                self.check(pc, 2, Jump, next, "the skip")?;
+            }
+            pc = next as usize;
+        }
+        Ok(())
+    }
+
+    /// Checks no temporary is read before it's written, on any path through the code.
+    /// The results, the parameters and the locals are set up by the call, only the
+    /// registers after them can be read unwritten.
+    fn check_temps(&self) -> Result<(), VerifyError> {
+        let code = &self.func.code;
+        if code.is_empty() {
+            return Ok(());
+        }
+        // the instructions, without their extra ones, and where each can go next
+        let mut insts = vec![];
+        let mut pc = 0;
+        while pc < code.len() {
+            let next = pc + 1 + code[pc].ext_count();
+            insts.push((pc, self.successors(pc, next)));
+            pc = next;
+        }
+        let mut leaders = vec![false; code.len() + 1];
+        leaders[0] = true;
+        for (pc, succ) in insts.iter() {
+            let next = pc + 1 + code[*pc].ext_count();
+            if succ.len() != 1 || succ[0] != next {
+                succ.iter().for_each(|&s| leaders[s] = true);
+                leaders[next] = true;
+            }
+        }
+        // the basic blocks, as ranges of `insts`
+        let mut blocks: Vec<(usize, usize)> = vec![];
+        for (i, (pc, _)) in insts.iter().enumerate() {
+            match blocks.last_mut() {
+                Some(b) if !leaders[*pc] => b.1 = i + 1,
+                _ => blocks.push((i, i + 1)),
+            }
+        }
+        let mut block_at = vec![None; code.len()];
+        for (b, (begin, _)) in blocks.iter().enumerate() {
+            block_at[insts[*begin].0] = Some(b);
+        }
+
+        // the registers written on entry to each block, on all the paths to it
+        let mut vars = self.func.ret_count() + self.func.param_count();
+        vars += self.func.local_count();
+        let size = self.frame_size() as usize;
+        let mut entries: Vec<Option<Vec<bool>>> = vec![None; blocks.len()];
+        entries[0] = Some((0..size).map(|r| r < vars as usize).collect());
+        let mut work = vec![0];
+        let mut queued = vec![false; blocks.len()];
+        queued[0] = true;
+        while let Some(b) = work.pop() {
+            queued[b] = false;
+            let mut written = entries[b].clone().unwrap();
+            let (begin, end) = blocks[b];
+            for (pc, _) in insts[begin..end].iter() {
+                self.run_temps(*pc, &mut written, false)?;
+            }
+            for &s in insts[end - 1].1.iter() {
+                let s = match block_at[s] {
+                    Some(s) => s,
+                    // a jump into the extra instructions, the other checks report it
+                    None => continue,
+                };
+                let changed = match &mut entries[s] {
+                    Some(entry) => {
+                        let mut changed = false;
+                        for (e, w) in entry.iter_mut().zip(written.iter()) {
+                            if *e && !*w {
+                                *e = false;
+                                changed = true;
+                            }
+                        }
+                        changed
+                    }
+                    None => {
+                        entries[s] = Some(written.clone());
+                        true
+                    }
+                };
+                if changed && !queued[s] {
+                    queued[s] = true;
+                    work.push(s);
+                }
+            }
+        }
+        for (b, entry) in entries.into_iter().enumerate() {
+            // the blocks never reached, like the code after a jump out of a loop
+            if let Some(mut written) = entry {
+                let (begin, end) = blocks[b];
+                for (pc, _) in insts[begin..end].iter() {
+                    self.run_temps(*pc, &mut written, true)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The instructions the one at `pc` can go to, `next` is the one after it and
+    /// its extra ones
+    fn successors(&self, pc: usize, next: usize) -> Vec<usize> {
+        let code = &self.func.code;
+        let mut result = vec![];
+        let mut add = |i: &Instruction, kinds: [Operand; 3]| {
+            for (val, kind) in [i.d, i.s0, i.s1].into_iter().zip(kinds) {
+                if kind == Jump {
+                    // the jumps are checked to be in the code by then
+                    result.push((next as OpIndex + val) as usize);
+                }
+            }
+        };
+        let inst = &code[pc];
+        add(inst, inst.operands());
+        for ex in code[pc + 1..next].iter() {
+            add(ex, inst.ext_operands(ex));
+        }
+        if inst.op0 == Opcode::LOAD_INIT_FUNC && next + 2 < code.len() {
+            result.push(next + 2);
+        }
+        if !matches!(inst.op0, Opcode::JUMP | Opcode::RETURN) && next < code.len() {
+            result.push(next);
+        }
+        result
+    }
+
+    /// Runs the instruction at `pc` on the registers `written`, with `check` an error
+    /// if it reads a temporary not written
+    fn run_temps(&self, pc: usize, written: &mut [bool], check: bool) -> Result<(), VerifyError> {
+        let code = &self.func.code;
+        let inst = &code[pc];
+        let ext = &code[pc + 1..pc + 1 + inst.ext_count()];
+        let mut ops: Vec<(usize, &Instruction, [Operand; 3])> = vec![(pc, inst, inst.operands())];
+        ops.extend(
+            ext.iter()
+                .enumerate()
+                .map(|(i, ex)| (pc + 1 + i, ex, inst.ext_operands(ex))),
+        );
+        // the assignments like `+=` read the register they write
+        let reads_d = matches!(
+            inst.op0,
+            Opcode::ADD_ASSIGN
+                | Opcode::SUB_ASSIGN
+                | Opcode::MUL_ASSIGN
+                | Opcode::QUO_ASSIGN
+                | Opcode::REM_ASSIGN
+                | Opcode::AND_ASSIGN
+                | Opcode::OR_ASSIGN
+                | Opcode::XOR_ASSIGN
+                | Opcode::AND_NOT_ASSIGN
+                | Opcode::SHL_ASSIGN
+                | Opcode::SHR_ASSIGN
+                | Opcode::INC
+                | Opcode::DEC
+        );
+        for (i, (at, ex, kinds)) in ops.iter().enumerate() {
+            let names = ["d", "s0", "s1"];
+            for (j, (val, kind)) in [ex.d, ex.s0, ex.s1].into_iter().zip(kinds).enumerate() {
+                let read = *kind == Read || (i == 0 && j == 0 && reads_d);
+                // the operands left out are `OpIndex::MAX`
+                let temp = val >= 0 && (val as usize) < written.len();
+                if check && read && temp && !written[val as usize] {
+                    let msg = format!(
+                        "{}: {} reads temporary {} before it's written",
+                        names[j], inst.op0, val
+                    );
+                    return self.error(*at, msg);
+                }
+            }
+        }
+        for (_, ex, kinds) in ops.iter() {
+            for (val, kind) in [ex.d, ex.s0, ex.s1].into_iter().zip(kinds) {
+                if *kind == Write && val >= 0 && (val as usize) < written.len() {
+                    written[val as usize] = true;
+                }
+            }
+        }
+        if inst.op0 == Opcode::SELECT {
+            // `v, ok := <-ch` puts ok after v
+            for ex in ext.iter().filter(|ex| ex.t0 == ValueType::FlagD) {
+                if ex.s1 >= 0 && ((ex.s1 + 1) as usize) < written.len() {
+                    written[(ex.s1 + 1) as usize] = true;
+                }
+            }
+        }
+        if inst.op0 == Opcode::CALL && inst.s0 >= 0 {
+            // the results are put from s0 on, how many depends on the callee
+            let from = (inst.s0 as usize).min(written.len());
+            written[from..].iter_mut().for_each(|w| *w = true);
+        }
+        Ok(())
+    }
+
+    /// Checks the operand `val` of the instruction at `pc` is a `kind`, `next` is the
+    /// index of the instruction after it and its extra ones
+    fn check(
+        &self,
+        pc: usize,
+        val: OpIndex,
+        kind: Operand,
+        next: OpIndex,
+        name: &str,
+    ) -> Result<(), VerifyError> {
+        let consts = &self.code.consts;
+        let msg = match kind {
+            Any => None,
+            Write if val < 0 || val >= self.frame_size() => Some(format!(
+                "register {} written is out of the frame of {}",
+                val,
+                self.frame_size()
+            )),
+            Read | Const(_) if val < 0 && (-val - 1) as usize >= consts.len() => Some(format!(
+                "constant {} doesn't exist, there are {}",
+                -val - 1,
+                consts.len()
+            )),
+            Const(_) if val >= 0 => Some(format!("register {} is read for a constant", val)),
+            Const(t) if consts[(-val - 1) as usize].typ() != t => Some(format!(
+                "constant {} is a {}, not a {}",
+                -val - 1,
+                consts[(-val - 1) as usize].typ(),
+                t
+            )),
+            Reg if val < 0 => Some(format!("register {} is negative", val)),
+            Jump if next + val < 0 || next + val >= self.func.code.len() as OpIndex => {
+                Some(format!(
+                    "jumps to {}, out of the {} instructions",
+                    next + val,
+                    self.func.code.len()
+                ))
+            }
+            Indices if val < 0 || val as usize >= self.code.indices.len() => Some(format!(
+                "embedded field path {} doesn't exist, there are {}",
+                val,
+                self.code.indices.len()
+            )),
+            Iface if val < 0 || val as usize >= self.code.ifaces.len() => Some(format!(
+                "interface binding {} doesn't exist, there are {}",
+                val,
+                self.code.ifaces.len()
+            )),
+            UpValue if val < 0 || val as usize >= self.func.up_ptrs.len() => Some(format!(
+                "up value {} doesn't exist, there are {}",
+                val,
+                self.func.up_ptrs.len()
+            )),
+            _ => None,
+        };
+        match msg {
+            Some(msg) => self.error(pc, format!("{}: {}", name, msg)),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks the types of the arithmetic, the logical and the comparison
+    /// instructions are ones they work on
+    fn check_types(&self, pc: usize, inst: &Instruction) -> Result<(), VerifyError> {
+        let is_int = |t: ValueType| t >= ValueType::Int && t <= ValueType::Uint64;
+        let is_num = |t: ValueType| {
+            is_int(t)
+                || matches!(
+                    t,
+                    ValueType::Float32
+                        | ValueType::Float64
+                        | ValueType::Complex64
+                        | ValueType::Complex128
+                )
+        };
+        let is_ordered = |t: ValueType| {
+            is_int(t)
+                || matches!(
+                    t,
+                    ValueType::Float32 | ValueType::Float64 | ValueType::String
+                )
+        };
+        let ok = match inst.op0 {
+            Opcode::VOID => return self.error(pc, "VOID is run".to_owned()),
+            Opcode::ADD | Opcode::ADD_ASSIGN => is_num(inst.t0) || inst.t0 == ValueType::String,
+            Opcode::SUB
+            | Opcode::MUL
+            | Opcode::QUO
+            | Opcode::SUB_ASSIGN
+            | Opcode::MUL_ASSIGN
+            | Opcode::QUO_ASSIGN
+            | Opcode::UNARY_SUB
+            | Opcode::INC
+            | Opcode::DEC => is_num(inst.t0),
+            Opcode::REM
+            | Opcode::AND
+            | Opcode::OR
+            | Opcode::XOR
+            | Opcode::AND_NOT
+            | Opcode::REM_ASSIGN
+            | Opcode::AND_ASSIGN
+            | Opcode::OR_ASSIGN
+            | Opcode::XOR_ASSIGN
+            | Opcode::AND_NOT_ASSIGN
+            | Opcode::UNARY_XOR => is_int(inst.t0),
+            Opcode::SHL | Opcode::SHR | Opcode::SHL_ASSIGN | Opcode::SHR_ASSIGN => {
+                is_int(inst.t0) && is_int(inst.t1)
+            }
+            Opcode::NOT => inst.t0 == ValueType::Bool,
+            Opcode::LSS | Opcode::GTR | Opcode::LEQ | Opcode::GEQ => is_ordered(inst.t0),
+            Opcode::EQL | Opcode::NEQ => inst.t0 < ValueType::FlagA,
+            Opcode::STORE_SLICE
+            | Opcode::STORE_ARRAY
+            | Opcode::STORE_MAP
+            | Opcode::STORE_STRUCT
+            | Opcode::STORE_EMBEDDED
+            | Opcode::STORE_PKG
+            | Opcode::STORE_POINTER
+            | Opcode::STORE_UP_VALUE
+                if inst.op1 != Opcode::VOID =>
+            {
+                // the operators of the assignments like `+=`
+                matches!(
+                    inst.op1,
+                    Opcode::INC
+                        | Opcode::DEC
+                        | Opcode::ADD
+                        | Opcode::SUB
+                        | Opcode::MUL
+                        | Opcode::QUO
+                        | Opcode::REM
+                        | Opcode::AND
+                        | Opcode::OR
+                        | Opcode::XOR
+                        | Opcode::AND_NOT
+                        | Opcode::SHL
+                        | Opcode::SHR
+                ) && inst.t0 < ValueType::FlagA
+            }
+            _ => true,
+        };
+        if ok {
+            Ok(())
+        } else {
+            let op = match inst.op1 {
+                Opcode::VOID => inst.op0.to_string(),
+                _ => format!("{}.{}", inst.op0, inst.op1),
+            };
+            let msg = format!("{} can't work on {} and {}", op, inst.t0, inst.t1);
+            self.error(pc, msg)
+        }
+    }
+
+    /// The registers of a call: the results, the parameters, the locals, and the
+    /// temporaries up to the max one written
+    fn frame_size(&self) -> OpIndex {
+        let f = self.func;
+        let vars = f.ret_count() + f.param_count() + f.local_count();
+        vars.max(f.max_write_index + 1)
+    }
+
+    fn error(&self, pc: usize, msg: String) -> Result<(), VerifyError> {
+        Err(VerifyError {
+            func: self.key,
+            pc,
+            msg,
+        })
+    }
+}
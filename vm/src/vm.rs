@@ -2,24 +2,54 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
+use crate::control::{PauseInfo, VmController};
 use crate::ffi::{FfiCtx, FfiFactory};
-use crate::gc::{collect, GcContainer};
-use crate::objects::ClosureObj;
-use crate::stack::{RangeStack, Stack};
+#[cfg(feature = "async")]
+use crate::ffi::{GoCall, GoCaller};
+use crate::gc::{collect, collect_step, GcContainer, RootedValue, MAP_ENTRY_SIZE};
+use crate::objects::{ClosureObj, FunctionObj, BORROW_CONFLICT};
+#[cfg(feature = "race")]
+use crate::race::{Cell as RaceCell, Race, RaceCtx, RaceDetector};
+#[cfg(feature = "async")]
+use crate::sandbox::Capability;
+use crate::sandbox::SandboxPolicy;
+#[cfg(feature = "async")]
+use crate::scheduler::Scheduler;
+use crate::stack::{RangeStack, Stack, StackSize};
+use crate::step::{Step, Stepper};
 use crate::value::*;
 use go_parser::Map;
+#[cfg(feature = "race")]
+use go_parser::PiggyVecKey;
 use go_parser::{FilePos, FileSet};
 use std::cell::{Cell, RefCell};
 use std::cmp::Ordering;
-use std::rc::Rc;
+use std::collections::BTreeMap;
+use std::rc::{Rc, Weak};
+use std::time::Duration;
 
 #[cfg(feature = "async")]
 use crate::channel;
 #[cfg(feature = "async")]
-use async_executor::LocalExecutor;
-#[cfg(feature = "async")]
 use futures_lite::future;
 
+// the number of collections an object survives to be reported as possibly leaked
+#[cfg(feature = "leak_track")]
+const LEAK_MIN_SURVIVED: u32 = 3;
+
+// the allocations from this size on are checked with the allocator before made
+const PROBE_ALLOC_SIZE: usize = 1 << 26;
+
+// the panic of a goroutine allocating over `RunOptions::max_heap`
+const OUT_OF_MEMORY: &str = "runtime: out of memory";
+
+// the instructions a goroutine runs before the others get a turn, by default
+const TIME_SLICE: usize = 1024;
+
+// the number of stacks of finished goroutines kept for reuse
+#[cfg(feature = "async")]
+const STACK_POOL_SIZE: usize = 64;
+
 // restore stack_ref after drop to allow code in block call yield
 macro_rules! restore_stack_ref {
     ($self_:ident, $stack:ident, $stack_ref:ident) => {{
@@ -52,20 +82,88 @@ macro_rules! go_panic_no_async {
     }};
 }
 
+// checks an allocation, panics or stops the fiber if it's not to be made
+macro_rules! alloc_or_stop {
+    ($self_:ident, $bytes:expr, $panic:ident, $frame:ident, $code:ident) => {{
+        let bytes = $bytes;
+        if let Some(msg) = $self_.quota.charge_alloc(bytes) {
+            go_panic_str!($panic, &msg, $frame, $code);
+            continue;
+        }
+        if $self_.context.over_heap_limit(bytes) {
+            go_panic_str!($panic, OUT_OF_MEMORY, $frame, $code);
+            continue;
+        }
+        match $self_.context.check_alloc(bytes) {
+            None => {}
+            Some((OomAction::Abort, failure)) => {
+                $self_.context.stop(RunError::OutOfMemory(failure));
+                return None;
+            }
+            Some((_, failure)) => {
+                let msg = format!(
+                    "runtime error: out of memory allocating {} bytes",
+                    failure.bytes
+                );
+                go_panic_str!($panic, &msg, $frame, $code);
+                continue;
+            }
+        }
+    }};
+}
+
+// panics with a runtime error, which says where it happened if it's a borrow conflict
+macro_rules! go_panic_err {
+    ($panic:ident, $err:expr, $ctx:ident, $frame:ident, $code:ident) => {{
+        let msg = $ctx.error_msg($err.as_str(), $frame.func(), $frame.pc - 1);
+        go_panic_str!($panic, &msg, $frame, $code);
+    }};
+}
+
 macro_rules! panic_if_err {
-    ($result:expr, $panic:ident, $frame:ident, $code:ident) => {{
+    ($result:expr, $panic:ident, $ctx:ident, $frame:ident, $code:ident) => {{
         if let Err(e) = $result {
-            go_panic_str!($panic, e.as_str(), $frame, $code);
+            go_panic_err!($panic, e, $ctx, $frame, $code);
         }
     }};
 }
 
+// record a memory access for the race detector
+#[cfg(feature = "race")]
+macro_rules! race_access {
+    ($self_:ident, $frame:ident, $cell:expr, $write:expr) => {{
+        let cell = $cell;
+        $self_.race_access(cell, $write);
+        $frame = $self_.frames.last_mut().unwrap();
+    }};
+}
+
+#[cfg(not(feature = "race"))]
+macro_rules! race_access {
+    ($self_:ident, $frame:ident, $cell:expr, $write:expr) => {{}};
+}
+
+// publish the fiber's clock to a channel before the operation, or pick up the clock
+// of the channel after it, for the race detector
+#[cfg(feature = "race")]
+macro_rules! race_sync {
+    ($self_:ident, $op:ident, $chan:expr) => {{
+        let addr = $chan.as_addr() as usize;
+        $self_.context.race.borrow_mut().$op($self_._id, addr);
+    }};
+}
+
+#[cfg(all(feature = "async", not(feature = "race")))]
+macro_rules! race_sync {
+    ($self_:ident, $op:ident, $chan:expr) => {{}};
+}
+
 #[cfg(feature = "async")]
 macro_rules! unwrap_recv_val {
-    ($chan:expr, $val:expr, $gcc:expr) => {
+    ($chan:expr, $val:expr, $objs:expr, $gcc:expr) => {
         match $val {
-            Some(v) => (v, true),
-            None => ($chan.recv_zero.copy_semantic($gcc), false),
+            Some(v) => v,
+            None => $objs.zero(&$chan.elem_meta, $gcc),
         }
     };
 }
@@ -85,7 +183,13 @@ macro_rules! binary_op_assign {
     ($stack:ident, $op:tt, $inst:expr, $sb:expr, $consts:expr) => {{
         let right = unsafe { $stack.read($inst.s0, $sb, $consts).data().copy_non_ptr() };
         let d = $stack.get_data_mut($inst.d + $sb);
-        *d = d.$op(&right, $inst.t0);
+        let result = d.$op(&right, $inst.t0);
+        if $inst.t0 == ValueType::String {
+            // the old string has to be released
+            $stack.set($inst.d + $sb, GosValue::new(ValueType::String, result));
+        } else {
+            *d = result;
+        }
     }};
 }
 
@@ -123,30 +227,702 @@ macro_rules! unary_op {
     }};
 }
 
-/// Entry point
-pub fn run(code: &Bytecode, ffi: &FfiFactory, fs: Option<&FileSet>) {
-    let gcc = GcContainer::new();
+#[cfg(feature = "threaded_dispatch")]
+mod threaded;
+
+/// Called with the panics no deferred call recovers, which end their goroutines
+pub type PanicHook = std::sync::Arc<dyn Fn(&PanicInfo) + Send + Sync>;
+
+/// A panic not recovered
+pub struct PanicInfo<'a> {
+    /// The value passed to panic, or the runtime.Error of a panic raised by the VM
+    pub value: &'a GosValue,
+    /// The value as the VM prints it
+    pub message: String,
+    /// The id of the goroutine, the main goroutine is 0
+    pub goroutine: usize,
+    /// The positions of the calls the panic unwound, the innermost first, None for
+    /// the ones without debug info
+    pub call_stack: Vec<Option<String>>,
+    /// The names of the functions of the calls in `call_stack`, like `main.(*T).m`
+    pub functions: Vec<String>,
+    /// The named local variables of the calls in `call_stack` with their values, empty
+    /// unless `RunOptions::dump_locals_on_panic` is set
+    pub locals: Vec<Vec<(String, String)>>,
+}
+
+/// Called with the calls of the built-in `assert` with a false argument, before
+/// they panic
+pub type AssertHook = std::sync::Arc<dyn Fn(&AssertInfo) + Send + Sync>;
+
+/// A failed assertion
+pub struct AssertInfo {
+    /// The position of the call of `assert`, None without debug info
+    pub position: Option<String>,
+    /// The id of the goroutine, the main goroutine is 0
+    pub goroutine: usize,
+}
+
+/// The settings of a run of the VM
+#[derive(Clone, Default)]
+pub struct RunOptions {
+    /// The initial sizes of the goroutine stacks
+    pub stack_size: StackSize,
+    /// For the host to pause and resume the run from other threads
+    pub controller: VmController,
+    /// Replaces printing the panics not recovered
+    pub panic_hook: Option<PanicHook>,
+    /// Sees the failed assertions, which panic like any other runtime error, for a
+    /// test harness to fail the test even when the script recovers
+    pub assert_hook: Option<AssertHook>,
+    /// Keeps the local variables of the calls a panic unwinds, to be printed or passed
+    /// to the panic hook if it's not recovered
+    pub dump_locals_on_panic: bool,
+    /// The largest allocation, in bytes, a `make` may ask for, no limit if None
+    pub max_alloc: Option<usize>,
+    /// What to do with the allocations over `max_alloc`, or failed
+    pub oom_policy: OomPolicy,
+    /// What the scripts may do to the host, not restricted if None
+    pub sandbox: Option<SandboxPolicy>,
+    /// The quota of every goroutine started by a go statement, or by a `GoCaller`
+    /// without its own, not limited if None
+    pub goroutine_quota: Option<Quota>,
+    /// The instructions all the goroutines may run, the run is stopped with
+    /// `RunError::OutOfFuel` past it, not limited if None. It's checked at the end of
+    /// the time slices of the goroutines, which may run a time slice over it.
+    pub max_instructions: Option<u64>,
+    /// The bytes the live objects may take, estimated like `HeapStats::bytes`, not
+    /// limited if None. A goroutine going over it, after a collection couldn't free
+    /// enough, panics with "runtime: out of memory". It's checked by `make` and
+    /// `append`, and at the end of every time slice for the other allocations.
+    pub max_heap: Option<usize>,
+    /// The instructions a goroutine runs before the others get a turn, 1024 if None.
+    /// The goroutines take turns in the order they became runnable, shorter slices
+    /// switch between them more often, at some cost to the throughput.
+    pub time_slice: Option<usize>,
+    /// Prints made up addresses for the pointers, funcs and channels, like
+    /// 0xc000010000, numbered in the order they are first printed, so that the output
+    /// is the same on every run
+    pub stable_addresses: bool,
+    /// Makes a select pick the first of its cases ready, in the order they are
+    /// written, instead of one at random, so that the runs are repeatable
+    pub deterministic_select: bool,
+}
+
+/// Limits on what a single goroutine may use. A goroutine going over them panics
+/// with a runtime error, which unwinds it and runs its deferred calls, and panics
+/// again at its next safepoint if it recovers, the other goroutines keep running.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Quota {
+    /// The instructions it may run, checked at the end of every time slice
+    pub instructions: Option<u64>,
+    /// The bytes it may ask for with `make` in total
+    pub alloc_bytes: Option<usize>,
+}
+
+// what a goroutine used, against its quota
+#[derive(Default)]
+struct QuotaUsage {
+    quota: Quota,
+    instructions: u64,
+    allocated: usize,
+}
+
+impl QuotaUsage {
+    #[cfg(feature = "async")]
+    fn new(quota: Option<Quota>) -> QuotaUsage {
+        QuotaUsage {
+            quota: quota.unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the message to panic with if the allocation is over the quota
+    #[inline]
+    fn charge_alloc(&mut self, bytes: usize) -> Option<String> {
+        let max = self.quota.alloc_bytes?;
+        self.allocated = self.allocated.saturating_add(bytes);
+        (self.allocated > max).then(|| {
+            format!(
+                "runtime error: goroutine quota exceeded: allocating {} bytes, {} allowed",
+                self.allocated, max
+            )
+        })
+    }
+
+    #[inline]
+    fn charge_instructions(&mut self, count: usize) {
+        self.instructions += count as u64;
+    }
+
+    /// Returns the message to panic with if the goroutine ran too many instructions
+    #[inline]
+    fn exceeded(&self) -> Option<String> {
+        let max = self.quota.instructions?;
+        (self.instructions > max).then(|| {
+            format!(
+                "runtime error: goroutine quota exceeded: {} instructions run, {} allowed",
+                self.instructions, max
+            )
+        })
+    }
+}
+
+/// An allocation the VM refused or failed to make
+#[derive(Clone, Debug)]
+pub struct AllocFailure {
+    /// The size asked for, in bytes, usize::MAX if it overflows
+    pub bytes: usize,
+    /// Over `RunOptions::max_alloc`, otherwise the allocator failed
+    pub over_limit: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OomAction {
+    /// Makes the allocation anyway, taken as Panic if the allocator failed
+    Allow,
+    /// Panics in the script, which can recover from it
+    Panic,
+    /// Stops the run, `run` returns `RunError::OutOfMemory`
+    Abort,
+}
+
+/// What the VM does when a script asks for too much memory
+#[derive(Clone, Default)]
+pub enum OomPolicy {
+    #[default]
+    Panic,
+    Abort,
+    /// Lets the host decide for every allocation
+    Ask(std::sync::Arc<dyn Fn(&AllocFailure) -> OomAction + Send + Sync>),
+}
+
+/// What a run used, for billing the hosts of the scripts or enforcing quotas
+#[derive(Clone, Debug, Default)]
+pub struct RunStats {
+    /// The instructions run by all the goroutines
+    pub instructions: u64,
+    /// The most stack slots a goroutine used
+    pub peak_stack_slots: usize,
+    /// The most objects the collector tracked at once, they are the maps, structs,
+    /// closures and the arrays of values that may refer to others
+    pub peak_heap_objects: usize,
+    /// The most bytes the live objects took at once, estimated like
+    /// `HeapStats::peak_bytes`
+    pub peak_heap_bytes: usize,
+    /// The bytes allocated over the run, the freed ones included
+    pub allocated_bytes: u64,
+    /// The objects made over the run, like `HeapStats::allocations`
+    pub allocations: u64,
+    /// The goroutines run, the main one included
+    pub goroutines: usize,
+    /// The collections of cycles run, of the young objects and of the old ones
+    pub gc_cycles: usize,
+    /// The calls to the FFIs, by "ffi.func" with the name the FFI is registered with
+    pub ffi_calls: BTreeMap<String, u64>,
+    /// The time from the start to the end of the run, zero on wasm32
+    pub wall_time: Duration,
+}
+
+/// Why a run stopped before its goroutines finished
+#[derive(Clone, Debug)]
+pub enum RunError {
+    OutOfMemory(AllocFailure),
+    /// Over `RunOptions::max_instructions`, with the limit
+    OutOfFuel(u64),
+    /// Stopped with a `CancelHandle`
+    Cancelled,
+    /// Shut down with `VmController::shutdown`, `completed` if all the goroutines
+    /// ended within the grace period
+    Shutdown {
+        completed: bool,
+    },
+}
+
+impl std::fmt::Display for RunError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RunError::OutOfMemory(a) => write!(f, "out of memory allocating {} bytes", a.bytes),
+            RunError::OutOfFuel(max) => write!(f, "instruction budget of {} exhausted", max),
+            RunError::Cancelled => write!(f, "cancelled by the host"),
+            RunError::Shutdown { completed: true } => write!(f, "shut down by the host"),
+            RunError::Shutdown { completed: false } => {
+                write!(f, "shut down by the host, the grace period ran out")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
+
+/// Why `call` didn't return the results of the function
+#[derive(Clone, Debug)]
+pub enum CallError {
+    /// The package has no function of the name, with "pkg.func"
+    NotFound(String),
+    /// The arguments don't match the parameters of the function
+    Arguments(String),
+    /// The initializers or the function panicked, with the message
+    Panic(String),
+    /// The function never returned, all the goroutines are blocked
+    Blocked,
+    /// The VM stopped the run
+    Run(RunError),
+}
+
+impl std::fmt::Display for CallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CallError::NotFound(name) => write!(f, "function {} not found", name),
+            CallError::Arguments(msg) => write!(f, "{}", msg),
+            CallError::Panic(msg) => write!(f, "panic: {}", msg),
+            CallError::Blocked => write!(f, "all goroutines are asleep - deadlock!"),
+            CallError::Run(e) => write!(f, "run aborted: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CallError {}
+
+/// Entry point, returns what the run used
+pub fn run(
+    code: &Bytecode,
+    ffi: &FfiFactory,
+    fs: Option<&FileSet>,
+    opts: &RunOptions,
+) -> std::result::Result<RunStats, RunError> {
+    execute(code, ffi, fs, opts, None, start_main)
+}
+
+/// Calls the function `func` of the package named `pkg` with `args` instead of main,
+/// and returns its results. The packages not initialized yet are initialized first.
+/// The goroutines it starts run until they finish or block, like the ones of main.
+pub fn call(
+    code: &Bytecode,
+    ffi: &FfiFactory,
+    fs: Option<&FileSet>,
+    opts: &RunOptions,
+    pkg: &str,
+    func: &str,
+    args: Vec<GosValue>,
+) -> std::result::Result<Vec<GosValue>, CallError> {
+    let result = Rc::new(RefCell::new(None));
+    let r = result.clone();
+    let (pkg, func) = (pkg.to_owned(), func.to_owned());
+    execute(code, ffi, fs, opts, None, move |ctx| {
+        #[cfg(not(feature = "async"))]
+        {
+            *r.borrow_mut() = Some(ctx.call_entry(&pkg, &func, args));
+        }
+        #[cfg(feature = "async")]
+        {
+            let ctx2 = ctx.clone();
+            ctx.exec.spawn(async move {
+                let res = ctx2.call_entry(&pkg, &func, args).await;
+                *r.borrow_mut() = Some(res);
+            });
+        }
+    })
+    .map_err(CallError::Run)?;
+    let result = result.borrow_mut().take();
+    result.unwrap_or(Err(CallError::Blocked))
+}
+
+/// A VM kept between calls, for hosts calling the functions of a script many times
+/// with low latency, like serving requests with them. The packages are initialized
+/// once, when it's made, and the calls share the executor, the heap and the package
+/// variables. The goroutines a call starts and leaves running go on during the calls
+/// after it.
+pub struct Instance<'a> {
+    ctx: Context<'a>,
+    // the receiving end of the GoCaller, closed when dropped
+    #[cfg(feature = "async")]
+    calls: async_channel::Receiver<GoCall>,
+    // the members of the packages before they were initialized, for `reset`
+    initial: Vec<Vec<GosValue>>,
+}
+
+impl<'a> Instance<'a> {
+    /// Initializes the packages of `code`, the ones initialized by runs before are
+    /// left as they are
+    pub fn new(
+        code: &'a Bytecode,
+        ffi: &'a FfiFactory,
+        fs: Option<&'a FileSet>,
+        opts: &'a RunOptions,
+    ) -> std::result::Result<Instance<'a>, CallError> {
+        let gcc = Rc::new(GcContainer::new());
+        let initial = code
+            .objects
+            .packages
+            .vec()
+            .iter()
+            .map(|p| p.snapshot(&gcc))
+            .collect();
+        #[cfg(not(feature = "async"))]
+        let inst = Instance {
+            ctx: Context::new(code, gcc, ffi, fs, opts),
+            initial,
+        };
+        #[cfg(feature = "async")]
+        let inst = {
+            let exec = Rc::new(Scheduler::new());
+            let (go_caller, calls) = GoCaller::new();
+            let ctx = Context::new(exec, go_caller, code, gcc, ffi, fs, opts);
+            ctx.serve_go_calls(calls.clone());
+            Instance {
+                ctx,
+                calls,
+                initial,
+            }
+        };
+        inst.init()?;
+        Ok(inst)
+    }
+
+    /// Calls the function `func` of the package named `pkg` with `args` and returns
+    /// its results, like `call` but without initializing the packages again. The
+    /// limits of the options, like `max_instructions`, apply to each call.
+    pub fn call(
+        &self,
+        pkg: &str,
+        func: &str,
+        args: Vec<GosValue>,
+    ) -> std::result::Result<Vec<GosValue>, CallError> {
+        #[cfg(not(feature = "async"))]
+        let f = |ctx: &Context<'a>| ctx.call_func(pkg, func, args);
+        #[cfg(feature = "async")]
+        let f = {
+            let (pkg, func) = (pkg.to_owned(), func.to_owned());
+            move |ctx: Context<'a>| async move { ctx.call_func(&pkg, &func, args).await }
+        };
+        self.run(f)
+    }
+
+    /// Puts the package variables back to what they were before the packages were
+    /// initialized, and runs the initializers again, for the calls after it not to
+    /// see what the ones before it did. Their side effects happen again, and the
+    /// goroutines left running are not stopped.
+    pub fn reset(&self) -> std::result::Result<(), CallError> {
+        let packages = self.ctx.code.objects.packages.vec();
+        for (p, members) in packages.iter().zip(self.initial.iter()) {
+            p.restore(members, &self.ctx.gcc);
+        }
+        self.init()
+    }
+
+    /// Makes a []byte of `bytes` without copying them, for passing to the calls. The
+    /// host can keep it for many calls and change it in place between them with
+    /// `FfiCtx::slice_as_primitive_slice_mut`, the calls see the same array, and the
+    /// host what they wrote to it. Appending past its capacity makes a new array.
+    pub fn new_bytes(&self, bytes: Vec<u8>) -> GosValue {
+        let buf: Vec<Elem8> = unsafe { std::mem::transmute(bytes) };
+        let arr = GosValue::new_array(
+            ArrayObj::with_raw_data(buf),
+            ValueType::Uint8,
+            &self.ctx.gcc,
+        );
+        GosValue::new_slice(StringObj::with_array(arr, 0, -1).unwrap(), ValueType::Uint8)
+    }
+
+    /// Makes a map of `entries` for passing to the calls, shared by them and the host
+    /// like `new_bytes`, the host changes it with the methods of `MapObj`
+    pub fn new_map(
+        &self,
+        entries: Vec<(GosValue, GosValue)>,
+    ) -> std::result::Result<GosValue, CallError> {
+        let map = GosValue::new_map(&self.ctx.gcc);
+        let obj = &map.as_map().unwrap().0;
+        self.ctx.gcc.charge(entries.len() * MAP_ENTRY_SIZE);
+        for (k, v) in entries {
+            obj.insert(k, v)
+                .map_err(|e| CallError::Arguments(e.to_string()))?;
+        }
+        Ok(map)
+    }
+
+    /// Keeps `val`, like the results of a call, alive until the handle returned is
+    /// dropped, see `GcContainer::root`
+    pub fn root(&self, val: GosValue) -> RootedValue {
+        self.ctx.gcc.root(val)
+    }
+
+    /// What the calls so far used
+    pub fn stats(&self) -> RunStats {
+        self.ctx.usage.stats(self.ctx.next_id.get(), &self.ctx.gcc)
+    }
+
+    fn init(&self) -> std::result::Result<(), CallError> {
+        #[cfg(not(feature = "async"))]
+        let f = |ctx: &Context<'a>| ctx.init_packages();
+        #[cfg(feature = "async")]
+        let f = |ctx: Context<'a>| async move { ctx.init_packages().await };
+        self.run(f)
+    }
 
     #[cfg(not(feature = "async"))]
+    fn run<T, F>(&self, f: F) -> std::result::Result<T, CallError>
+    where
+        F: FnOnce(&Context<'a>) -> std::result::Result<T, CallError>,
     {
-        let ctx = Context::new(code, &gcc, ffi, fs);
-        let first_frame = ctx.new_entry_frame(code.entry);
-        Fiber::new(ctx, Stack::new(), first_frame).main_loop();
+        self.begin();
+        let result = f(&self.ctx);
+        self.end(result)
     }
+
+    /// Runs the fibers until the one running `f` is done, the others are left to
+    /// run during the next calls
     #[cfg(feature = "async")]
+    fn run<T, F, Fut>(&self, f: F) -> std::result::Result<T, CallError>
+    where
+        T: 'a,
+        F: FnOnce(Context<'a>) -> Fut,
+        Fut: std::future::Future<Output = std::result::Result<T, CallError>> + 'a,
     {
-        let exec = Rc::new(LocalExecutor::new());
-        let ctx = Context::new(exec.clone(), code, &gcc, ffi, fs);
-        let entry = ctx.new_entry_frame(code.entry);
-        ctx.spawn_fiber(Stack::new(), entry);
-        future::block_on(async {
-            loop {
-                if !exec.try_tick() {
-                    break;
-                }
-            }
+        self.begin();
+        let result = Rc::new(RefCell::new(None));
+        let r = result.clone();
+        let fut = f(self.ctx.clone());
+        let task = self.ctx.exec.spawn(async move {
+            let res = fut.await;
+            *r.borrow_mut() = Some(res);
         });
+        self.ctx.run_executor(|| result.borrow().is_some());
+        // a call stopped or blocked is dropped where it is, the goroutines waiting on
+        // channels never see the run is stopped
+        self.ctx.exec.cancel(task);
+        let result = result.borrow_mut().take();
+        self.end(result.unwrap_or(Err(CallError::Blocked)))
+    }
+
+    fn begin(&self) {
+        let opts = self.ctx.opts;
+        opts.controller.start();
+        self.ctx.fuel.set(opts.max_instructions.unwrap_or(0));
+        set_stable_addresses(opts.stable_addresses);
+    }
+
+    fn end<T>(
+        &self,
+        result: std::result::Result<T, CallError>,
+    ) -> std::result::Result<T, CallError> {
+        let ctl = &self.ctx.opts.controller;
+        if ctl.shutdown_requested() {
+            self.ctx.stop(RunError::Shutdown { completed: true });
+        } else if ctl.cancel_requested() {
+            self.ctx.stop(RunError::Cancelled);
+        }
+        ctl.finish();
+        set_stable_addresses(false);
+        // the next call starts over
+        match self.ctx.error.borrow_mut().take() {
+            Some(e) => Err(CallError::Run(e)),
+            None => result,
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a> Drop for Instance<'a> {
+    fn drop(&mut self) {
+        // let the task serving the calls and the goroutines still running end, they
+        // hold references to the executor. The ones waiting on channels keep polling
+        // them, every task gets one turn.
+        self.ctx.stop(RunError::Cancelled);
+        self.calls.close();
+        for _ in 0..=self.ctx.next_id.get() {
+            if !self.ctx.exec.try_tick() {
+                break;
+            }
+        }
+    }
+}
+
+pub(crate) fn run_with(
+    code: &Bytecode,
+    ffi: &FfiFactory,
+    fs: Option<&FileSet>,
+    opts: &RunOptions,
+    stepper: Option<&Stepper>,
+) -> std::result::Result<(), RunError> {
+    execute(code, ffi, fs, opts, stepper, start_main).map(|_| ())
+}
+
+/// Starts the goroutine running main
+fn start_main(ctx: &Context) {
+    let entry = ctx.new_entry_frame(ctx.code.entry);
+    let stack = Stack::with_size(ctx.opts.stack_size.main);
+    #[cfg(not(feature = "async"))]
+    {
+        let mut fiber = Fiber::new(ctx.clone(), stack, entry);
+        if let Some(p) = fiber.main_loop() {
+            fiber.context.report_panic(p, fiber._id);
+        }
+        fiber.recycle();
+    }
+    #[cfg(feature = "async")]
+    ctx.spawn_fiber(stack, entry, None);
+}
+
+/// Runs the goroutines `start` starts until they finish or block
+fn execute<F>(
+    code: &Bytecode,
+    ffi: &FfiFactory,
+    fs: Option<&FileSet>,
+    opts: &RunOptions,
+    stepper: Option<&Stepper>,
+    start: F,
+) -> std::result::Result<RunStats, RunError>
+where
+    F: FnOnce(&Context<'_>),
+{
+    let gcc = Rc::new(GcContainer::new());
+    let ctl = &opts.controller;
+    #[cfg(not(target_arch = "wasm32"))]
+    let started = std::time::Instant::now();
+    ctl.start();
+    set_stable_addresses(opts.stable_addresses);
+    let (error, usage, fibers);
+
+    #[cfg(not(feature = "async"))]
+    {
+        let ctx = Context::new(code, gcc.clone(), ffi, fs, opts).with_stepper(stepper);
+        (error, usage, fibers) = (ctx.error.clone(), ctx.usage.clone(), ctx.next_id.clone());
+        start(&ctx);
+    }
+    #[cfg(feature = "async")]
+    {
+        let exec = Rc::new(Scheduler::new());
+        let (go_caller, calls) = GoCaller::new();
+        let ctx = Context::new(exec.clone(), go_caller, code, gcc.clone(), ffi, fs, opts)
+            .with_stepper(stepper);
+        (error, usage, fibers) = (ctx.error.clone(), ctx.usage.clone(), ctx.next_id.clone());
+        #[cfg(feature = "race")]
+        let race = ctx.race.clone();
+        start(&ctx);
+        ctx.serve_go_calls(calls.clone());
+        ctx.run_executor(|| false);
+        // let the task serving the calls end, it holds a reference to the executor
+        calls.close();
+        while exec.try_tick() {}
+        #[cfg(feature = "race")]
+        {
+            let count = race.borrow().count();
+            if count > 0 {
+                println!("Found {} data race(s)", count);
+            }
+        }
+    }
+    if ctl.shutdown_requested() {
+        // the goroutines have ended, the run must not look like it completed
+        error
+            .borrow_mut()
+            .get_or_insert(RunError::Shutdown { completed: true });
+    } else if ctl.cancel_requested() {
+        error.borrow_mut().get_or_insert(RunError::Cancelled);
+    }
+    ctl.finish();
+    set_stable_addresses(false);
+    #[allow(unused_mut)]
+    let mut stats = usage.stats(fibers.get(), &gcc);
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        stats.wall_time = started.elapsed();
+    }
+    collect(&gcc);
+    #[cfg(feature = "leak_track")]
+    report_leaks(&gcc, code, fs);
+    let error = error.borrow_mut().take();
+    match error {
+        Some(e) => Err(e),
+        None => Ok(stats),
+    }
+}
+
+/// What the fibers of a run used, shared by them
+#[derive(Default)]
+struct Usage {
+    instructions: Cell<u64>,
+    peak_stack: Cell<usize>,
+    // by the name of the FFI, then by the function
+    ffi_calls: RefCell<Map<Rc<str>, Map<String, u64>>>,
+}
+
+impl Usage {
+    fn add_ffi_call(&self, ffi: &Rc<str>, func: &str) {
+        let mut calls = self.ffi_calls.borrow_mut();
+        if !calls.contains_key(ffi) {
+            calls.insert(ffi.clone(), Map::new());
+        }
+        let funcs = calls.get_mut(ffi).unwrap();
+        match funcs.get_mut(func) {
+            Some(n) => *n += 1,
+            None => {
+                funcs.insert(func.to_owned(), 1);
+            }
+        }
+    }
+
+    fn stats(&self, goroutines: usize, gcc: &GcContainer) -> RunStats {
+        let ffi_calls = self
+            .ffi_calls
+            .borrow()
+            .iter()
+            .flat_map(|(ffi, funcs)| {
+                funcs
+                    .iter()
+                    .map(move |(func, n)| (format!("{}.{}", ffi, func), *n))
+            })
+            .collect();
+        let heap = gcc.heap_stats();
+        RunStats {
+            instructions: self.instructions.get(),
+            peak_stack_slots: self.peak_stack.get(),
+            peak_heap_objects: gcc.peak(),
+            peak_heap_bytes: heap.peak_bytes,
+            allocated_bytes: heap.allocated_bytes,
+            allocations: heap.allocations,
+            goroutines,
+            gc_cycles: heap.collections + heap.minor_collections,
+            ffi_calls,
+            wall_time: Duration::ZERO,
+        }
+    }
+}
+
+/// Prints where the objects that have survived many collections were allocated, they
+/// are held by long-lived variables, or by cycles the collector can't see through,
+/// like the ones going through channels.
+#[cfg(feature = "leak_track")]
+fn report_leaks(gcc: &GcContainer, code: &Bytecode, fs: Option<&FileSet>) {
+    let sites = gcc.surviving_sites(LEAK_MIN_SURVIVED);
+    if sites.is_empty() {
+        return;
     }
+    println!("==================");
+    println!(
+        "objects surviving {} or more collections, by allocation site:",
+        LEAK_MIN_SURVIVED
+    );
+    for (site, count) in sites {
+        match site.and_then(|(fkey, pc)| source_pos(code, fs, fkey, pc)) {
+            Some(p) => println!("{:>8} {}", count, p),
+            None => println!("{:>8} <unknown site>", count),
+        }
+    }
+    println!("==================");
+}
+
+/// The source position of the instruction at `pc` of the function `fkey`
+fn source_pos(
+    code: &Bytecode,
+    fs: Option<&FileSet>,
+    fkey: FunctionKey,
+    pc: OpIndex,
+) -> Option<FilePos> {
+    let func = &code.objects.functions[fkey];
+    let p = func.pos.get(pc.max(0) as usize)?;
+    fs?.position(p as usize)
 }
 
 #[derive(Clone, Debug)]
@@ -202,6 +978,79 @@ impl CallFrame {
         }
     }
 
+    /// Sets up the upvalues of the Go closure being called, the ones pointing to the
+    /// locals of the function itself refer to `stack`.
+    #[inline]
+    fn bind_up_values(
+        &mut self,
+        uvs: &Map<usize, UpValue>,
+        func: &FunctionObj,
+        stack: Weak<RefCell<Stack>>,
+    ) {
+        let mut ptrs: Vec<UpValue> = Vec::with_capacity(func.up_ptrs.len());
+        for (i, p) in func.up_ptrs.iter().enumerate() {
+            ptrs.push(if p.is_local {
+                // local pointers
+                let uv = UpValue::new(p.clone_with_stack(stack.clone(), self.stack_base));
+                self.add_referred_by(p.index, p.typ, &uv);
+                uv
+            } else {
+                uvs[&i].clone()
+            });
+        }
+        self.var_ptrs = Some(ptrs);
+    }
+
+    /// The live upvalue pointing to the local at `index`, if any. All the closures and
+    /// pointers of a variable share one upvalue, for them to see the same value after
+    /// it is closed, and to compare equal.
+    fn referred_upvalue(&self, index: OpIndex) -> Option<UpValue> {
+        self.referred_by
+            .as_ref()?
+            .get(&index)?
+            .weaks
+            .iter()
+            .find_map(|w| w.upgrade())
+    }
+
+    /// Closes the upvalues of the locals in `begin..end`, and gives the function's own
+    /// pointers to them new upvalues, so that each iteration of a loop body has its
+    /// own variables.
+    fn close_up_values(
+        &mut self,
+        begin: OpIndex,
+        end: OpIndex,
+        func: &FunctionObj,
+        stack: &Stack,
+        stack_rc: &Rc<RefCell<Stack>>,
+    ) {
+        let sb = self.stack_base;
+        match &mut self.referred_by {
+            Some(referred) => referred.retain(|ind, referrers| {
+                if *ind < begin || *ind >= end {
+                    return true;
+                }
+                let val = stack.get(sb + *ind);
+                for weak in referrers.weaks.iter() {
+                    if let Some(uv) = weak.upgrade() {
+                        uv.close(val.clone());
+                    }
+                }
+                false
+            }),
+            None => return,
+        }
+        if let Some(mut ptrs) = self.var_ptrs.take() {
+            for (p, uv) in func.up_ptrs.iter().zip(ptrs.iter_mut()) {
+                if p.is_local && p.index >= begin && p.index < end {
+                    *uv = UpValue::new(p.clone_with_stack(Rc::downgrade(stack_rc), sb));
+                    self.add_referred_by(p.index, p.typ, uv);
+                }
+            }
+            self.var_ptrs = Some(ptrs);
+        }
+    }
+
     #[inline]
     fn func(&self) -> FunctionKey {
         self.closure.as_gos().func
@@ -243,10 +1092,15 @@ enum Result {
     End,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 struct PanicData {
     msg: GosValue,
     call_stack: Vec<(FunctionKey, OpIndex)>,
+    // the locals of the calls in call_stack, if kept
+    locals: Vec<Vec<(String, String)>>,
+    // unwinding the goroutine for a shutdown or a cancel, not a panic, it can't be
+    // recovered
+    goexit: bool,
 }
 
 impl PanicData {
@@ -254,6 +1108,29 @@ impl PanicData {
         PanicData {
             msg: m,
             call_stack: vec![],
+            locals: vec![],
+            goexit: false,
+        }
+    }
+
+    /// Keeps the locals of the call being unwound, the last one in call_stack
+    fn keep_locals(&mut self, objs: &VMObjects, stack: &Stack, sb: OpIndex) {
+        let (fkey, pc) = *self.call_stack.last().unwrap();
+        self.locals.resize(self.call_stack.len() - 1, vec![]);
+        self.locals.push(frame_locals(objs, fkey, pc, stack, sb));
+    }
+
+    /// Returns the value to be recovered. The panics raised by the VM itself carry
+    /// a bare string, it's recovered as an error of the type `runtime_error`.
+    fn into_value(self, runtime_error: Meta) -> GosValue {
+        match self.msg.as_interface() {
+            Some(InterfaceObj::Gos(v, None)) if v.typ() == ValueType::String => {
+                GosValue::new_interface(InterfaceObj::with_value(
+                    v.clone(),
+                    Some((runtime_error, Rc::from([]))),
+                ))
+            }
+            _ => self.msg,
         }
     }
 }
@@ -261,56 +1138,474 @@ impl PanicData {
 #[derive(Clone)]
 struct Context<'a> {
     #[cfg(feature = "async")]
-    exec: Rc<LocalExecutor<'a>>,
+    exec: Rc<Scheduler<'a>>,
+    // number of async FFI calls in flight, which may be woken up from outside
+    #[cfg(feature = "async")]
+    pending_ffi: Rc<Cell<usize>>,
+    #[cfg(feature = "async")]
+    go_caller: GoCaller,
     code: &'a Bytecode,
-    gcc: &'a GcContainer,
+    gcc: Rc<GcContainer>,
     ffi_factory: &'a FfiFactory,
     fs: Option<&'a FileSet>,
-    next_id: Cell<usize>,
+    opts: &'a RunOptions,
+    stepper: Option<&'a Stepper<'a>>,
+    // the stacks of the finished goroutines, for the new ones to reuse
+    #[cfg(feature = "async")]
+    stack_pool: Rc<RefCell<Vec<Stack>>>,
+    // shared by the clones of all the fibers
+    next_id: Rc<Cell<usize>>,
+    // the number of fibers not finished
+    #[cfg(feature = "async")]
+    live: Rc<Cell<usize>>,
+    // the instructions left to run, if limited
+    fuel: Rc<Cell<u64>>,
+    usage: Rc<Usage>,
+    // set to stop all the fibers
+    error: Rc<RefCell<Option<RunError>>>,
+    #[cfg(feature = "race")]
+    race: Rc<RefCell<RaceDetector>>,
 }
 
 impl<'a> Context<'a> {
     fn new(
-        #[cfg(feature = "async")] exec: Rc<LocalExecutor<'a>>,
+        #[cfg(feature = "async")] exec: Rc<Scheduler<'a>>,
+        #[cfg(feature = "async")] go_caller: GoCaller,
         code: &'a Bytecode,
-        gcc: &'a GcContainer,
+        gcc: Rc<GcContainer>,
         ffi_factory: &'a FfiFactory,
         fs: Option<&'a FileSet>,
+        opts: &'a RunOptions,
     ) -> Context<'a> {
         Context {
             #[cfg(feature = "async")]
             exec,
+            #[cfg(feature = "async")]
+            pending_ffi: Rc::new(Cell::new(0)),
+            #[cfg(feature = "async")]
+            go_caller,
             code,
             gcc,
             ffi_factory,
             fs,
-            next_id: Cell::new(0),
+            opts,
+            stepper: None,
+            #[cfg(feature = "async")]
+            stack_pool: Rc::new(RefCell::new(vec![])),
+            next_id: Rc::new(Cell::new(0)),
+            #[cfg(feature = "async")]
+            live: Rc::new(Cell::new(0)),
+            fuel: Rc::new(Cell::new(opts.max_instructions.unwrap_or(0))),
+            usage: Rc::new(Usage::default()),
+            error: Rc::new(RefCell::new(None)),
+            #[cfg(feature = "race")]
+            race: Rc::new(RefCell::new(RaceDetector::new())),
         }
     }
 
+    /// Makes the fibers stop at every instruction for `stepper`, if any
+    fn with_stepper(mut self, stepper: Option<&'a Stepper<'a>>) -> Context<'a> {
+        self.stepper = stepper;
+        self
+    }
+
     fn new_entry_frame(&self, entry: FunctionKey) -> CallFrame {
         let cls = ClosureObj::gos_from_func(entry, &self.code.objects.functions, None);
         CallFrame::with_closure(cls, 0)
     }
 
+    /// Returns the id of the new fiber
     #[cfg(feature = "async")]
-    fn spawn_fiber(&self, stack: Stack, first_frame: CallFrame) {
+    fn spawn_fiber(&self, stack: Stack, first_frame: CallFrame, quota: Option<Quota>) -> usize {
         let mut f = Fiber::new(self.clone(), stack, first_frame);
-        self.exec
-            .spawn(async move {
-                // let parent fiber go first
-                future::yield_now().await;
-                f.main_loop().await;
-            })
-            .detach();
+        f.quota = QuotaUsage::new(quota);
+        let id = f._id();
+        self.exec.spawn(async move {
+            // let parent fiber go first
+            future::yield_now().await;
+            if let Some(p) = f.main_loop().await {
+                f.context.report_panic(p, id);
+            }
+            f.recycle();
+        });
+        id
+    }
+
+    /// Runs the fibers until they all finish or block, the run is stopped, or `done`
+    #[cfg(feature = "async")]
+    fn run_executor<F: Fn() -> bool>(&self, done: F) {
+        let ctl = &self.opts.controller;
+        loop {
+            if ctl.shutdown_requested() && ctl.shutdown_overdue() {
+                self.stop(RunError::Shutdown { completed: false });
+            }
+            if self.stopped() || done() {
+                break;
+            }
+            if !self.exec.try_tick() {
+                if ctl.cancel_requested() {
+                    // the fibers left are blocked, they can't unwind
+                    self.stop(RunError::Cancelled);
+                    break;
+                }
+                // the fibers left all sleep in the timers of package time, if any
+                let timers_only = self.live.get() == self.exec.timers().daemons();
+                if self.pending_ffi.get() == 0 || timers_only {
+                    break;
+                }
+                // all fibers are waiting for async FFI calls to complete
+                self.exec.wait();
+            }
+        }
+    }
+
+    /// Runs the calls made through the GoCaller of the FFIs, each in a new fiber
+    #[cfg(feature = "async")]
+    fn serve_go_calls(&self, calls: async_channel::Receiver<GoCall>) {
+        let ctx = self.clone();
+        self.exec.spawn(async move {
+            while let Ok(call) = calls.recv().await {
+                ctx.spawn_call(call);
+            }
+        });
+    }
+
+    #[cfg(feature = "async")]
+    fn spawn_call(&self, call: GoCall) {
+        let GoCall {
+            closure,
+            args,
+            quota,
+            reply,
+        } = call;
+        let (frame, vec) = match self.call_frame(&closure, args) {
+            Ok(x) => x,
+            Err(e) => {
+                let _ = reply.try_send(Err(e.into()));
+                return;
+            }
+        };
+        let ret_count = self.code.objects.functions[frame.func()].ret_count();
+        let mut f = Fiber::new(self.clone(), self.new_stack(vec), frame);
+        f.quota = QuotaUsage::new(quota.or(self.opts.goroutine_quota));
+        self.exec.spawn(async move {
+            let result = match f.main_loop().await {
+                None if f.context.stopped() => Err("the VM has stopped".to_owned().into()),
+                None => Ok(f.stack.borrow_mut().move_vec(0, ret_count)),
+                Some(p) => Err(format!("panic: {}", p.msg).into()),
+            };
+            f.recycle();
+            let _ = reply.try_send(result);
+        });
+    }
+
+    /// The frame calling the Go function `closure` with `args`, with the bottom of
+    /// its stack, the zero results followed by the receiver and the arguments
+    fn call_frame(
+        &self,
+        closure: &GosValue,
+        args: Vec<GosValue>,
+    ) -> std::result::Result<(CallFrame, Vec<GosValue>), String> {
+        let gosc = match closure.as_closure().map(|c| &c.0) {
+            Some(ClosureObj::Gos(c)) => c.clone(),
+            _ => return Err("not a non-nil Go function".to_owned()),
+        };
+        let func = &self.code.objects.functions[gosc.func];
+        let mut vec: Vec<GosValue> = func
+            .ret_zeros
+            .iter()
+            .map(|x| x.copy_semantic(&self.gcc))
+            .collect();
+        vec.extend(gosc.recv.clone());
+        vec.extend(args);
+        if vec.len() != (func.ret_count() + func.param_count()) as usize {
+            return Err("wrong number of arguments".to_owned());
+        }
+        let mut frame = CallFrame::with_closure(ClosureObj::Gos(gosc.clone()), 0);
+        if let Some(uvs) = &gosc.uvs {
+            // pointed to the stack of the new fiber by Fiber::new
+            frame.bind_up_values(uvs, func, Weak::new());
+        }
+        Ok((frame, vec))
+    }
+
+    /// Runs the initializers of the packages not initialized yet
+    #[cfg_attr(feature = "async", go_pmacro::async_fn)]
+    fn init_packages(&self) -> std::result::Result<(), CallError> {
+        let size = self.opts.stack_size.main;
+        let init = self.new_entry_frame(self.code.init_entry);
+        let mut fiber = Fiber::new(self.clone(), Stack::with_size(size), init);
+        #[cfg(feature = "async")]
+        let panic = fiber.main_loop().await;
+        #[cfg(not(feature = "async"))]
+        let panic = fiber.main_loop();
+        fiber.recycle();
+        match panic {
+            Some(p) => Err(CallError::Panic(p.msg.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs the initializers of the packages not initialized yet, then the function
+    /// `func` of the package `pkg` with `args`, for `call`
+    #[cfg_attr(feature = "async", go_pmacro::async_fn)]
+    fn call_entry(
+        &self,
+        pkg: &str,
+        func: &str,
+        args: Vec<GosValue>,
+    ) -> std::result::Result<Vec<GosValue>, CallError> {
+        #[cfg(feature = "async")]
+        self.init_packages().await?;
+        #[cfg(not(feature = "async"))]
+        self.init_packages()?;
+        #[cfg(feature = "async")]
+        return self.call_func(pkg, func, args).await;
+        #[cfg(not(feature = "async"))]
+        return self.call_func(pkg, func, args);
+    }
+
+    /// Calls the function `func` of the package `pkg` with `args`, the packages are
+    /// initialized
+    #[cfg_attr(feature = "async", go_pmacro::async_fn)]
+    fn call_func(
+        &self,
+        pkg: &str,
+        func: &str,
+        args: Vec<GosValue>,
+    ) -> std::result::Result<Vec<GosValue>, CallError> {
+        let objs = &self.code.objects;
+        let size = self.opts.stack_size.main;
+        // the variables holding functions are set by the initializers
+        let name = format!("{}.{}", pkg, func);
+        let closure = objs
+            .packages
+            .vec()
+            .iter()
+            .filter(|p| p.name() == pkg)
+            .find_map(|p| p.member_index(func).map(|&i| p.member(i).clone()))
+            .ok_or_else(|| CallError::NotFound(name.clone()))?;
+        let fkey = match closure.typ() {
+            ValueType::Closure => match closure.as_closure().map(|c| &c.0) {
+                Some(ClosureObj::Gos(c)) => c.func,
+                _ => return Err(CallError::NotFound(name)),
+            },
+            _ => return Err(CallError::NotFound(name)),
+        };
+        let metas = &objs.metas;
+        let params = &metas[objs.functions[fkey].meta.key].as_signature().params;
+        if args.len() != params.len() {
+            return Err(CallError::Arguments(format!(
+                "{} takes {} arguments, {} given",
+                name,
+                params.len(),
+                args.len()
+            )));
+        }
+        for (i, (arg, param)) in args.iter().zip(params.iter()).enumerate() {
+            let want = param.value_type(metas);
+            if arg.typ() != want {
+                return Err(CallError::Arguments(format!(
+                    "argument {} of {}: {:?} given, {:?} expected",
+                    i + 1,
+                    name,
+                    arg.typ(),
+                    want
+                )));
+            }
+        }
+
+        let (frame, vec) = self
+            .call_frame(&closure, args)
+            .map_err(CallError::Arguments)?;
+        let ret_count = objs.functions[frame.func()].ret_count();
+        let mut fiber = Fiber::new(self.clone(), Stack::with_vec(vec, size), frame);
+        #[cfg(feature = "async")]
+        let panic = fiber.main_loop().await;
+        #[cfg(not(feature = "async"))]
+        let panic = fiber.main_loop();
+        let result = match panic {
+            None => Ok(fiber.stack.borrow_mut().move_vec(0, ret_count)),
+            Some(p) => Err(CallError::Panic(p.msg.to_string())),
+        };
+        fiber.recycle();
+        result
+    }
+
+    /// A stack for a new goroutine, with `vec` at the bottom
+    #[cfg(feature = "async")]
+    fn new_stack(&self, vec: Vec<GosValue>) -> Stack {
+        let size = self.opts.stack_size.goroutine;
+        match self.stack_pool.borrow_mut().pop() {
+            Some(stack) => stack.reuse(vec, size),
+            None => Stack::with_vec(vec, size),
+        }
+    }
+
+    #[cfg(feature = "async")]
+    fn put_stack(&self, mut stack: Stack) {
+        let mut pool = self.stack_pool.borrow_mut();
+        // the big ones are left for the allocator to take back
+        if pool.len() < STACK_POOL_SIZE && stack.capacity() <= self.opts.stack_size.goroutine * 4 {
+            stack.clear();
+            pool.push(stack);
+        }
+    }
+
+    /// Reports whether the sandbox policy, if any, lets the script start one more
+    /// goroutine
+    #[cfg(feature = "async")]
+    fn may_spawn(&self) -> bool {
+        match &self.opts.sandbox {
+            Some(policy) => policy.check(&Capability::Spawn(self.live.get() + 1)),
+            None => true,
+        }
+    }
+
+    #[inline]
+    fn stopped(&self) -> bool {
+        self.error.borrow().is_some()
+    }
+
+    /// Charges `count` instructions to the budget of the run, stops it if there are
+    /// not that many left
+    #[inline]
+    fn burn_fuel(&self, count: usize) {
+        if let Some(max) = self.opts.max_instructions {
+            match self.fuel.get().checked_sub(count as u64) {
+                Some(left) => self.fuel.set(left),
+                None => self.stop(RunError::OutOfFuel(max)),
+            }
+        }
+    }
+
+    /// Stops all the fibers, at their next safepoints
+    fn stop(&self, e: RunError) {
+        self.error.borrow_mut().get_or_insert(e);
+    }
+
+    /// Checks an allocation of `bytes` against `max_alloc` and the allocator, returns
+    /// what to do instead if it's not to be made.
+    fn check_alloc(&self, bytes: usize) -> Option<(OomAction, AllocFailure)> {
+        let over_limit = self.opts.max_alloc.is_some_and(|max| bytes > max);
+        // probing is cheap, the memory is not touched, but only worth it for big ones
+        let failed = !over_limit
+            && bytes >= PROBE_ALLOC_SIZE
+            && Vec::<u8>::new().try_reserve_exact(bytes).is_err();
+        if !over_limit && !failed {
+            return None;
+        }
+        let failure = AllocFailure { bytes, over_limit };
+        let action = match &self.opts.oom_policy {
+            OomPolicy::Panic => OomAction::Panic,
+            OomPolicy::Abort => OomAction::Abort,
+            OomPolicy::Ask(f) => f(&failure),
+        };
+        match action {
+            OomAction::Allow if over_limit => None,
+            OomAction::Allow => Some((OomAction::Panic, failure)),
+            _ => Some((action, failure)),
+        }
+    }
+
+    /// Checks an allocation of `bytes` against `max_heap`, collects first if it's
+    /// over it, for the estimate to not count what's been freed.
+    fn over_heap_limit(&self, bytes: usize) -> bool {
+        match self.opts.max_heap {
+            Some(max) if self.gcc.heap_bytes().saturating_add(bytes) > max => {
+                collect(&self.gcc);
+                self.gcc.heap_bytes().saturating_add(bytes) > max
+            }
+            _ => false,
+        }
+    }
+
+    fn report_panic(&self, p: PanicData, goroutine: usize) {
+        match &self.opts.panic_hook {
+            Some(hook) => {
+                let call_stack = p
+                    .call_stack
+                    .iter()
+                    .map(|(fkey, pc)| self.position(*fkey, *pc).map(|p| p.to_string()))
+                    .collect();
+                hook(&PanicInfo {
+                    value: &p.clone().into_value(self.code.runtime_error),
+                    message: p.msg.to_string(),
+                    goroutine,
+                    functions: self.func_names(p.call_stack.iter().map(|(f, _)| *f)),
+                    call_stack,
+                    locals: p.locals.clone(),
+                });
+            }
+            None => {
+                println!("panic: {}", p.msg);
+                self.print_call_stack(&p.call_stack, &p.locals);
+            }
+        }
+    }
+
+    /// The message of the Go panic for a runtime error at `pc`, a borrow conflict also
+    /// names where the access was made
+    fn error_msg(&self, msg: &str, fkey: FunctionKey, pc: OpIndex) -> String {
+        match self.position(fkey, pc) {
+            Some(p) if msg.starts_with(BORROW_CONFLICT) => format!("{} at {}", msg, p),
+            _ => msg.to_owned(),
+        }
+    }
+
+    fn position(&self, fkey: FunctionKey, pc: OpIndex) -> Option<FilePos> {
+        source_pos(self.code, self.fs, fkey, pc)
+    }
+
+    fn func_names(&self, funcs: impl Iterator<Item = FunctionKey>) -> Vec<String> {
+        let objs = &self.code.objects;
+        funcs.map(|f| objs.functions[f].name.to_string()).collect()
+    }
+
+    fn print_call_stack(
+        &self,
+        call_stack: &[(FunctionKey, OpIndex)],
+        locals: &[Vec<(String, String)>],
+    ) {
+        for (i, (fkey, pc)) in call_stack.iter().enumerate() {
+            println!("{}()", self.code.objects.functions[*fkey].name);
+            match self.position(*fkey, *pc) {
+                Some(p) => println!("\t{}", p),
+                None => println!("\t<no debug info available>"),
+            }
+            for (name, val) in locals.get(i).into_iter().flatten() {
+                println!("\t\t{} = {}", name, val);
+            }
+        }
+    }
+
+    #[cfg(feature = "race")]
+    fn report_race(&self, race: &Race, call_stack: &[(FunctionKey, OpIndex)]) {
+        let kind = |write: bool| if write { "write" } else { "read" };
+        println!("==================");
+        println!("WARNING: DATA RACE");
+        let current = &race.current;
+        println!("{} by goroutine {}:", kind(current.write), current.fiber);
+        self.print_call_stack(call_stack, &[]);
+        let previous = &race.previous;
+        println!(
+            "previous {} by goroutine {}:",
+            kind(previous.write),
+            previous.fiber
+        );
+        // only where it was made, the stack is gone
+        self.print_call_stack(&[previous.pc], &[]);
+        println!("==================");
     }
 }
 
 struct Fiber<'a> {
     stack: Rc<RefCell<Stack>>,
-    rstack: RangeStack,
+    // created on the first range statement, most goroutines never need it
+    rstack: Option<Box<RangeStack>>,
     frames: Vec<CallFrame>,
     context: Context<'a>,
+    quota: QuotaUsage,
     _id: usize,
 }
 
@@ -322,19 +1617,114 @@ impl<'a> Fiber<'a> {
     fn new(context: Context<'a>, stack: Stack, first_frame: CallFrame) -> Fiber<'a> {
         let _id = context.next_id.get();
         context.next_id.set(_id + 1);
+        #[cfg(feature = "async")]
+        context.live.set(context.live.get() + 1);
+        let stack = Rc::new(RefCell::new(stack));
+        // The upvalues of the locals of a goroutine were created on the stack of the
+        // parent, point them to the stack of the new fiber.
+        if let Some(ptrs) = &first_frame.var_ptrs {
+            let func = &context.code.objects.functions[first_frame.func()];
+            for (p, uv) in func.up_ptrs.iter().zip(ptrs.iter()) {
+                if p.is_local {
+                    if let UpValueState::Open(d) = &mut uv.inner.borrow_mut() as &mut UpValueState {
+                        d.stack = Rc::downgrade(&stack);
+                        d.stack_base = first_frame.stack_base;
+                    }
+                }
+            }
+        }
         Fiber {
-            stack: Rc::new(RefCell::new(stack)),
-            rstack: RangeStack::new(),
+            stack,
+            rstack: None,
             frames: vec![first_frame],
             context,
+            quota: QuotaUsage::default(),
+            _id,
+        }
+    }
+
+    /// Gives the stack back to the context after the goroutine is done. The upvalues
+    /// still pointing to it hold weak references, which are not upgraded after this.
+    fn recycle(self) {
+        let Fiber {
+            stack,
+            frames,
+            context,
             _id,
+            ..
+        } = self;
+        drop(frames);
+        #[cfg(feature = "race")]
+        context
+            .race
+            .borrow_mut()
+            .exit(_id, Rc::as_ptr(&stack) as usize);
+        let peak = &context.usage.peak_stack;
+        peak.set(peak.get().max(stack.borrow().len()));
+        #[cfg(feature = "async")]
+        {
+            context.live.set(context.live.get() - 1);
+            if let Ok(stack) = Rc::try_unwrap(stack) {
+                context.put_stack(stack.into_inner());
+            }
+        }
+    }
+
+    /// Blocks at a safepoint until the host resumes the VM
+    fn pause(&self) {
+        let ctx = &self.context;
+        let call_stack = self
+            .frames
+            .iter()
+            .rev()
+            .map(|f| ctx.position(f.func(), f.pc - 1).map(|p| p.to_string()))
+            .collect();
+        let stack = self.stack.borrow();
+        let locals = self
+            .frames
+            .iter()
+            .rev()
+            .map(|f| frame_locals(&ctx.code.objects, f.func(), f.pc - 1, &stack, f.stack_base))
+            .collect();
+        drop(stack);
+        ctx.opts.controller.park(PauseInfo {
+            goroutine: self._id,
+            functions: ctx.func_names(self.frames.iter().rev().map(|f| f.func())),
+            call_stack,
+            locals,
+        });
+    }
+
+    #[cfg(feature = "race")]
+    fn race_access(&self, cell: RaceCell, write: bool) {
+        let objs = &self.context.code.objects;
+        let func = &objs.functions[self.frames.last().unwrap().func()];
+        let mut detector = self.context.race.borrow_mut();
+        let pkg = objs.packages.vec().get(func.package.as_usize());
+        if pkg.is_some_and(|p| p.name() == "atomic") {
+            detector.atomic(self._id, cell, write);
+            return;
+        }
+        detector.forget_objects(self.context.gcc.take_made().into_iter());
+        let frame = self.frames.last().unwrap();
+        let race = detector.access(self._id, cell, write, (frame.func(), frame.pc - 1));
+        drop(detector);
+        if let Some(race) = race {
+            let call_stack: Vec<(FunctionKey, OpIndex)> = self
+                .frames
+                .iter()
+                .rev()
+                .map(|f| (f.func(), f.pc - 1))
+                .collect();
+            self.context.report_race(&race, &call_stack);
         }
     }
 
+    /// Runs the fiber to the end, returns the panic that ended it if not recovered
     #[cfg_attr(feature = "async", go_pmacro::async_fn)]
-    fn main_loop(&mut self) {
+    fn main_loop(&mut self) -> Option<PanicData> {
         let ctx = &self.context;
-        let gcc = ctx.gcc;
+        let gcc = &*ctx.gcc;
         let objs: &VMObjects = &ctx.code.objects;
         let caller: &ArrCaller = &objs.arr_slice_caller;
         let consts = &ctx.code.consts;
@@ -348,25 +1738,70 @@ impl<'a> Fiber<'a> {
 
         let mut stack_mut_ref = self.stack.borrow_mut();
         let mut stack: &mut Stack = &mut stack_mut_ref;
-        // allocate local variables
-        stack.set_vec(func.param_count(), func.local_zeros.clone());
+        // allocate local variables, after the results and the parameters of a function
+        // called by the host
+        stack.set_min_size((sb + func.max_write_index + 1) as usize);
+        let locals = sb + func.ret_count() + func.param_count();
+        stack.set_vec(locals, func.local_zeros.to_vec());
 
         let mut code = &func.code;
 
-        let mut total_inst = 0;
+        // kept across the safepoints, a panic may unwind through long deferred calls
+        let mut panic: Option<PanicData> = None;
         //let mut stats: Map<Opcode, usize> = Map::new();
         loop {
+            let exceeded = match panic {
+                None => self
+                    .quota
+                    .exceeded()
+                    .or_else(|| ctx.over_heap_limit(0).then(|| OUT_OF_MEMORY.to_owned())),
+                Some(_) => None,
+            };
+            let ctl = &ctx.opts.controller;
+            let exiting = panic.is_none() && (ctl.shutdown_requested() || ctl.cancel_requested());
             let mut frame = self.frames.last_mut().unwrap();
+            if let Some(msg) = exceeded {
+                go_panic_str!(panic, &msg, frame, code);
+            } else if exiting {
+                go_panic!(panic, GosValue::new_nil(ValueType::Void), frame, code);
+                panic.as_mut().unwrap().goexit = true;
+            }
             let mut result: Result = Result::Continue;
-            let mut panic: Option<PanicData> = None;
-            let yield_unit = 1024;
-            for _ in 0..yield_unit {
+            // a stepper sees every instruction, with the goroutines switched after each
+            let yield_unit = match ctx.stepper {
+                Some(stepper) => {
+                    let vars = func.ret_count() + func.param_count() + func.local_count();
+                    let size = vars.max(func.max_write_index + 1);
+                    let step = Step {
+                        goroutine: self._id,
+                        depth: frame_height,
+                        func: frame.func(),
+                        pc: frame.pc,
+                        inst: &code[frame.pc as usize],
+                        frame: stack.slice(sb, sb + size),
+                        objects: objs,
+                    };
+                    if !stepper.step(&step) {
+                        return None;
+                    }
+                    1
+                }
+                None => ctx.opts.time_slice.unwrap_or(TIME_SLICE).max(1),
+            };
+            let mut executed = yield_unit;
+            for n in 0..yield_unit {
                 let inst = &code[frame.pc as usize];
                 let inst_op = inst.op0;
-                total_inst += 1;
                 //stats.entry(*inst).and_modify(|e| *e += 1).or_insert(1);
+                #[cfg(feature = "leak_track")]
+                gcc.set_site(frame.func(), frame.pc);
                 frame.pc += 1;
                 //dbg!(inst);
+                #[cfg(feature = "threaded_dispatch")]
+                if let Some(handler) = threaded::HANDLERS[inst_op as usize] {
+                    handler(stack, inst, sb, consts, gcc, &mut frame.pc);
+                    continue;
+                }
                 match inst_op {
                     // desc: local
                     // s0: local/const
@@ -378,17 +1813,32 @@ impl<'a> Fiber<'a> {
                         )
                     }
                     // desc: local
+                    // s0: value
+                    // a struct or an array assigned to a local variable is copied into it in
+                    // place, for the pointers to its fields and elements to see the new value
+                    Opcode::STORE_LOCAL => {
+                        let val = stack.read(inst.s0, sb, consts).copy_semantic(gcc);
+                        match stack.get(inst.d + sb).overwrite(&val, caller) {
+                            Ok(true) => {}
+                            Ok(false) => stack.set(inst.d + sb, val),
+                            Err(e) => go_panic_err!(panic, e, ctx, frame, code),
+                        }
+                    }
+                    // desc: local
                     // s0: slice
                     // s1: index
                     Opcode::LOAD_SLICE => {
                         let slice = stack.read(inst.s0, sb, consts);
                         let index = stack.read(inst.s1, sb, consts).as_index();
                         match slice.slice_array_equivalent(index) {
-                            Ok((array, i)) => match array.caller(caller).array_get(&array, i) {
-                                Ok(val) => stack.set(sb + inst.d, val),
-                                Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
-                            },
-                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                            Ok((array, i)) => {
+                                race_access!(self, frame, RaceCell::element(array, i), false);
+                                match array.caller(caller).array_get(&array, i) {
+                                    Ok(val) => stack.set(sb + inst.d, val),
+                                    Err(e) => go_panic_err!(panic, e, ctx, frame, code),
+                                }
+                            }
+                            Err(e) => go_panic_err!(panic, e, ctx, frame, code),
                         }
                     }
                     // desc: slice
@@ -398,30 +1848,34 @@ impl<'a> Fiber<'a> {
                         let dest = stack.read(inst.d, sb, consts);
                         let index = stack.read(inst.s0, sb, consts).as_index();
                         match dest.slice_array_equivalent(index) {
-                            Ok((array, i)) => match inst.op1 {
-                                Opcode::VOID => {
-                                    let val = stack.read(inst.s1, sb, consts).copy_semantic(gcc);
-                                    let result = array.caller(caller).array_set(&array, &val, i);
-                                    panic_if_err!(result, panic, frame, code);
-                                }
-                                _ => match array.caller(caller).array_get(&array, i) {
-                                    Ok(old) => {
-                                        let val = stack.read_and_op(
-                                            old.data(),
-                                            inst.t0,
-                                            inst.op1,
-                                            inst.s1,
-                                            sb,
-                                            &consts,
-                                        );
-                                        let result =
-                                            array.caller(caller).array_set(&array, &val, i);
-                                        panic_if_err!(result, panic, frame, code);
+                            Ok((array, i)) => {
+                                race_access!(self, frame, RaceCell::element(array, i), true);
+                                match inst.op1 {
+                                    Opcode::VOID => {
+                                        let val =
+                                            stack.read(inst.s1, sb, consts).copy_semantic(gcc);
+                                        let result = array.overwrite_element(&val, i, caller);
+                                        panic_if_err!(result, panic, ctx, frame, code);
                                     }
-                                    Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
-                                },
-                            },
-                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                                    _ => match array.caller(caller).array_get(&array, i) {
+                                        Ok(old) => {
+                                            let val = stack.read_and_op(
+                                                old.data(),
+                                                inst.t0,
+                                                inst.op1,
+                                                inst.s1,
+                                                sb,
+                                                &consts,
+                                            );
+                                            let result =
+                                                array.caller(caller).array_set(&array, &val, i);
+                                            panic_if_err!(result, panic, ctx, frame, code);
+                                        }
+                                        Err(e) => go_panic_err!(panic, e, ctx, frame, code),
+                                    },
+                                }
+                            }
+                            Err(e) => go_panic_err!(panic, e, ctx, frame, code),
                         }
                     }
                     // desc: local
@@ -430,9 +1884,10 @@ impl<'a> Fiber<'a> {
                     Opcode::LOAD_ARRAY => {
                         let array = stack.read(inst.s0, sb, consts);
                         let index = stack.read(inst.s1, sb, consts).as_index();
+                        race_access!(self, frame, RaceCell::element(array, index), false);
                         match array.caller(caller).array_get(&array, index) {
                             Ok(val) => stack.set(inst.d + sb, val),
-                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                            Err(e) => go_panic_err!(panic, e, ctx, frame, code),
                         }
                     }
                     // desc: array
@@ -441,11 +1896,12 @@ impl<'a> Fiber<'a> {
                     Opcode::STORE_ARRAY => {
                         let array = stack.read(inst.d, sb, consts);
                         let index = stack.read(inst.s0, sb, consts).as_index();
+                        race_access!(self, frame, RaceCell::element(array, index), true);
                         match inst.op1 {
                             Opcode::VOID => {
                                 let val = stack.read(inst.s1, sb, consts).copy_semantic(gcc);
-                                let result = array.caller(caller).array_set(&array, &val, index);
-                                panic_if_err!(result, panic, frame, code);
+                                let result = array.overwrite_element(&val, index, caller);
+                                panic_if_err!(result, panic, ctx, frame, code);
                             }
                             _ => match array.caller(caller).array_get(&array, index) {
                                 Ok(old) => {
@@ -459,9 +1915,9 @@ impl<'a> Fiber<'a> {
                                     );
                                     let result =
                                         array.caller(caller).array_set(&array, &val, index);
-                                    panic_if_err!(result, panic, frame, code);
+                                    panic_if_err!(result, panic, ctx, frame, code);
                                 }
-                                Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                                Err(e) => go_panic_err!(panic, e, ctx, frame, code),
                             },
                         }
                     }
@@ -470,18 +1926,27 @@ impl<'a> Fiber<'a> {
                     // inst.s0: map
                     // inst.s1: key
                     // inst_ex.s0: zero_val
+                    // inst.t0: FlagA if the key has to be checked for being hashable
                     Opcode::LOAD_MAP => {
+                        let map = stack.read(inst.s0, sb, consts);
+                        race_access!(self, frame, RaceCell::element(map, 0), false);
                         let inst_ex = &code[frame.pc as usize];
                         frame.pc += 1;
-                        let map = stack.read(inst.s0, sb, consts);
                         let key = stack.read(inst.s1, sb, consts);
-                        let val = match map.as_map() {
-                            Some(map) => map.0.get(&key),
-                            None => None,
-                        };
+                        // Go hashes the key even if the map is nil
+                        let val = check_map_key(inst.t0, key).and_then(|_| match map.as_map() {
+                            Some(map) => map.0.get_hashable(key),
+                            None => Ok(None),
+                        });
                         let (v, ok) = match val {
-                            Some(v) => (v, true),
-                            None => (stack.read(inst_ex.s0, sb, consts).copy_semantic(gcc), false),
+                            Ok(Some(v)) => (v, true),
+                            Ok(None) => {
+                                (stack.read(inst_ex.s0, sb, consts).copy_semantic(gcc), false)
+                            }
+                            Err(e) => {
+                                go_panic_err!(panic, e, ctx, frame, code);
+                                continue;
+                            }
                         };
                         stack.set(inst.d + sb, v);
                         if inst.t1 == ValueType::FlagB {
@@ -492,23 +1957,39 @@ impl<'a> Fiber<'a> {
                     // s0: index
                     // s1: value
                     // inst_ex.s0: zero_val
+                    // inst_ex.t0: FlagA if the key has to be checked for being hashable
                     Opcode::STORE_MAP => {
+                        let dest = stack.read(inst.d, sb, consts);
+                        if !dest.is_nil() {
+                            race_access!(self, frame, RaceCell::element(dest, 0), true);
+                        }
                         let inst_ex = &code[frame.pc as usize];
                         frame.pc += 1;
-                        let dest = stack.read(inst.d, sb, consts);
                         match dest.as_non_nil_map() {
                             Ok(map) => {
                                 let key = stack.read(inst.s0, sb, consts);
+                                if let Err(e) = check_map_key(inst_ex.t0, key) {
+                                    go_panic_err!(panic, e, ctx, frame, code);
+                                    continue;
+                                }
                                 match inst.op1 {
                                     Opcode::VOID => {
                                         let val =
                                             stack.read(inst.s1, sb, consts).copy_semantic(gcc);
-                                        map.0.insert(key.clone(), val);
+                                        let result = map.0.insert_hashable(key.clone(), val);
+                                        if let Ok(None) = result {
+                                            gcc.charge(MAP_ENTRY_SIZE);
+                                        }
+                                        panic_if_err!(result, panic, ctx, frame, code);
                                     }
                                     _ => {
-                                        let old = match map.0.get(&key) {
-                                            Some(v) => v,
-                                            None => stack.read(inst_ex.s0, sb, consts).clone(),
+                                        let old = match map.0.get_hashable(key) {
+                                            Ok(Some(v)) => v,
+                                            Ok(None) => stack.read(inst_ex.s0, sb, consts).clone(),
+                                            Err(e) => {
+                                                go_panic_err!(panic, e, ctx, frame, code);
+                                                continue;
+                                            }
                                         };
                                         let val = stack.read_and_op(
                                             old.data(),
@@ -516,13 +1997,17 @@ impl<'a> Fiber<'a> {
                                             inst.op1,
                                             inst.s1,
                                             sb,
-                                            &consts,
+                                            consts,
                                         );
-                                        map.0.insert(key.clone(), val);
+                                        let result = map.0.insert_hashable(key.clone(), val);
+                                        if let Ok(None) = result {
+                                            gcc.charge(MAP_ENTRY_SIZE);
+                                        }
+                                        panic_if_err!(result, panic, ctx, frame, code);
                                     }
                                 }
                             }
-                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                            Err(e) => go_panic_err!(panic, e, ctx, frame, code),
                         }
                     }
                     // desc: local
@@ -530,32 +2015,57 @@ impl<'a> Fiber<'a> {
                     // s1: index
                     Opcode::LOAD_STRUCT => {
                         let struct_ = stack.read(inst.s0, sb, consts);
-                        let val = struct_.as_struct().0.borrow_fields()[inst.s1 as usize].clone();
-                        stack.set(inst.d + sb, val);
+                        race_access!(
+                            self,
+                            frame,
+                            RaceCell::element(struct_, inst.s1 as usize),
+                            false
+                        );
+                        let result = struct_
+                            .as_struct()
+                            .0
+                            .try_borrow_fields()
+                            .map(|fields| fields[inst.s1 as usize].clone());
+                        match result {
+                            Ok(val) => stack.set(inst.d + sb, val),
+                            Err(e) => go_panic_err!(panic, e, ctx, frame, code),
+                        }
                     }
                     // desc: struct
                     // s0: index
                     // s1: value
                     Opcode::STORE_STRUCT => {
                         let dest = stack.read(inst.d, sb, consts);
+                        race_access!(self, frame, RaceCell::element(dest, inst.s0 as usize), true);
                         match inst.op1 {
                             Opcode::VOID => {
                                 let val = stack.read(inst.s1, sb, consts).copy_semantic(gcc);
-                                dest.as_struct().0.borrow_fields_mut()[inst.s0 as usize] = val;
-                            }
-                            _ => {
-                                let old =
-                                    &mut dest.as_struct().0.borrow_fields_mut()[inst.s0 as usize];
-                                let val = stack.read_and_op(
-                                    old.data(),
-                                    inst.t0,
-                                    inst.op1,
-                                    inst.s1,
-                                    sb,
-                                    &consts,
+                                let result = dest.as_struct().0.try_borrow_fields_mut().and_then(
+                                    |mut fields| {
+                                        let old = &mut fields[inst.s0 as usize];
+                                        if !old.overwrite(&val, caller)? {
+                                            *old = val;
+                                        }
+                                        Ok(())
+                                    },
                                 );
-                                *old = val;
+                                panic_if_err!(result, panic, ctx, frame, code);
                             }
+                            _ => match dest.as_struct().0.try_borrow_fields_mut() {
+                                Ok(mut fields) => {
+                                    let old = &mut fields[inst.s0 as usize];
+                                    let val = stack.read_and_op(
+                                        old.data(),
+                                        inst.t0,
+                                        inst.op1,
+                                        inst.s1,
+                                        sb,
+                                        consts,
+                                    );
+                                    *old = val;
+                                }
+                                Err(e) => go_panic_err!(panic, e, ctx, frame, code),
+                            },
                         }
                     }
                     // desc: local
@@ -571,10 +2081,18 @@ impl<'a> Fiber<'a> {
                         );
                         match struct_ {
                             Ok(s) => {
-                                let val = s.as_struct().0.borrow_fields()[index].clone();
-                                stack.set(inst.d + sb, val);
+                                race_access!(self, frame, RaceCell::element(&s, index), false);
+                                let result = s
+                                    .as_struct()
+                                    .0
+                                    .try_borrow_fields()
+                                    .map(|fields| fields[index].clone());
+                                match result {
+                                    Ok(val) => stack.set(inst.d + sb, val),
+                                    Err(e) => go_panic_err!(panic, e, ctx, frame, code),
+                                }
                             }
-                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                            Err(e) => go_panic_err!(panic, e, ctx, frame, code),
                         }
                     }
                     // desc: struct
@@ -589,25 +2107,42 @@ impl<'a> Fiber<'a> {
                             objs,
                         );
                         match struct_ {
-                            Ok(s) => match inst.op1 {
-                                Opcode::VOID => {
-                                    let val = stack.read(inst.s1, sb, consts).copy_semantic(gcc);
-                                    s.as_struct().0.borrow_fields_mut()[index] = val;
-                                }
-                                _ => {
-                                    let old = &s.as_struct().0.borrow_fields()[index as usize];
-                                    let val = stack.read_and_op(
-                                        old.data(),
-                                        inst.t0,
-                                        inst.op1,
-                                        inst.s1,
-                                        sb,
-                                        &consts,
-                                    );
-                                    s.as_struct().0.borrow_fields_mut()[index as usize] = val;
+                            Ok(s) => {
+                                race_access!(self, frame, RaceCell::element(&s, index), true);
+                                match inst.op1 {
+                                    Opcode::VOID => {
+                                        let val =
+                                            stack.read(inst.s1, sb, consts).copy_semantic(gcc);
+                                        let result =
+                                            s.as_struct().0.try_borrow_fields_mut().and_then(
+                                                |mut fields| {
+                                                    let old = &mut fields[index];
+                                                    if !old.overwrite(&val, caller)? {
+                                                        *old = val;
+                                                    }
+                                                    Ok(())
+                                                },
+                                            );
+                                        panic_if_err!(result, panic, ctx, frame, code);
+                                    }
+                                    _ => match s.as_struct().0.try_borrow_fields_mut() {
+                                        Ok(mut fields) => {
+                                            let old = &mut fields[index];
+                                            let val = stack.read_and_op(
+                                                old.data(),
+                                                inst.t0,
+                                                inst.op1,
+                                                inst.s1,
+                                                sb,
+                                                consts,
+                                            );
+                                            *old = val;
+                                        }
+                                        Err(e) => go_panic_err!(panic, e, ctx, frame, code),
+                                    },
                                 }
-                            },
-                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                            }
+                            Err(e) => go_panic_err!(panic, e, ctx, frame, code),
                         }
                     }
                     // desc: local
@@ -617,6 +2152,12 @@ impl<'a> Fiber<'a> {
                         let src = stack.read(inst.s0, sb, consts);
                         let index = inst.s1;
                         let pkg = &objs.packages[*src.as_package()];
+                        race_access!(
+                            self,
+                            frame,
+                            RaceCell::package(*src.as_package(), index),
+                            false
+                        );
                         let val = pkg.member(index).clone();
                         stack.set(inst.d + sb, val);
                     }
@@ -626,12 +2167,23 @@ impl<'a> Fiber<'a> {
                     Opcode::STORE_PKG => {
                         let dest = stack.read(inst.d, sb, consts);
                         let index = inst.s0;
+                        race_access!(
+                            self,
+                            frame,
+                            RaceCell::package(*dest.as_package(), index),
+                            true
+                        );
 
                         let pkg = &objs.packages[*dest.as_package()];
                         match inst.op1 {
                             Opcode::VOID => {
                                 let val = stack.read(inst.s1, sb, consts).copy_semantic(gcc);
-                                *pkg.member_mut(index) = val;
+                                let mut old = pkg.member_mut(index);
+                                match old.overwrite(&val, caller) {
+                                    Ok(true) => {}
+                                    Ok(false) => *old = val,
+                                    Err(e) => go_panic_err!(panic, e, ctx, frame, code),
+                                }
                             }
                             _ => {
                                 let mut old = pkg.member_mut(index);
@@ -652,17 +2204,24 @@ impl<'a> Fiber<'a> {
                     Opcode::LOAD_POINTER => {
                         let src = stack.read(inst.s0, sb, consts);
                         match src.as_non_nil_pointer() {
-                            Ok(p) => match p.deref(stack, &objs.packages) {
-                                Ok(val) => stack.set(inst.d + sb, val),
-                                Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
-                            },
-                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                            Ok(p) => {
+                                race_access!(self, frame, RaceCell::pointee(p), false);
+                                match p.deref(stack, &objs.packages) {
+                                    Ok(val) => stack.set(inst.d + sb, val),
+                                    Err(e) => go_panic_err!(panic, e, ctx, frame, code),
+                                }
+                            }
+                            Err(e) => go_panic_err!(panic, e, ctx, frame, code),
                         }
                     }
                     // desc: pointer
                     // s0: value
                     Opcode::STORE_POINTER => {
                         let dest = stack.read(inst.d, sb, consts).clone();
+                        #[cfg(feature = "race")]
+                        if let Ok(p) = dest.as_non_nil_pointer() {
+                            race_access!(self, frame, RaceCell::pointee(p), true);
+                        }
                         let result = dest.as_non_nil_pointer().and_then(|p| {
                             let val = match inst.op1 {
                                 Opcode::VOID => stack.read(inst.s0, sb, consts).copy_semantic(gcc),
@@ -678,6 +2237,11 @@ impl<'a> Fiber<'a> {
                                     )
                                 }
                             };
+                            // a struct or an array is overwritten in place, for the pointers
+                            // to its fields and elements to see the new value
+                            if p.deref(stack, &objs.packages)?.overwrite(&val, caller)? {
+                                return Ok(());
+                            }
                             match p {
                                 PointerObj::UpVal(uv) => {
                                     uv.set_value(val, stack);
@@ -689,7 +2253,7 @@ impl<'a> Fiber<'a> {
                                     array.caller(caller).array_set(&array, &val, index)
                                 }
                                 PointerObj::StructField(s, index) => {
-                                    s.as_struct().0.borrow_fields_mut()[*index as usize] = val;
+                                    s.as_struct().0.try_borrow_fields_mut()?[*index as usize] = val;
                                     Ok(())
                                 }
                                 PointerObj::PkgMember(p, index) => {
@@ -699,22 +2263,38 @@ impl<'a> Fiber<'a> {
                                 }
                             }
                         });
-                        panic_if_err!(result, panic, frame, code);
+                        panic_if_err!(result, panic, ctx, frame, code);
                     }
                     // desc: local
                     // s0: upvalue
                     Opcode::LOAD_UP_VALUE => {
+                        race_access!(
+                            self,
+                            frame,
+                            RaceCell::up_value(&frame.var_ptrs.as_ref().unwrap()[inst.s0 as usize]),
+                            false
+                        );
                         let uvs = frame.var_ptrs.as_ref().unwrap();
                         let val = uvs[inst.s0 as usize].value(stack).into_owned();
                         stack.set(inst.d + sb, val);
                     }
                     Opcode::STORE_UP_VALUE => {
+                        race_access!(
+                            self,
+                            frame,
+                            RaceCell::up_value(&frame.var_ptrs.as_ref().unwrap()[inst.d as usize]),
+                            true
+                        );
                         let uvs = frame.var_ptrs.as_ref().unwrap();
                         let uv = &uvs[inst.d as usize];
                         match inst.op1 {
                             Opcode::VOID => {
                                 let val = stack.read(inst.s0, sb, consts).copy_semantic(gcc);
-                                uv.set_value(val, stack);
+                                match uv.value(stack).overwrite(&val, caller) {
+                                    Ok(true) => {}
+                                    Ok(false) => uv.set_value(val, stack),
+                                    Err(e) => go_panic_err!(panic, e, ctx, frame, code),
+                                }
                             }
                             _ => {
                                 let old = uv.value(stack);
@@ -730,7 +2310,12 @@ impl<'a> Fiber<'a> {
                             }
                         }
                     }
-                    Opcode::ADD => binary_op!(stack, binary_op_add, inst, sb, consts),
+                    Opcode::ADD => {
+                        binary_op!(stack, binary_op_add, inst, sb, consts);
+                        if inst.t0 == ValueType::String {
+                            gcc.charge(stack.get(inst.d + sb).len());
+                        }
+                    }
                     Opcode::SUB => binary_op!(stack, binary_op_sub, inst, sb, consts),
                     Opcode::MUL => binary_op!(stack, binary_op_mul, inst, sb, consts),
                     Opcode::QUO => binary_op!(stack, binary_op_quo, inst, sb, consts),
@@ -741,7 +2326,12 @@ impl<'a> Fiber<'a> {
                     Opcode::AND_NOT => binary_op!(stack, binary_op_and_not, inst, sb, consts),
                     Opcode::SHL => shift_op!(stack, binary_op_shl, inst, sb, consts),
                     Opcode::SHR => shift_op!(stack, binary_op_shr, inst, sb, consts),
-                    Opcode::ADD_ASSIGN => binary_op_assign!(stack, binary_op_add, inst, sb, consts),
+                    Opcode::ADD_ASSIGN => {
+                        binary_op_assign!(stack, binary_op_add, inst, sb, consts);
+                        if inst.t0 == ValueType::String {
+                            gcc.charge(stack.get(inst.d + sb).len());
+                        }
+                    }
                     Opcode::SUB_ASSIGN => binary_op_assign!(stack, binary_op_sub, inst, sb, consts),
                     Opcode::MUL_ASSIGN => binary_op_assign!(stack, binary_op_mul, inst, sb, consts),
                     Opcode::QUO_ASSIGN => binary_op_assign!(stack, binary_op_quo, inst, sb, consts),
@@ -771,7 +2361,7 @@ impl<'a> Fiber<'a> {
                         let eq = if inst.t0.copyable() && inst.t0 == inst.t1 {
                             a.data().compare_eql(b.data(), inst.t0)
                         } else {
-                            a.eq(b)
+                            values_eq(a, b, &objs.metas)
                         };
                         stack.set(inst.d + sb, eq.into());
                     }
@@ -781,7 +2371,7 @@ impl<'a> Fiber<'a> {
                         let neq = if inst.t0.copyable() {
                             a.data().compare_neq(b.data(), inst.t0)
                         } else {
-                            !a.eq(b)
+                            !values_eq(a, b, &objs.metas)
                         };
                         stack.set(inst.d + sb, neq.into());
                     }
@@ -838,6 +2428,11 @@ impl<'a> Fiber<'a> {
                             GosValue::new_pointer(PointerObj::UpVal(upvalue.clone())),
                         );
                     }
+                    // s0: the first local
+                    // s1: the end of the locals, exclusive
+                    Opcode::CLOSE_UP_VALUES => {
+                        frame.close_up_values(inst.s0, inst.s1, func, stack, &self.stack);
+                    }
                     Opcode::REF_SLICE_MEMBER => {
                         let arr_or_slice = stack.read(inst.s0, sb, consts).clone();
                         let index = stack.read(inst.s1, sb, consts).as_index() as OpIndex;
@@ -849,7 +2444,7 @@ impl<'a> Fiber<'a> {
                         ) {
                             Ok(p) => stack.set(inst.d + sb, GosValue::new_pointer(p)),
                             Err(e) => {
-                                go_panic_str!(panic, e.as_str(), frame, code)
+                                go_panic_err!(panic, e, ctx, frame, code)
                             }
                         }
                     }
@@ -878,7 +2473,7 @@ impl<'a> Fiber<'a> {
                                     )),
                                 );
                             }
-                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                            Err(e) => go_panic_err!(panic, e, ctx, frame, code),
                         }
                     }
                     Opcode::REF_PKG_MEMBER => {
@@ -892,30 +2487,43 @@ impl<'a> Fiber<'a> {
                     Opcode::SEND => go_panic_no_async!(panic, frame, code),
                     #[cfg(feature = "async")]
                     Opcode::SEND => {
-                        let chan = stack.read(inst.s0, sb, consts).as_channel().cloned();
-                        let val = stack.read(inst.s1, sb, consts).clone();
+                        let chan_val = stack.read(inst.s0, sb, consts).clone();
+                        let val = stack.read(inst.s1, sb, consts).copy_semantic(gcc);
                         drop(stack_mut_ref);
-                        let re = match chan {
-                            Some(c) => c.send(&val).await,
+                        let re = match chan_val.as_channel() {
+                            Some(c) => {
+                                race_sync!(self, release, chan_val);
+                                let re = c.send(&val).await;
+                                race_sync!(self, acquire, chan_val);
+                                re
+                            }
                             None => loop {
                                 future::yield_now().await;
                             },
                         };
                         restore_stack_ref!(self, stack, stack_mut_ref);
-                        panic_if_err!(re, panic, frame, code);
+                        panic_if_err!(re, panic, ctx, frame, code);
                     }
                     #[cfg(not(feature = "async"))]
                     Opcode::RECV => go_panic_no_async!(panic, frame, code),
+                    // t1: FlagA: value, FlagB: value and ok, FlagC: ok only, FlagD: nothing
+                    // the zero value of a closed channel is not made if it's discarded
                     #[cfg(feature = "async")]
                     Opcode::RECV => {
-                        match stack.read(inst.s0, sb, consts).as_channel().cloned() {
+                        let chan_val = stack.read(inst.s0, sb, consts).clone();
+                        match chan_val.as_channel() {
                             Some(chan) => {
                                 drop(stack_mut_ref);
+                                race_sync!(self, release, chan_val);
                                 let val = chan.recv().await;
+                                race_sync!(self, acquire, chan_val);
                                 restore_stack_ref!(self, stack, stack_mut_ref);
-                                let (unwrapped, ok) = unwrap_recv_val!(chan, val, gcc);
-                                stack.set(inst.d + sb, unwrapped);
-                                if inst.t1 == ValueType::FlagB {
+                                let ok = val.is_some();
+                                if let ValueType::FlagA | ValueType::FlagB = inst.t1 {
+                                    let unwrapped = unwrap_recv_val!(chan, val, objs, gcc);
+                                    stack.set(inst.d + sb, unwrapped);
+                                }
+                                if let ValueType::FlagB | ValueType::FlagC = inst.t1 {
                                     stack.set(inst.s1 + sb, ok.into());
                                 }
                             }
@@ -944,11 +2552,21 @@ impl<'a> Fiber<'a> {
                         match &cls {
                             ClosureObj::Gos(gosc) => {
                                 let next_func = &objs.functions[gosc.func];
-                                let mut returns_recv = next_func.ret_zeros.clone();
+                                // named results are variables, each call needs its own
+                                let mut returns_recv: Vec<GosValue> = next_func
+                                    .ret_zeros
+                                    .iter()
+                                    .map(|x| x.copy_semantic(gcc))
+                                    .collect();
                                 if let Some(r) = &gosc.recv {
-                                    // push receiver on stack as the first parameter
+                                    // push receiver on stack as the first parameter, the call
+                                    // site puts the arguments right after the results
                                     // don't call copy_semantic because BIND_METHOD did it already
+                                    let begin = next_sb + next_func.ret_count();
+                                    let args =
+                                        stack.move_vec(begin, begin + next_func.param_count() - 1);
                                     returns_recv.push(r.clone());
+                                    returns_recv.extend(args);
                                 }
                                 stack.set_min_size(
                                     (next_sb + next_func.max_write_index + 1) as usize,
@@ -962,23 +2580,9 @@ impl<'a> Fiber<'a> {
                         match cls {
                             ClosureObj::Gos(gosc) => {
                                 let nfunc = &objs.functions[gosc.func];
-                                if let Some(uvs) = gosc.uvs {
-                                    let mut ptrs: Vec<UpValue> =
-                                        Vec::with_capacity(nfunc.up_ptrs.len());
-                                    for (i, p) in nfunc.up_ptrs.iter().enumerate() {
-                                        ptrs.push(if p.is_local {
-                                            // local pointers
-                                            let uv = UpValue::new(p.clone_with_stack(
-                                                Rc::downgrade(&self.stack),
-                                                nframe.stack_base as OpIndex,
-                                            ));
-                                            nframe.add_referred_by(p.index, p.typ, &uv);
-                                            uv
-                                        } else {
-                                            uvs[&i].clone()
-                                        });
-                                    }
-                                    nframe.var_ptrs = Some(ptrs);
+                                if let Some(uvs) = &gosc.uvs {
+                                    let stack = Rc::downgrade(&self.stack);
+                                    nframe.bind_up_values(uvs, nfunc, stack);
                                 }
                                 match call_style {
                                     ValueType::FlagA => {
@@ -996,14 +2600,27 @@ impl<'a> Fiber<'a> {
                                     #[cfg(feature = "async")]
                                     ValueType::FlagB => {
                                         // goroutine
-                                        let begin = nframe.stack_base;
-                                        let end = begin
-                                            + nfunc.ret_count()
-                                            + nfunc.param_count() as OpIndex;
-                                        let vec = stack.move_vec(begin, end);
-                                        let nstack = Stack::with_vec(vec);
-                                        nframe.stack_base = 0;
-                                        self.context.spawn_fiber(nstack, nframe);
+                                        if !self.context.may_spawn() {
+                                            go_panic_str!(
+                                                panic,
+                                                "too many goroutines",
+                                                frame,
+                                                code
+                                            );
+                                        } else {
+                                            let begin = nframe.stack_base;
+                                            let end = begin
+                                                + nfunc.ret_count()
+                                                + nfunc.param_count() as OpIndex;
+                                            let vec = stack.move_vec(begin, end);
+                                            let nstack = self.context.new_stack(vec);
+                                            nframe.stack_base = 0;
+                                            let quota = self.context.opts.goroutine_quota;
+                                            let _id =
+                                                self.context.spawn_fiber(nstack, nframe, quota);
+                                            #[cfg(feature = "race")]
+                                            self.context.race.borrow_mut().fork(self._id, _id);
+                                        }
                                     }
                                     ValueType::FlagC => {
                                         // deferred
@@ -1024,9 +2641,10 @@ impl<'a> Fiber<'a> {
                             ClosureObj::Ffi(ffic) => {
                                 let sig = objs.metas[ffic.meta.key].as_signature();
                                 let result_begin = nframe.stack_base;
-                                let param_begin = result_begin + 1 + sig.results.len() as OpIndex;
+                                let param_begin = result_begin + sig.results.len() as OpIndex;
                                 let end = param_begin + sig.params.len() as OpIndex;
                                 let params = stack.move_vec(param_begin, end);
+                                ctx.usage.add_ffi_call(&ffic.ffi_name, &ffic.func_name);
                                 // release stack so that code in ffi can yield
                                 drop(stack_mut_ref);
                                 let returns = {
@@ -1034,9 +2652,22 @@ impl<'a> Fiber<'a> {
                                         func_name: &ffic.func_name,
                                         vm_objs: objs,
                                         user_data: ctx.ffi_factory.user_data(),
+                                        #[cfg(feature = "async")]
+                                        go_caller: Some(&ctx.go_caller),
+                                        #[cfg(feature = "async")]
+                                        timers: Some(ctx.exec.timers()),
                                         stack: &mut self.stack.borrow_mut(),
                                         gcc,
                                         array_slice_caller: caller,
+                                        func_meta: ffic.meta,
+                                        ffi_factory: Some(ctx.ffi_factory),
+                                        sandbox: ctx.opts.sandbox.as_ref(),
+                                        goroutine: self._id,
+                                        #[cfg(feature = "race")]
+                                        race: Some(RaceCtx {
+                                            detector: ctx.race.clone(),
+                                            fiber: self._id,
+                                        }),
                                     };
                                     if !ffic.is_async {
                                         ffic.ffi.call(&mut ctx, params)
@@ -1046,14 +2677,21 @@ impl<'a> Fiber<'a> {
                                             Err("Async features disabled".to_owned().into())
                                         }
                                         #[cfg(feature = "async")]
-                                        ffic.ffi.async_call(&mut ctx, params).await
+                                        {
+                                            let pending = &self.context.pending_ffi;
+                                            pending.set(pending.get() + 1);
+                                            let result =
+                                                ffic.ffi.async_call(&mut ctx, params).await;
+                                            pending.set(pending.get() - 1);
+                                            result
+                                        }
                                     }
                                 };
                                 restore_stack_ref!(self, stack, stack_mut_ref);
                                 match returns {
                                     Ok(result) => stack.set_vec(result_begin, result),
                                     Err(e) => {
-                                        go_panic_str!(panic, e.as_str(), frame, code);
+                                        go_panic_err!(panic, e, ctx, frame, code);
                                     }
                                 }
                             }
@@ -1071,13 +2709,8 @@ impl<'a> Fiber<'a> {
                             // init_package func
                             ValueType::FlagB => {
                                 let pkey = stack.read(inst.d, sb, consts).as_package();
-                                let pkg = &objs.packages[*pkey];
-                                // the var values left on the stack are for pkg members
-                                let func = frame.func_obj(objs);
-                                let begin = sb;
-                                let end = begin + func.local_count();
-                                pkg.init_vars(stack.move_vec(begin, end));
-                                false
+                                objs.packages[*pkey].set_inited();
+                                true
                             }
                             // func with deferred calls
                             ValueType::FlagC => {
@@ -1094,6 +2727,10 @@ impl<'a> Fiber<'a> {
                                         + cur_func.ret_count()
                                         + cur_func.param_count()
                                         + cur_func.local_count();
+                                    let next_func = &objs.functions[call.frame.func()];
+                                    stack.set_min_size(
+                                        (new_sb + next_func.max_write_index + 1) as usize,
+                                    );
                                     stack.set_vec(new_sb, call.vec);
                                     let nframe = call.frame;
 
@@ -1107,7 +2744,7 @@ impl<'a> Fiber<'a> {
                                     code = &func.code;
                                     //dbg!("deferred", &code);
                                     let index = new_sb + call_vec_len;
-                                    stack.set_vec(index, func.local_zeros.clone());
+                                    stack.set_vec(index, func.local_zeros.to_vec());
                                     continue;
                                 }
                                 true
@@ -1116,6 +2753,11 @@ impl<'a> Fiber<'a> {
                         };
 
                         if clear_stack {
+                            if ctx.opts.dump_locals_on_panic {
+                                if let Some(p) = &mut panic {
+                                    p.keep_locals(objs, stack, sb);
+                                }
+                            }
                             // println!(
                             //     "current line: {}",
                             //     self.context.fs.unwrap().position(
@@ -1134,9 +2776,8 @@ impl<'a> Fiber<'a> {
                         self.frames.pop();
                         frame_height -= 1;
                         if self.frames.is_empty() {
-                            dbg!(total_inst);
-
                             result = Result::End;
+                            executed = n + 1;
                             break;
                         }
                         frame = self.frames.last_mut().unwrap();
@@ -1168,9 +2809,16 @@ impl<'a> Fiber<'a> {
                         let ok = if t.copyable() {
                             a.data().compare_eql(b.data(), t)
                         } else if t != ValueType::Metadata {
-                            a.eq(&b)
+                            values_eq(a, b, &objs.metas)
+                        } else if b.typ() == ValueType::Metadata {
+                            let (a, b) = (a.as_metadata(), b.as_metadata());
+                            a.identical(b, &objs.metas)
+                                || (b.is_interface(&objs.metas)
+                                    && *a != prim_meta.none
+                                    && objs.iface_binding(a, b).is_some())
                         } else {
-                            a.as_metadata().identical(b.as_metadata(), &objs.metas)
+                            // case nil
+                            *a.as_metadata() == prim_meta.none
                         };
                         if ok {
                             frame.pc += inst.d;
@@ -1202,11 +2850,25 @@ impl<'a> Fiber<'a> {
                             };
                             comms.push(channel::SelectComm { typ, chan, offset });
                         }
-                        let selector = channel::Selector::new(comms, default_offset);
+                        let selector = channel::Selector::new(
+                            comms,
+                            default_offset,
+                            ctx.opts.deterministic_select,
+                        );
+                        #[cfg(feature = "race")]
+                        for comm in selector.comms.iter() {
+                            race_sync!(self, release, comm.chan);
+                        }
 
                         drop(stack_mut_ref);
                         let re = selector.select().await;
                         restore_stack_ref!(self, stack, stack_mut_ref);
+                        #[cfg(feature = "race")]
+                        if let Ok((i, _)) = &re {
+                            if let Some(comm) = selector.comms.get(*i) {
+                                race_sync!(self, acquire, comm.chan);
+                            }
+                        }
 
                         match re {
                             Ok((i, val)) => {
@@ -1217,17 +2879,21 @@ impl<'a> Fiber<'a> {
                                     match comm.typ {
                                         channel::SelectCommType::Send(_) => {}
                                         channel::SelectCommType::Recv(flag, dst) => {
-                                            let (unwrapped, ok) = unwrap_recv_val!(
-                                                comm.chan.as_channel().as_ref().unwrap(),
-                                                val,
-                                                gcc
-                                            );
+                                            let ok = val.is_some();
+                                            let unwrap = |val| {
+                                                unwrap_recv_val!(
+                                                    comm.chan.as_channel().as_ref().unwrap(),
+                                                    val,
+                                                    objs,
+                                                    gcc
+                                                )
+                                            };
                                             match flag {
                                                 ValueType::FlagC => {
-                                                    stack.set(dst + sb, unwrapped);
+                                                    stack.set(dst + sb, unwrap(val));
                                                 }
                                                 ValueType::FlagD => {
-                                                    stack.set(dst + sb, unwrapped);
+                                                    stack.set(dst + sb, unwrap(val));
                                                     stack.set(dst + 1 + sb, ok.into());
                                                 }
                                                 _ => {}
@@ -1240,17 +2906,20 @@ impl<'a> Fiber<'a> {
                                 frame.pc += block_offset;
                             }
                             Err(e) => {
-                                go_panic_str!(panic, e.as_str(), frame, code);
+                                go_panic_err!(panic, e, ctx, frame, code);
                             }
                         }
                     }
                     Opcode::RANGE_INIT => {
                         let target = stack.read(inst.s0, sb, consts);
-                        let re = self.rstack.range_init(target, inst.t0, caller.get(inst.t1));
-                        panic_if_err!(re, panic, frame, code);
+                        let rstack = self
+                            .rstack
+                            .get_or_insert_with(|| Box::new(RangeStack::new()));
+                        let re = rstack.range_init(target, inst.t0, caller.get(inst.t1));
+                        panic_if_err!(re, panic, ctx, frame, code);
                     }
                     Opcode::RANGE => {
-                        if self.rstack.range_body(
+                        if self.rstack.as_mut().unwrap().range_body(
                             inst.t0,
                             caller.get(inst.t1),
                             stack,
@@ -1291,10 +2960,10 @@ impl<'a> Fiber<'a> {
                             Ok(iface) => {
                                 match bind_iface_method(iface, inst.s1 as usize, stack, objs, gcc) {
                                     Ok(cls) => stack.set(inst.d + sb, cls),
-                                    Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                                    Err(e) => go_panic_err!(panic, e, ctx, frame, code),
                                 }
                             }
-                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                            Err(e) => go_panic_err!(panic, e, ctx, frame, code),
                         }
                     }
                     Opcode::CAST => {
@@ -1317,12 +2986,29 @@ impl<'a> Fiber<'a> {
                                 .read(inst.s0, sb, consts)
                                 .cast_copyable(from_type, to_type),
                             ValueType::Interface => {
-                                let binding = ifaces[inst.s1 as usize].clone();
-                                let under = stack.read(inst.s0, sb, consts).copy_semantic(gcc);
-                                GosValue::new_interface(InterfaceObj::with_value(
-                                    under,
-                                    Some(binding),
-                                ))
+                                let val = stack.read(inst.s0, sb, consts);
+                                match val.typ() {
+                                    // from another interface, only the bindings change
+                                    ValueType::Interface => match val.as_interface() {
+                                        Some(iface) => {
+                                            match ctx.code.rebind(iface, inst.s1 as usize) {
+                                                Ok(i) => GosValue::new_interface(i),
+                                                Err(e) => {
+                                                    go_panic_err!(panic, e, ctx, frame, code);
+                                                    continue;
+                                                }
+                                            }
+                                        }
+                                        None => val.clone(),
+                                    },
+                                    _ => {
+                                        let binding = ifaces[inst.s1 as usize].clone();
+                                        GosValue::new_interface(InterfaceObj::with_value(
+                                            val.copy_semantic(gcc),
+                                            Some(binding),
+                                        ))
+                                    }
+                                }
                             }
                             ValueType::String => match from_type {
                                 ValueType::Slice => match inst.op1_as_t() {
@@ -1343,27 +3029,40 @@ impl<'a> Fiber<'a> {
                                     }
                                     ValueType::Uint8 => {
                                         match stack.read(inst.s0, sb, consts).as_slice::<Elem8>() {
-                                            Some(slice) => GosValue::new_string(slice.0.clone()),
+                                            Some(slice) => GosValue::new_string(
+                                                StringObj::with_bytes(&slice.0.as_rust_slice()),
+                                            ),
                                             None => GosValue::with_str(""),
                                         }
                                     }
                                     _ => unreachable!(),
                                 },
                                 _ => {
-                                    let val = stack
-                                        .read(inst.s0, sb, consts)
-                                        .cast_copyable(from_type, ValueType::Uint32);
-                                    GosValue::with_str(&char_from_u32(*val.as_uint32()).to_string())
+                                    let val = stack.read(inst.s0, sb, consts);
+                                    let c = match from_type {
+                                        ValueType::Uint
+                                        | ValueType::UintPtr
+                                        | ValueType::Uint8
+                                        | ValueType::Uint16
+                                        | ValueType::Uint32
+                                        | ValueType::Uint64 => char_from_u64(
+                                            *val.cast_copyable(from_type, ValueType::Uint64)
+                                                .as_uint64(),
+                                        ),
+                                        _ => char_from_i64(
+                                            *val.cast_copyable(from_type, ValueType::Int64)
+                                                .as_int64(),
+                                        ),
+                                    };
+                                    GosValue::with_str(&c.to_string())
                                 }
                             },
                             ValueType::Slice => {
                                 let from = stack.read(inst.s0, sb, consts).as_string();
                                 match inst.op1_as_t() {
                                     ValueType::Int32 => {
-                                        let data = from
-                                            .as_str()
-                                            .chars()
-                                            .map(|x| (x as i32).into())
+                                        let data = StringIter::new(&from.as_bytes())
+                                            .map(|(_, c)| (c as i32).into())
                                             .collect();
                                         GosValue::slice_with_data(
                                             data,
@@ -1371,9 +3070,10 @@ impl<'a> Fiber<'a> {
                                             gcc,
                                         )
                                     }
-                                    ValueType::Uint8 => {
-                                        GosValue::new_slice(from.clone(), ValueType::Uint8)
-                                    }
+                                    ValueType::Uint8 => GosValue::new_slice(
+                                        StringObj::with_bytes(&from.as_rust_slice()),
+                                        ValueType::Uint8,
+                                    ),
                                     _ => unreachable!(),
                                 }
                             }
@@ -1391,11 +3091,8 @@ impl<'a> Fiber<'a> {
                                                     ) {
                                                         Ok(p) => GosValue::new_pointer(p),
                                                         Err(e) => {
-                                                            go_panic_str!(
-                                                                panic,
-                                                                e.as_str(),
-                                                                frame,
-                                                                code
+                                                            go_panic_err!(
+                                                                panic, e, ctx, frame, code
                                                             );
                                                             continue;
                                                         }
@@ -1427,7 +3124,16 @@ impl<'a> Fiber<'a> {
                     }
                     Opcode::TYPE_ASSERT => {
                         let val = stack.read(inst.s0, sb, consts);
-                        match type_assert(val, cst(consts, inst.s1), gcc, Some(&objs.metas)) {
+                        // x.(T) panics when it fails, the type of x is for the error
+                        let iface_meta = match inst.t1 {
+                            ValueType::FlagC => {
+                                let inst_ex = &code[frame.pc as usize];
+                                frame.pc += 1;
+                                Some(cst(consts, inst_ex.s0).as_metadata())
+                            }
+                            _ => None,
+                        };
+                        match type_assert(val, cst(consts, inst.s1), iface_meta, gcc, objs) {
                             Ok((val, ok)) => {
                                 stack.set(inst.d + sb, val);
                                 if inst.t1 == ValueType::FlagB {
@@ -1436,7 +3142,7 @@ impl<'a> Fiber<'a> {
                                     stack.set(inst_ex.d + sb, ok.into());
                                 }
                             }
-                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                            Err(e) => go_panic_err!(panic, e, ctx, frame, code),
                         }
                     }
                     Opcode::TYPE => {
@@ -1488,7 +3194,7 @@ impl<'a> Fiber<'a> {
 
                         match result {
                             Ok(v) => stack.set(inst.d + sb, v),
-                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                            Err(e) => go_panic_err!(panic, e, ctx, frame, code),
                         }
                     }
                     Opcode::CLOSURE => {
@@ -1500,19 +3206,43 @@ impl<'a> Fiber<'a> {
                                 if let Some(uvs) = &mut gos.uvs {
                                     drop(frame);
                                     for (_, uv) in uvs.iter_mut() {
-                                        let r: &mut UpValueState = &mut uv.inner.borrow_mut();
-                                        if let UpValueState::Open(d) = r {
+                                        let mut existing = None;
+                                        let found = match &mut uv.inner.borrow_mut()
+                                            as &mut UpValueState
+                                        {
                                             // get frame index, and add_referred_by
-                                            for i in 1..frame_height {
-                                                let index = frame_height - i;
-                                                if self.frames[index].func() == d.func {
+                                            UpValueState::Open(d) => (0..frame_height)
+                                                .rev()
+                                                .find(|&index| self.frames[index].func() == d.func)
+                                                .map(|index| {
                                                     let upframe = &mut self.frames[index];
-                                                    d.stack = Rc::downgrade(&self.stack);
-                                                    d.stack_base = upframe.stack_base as OpIndex;
-                                                    upframe.add_referred_by(d.index, d.typ, uv);
-                                                    // if not found, the upvalue is already closed, nothing to be done
-                                                    break;
-                                                }
+                                                    existing = upframe.referred_upvalue(d.index);
+                                                    if existing.is_none() {
+                                                        d.stack = Rc::downgrade(&self.stack);
+                                                        d.stack_base =
+                                                            upframe.stack_base as OpIndex;
+                                                        upframe.add_referred_by(d.index, d.typ, uv);
+                                                    }
+                                                })
+                                                .is_some(),
+                                            UpValueState::Closed(_) => true,
+                                        };
+                                        if let Some(existing) = existing {
+                                            *uv = existing;
+                                        }
+                                        // The frame is gone or belongs to another goroutine, share
+                                        // the upvalue of the enclosing closure, which is either
+                                        // already closed or still pointing to that frame.
+                                        if !found {
+                                            let d = uv.desc();
+                                            let frame = self.frames.last().unwrap();
+                                            let outer = &objs.functions[frame.func()];
+                                            let shared = outer.up_ptrs.iter().position(|p| {
+                                                p.func == d.func && p.index == d.index
+                                            });
+                                            if let (Some(i), Some(ptrs)) = (shared, &frame.var_ptrs)
+                                            {
+                                                *uv = ptrs[i].clone();
                                             }
                                         }
                                     }
@@ -1530,9 +3260,21 @@ impl<'a> Fiber<'a> {
 
                         let begin = inst.s0 + sb;
                         let count = inst.s1;
-                        let build_val = |m: &Meta| {
-                            let zero_val = m.zero(&objs.metas, gcc);
-                            let mut val = vec![];
+                        // FlagA: the elements are in order without the indices
+                        let elems = match inst.t0 {
+                            ValueType::FlagA => Some(stack.move_vec(begin, begin + count)),
+                            _ => None,
+                        };
+                        let build_val = |m: &Meta, len: usize, elems: Option<Vec<GosValue>>| {
+                            if let Some(mut val) = elems {
+                                if val.len() < len {
+                                    let zero_val = objs.zero(m, gcc);
+                                    val.resize_with(len, || zero_val.copy_semantic(gcc));
+                                }
+                                return (val, m.value_type(&objs.metas));
+                            }
+                            let zero_val = objs.zero(m, gcc);
+                            let mut val = Vec::with_capacity(len);
                             let mut cur_index = -1;
                             for i in 0..count {
                                 let index = *stack.get(begin + i * 2).as_int32();
@@ -1547,36 +3289,42 @@ impl<'a> Fiber<'a> {
                                     val.push(elem);
                                 } else if gap > 0 {
                                     for _ in 0..gap {
-                                        val.push(zero_val.clone());
+                                        val.push(zero_val.copy_semantic(gcc));
                                     }
                                     val.push(elem);
                                 } else {
                                     val[cur_index as usize] = elem;
                                 }
                             }
+                            // the elements not in the literal are zero values
+                            val.resize_with(len.max(val.len()), || zero_val.copy_semantic(gcc));
                             (val, zero_val.typ())
                         };
                         let new_val = match &objs.metas[md.key] {
                             MetadataType::Slice(m) => {
-                                let (val, typ) = build_val(m);
+                                let (val, typ) = build_val(m, 0, elems);
                                 GosValue::slice_with_data(val, caller.get(typ), gcc)
                             }
-                            MetadataType::Array(m, _) => {
-                                let (val, typ) = build_val(m);
+                            MetadataType::Array(m, size) => {
+                                let (val, typ) = build_val(m, *size, elems);
                                 GosValue::array_with_data(val, caller.get(typ), gcc)
                             }
                             MetadataType::Map(_, _) => {
                                 let map_val = GosValue::new_map(gcc);
                                 let map = map_val.as_map().unwrap();
+                                gcc.charge(count as usize * MAP_ENTRY_SIZE);
                                 for i in 0..count {
                                     let k = stack.get(begin + i * 2).clone();
                                     let v = stack.get(begin + 1 + i * 2).clone();
-                                    map.0.insert(k, v);
+                                    map.0.borrow_data_mut().insert(k, v);
                                 }
                                 map_val
                             }
+                            MetadataType::Struct(_) if elems.is_some() => {
+                                GosValue::new_struct(StructObj::new(elems.unwrap()), gcc)
+                            }
                             MetadataType::Struct(_) => {
-                                let struct_val = md.zero(&objs.metas, gcc);
+                                let struct_val = objs.zero(md, gcc);
                                 {
                                     let fields = &mut struct_val.as_struct().0.borrow_fields_mut();
                                     for i in 0..count {
@@ -1592,7 +3340,7 @@ impl<'a> Fiber<'a> {
                     }
                     Opcode::NEW => {
                         let md = stack.read(inst.s0, sb, consts).as_metadata();
-                        let v = md.into_value_category().zero(&objs.metas, gcc);
+                        let v = objs.zero(&md.into_value_category(), gcc);
                         let p = GosValue::new_pointer(PointerObj::UpVal(UpValue::new_closed(v)));
                         stack.set(inst.d + sb, p);
                     }
@@ -1600,32 +3348,35 @@ impl<'a> Fiber<'a> {
                         let md = stack.read(inst.s0, sb, consts).as_metadata();
                         let val = match md.mtype_unwraped(&objs.metas) {
                             MetadataType::Slice(vmeta) => {
-                                let (cap, len) = match inst.t0 {
+                                let (len, cap) = match inst.t0 {
                                     // 3 args
                                     ValueType::FlagC => {
                                         let inst_ex = &code[frame.pc as usize];
                                         frame.pc += 1;
                                         (
-                                            *stack.read(inst.s1, sb, consts).as_int() as usize,
-                                            *stack.read(inst_ex.s0, sb, consts).as_int() as usize,
+                                            *stack.read(inst.s1, sb, consts).as_int(),
+                                            *stack.read(inst_ex.s0, sb, consts).as_int(),
                                         )
                                     }
                                     // 2 args
                                     ValueType::FlagB => {
-                                        let len =
-                                            *stack.read(inst.s1, sb, consts).as_int() as usize;
+                                        let len = *stack.read(inst.s1, sb, consts).as_int();
                                         (len, len)
                                     }
                                     _ => unreachable!(),
                                 };
-                                let zero = vmeta.zero(&objs.metas, gcc);
-                                GosValue::slice_with_size(
-                                    len,
-                                    cap,
-                                    &zero,
-                                    caller.get(zero.typ()),
-                                    gcc,
-                                )
+                                let zero = objs.zero(vmeta, gcc);
+                                let disp = caller.get(zero.typ());
+                                let size = slice_size(len, cap, disp.elem_size());
+                                let (len, cap, bytes) = match size {
+                                    Ok(size) => size,
+                                    Err(msg) => {
+                                        go_panic_str!(panic, msg, frame, code);
+                                        continue;
+                                    }
+                                };
+                                alloc_or_stop!(self, bytes, panic, frame, code);
+                                GosValue::slice_with_size(len, cap, &zero, disp, gcc)
                             }
                             MetadataType::Map(_, _) => GosValue::new_map(gcc),
                             #[cfg(not(feature = "async"))]
@@ -1637,15 +3388,26 @@ impl<'a> Fiber<'a> {
                             MetadataType::Channel(_, val_meta) => {
                                 let cap = match inst.t0 {
                                     // 2 args
-                                    ValueType::FlagB => {
-                                        *stack.read(inst.s1, sb, consts).as_int() as usize
-                                    }
+                                    ValueType::FlagB => *stack.read(inst.s1, sb, consts).as_int(),
                                     // 1 arg
                                     ValueType::FlagA => 0,
                                     _ => unreachable!(),
                                 };
-                                let zero = val_meta.zero(&objs.metas, gcc);
-                                GosValue::new_channel(ChannelObj::new(cap, zero))
+                                let (cap, bytes) =
+                                    match alloc_size(cap, std::mem::size_of::<GosValue>()) {
+                                        Some(size) => size,
+                                        None => {
+                                            go_panic_str!(
+                                                panic,
+                                                "runtime error: makechan: size out of range",
+                                                frame,
+                                                code
+                                            );
+                                            continue;
+                                        }
+                                    };
+                                alloc_or_stop!(self, bytes, panic, frame, code);
+                                GosValue::new_channel(ChannelObj::new(cap, *val_meta, &objs.metas))
                             }
                             _ => unreachable!(),
                         };
@@ -1701,7 +3463,27 @@ impl<'a> Fiber<'a> {
                         stack.set(inst.d + sb, (l as isize).into());
                     }
                     Opcode::APPEND => {
-                        let a = stack.read(inst.s0, sb, consts).clone();
+                        let added =
+                            stack.read(inst.s1, sb, consts).len() * caller.get(inst.t1).elem_size();
+                        if ctx.over_heap_limit(added) {
+                            go_panic_str!(panic, OUT_OF_MEMORY, frame, code);
+                            continue;
+                        }
+                        // the arguments are in temporary registers, the slice is moved out of
+                        // its register, and the old value of the destination is dropped if it's
+                        // the same slice, so that it's appended in place when it's not shared
+                        // with anything else, like in `s = append(s, v)`
+                        let a = std::mem::replace(
+                            stack.get_mut(inst.s0 + sb),
+                            GosValue::new_nil(ValueType::Void),
+                        );
+                        let dst = stack.get(inst.d + sb);
+                        if dst.typ() == ValueType::Slice
+                            && !a.is_nil()
+                            && dst.as_addr() == a.as_addr()
+                        {
+                            stack.set(inst.d + sb, GosValue::new_nil(ValueType::Void));
+                        }
                         let b = if inst.t0 != ValueType::String {
                             stack.read(inst.s1, sb, consts).clone()
                         } else {
@@ -1715,8 +3497,11 @@ impl<'a> Fiber<'a> {
                         };
 
                         match caller.get(inst.t1).slice_append(a, b, gcc) {
-                            Ok(slice) => stack.set(inst.d + sb, slice),
-                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                            Ok(slice) => {
+                                gcc.charge(added);
+                                stack.set(inst.d + sb, slice);
+                            }
+                            Err(e) => go_panic_err!(panic, e, ctx, frame, code),
                         };
                     }
                     Opcode::COPY => {
@@ -1734,36 +3519,52 @@ impl<'a> Fiber<'a> {
                         };
                         stack.set(inst.d + sb, (count as isize).into());
                     }
+                    // t0: FlagA if the key has to be checked for being hashable
                     Opcode::DELETE => {
                         let map = stack.read(inst.s0, sb, consts);
                         let key = stack.read(inst.s1, sb, consts);
-                        match map.as_map() {
-                            Some(m) => m.0.delete(key),
-                            None => {}
-                        }
+                        let result = check_map_key(inst.t0, key).and_then(|_| match map.as_map() {
+                            Some(m) => m.0.delete_hashable(key),
+                            None => Ok(()),
+                        });
+                        panic_if_err!(result, panic, ctx, frame, code);
                     }
                     #[cfg(not(feature = "async"))]
                     Opcode::CLOSE => go_panic_no_async!(panic, frame, code),
                     #[cfg(feature = "async")]
-                    Opcode::CLOSE => match stack.read(inst.s0, sb, consts).as_channel() {
-                        Some(c) => c.close(),
-                        None => {}
-                    },
+                    Opcode::CLOSE => {
+                        let chan = stack.read(inst.s0, sb, consts);
+                        if let Some(c) = chan.as_channel() {
+                            race_sync!(self, release, chan);
+                            c.close();
+                        }
+                    }
                     Opcode::PANIC => {
                         let val = stack.read(inst.s0, sb, consts).clone();
                         go_panic!(panic, val, frame, code);
                     }
                     Opcode::RECOVER => {
-                        let p = panic.take();
+                        let p = match &panic {
+                            Some(p) if p.goexit => None,
+                            _ => panic.take(),
+                        };
                         let val = p.map_or(GosValue::new_nil(ValueType::Void), |x| {
-                            GosValue::new_interface(InterfaceObj::with_value(x.msg, None))
+                            x.into_value(ctx.code.runtime_error)
                         });
                         stack.set(inst.d + sb, val);
                     }
                     Opcode::ASSERT => {
                         let ok = *stack.read(inst.s0, sb, consts).as_bool();
                         if !ok {
-                            go_panic_str!(panic, "Opcode::ASSERT: not true!", frame, code);
+                            if let Some(hook) = &ctx.opts.assert_hook {
+                                hook(&AssertInfo {
+                                    position: ctx
+                                        .position(frame.func(), frame.pc - 1)
+                                        .map(|p| p.to_string()),
+                                    goroutine: self._id,
+                                });
+                            }
+                            go_panic_str!(panic, "assertion failed", frame, code);
                         }
                     }
                     Opcode::FFI => {
@@ -1775,11 +3576,13 @@ impl<'a> Fiber<'a> {
                                 Ok(v) => {
                                     let meta = itype.as_metadata().underlying(&objs.metas).clone();
                                     GosValue::new_interface(InterfaceObj::Ffi(UnderlyingFfi::new(
-                                        v, meta,
+                                        v,
+                                        meta,
+                                        Rc::from(&*name_str),
                                     )))
                                 }
                                 Err(e) => {
-                                    go_panic_str!(panic, e.as_str(), frame, code);
+                                    go_panic_err!(panic, e, ctx, frame, code);
                                     continue;
                                 }
                             }
@@ -1789,56 +3592,94 @@ impl<'a> Fiber<'a> {
                     Opcode::VOID => unreachable!(),
                 }
             } //yield unit
+            let count = &ctx.usage.instructions;
+            count.set(count.get() + executed as u64);
+            self.quota.charge_instructions(executed);
             match result {
                 Result::End => {
-                    if let Some(p) = panic {
-                        println!("panic: {}", p.msg);
-                        if let Some(files) = self.context.fs {
-                            for (fkey, pc) in p.call_stack.iter() {
-                                let func = &objs.functions[*fkey];
-                                if let Some(p) = func.pos[*pc as usize] {
-                                    println!(
-                                        "{}",
-                                        files.position(p as usize).unwrap_or(FilePos::null())
-                                    );
-                                } else {
-                                    println!("<no debug info available>");
-                                }
-                            }
-                        }
-
-                        // a hack to make the test case fail
-                        let iface = p.msg.as_interface().unwrap();
-                        let val = iface.underlying_value().unwrap();
-                        if val.typ() == ValueType::String
-                            && val.as_string().as_str().starts_with("Opcode::ASSERT")
-                        {
-                            panic!("ASSERT");
-                        }
-                    }
-                    break;
+                    collect_step(gcc);
+                    return panic.filter(|p| !p.goexit);
                 }
                 Result::Continue => {
                     drop(stack_mut_ref);
+                    collect_step(gcc);
+                    ctx.burn_fuel(executed);
+                    let ctl = &ctx.opts.controller;
+                    if ctl.pause_requested() {
+                        self.pause();
+                    }
+                    if ctl.shutdown_requested() && ctl.shutdown_overdue() {
+                        ctx.stop(RunError::Shutdown { completed: false });
+                    }
                     #[cfg(feature = "async")]
                     future::yield_now().await;
+                    if ctx.stopped() {
+                        return None;
+                    }
                     restore_stack_ref!(self, stack, stack_mut_ref);
                 }
             };
         } //loop
+    }
+}
+
+/// The named locals of a call in scope at `pc`, with their values as the VM prints them
+fn frame_locals(
+    objs: &VMObjects,
+    fkey: FunctionKey,
+    pc: OpIndex,
+    stack: &Stack,
+    sb: OpIndex,
+) -> Vec<(String, String)> {
+    objs.functions[fkey]
+        .locals_at(pc)
+        .iter()
+        .map(|v| (v.name.clone(), stack.get(sb + v.slot).to_string()))
+        .collect()
+}
+
+/// The number of elements and the bytes of an allocation of `count` elements of
+/// `elem_size` bytes, None if `count` is negative or the bytes overflow an isize
+#[inline]
+fn alloc_size(count: isize, elem_size: usize) -> Option<(usize, usize)> {
+    let count = usize::try_from(count).ok()?;
+    let bytes = count.checked_mul(elem_size)?;
+    (bytes <= isize::MAX as usize).then_some((count, bytes))
+}
 
-        collect(gcc);
+/// The length, the capacity and the bytes of the array of a slice made with
+/// `make`, checked in the order of Go's makeslice for the same panics
+fn slice_size(
+    len: isize,
+    cap: isize,
+    elem_size: usize,
+) -> std::result::Result<(usize, usize, usize), &'static str> {
+    match alloc_size(cap, elem_size) {
+        Some((cap, bytes)) if len >= 0 && len as usize <= cap => Ok((len as usize, cap, bytes)),
+        _ => match alloc_size(len, elem_size) {
+            None => Err("runtime error: makeslice: len out of range"),
+            Some(_) => Err("runtime error: makeslice: cap out of range"),
+        },
     }
 }
 
+/// Surrogates and values out of the range of Unicode convert to "\uFFFD" like in Go
+#[inline]
+fn char_from_u64(u: u64) -> char {
+    u32::try_from(u)
+        .ok()
+        .and_then(char::from_u32)
+        .unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
 #[inline]
-fn char_from_u32(u: u32) -> char {
-    unsafe { char::from_u32_unchecked(u) }
+fn char_from_i64(i: i64) -> char {
+    u64::try_from(i).map_or(char::REPLACEMENT_CHARACTER, char_from_u64)
 }
 
 #[inline]
 fn char_from_i32(i: i32) -> char {
-    unsafe { char::from_u32_unchecked(i as u32) }
+    char_from_i64(i as i64)
 }
 
 #[inline]
@@ -1846,39 +3687,111 @@ fn deref_value(v: &GosValue, stack: &Stack, objs: &VMObjects) -> RuntimeResult<G
     v.as_non_nil_pointer()?.deref(stack, &objs.packages)
 }
 
+/// Compares the values like `==`. For two interfaces Go compares their dynamic types
+/// too, which `GosValue::eq` can't tell apart when they are the same kind of value,
+/// like a `chan int` and a `<-chan int`, or a named type and its underlying one.
+#[inline]
+fn values_eq(a: &GosValue, b: &GosValue, metas: &MetadataObjs) -> bool {
+    if !a.eq(b) {
+        return false;
+    }
+    let dyn_meta = |v: &GosValue| match v.typ() {
+        ValueType::Interface => match v.as_interface() {
+            Some(InterfaceObj::Gos(_, Some((m, _)))) => Some(*m),
+            _ => None,
+        },
+        _ => None,
+    };
+    match (dyn_meta(a), dyn_meta(b)) {
+        (Some(x), Some(y)) => x.identical(&y, metas),
+        _ => true,
+    }
+}
+
+/// Checks the key of a map operation for being hashable if codegen `flag`ged that it
+/// can hold a slice, a map or a func through an interface
+#[inline]
+fn check_map_key(flag: ValueType, key: &GosValue) -> RuntimeResult<()> {
+    match flag {
+        ValueType::FlagA => key.check_hashable(),
+        _ => Ok(()),
+    }
+}
+
 #[inline(always)]
 fn cst(consts: &Vec<GosValue>, i: OpIndex) -> &GosValue {
     &consts[(-i - 1) as usize]
 }
 
+/// Asserts that the interface `val` holds a `want_meta`, returns the value and
+/// whether it does. When it doesn't, the value is the zero one for `v, ok := x.(T)`,
+/// and `x.(T)`, which passes the type of x as `iface_meta`, panics naming the types.
 #[inline(always)]
 fn type_assert(
     val: &GosValue,
     want_meta: &GosValue,
+    iface_meta: Option<&Meta>,
     gcc: &GcContainer,
-    metas: Option<&MetadataObjs>,
+    objs: &VMObjects,
 ) -> RuntimeResult<(GosValue, bool)> {
-    match val.as_non_nil_interface() {
-        Ok(iface) => match &iface as &InterfaceObj {
+    let want_meta = want_meta.as_metadata();
+    let metas = &objs.metas;
+    let meta = match val.as_interface() {
+        Some(iface) => match &iface as &InterfaceObj {
             InterfaceObj::Gos(v, b) => {
                 let meta = b.as_ref().unwrap().0;
-                let want_meta = want_meta.as_metadata();
                 if *want_meta == meta {
-                    Ok((v.copy_semantic(gcc), true))
-                } else {
-                    if let Some(mobjs) = metas {
-                        Ok((want_meta.zero(mobjs, gcc), false))
-                    } else {
-                        Err("interface conversion: wrong type".to_owned().into())
+                    return Ok((v.copy_semantic(gcc), true));
+                }
+                if want_meta.is_interface(metas) {
+                    if let Some(b) = objs.iface_binding(&meta, want_meta) {
+                        let iface = InterfaceObj::with_value(v.clone(), Some((meta, b)));
+                        return Ok((GosValue::new_interface(iface), true));
                     }
                 }
+                Some(meta)
+            }
+            InterfaceObj::Ffi(_) => {
+                return Err("FFI interface do not support type assertion"
+                    .to_owned()
+                    .into())
             }
-            InterfaceObj::Ffi(_) => Err("FFI interface do not support type assertion"
-                .to_owned()
-                .into()),
         },
-        Err(e) => Err(e),
-    }
+        None => None,
+    };
+    let iface_meta = match iface_meta {
+        Some(m) => m,
+        None => return Ok((objs.zero(want_meta, gcc), false)),
+    };
+    // like the TypeAssertionError of Go, the type asserted to an interface is not
+    // known to the code panicking in Go either
+    let inter = match want_meta.is_interface(metas) {
+        true => "interface".to_owned(),
+        false => type_string(iface_meta, metas),
+    };
+    let want = type_string(want_meta, metas);
+    let missing = |m: &Meta| match want_meta.is_interface(metas) {
+        true => objs.missing_method(m, want_meta),
+        false => None,
+    };
+    let msg = match meta {
+        None => format!("interface conversion: {} is nil, not {}", inter, want),
+        Some(m) => match missing(&m) {
+            Some(name) => format!(
+                "interface conversion: {} is not {}: missing method {}",
+                type_string(&m, metas),
+                want,
+                name
+            ),
+            None => format!(
+                "interface conversion: {} is {}, not {}",
+                inter,
+                type_string(&m, metas),
+                want
+            ),
+        },
+    };
+    Err(msg.into())
 }
 
 #[inline(always)]
@@ -1911,40 +3824,40 @@ fn get_embeded(
     stack: &Stack,
     pkgs: &PackageObjs,
 ) -> RuntimeResult<GosValue> {
-    let typ = val.typ();
     let mut cur_val: GosValue = val;
-    if typ == ValueType::Pointer {
-        cur_val = cur_val.as_non_nil_pointer()?.deref(stack, pkgs)?;
-    }
     for &i in indices.iter() {
-        let s = &cur_val.as_struct().0;
-        let v = s.borrow_fields()[i as usize].clone();
+        // the struct itself or any embedded struct on the way can be a pointer
+        if cur_val.typ() == ValueType::Pointer {
+            cur_val = cur_val.as_non_nil_pointer()?.deref(stack, pkgs)?;
+        }
+        let v = cur_val.as_struct().0.borrow_fields()[i as usize].clone();
         cur_val = v;
     }
     Ok(cur_val)
 }
 
+/// Makes the receiver of a method bound through an interface match the receiver of
+/// the method. Methods with pointer receivers are not in the method set of a value,
+/// so a pointer receiver is already a pointer here, and a value receiver gets a copy
+/// of what the pointer points to.
 #[inline]
 fn cast_receiver(
     receiver: GosValue,
-    b1: bool,
+    ptr_recv: bool,
     stack: &Stack,
     objs: &VMObjects,
+    gcc: &GcContainer,
 ) -> RuntimeResult<GosValue> {
-    let b0 = receiver.typ() == ValueType::Pointer;
-    if b0 == b1 {
+    if ptr_recv || receiver.typ() != ValueType::Pointer {
+        debug_assert_eq!(ptr_recv, receiver.typ() == ValueType::Pointer);
         Ok(receiver)
-    } else if b1 {
-        Ok(GosValue::new_pointer(PointerObj::UpVal(
-            UpValue::new_closed(receiver.clone()),
-        )))
     } else {
-        deref_value(&receiver, stack, objs)
+        Ok(deref_value(&receiver, stack, objs)?.copy_semantic(gcc))
     }
 }
 
 #[inline]
-fn bind_iface_method(
+pub(crate) fn bind_iface_method(
     iface: &InterfaceObj,
     index: usize,
     stack: &Stack,
@@ -1958,16 +3871,29 @@ fn bind_iface_method(
                 Binding4Runtime::Struct(func, ptr_recv, indices) => {
                     let obj = match indices {
                         None => obj.copy_semantic(gcc),
-                        Some(inds) => get_embeded(obj.clone(), inds, stack, &objs.packages)?
-                            .copy_semantic(gcc),
+                        Some(inds) => {
+                            let (last, path) = inds.split_last().unwrap();
+                            let mut outer = get_embeded(obj.clone(), path, stack, &objs.packages)?;
+                            if outer.typ() == ValueType::Pointer {
+                                outer = deref_value(&outer, stack, objs)?;
+                            }
+                            let field = outer.as_struct().0.borrow_fields()[*last as usize].clone();
+                            if *ptr_recv && field.typ() != ValueType::Pointer {
+                                // the method is promoted from an embedded value, which
+                                // is addressable as the struct is behind a pointer
+                                GosValue::new_pointer(PointerObj::StructField(outer, *last))
+                            } else {
+                                field.copy_semantic(gcc)
+                            }
+                        }
                     };
-                    let obj = cast_receiver(obj, *ptr_recv, stack, objs)?;
+                    let obj = cast_receiver(obj, *ptr_recv, stack, objs, gcc)?;
                     let cls = ClosureObj::gos_from_func(*func, &objs.functions, Some(obj));
                     Ok(GosValue::new_closure(cls, gcc))
                 }
                 Binding4Runtime::Iface(i, indices) => {
                     let bind = |obj: &GosValue| {
-                        bind_iface_method(&obj.as_interface().unwrap(), *i, stack, objs, gcc)
+                        bind_iface_method(obj.as_non_nil_interface()?, *i, stack, objs, gcc)
                     };
                     match indices {
                         None => bind(&obj),
@@ -1981,6 +3907,7 @@ fn bind_iface_method(
             let (func_name, meta) = (method.name.clone(), method.meta);
             let cls = FfiClosureObj {
                 ffi: ffi.ffi_obj.clone(),
+                ffi_name: ffi.name.clone(),
                 is_async: func_name.starts_with("async"),
                 func_name,
                 meta,
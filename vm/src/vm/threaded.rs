@@ -0,0 +1,235 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The handlers `main_loop` calls through a table indexed by the opcode, instead
+//! of matching it, with the `threaded_dispatch` feature.
+//!
+//! Only the opcodes that read and write the stack, and maybe move the pc, have a
+//! handler: the arithmetic, the comparisons of ordered values and the jumps, which
+//! are most of what a tight numeric loop runs. The others still go through the
+//! `match`, which needs the frame, the objects and the panic state.
+
+use crate::gc::GcContainer;
+use crate::stack::Stack;
+use crate::value::*;
+use std::cmp::Ordering;
+
+pub(super) type Handler =
+    fn(&mut Stack, &Instruction, OpIndex, &[GosValue], &GcContainer, &mut OpIndex);
+
+pub(super) static HANDLERS: [Option<Handler>; 256] = table();
+
+const fn table() -> [Option<Handler>; 256] {
+    let mut t: [Option<Handler>; 256] = [None; 256];
+    t[Opcode::ADD as usize] = Some(add);
+    t[Opcode::SUB as usize] = Some(sub);
+    t[Opcode::MUL as usize] = Some(mul);
+    t[Opcode::QUO as usize] = Some(quo);
+    t[Opcode::REM as usize] = Some(rem);
+    t[Opcode::AND as usize] = Some(and);
+    t[Opcode::OR as usize] = Some(or);
+    t[Opcode::XOR as usize] = Some(xor);
+    t[Opcode::AND_NOT as usize] = Some(and_not);
+    t[Opcode::SHL as usize] = Some(shl);
+    t[Opcode::SHR as usize] = Some(shr);
+    t[Opcode::ADD_ASSIGN as usize] = Some(add_assign);
+    t[Opcode::SUB_ASSIGN as usize] = Some(sub_assign);
+    t[Opcode::MUL_ASSIGN as usize] = Some(mul_assign);
+    t[Opcode::QUO_ASSIGN as usize] = Some(quo_assign);
+    t[Opcode::REM_ASSIGN as usize] = Some(rem_assign);
+    t[Opcode::AND_ASSIGN as usize] = Some(and_assign);
+    t[Opcode::OR_ASSIGN as usize] = Some(or_assign);
+    t[Opcode::XOR_ASSIGN as usize] = Some(xor_assign);
+    t[Opcode::AND_NOT_ASSIGN as usize] = Some(and_not_assign);
+    t[Opcode::SHL_ASSIGN as usize] = Some(shl_assign);
+    t[Opcode::SHR_ASSIGN as usize] = Some(shr_assign);
+    t[Opcode::INC as usize] = Some(inc);
+    t[Opcode::DEC as usize] = Some(dec);
+    t[Opcode::UNARY_SUB as usize] = Some(unary_sub);
+    t[Opcode::UNARY_XOR as usize] = Some(unary_xor);
+    t[Opcode::NOT as usize] = Some(not);
+    t[Opcode::LSS as usize] = Some(lss);
+    t[Opcode::GTR as usize] = Some(gtr);
+    t[Opcode::LEQ as usize] = Some(leq);
+    t[Opcode::GEQ as usize] = Some(geq);
+    t[Opcode::JUMP as usize] = Some(jump);
+    t[Opcode::JUMP_IF as usize] = Some(jump_if);
+    t[Opcode::JUMP_IF_NOT as usize] = Some(jump_if_not);
+    t
+}
+
+/// Handlers that expand the macro of the same arm of the `match`
+macro_rules! handlers {
+    ($mac:ident: $($name:ident => $op:tt),+ $(,)?) => {
+        $(
+            fn $name(
+                stack: &mut Stack,
+                inst: &Instruction,
+                sb: OpIndex,
+                consts: &[GosValue],
+                _: &GcContainer,
+                _: &mut OpIndex,
+            ) {
+                $mac!(stack, $op, inst, sb, consts)
+            }
+        )+
+    };
+}
+
+handlers!(binary_op:
+    sub => binary_op_sub,
+    mul => binary_op_mul,
+    quo => binary_op_quo,
+    rem => binary_op_rem,
+    and => binary_op_and,
+    or => binary_op_or,
+    xor => binary_op_xor,
+    and_not => binary_op_and_not,
+);
+
+handlers!(shift_op: shl => binary_op_shl, shr => binary_op_shr);
+
+handlers!(binary_op_assign:
+    sub_assign => binary_op_sub,
+    mul_assign => binary_op_mul,
+    quo_assign => binary_op_quo,
+    rem_assign => binary_op_rem,
+    and_assign => binary_op_and,
+    or_assign => binary_op_or,
+    xor_assign => binary_op_xor,
+    and_not_assign => binary_op_and_not,
+);
+
+handlers!(shift_op_assign: shl_assign => binary_op_shl, shr_assign => binary_op_shr);
+
+handlers!(unary_op:
+    unary_sub => unary_negate,
+    unary_xor => unary_xor,
+    not => logical_not,
+);
+
+fn add(
+    stack: &mut Stack,
+    inst: &Instruction,
+    sb: OpIndex,
+    consts: &[GosValue],
+    gcc: &GcContainer,
+    _: &mut OpIndex,
+) {
+    binary_op!(stack, binary_op_add, inst, sb, consts);
+    if inst.t0 == ValueType::String {
+        gcc.charge(stack.get(inst.d + sb).len());
+    }
+}
+
+fn add_assign(
+    stack: &mut Stack,
+    inst: &Instruction,
+    sb: OpIndex,
+    consts: &[GosValue],
+    gcc: &GcContainer,
+    _: &mut OpIndex,
+) {
+    binary_op_assign!(stack, binary_op_add, inst, sb, consts);
+    if inst.t0 == ValueType::String {
+        gcc.charge(stack.get(inst.d + sb).len());
+    }
+}
+
+fn inc(
+    stack: &mut Stack,
+    inst: &Instruction,
+    sb: OpIndex,
+    _: &[GosValue],
+    _: &GcContainer,
+    _: &mut OpIndex,
+) {
+    unsafe {
+        let v = stack.get_mut(inst.d + sb).data_mut();
+        *v = v.inc(inst.t0);
+    }
+}
+
+fn dec(
+    stack: &mut Stack,
+    inst: &Instruction,
+    sb: OpIndex,
+    _: &[GosValue],
+    _: &GcContainer,
+    _: &mut OpIndex,
+) {
+    unsafe {
+        let v = stack.get_mut(inst.d + sb).data_mut();
+        *v = v.dec(inst.t0);
+    }
+}
+
+/// Comparisons of ordered values, by their data when they are copyable
+macro_rules! compare_handlers {
+    ($($name:ident => $compare:ident, $holds:expr),+ $(,)?) => {
+        $(
+            fn $name(
+                stack: &mut Stack,
+                inst: &Instruction,
+                sb: OpIndex,
+                consts: &[GosValue],
+                _: &GcContainer,
+                _: &mut OpIndex,
+            ) {
+                let a = stack.read(inst.s0, sb, consts);
+                let b = stack.read(inst.s1, sb, consts);
+                let result = if inst.t0.copyable() {
+                    a.data().$compare(b.data(), inst.t0)
+                } else {
+                    $holds(a.cmp(b))
+                };
+                stack.set(inst.d + sb, result.into());
+            }
+        )+
+    };
+}
+
+compare_handlers!(
+    lss => compare_lss, |o| o == Ordering::Less,
+    gtr => compare_gtr, |o| o == Ordering::Greater,
+    leq => compare_leq, |o| o != Ordering::Greater,
+    geq => compare_geq, |o| o != Ordering::Less,
+);
+
+fn jump(
+    _: &mut Stack,
+    inst: &Instruction,
+    _: OpIndex,
+    _: &[GosValue],
+    _: &GcContainer,
+    pc: &mut OpIndex,
+) {
+    *pc += inst.d;
+}
+
+fn jump_if(
+    stack: &mut Stack,
+    inst: &Instruction,
+    sb: OpIndex,
+    consts: &[GosValue],
+    _: &GcContainer,
+    pc: &mut OpIndex,
+) {
+    if *stack.read(inst.s0, sb, consts).as_bool() {
+        *pc += inst.d;
+    }
+}
+
+fn jump_if_not(
+    stack: &mut Stack,
+    inst: &Instruction,
+    sb: OpIndex,
+    consts: &[GosValue],
+    _: &GcContainer,
+    pc: &mut OpIndex,
+) {
+    if !*stack.read(inst.s0, sb, consts).as_bool() {
+        *pc += inst.d;
+    }
+}